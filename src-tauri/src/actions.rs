@@ -0,0 +1,897 @@
+use super::*;
+
+/// What a button is mapped to: an action such as `{"type": "back"}`, or a
+/// gesture stored as `{"type": "gesture", "tap": {...}, ...}`. A value that
+/// is no action this version knows, or one with bad parameters, is kept as
+/// `Unknown`, so it survives a save and `validate_settings` can point at it;
+/// it does what Default does.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum ActionValue {
+    Action(Action),
+    Gesture { gesture: Box<GestureConfig> },
+    Unknown(serde_json::Value),
+}
+
+/// How `ActionValue` writes a gesture.
+#[derive(Serialize)]
+struct TaggedGesture<'a> {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    #[serde(flatten)]
+    gesture: &'a GestureConfig,
+}
+
+impl Serialize for ActionValue {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            ActionValue::Action(action) => action.serialize(serializer),
+            ActionValue::Gesture { gesture } => TaggedGesture {
+                kind: "gesture",
+                gesture,
+            }
+            .serialize(serializer),
+            ActionValue::Unknown(value) => value.serialize(serializer),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for ActionValue {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = serde_json::Value::deserialize(deserializer)?;
+        ActionValue::from_json(value).map_err(serde::de::Error::custom)
+    }
+}
+
+impl Default for ActionValue {
+    fn default() -> Self {
+        ActionValue::Action(Action::Default)
+    }
+}
+
+impl ActionValue {
+    /// Reads a stored mapping. Plain strings and `{"gesture": {...}}` are how
+    /// settings before version 3 stored actions and gestures.
+    pub(crate) fn from_json(value: serde_json::Value) -> Result<Self, serde_json::Error> {
+        let gesture = match &value {
+            serde_json::Value::String(id) => {
+                return Ok(Action::from_id(id)
+                    .or_else(|| Action::from_name(id))
+                    .map_or(ActionValue::Unknown(value), ActionValue::Action));
+            }
+            serde_json::Value::Object(object) => match object.get("type") {
+                Some(kind) if kind == "gesture" => value,
+                Some(_) => {
+                    return Ok(serde_json::from_value(value.clone())
+                        .map_or(ActionValue::Unknown(value), ActionValue::Action));
+                }
+                None => match object.get("gesture") {
+                    Some(gesture) => gesture.clone(),
+                    None => return Ok(ActionValue::Unknown(value)),
+                },
+            },
+            _ => return Ok(ActionValue::Unknown(value)),
+        };
+        serde_json::from_value(gesture).map(|gesture| ActionValue::Gesture {
+            gesture: Box::new(gesture),
+        })
+    }
+
+    /// How the value reads in a change summary.
+    pub(crate) fn label(&self) -> String {
+        match self {
+            ActionValue::Action(Action::SetDpi { dpi }) => format!("Set DPI {}", dpi),
+            ActionValue::Action(Action::Shortcut {
+                keycode,
+                key,
+                modifiers,
+            }) => format!("Shortcut {}", shortcut_label(*keycode, *key, *modifiers)),
+            ActionValue::Action(action) => action.name().to_string(),
+            ActionValue::Gesture { .. } => "Gesture".to_string(),
+            ActionValue::Unknown(value) => value.to_string(),
+        }
+    }
+
+    /// The plain action this value performs on press; gestures resolve on
+    /// release instead, so they read as Default here.
+    pub(crate) fn action(&self) -> Action {
+        match self {
+            ActionValue::Action(action) => *action,
+            ActionValue::Gesture { .. } | ActionValue::Unknown(_) => Action::Default,
+        }
+    }
+}
+
+/// Hold-and-flick mapping: moving further than `threshold` points while the
+/// button is held fires the dominant direction's action, a plain press fires
+/// `tap`.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub(crate) struct GestureConfig {
+    pub(crate) tap: ActionValue,
+    pub(crate) up: ActionValue,
+    pub(crate) down: ActionValue,
+    pub(crate) left: ActionValue,
+    pub(crate) right: ActionValue,
+    pub(crate) threshold: f64,
+    /// Keep the cursor still while the button is held.
+    pub(crate) suppress_cursor: bool,
+}
+
+impl Default for GestureConfig {
+    fn default() -> Self {
+        Self {
+            tap: ActionValue::default(),
+            up: ActionValue::default(),
+            down: ActionValue::default(),
+            left: ActionValue::default(),
+            right: ActionValue::default(),
+            threshold: 50.0,
+            suppress_cursor: true,
+        }
+    }
+}
+
+/// A `GestureConfig` with its action names parsed, for the event callback.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct ResolvedGesture {
+    pub(crate) tap: Action,
+    pub(crate) up: Action,
+    pub(crate) down: Action,
+    pub(crate) left: Action,
+    pub(crate) right: Action,
+    pub(crate) threshold: f64,
+    pub(crate) suppress_cursor: bool,
+}
+
+impl From<&GestureConfig> for ResolvedGesture {
+    fn from(config: &GestureConfig) -> Self {
+        Self {
+            tap: config.tap.action(),
+            up: config.up.action(),
+            down: config.down.action(),
+            left: config.left.action(),
+            right: config.right.action(),
+            threshold: config.threshold,
+            suppress_cursor: config.suppress_cursor,
+        }
+    }
+}
+
+impl ResolvedGesture {
+    /// Action for a gesture that moved `dx`/`dy` points (screen coordinates,
+    /// so negative `dy` is up) between press and release.
+    pub(crate) fn resolve(&self, dx: f64, dy: f64) -> Action {
+        if dx.hypot(dy) < self.threshold {
+            self.tap
+        } else if dx.abs() > dy.abs() {
+            if dx < 0.0 {
+                self.left
+            } else {
+                self.right
+            }
+        } else if dy < 0.0 {
+            self.up
+        } else {
+            self.down
+        }
+    }
+}
+
+/// An `ActionValue` compiled for the event callback, which then only
+/// matches enums.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum ResolvedAction {
+    Action(Action),
+    Gesture(ResolvedGesture),
+    /// An action of a button whose `Trigger` is `Release`.
+    OnRelease(Action),
+}
+
+impl From<&ActionValue> for ResolvedAction {
+    fn from(value: &ActionValue) -> Self {
+        match value {
+            ActionValue::Gesture { gesture } => ResolvedAction::Gesture(gesture.as_ref().into()),
+            value => ResolvedAction::Action(value.action()),
+        }
+    }
+}
+
+impl ResolvedAction {
+    /// Like `ActionValue::action`: gestures read as Default on press.
+    pub(crate) fn action(self) -> Action {
+        match self {
+            ResolvedAction::Action(action) | ResolvedAction::OnRelease(action) => action,
+            ResolvedAction::Gesture(_) => Action::Default,
+        }
+    }
+
+    /// This mapping fired as `trigger` says. Gestures always finish on
+    /// release, and Default has nothing to fire.
+    pub(crate) fn triggered(self, trigger: Trigger) -> Self {
+        match (self, trigger) {
+            (ResolvedAction::Action(action), Trigger::Release) if action != Action::Default => {
+                ResolvedAction::OnRelease(action)
+            }
+            _ => self,
+        }
+    }
+}
+
+/// Which edge of a button press fires its action.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Trigger {
+    #[default]
+    Press,
+    /// Fires as the button comes up, so a press can be held to think it
+    /// over; both edges are swallowed all the same.
+    Release,
+}
+
+/// What a button does to confirm its mapping fired, for actions with
+/// nothing to see such as a DPI step or a profile switch.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Feedback {
+    #[default]
+    None,
+    /// A short system sound.
+    Sound,
+    /// A tap of the trackpad's actuator, where there is one.
+    Haptic,
+}
+
+/// A gesture button that is currently held.
+#[derive(Debug, Clone)]
+pub(crate) struct ActiveGesture {
+    pub(crate) button: i64,
+    pub(crate) config: ResolvedGesture,
+    pub(crate) dx: f64,
+    pub(crate) dy: f64,
+}
+
+/// Keyboard modifiers a mapping can be conditioned on. Caps Lock is not one
+/// of them, so it never changes which mapping applies.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Modifier {
+    Shift,
+    #[serde(alias = "ctrl")]
+    Control,
+    #[serde(alias = "alt")]
+    Option,
+    #[serde(alias = "cmd")]
+    Command,
+}
+
+const MODIFIERS: [Modifier; 4] = [
+    Modifier::Shift,
+    Modifier::Control,
+    Modifier::Option,
+    Modifier::Command,
+];
+
+/// A set of `Modifier`s as bit flags.
+pub(crate) type ModifierMask = u8;
+
+pub(crate) fn modifier_mask(modifiers: &[Modifier]) -> ModifierMask {
+    modifiers
+        .iter()
+        .fold(0, |mask, modifier| mask | 1 << *modifier as u8)
+}
+
+/// Mapping that applies while exactly `modifiers` are held.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub(crate) struct ModifierMapping {
+    pub(crate) modifiers: Vec<Modifier>,
+    pub(crate) action: ActionValue,
+}
+
+/// A button action, stored tagged by `type` with its parameters alongside,
+/// such as `{"type": "set_dpi", "dpi": 1600}`. Settings before version 3
+/// stored plain strings, identifiers like `"set_dpi:1600"` (`Action::id`) or
+/// older still display names like `"Set DPI 1600"`; `ActionValue` reads both.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub(crate) enum Action {
+    Default,
+    Disabled,
+    Back,
+    Forward,
+    MiddleClick,
+    DoubleClick,
+    CycleDpi,
+    SetDpi {
+        dpi: u32,
+    },
+    NextProfile,
+    PreviousProfile,
+    /// Switch tabs, repeating while the button is held; see `TabKeys`.
+    NextTab,
+    PreviousTab,
+    /// Turns Do Not Disturb on or off; see `focus`.
+    ToggleFocus,
+    /// Taps Space to preview the selection, only in
+    /// `Settings::quick_look_apps`.
+    QuickLook,
+    /// Looks up the word under the pointer, as a Force click does.
+    LookUp,
+    /// Swipe between pages, and between full-screen spaces, as on a
+    /// trackpad; see `Swipe`.
+    SwipeBack,
+    SwipeForward,
+    PreviousSpace,
+    NextSpace,
+    /// Taps a key with `modifiers` held. `key` is the character it types
+    /// without modifiers, where the shortcut has one: the key typing it on
+    /// the current keyboard layout is pressed, so ⌘[ stays ⌘[ on a German
+    /// layout. `keycode`, a macOS virtual keycode, is for keys that type
+    /// nothing and layouts without the character. Stored with the modifiers
+    /// as a list, `{"type": "shortcut", "key": "k", "keycode": 40,
+    /// "modifiers": ["command"]}`, either of `key` and `keycode` left out.
+    Shortcut {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        keycode: Option<u16>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        key: Option<char>,
+        #[serde(default, with = "modifier_list")]
+        modifiers: ModifierMask,
+    },
+}
+
+/// What Next Tab and Previous Tab press. Only macOS tells applications
+/// apart; elsewhere they always press Control-Tab and Control-Shift-Tab.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum TabKeys {
+    /// Control-Tab and Control-Shift-Tab.
+    #[default]
+    ControlTab,
+    /// Control-Page Down and Control-Page Up, which keep to the tab order
+    /// where Control-Tab goes by recent use.
+    ControlPage,
+    /// Command-Shift-] and Command-Shift-[.
+    CommandBrackets,
+}
+
+/// The trackpad swipes the swipe actions stand in for.
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum Swipe {
+    /// Between pages: back and forward in Safari and many document apps.
+    Pages,
+    /// Between full-screen apps and desktops.
+    Spaces,
+}
+
+/// How the swipe actions reach apps here; `swipe_strategy` picks it once
+/// at launch.
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum SwipeStrategy {
+    /// Gesture events as a trackpad posts them, on macOS.
+    Gesture,
+    /// The shortcut doing the same: Back and Forward's keys for pages, and
+    /// the system's shortcut for the next desktop for spaces.
+    Keys,
+}
+
+pub(crate) const FINDER_BUNDLE_ID: &str = "com.apple.finder";
+
+/// Accessibility roles of the elements Look Up finds words in. Anywhere
+/// else ⌃⌘D would only beep.
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+pub(crate) const LOOK_UP_ROLES: [&str; 6] = [
+    "AXStaticText",
+    "AXTextArea",
+    "AXTextField",
+    "AXWebArea",
+    "AXLink",
+    "AXCell",
+];
+
+/// macOS virtual keycode of Space, which Quick Look taps.
+pub(crate) const KEYCODE_SPACE: u16 = 0x31;
+
+/// Name of the shortcut Toggle Focus runs unless `Settings::focus_shortcut`
+/// names another.
+pub(crate) const DEFAULT_FOCUS_SHORTCUT: &str = "Toggle Focus";
+
+/// `TabKeys` of applications where Control-Tab doesn't walk the tabs in
+/// order, unless `Settings::tab_keys` says otherwise.
+pub(crate) const DEFAULT_TAB_KEYS: [(&str, TabKeys); 4] = [
+    ("com.apple.Safari", TabKeys::CommandBrackets),
+    ("com.google.Chrome", TabKeys::CommandBrackets),
+    ("org.mozilla.firefox", TabKeys::ControlPage),
+    ("com.googlecode.iterm2", TabKeys::CommandBrackets),
+];
+
+/// Largest macOS virtual keycode.
+pub(crate) const MAX_KEYCODE: u16 = 0x7f;
+
+/// Labels of macOS virtual keycodes on a US layout.
+pub(crate) const KEY_NAMES: &[(u16, &str)] = &[
+    (0x00, "A"),
+    (0x01, "S"),
+    (0x02, "D"),
+    (0x03, "F"),
+    (0x04, "H"),
+    (0x05, "G"),
+    (0x06, "Z"),
+    (0x07, "X"),
+    (0x08, "C"),
+    (0x09, "V"),
+    (0x0b, "B"),
+    (0x0c, "Q"),
+    (0x0d, "W"),
+    (0x0e, "E"),
+    (0x0f, "R"),
+    (0x10, "Y"),
+    (0x11, "T"),
+    (0x12, "1"),
+    (0x13, "2"),
+    (0x14, "3"),
+    (0x15, "4"),
+    (0x16, "6"),
+    (0x17, "5"),
+    (0x18, "="),
+    (0x19, "9"),
+    (0x1a, "7"),
+    (0x1b, "-"),
+    (0x1c, "8"),
+    (0x1d, "0"),
+    (0x1e, "]"),
+    (0x1f, "O"),
+    (0x20, "U"),
+    (0x21, "["),
+    (0x22, "I"),
+    (0x23, "P"),
+    (0x24, "Return"),
+    (0x25, "L"),
+    (0x26, "J"),
+    (0x27, "'"),
+    (0x28, "K"),
+    (0x29, ";"),
+    (0x2a, "\\"),
+    (0x2b, ","),
+    (0x2c, "/"),
+    (0x2d, "N"),
+    (0x2e, "M"),
+    (0x2f, "."),
+    (0x30, "Tab"),
+    (0x31, "Space"),
+    (0x32, "`"),
+    (0x33, "Delete"),
+    (0x35, "Escape"),
+    (0x40, "F17"),
+    (0x4f, "F18"),
+    (0x50, "F19"),
+    (0x5a, "F20"),
+    (0x60, "F5"),
+    (0x61, "F6"),
+    (0x62, "F7"),
+    (0x63, "F3"),
+    (0x64, "F8"),
+    (0x65, "F9"),
+    (0x67, "F11"),
+    (0x69, "F13"),
+    (0x6a, "F16"),
+    (0x6b, "F14"),
+    (0x6d, "F10"),
+    (0x6f, "F12"),
+    (0x71, "F15"),
+    (0x72, "Help"),
+    (0x73, "Home"),
+    (0x74, "Page Up"),
+    (0x75, "Forward Delete"),
+    (0x76, "F4"),
+    (0x77, "End"),
+    (0x78, "F2"),
+    (0x79, "Page Down"),
+    (0x7a, "F1"),
+    (0x7b, "Left Arrow"),
+    (0x7c, "Right Arrow"),
+    (0x7d, "Down Arrow"),
+    (0x7e, "Up Arrow"),
+];
+
+/// A key combo as macOS menus show it, such as "⌃⌥⌘M": the key by its
+/// character where it has one, else by its name on a US layout.
+pub(crate) fn shortcut_label(
+    keycode: Option<u16>,
+    key: Option<char>,
+    modifiers: ModifierMask,
+) -> String {
+    let mut label = modifier_symbols(modifiers);
+    let name = KEY_NAMES
+        .iter()
+        .find(|(code, _)| Some(*code) == keycode)
+        .map(|(_, name)| *name);
+    match (key, name) {
+        (Some(' '), _) => label.push_str("Space"),
+        (Some(key), _) => label.extend(key.to_uppercase()),
+        (None, Some(name)) => label.push_str(name),
+        (None, None) => label.push_str(&format!("Key {}", keycode.unwrap_or_default())),
+    }
+    label
+}
+
+/// `modifiers` as their menu symbols, in the order menus list them.
+pub(crate) fn modifier_symbols(modifiers: ModifierMask) -> String {
+    let symbols = [
+        (Modifier::Control, "⌃"),
+        (Modifier::Option, "⌥"),
+        (Modifier::Shift, "⇧"),
+        (Modifier::Command, "⌘"),
+    ];
+    symbols
+        .iter()
+        .filter(|(modifier, _)| modifiers & modifier_mask(&[*modifier]) != 0)
+        .map(|(_, symbol)| *symbol)
+        .collect()
+}
+
+/// Every action without a parameter, for looking up identifiers and names.
+pub(crate) const PLAIN_ACTIONS: [Action; 18] = [
+    Action::Default,
+    Action::Disabled,
+    Action::Back,
+    Action::Forward,
+    Action::MiddleClick,
+    Action::DoubleClick,
+    Action::CycleDpi,
+    Action::NextProfile,
+    Action::PreviousProfile,
+    Action::NextTab,
+    Action::PreviousTab,
+    Action::ToggleFocus,
+    Action::QuickLook,
+    Action::LookUp,
+    Action::SwipeBack,
+    Action::SwipeForward,
+    Action::PreviousSpace,
+    Action::NextSpace,
+];
+
+impl Action {
+    /// The identifier settings version 2 stored this action as. Shortcuts
+    /// came later and only have their type.
+    pub(crate) fn id(self) -> String {
+        let id = match self {
+            Action::Default => "default",
+            Action::Disabled => "disabled",
+            Action::Back => "back",
+            Action::Forward => "forward",
+            Action::MiddleClick => "middle_click",
+            Action::DoubleClick => "double_click",
+            Action::CycleDpi => "cycle_dpi",
+            Action::SetDpi { dpi } => return format!("set_dpi:{}", dpi),
+            Action::NextProfile => "next_profile",
+            Action::PreviousProfile => "previous_profile",
+            Action::NextTab => "next_tab",
+            Action::PreviousTab => "previous_tab",
+            Action::ToggleFocus => "toggle_focus",
+            Action::QuickLook => "quick_look",
+            Action::LookUp => "look_up",
+            Action::SwipeBack => "swipe_back",
+            Action::SwipeForward => "swipe_forward",
+            Action::PreviousSpace => "previous_space",
+            Action::NextSpace => "next_space",
+            Action::Shortcut { .. } => "shortcut",
+        };
+        id.to_string()
+    }
+
+    pub(crate) fn from_id(id: &str) -> Option<Self> {
+        if let Some(dpi) = id.strip_prefix("set_dpi:") {
+            return dpi.parse().ok().map(|dpi| Action::SetDpi { dpi });
+        }
+        PLAIN_ACTIONS.into_iter().find(|action| action.id() == id)
+    }
+
+    /// Reads a display name as settings stored it before identifiers,
+    /// `"Set DPI <value>"` included.
+    pub(crate) fn from_name(name: &str) -> Option<Self> {
+        if let Some(dpi) = name.strip_prefix("Set DPI ") {
+            return dpi.trim().parse().ok().map(|dpi| Action::SetDpi { dpi });
+        }
+        PLAIN_ACTIONS
+            .into_iter()
+            .find(|action| action.name() == name)
+    }
+
+    /// Name shown for this action, as in the `button-event` log.
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            Action::Default => "Default",
+            Action::Disabled => "Disabled",
+            Action::Back => "Back",
+            Action::Forward => "Forward",
+            Action::MiddleClick => "Middle Click",
+            Action::DoubleClick => "Double Click",
+            Action::CycleDpi => "Cycle DPI",
+            Action::SetDpi { .. } => "Set DPI",
+            Action::NextProfile => "Next Profile",
+            Action::PreviousProfile => "Previous Profile",
+            Action::NextTab => "Next Tab",
+            Action::PreviousTab => "Previous Tab",
+            Action::ToggleFocus => "Toggle Focus",
+            Action::QuickLook => "Quick Look",
+            Action::LookUp => "Look Up",
+            Action::SwipeBack => "Swipe Back",
+            Action::SwipeForward => "Swipe Forward",
+            Action::PreviousSpace => "Previous Space",
+            Action::NextSpace => "Next Space",
+            Action::Shortcut { .. } => "Shortcut",
+        }
+    }
+
+    /// Whether this posts keystrokes, which secure input keeps from
+    /// arriving whole. On macOS Back and Forward are Cmd+[ and Cmd+]. The
+    /// swipes do where they can't be posted as gestures.
+    pub(crate) fn posts_keys(self) -> bool {
+        match self {
+            Action::Back
+            | Action::Forward
+            | Action::NextTab
+            | Action::PreviousTab
+            | Action::QuickLook
+            | Action::LookUp
+            | Action::Shortcut { .. } => true,
+            _ if self.swipe().is_some() => swipe_strategy() == SwipeStrategy::Keys,
+            _ => false,
+        }
+    }
+
+    /// The swipe this stands in for, and whether it goes forward, to the
+    /// next page or the space on the right.
+    pub(crate) fn swipe(self) -> Option<(Swipe, bool)> {
+        match self {
+            Action::SwipeBack => Some((Swipe::Pages, false)),
+            Action::SwipeForward => Some((Swipe::Pages, true)),
+            Action::PreviousSpace => Some((Swipe::Spaces, false)),
+            Action::NextSpace => Some((Swipe::Spaces, true)),
+            _ => None,
+        }
+    }
+
+    /// Whether holding the button fires this again and again.
+    pub(crate) fn repeats_while_held(self) -> bool {
+        matches!(self, Action::NextTab | Action::PreviousTab)
+    }
+
+    /// Whether `test_action` asks for `confirm` before running this, for
+    /// actions with effects beyond input that can't be taken back. None of
+    /// today's do.
+    pub(crate) fn needs_confirmation(self) -> bool {
+        match self {
+            Action::Default
+            | Action::Disabled
+            | Action::Back
+            | Action::Forward
+            | Action::MiddleClick
+            | Action::DoubleClick
+            | Action::CycleDpi
+            | Action::SetDpi { .. }
+            | Action::NextProfile
+            | Action::PreviousProfile
+            | Action::NextTab
+            | Action::PreviousTab
+            | Action::ToggleFocus
+            | Action::QuickLook
+            | Action::LookUp
+            | Action::SwipeBack
+            | Action::SwipeForward
+            | Action::PreviousSpace
+            | Action::NextSpace
+            | Action::Shortcut { .. } => false,
+        }
+    }
+}
+
+/// Serializes a `ModifierMask` as the list of its `Modifier`s.
+pub(crate) mod modifier_list {
+    use super::{modifier_mask, Modifier, ModifierMask, MODIFIERS};
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S: Serializer>(mask: &ModifierMask, serializer: S) -> Result<S::Ok, S::Error> {
+        let modifiers: Vec<Modifier> = MODIFIERS
+            .into_iter()
+            .filter(|modifier| mask & modifier_mask(&[*modifier]) != 0)
+            .collect();
+        modifiers.serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<ModifierMask, D::Error> {
+        Vec::<Modifier>::deserialize(deserializer).map(|modifiers| modifier_mask(&modifiers))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn actions_read_names_and_keep_unknown_identifiers() {
+        let value = |json: &str| serde_json::from_str::<ActionValue>(json).unwrap();
+        assert_eq!(
+            value(r#""middle_click""#),
+            ActionValue::Action(Action::MiddleClick)
+        );
+        assert_eq!(
+            value(r#""Middle Click""#),
+            ActionValue::Action(Action::MiddleClick)
+        );
+        assert_eq!(
+            value(r#""set_dpi:800""#),
+            ActionValue::Action(Action::SetDpi { dpi: 800 })
+        );
+        assert_eq!(
+            value(r#""Set DPI 800""#),
+            ActionValue::Action(Action::SetDpi { dpi: 800 })
+        );
+        // Case matters, as it did for display names.
+        let unknown = value(r#""middle click""#);
+        assert_eq!(unknown, ActionValue::Unknown("middle click".into()));
+        assert_eq!(unknown.action(), Action::Default);
+        assert_eq!(
+            ResolvedAction::from(&unknown),
+            ResolvedAction::Action(Action::Default)
+        );
+
+        for action in PLAIN_ACTIONS
+            .into_iter()
+            .chain([Action::SetDpi { dpi: 1600 }])
+        {
+            let json = serde_json::to_string(&action).unwrap();
+            assert_eq!(
+                serde_json::from_str::<Action>(&json).unwrap(),
+                action,
+                "{}",
+                json
+            );
+        }
+        assert_eq!(
+            serde_json::to_string(&unknown).unwrap(),
+            r#""middle click""#
+        );
+        assert_eq!(
+            serde_json::to_string(&ActionValue::Action(Action::DoubleClick)).unwrap(),
+            r#"{"type":"double_click"}"#
+        );
+    }
+
+    #[test]
+    fn action_payloads_round_trip_and_migrate() {
+        let value = |json: &str| serde_json::from_str::<ActionValue>(json).unwrap();
+        let shortcut = ActionValue::Action(Action::Shortcut {
+            keycode: Some(40),
+            key: None,
+            modifiers: modifier_mask(&[Modifier::Command, Modifier::Shift]),
+        });
+        assert_eq!(
+            value(r#"{"type": "shortcut", "keycode": 40, "modifiers": ["cmd", "shift"]}"#),
+            shortcut
+        );
+        assert_eq!(
+            serde_json::to_value(&shortcut).unwrap(),
+            serde_json::json!({"type": "shortcut", "keycode": 40, "modifiers": ["shift", "command"]})
+        );
+        assert_eq!(
+            value(r#"{"type": "shortcut", "keycode": 12}"#),
+            ActionValue::Action(Action::Shortcut {
+                keycode: Some(12),
+                key: None,
+                modifiers: 0
+            })
+        );
+        assert_eq!(
+            value(r#"{"type": "set_dpi", "dpi": 800}"#),
+            ActionValue::Action(Action::SetDpi { dpi: 800 })
+        );
+
+        // Unknown types and bad parameters are kept as written.
+        for json in [
+            r#"{"type": "launch_app", "path": "/Applications/Notes.app"}"#,
+            r#"{"type": "set_dpi"}"#,
+            r#"{"type": "shortcut", "keycode": 40, "modifiers": ["hyper"]}"#,
+            r#"{"keycode": 40}"#,
+            "7",
+        ] {
+            let unknown = value(json);
+            assert!(matches!(unknown, ActionValue::Unknown(_)), "{}", json);
+            assert_eq!(
+                serde_json::to_value(&unknown).unwrap(),
+                serde_json::from_str::<serde_json::Value>(json).unwrap()
+            );
+        }
+
+        // Version 2 gestures and string actions come back tagged.
+        let legacy =
+            value(r#"{"gesture": {"tap": "set_dpi:400", "up": "back", "threshold": 30.0}}"#);
+        let ActionValue::Gesture { gesture } = &legacy else {
+            panic!("not a gesture: {:?}", legacy);
+        };
+        assert_eq!(gesture.tap.action(), Action::SetDpi { dpi: 400 });
+        assert_eq!(gesture.up.action(), Action::Back);
+        let saved = serde_json::to_value(&legacy).unwrap();
+        assert_eq!(saved["type"], "gesture");
+        assert_eq!(
+            saved["tap"],
+            serde_json::json!({"type": "set_dpi", "dpi": 400})
+        );
+        assert_eq!(saved["threshold"], 30.0);
+        assert_eq!(
+            serde_json::from_value::<ActionValue>(saved).unwrap(),
+            legacy
+        );
+        assert!(
+            serde_json::from_str::<ActionValue>(r#"{"type": "gesture", "threshold": "far"}"#)
+                .is_err()
+        );
+
+        let v2 = r#"{
+            "version": 2,
+            "profiles": {"Default": {"devices": {"046d:c08b:A": {
+                "buttons": {"button4": "back", "button5": "Set DPI 1600"},
+                "modifier_mappings": {"button4": [{"modifiers": ["shift"], "action": "cycle_dpi"}]}
+            }}}}
+        }"#;
+        let settings = parse_settings(v2).unwrap();
+        assert_eq!(settings.version, SETTINGS_VERSION);
+        let config = settings.device_config("046d:c08b:A").unwrap();
+        assert_eq!(
+            config.buttons["button5"].action(),
+            Action::SetDpi { dpi: 1600 }
+        );
+        assert_eq!(
+            config.mapping(3, 5, None, modifier_mask(&[Modifier::Shift])),
+            Some(&ActionValue::Action(Action::CycleDpi))
+        );
+        let saved = serde_json::to_value(&settings).unwrap();
+        let buttons = &saved["profiles"]["Default"]["devices"]["046d:c08b:A"]["buttons"];
+        assert_eq!(buttons["button4"], serde_json::json!({"type": "back"}));
+        assert_eq!(
+            parse_settings(&saved.to_string())
+                .unwrap()
+                .device_config("046d:c08b:A")
+                .unwrap()
+                .buttons,
+            config.buttons
+        );
+
+        // The resolved cache keeps the payload.
+        assert_eq!(
+            ResolvedAction::from(&shortcut),
+            ResolvedAction::Action(shortcut.action())
+        );
+    }
+
+    #[test]
+    fn modifier_mappings_match_exactly_and_fall_through() {
+        let json = r#"{
+            "buttons":{"button4":"Back"},
+            "modifier_mappings":{"button4":[
+                {"modifiers":["shift"],"action":"Forward"},
+                {"modifiers":["command","shift"],"action":"Middle Click"}
+            ]}
+        }"#;
+        let config: DeviceConfig = serde_json::from_str(json).unwrap();
+        let action = |held: &[Modifier]| {
+            config
+                .mapping(3, 5, None, modifier_mask(held))
+                .map_or(Action::Default, ActionValue::action)
+        };
+        assert_eq!(action(&[]), Action::Back);
+        assert_eq!(action(&[Modifier::Shift]), Action::Forward);
+        assert_eq!(
+            action(&[Modifier::Shift, Modifier::Command]),
+            Action::MiddleClick
+        );
+        // Combinations without an entry fall through to the plain mapping.
+        assert_eq!(action(&[Modifier::Control]), Action::Back);
+        assert_eq!(action(&[Modifier::Shift, Modifier::Option]), Action::Back);
+    }
+}
@@ -0,0 +1,380 @@
+use super::*;
+
+/// Remapping software that is known to act on the same buttons.
+struct KnownRemapper {
+    /// `Conflict::id`, the same on every platform.
+    id: &'static str,
+    name: &'static str,
+    /// Bundle ids on macOS, process names elsewhere, of any of its
+    /// programs.
+    programs: &'static [&'static str],
+    guidance: &'static str,
+}
+
+const KNOWN_REMAPPERS: [KnownRemapper; 12] = [
+    KnownRemapper {
+        id: "karabiner-elements",
+        name: "Karabiner-Elements",
+        programs: &[
+            "org.pqrs.Karabiner-Menu",
+            "org.pqrs.Karabiner-NotificationWindow",
+            "org.pqrs.Karabiner-Elements.Settings",
+        ],
+        guidance: "Karabiner-Elements can remap mouse buttons too. Leave the mouse unchecked under Devices in its settings, or remove its rules for the buttons you map here.",
+    },
+    KnownRemapper {
+        id: "logi-options",
+        name: "Logi Options+",
+        programs: &[
+            "com.logi.optionsplus",
+            "com.logi.optionsplus.agent",
+            "com.logitech.manager.daemon",
+            "logioptionsplus_agent.exe",
+            "LogiOptionsMgr.exe",
+        ],
+        guidance: "Logi Options+ assigns the buttons of Logitech mice itself. Set the buttons you map here back to their default in Options+, or quit it.",
+    },
+    KnownRemapper {
+        id: "logitech-g-hub",
+        name: "Logitech G HUB",
+        programs: &["com.logi.ghub", "lghub_agent.exe"],
+        guidance: "G HUB assigns the buttons of Logitech gaming mice itself. Set the buttons you map here back to their default in G HUB, or quit it.",
+    },
+    KnownRemapper {
+        id: "bettertouchtool",
+        name: "BetterTouchTool",
+        programs: &["com.hegenberg.BetterTouchTool"],
+        guidance: "BetterTouchTool can trigger actions from mouse buttons. Remove its Normal Mouse triggers for the buttons you map here.",
+    },
+    KnownRemapper {
+        id: "steermouse",
+        name: "SteerMouse",
+        programs: &["jp.plentycom.boa.SteerMouse", "jp.plentycom.boa.SteerMouse.Agent"],
+        guidance: "SteerMouse takes over the buttons of every mouse. Set the buttons you map here back to their default in SteerMouse, or quit it.",
+    },
+    KnownRemapper {
+        id: "linearmouse",
+        name: "LinearMouse",
+        programs: &["com.lujjjh.LinearMouse"],
+        guidance: "LinearMouse remaps buttons too. Import its mappings here, then remove them from LinearMouse or quit it.",
+    },
+    KnownRemapper {
+        id: "mac-mouse-fix",
+        name: "Mac Mouse Fix",
+        programs: &["com.nuebling.mac-mouse-fix.helper"],
+        guidance: "Mac Mouse Fix remaps the middle and side buttons. Turn it off, or leave those buttons at their default there.",
+    },
+    KnownRemapper {
+        id: "x-mouse-button-control",
+        name: "X-Mouse Button Control",
+        programs: &["XMouseButtonControl.exe"],
+        guidance: "X-Mouse Button Control remaps the buttons of every mouse. Set the buttons you map here to Default in its layers, or quit it.",
+    },
+    KnownRemapper {
+        id: "logiops",
+        name: "logiops",
+        programs: &["logid"],
+        guidance: "logiops' logid remaps the buttons of Logitech mice. Leave the buttons you map here out of its config, or stop logid.",
+    },
+    KnownRemapper {
+        id: "input-remapper",
+        name: "Input Remapper",
+        programs: &["input-remapper-service"],
+        guidance: "Input Remapper may have a preset applied to the mouse. Stop its injection for the mouse, or map its buttons there instead.",
+    },
+    KnownRemapper {
+        id: "imwheel",
+        name: "imwheel",
+        programs: &["imwheel"],
+        guidance: "imwheel turns the side buttons into keys unless started with -b. Start it with `imwheel -b 45`, or stop it.",
+    },
+    KnownRemapper {
+        id: "xbindkeys",
+        name: "xbindkeys",
+        programs: &["xbindkeys"],
+        guidance: "xbindkeys may run commands on mouse buttons. Remove the b: bindings for the buttons you map here.",
+    },
+];
+
+/// Longest process name Linux keeps in `/proc/<pid>/comm`.
+const COMM_LEN: usize = 15;
+
+/// A program running now.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct RunningProgram {
+    /// Bundle id on macOS, process name elsewhere.
+    pub(crate) id: String,
+    pub(crate) name: String,
+    /// It has an active event tap on mouse buttons.
+    pub(crate) mouse_tap: bool,
+}
+
+/// Another program that may remap the buttons Edit Mouse does, so presses
+/// get remapped twice.
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub(crate) struct Conflict {
+    /// What `Settings::ignored_conflicts` holds: a `KNOWN_REMAPPERS` id, or
+    /// the bundle id of an unknown program tapping mouse events.
+    pub(crate) id: String,
+    pub(crate) name: String,
+    /// What to do about it.
+    pub(crate) guidance: String,
+    /// Seen tapping mouse buttons, not just running.
+    pub(crate) event_tap: bool,
+    /// `Settings::ignored_conflicts` lists it.
+    pub(crate) ignored: bool,
+}
+
+/// The conflicts among `running`: each known remapper with a program of
+/// its own running, then any other program tapping mouse buttons.
+pub(crate) fn find(running: &[RunningProgram], ignored: &[String]) -> Vec<Conflict> {
+    let is_ignored = |id: &str| ignored.iter().any(|listed| listed == id);
+    let mut conflicts: Vec<Conflict> = KNOWN_REMAPPERS
+        .iter()
+        .filter_map(|known| {
+            let programs: Vec<_> = running
+                .iter()
+                .filter(|program| known.runs(program))
+                .collect();
+            (!programs.is_empty()).then(|| Conflict {
+                id: known.id.to_string(),
+                name: known.name.to_string(),
+                guidance: known.guidance.to_string(),
+                event_tap: programs.iter().any(|program| program.mouse_tap),
+                ignored: is_ignored(known.id),
+            })
+        })
+        .collect();
+    let unknown = running.iter().filter(|program| {
+        program.mouse_tap && !KNOWN_REMAPPERS.iter().any(|known| known.runs(program))
+    });
+    for program in unknown {
+        if conflicts.iter().any(|conflict| conflict.id == program.id) {
+            continue;
+        }
+        conflicts.push(Conflict {
+            id: program.id.clone(),
+            name: program.name.clone(),
+            guidance: format!(
+                "{} watches mouse buttons and may change them. If a button acts twice, turn off its mouse settings or quit it.",
+                program.name
+            ),
+            event_tap: true,
+            ignored: is_ignored(&program.id),
+        });
+    }
+    conflicts
+}
+
+impl KnownRemapper {
+    /// Whether `program` is one of its programs. Windows' process names
+    /// don't keep their case, and Linux cuts them short.
+    fn runs(&self, program: &RunningProgram) -> bool {
+        self.programs.iter().any(|known| {
+            program.id.eq_ignore_ascii_case(known)
+                || (cfg!(target_os = "linux")
+                    && known.len() > COMM_LEN
+                    && program.id.eq_ignore_ascii_case(&known[..COMM_LEN]))
+        })
+    }
+}
+
+/// What's running and which of it taps mouse buttons, on this system.
+pub(crate) fn detect(ignored: &[String]) -> Vec<Conflict> {
+    find(&running_programs(), ignored)
+}
+
+/// Applications with a bundle id, and which of them have an active event
+/// tap listening for mouse buttons in `CGGetEventTapList`.
+#[cfg(target_os = "macos")]
+fn running_programs() -> Vec<RunningProgram> {
+    use objc2_app_kit::{NSRunningApplication, NSWorkspace};
+
+    /// Laid out as `CGEventTapInformation`.
+    #[repr(C)]
+    #[derive(Debug, Default, Clone, Copy)]
+    #[allow(dead_code)]
+    struct EventTapInformation {
+        event_tap_id: u32,
+        tap_point: u32,
+        options: u32,
+        events_of_interest: u64,
+        tapping_process: i32,
+        process_being_tapped: i32,
+        enabled: bool,
+        min_usec_latency: f32,
+        avg_usec_latency: f32,
+        max_usec_latency: f32,
+    }
+
+    /// `kCGEventTapOptionDefault`: a tap that can change events, unlike a
+    /// listen-only one.
+    const TAP_OPTION_DEFAULT: u32 = 0;
+    /// Left, right, and other mouse downs and ups, as `CGEventMaskBit`s.
+    const MOUSE_BUTTON_EVENTS: u64 =
+        (1 << 1) | (1 << 2) | (1 << 3) | (1 << 4) | (1 << 25) | (1 << 26);
+
+    #[link(name = "CoreGraphics", kind = "framework")]
+    extern "C" {
+        fn CGGetEventTapList(max: u32, taps: *mut EventTapInformation, count: *mut u32) -> i32;
+    }
+
+    let own = i32::try_from(std::process::id()).unwrap_or_default();
+    let mut count = 0u32;
+    let mut taps = Vec::new();
+    if unsafe { CGGetEventTapList(0, std::ptr::null_mut(), &mut count) } == 0 && count > 0 {
+        taps = vec![EventTapInformation::default(); count as usize];
+        if unsafe { CGGetEventTapList(count, taps.as_mut_ptr(), &mut count) } == 0 {
+            taps.truncate(count as usize);
+        } else {
+            taps.clear();
+        }
+    }
+    let tapping: Vec<i32> = taps
+        .iter()
+        .filter(|tap| {
+            tap.enabled
+                && tap.options == TAP_OPTION_DEFAULT
+                && tap.events_of_interest & MOUSE_BUTTON_EVENTS != 0
+                && tap.tapping_process != own
+        })
+        .map(|tap| tap.tapping_process)
+        .collect();
+    let program = |app: &NSRunningApplication| {
+        let id = app.bundleIdentifier()?.to_string();
+        let pid = app.processIdentifier();
+        let name = app
+            .localizedName()
+            .map_or_else(|| id.clone(), |name| name.to_string());
+        Some(RunningProgram {
+            id,
+            name,
+            mouse_tap: tapping.contains(&pid),
+        })
+    };
+    NSWorkspace::sharedWorkspace()
+        .runningApplications()
+        .iter()
+        .filter(|app| app.processIdentifier() != own)
+        .filter_map(|app| program(&app))
+        .collect()
+}
+
+/// Process names from `/proc`. Whether they tap mouse events can't be
+/// told.
+#[cfg(target_os = "linux")]
+fn running_programs() -> Vec<RunningProgram> {
+    let Ok(entries) = fs::read_dir("/proc") else {
+        return Vec::new();
+    };
+    let mut names: Vec<String> = entries
+        .flatten()
+        .filter(|entry| entry.file_name().to_string_lossy().parse::<u32>().is_ok())
+        .filter_map(|entry| fs::read_to_string(entry.path().join("comm")).ok())
+        .map(|comm| comm.trim_end().to_string())
+        .collect();
+    names.sort();
+    names.dedup();
+    names
+        .into_iter()
+        .map(|name| RunningProgram {
+            id: name.clone(),
+            name,
+            mouse_tap: false,
+        })
+        .collect()
+}
+
+/// Image names from `tasklist`. Whether they hook the mouse can't be told.
+#[cfg(windows)]
+fn running_programs() -> Vec<RunningProgram> {
+    let mut command = std::process::Command::new("tasklist");
+    command.args(["/fo", "csv", "/nh"]);
+    // Keeps a console window from flashing up.
+    std::os::windows::process::CommandExt::creation_flags(&mut command, 0x0800_0000);
+    let output = match command.output() {
+        Ok(output) if output.status.success() => output,
+        Ok(output) => {
+            log::warn!("conflicts: tasklist exited with {}", output.status);
+            return Vec::new();
+        }
+        Err(err) => {
+            log::warn!("conflicts: couldn't run tasklist: {}", err);
+            return Vec::new();
+        }
+    };
+    let mut names: Vec<String> = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| line.strip_prefix('"')?.split('"').next())
+        .map(str::to_string)
+        .collect();
+    names.sort();
+    names.dedup();
+    names
+        .into_iter()
+        .map(|name| RunningProgram {
+            id: name.clone(),
+            name,
+            mouse_tap: false,
+        })
+        .collect()
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "linux", windows)))]
+fn running_programs() -> Vec<RunningProgram> {
+    Vec::new()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn program(id: &str, mouse_tap: bool) -> RunningProgram {
+        RunningProgram {
+            id: id.to_string(),
+            name: id.to_string(),
+            mouse_tap,
+        }
+    }
+
+    #[test]
+    fn known_remappers_and_unknown_mouse_taps_conflict() {
+        let running = [
+            program("com.apple.Safari", false),
+            program("com.hegenberg.BetterTouchTool", true),
+            program("org.pqrs.Karabiner-Menu", false),
+            program("org.pqrs.Karabiner-NotificationWindow", false),
+            program("com.example.Clicker", true),
+        ];
+        let ignored = ["karabiner-elements".to_string()];
+        let conflicts = find(&running, &ignored);
+        let found: Vec<(&str, bool, bool)> = conflicts
+            .iter()
+            .map(|conflict| (conflict.id.as_str(), conflict.event_tap, conflict.ignored))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                ("karabiner-elements", false, true),
+                ("bettertouchtool", true, false),
+                ("com.example.Clicker", true, false),
+            ]
+        );
+        assert!(conflicts[2]
+            .guidance
+            .starts_with("com.example.Clicker watches"));
+        assert!(find(&[program("com.apple.Safari", false)], &[]).is_empty());
+        assert_eq!(
+            find(&[program("XMOUSEBUTTONCONTROL.EXE", false)], &[])[0].name,
+            "X-Mouse Button Control"
+        );
+        if cfg!(target_os = "linux") {
+            assert_eq!(
+                find(&[program("input-remapper-", false)], &[])[0].id,
+                "input-remapper"
+            );
+        }
+        let ids: HashSet<&str> = KNOWN_REMAPPERS.iter().map(|known| known.id).collect();
+        assert_eq!(ids.len(), KNOWN_REMAPPERS.len());
+    }
+}
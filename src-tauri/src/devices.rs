@@ -0,0 +1,3191 @@
+use super::*;
+
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum DeviceKind {
+    Mouse,
+    /// A Generic Desktop Pointer with buttons, as some trackballs and
+    /// remotes enumerate.
+    Pointer,
+    Trackball,
+    Presenter,
+    Trackpad,
+    Tablet,
+    Unknown,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub(crate) struct MouseDevice {
+    pub(crate) id: String,
+    pub(crate) name: String,
+    pub(crate) kind: DeviceKind,
+    pub(crate) button_count: u8,
+    /// Built-in preset made for this model, for the settings window to
+    /// suggest.
+    pub(crate) preset: Option<&'static str>,
+    /// Unix time, in seconds, the device was first seen, from its config.
+    pub(crate) first_seen: Option<u64>,
+    /// `DeviceConfig::disabled`.
+    pub(crate) disabled: bool,
+    /// In `Settings::ignored_devices`, so never remapped.
+    pub(crate) ignored: bool,
+    /// Set when another connected unit of the same model looks the same in
+    /// every way, so which is which can't be told. `id` then carries a unit
+    /// number that follows plugging order, not the unit itself.
+    pub(crate) ambiguous: bool,
+}
+
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Transport {
+    Usb,
+    /// USB wireless receiver; still tagged "usb" in device ids.
+    Dongle,
+    Bluetooth,
+    I2c,
+    Spi,
+    Unknown,
+}
+
+impl Transport {
+    pub(crate) fn tag(self) -> &'static str {
+        match self {
+            Transport::Usb | Transport::Dongle => "usb",
+            Transport::Bluetooth => "bt",
+            Transport::I2c => "i2c",
+            Transport::Spi => "spi",
+            Transport::Unknown => "unk",
+        }
+    }
+}
+
+impl From<hidapi::BusType> for Transport {
+    fn from(bus: hidapi::BusType) -> Self {
+        match bus {
+            hidapi::BusType::Usb => Transport::Usb,
+            hidapi::BusType::Bluetooth => Transport::Bluetooth,
+            hidapi::BusType::I2c => Transport::I2c,
+            hidapi::BusType::Spi => Transport::Spi,
+            hidapi::BusType::Unknown => Transport::Unknown,
+        }
+    }
+}
+
+/// Wireless receivers that enumerate like any USB device.
+const RECEIVER_IDS: &[(u16, u16)] = &[
+    (0x046d, 0xc52b), // Logitech Unifying
+    (0x046d, 0xc532), // Logitech Unifying
+    (0x046d, 0xc534), // Logitech Nano
+    (0x046d, 0xc539), // Logitech Lightspeed
+    (0x046d, 0xc53a), // Logitech Lightspeed
+    (0x046d, 0xc53f), // Logitech Lightspeed
+    (0x046d, 0xc547), // Logitech Lightspeed
+    (0x046d, 0xc548), // Logitech Bolt
+];
+
+fn is_receiver(vendor_id: u16, product_id: u16, name: &str) -> bool {
+    RECEIVER_IDS.contains(&(vendor_id, product_id)) || name.to_lowercase().contains("receiver")
+}
+
+type GroupKey<'a> = (u16, u16, std::borrow::Cow<'a, str>);
+
+/// One enumerated HID interface/usage, copied out of `hidapi::DeviceInfo` so
+/// the grouping and filtering logic can run on plain data.
+#[derive(Debug, Clone)]
+pub(crate) struct HidEntry {
+    pub(crate) vendor_id: u16,
+    pub(crate) product_id: u16,
+    pub(crate) serial: Option<String>,
+    pub(crate) name: String,
+    pub(crate) manufacturer: Option<String>,
+    pub(crate) release_number: u16,
+    pub(crate) usage_page: u16,
+    pub(crate) usage: u16,
+    pub(crate) interface_number: i32,
+    pub(crate) transport: Transport,
+    pub(crate) path: String,
+}
+
+impl HidEntry {
+    fn from_info(device: &hidapi::DeviceInfo) -> Self {
+        let name = device
+            .product_string()
+            .map(|value| value.to_string())
+            .or_else(|| device.manufacturer_string().map(|value| value.to_string()))
+            .unwrap_or_else(|| "Unknown Mouse".to_string());
+        let serial = device
+            .serial_number()
+            .map(str::trim)
+            .filter(|serial| !serial.is_empty())
+            .map(str::to_string);
+        let mut transport = Transport::from(device.bus_type());
+        if transport == Transport::Usb
+            && is_receiver(device.vendor_id(), device.product_id(), &name)
+        {
+            transport = Transport::Dongle;
+        }
+        Self {
+            vendor_id: device.vendor_id(),
+            product_id: device.product_id(),
+            serial,
+            name,
+            manufacturer: device.manufacturer_string().map(str::to_string),
+            release_number: device.release_number(),
+            usage_page: device.usage_page(),
+            usage: device.usage(),
+            interface_number: device.interface_number(),
+            transport,
+            path: device.path().to_string_lossy().into_owned(),
+        }
+    }
+
+    fn is_mouse(&self) -> bool {
+        self.usage_page == 0x01 && self.usage == 0x02
+    }
+
+    fn is_pointer(&self) -> bool {
+        self.usage_page == 0x01 && self.usage == 0x01
+    }
+
+    fn is_digitizer(&self) -> bool {
+        self.usage_page == 0x0d && matches!(self.usage, 0x01 | 0x02 | 0x05)
+    }
+
+    fn is_keyboard(&self) -> bool {
+        self.usage_page == 0x01 && matches!(self.usage, 0x06 | 0x07)
+    }
+
+    /// Entries sharing this key belong to the same physical device. Without a
+    /// serial only the part of the platform path naming the device ties its
+    /// collections together.
+    fn group_key(&self) -> GroupKey<'_> {
+        let rest = match self.serial.as_deref() {
+            Some(serial) => std::borrow::Cow::Borrowed(serial),
+            None => physical_path(&self.path),
+        };
+        (self.vendor_id, self.product_id, rest)
+    }
+}
+
+/// The part of a platform path naming the physical device. Windows gives
+/// each top-level collection a path of its own, such as
+/// `\\?\HID#VID_046D&PID_C08B&MI_01&Col02#7&2ca8ec4a&0&0001#{...}`, which
+/// agree once the interface and collection are dropped from the hardware id
+/// and the collection's index from the end of the instance id. Elsewhere the
+/// collections of an interface already share one path.
+fn physical_path(path: &str) -> std::borrow::Cow<'_, str> {
+    let parts: Vec<&str> = path.split('#').collect();
+    let [prefix, hardware, instance, ..] = parts.as_slice() else {
+        return std::borrow::Cow::Borrowed(path);
+    };
+    if !prefix.to_ascii_uppercase().ends_with("HID") {
+        return std::borrow::Cow::Borrowed(path);
+    }
+    let hardware: Vec<&str> = hardware
+        .split('&')
+        .filter(|part| {
+            let part = part.to_ascii_uppercase();
+            !part.starts_with("MI_") && !part.starts_with("COL")
+        })
+        .collect();
+    let parent = instance
+        .rsplit_once('&')
+        .map_or(*instance, |(parent, _)| parent);
+    std::borrow::Cow::Owned(format!("{}#{}#{}", prefix, hardware.join("&"), parent))
+}
+
+/// Opens the HID API. On macOS hidapi seizes devices it opens by default,
+/// which would cut a mouse off from the system while a report is read.
+fn hid_api() -> Result<hidapi::HidApi, String> {
+    let api = hidapi::HidApi::new().map_err(|err| err.to_string())?;
+    #[cfg(target_os = "macos")]
+    api.set_open_exclusive(false);
+    Ok(api)
+}
+
+/// The app's one `HidApi`, opened on first use and refreshed for each one
+/// after, since opening it enumerates every HID device from scratch. Uses
+/// take turns.
+#[derive(Clone, Default)]
+pub(crate) struct SharedHidApi(pub(crate) Arc<Mutex<Option<hidapi::HidApi>>>);
+
+impl SharedHidApi {
+    /// Runs `use_api` with the devices connected now. When refreshing
+    /// fails the instance is opened again, which is all hidapi offers to
+    /// recover.
+    pub(crate) fn with<T>(
+        &self,
+        use_api: impl FnOnce(&hidapi::HidApi) -> Result<T, String>,
+    ) -> Result<T, String> {
+        let mut cached = self
+            .0
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        let api = match cached.take() {
+            Some(mut api) => match api.refresh_devices() {
+                Ok(()) => api,
+                Err(err) => {
+                    log::warn!("hid: refreshing failed, opening again: {}", err);
+                    hid_api()?
+                }
+            },
+            None => hid_api()?,
+        };
+        let used = use_api(&api);
+        *cached = Some(api);
+        used
+    }
+}
+
+pub(crate) fn hid_entries(api: &hidapi::HidApi) -> Vec<HidEntry> {
+    api.device_list().map(HidEntry::from_info).collect()
+}
+
+/// Enumeration switches that come from `Settings`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) struct DeviceFilter {
+    pub(crate) include_all_pointing_devices: bool,
+    pub(crate) show_non_mouse_devices: bool,
+}
+
+impl From<&Settings> for DeviceFilter {
+    fn from(settings: &Settings) -> Self {
+        Self {
+            include_all_pointing_devices: settings.include_all_pointing_devices,
+            show_non_mouse_devices: settings.show_non_mouse_devices,
+        }
+    }
+}
+
+pub(crate) fn list_mouse_devices(
+    hid: &SharedHidApi,
+    filter: DeviceFilter,
+) -> Result<Vec<MouseDevice>, String> {
+    hid.with(|api| {
+        let entries = hid_entries(api);
+        Ok(collect_mouse_devices(
+            &entries,
+            filter,
+            descriptor_probe(api),
+        ))
+    })
+}
+
+pub(crate) fn descriptor_probe(api: &hidapi::HidApi) -> impl Fn(&HidEntry) -> Option<Vec<u8>> + '_ {
+    move |entry: &HidEntry| read_report_descriptor(api, &entry.path)
+}
+
+pub(crate) fn read_report_descriptor(api: &hidapi::HidApi, path: &str) -> Option<Vec<u8>> {
+    let path = CString::new(path).ok()?;
+    let device = api.open_path(&path).ok()?;
+    let mut buf = vec![0u8; hidapi::MAX_REPORT_DESCRIPTOR_SIZE];
+    let len = device.get_report_descriptor(&mut buf).ok()?;
+    buf.truncate(len);
+    Some(buf)
+}
+
+/// Walks the short items of a HID report descriptor, yielding the item
+/// prefix with its size bits cleared (e.g. `0x04` Usage Page, `0x08` Usage),
+/// the data size in bytes, and the little-endian data value. Long items are
+/// skipped.
+fn descriptor_items(descriptor: &[u8]) -> Vec<(u8, usize, u32)> {
+    let mut items = Vec::new();
+    let mut index = 0;
+    while index < descriptor.len() {
+        let prefix = descriptor[index];
+        if prefix == 0xfe {
+            let size = descriptor.get(index + 1).copied().unwrap_or(0) as usize;
+            index += 3 + size;
+            continue;
+        }
+        let size = match prefix & 0x03 {
+            3 => 4,
+            size => size as usize,
+        };
+        let Some(data) = descriptor.get(index + 1..index + 1 + size) else {
+            break;
+        };
+        let value = data
+            .iter()
+            .rev()
+            .fold(0u32, |value, byte| (value << 8) | u32::from(*byte));
+        items.push((prefix & 0xfc, size, value));
+        index += 1 + size;
+    }
+    items
+}
+
+/// True when the descriptor declares Generic Desktop X and Y usages.
+pub(crate) fn descriptor_has_pointer_axes(descriptor: &[u8]) -> bool {
+    let mut usage_page = 0u32;
+    let mut has_x = false;
+    let mut has_y = false;
+    for (item, size, value) in descriptor_items(descriptor) {
+        match item {
+            0x04 => usage_page = value,
+            0x08 => {
+                let (page, usage) = if size == 4 {
+                    (value >> 16, value & 0xffff)
+                } else {
+                    (usage_page, value)
+                };
+                if page == 0x01 {
+                    has_x |= usage == 0x30;
+                    has_y |= usage == 0x31;
+                }
+            }
+            _ => {}
+        }
+    }
+    has_x && has_y
+}
+
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum ReportKind {
+    Input,
+    Output,
+    Feature,
+}
+
+/// One main item of a report descriptor: `count` elements of `bit_size` bits
+/// starting at `bit_offset` within the report (after the report id byte).
+#[derive(Debug, Serialize, Clone)]
+pub(crate) struct ReportField {
+    pub(crate) kind: ReportKind,
+    pub(crate) report_id: u8,
+    pub(crate) bit_offset: u32,
+    pub(crate) bit_size: u32,
+    pub(crate) count: u32,
+    /// Full usages (`page << 16 | id`), one per element; the last one repeats
+    /// when the descriptor lists fewer usages than elements.
+    pub(crate) usages: Vec<u32>,
+    pub(crate) logical_min: i32,
+    pub(crate) logical_max: i32,
+    /// Variable (one value per usage) rather than array (values are usage
+    /// indices) data.
+    pub(crate) variable: bool,
+}
+
+impl ReportField {
+    pub(crate) fn contains_usage(&self, usage: u32) -> bool {
+        self.usages.contains(&usage)
+    }
+
+    /// Reads element `element` out of a report payload (report id stripped).
+    pub(crate) fn read(&self, payload: &[u8], element: u32) -> Option<i64> {
+        let start = self.bit_offset + element * self.bit_size;
+        if self.bit_size == 0 || self.bit_size > 32 {
+            return None;
+        }
+        let mut value: u64 = 0;
+        for bit in 0..self.bit_size {
+            let position = start + bit;
+            let byte = *payload.get((position / 8) as usize)?;
+            if byte & (1 << (position % 8)) != 0 {
+                value |= 1 << bit;
+            }
+        }
+        if self.logical_min < 0 && value & (1 << (self.bit_size - 1)) != 0 {
+            Some(value as i64 - (1i64 << self.bit_size))
+        } else {
+            Some(value as i64)
+        }
+    }
+}
+
+fn sign_extend(value: u32, size: usize) -> i32 {
+    match size {
+        1 => value as u8 as i8 as i32,
+        2 => value as u16 as i16 as i32,
+        _ => value as i32,
+    }
+}
+
+/// Parses input/output/feature fields out of a report descriptor, tracking
+/// global state (including push/pop), local usages, and per-report offsets.
+pub(crate) fn parse_report_fields(descriptor: &[u8]) -> Vec<ReportField> {
+    #[derive(Clone, Copy, Default)]
+    struct Globals {
+        usage_page: u32,
+        logical_min: i32,
+        logical_max: i32,
+        report_size: u32,
+        report_count: u32,
+        report_id: u8,
+    }
+    const MAX_EXPANDED_USAGES: u32 = 1024;
+
+    let mut globals = Globals::default();
+    let mut stack: Vec<Globals> = Vec::new();
+    let mut usages: Vec<u32> = Vec::new();
+    let mut usage_min: Option<u32> = None;
+    let mut offsets: HashMap<(u8, u8), u32> = HashMap::new();
+    let mut fields = Vec::new();
+
+    let full_usage = |page: u32, size: usize, value: u32| {
+        if size == 4 {
+            value
+        } else {
+            (page << 16) | value
+        }
+    };
+
+    for (item, size, value) in descriptor_items(descriptor) {
+        match item {
+            0x04 => globals.usage_page = value,
+            0x14 => globals.logical_min = sign_extend(value, size),
+            0x24 => {
+                globals.logical_max = if globals.logical_min < 0 {
+                    sign_extend(value, size)
+                } else {
+                    value as i32
+                }
+            }
+            0x74 => globals.report_size = value,
+            0x84 => globals.report_id = value as u8,
+            0x94 => globals.report_count = value,
+            0xa4 => stack.push(globals),
+            0xb4 => globals = stack.pop().unwrap_or(globals),
+            0x08 => usages.push(full_usage(globals.usage_page, size, value)),
+            0x18 => usage_min = Some(full_usage(globals.usage_page, size, value)),
+            0x28 => {
+                if let Some(min) = usage_min.take() {
+                    let max = full_usage(globals.usage_page, size, value);
+                    let max = max.min(min.saturating_add(MAX_EXPANDED_USAGES));
+                    usages.extend(min..=max);
+                }
+            }
+            0x80 | 0x90 | 0xb0 => {
+                let kind = match item {
+                    0x80 => ReportKind::Input,
+                    0x90 => ReportKind::Output,
+                    _ => ReportKind::Feature,
+                };
+                let kind_tag = item;
+                let offset = offsets.entry((kind_tag, globals.report_id)).or_insert(0);
+                fields.push(ReportField {
+                    kind,
+                    report_id: globals.report_id,
+                    bit_offset: *offset,
+                    bit_size: globals.report_size,
+                    count: globals.report_count,
+                    usages: std::mem::take(&mut usages),
+                    logical_min: globals.logical_min,
+                    logical_max: globals.logical_max,
+                    variable: value & 0x02 != 0,
+                });
+                *offset += globals.report_size * globals.report_count;
+                usage_min = None;
+            }
+            0xa0 | 0xc0 => {
+                usages.clear();
+                usage_min = None;
+            }
+            _ => {}
+        }
+    }
+    fields
+}
+
+/// Number of buttons declared by the input reports of a descriptor: the
+/// highest Button page usage, bounded by the report slots that carry them.
+pub(crate) fn descriptor_button_count(descriptor: &[u8]) -> Option<u8> {
+    let (highest, slots) = parse_report_fields(descriptor)
+        .iter()
+        .filter(|field| field.kind == ReportKind::Input)
+        .fold((0u32, 0u32), |(highest, slots), field| {
+            let buttons = field.usages.iter().filter(|usage| *usage >> 16 == 0x09);
+            match buttons.map(|usage| usage & 0xffff).max() {
+                Some(max) => (highest.max(max), slots + field.count),
+                None => (highest, slots),
+            }
+        });
+    let count = highest.min(slots);
+    (count > 0).then(|| count.min(u32::from(u8::MAX)) as u8)
+}
+
+/// Generic Desktop X, which marks the pointer report the OS already decodes.
+const USAGE_POINTER_X: u32 = 0x0001_0030;
+
+/// Consumer page AC Back / AC Forward, which some receivers send instead of
+/// mouse buttons 4 and 5.
+const CONSUMER_BUTTONS: &[(u32, i64)] = &[(0x000c_0224, 3), (0x000c_0225, 4)];
+
+/// Zero-based button number a usage stands for, if any.
+fn usage_button(usage: u32) -> Option<i64> {
+    if usage >> 16 == 0x09 && usage & 0xffff > 0 {
+        return Some(i64::from(usage & 0xffff) - 1);
+    }
+    CONSUMER_BUTTONS
+        .iter()
+        .find(|(consumer, _)| *consumer == usage)
+        .map(|(_, button)| *button)
+}
+
+/// Input fields carrying buttons outside the pointer report, i.e. the ones
+/// that never show up as system mouse button events.
+pub(crate) fn extended_button_fields(descriptor: &[u8]) -> Vec<ReportField> {
+    let fields: Vec<ReportField> = parse_report_fields(descriptor)
+        .into_iter()
+        .filter(|field| field.kind == ReportKind::Input)
+        .collect();
+    let pointer_reports: Vec<u8> = fields
+        .iter()
+        .filter(|field| field.contains_usage(USAGE_POINTER_X))
+        .map(|field| field.report_id)
+        .collect();
+    fields
+        .into_iter()
+        .filter(|field| !pointer_reports.contains(&field.report_id))
+        .filter(|field| {
+            field
+                .usages
+                .iter()
+                .any(|usage| usage_button(*usage).is_some())
+        })
+        .collect()
+}
+
+/// Decodes the pressed buttons out of one input report. Returns the report id
+/// and the held buttons, or `None` when the report has no button fields.
+pub(crate) fn report_buttons(fields: &[ReportField], report: &[u8]) -> Option<(u8, Vec<i64>)> {
+    let numbered = fields.iter().any(|field| field.report_id != 0);
+    let (report_id, payload) = match report.split_first() {
+        Some((id, payload)) if numbered => (*id, payload),
+        _ => (0, report),
+    };
+    let fields: Vec<&ReportField> = fields
+        .iter()
+        .filter(|field| field.report_id == report_id)
+        .collect();
+    if fields.is_empty() {
+        return None;
+    }
+    let mut pressed = Vec::new();
+    for field in fields {
+        for element in 0..field.count {
+            let Some(value) = field.read(payload, element) else {
+                continue;
+            };
+            let usage = if field.variable {
+                if value == 0 {
+                    continue;
+                }
+                field.usages.get(element as usize).or(field.usages.last())
+            } else {
+                if value < i64::from(field.logical_min) || value > i64::from(field.logical_max) {
+                    continue;
+                }
+                field
+                    .usages
+                    .get((value - i64::from(field.logical_min)) as usize)
+            };
+            if let Some(button) = usage.and_then(|usage| usage_button(*usage)) {
+                if !pressed.contains(&button) {
+                    pressed.push(button);
+                }
+            }
+        }
+    }
+    Some((report_id, pressed))
+}
+
+/// Collapses per-interface entries into one `MouseDevice` per physical mouse.
+///
+/// Interfaces of one device share vendor, product, and serial, and all their
+/// usages are considered together: a device whose primary usage is keyboard
+/// only counts when its pointer interface's report descriptor (`descriptor`)
+/// declares real X/Y axes, unless the filter asks for every pointing device.
+/// The same descriptor provides each device's button count, and lets in a
+/// Pointer usage that declares buttons. Touchpads are skipped and
+/// digitizer-only tablets left out unless `show_non_mouse_devices` is set.
+///
+/// A receiver hosting several mice reports one serial for all of them, so
+/// pointer entries with different names in a group stay separate devices.
+pub(crate) fn collect_mouse_devices(
+    entries: &[HidEntry],
+    filter: DeviceFilter,
+    descriptor: impl Fn(&HidEntry) -> Option<Vec<u8>>,
+) -> Vec<MouseDevice> {
+    mouse_device_groups(entries, filter, descriptor)
+        .into_iter()
+        .map(|group| group.device)
+        .collect()
+}
+
+/// A listed device together with the entry it was built from and every
+/// interface/usage of the same physical device.
+pub(crate) struct DeviceGroup<'a> {
+    pub(crate) device: MouseDevice,
+    pub(crate) entry: &'a HidEntry,
+    pub(crate) members: Vec<&'a HidEntry>,
+}
+
+pub(crate) fn mouse_device_groups<'a>(
+    entries: &'a [HidEntry],
+    filter: DeviceFilter,
+    descriptor: impl Fn(&HidEntry) -> Option<Vec<u8>>,
+) -> Vec<DeviceGroup<'a>> {
+    // Reading a descriptor opens the device, so each is read once a listing.
+    let descriptors: std::cell::RefCell<HashMap<&str, Option<Vec<u8>>>> = Default::default();
+    let descriptor = |entry: &'a HidEntry| {
+        descriptors
+            .borrow_mut()
+            .entry(entry.path.as_str())
+            .or_insert_with(|| descriptor(entry))
+            .clone()
+    };
+    let mut groups: Vec<(GroupKey, Vec<&HidEntry>)> = Vec::new();
+    for entry in entries {
+        let key = entry.group_key();
+        match groups.iter_mut().find(|(existing, _)| *existing == key) {
+            Some((_, members)) => members.push(entry),
+            None => groups.push((key, vec![entry])),
+        }
+    }
+
+    let mut devices = Vec::new();
+    for (_, mut members) in groups {
+        members.sort_by_key(|entry| entry.interface_number);
+        let touchpad = is_touchpad(&members);
+        if touchpad && !filter.show_non_mouse_devices {
+            continue;
+        }
+        let pointers: Vec<&HidEntry> = members
+            .iter()
+            .copied()
+            .filter(|entry| {
+                entry.is_mouse()
+                    || (entry.is_pointer()
+                        && (filter.include_all_pointing_devices
+                            || descriptor(entry).is_some_and(|descriptor| {
+                                descriptor_button_count(&descriptor).is_some()
+                            })))
+                    || (filter.show_non_mouse_devices && entry.is_digitizer())
+            })
+            .collect();
+        if pointers.is_empty() {
+            continue;
+        }
+        if members[0].is_keyboard()
+            && !filter.include_all_pointing_devices
+            && !pointers.iter().any(|entry| {
+                descriptor(entry).is_some_and(|descriptor| descriptor_has_pointer_axes(&descriptor))
+            })
+        {
+            continue;
+        }
+
+        // Units sharing a serial land in one group, where each adds
+        // another entry with the same usage and interface.
+        let mut names: Vec<&HidEntry> = Vec::new();
+        for entry in pointers {
+            let another_unit = |seen: &&HidEntry| {
+                seen.path != entry.path
+                    && (seen.usage_page, seen.usage, seen.interface_number)
+                        == (entry.usage_page, entry.usage, entry.interface_number)
+            };
+            if !names
+                .iter()
+                .any(|seen| seen.name == entry.name && !another_unit(seen))
+            {
+                names.push(entry);
+            }
+        }
+        let shared = names.iter().any(|entry| entry.name != names[0].name);
+        for entry in names {
+            let mut id = device_id(entry);
+            if shared {
+                id = format!("{}#{:08x}", id, path_hash(entry.name.as_bytes()));
+            }
+            let device = MouseDevice {
+                preset: presets::matching(&id).map(|preset| preset.id),
+                first_seen: None,
+                ambiguous: false,
+                disabled: false,
+                ignored: false,
+                id,
+                name: entry.name.clone(),
+                kind: if touchpad {
+                    DeviceKind::Trackpad
+                } else {
+                    device_kind(entry, &members)
+                },
+                button_count: descriptor(entry)
+                    .and_then(|descriptor| descriptor_button_count(&descriptor))
+                    .unwrap_or(DEFAULT_BUTTON_COUNT),
+            };
+            devices.push(DeviceGroup {
+                device,
+                entry,
+                members: members.clone(),
+            });
+        }
+    }
+    mark_ambiguous_units(&mut devices);
+    devices
+}
+
+/// Separates the unit number `mark_ambiguous_units` adds from the id.
+const UNIT_SEPARATOR: char = '~';
+
+/// Gives devices that came out with the same id a unit number, in the
+/// order of their platform paths, and marks them all ambiguous. The first
+/// keeps the plain id, so its config still applies when it's the only one.
+fn mark_ambiguous_units(groups: &mut [DeviceGroup]) {
+    let mut ids: Vec<(String, Vec<usize>)> = Vec::new();
+    for (index, group) in groups.iter().enumerate() {
+        match ids.iter_mut().find(|(id, _)| *id == group.device.id) {
+            Some((_, indexes)) => indexes.push(index),
+            None => ids.push((group.device.id.clone(), vec![index])),
+        }
+    }
+    for (_, mut indexes) in ids {
+        if indexes.len() < 2 {
+            continue;
+        }
+        indexes.sort_by(|a, b| groups[*a].entry.path.cmp(&groups[*b].entry.path));
+        for (unit, index) in indexes.into_iter().enumerate() {
+            let device = &mut groups[index].device;
+            device.ambiguous = true;
+            if unit > 0 {
+                device.id = format!("{}{}{}", device.id, UNIT_SEPARATOR, unit + 1);
+            }
+        }
+    }
+}
+
+/// `id` without a unit number from `mark_ambiguous_units`.
+pub(crate) fn unit_base_id(id: &str) -> &str {
+    match id.rsplit_once(UNIT_SEPARATOR) {
+        Some((base, unit)) if !unit.is_empty() && unit.bytes().all(|b| b.is_ascii_digit()) => base,
+        _ => id,
+    }
+}
+
+/// Apple's vendor id; its SPI/I2C devices are the built-in keyboard/trackpad.
+const APPLE_VENDOR_ID: u16 = 0x05ac;
+
+/// External touchpads that enumerate with a plain mouse usage and no digitizer
+/// collection, so they can only be recognised by id.
+const TOUCHPAD_IDS: &[(u16, u16)] = &[
+    (APPLE_VENDOR_ID, 0x030e), // Magic Trackpad
+    (APPLE_VENDOR_ID, 0x0265), // Magic Trackpad 2
+    (APPLE_VENDOR_ID, 0x0324), // Magic Trackpad (USB-C)
+    (0x046d, 0x4101),          // Logitech T650
+    (0x046d, 0xb00c),          // Logitech T651
+];
+
+/// Classifies a physical device as a touchpad from its ids, transport, and
+/// usages rather than its (localised, user-visible) name.
+pub(crate) fn is_touchpad(members: &[&HidEntry]) -> bool {
+    members.iter().any(|entry| {
+        let is_digitizer_touchpad = entry.usage_page == 0x0d && entry.usage == 0x05;
+        let is_apple_internal = entry.vendor_id == APPLE_VENDOR_ID
+            && matches!(entry.transport, Transport::Spi | Transport::I2c);
+        is_digitizer_touchpad
+            || is_apple_internal
+            || TOUCHPAD_IDS.contains(&(entry.vendor_id, entry.product_id))
+    })
+}
+
+fn device_kind(entry: &HidEntry, members: &[&HidEntry]) -> DeviceKind {
+    const KENSINGTON: u16 = 0x047d;
+    if members
+        .iter()
+        .any(|member| member.usage_page == 0x0d && matches!(member.usage, 0x01 | 0x02))
+    {
+        return DeviceKind::Tablet;
+    }
+    let name = entry.name.to_lowercase();
+    if name.contains("trackball") || entry.vendor_id == KENSINGTON {
+        return DeviceKind::Trackball;
+    }
+    let has_keys = members
+        .iter()
+        .any(|member| member.is_keyboard() || member.usage_page == 0x0c);
+    let presenter_name = ["present", "remote", "clicker", "spotlight"]
+        .iter()
+        .any(|word| name.contains(word));
+    if presenter_name || (has_keys && !entry.is_mouse()) {
+        return DeviceKind::Presenter;
+    }
+    if entry.is_mouse() {
+        DeviceKind::Mouse
+    } else if entry.is_pointer() {
+        DeviceKind::Pointer
+    } else {
+        DeviceKind::Unknown
+    }
+}
+
+/// Builds the settings key for a HID device.
+///
+/// Devices with a serial keep the original `vid:pid:serial` format. Without one
+/// (common for Bluetooth mice) the transport, interface, and a hash of the
+/// platform path are used so two identical models don't collide.
+pub(crate) fn device_id(device: &HidEntry) -> String {
+    let suffix = match device.serial.as_deref() {
+        Some(serial) => serial.to_string(),
+        None => {
+            let hash = path_hash(device.path.as_bytes());
+            if device.interface_number >= 0 {
+                format!(
+                    "{}-i{}-{:08x}",
+                    device.transport.tag(),
+                    device.interface_number,
+                    hash
+                )
+            } else {
+                format!("{}-{:08x}", device.transport.tag(), hash)
+            }
+        }
+    };
+    format!(
+        "{:04x}:{:04x}:{}",
+        device.vendor_id, device.product_id, suffix
+    )
+}
+
+/// FNV-1a, used instead of `DefaultHasher` because ids are persisted and must
+/// not change between Rust releases.
+fn path_hash(bytes: &[u8]) -> u32 {
+    let mut hash: u32 = 0x811c_9dc5;
+    for byte in bytes {
+        hash ^= u32::from(*byte);
+        hash = hash.wrapping_mul(0x0100_0193);
+    }
+    hash
+}
+
+pub(crate) fn vendor_product(id: &str) -> Option<&str> {
+    let mut parts = id.splitn(3, ':');
+    let vendor = parts.next()?;
+    let product = parts.next()?;
+    parts.next()?;
+    Some(&id[..vendor.len() + 1 + product.len()])
+}
+
+/// Vendor and product id at the start of a settings key built by
+/// `device_id`.
+pub(crate) fn hid_vendor_product(device_id: &str) -> Option<(u16, u16)> {
+    let mut parts = device_id.split(':');
+    let vendor = u16::from_str_radix(parts.next()?, 16).ok()?;
+    let product = u16::from_str_radix(parts.next()?, 16).ok()?;
+    Some((vendor, product))
+}
+
+fn is_legacy_device_id(id: &str) -> bool {
+    id.ends_with(":noserial")
+}
+
+/// The serial at the end of a device id, or None when the id ends in the
+/// transport and path hash `device_id` uses for devices without one.
+pub(crate) fn device_id_serial(id: &str) -> Option<&str> {
+    let suffix = &id[vendor_product(id)?.len() + 1..];
+    let generated = suffix == "noserial"
+        || suffix.rsplit_once('-').is_some_and(|(tag, hash)| {
+            let tag = tag.split_once("-i").map_or(tag, |(tag, _)| tag);
+            hash.len() == 8
+                && hash.bytes().all(|byte| byte.is_ascii_hexdigit())
+                && ["usb", "bt", "i2c", "spi", "unk"].contains(&tag)
+        });
+    (!generated && !suffix.is_empty()).then_some(suffix)
+}
+
+/// Re-keys stored configs whose id no longer matches a connected device.
+///
+/// Only configs keyed without a serial move, as those ids can change under
+/// a device; one keyed by a serial belongs to that unit alone, and stays put
+/// while it's away. Such a config is moved onto a connected device when they
+/// share vendor/product ids and either the stored name matches or the key is
+/// from the old `noserial` format. Ambiguous cases (several candidates on
+/// either side) are left alone rather than guessed. Pristine configs hold
+/// nothing worth moving, so they're never candidates. Returns true when
+/// anything changed.
+pub(crate) fn reconcile_device_ids(settings: &mut Settings, devices: &[MouseDevice]) -> bool {
+    let is_connected = |id: &str| devices.iter().any(|device| device.id == id);
+    let matches = |config_id: &str, config: &DeviceConfig, device: &MouseDevice| {
+        !config.pristine
+            && device_id_serial(config_id).is_none()
+            && vendor_product(config_id).is_some()
+            && vendor_product(config_id) == vendor_product(&device.id)
+            && (config.name == device.name || is_legacy_device_id(config_id))
+    };
+
+    let mut changed = false;
+    for profile in settings.profiles.values_mut() {
+        for device in devices {
+            if profile.devices.contains_key(&device.id) {
+                continue;
+            }
+            let candidates: Vec<&String> = profile
+                .devices
+                .iter()
+                .filter(|(id, config)| !is_connected(id) && matches(id, config, device))
+                .map(|(id, _)| id)
+                .collect();
+            let [old_id] = candidates.as_slice() else {
+                continue;
+            };
+            let old_id = (*old_id).clone();
+            let config = &profile.devices[&old_id];
+            let contenders = devices
+                .iter()
+                .filter(|other| {
+                    !profile.devices.contains_key(&other.id) && matches(&old_id, config, other)
+                })
+                .count();
+            if contenders != 1 {
+                continue;
+            }
+
+            if let Some(config) = profile.devices.remove(&old_id) {
+                log::info!("device-migrate: {} -> {}", old_id, device.id);
+                profile.devices.insert(device.id.clone(), config);
+                if settings.selected_device.as_deref() == Some(old_id.as_str()) {
+                    settings.selected_device = Some(device.id.clone());
+                }
+                changed = true;
+            }
+        }
+    }
+    changed
+}
+
+/// Moves stored configs onto `devices` with `reconcile_device_ids`, then
+/// adds one for each still without, with `Settings::adopt_new_devices`.
+/// Returns true when anything changed.
+pub(crate) fn settle_device_configs(settings: &mut Settings, devices: &[MouseDevice]) -> bool {
+    let reconciled = reconcile_device_ids(settings, devices);
+    settings.adopt_new_devices(devices) || reconciled
+}
+
+/// The connected device remapped in place of the selected one while that one
+/// isn't connected, with `Settings::fallback_to_any_device` on: the only one
+/// of `connected` that `Settings::fallback_devices` allows, provided
+/// `Settings::fallback_config` has mappings for it. Ignored devices are
+/// never candidates. Two candidates make none, as there's no telling which
+/// is in use.
+pub(crate) fn fallback_device(settings: &Settings, connected: &[MouseDevice]) -> Option<String> {
+    if !settings.fallback_to_any_device {
+        return None;
+    }
+    let selected = settings
+        .selected_device
+        .as_deref()
+        .filter(|id| *id != ANY_DEVICE_ID)?;
+    let base = unit_base_id(selected);
+    if connected
+        .iter()
+        .any(|device| device.id == selected || unit_base_id(&device.id) == base)
+    {
+        return None;
+    }
+    let mut candidates = connected.iter().filter(|device| {
+        !settings.is_device_ignored(&device.id)
+            && (settings.fallback_devices == FallbackDevices::Any
+                || settings
+                    .device_config(&device.id)
+                    .is_some_and(|config| !config.pristine))
+    });
+    let only = candidates.next()?;
+    (candidates.next().is_none() && settings.fallback_config(&only.id).is_some())
+        .then(|| only.id.clone())
+}
+
+/// The device the cycle hotkey selects: the one after the selected device
+/// among `connected`, in listing order and wrapping around, counting only
+/// devices set up in the active profile that aren't ignored. `None` when
+/// there's no other to switch to.
+pub(crate) fn next_selected_device(
+    settings: &Settings,
+    connected: &[MouseDevice],
+) -> Option<String> {
+    let candidates: Vec<&str> = connected
+        .iter()
+        .filter(|device| {
+            !settings.is_device_ignored(&device.id)
+                && settings
+                    .device_config(&device.id)
+                    .is_some_and(|config| !config.pristine)
+        })
+        .map(|device| device.id.as_str())
+        .collect();
+    let selected = settings.selected_device.as_deref();
+    let next = match candidates.iter().position(|id| Some(*id) == selected) {
+        Some(index) => candidates[(index + 1) % candidates.len()],
+        None => candidates.first()?,
+    };
+    (Some(next) != selected).then(|| next.to_string())
+}
+
+/// A stored device `device_predecessors` suggests copying from.
+#[derive(Debug, Serialize, Clone, PartialEq, Eq)]
+pub(crate) struct Predecessor {
+    pub(crate) id: String,
+    pub(crate) name: String,
+    /// Unix time, in seconds, it was last seen connected.
+    pub(crate) last_seen: Option<u64>,
+}
+
+/// Devices configured in the active profile that aren't connected and may
+/// be the one `device` replaced: the same model by vendor/product id and
+/// name. Most recently seen first.
+pub(crate) fn device_predecessors(
+    settings: &Settings,
+    device: &MouseDevice,
+    connected: &[MouseDevice],
+) -> Vec<Predecessor> {
+    let Some(model) = vendor_product(&device.id) else {
+        return Vec::new();
+    };
+    let mut predecessors: Vec<Predecessor> = settings
+        .profiles
+        .get(&settings.active_profile)
+        .into_iter()
+        .flat_map(|profile| &profile.devices)
+        .filter(|(id, config)| {
+            !config.pristine
+                && vendor_product(id) == Some(model)
+                && config.name == device.name
+                && connected.iter().all(|other| other.id != **id)
+        })
+        .map(|(id, config)| Predecessor {
+            id: id.clone(),
+            name: config.display_name(id).to_string(),
+            last_seen: config.last_seen,
+        })
+        .collect();
+    predecessors.sort_by(|a, b| b.last_seen.cmp(&a.last_seen).then_with(|| a.id.cmp(&b.id)));
+    predecessors
+}
+
+/// Stored devices the connected `device_id` may have replaced; see
+/// `device_predecessors`.
+#[tauri::command]
+pub(crate) fn get_device_predecessors(
+    state: State<'_, AppState>,
+    device_id: String,
+) -> Result<Vec<Predecessor>, String> {
+    let (connected, _) = state.listed_devices();
+    let device = connected
+        .iter()
+        .find(|device| device.id == device_id)
+        .ok_or_else(|| format!("{} isn't connected", device_id))?;
+    Ok(device_predecessors(
+        &state.snapshot_settings(),
+        device,
+        &connected,
+    ))
+}
+
+/// A mouse interface as `log_mouse_devices` and `--list-devices` report it.
+#[derive(Debug, Serialize, Clone)]
+pub(crate) struct MouseInterface {
+    pub(crate) name: String,
+    pub(crate) vendor_id: u16,
+    pub(crate) product_id: u16,
+    pub(crate) usage_page: u16,
+    pub(crate) usage: u16,
+}
+
+pub(crate) fn mouse_interfaces(hid: &SharedHidApi) -> Result<Vec<MouseInterface>, String> {
+    Ok(hid
+        .with(|api| Ok(hid_entries(api)))?
+        .into_iter()
+        .filter(|entry| entry.is_mouse() && !is_touchpad(&[entry]))
+        .map(|entry| MouseInterface {
+            name: entry.name,
+            vendor_id: entry.vendor_id,
+            product_id: entry.product_id,
+            usage_page: entry.usage_page,
+            usage: entry.usage,
+        })
+        .collect())
+}
+
+pub(crate) fn log_mouse_devices(hid: &SharedHidApi) {
+    match mouse_interfaces(hid) {
+        Ok(devices) => {
+            for device in &devices {
+                log::info!(
+                    "mouse-device: name=\"{}\" vendor_id=0x{:04x} product_id=0x{:04x} usage_page=0x{:02x} usage=0x{:02x}",
+                    device.name,
+                    device.vendor_id,
+                    device.product_id,
+                    device.usage_page,
+                    device.usage
+                );
+            }
+
+            if devices.is_empty() {
+                log::info!("mouse-device: none found");
+            }
+        }
+        Err(err) => {
+            log::info!("mouse-scan error: {}", err);
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub(crate) struct UsagePair {
+    pub(crate) usage_page: u16,
+    pub(crate) usage: u16,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub(crate) struct DeviceDetails {
+    pub(crate) id: String,
+    pub(crate) name: String,
+    pub(crate) kind: DeviceKind,
+    pub(crate) vendor_id: u16,
+    pub(crate) product_id: u16,
+    pub(crate) serial: Option<String>,
+    pub(crate) manufacturer: Option<String>,
+    pub(crate) transport: Transport,
+    pub(crate) interface_number: i32,
+    pub(crate) usage_page: u16,
+    pub(crate) usage: u16,
+    pub(crate) usages: Vec<UsagePair>,
+    pub(crate) release_number: u16,
+    pub(crate) path: String,
+    /// Latest battery reading, however old; `get_device_details` doesn't
+    /// read the battery itself.
+    pub(crate) last_battery: Option<LastBattery>,
+    /// Latest `measure_report_rate` result.
+    pub(crate) report_rate: Option<ReportRate>,
+}
+
+impl From<&DeviceGroup<'_>> for DeviceDetails {
+    fn from(group: &DeviceGroup<'_>) -> Self {
+        let entry = group.entry;
+        let mut usages: Vec<UsagePair> = Vec::new();
+        for member in &group.members {
+            if !usages
+                .iter()
+                .any(|pair| pair.usage_page == member.usage_page && pair.usage == member.usage)
+            {
+                usages.push(UsagePair {
+                    usage_page: member.usage_page,
+                    usage: member.usage,
+                });
+            }
+        }
+        Self {
+            id: group.device.id.clone(),
+            name: group.device.name.clone(),
+            kind: group.device.kind,
+            vendor_id: entry.vendor_id,
+            product_id: entry.product_id,
+            serial: entry.serial.clone(),
+            manufacturer: entry.manufacturer.clone(),
+            transport: entry.transport,
+            interface_number: entry.interface_number,
+            usage_page: entry.usage_page,
+            usage: entry.usage,
+            usages,
+            release_number: entry.release_number,
+            path: entry.path.clone(),
+            last_battery: None,
+            report_rate: None,
+        }
+    }
+}
+
+/// Generic Device Controls: Battery Strength.
+pub(crate) const USAGE_BATTERY_STRENGTH: u32 = 0x0006_0020;
+pub(crate) const BATTERY_CACHE_TTL: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Serialize, Clone, PartialEq, Eq)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub(crate) enum BatteryReport {
+    Available {
+        percentage: u8,
+        charging: Option<bool>,
+    },
+    NotSupported,
+}
+
+/// A battery reading and how long ago it was taken.
+#[derive(Debug, Serialize, Clone, PartialEq, Eq)]
+pub(crate) struct LastBattery {
+    #[serde(flatten)]
+    pub(crate) report: BatteryReport,
+    pub(crate) seconds_ago: u64,
+}
+
+pub(crate) const DEFAULT_BATTERY_CHECK_MINUTES: u32 = 30;
+pub(crate) const DEFAULT_LOW_BATTERY_PERCENT: u8 = 15;
+/// How often the battery watcher wakes to see whether a check is due.
+const BATTERY_WATCH_POLL: Duration = Duration::from_secs(60);
+/// Points above `Settings::low_battery_percent` a battery has to reach
+/// before it's warned about again, so a reading wobbling around the
+/// threshold doesn't warn twice.
+const BATTERY_REARM_MARGIN: u8 = 5;
+
+/// What the battery watcher remembers between checks: the devices warned
+/// about this discharge, and those that can't report a battery.
+#[derive(Debug, Default)]
+pub(crate) struct BatteryWatch {
+    pub(crate) warned: HashSet<String>,
+    pub(crate) unsupported: HashSet<String>,
+}
+
+impl BatteryWatch {
+    /// Whether `device_id` is worth reading: it hasn't said it can't
+    /// report a battery since it connected.
+    pub(crate) fn should_check(&self, device_id: &str) -> bool {
+        !self.unsupported.contains(device_id)
+    }
+
+    /// Notes `report` of `device_id`, returning the percentage to warn at
+    /// when it's the first reading below `threshold` this discharge.
+    /// Charging, or charge well above it, ends the discharge.
+    pub(crate) fn observe(
+        &mut self,
+        device_id: &str,
+        report: &BatteryReport,
+        threshold: u8,
+    ) -> Option<u8> {
+        let BatteryReport::Available {
+            percentage,
+            charging,
+        } = *report
+        else {
+            self.unsupported.insert(device_id.to_string());
+            return None;
+        };
+        if charging == Some(true) || percentage >= threshold.saturating_add(BATTERY_REARM_MARGIN) {
+            self.warned.remove(device_id);
+            return None;
+        }
+        (percentage < threshold && self.warned.insert(device_id.to_string())).then_some(percentage)
+    }
+
+    /// Forgets which devices missing from `connected` can't report a
+    /// battery, since one may come back on a receiver that can. Warnings
+    /// are kept, so a dropped connection doesn't warn again; the next
+    /// reading says whether the device was charged meanwhile.
+    pub(crate) fn retain_connected(&mut self, connected: &[MouseDevice]) {
+        self.unsupported
+            .retain(|id| connected.iter().any(|device| device.id == *id));
+    }
+}
+
+/// Checks the selected device's battery every
+/// `Settings::battery_check_minutes` and posts a notification the first
+/// time each discharge it falls below `Settings::low_battery_percent`.
+pub(crate) fn watch_battery(state: AppState) {
+    std::thread::spawn(move || {
+        let mut watch = BatteryWatch::default();
+        let mut checked: Option<Instant> = None;
+        loop {
+            std::thread::sleep(BATTERY_WATCH_POLL);
+            let settings = state.snapshot_settings();
+            let (devices, _) = state.listed_devices();
+            watch.retain_connected(&devices);
+            let interval =
+                Duration::from_secs(u64::from(settings.battery_check_minutes.max(1)) * 60);
+            let due = checked.is_none_or(|at| at.elapsed() >= interval);
+            let Some(id) = settings
+                .selected_device
+                .as_deref()
+                .filter(|id| *id != ANY_DEVICE_ID && state.is_selected_device_available(id))
+            else {
+                continue;
+            };
+            if !due || settings.low_battery_percent == 0 || !watch.should_check(id) {
+                continue;
+            }
+            checked = Some(Instant::now());
+            let report = match read_device_battery(&state.hid, DeviceFilter::from(&settings), id) {
+                Ok(report) => report,
+                Err(err) => {
+                    log::info!("battery: couldn't read {}: {}", id, err);
+                    continue;
+                }
+            };
+            state.cache_battery(id, report.clone());
+            if let Some(percentage) = watch.observe(id, &report, settings.low_battery_percent) {
+                let listed = devices
+                    .iter()
+                    .find(|device| device.id == id)
+                    .map_or(id, |device| device.name.as_str());
+                let name = settings
+                    .device_config(id)
+                    .map_or(listed, |config| config.display_name(listed));
+                let percentage = percentage.to_string();
+                state.post_notification(
+                    Locale::from_setting(&settings.locale)
+                        .format(Text::NoticeBattery, &[name, &percentage]),
+                );
+            }
+        }
+    });
+}
+
+/// Reads the battery of a connected device, trying the standard HID battery
+/// strength usage on every interface before falling back to Logitech HID++.
+/// Blocks on device I/O, so callers run it off the main thread.
+fn read_device_battery(
+    hid: &SharedHidApi,
+    filter: DeviceFilter,
+    device_id: &str,
+) -> Result<BatteryReport, String> {
+    hid.with(|api| read_group_battery(api, filter, device_id))
+}
+
+fn read_group_battery(
+    api: &hidapi::HidApi,
+    filter: DeviceFilter,
+    device_id: &str,
+) -> Result<BatteryReport, String> {
+    let entries = hid_entries(api);
+    let groups = mouse_device_groups(&entries, filter, descriptor_probe(api));
+    let group = groups
+        .iter()
+        .find(|group| group.device.id == device_id)
+        .ok_or_else(|| format!("device not connected: {}", device_id))?;
+
+    for member in &group.members {
+        if let Some(report) = read_standard_battery(api, member) {
+            return Ok(report);
+        }
+    }
+    if group.entry.vendor_id == LOGITECH_VENDOR_ID {
+        for member in group
+            .members
+            .iter()
+            .filter(|member| member.usage_page >= 0xff00)
+        {
+            if let Some(report) = hidpp::read_battery(api, member) {
+                return Ok(report);
+            }
+        }
+    }
+    Ok(BatteryReport::NotSupported)
+}
+
+fn read_standard_battery(api: &hidapi::HidApi, entry: &HidEntry) -> Option<BatteryReport> {
+    let descriptor = read_report_descriptor(api, &entry.path)?;
+    let field = parse_report_fields(&descriptor).into_iter().find(|field| {
+        field.kind != ReportKind::Output && field.contains_usage(USAGE_BATTERY_STRENGTH)
+    })?;
+    let element = field
+        .usages
+        .iter()
+        .position(|usage| *usage == USAGE_BATTERY_STRENGTH)? as u32;
+
+    let path = CString::new(entry.path.as_str()).ok()?;
+    let device = api.open_path(&path).ok()?;
+    let mut buf = [0u8; 64];
+    buf[0] = field.report_id;
+    let len = match field.kind {
+        ReportKind::Feature => device.get_feature_report(&mut buf).ok()?,
+        _ => device.get_input_report(&mut buf).ok()?,
+    };
+    let payload = if field.report_id == 0 {
+        &buf[..len]
+    } else {
+        &buf[1..len]
+    };
+    let raw = field.read(payload, element.min(field.count.saturating_sub(1)))?;
+    let range = i64::from(field.logical_max) - i64::from(field.logical_min);
+    let percentage = if range > 0 {
+        (raw - i64::from(field.logical_min)) * 100 / range
+    } else {
+        raw
+    };
+    Some(BatteryReport::Available {
+        percentage: percentage.clamp(0, 100) as u8,
+        charging: None,
+    })
+}
+
+const LOGITECH_VENDOR_ID: u16 = 0x046d;
+
+/// Payload of the `dpi-changed` event.
+#[derive(Debug, Serialize, Clone)]
+struct DpiChanged {
+    device_id: String,
+    dpi: u32,
+}
+
+/// Payload of the `dpi-error` event.
+#[derive(Debug, Serialize, Clone)]
+struct DpiError {
+    device_id: Option<String>,
+    message: String,
+}
+
+/// Vendor protocols that can change a mouse's sensor DPI.
+enum DpiDriver {
+    Hidpp(hidpp::DpiSensor),
+}
+
+impl DpiDriver {
+    pub(crate) fn open(api: &hidapi::HidApi, group: &DeviceGroup) -> Option<Self> {
+        if group.entry.vendor_id == LOGITECH_VENDOR_ID {
+            let sensor = group
+                .members
+                .iter()
+                .filter(|member| member.usage_page >= 0xff00)
+                .find_map(|member| hidpp::DpiSensor::open(api, member));
+            if let Some(sensor) = sensor {
+                return Some(DpiDriver::Hidpp(sensor));
+            }
+        }
+        None
+    }
+
+    pub(crate) fn dpi(&self) -> Option<u32> {
+        match self {
+            DpiDriver::Hidpp(sensor) => sensor.dpi(),
+        }
+    }
+
+    pub(crate) fn set_dpi(&self, dpi: u32) -> Option<u32> {
+        match self {
+            DpiDriver::Hidpp(sensor) => sensor.set_dpi(dpi),
+        }
+    }
+}
+
+/// The stop after `current`, wrapping to the lowest one.
+pub(crate) fn next_dpi_stop(stops: &[u32], current: u32) -> Option<u32> {
+    let mut stops = stops.to_vec();
+    stops.sort_unstable();
+    stops
+        .iter()
+        .copied()
+        .find(|stop| *stop > current)
+        .or_else(|| stops.first().copied())
+}
+
+/// Applies a `CycleDpi`/`SetDpi` action to the selected device off the
+/// input thread and reports the outcome as `dpi-changed` or `dpi-error`.
+pub(crate) fn change_dpi(app: tauri::AppHandle, state: AppState, action: Action) {
+    std::thread::spawn(move || {
+        let settings = state.snapshot_settings();
+        let device_id = settings.selected_device.clone();
+        let result = match device_id.as_deref() {
+            Some(device_id) => apply_dpi_action(&state.hid, &settings, device_id, action),
+            None => Err("no device selected".to_string()),
+        };
+        let emitted = match result {
+            Ok(dpi) => {
+                let device_id = device_id.unwrap_or_default();
+                log::info!("dpi: {} set to {}", device_id, dpi);
+                app.emit("dpi-changed", DpiChanged { device_id, dpi })
+            }
+            Err(message) => {
+                log::error!("dpi: {}", message);
+                app.emit("dpi-error", DpiError { device_id, message })
+            }
+        };
+        if let Err(err) = emitted {
+            log::error!("dpi: failed to emit event: {}", err);
+        }
+    });
+}
+
+fn apply_dpi_action(
+    hid: &SharedHidApi,
+    settings: &Settings,
+    device_id: &str,
+    action: Action,
+) -> Result<u32, String> {
+    if device_id == ANY_DEVICE_ID {
+        return Err("DPI switching needs a specific device, not \"Any mouse\"".to_string());
+    }
+    hid.with(|api| set_group_dpi(api, settings, device_id, action))
+}
+
+fn set_group_dpi(
+    api: &hidapi::HidApi,
+    settings: &Settings,
+    device_id: &str,
+    action: Action,
+) -> Result<u32, String> {
+    let entries = hid_entries(api);
+    let groups = mouse_device_groups(
+        &entries,
+        DeviceFilter::from(settings),
+        descriptor_probe(api),
+    );
+    let group = groups
+        .iter()
+        .find(|group| group.device.id == device_id)
+        .ok_or_else(|| format!("device not connected: {}", device_id))?;
+    let driver = DpiDriver::open(api, group)
+        .ok_or_else(|| format!("DPI switching is not supported on {}", group.device.name))?;
+
+    let target = match action {
+        Action::SetDpi { dpi } => dpi,
+        Action::CycleDpi => {
+            let stops = settings
+                .device_config(device_id)
+                .map(|config| config.dpi_stops.as_slice())
+                .unwrap_or_default();
+            let current = driver
+                .dpi()
+                .ok_or_else(|| format!("failed to read DPI from {}", group.device.name))?;
+            next_dpi_stop(stops, current)
+                .ok_or_else(|| format!("no DPI stops configured for {}", group.device.name))?
+        }
+        _ => return Err(format!("not a DPI action: {}", action.name())),
+    };
+    driver
+        .set_dpi(target)
+        .ok_or_else(|| format!("{} did not accept {} DPI", group.device.name, target))
+}
+
+#[tauri::command]
+pub(crate) async fn get_device_battery(
+    state: State<'_, AppState>,
+    device_id: String,
+) -> Result<BatteryReport, String> {
+    if let Some(report) = state.cached_battery(&device_id) {
+        return Ok(report);
+    }
+    let filter = DeviceFilter::from(&state.snapshot_settings());
+    let (hid, lookup_id) = (state.hid.clone(), device_id.clone());
+    let report =
+        tauri::async_runtime::spawn_blocking(move || read_device_battery(&hid, filter, &lookup_id))
+            .await
+            .map_err(|err| err.to_string())??;
+    state.cache_battery(&device_id, report.clone());
+    Ok(report)
+}
+
+/// Reports further apart than this are the mouse lying still, which the
+/// rate leaves out.
+const REPORT_RATE_IDLE_GAP: Duration = Duration::from_millis(50);
+/// Fewest intervals between reports a rate is worked out from.
+const REPORT_RATE_MIN_INTERVALS: usize = 20;
+const REPORT_RATE_MIN_DURATION_MS: u64 = 500;
+const REPORT_RATE_MAX_DURATION_MS: u64 = 10_000;
+/// Change of the average rate, as a fraction of the last measurement's,
+/// that counts as the rate changing.
+const REPORT_RATE_CHANGE: f64 = 0.25;
+
+/// Reports per second seen from a device's mouse interfaces while it moved.
+#[derive(Debug, Serialize, Clone, Copy, PartialEq)]
+pub(crate) struct ReportRate {
+    pub(crate) min_hz: f64,
+    pub(crate) avg_hz: f64,
+    pub(crate) max_hz: f64,
+    /// Intervals between reports the rate was worked out from.
+    pub(crate) intervals: usize,
+}
+
+impl ReportRate {
+    /// Whether the average moved by more than `REPORT_RATE_CHANGE` since
+    /// `previous`, as when a mouse drops from 1000 Hz to 125 Hz.
+    pub(crate) fn differs_from(&self, previous: &ReportRate) -> bool {
+        (self.avg_hz - previous.avg_hz).abs() > previous.avg_hz * REPORT_RATE_CHANGE
+    }
+}
+
+/// Payload of `report-rate-changed`.
+#[derive(Debug, Serialize, Clone)]
+struct ReportRateChanged {
+    device_id: String,
+    previous: ReportRate,
+    current: ReportRate,
+}
+
+/// The rate of reports that arrived at `times` after the start of a
+/// measurement, over the intervals the mouse was moving. None when there
+/// are too few of those.
+pub(crate) fn report_rate(times: &[Duration]) -> Option<ReportRate> {
+    let intervals: Vec<f64> = times
+        .windows(2)
+        .map(|pair| pair[1].saturating_sub(pair[0]))
+        .filter(|interval| !interval.is_zero() && *interval <= REPORT_RATE_IDLE_GAP)
+        .map(|interval| interval.as_secs_f64())
+        .collect();
+    if intervals.len() < REPORT_RATE_MIN_INTERVALS {
+        return None;
+    }
+    let shortest = intervals.iter().copied().fold(f64::INFINITY, f64::min);
+    let longest = intervals.iter().copied().fold(0.0, f64::max);
+    Some(ReportRate {
+        min_hz: 1.0 / longest,
+        avg_hz: intervals.len() as f64 / intervals.iter().sum::<f64>(),
+        max_hz: 1.0 / shortest,
+        intervals: intervals.len(),
+    })
+}
+
+/// Times the reports of `device_id`'s mouse interfaces for `duration`.
+/// They're opened as the extended-buttons listener opens them, alongside
+/// the system, so the pointer and remapping carry on meanwhile.
+fn measure_device_report_rate(
+    hid: &SharedHidApi,
+    filter: DeviceFilter,
+    device_id: &str,
+    duration: Duration,
+) -> Result<ReportRate, String> {
+    let devices = hid.with(|api| {
+        let entries = hid_entries(api);
+        let groups = mouse_device_groups(&entries, filter, descriptor_probe(api));
+        let group = groups
+            .iter()
+            .find(|group| group.device.id == device_id)
+            .ok_or_else(|| format!("device not connected: {}", device_id))?;
+        let mut paths: Vec<&str> = Vec::new();
+        let mut devices = Vec::new();
+        let mice = group
+            .members
+            .iter()
+            .filter(|member| member.is_mouse() || member.is_pointer());
+        for member in mice {
+            if paths.contains(&member.path.as_str()) {
+                continue;
+            }
+            paths.push(&member.path);
+            let Ok(path) = CString::new(member.path.as_str()) else {
+                continue;
+            };
+            match api.open_path(&path) {
+                Ok(device) => devices.push(device),
+                Err(err) => log::error!("report-rate: failed to open {}: {}", member.path, err),
+            }
+        }
+        Ok(devices)
+    })?;
+    if devices.is_empty() {
+        return Err(format!("{} has no mouse interface to read", device_id));
+    }
+
+    // A lone interface can wait for its reports; several take turns,
+    // waiting as little as hidapi allows.
+    let wait = if devices.len() == 1 { 10 } else { 1 };
+    let mut buf = [0u8; 64];
+    let mut times = Vec::new();
+    let start = Instant::now();
+    while start.elapsed() < duration {
+        for device in &devices {
+            match device.read_timeout(&mut buf, wait) {
+                Ok(0) => {}
+                Ok(_) => times.push(start.elapsed()),
+                Err(err) => return Err(format!("{} disconnected: {}", device_id, err)),
+            }
+        }
+    }
+    report_rate(&times)
+        .ok_or_else(|| "the mouse wasn't moved, so there's no rate to measure".to_string())
+}
+
+/// Measures how often `device_id` reports while it's moved for
+/// `duration_ms`, kept between `REPORT_RATE_MIN_DURATION_MS` and
+/// `REPORT_RATE_MAX_DURATION_MS`. The result is kept for
+/// `get_device_details`, and `report-rate-changed` is emitted when it
+/// differs from the last one.
+#[tauri::command]
+pub(crate) async fn measure_report_rate(
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+    device_id: String,
+    duration_ms: u64,
+) -> Result<ReportRate, String> {
+    let duration = Duration::from_millis(
+        duration_ms.clamp(REPORT_RATE_MIN_DURATION_MS, REPORT_RATE_MAX_DURATION_MS),
+    );
+    let filter = DeviceFilter::from(&state.snapshot_settings());
+    let (hid, lookup_id) = (state.hid.clone(), device_id.clone());
+    let rate = tauri::async_runtime::spawn_blocking(move || {
+        measure_device_report_rate(&hid, filter, &lookup_id, duration)
+    })
+    .await
+    .map_err(|err| err.to_string())??;
+    log::info!(
+        "report-rate: {} at {:.0} Hz ({:.0}–{:.0})",
+        device_id,
+        rate.avg_hz,
+        rate.min_hz,
+        rate.max_hz
+    );
+    if let Some(previous) = state.cache_report_rate(&device_id, rate) {
+        if rate.differs_from(&previous) {
+            log::warn!(
+                "report-rate: {} changed from {:.0} Hz to {:.0} Hz",
+                device_id,
+                previous.avg_hz,
+                rate.avg_hz
+            );
+            let payload = ReportRateChanged {
+                device_id,
+                previous,
+                current: rate,
+            };
+            if let Err(err) = app.emit("report-rate-changed", payload) {
+                log::error!("report-rate: failed to emit report-rate-changed: {}", err);
+            }
+        }
+    }
+    Ok(rate)
+}
+
+/// Longest a `start_hid_debug` session runs before it stops by itself.
+const HID_DEBUG_LIMIT: Duration = Duration::from_secs(60);
+/// Most `hid-report` events a session emits in a second; the reports past
+/// that are only counted.
+pub(crate) const HID_DEBUG_EVENTS_PER_SECOND: u32 = 50;
+
+pub(crate) struct HidDebugSession {
+    pub(crate) device_id: String,
+    pub(crate) stop: Arc<AtomicBool>,
+}
+
+/// One interface of the device under `start_hid_debug`, as
+/// `hid-debug-started` describes it.
+#[derive(Debug, Serialize, Clone)]
+struct HidDebugInterface {
+    path: String,
+    usage_page: u16,
+    usage: u16,
+    /// The raw report descriptor as hex, empty when it couldn't be read.
+    descriptor: String,
+    fields: Vec<ReportField>,
+}
+
+/// Payload of `hid-debug-started`.
+#[derive(Debug, Serialize, Clone)]
+struct HidDebugStarted {
+    device_id: String,
+    interfaces: Vec<HidDebugInterface>,
+}
+
+/// Payload of `hid-report`.
+#[derive(Debug, Serialize, Clone)]
+struct HidDebugReport {
+    /// Index into `HidDebugStarted::interfaces`.
+    interface: usize,
+    data: String,
+    /// Reports held back by the rate limit since the previous event.
+    dropped: u64,
+}
+
+/// Payload of `hid-debug-stopped`.
+#[derive(Debug, Serialize, Clone)]
+struct HidDebugStopped {
+    device_id: String,
+    reason: String,
+}
+
+/// Bytes as space-separated hex pairs, as in `01 ff 00`.
+pub(crate) fn hex_dump(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Lets through `HID_DEBUG_EVENTS_PER_SECOND` reports in each second of a
+/// session and counts the rest.
+#[derive(Debug, Default)]
+pub(crate) struct ReportLimiter {
+    pub(crate) window_start: Duration,
+    pub(crate) sent: u32,
+    pub(crate) dropped: u64,
+}
+
+impl ReportLimiter {
+    /// For a report arriving `now` into the session: the number dropped
+    /// since the last one let through, or None when this one is dropped too.
+    pub(crate) fn admit(&mut self, now: Duration) -> Option<u64> {
+        if now.saturating_sub(self.window_start) >= Duration::from_secs(1) {
+            self.window_start = now;
+            self.sent = 0;
+        }
+        if self.sent >= HID_DEBUG_EVENTS_PER_SECOND {
+            self.dropped += 1;
+            return None;
+        }
+        self.sent += 1;
+        Some(std::mem::take(&mut self.dropped))
+    }
+}
+
+/// Opens every interface of `device_id` alongside the system, with what
+/// its report descriptor says about it.
+fn open_hid_debug(
+    hid: &SharedHidApi,
+    filter: DeviceFilter,
+    device_id: &str,
+) -> Result<Vec<(hidapi::HidDevice, HidDebugInterface)>, String> {
+    let opened = hid.with(|api| {
+        let entries = hid_entries(api);
+        let groups = mouse_device_groups(&entries, filter, descriptor_probe(api));
+        let group = groups
+            .iter()
+            .find(|group| group.device.id == device_id)
+            .ok_or_else(|| format!("device not connected: {}", device_id))?;
+        let mut paths: Vec<&str> = Vec::new();
+        let mut opened = Vec::new();
+        for member in &group.members {
+            if paths.contains(&member.path.as_str()) {
+                continue;
+            }
+            paths.push(&member.path);
+            let Ok(path) = CString::new(member.path.as_str()) else {
+                continue;
+            };
+            let descriptor = read_report_descriptor(api, &member.path).unwrap_or_default();
+            match api.open_path(&path) {
+                Ok(device) => opened.push((
+                    device,
+                    HidDebugInterface {
+                        path: member.path.clone(),
+                        usage_page: member.usage_page,
+                        usage: member.usage,
+                        descriptor: hex_dump(&descriptor),
+                        fields: parse_report_fields(&descriptor),
+                    },
+                )),
+                Err(err) => log::warn!("hid-debug: failed to open {}: {}", member.path, err),
+            }
+        }
+        Ok(opened)
+    })?;
+    if opened.is_empty() {
+        return Err(format!(
+            "none of {}'s interfaces could be opened",
+            device_id
+        ));
+    }
+    Ok(opened)
+}
+
+/// Emits `device_id`'s reports as `hid-report` until the session is
+/// stopped, `HID_DEBUG_LIMIT` passes, the device goes away, or the
+/// extended-buttons listener wants it.
+fn run_hid_debug(
+    app: &tauri::AppHandle,
+    state: &AppState,
+    device_id: &str,
+    devices: &[hidapi::HidDevice],
+    stop: &AtomicBool,
+) -> String {
+    let wait = if devices.len() == 1 { 50 } else { 10 };
+    let mut buf = [0u8; 64];
+    let mut limiter = ReportLimiter::default();
+    let start = Instant::now();
+    loop {
+        if stop.load(Ordering::SeqCst) {
+            return "stopped".to_string();
+        }
+        if start.elapsed() >= HID_DEBUG_LIMIT {
+            return format!("stopped after {} seconds", HID_DEBUG_LIMIT.as_secs());
+        }
+        if extended_buttons_target(state).as_deref() == Some(device_id) {
+            return "stopped for the extended-buttons listener".to_string();
+        }
+        for (interface, device) in devices.iter().enumerate() {
+            let len = match device.read_timeout(&mut buf, wait) {
+                Ok(0) => continue,
+                Ok(len) => len,
+                Err(err) => return format!("stopped as the device disconnected: {}", err),
+            };
+            let Some(dropped) = limiter.admit(start.elapsed()) else {
+                continue;
+            };
+            let report = HidDebugReport {
+                interface,
+                data: hex_dump(&buf[..len]),
+                dropped,
+            };
+            if let Err(err) = app.emit("hid-report", report) {
+                log::error!("hid-debug: failed to emit hid-report: {}", err);
+            }
+        }
+    }
+}
+
+/// Streams `device_id`'s raw input reports for supporting unusual mice:
+/// `hid-debug-started` carries each interface's report descriptor, then
+/// every report comes as `hid-report`, at most
+/// `HID_DEBUG_EVENTS_PER_SECOND` a second, until `hid-debug-stopped`. One
+/// device at a time, never one the extended-buttons listener reads, and
+/// for `HID_DEBUG_LIMIT` at most.
+#[tauri::command]
+pub(crate) async fn start_hid_debug(
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+    device_id: String,
+) -> Result<(), String> {
+    if extended_buttons_target(&state).as_deref() == Some(device_id.as_str()) {
+        return Err(format!(
+            "{} is read for its extended buttons; turn them off to debug it",
+            device_id
+        ));
+    }
+    let stop = Arc::new(AtomicBool::new(false));
+    {
+        let mut session = lock_recovering(&state.hid_debug, "hid debug");
+        if let Some(running) = session.as_ref() {
+            return Err(format!("already debugging {}", running.device_id));
+        }
+        *session = Some(HidDebugSession {
+            device_id: device_id.clone(),
+            stop: stop.clone(),
+        });
+    }
+    let filter = DeviceFilter::from(&state.snapshot_settings());
+    let (hid, lookup_id) = (state.hid.clone(), device_id.clone());
+    let opened =
+        tauri::async_runtime::spawn_blocking(move || open_hid_debug(&hid, filter, &lookup_id))
+            .await
+            .map_err(|err| err.to_string())
+            .and_then(|opened| opened);
+    let opened = match opened {
+        Ok(opened) => opened,
+        Err(err) => {
+            *lock_recovering(&state.hid_debug, "hid debug") = None;
+            return Err(err);
+        }
+    };
+    let (devices, interfaces): (Vec<_>, Vec<_>) = opened.into_iter().unzip();
+    log::info!(
+        "hid-debug: reading {} interface(s) of {}",
+        devices.len(),
+        device_id
+    );
+    let started = HidDebugStarted {
+        device_id: device_id.clone(),
+        interfaces,
+    };
+    if let Err(err) = app.emit("hid-debug-started", started) {
+        log::error!("hid-debug: failed to emit hid-debug-started: {}", err);
+    }
+
+    let state = state.inner().clone();
+    std::thread::spawn(move || {
+        let reason = run_hid_debug(&app, &state, &device_id, &devices, &stop);
+        drop(devices);
+        *lock_recovering(&state.hid_debug, "hid debug") = None;
+        log::info!("hid-debug: {} {}", device_id, reason);
+        if let Err(err) = app.emit("hid-debug-stopped", HidDebugStopped { device_id, reason }) {
+            log::error!("hid-debug: failed to emit hid-debug-stopped: {}", err);
+        }
+    });
+    Ok(())
+}
+
+/// Ends the `start_hid_debug` session, if there is one. It's over once
+/// `hid-debug-stopped` arrives.
+#[tauri::command]
+pub(crate) fn stop_hid_debug(state: State<'_, AppState>) {
+    if let Some(session) = lock_recovering(&state.hid_debug, "hid debug").as_ref() {
+        session.stop.store(true, Ordering::SeqCst);
+    }
+}
+
+/// Generic Desktop Wheel and Consumer AC Pan, vertical and tilt scrolling.
+const USAGE_WHEEL: u32 = 0x0001_0038;
+const USAGE_AC_PAN: u32 = 0x000c_0238;
+/// Longest `probe_device_capabilities` waits for a device to answer.
+const CAPABILITY_PROBE_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Reply of `probe_device_capabilities`.
+#[derive(Debug, Serialize, Clone, PartialEq, Eq)]
+pub(crate) struct DeviceCapabilities {
+    pub(crate) buttons: u8,
+    pub(crate) has_wheel: bool,
+    pub(crate) has_tilt: bool,
+    pub(crate) battery: bool,
+    pub(crate) dpi_control: bool,
+    pub(crate) extended_buttons: bool,
+    /// False when the device couldn't be read, because another driver holds
+    /// it or it didn't answer in time. Only the wheel is assumed then.
+    pub(crate) known: bool,
+}
+
+impl DeviceCapabilities {
+    pub(crate) fn unknown(buttons: u8) -> Self {
+        Self {
+            buttons,
+            has_wheel: true,
+            has_tilt: false,
+            battery: false,
+            dpi_control: false,
+            extended_buttons: false,
+            known: false,
+        }
+    }
+
+    /// What the report descriptors of a device's interfaces declare; vendor
+    /// protocols are probed separately.
+    pub(crate) fn from_descriptors(buttons: u8, descriptors: &[Vec<u8>]) -> Self {
+        if descriptors.is_empty() {
+            return Self::unknown(buttons);
+        }
+        let fields: Vec<ReportField> = descriptors
+            .iter()
+            .flat_map(|descriptor| parse_report_fields(descriptor))
+            .collect();
+        let scrolls = |usage| {
+            fields.iter().any(|field| {
+                field.kind == ReportKind::Input && field.variable && field.contains_usage(usage)
+            })
+        };
+        Self {
+            buttons,
+            has_wheel: scrolls(USAGE_WHEEL),
+            has_tilt: scrolls(USAGE_AC_PAN),
+            battery: fields.iter().any(|field| {
+                field.kind != ReportKind::Output && field.contains_usage(USAGE_BATTERY_STRENGTH)
+            }),
+            dpi_control: false,
+            extended_buttons: descriptors
+                .iter()
+                .any(|descriptor| !extended_button_fields(descriptor).is_empty()),
+            known: true,
+        }
+    }
+}
+
+/// Reads what a connected device supports: its descriptors, then HID++
+/// battery and DPI for Logitech devices. Blocks on device I/O.
+fn probe_capabilities(
+    hid: &SharedHidApi,
+    filter: DeviceFilter,
+    device_id: &str,
+) -> Result<DeviceCapabilities, String> {
+    hid.with(|api| probe_group_capabilities(api, filter, device_id))
+}
+
+fn probe_group_capabilities(
+    api: &hidapi::HidApi,
+    filter: DeviceFilter,
+    device_id: &str,
+) -> Result<DeviceCapabilities, String> {
+    let entries = hid_entries(api);
+    let groups = mouse_device_groups(&entries, filter, descriptor_probe(api));
+    let group = groups
+        .iter()
+        .find(|group| group.device.id == device_id)
+        .ok_or_else(|| format!("device not connected: {}", device_id))?;
+    let descriptors: Vec<Vec<u8>> = group
+        .members
+        .iter()
+        .filter_map(|member| read_report_descriptor(api, &member.path))
+        .collect();
+    let mut capabilities =
+        DeviceCapabilities::from_descriptors(group.device.button_count, &descriptors);
+    if capabilities.known && group.entry.vendor_id == LOGITECH_VENDOR_ID {
+        capabilities.battery = capabilities.battery
+            || group
+                .members
+                .iter()
+                .filter(|member| member.usage_page >= 0xff00)
+                .any(|member| hidpp::read_battery(api, member).is_some());
+        capabilities.dpi_control = DpiDriver::open(api, group).is_some();
+    }
+    Ok(capabilities)
+}
+
+/// What `device_id` supports, so the UI only offers options it can use.
+/// A device that takes longer than `CAPABILITY_PROBE_TIMEOUT` reads as
+/// unknown, like one that can't be opened.
+#[tauri::command]
+pub(crate) async fn probe_device_capabilities(
+    state: State<'_, AppState>,
+    device_id: String,
+) -> Result<DeviceCapabilities, String> {
+    let filter = DeviceFilter::from(&state.snapshot_settings());
+    let (sender, probed) = std::sync::mpsc::channel();
+    let (hid, probe_id) = (state.hid.clone(), device_id.clone());
+    std::thread::spawn(move || {
+        let _ = sender.send(probe_capabilities(&hid, filter, &probe_id));
+    });
+    tauri::async_runtime::spawn_blocking(move || {
+        probed
+            .recv_timeout(CAPABILITY_PROBE_TIMEOUT)
+            .unwrap_or_else(|_| {
+                log::warn!("devices: probing {} timed out", device_id);
+                Ok(DeviceCapabilities::unknown(DEFAULT_BUTTON_COUNT))
+            })
+    })
+    .await
+    .map_err(|err| err.to_string())?
+}
+
+/// Longest `get_mouse_devices` waits for the HID manager before answering
+/// with the devices it found last.
+const DEVICE_LIST_TIMEOUT: Duration = Duration::from_secs(2);
+/// How long a listing answers `get_mouse_devices` without listing again.
+/// `watch_devices` lists more often than this, so while it runs the window
+/// never has to wait for the HID manager.
+pub(crate) const DEVICE_CACHE_TTL: Duration = Duration::from_secs(5);
+
+/// The devices as last listed, by whichever listed them.
+#[derive(Debug, Default)]
+pub(crate) struct DeviceCache {
+    pub(crate) devices: Vec<MouseDevice>,
+    /// `None` before the first listing, or once the filter changed.
+    pub(crate) listed_at: Option<Instant>,
+}
+
+/// What `get_mouse_devices` returns.
+#[derive(Debug, Serialize)]
+pub(crate) struct DeviceListing {
+    pub(crate) devices: Vec<MouseDevice>,
+    /// Set when listing took longer than `DEVICE_LIST_TIMEOUT`, so
+    /// `devices` are the ones listed before.
+    pub(crate) stale: bool,
+}
+
+/// Lists the devices on a thread of their own, for commands that mustn't
+/// wait past `DEVICE_LIST_TIMEOUT` on a stalled HID manager. `None` when
+/// listing failed or took longer.
+pub(crate) fn list_mouse_devices_promptly(
+    hid: &SharedHidApi,
+    filter: DeviceFilter,
+) -> Option<Vec<MouseDevice>> {
+    let (sender, listed) = std::sync::mpsc::channel();
+    let hid = hid.clone();
+    std::thread::spawn(move || {
+        let _ = sender.send(list_mouse_devices(&hid, filter));
+    });
+    match listed.recv_timeout(DEVICE_LIST_TIMEOUT) {
+        Ok(Ok(devices)) => Some(devices),
+        Ok(Err(err)) => {
+            log::warn!("devices: failed to list devices: {}", err);
+            None
+        }
+        Err(_) => {
+            log::warn!(
+                "devices: listing took over {:?}, keeping the last list",
+                DEVICE_LIST_TIMEOUT
+            );
+            None
+        }
+    }
+}
+
+/// Lists the devices off the main thread, or answers from the last listing
+/// when it's under `DEVICE_CACHE_TTL` old, unless `force` is set. Listing
+/// shares `AppState::hid` with the remap engine's own polling, so the two
+/// take turns rather than enumerating at once.
+#[tauri::command]
+pub(crate) async fn get_mouse_devices(
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+    force: bool,
+) -> Result<DeviceListing, String> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or_default();
+    if !force {
+        if let (mut devices, true) = state.listed_devices() {
+            fill_from_configs(&state.snapshot_settings(), &mut devices, now);
+            return Ok(DeviceListing {
+                devices,
+                stale: false,
+            });
+        }
+    }
+    let filter = DeviceFilter::from(&state.snapshot_settings());
+    let (sender, listed) = std::sync::mpsc::channel();
+    let hid = state.hid.clone();
+    // Not awaited: one that stalls is left to finish on its own.
+    drop(tauri::async_runtime::spawn_blocking(move || {
+        let _ = sender.send(list_mouse_devices(&hid, filter));
+    }));
+    let listed =
+        tauri::async_runtime::spawn_blocking(move || listed.recv_timeout(DEVICE_LIST_TIMEOUT))
+            .await
+            .map_err(|err| err.to_string())?;
+    let Ok(devices) = listed else {
+        log::warn!(
+            "get_mouse_devices: listing took over {:?}, answering with the last list",
+            DEVICE_LIST_TIMEOUT
+        );
+        let (mut devices, _) = state.listed_devices();
+        fill_from_configs(&state.snapshot_settings(), &mut devices, now);
+        return Ok(DeviceListing {
+            devices,
+            stale: true,
+        });
+    };
+    let mut devices = devices?;
+    let mut settings = state.snapshot_settings();
+    if state.update_devices(&devices) {
+        refresh_tray_menu(&app);
+        show_hud(&app, &state);
+    }
+    let sightings_due = state.note_devices_seen(&devices, now);
+    let settled = settle_device_configs(&mut settings, &devices);
+    let writable = !state.is_safe_mode() && !state.is_settings_read_only();
+    if (settled || sightings_due) && writable {
+        let sightings = state.take_sightings(now);
+        settings = state.change_settings(&app, None, |settings| {
+            settle_device_configs(settings, &devices);
+            settings.record_sightings(&sightings);
+            Ok(())
+        })?;
+    }
+    fill_from_configs(&settings, &mut devices, now);
+    log::info!("get_mouse_devices: {} device(s)", devices.len());
+    Ok(DeviceListing {
+        devices,
+        stale: false,
+    })
+}
+
+/// When each device was first seen, or `now` for one seen only today, and
+/// whether its buttons are disabled or it's ignored.
+fn fill_from_configs(settings: &Settings, devices: &mut [MouseDevice], now: u64) {
+    for device in devices {
+        let config = settings.device_config(&device.id);
+        device.first_seen = config.and_then(|config| config.first_seen).or(Some(now));
+        device.disabled = config.is_some_and(|config| config.disabled);
+        device.ignored = settings.is_device_ignored(&device.id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn device(id: &str, name: &str) -> MouseDevice {
+        MouseDevice {
+            id: id.to_string(),
+            name: name.to_string(),
+            kind: DeviceKind::Mouse,
+            button_count: 7,
+            preset: None,
+            first_seen: None,
+            ambiguous: false,
+            disabled: false,
+            ignored: false,
+        }
+    }
+
+    /// Settings with one G502 config under `id`, selected.
+    fn settings_with(id: &str) -> Settings {
+        let json = format!(
+            r#"{{"selected_device":"{id}","devices":{{"{id}":{{"name":"G502","buttons":{{"button4":"Back"}}}}}}}}"#
+        );
+        parse_settings(&json).unwrap()
+    }
+
+    #[test]
+    fn serial_less_configs_follow_their_device_to_a_new_id() {
+        for old_id in ["046d:c08b:noserial", "046d:c08b:bt-0a1b2c3d"] {
+            let mut settings = settings_with(old_id);
+            let connected = [device("046d:c08b:usb-i0-11223344", "G502")];
+            assert!(settle_device_configs(&mut settings, &connected));
+            let devices = &settings.profiles[DEFAULT_PROFILE].devices;
+            assert!(!devices.contains_key(old_id));
+            assert_eq!(
+                devices["046d:c08b:usb-i0-11223344"].buttons["button4"],
+                ActionValue::Action(Action::Back)
+            );
+            assert_eq!(
+                settings.selected_device.as_deref(),
+                Some("046d:c08b:usb-i0-11223344")
+            );
+        }
+    }
+
+    #[test]
+    fn configs_keyed_by_a_serial_stay_with_their_unit() {
+        let mut settings = settings_with("046d:c08b:A");
+        // Another unit of the same model, while the first is away.
+        let connected = [device("046d:c08b:B", "G502")];
+        assert!(!reconcile_device_ids(&mut settings, &connected));
+        let devices = &settings.profiles[DEFAULT_PROFILE].devices;
+        assert_eq!(
+            devices["046d:c08b:A"].buttons["button4"],
+            ActionValue::Action(Action::Back)
+        );
+        assert!(!devices.contains_key("046d:c08b:B"));
+        assert_eq!(settings.selected_device.as_deref(), Some("046d:c08b:A"));
+    }
+
+    fn collect(entries: &[HidEntry]) -> Vec<MouseDevice> {
+        collect_mouse_devices(entries, DeviceFilter::default(), |_| None)
+    }
+
+    fn entry(name: &str, serial: Option<&str>, usage: u16, interface: i32, path: &str) -> HidEntry {
+        HidEntry {
+            vendor_id: 0x046d,
+            product_id: 0xc08b,
+            serial: serial.map(str::to_string),
+            name: name.to_string(),
+            manufacturer: None,
+            release_number: 0x0100,
+            usage_page: 0x01,
+            usage,
+            interface_number: interface,
+            transport: Transport::Usb,
+            path: path.to_string(),
+        }
+    }
+
+    /// Generic Desktop Mouse with `buttons` one-bit buttons and i8 X/Y axes.
+    fn mouse_report(buttons: u8) -> Vec<u8> {
+        let padding = (8 - buttons % 8) % 8;
+        let mut descriptor = vec![
+            0x05, 0x01, 0x09, 0x02, 0xa1, 0x01, // Generic Desktop, Mouse, Collection
+            0x05, 0x09, 0x19, 0x01, 0x29, buttons, // Buttons 1-n
+            0x15, 0x00, 0x25, 0x01, 0x75, 0x01, 0x95, buttons, 0x81, 0x02, // n x 1 bit
+        ];
+        if padding > 0 {
+            descriptor.extend([0x75, padding, 0x95, 0x01, 0x81, 0x01]);
+        }
+        descriptor.extend([
+            0x05, 0x01, 0x09, 0x30, 0x09, 0x31, // X, Y
+            0x15, 0x81, 0x25, 0x7f, 0x75, 0x08, 0x95, 0x02, 0x81, 0x06, // 2 x i8
+            0xc0,
+        ]);
+        descriptor
+    }
+
+    fn mouse_descriptor(_: &HidEntry) -> Option<Vec<u8>> {
+        Some(mouse_report(5))
+    }
+
+    fn descriptor(
+        name: &str,
+        vendor_id: u16,
+        product_id: u16,
+        transport: Transport,
+        usages: &[(u16, u16)],
+    ) -> Vec<HidEntry> {
+        usages
+            .iter()
+            .map(|(usage_page, usage)| HidEntry {
+                vendor_id,
+                product_id,
+                serial: Some(format!("{}-serial", name)),
+                name: name.to_string(),
+                manufacturer: None,
+                release_number: 0x0100,
+                usage_page: *usage_page,
+                usage: *usage,
+                interface_number: 0,
+                transport,
+                path: format!("{}-path", name),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn interfaces_of_one_mouse_collapse() {
+        let entries = vec![
+            entry("G502 HERO", Some("ABC123"), 0x02, 0, "p0"),
+            entry("G502 HERO", Some("ABC123"), 0x06, 1, "p1"),
+            entry("G502 HERO", Some("ABC123"), 0x02, 1, "p1"),
+            entry("G502 HERO", Some("ABC123"), 0x02, 2, "p2"),
+        ];
+        let devices = collect(&entries);
+        assert_eq!(devices.len(), 1);
+        assert_eq!(devices[0].id, "046d:c08b:ABC123");
+        assert_eq!(devices[0].name, "G502 HERO");
+    }
+
+    #[test]
+    fn name_comes_from_mouse_interface() {
+        let entries = vec![
+            entry("G502 Keyboard", Some("ABC123"), 0x06, 0, "p0"),
+            entry("G502 HERO", Some("ABC123"), 0x02, 1, "p1"),
+        ];
+        let devices = collect_mouse_devices(&entries, DeviceFilter::default(), mouse_descriptor);
+        assert_eq!(devices.len(), 1);
+        assert_eq!(devices[0].name, "G502 HERO");
+    }
+
+    #[test]
+    fn receiver_with_several_mice_is_not_merged() {
+        let entries = vec![
+            entry("MX Master 3", Some("RCV01"), 0x02, 2, "p2"),
+            entry("M720 Triathlon", Some("RCV01"), 0x02, 2, "p2"),
+        ];
+        let devices = collect(&entries);
+        assert_eq!(devices.len(), 2);
+        assert_ne!(devices[0].id, devices[1].id);
+        assert!(devices
+            .iter()
+            .all(|device| device.id.starts_with("046d:c08b:RCV01#")));
+    }
+
+    #[test]
+    fn serial_less_devices_stay_separate_per_path() {
+        let entries = vec![
+            entry("Office Mouse", None, 0x02, -1, "DevSrvsID:1"),
+            entry("Office Mouse", None, 0x02, -1, "DevSrvsID:2"),
+        ];
+        let devices = collect(&entries);
+        assert_eq!(devices.len(), 2);
+        assert_ne!(devices[0].id, devices[1].id);
+
+        let windows = |collection: &str, instance: &str| {
+            format!(
+                r"\\?\HID#VID_046D&PID_C08B&MI_01&{}#7&{}&0&{}#{{4d1e55b2-f16f-11cf-88cb-001111000030}}",
+                collection,
+                instance,
+                &collection[3..]
+            )
+        };
+        let entries = vec![
+            entry("Office Mouse", None, 0x02, 1, &windows("Col01", "2ca8ec4a")),
+            entry("Office Mouse", None, 0x80, 1, &windows("Col02", "2ca8ec4a")),
+            entry("Office Mouse", None, 0x02, 1, &windows("Col01", "1f03bb21")),
+        ];
+        let devices = collect(&entries);
+        assert_eq!(devices.len(), 2);
+        assert_ne!(devices[0].id, devices[1].id);
+    }
+
+    #[test]
+    fn each_descriptor_is_read_once_per_listing() {
+        let entries = vec![
+            entry("Mech Keyboard", Some("KB1"), 0x06, 0, "p0"),
+            entry("Mech Keyboard", Some("KB1"), 0x02, 1, "p1"),
+            entry("MX Master 3", Some("MX1"), 0x02, 0, "p2"),
+        ];
+        let reads = std::cell::RefCell::new(Vec::new());
+        let devices = collect_mouse_devices(&entries, DeviceFilter::default(), |entry| {
+            reads.borrow_mut().push(entry.path.clone());
+            mouse_descriptor(entry)
+        });
+        assert_eq!(devices.len(), 2);
+        let mut reads = reads.into_inner();
+        let total = reads.len();
+        reads.sort();
+        reads.dedup();
+        assert_eq!(reads.len(), total);
+    }
+
+    #[test]
+    fn keyboard_pointer_without_axes_is_skipped() {
+        let entries = vec![
+            entry("Mech Keyboard", Some("KB1"), 0x06, 0, "p0"),
+            entry("Mech Keyboard", Some("KB1"), 0x02, 1, "p1"),
+        ];
+        assert!(collect(&entries).is_empty());
+
+        let with_axes = collect_mouse_devices(&entries, DeviceFilter::default(), mouse_descriptor);
+        assert_eq!(with_axes.len(), 1);
+
+        let filter = DeviceFilter {
+            include_all_pointing_devices: true,
+            ..DeviceFilter::default()
+        };
+        assert_eq!(collect_mouse_devices(&entries, filter, |_| None).len(), 1);
+    }
+
+    #[test]
+    fn pointer_usage_needs_override() {
+        let entries = vec![
+            entry("Laser Presenter", Some("PR1"), 0x06, 0, "p0"),
+            entry("Laser Presenter", Some("PR1"), 0x01, 0, "p0"),
+        ];
+        assert!(collect(&entries).is_empty());
+
+        let filter = DeviceFilter {
+            include_all_pointing_devices: true,
+            ..DeviceFilter::default()
+        };
+        let devices = collect_mouse_devices(&entries, filter, |_| None);
+        assert_eq!(devices.len(), 1);
+        assert_eq!(devices[0].kind, DeviceKind::Presenter);
+    }
+
+    #[test]
+    fn kinds_are_classified() {
+        let mouse = collect(&[entry("MX Master 3", Some("A"), 0x02, 0, "p0")]);
+        assert_eq!(mouse[0].kind, DeviceKind::Mouse);
+        let trackball = collect(&[entry("Expert Trackball", Some("B"), 0x02, 0, "p0")]);
+        assert_eq!(trackball[0].kind, DeviceKind::Trackball);
+
+        // Pointer usage counts when its descriptor declares buttons.
+        let pointer = [entry("Orbit", Some("C"), 0x01, 0, "p0")];
+        assert!(collect(&pointer).is_empty());
+        let with_buttons = |_: &HidEntry| Some(mouse_report(4));
+        let devices = collect_mouse_devices(&pointer, DeviceFilter::default(), with_buttons);
+        assert_eq!(devices.len(), 1);
+        assert_eq!(devices[0].kind, DeviceKind::Pointer);
+        assert_eq!(devices[0].button_count, 4);
+        let without = |_: &HidEntry| Some(vec![0x05, 0x01, 0x09, 0x01, 0xa1, 0x01, 0xc0]);
+        assert!(collect_mouse_devices(&pointer, DeviceFilter::default(), without).is_empty());
+    }
+
+    #[test]
+    fn new_devices_get_a_pristine_config_until_edited() {
+        let device = |id: &str, name: &str| MouseDevice {
+            id: id.to_string(),
+            name: name.to_string(),
+            kind: DeviceKind::Mouse,
+            button_count: 7,
+            preset: None,
+            first_seen: None,
+            ambiguous: false,
+            disabled: false,
+            ignored: false,
+        };
+        let connected = [
+            device("046d:c08b:A", "G502"),
+            device("046d:c08b:A~2", "G502"),
+        ];
+        let before = Settings::default();
+        let mut settings = before.clone();
+        assert!(settle_device_configs(&mut settings, &connected));
+        assert!(!settle_device_configs(&mut settings, &connected));
+        let devices = &settings.profiles[DEFAULT_PROFILE].devices;
+        // The second unit keeps sharing the first's config.
+        assert_eq!(devices.len(), 1);
+        let config = &devices["046d:c08b:A"];
+        assert!(config.pristine && !config.has_remapping());
+        assert_eq!(config.name, "G502");
+        assert_eq!(config.buttons.len(), 7);
+        assert!(describe_settings_changes(&before, &settings).is_empty());
+        assert!(settings.issues().is_empty());
+        assert!(tray_devices(&settings, &[], Locale::En)
+            .iter()
+            .all(|entry| entry.id != "046d:c08b:A"));
+
+        // Sightings alone leave it pristine; an edit doesn't.
+        let adopted = settings.clone();
+        settings.record_sightings(&HashMap::from([("046d:c08b:A".to_string(), 5)]));
+        settings.settle_pristine_devices(&adopted);
+        assert!(settings.device_config("046d:c08b:A").unwrap().pristine);
+        settings
+            .set_button_action("046d:c08b:A", "button4", ActionValue::Action(Action::Back))
+            .unwrap();
+        settings.settle_pristine_devices(&adopted);
+        let config = settings.device_config("046d:c08b:A").unwrap();
+        assert!(!config.pristine);
+        assert_eq!(serde_json::to_value(config).unwrap().get("pristine"), None);
+
+        // A pristine config isn't carried over to a new id of its device.
+        let mut settings = adopted.clone();
+        assert!(!reconcile_device_ids(
+            &mut settings,
+            &[device("046d:c08b:B", "G502")]
+        ));
+    }
+
+    #[test]
+    fn a_replaced_mouse_takes_over_its_predecessors_config() {
+        let device = |id: &str| MouseDevice {
+            id: id.to_string(),
+            name: "G502".to_string(),
+            kind: DeviceKind::Mouse,
+            button_count: 5,
+            preset: None,
+            first_seen: None,
+            ambiguous: false,
+            disabled: false,
+            ignored: false,
+        };
+        let mut settings = Settings {
+            selected_device: Some("046d:c08b:OLD".to_string()),
+            ..Settings::default()
+        };
+        settings
+            .profiles
+            .insert("Games".to_string(), Profile::default());
+        for (id, name, last_seen) in [
+            ("046d:c08b:OLD", "G502", Some(20)),
+            ("046d:c08b:OLDER", "G502", Some(10)),
+            ("046d:c08b:OTHER", "G502 X", Some(30)),
+            ("046d:b023:OLD", "G502", Some(30)),
+        ] {
+            settings
+                .set_button_action(id, "button4", ActionValue::Action(Action::Back))
+                .unwrap();
+            let config = settings
+                .profiles
+                .get_mut(DEFAULT_PROFILE)
+                .unwrap()
+                .devices
+                .get_mut(id)
+                .unwrap();
+            config.name = name.to_string();
+            config.last_seen = last_seen;
+        }
+        let new = device("046d:c08b:NEW");
+        settings.adopt_new_devices(std::slice::from_ref(&new));
+        settings.record_sightings(&HashMap::from([("046d:c08b:NEW".to_string(), 40)]));
+        let ids = |connected: &[MouseDevice]| -> Vec<String> {
+            device_predecessors(&settings, &new, connected)
+                .into_iter()
+                .map(|predecessor| predecessor.id)
+                .collect()
+        };
+        assert_eq!(
+            ids(std::slice::from_ref(&new)),
+            ["046d:c08b:OLD", "046d:c08b:OLDER"]
+        );
+        assert_eq!(
+            ids(&[new.clone(), device("046d:c08b:OLD")]),
+            ["046d:c08b:OLDER"]
+        );
+
+        let mut copied = settings.clone();
+        copied
+            .copy_device_config("046d:c08b:OLD", "046d:c08b:NEW", false)
+            .unwrap();
+        let config = copied.device_config("046d:c08b:NEW").unwrap();
+        assert_eq!(config.buttons["button4"], ActionValue::Action(Action::Back));
+        assert_eq!((config.last_seen, config.pristine), (Some(40), false));
+        assert!(copied.device_config("046d:c08b:OLD").is_some());
+        // Profiles without the source are left as they were.
+        assert!(!copied.profiles["Games"]
+            .devices
+            .contains_key("046d:c08b:NEW"));
+
+        settings
+            .copy_device_config("046d:c08b:OLD", "046d:c08b:NEW", true)
+            .unwrap();
+        assert!(settings.device_config("046d:c08b:OLD").is_none());
+        assert_eq!(settings.selected_device.as_deref(), Some("046d:c08b:NEW"));
+        assert!(settings
+            .copy_device_config("046d:c08b:OLD", "046d:c08b:NEW", true)
+            .is_err());
+        assert!(settings
+            .copy_device_config("046d:c08b:NEW", "046d:c08b:NEW", false)
+            .is_err());
+    }
+
+    #[test]
+    fn another_mouse_stands_in_while_the_selected_one_is_away() {
+        let device = |id: &str| MouseDevice {
+            id: id.to_string(),
+            name: "Spare".to_string(),
+            kind: DeviceKind::Mouse,
+            button_count: 5,
+            preset: None,
+            first_seen: None,
+            ambiguous: false,
+            disabled: false,
+            ignored: false,
+        };
+        let mut settings = Settings {
+            selected_device: Some("046d:c08b:MAIN".to_string()),
+            fallback_to_any_device: true,
+            ..Settings::default()
+        };
+        settings
+            .set_button_action(
+                "046d:c08b:MAIN",
+                "button4",
+                ActionValue::Action(Action::Back),
+            )
+            .unwrap();
+        settings
+            .set_button_action(
+                "1532:0084:SPARE",
+                "button4",
+                ActionValue::Action(Action::Forward),
+            )
+            .unwrap();
+        let spare = device("1532:0084:SPARE");
+        let new = device("093a:2510:NEW");
+        settings.adopt_new_devices(std::slice::from_ref(&new));
+
+        // Nothing stands in for a connected mouse. The spare does, even next
+        // to a mouse that was never set up.
+        assert_eq!(
+            fallback_device(&settings, &[device("046d:c08b:MAIN"), spare.clone()]),
+            None
+        );
+        assert_eq!(
+            fallback_device(&settings, std::slice::from_ref(&spare)).as_deref(),
+            Some("1532:0084:SPARE")
+        );
+        assert_eq!(
+            fallback_device(&settings, &[spare.clone(), new.clone()]).as_deref(),
+            Some("1532:0084:SPARE")
+        );
+
+        // Any mouse counts them all, and one never set up takes the "Any
+        // mouse" mappings, once there are some.
+        settings.fallback_devices = FallbackDevices::Any;
+        assert_eq!(
+            fallback_device(&settings, &[spare.clone(), new.clone()]),
+            None
+        );
+        assert_eq!(fallback_device(&settings, std::slice::from_ref(&new)), None);
+        settings
+            .set_button_action(ANY_DEVICE_ID, "button5", ActionValue::Action(Action::Back))
+            .unwrap();
+        assert_eq!(
+            fallback_device(&settings, std::slice::from_ref(&new)).as_deref(),
+            Some("093a:2510:NEW")
+        );
+        // An ignored device neither stands in nor counts as a candidate.
+        settings
+            .set_device_ignored("1532:0084:SPARE", true)
+            .unwrap();
+        assert_eq!(
+            fallback_device(&settings, &[spare.clone(), new.clone()]).as_deref(),
+            Some("093a:2510:NEW")
+        );
+        settings
+            .set_device_ignored("1532:0084:SPARE", false)
+            .unwrap();
+
+        let context = MappingContext {
+            device_available: true,
+            fallback: Some("1532:0084:SPARE"),
+            suspended: false,
+            ignored_connected: false,
+            button_count: 5,
+            app: None,
+        };
+        let resolved = ResolvedMappings::resolve(&settings, &context);
+        assert_eq!(resolved.device_id.as_deref(), Some("1532:0084:SPARE"));
+        assert_eq!(
+            resolved.mapping(3, 0).map(ResolvedAction::action),
+            Some(Action::Forward)
+        );
+        let resolved = ResolvedMappings::resolve(
+            &settings,
+            &MappingContext {
+                fallback: Some("093a:2510:NEW"),
+                ..context
+            },
+        );
+        assert_eq!(resolved.device_id.as_deref(), Some("093a:2510:NEW"));
+        assert_eq!(
+            resolved.mapping(4, 0).map(ResolvedAction::action),
+            Some(Action::Back)
+        );
+
+        settings.fallback_to_any_device = false;
+        assert_eq!(fallback_device(&settings, std::slice::from_ref(&new)), None);
+    }
+
+    #[test]
+    fn the_cycle_hotkey_steps_through_connected_configured_devices() {
+        let device = |id: &str| MouseDevice {
+            id: id.to_string(),
+            name: "Mouse".to_string(),
+            kind: DeviceKind::Mouse,
+            button_count: 5,
+            preset: None,
+            first_seen: None,
+            ambiguous: false,
+            disabled: false,
+            ignored: false,
+        };
+        let mut settings = Settings {
+            selected_device: Some("046d:c08b:A".to_string()),
+            ..Settings::default()
+        };
+        for id in ["046d:c08b:A", "046d:c408:B", "1532:0084:C"] {
+            settings
+                .set_button_action(id, "button4", ActionValue::Action(Action::Back))
+                .unwrap();
+        }
+        let fresh = device("093a:2510:NEW");
+        settings.adopt_new_devices(std::slice::from_ref(&fresh));
+        let connected = [
+            device("046d:c08b:A"),
+            fresh,
+            device("046d:c408:B"),
+            device("1532:0084:C"),
+        ];
+        // Never-set-up and ignored devices are skipped, and the cycle
+        // wraps around.
+        settings.set_device_ignored("1532:0084:C", true).unwrap();
+        assert_eq!(
+            next_selected_device(&settings, &connected).as_deref(),
+            Some("046d:c408:B")
+        );
+        settings.selected_device = Some("046d:c408:B".to_string());
+        assert_eq!(
+            next_selected_device(&settings, &connected).as_deref(),
+            Some("046d:c08b:A")
+        );
+        // A selection that isn't connected gives way to the first.
+        settings.selected_device = Some(ANY_DEVICE_ID.to_string());
+        assert_eq!(
+            next_selected_device(&settings, &connected).as_deref(),
+            Some("046d:c08b:A")
+        );
+        // With only the selected device there's nothing to switch to.
+        settings.selected_device = Some("046d:c08b:A".to_string());
+        assert_eq!(next_selected_device(&settings, &connected[..2]), None);
+        assert_eq!(next_selected_device(&settings, &[]), None);
+    }
+
+    #[test]
+    fn capabilities_come_from_descriptors() {
+        let mut wheel = mouse_report(5);
+        // Wheel and AC Pan, one relative byte each, before End Collection.
+        wheel.splice(
+            wheel.len() - 1..wheel.len() - 1,
+            [
+                0x09, 0x38, 0x15, 0x81, 0x25, 0x7f, 0x75, 0x08, 0x95, 0x01, 0x81, 0x06, //
+                0x05, 0x0c, 0x0a, 0x38, 0x02, 0x95, 0x01, 0x81, 0x06,
+            ],
+        );
+        let capabilities = DeviceCapabilities::from_descriptors(5, &[wheel]);
+        assert!(capabilities.known && capabilities.has_wheel && capabilities.has_tilt);
+        assert!(!capabilities.battery && !capabilities.extended_buttons);
+
+        let plain = DeviceCapabilities::from_descriptors(3, &[mouse_report(3)]);
+        assert!(!plain.has_wheel && !plain.has_tilt);
+        assert_eq!(
+            DeviceCapabilities::from_descriptors(5, &[]),
+            DeviceCapabilities::unknown(5)
+        );
+    }
+
+    #[test]
+    fn extended_buttons_decode_outside_pointer_report() {
+        let mut descriptor = mouse_report(5);
+        descriptor.splice(6..6, [0x85, 0x01]); // pointer report gets Report ID 1
+        descriptor.extend([
+            0x05, 0x0c, 0x09, 0x01, 0xa1, 0x01, // Consumer, Consumer Control, Collection
+            0x85, 0x03, 0x19, 0x00, 0x2a, 0xff, 0x03, // Report ID 3, usages 0-0x3ff
+            0x15, 0x00, 0x26, 0xff, 0x03, 0x75, 0x10, 0x95, 0x01, 0x81, 0x00, // array
+            0xc0, //
+            0x06, 0x00, 0xff, 0x09, 0x01, 0xa1, 0x01, // Vendor page collection
+            0x85, 0x04, 0x05, 0x09, 0x19, 0x06, 0x29, 0x08, // Report ID 4, buttons 6-8
+            0x15, 0x00, 0x25, 0x01, 0x75, 0x01, 0x95, 0x03, 0x81, 0x02, //
+            0x75, 0x05, 0x95, 0x01, 0x81, 0x01, // padding
+            0xc0,
+        ]);
+        let fields = extended_button_fields(&descriptor);
+        assert!(fields.iter().all(|field| field.report_id != 1));
+
+        assert_eq!(report_buttons(&fields, &[0x01, 0x1f, 0, 0]), None);
+        assert_eq!(
+            report_buttons(&fields, &[0x03, 0x24, 0x02]),
+            Some((3, vec![3]))
+        );
+        assert_eq!(report_buttons(&fields, &[0x03, 0, 0]), Some((3, vec![])));
+        assert_eq!(
+            report_buttons(&fields, &[0x04, 0b101]),
+            Some((4, vec![5, 7]))
+        );
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn vendor_and_product_come_from_device_ids() {
+        assert_eq!(
+            hid_vendor_product("046d:c539:ABC123"),
+            Some((0x046d, 0xc539))
+        );
+        assert_eq!(
+            hid_vendor_product("046d:b023:bt-1a2b3c4d"),
+            Some((0x046d, 0xb023))
+        );
+        assert_eq!(hid_vendor_product(ANY_DEVICE_ID), None);
+    }
+
+    #[test]
+    fn report_rates_leave_out_pauses_and_need_movement() {
+        let millis = |ms: f64| Duration::from_secs_f64(ms / 1000.0);
+        // 1000 Hz, a pause, then 500 Hz.
+        let mut times: Vec<Duration> = (0..30).map(|n| millis(f64::from(n))).collect();
+        times.extend((0..31).map(|n| millis(500.0 + 2.0 * f64::from(n))));
+        let rate = report_rate(&times).unwrap();
+        assert_eq!(rate.intervals, 59);
+        assert!((rate.max_hz - 1000.0).abs() < 1.0, "{:?}", rate);
+        assert!((rate.min_hz - 500.0).abs() < 1.0, "{:?}", rate);
+        assert!(rate.avg_hz > 500.0 && rate.avg_hz < 1000.0);
+
+        let slower = ReportRate {
+            avg_hz: 125.0,
+            ..rate
+        };
+        assert!(slower.differs_from(&rate));
+        assert!(!rate.differs_from(&ReportRate {
+            avg_hz: rate.avg_hz * 0.9,
+            ..rate
+        }));
+
+        // Still, or barely moved.
+        assert_eq!(report_rate(&[]), None);
+        let few: Vec<Duration> = (0..10).map(|n| millis(f64::from(n))).collect();
+        assert_eq!(report_rate(&few), None);
+        let idle: Vec<Duration> = (0..40).map(|n| millis(100.0 * f64::from(n))).collect();
+        assert_eq!(report_rate(&idle), None);
+    }
+
+    #[test]
+    fn hid_debug_reports_are_limited_per_second() {
+        assert_eq!(hex_dump(&[0x01, 0xff, 0x00]), "01 ff 00");
+        assert_eq!(hex_dump(&[]), "");
+
+        let mut limiter = ReportLimiter::default();
+        let at = |ms| Duration::from_millis(ms);
+        for n in 0..HID_DEBUG_EVENTS_PER_SECOND {
+            assert_eq!(limiter.admit(at(u64::from(n))), Some(0));
+        }
+        assert_eq!(limiter.admit(at(500)), None);
+        assert_eq!(limiter.admit(at(999)), None);
+        // The next second lets reports through again, with the count of the
+        // ones held back.
+        assert_eq!(limiter.admit(at(1000)), Some(2));
+        assert_eq!(limiter.admit(at(1001)), Some(0));
+    }
+
+    #[test]
+    fn low_battery_warns_once_per_discharge() {
+        let reading = |percentage, charging| BatteryReport::Available {
+            percentage,
+            charging: Some(charging),
+        };
+        let mut watch = BatteryWatch::default();
+        assert_eq!(watch.observe("m", &reading(40, false), 15), None);
+        assert_eq!(watch.observe("m", &reading(14, false), 15), Some(14));
+        assert_eq!(watch.observe("m", &reading(10, false), 15), None);
+        // Back above the threshold, but not by the margin.
+        assert_eq!(watch.observe("m", &reading(16, false), 15), None);
+        assert_eq!(watch.observe("m", &reading(13, false), 15), None);
+        // Reconnecting keeps the warning; charging ends the discharge.
+        watch.retain_connected(&[]);
+        assert_eq!(watch.observe("m", &reading(12, false), 15), None);
+        assert_eq!(watch.observe("m", &reading(12, true), 15), None);
+        assert_eq!(watch.observe("m", &reading(11, false), 15), Some(11));
+
+        assert!(watch.should_check("k"));
+        assert_eq!(watch.observe("k", &BatteryReport::NotSupported, 15), None);
+        assert!(!watch.should_check("k"));
+        watch.retain_connected(&[]);
+        assert!(watch.should_check("k"));
+
+        let settings = Settings {
+            battery_check_minutes: 0,
+            low_battery_percent: 101,
+            idle_suspend_minutes: Some(0),
+            ..Settings::default()
+        };
+        let fields: Vec<&str> = settings.issues().iter().map(|issue| issue.field).collect();
+        assert!(fields.contains(&"battery_check_minutes"));
+        assert!(fields.contains(&"low_battery_percent"));
+        assert!(fields.contains(&"idle_suspend_minutes"));
+    }
+
+    #[test]
+    fn dpi_actions_and_stops() {
+        assert_eq!(Action::from_name("Cycle DPI"), Some(Action::CycleDpi));
+        assert_eq!(
+            Action::from_name("Set DPI 1600"),
+            Some(Action::SetDpi { dpi: 1600 })
+        );
+        assert_eq!(Action::from_name("Set DPI fast"), None);
+        assert_eq!(
+            Action::from_id("set_dpi:1600"),
+            Some(Action::SetDpi { dpi: 1600 })
+        );
+        assert_eq!(Action::SetDpi { dpi: 1600 }.id(), "set_dpi:1600");
+
+        let stops = [1600, 400, 800];
+        assert_eq!(next_dpi_stop(&stops, 400), Some(800));
+        assert_eq!(next_dpi_stop(&stops, 1000), Some(1600));
+        assert_eq!(next_dpi_stop(&stops, 1600), Some(400));
+        assert_eq!(next_dpi_stop(&[], 800), None);
+    }
+
+    #[test]
+    fn descriptor_axes_are_detected() {
+        // Usage Page (Generic Desktop), Usage (Mouse), Collection, Usage (X), Usage (Y)
+        let mouse = [
+            0x05, 0x01, 0x09, 0x02, 0xa1, 0x01, 0x09, 0x30, 0x09, 0x31, 0xc0,
+        ];
+        assert!(descriptor_has_pointer_axes(&mouse));
+        // Usage Page (Generic Desktop), Usage (Keyboard), Usage Page (Keyboard), Usage (0x30)
+        let keyboard = [0x05, 0x01, 0x09, 0x06, 0x05, 0x07, 0x09, 0x30, 0x09, 0x31];
+        assert!(!descriptor_has_pointer_axes(&keyboard));
+    }
+
+    #[test]
+    fn button_count_comes_from_descriptor() {
+        assert_eq!(descriptor_button_count(&mouse_report(3)), Some(3));
+        assert_eq!(descriptor_button_count(&mouse_report(8)), Some(8));
+        // Usage Maximum (16) with only 8 report slots.
+        let mut declared = mouse_report(8);
+        declared[11] = 16;
+        assert_eq!(descriptor_button_count(&declared), Some(8));
+        assert_eq!(descriptor_button_count(&[0x05, 0x01, 0x09, 0x06]), None);
+
+        let entries = vec![entry("MX Master 3", Some("A"), 0x02, 0, "p0")];
+        let devices =
+            collect_mouse_devices(&entries, DeviceFilter::default(), |_| Some(mouse_report(8)));
+        assert_eq!(devices[0].button_count, 8);
+        assert_eq!(collect(&entries)[0].button_count, DEFAULT_BUTTON_COUNT);
+    }
+
+    #[test]
+    fn touchpads_are_detected_by_properties() {
+        let table = [
+            (
+                descriptor(
+                    "Magic Trackpad 2",
+                    0x05ac,
+                    0x0265,
+                    Transport::Bluetooth,
+                    &[(0x01, 0x02), (0x0d, 0x05)],
+                ),
+                true,
+            ),
+            (
+                descriptor(
+                    "Trackpad interno",
+                    0x05ac,
+                    0x0340,
+                    Transport::Spi,
+                    &[(0x01, 0x02), (0x01, 0x06)],
+                ),
+                true,
+            ),
+            (
+                descriptor(
+                    "Magic Trackpad",
+                    0x05ac,
+                    0x030e,
+                    Transport::Bluetooth,
+                    &[(0x01, 0x02)],
+                ),
+                true,
+            ),
+            (
+                descriptor(
+                    "Logitech T650",
+                    0x046d,
+                    0x4101,
+                    Transport::Usb,
+                    &[(0x01, 0x02)],
+                ),
+                true,
+            ),
+            (
+                descriptor(
+                    "ELAN Touchpad",
+                    0x04f3,
+                    0x3140,
+                    Transport::I2c,
+                    &[(0x01, 0x02), (0x0d, 0x05)],
+                ),
+                true,
+            ),
+            (
+                descriptor(
+                    "Magic Mouse 2",
+                    0x05ac,
+                    0x0269,
+                    Transport::Bluetooth,
+                    &[(0x01, 0x02)],
+                ),
+                false,
+            ),
+            (
+                descriptor(
+                    "MX Master 3",
+                    0x046d,
+                    0x4082,
+                    Transport::Bluetooth,
+                    &[(0x01, 0x02), (0xff43, 0x0202)],
+                ),
+                false,
+            ),
+            (
+                descriptor(
+                    "Trackpad Killer",
+                    0x1234,
+                    0x5678,
+                    Transport::Usb,
+                    &[(0x01, 0x02)],
+                ),
+                false,
+            ),
+        ];
+        for (entries, touchpad) in table {
+            let members: Vec<&HidEntry> = entries.iter().collect();
+            assert_eq!(is_touchpad(&members), touchpad, "{}", entries[0].name);
+            assert_eq!(
+                collect(&entries).is_empty(),
+                touchpad,
+                "{}",
+                entries[0].name
+            );
+        }
+    }
+
+    #[test]
+    fn non_mouse_devices_are_opt_in() {
+        let trackpad = descriptor(
+            "Magic Trackpad 2",
+            0x05ac,
+            0x0265,
+            Transport::Bluetooth,
+            &[(0x01, 0x02), (0x0d, 0x05)],
+        );
+        let tablet = descriptor("Intuos", 0x056a, 0x0374, Transport::Usb, &[(0x0d, 0x02)]);
+        assert!(collect(&trackpad).is_empty());
+        assert!(collect(&tablet).is_empty());
+
+        let filter = DeviceFilter {
+            show_non_mouse_devices: true,
+            ..DeviceFilter::default()
+        };
+        let devices = collect_mouse_devices(&trackpad, filter, |_| None);
+        assert_eq!(devices[0].kind, DeviceKind::Trackpad);
+        let devices = collect_mouse_devices(&tablet, filter, |_| None);
+        assert_eq!(devices[0].kind, DeviceKind::Tablet);
+    }
+
+    #[test]
+    fn report_fields_track_ids_and_offsets() {
+        let descriptor = [
+            0x05, 0x01, 0x09, 0x02, 0xa1, 0x01, // Generic Desktop, Mouse, Collection
+            0x85, 0x02, // Report ID 2
+            0x05, 0x09, 0x19, 0x01, 0x29, 0x05, // Buttons 1-5
+            0x15, 0x00, 0x25, 0x01, 0x75, 0x01, 0x95, 0x05, 0x81, 0x02, // 5 x 1 bit
+            0x75, 0x03, 0x95, 0x01, 0x81, 0x01, // padding
+            0x05, 0x01, 0x09, 0x30, 0x09, 0x31, // X, Y
+            0x15, 0x81, 0x25, 0x7f, 0x75, 0x08, 0x95, 0x02, 0x81, 0x06, // 2 x i8
+            0xc0, //
+            0x05, 0x06, 0x09, 0x20, // Generic Device Controls, Battery Strength
+            0x85, 0x05, 0x15, 0x00, 0x26, 0xff, 0x00, 0x75, 0x08, 0x95, 0x01, 0xb1, 0x02,
+        ];
+        let fields = parse_report_fields(&descriptor);
+        assert_eq!(fields.len(), 4);
+
+        let buttons = &fields[0];
+        assert_eq!(
+            (buttons.report_id, buttons.bit_offset, buttons.count),
+            (2, 0, 5)
+        );
+        assert_eq!(
+            buttons.usages,
+            vec![
+                0x0009_0001,
+                0x0009_0002,
+                0x0009_0003,
+                0x0009_0004,
+                0x0009_0005
+            ]
+        );
+
+        let axes = &fields[2];
+        assert_eq!(axes.bit_offset, 8);
+        assert_eq!(axes.read(&[0b0000_0101, 0xfe, 0x03], 0), Some(-2));
+        assert_eq!(axes.read(&[0b0000_0101, 0xfe, 0x03], 1), Some(3));
+        assert_eq!(buttons.read(&[0b0000_0101], 2), Some(1));
+
+        let battery = &fields[3];
+        assert_eq!(battery.kind, ReportKind::Feature);
+        assert_eq!((battery.report_id, battery.bit_offset), (5, 0));
+        assert!(battery.contains_usage(USAGE_BATTERY_STRENGTH));
+        assert_eq!(battery.logical_max, 255);
+    }
+}
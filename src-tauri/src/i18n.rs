@@ -0,0 +1,546 @@
+/// A language the tray and notifications are shown in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Locale {
+    En,
+    De,
+    Fr,
+    Es,
+    Ja,
+}
+
+/// `Settings::locale` that follows the system's language.
+pub(crate) const SYSTEM_LOCALE: &str = "system";
+
+/// Everything the backend shows the user, looked up with `Locale::text`.
+/// A "{}" stands for what `Locale::format` puts there, in order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Text {
+    Show,
+    Hide,
+    QuickPanel,
+    Device,
+    Profile,
+    PauseRemapping,
+    PauseQuarterHour,
+    PauseHour,
+    PauseUntilResumed,
+    Resume,
+    EnableRemapping,
+    NeverRemapInCurrentApp,
+    Configuration,
+    RevealConfigFolder,
+    ReloadFromDisk,
+    ResetToDefaults,
+    ClickAgainToReset,
+    EnterSafeMode,
+    ExitSafeMode,
+    Restart,
+    Quit,
+    NoDeviceSelected,
+    AnyMouse,
+    DeviceDisconnected,
+    DeviceDisabled,
+    NeedsAccessibility,
+    CouldntStart,
+    CantRemap,
+    RemappingOff,
+    Paused,
+    PausedGameMode,
+    PausedIdle,
+    SafeMode,
+    NoticeDisconnected,
+    NoticeDisconnectedPaused,
+    NoticeReconnected,
+    NoticeReconnectedResumed,
+    NoticeMissing,
+    NoticeBattery,
+    NoticePrimaryRestored,
+    NoticeFallbackActive,
+    NoticeFallbackEnded,
+    NoticeFallbackStopped,
+    NoticeDeviceSelected,
+}
+
+impl Text {
+    /// Every text, for checking the tables.
+    #[cfg(test)]
+    const ALL: [Text; 44] = [
+        Text::Show,
+        Text::Hide,
+        Text::QuickPanel,
+        Text::Device,
+        Text::Profile,
+        Text::PauseRemapping,
+        Text::PauseQuarterHour,
+        Text::PauseHour,
+        Text::PauseUntilResumed,
+        Text::Resume,
+        Text::EnableRemapping,
+        Text::NeverRemapInCurrentApp,
+        Text::Configuration,
+        Text::RevealConfigFolder,
+        Text::ReloadFromDisk,
+        Text::ResetToDefaults,
+        Text::ClickAgainToReset,
+        Text::EnterSafeMode,
+        Text::ExitSafeMode,
+        Text::Restart,
+        Text::Quit,
+        Text::NoDeviceSelected,
+        Text::AnyMouse,
+        Text::DeviceDisconnected,
+        Text::DeviceDisabled,
+        Text::NeedsAccessibility,
+        Text::CouldntStart,
+        Text::CantRemap,
+        Text::RemappingOff,
+        Text::Paused,
+        Text::PausedGameMode,
+        Text::PausedIdle,
+        Text::SafeMode,
+        Text::NoticeDisconnected,
+        Text::NoticeDisconnectedPaused,
+        Text::NoticeReconnected,
+        Text::NoticeReconnectedResumed,
+        Text::NoticeMissing,
+        Text::NoticeBattery,
+        Text::NoticePrimaryRestored,
+        Text::NoticeFallbackActive,
+        Text::NoticeFallbackEnded,
+        Text::NoticeFallbackStopped,
+        Text::NoticeDeviceSelected,
+    ];
+
+    /// The text in each of `Locale::ALL`, in that order.
+    fn translations(self) -> [&'static str; 5] {
+        match self {
+            Text::Show => ["Show", "Einblenden", "Afficher", "Mostrar", "表示"],
+            Text::Hide => ["Hide", "Ausblenden", "Masquer", "Ocultar", "非表示"],
+            Text::QuickPanel => [
+                "Quick Panel",
+                "Schnellzugriff",
+                "Panneau rapide",
+                "Panel rápido",
+                "クイックパネル",
+            ],
+            Text::Device => ["Device", "Gerät", "Appareil", "Dispositivo", "デバイス"],
+            Text::Profile => ["Profile", "Profil", "Profil", "Perfil", "プロファイル"],
+            Text::PauseRemapping => [
+                "Pause Remapping",
+                "Neubelegung pausieren",
+                "Suspendre la réaffectation",
+                "Pausar la reasignación",
+                "割り当てを一時停止",
+            ],
+            Text::PauseQuarterHour => ["15 Minutes", "15 Minuten", "15 minutes", "15 minutos", "15分"],
+            Text::PauseHour => ["1 Hour", "1 Stunde", "1 heure", "1 hora", "1時間"],
+            Text::PauseUntilResumed => [
+                "Until Resumed",
+                "Bis zur Fortsetzung",
+                "Jusqu’à la reprise",
+                "Hasta reanudar",
+                "再開するまで",
+            ],
+            Text::Resume => ["Resume", "Fortsetzen", "Reprendre", "Reanudar", "再開"],
+            Text::EnableRemapping => [
+                "Enable Remapping",
+                "Neubelegung aktivieren",
+                "Activer la réaffectation",
+                "Activar la reasignación",
+                "割り当てを有効にする",
+            ],
+            Text::NeverRemapInCurrentApp => [
+                "Never Remap in Current App",
+                "In aktueller App nie neu belegen",
+                "Ne jamais réaffecter dans l’app active",
+                "No reasignar nunca en la app actual",
+                "現在のアプリでは割り当てない",
+            ],
+            Text::Configuration => [
+                "Configuration",
+                "Konfiguration",
+                "Configuration",
+                "Configuración",
+                "設定",
+            ],
+            Text::RevealConfigFolder => [
+                "Reveal Config Folder",
+                "Konfigurationsordner zeigen",
+                "Afficher le dossier de configuration",
+                "Mostrar la carpeta de configuración",
+                "設定フォルダを表示",
+            ],
+            Text::ReloadFromDisk => [
+                "Reload From Disk",
+                "Von der Festplatte neu laden",
+                "Recharger depuis le disque",
+                "Recargar desde el disco",
+                "ディスクから再読み込み",
+            ],
+            Text::ResetToDefaults => [
+                "Reset To Defaults…",
+                "Auf Standard zurücksetzen…",
+                "Rétablir les réglages par défaut…",
+                "Restablecer valores predeterminados…",
+                "デフォルトに戻す…",
+            ],
+            Text::ClickAgainToReset => [
+                "Click Again to Reset",
+                "Zum Zurücksetzen erneut klicken",
+                "Cliquer à nouveau pour rétablir",
+                "Vuelve a hacer clic para restablecer",
+                "もう一度クリックしてリセット",
+            ],
+            Text::EnterSafeMode => [
+                "Enter Safe Mode",
+                "Abgesicherten Modus starten",
+                "Passer en mode sans échec",
+                "Entrar en modo seguro",
+                "セーフモードに入る",
+            ],
+            Text::ExitSafeMode => [
+                "Exit Safe Mode",
+                "Abgesicherten Modus beenden",
+                "Quitter le mode sans échec",
+                "Salir del modo seguro",
+                "セーフモードを終了",
+            ],
+            Text::Restart => [
+                "Restart Edit Mouse",
+                "Edit Mouse neu starten",
+                "Redémarrer Edit Mouse",
+                "Reiniciar Edit Mouse",
+                "Edit Mouseを再起動",
+            ],
+            Text::Quit => ["Quit", "Beenden", "Quitter", "Salir", "終了"],
+            Text::NoDeviceSelected => [
+                "No device selected",
+                "Kein Gerät ausgewählt",
+                "Aucun appareil sélectionné",
+                "Ningún dispositivo seleccionado",
+                "デバイスが選択されていません",
+            ],
+            Text::AnyMouse => [
+                "Any mouse",
+                "Jede Maus",
+                "N’importe quelle souris",
+                "Cualquier ratón",
+                "すべてのマウス",
+            ],
+            Text::DeviceDisconnected => [
+                "{} — disconnected",
+                "{} — getrennt",
+                "{} — déconnecté",
+                "{} — desconectado",
+                "{} — 未接続",
+            ],
+            Text::DeviceDisabled => [
+                "{} — disabled",
+                "{} — deaktiviert",
+                "{} — désactivé",
+                "{} — desactivado",
+                "{} — 無効",
+            ],
+            Text::NeedsAccessibility => [
+                "Edit Mouse needs Accessibility permission to remap buttons",
+                "Edit Mouse braucht die Berechtigung für Bedienungshilfen, um Tasten neu zu belegen",
+                "Edit Mouse a besoin de l’autorisation Accessibilité pour réaffecter les boutons",
+                "Edit Mouse necesita el permiso de Accesibilidad para reasignar botones",
+                "Edit Mouseがボタンを割り当てるにはアクセシビリティの許可が必要です",
+            ],
+            Text::CouldntStart => [
+                "Edit Mouse couldn't start remapping",
+                "Edit Mouse konnte die Neubelegung nicht starten",
+                "Edit Mouse n’a pas pu démarrer la réaffectation",
+                "Edit Mouse no pudo iniciar la reasignación",
+                "Edit Mouseは割り当てを開始できませんでした",
+            ],
+            Text::CantRemap => [
+                "Edit Mouse can't remap on this system",
+                "Edit Mouse kann auf diesem System keine Tasten neu belegen",
+                "Edit Mouse ne peut pas réaffecter de boutons sur ce système",
+                "Edit Mouse no puede reasignar botones en este sistema",
+                "Edit Mouseはこのシステムでは割り当てできません",
+            ],
+            Text::RemappingOff => [
+                "Edit Mouse (remapping off)",
+                "Edit Mouse (Neubelegung aus)",
+                "Edit Mouse (réaffectation désactivée)",
+                "Edit Mouse (reasignación desactivada)",
+                "Edit Mouse(割り当てオフ)",
+            ],
+            Text::Paused => [
+                "Edit Mouse (paused)",
+                "Edit Mouse (pausiert)",
+                "Edit Mouse (en pause)",
+                "Edit Mouse (en pausa)",
+                "Edit Mouse(一時停止中)",
+            ],
+            Text::PausedGameMode => [
+                "Edit Mouse (paused: game mode)",
+                "Edit Mouse (pausiert: Spielmodus)",
+                "Edit Mouse (en pause : mode jeu)",
+                "Edit Mouse (en pausa: modo juego)",
+                "Edit Mouse(一時停止中: ゲームモード)",
+            ],
+            Text::PausedIdle => [
+                "Edit Mouse (paused: idle)",
+                "Edit Mouse (pausiert: inaktiv)",
+                "Edit Mouse (en pause : inactivité)",
+                "Edit Mouse (en pausa: inactividad)",
+                "Edit Mouse(一時停止中: 操作なし)",
+            ],
+            Text::SafeMode => [
+                "Edit Mouse (safe mode)",
+                "Edit Mouse (abgesicherter Modus)",
+                "Edit Mouse (mode sans échec)",
+                "Edit Mouse (modo seguro)",
+                "Edit Mouse(セーフモード)",
+            ],
+            Text::NoticeDisconnected => [
+                "{} disconnected",
+                "{} getrennt",
+                "{} déconnecté",
+                "{} desconectado",
+                "{}の接続が切れました",
+            ],
+            Text::NoticeDisconnectedPaused => [
+                "{} disconnected — remapping paused",
+                "{} getrennt — Neubelegung pausiert",
+                "{} déconnecté — réaffectation suspendue",
+                "{} desconectado — reasignación en pausa",
+                "{}の接続が切れました — 割り当てを一時停止しました",
+            ],
+            Text::NoticeReconnected => [
+                "{} reconnected",
+                "{} wieder verbunden",
+                "{} reconnecté",
+                "{} reconectado",
+                "{}が再接続されました",
+            ],
+            Text::NoticeReconnectedResumed => [
+                "{} reconnected — remapping resumed",
+                "{} wieder verbunden — Neubelegung fortgesetzt",
+                "{} reconnecté — réaffectation reprise",
+                "{} reconectado — reasignación reanudada",
+                "{}が再接続されました — 割り当てを再開しました",
+            ],
+            Text::NoticeMissing => [
+                "{} isn't connected; its mappings resume when it reconnects",
+                "{} ist nicht verbunden; die Belegungen gelten wieder, sobald es sich verbindet",
+                "{} n’est pas connecté ; ses réaffectations reprendront à sa reconnexion",
+                "{} no está conectado; sus asignaciones se reanudan cuando se reconecte",
+                "{}は接続されていません。再接続すると割り当てが再開します",
+            ],
+            Text::NoticeBattery => [
+                "{} at {}%",
+                "{} bei {} %",
+                "{} à {} %",
+                "{} al {} %",
+                "{}のバッテリー残量 {}%",
+            ],
+            Text::NoticePrimaryRestored => [
+                "Left click is back — choose the left button's action again in Edit Mouse to remap it",
+                "Linksklick ist zurück — wähle die Aktion der linken Taste in Edit Mouse erneut, um sie neu zu belegen",
+                "Le clic gauche est rétabli — choisissez à nouveau l’action du bouton gauche dans Edit Mouse pour le réaffecter",
+                "El clic izquierdo ha vuelto — elige de nuevo la acción del botón izquierdo en Edit Mouse para reasignarlo",
+                "左クリックを元に戻しました — 再び割り当てるには、Edit Mouseで左ボタンの動作を選び直してください",
+            ],
+            Text::NoticeFallbackActive => [
+                "{} isn't connected — remapping {} instead",
+                "{} ist nicht verbunden — stattdessen wird {} neu belegt",
+                "{} n’est pas connecté — réaffectation de {} à la place",
+                "{} no está conectado — se reasigna {} en su lugar",
+                "{}は接続されていません — 代わりに{}を割り当てています",
+            ],
+            Text::NoticeFallbackEnded => [
+                "{} is back — remapping it again instead of {}",
+                "{} ist zurück — wird wieder statt {} neu belegt",
+                "{} est de retour — réaffecté à nouveau à la place de {}",
+                "{} ha vuelto — se reasigna de nuevo en lugar de {}",
+                "{}が戻りました — {}の代わりに再び割り当てています",
+            ],
+            Text::NoticeFallbackStopped => [
+                "No longer remapping {} in place of {}",
+                "{} wird nicht mehr anstelle von {} neu belegt",
+                "{} n’est plus réaffecté à la place de {}",
+                "{} ya no se reasigna en lugar de {}",
+                "{}は{}の代わりに割り当てられなくなりました",
+            ],
+            Text::NoticeDeviceSelected => [
+                "Now remapping {}",
+                "Jetzt wird {} neu belegt",
+                "Réaffectation de {} désormais",
+                "Ahora se reasigna {}",
+                "{}を割り当てています",
+            ],
+        }
+    }
+}
+
+impl Locale {
+    pub(crate) const ALL: [Locale; 5] =
+        [Locale::En, Locale::De, Locale::Fr, Locale::Es, Locale::Ja];
+
+    /// As `Settings::locale` stores it.
+    pub(crate) fn code(self) -> &'static str {
+        match self {
+            Locale::En => "en",
+            Locale::De => "de",
+            Locale::Fr => "fr",
+            Locale::Es => "es",
+            Locale::Ja => "ja",
+        }
+    }
+
+    /// The language's name in itself, for picking it.
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            Locale::En => "English",
+            Locale::De => "Deutsch",
+            Locale::Fr => "Français",
+            Locale::Es => "Español",
+            Locale::Ja => "日本語",
+        }
+    }
+
+    /// The locale of a language tag such as "de-CH" or "fr_FR.UTF-8", by its
+    /// language alone.
+    pub(crate) fn from_tag(tag: &str) -> Option<Self> {
+        let language = tag.split(['-', '_', '.', '@']).next()?;
+        Self::ALL
+            .into_iter()
+            .find(|locale| locale.code().eq_ignore_ascii_case(language))
+    }
+
+    /// The locale `Settings::locale` names, or the system's for
+    /// `SYSTEM_LOCALE` and codes it doesn't know. English when the system's
+    /// language isn't one of `ALL`.
+    pub(crate) fn from_setting(setting: &str) -> Self {
+        Self::from_tag(setting)
+            .or_else(system_locale)
+            .unwrap_or(Locale::En)
+    }
+
+    pub(crate) fn text(self, text: Text) -> &'static str {
+        text.translations()[self as usize]
+    }
+
+    /// `text` with each "{}" replaced by the next of `args`.
+    pub(crate) fn format(self, text: Text, args: &[&str]) -> String {
+        let mut parts = self.text(text).split("{}");
+        let mut formatted = parts.next().unwrap_or_default().to_string();
+        for (part, arg) in parts.zip(args.iter().chain(std::iter::repeat(&""))) {
+            formatted.push_str(arg);
+            formatted.push_str(part);
+        }
+        formatted
+    }
+}
+
+/// The first of the user's preferred languages there's a locale for.
+#[cfg(target_os = "macos")]
+fn system_locale() -> Option<Locale> {
+    use objc2_foundation::NSLocale;
+
+    NSLocale::preferredLanguages()
+        .iter()
+        .find_map(|language| Locale::from_tag(&language.to_string()))
+}
+
+/// The display language chosen under Time & Language.
+#[cfg(windows)]
+fn system_locale() -> Option<Locale> {
+    use windows_sys::Win32::System::Registry::{RegGetValueW, HKEY_CURRENT_USER, RRF_RT_REG_SZ};
+
+    let wide = |text: &str| text.encode_utf16().chain(Some(0)).collect::<Vec<u16>>();
+    let key = wide(r"Control Panel\International");
+    let value = wide("LocaleName");
+    let mut name = [0u16; 85];
+    let mut size = std::mem::size_of_val(&name) as u32;
+    let status = unsafe {
+        RegGetValueW(
+            HKEY_CURRENT_USER,
+            key.as_ptr(),
+            value.as_ptr(),
+            RRF_RT_REG_SZ,
+            std::ptr::null_mut(),
+            name.as_mut_ptr().cast(),
+            &mut size,
+        )
+    };
+    if status != 0 {
+        return None;
+    }
+    let len = name
+        .iter()
+        .position(|unit| *unit == 0)
+        .unwrap_or(name.len());
+    Locale::from_tag(&String::from_utf16_lossy(&name[..len]))
+}
+
+/// The language of messages, as the environment sets it.
+#[cfg(not(any(target_os = "macos", windows)))]
+fn system_locale() -> Option<Locale> {
+    ["LC_ALL", "LC_MESSAGES", "LANG"]
+        .into_iter()
+        .filter_map(|name| std::env::var(name).ok())
+        .find(|value| !value.is_empty())
+        .and_then(|value| Locale::from_tag(&value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Settings;
+
+    #[test]
+    fn locales_come_from_tags_and_settings() {
+        assert_eq!(Locale::from_tag("de-CH"), Some(Locale::De));
+        assert_eq!(Locale::from_tag("fr_FR.UTF-8"), Some(Locale::Fr));
+        assert_eq!(Locale::from_tag("JA"), Some(Locale::Ja));
+        assert_eq!(Locale::from_tag("pt-BR"), None);
+        assert_eq!(Locale::from_tag("C"), None);
+        assert_eq!(Locale::from_setting("es"), Locale::Es);
+        for locale in Locale::ALL {
+            assert_eq!(Locale::from_tag(locale.code()), Some(locale));
+        }
+
+        let mut settings = Settings::default();
+        assert_eq!(settings.locale, SYSTEM_LOCALE);
+        assert!(settings.issues().is_empty());
+        settings.locale = "klingon".to_string();
+        let issues = settings.issues();
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].code, "unknown-locale");
+        assert!(settings.validate().is_ok());
+    }
+
+    #[test]
+    fn every_translation_keeps_the_placeholders() {
+        let placeholders = |text: &str| text.matches("{}").count();
+        for text in Text::ALL {
+            let english = Locale::En.text(text);
+            for locale in Locale::ALL {
+                let translated = locale.text(text);
+                assert!(!translated.is_empty(), "{:?} for {:?}", text, locale);
+                assert_eq!(
+                    placeholders(translated),
+                    placeholders(english),
+                    "{:?} for {:?}",
+                    text,
+                    locale
+                );
+            }
+        }
+        assert_eq!(
+            Locale::En.format(Text::NoticeBattery, &["MX Master 3", "15"]),
+            "MX Master 3 at 15%"
+        );
+        assert_eq!(
+            Locale::De.format(Text::DeviceDisconnected, &["MX Master 3"]),
+            "MX Master 3 — getrennt"
+        );
+        assert_eq!(Locale::Ja.text(Text::Quit), "終了");
+    }
+}
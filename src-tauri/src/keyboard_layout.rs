@@ -0,0 +1,283 @@
+use super::*;
+
+/// Keycodes a `KeyLayout` covers: every macOS virtual keycode.
+const KEYCODES: usize = MAX_KEYCODE as usize + 1;
+
+/// Modifiers a character may need on top of its key, in the order they're
+/// tried, so a character is typed with as few as the layout allows.
+const LAYOUT_MODIFIERS: [&[Modifier]; 4] = [
+    &[],
+    &[Modifier::Shift],
+    &[Modifier::Option],
+    &[Modifier::Shift, Modifier::Option],
+];
+
+/// What each key types on a keyboard layout, for finding the key of a
+/// shortcut's character.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct KeyLayout {
+    /// The character each keycode types with the modifiers at the same
+    /// index of `LAYOUT_MODIFIERS`.
+    chars: [[Option<char>; KEYCODES]; LAYOUT_MODIFIERS.len()],
+}
+
+impl KeyLayout {
+    /// The US layout `KEY_NAMES` describes, for shortcuts stored by
+    /// character while the system's layout can't be read.
+    pub(crate) fn us() -> &'static Self {
+        static US: std::sync::OnceLock<KeyLayout> = std::sync::OnceLock::new();
+        US.get_or_init(|| {
+            let mut chars = [[None; KEYCODES]; LAYOUT_MODIFIERS.len()];
+            for (keycode, name) in KEY_NAMES {
+                let mut name = name.chars();
+                if let (Some(key), None) = (name.next(), name.next()) {
+                    chars[0][usize::from(*keycode)] = Some(key.to_ascii_lowercase());
+                }
+            }
+            Self { chars }
+        })
+    }
+
+    /// What `keycode` types without modifiers, when it types a character.
+    pub(crate) fn character(&self, keycode: u16) -> Option<char> {
+        *self.chars[0].get(usize::from(keycode))?
+    }
+
+    /// The key typing `key` and the modifiers it takes, fewest first.
+    /// `preferred` wins whenever it types `key`, so a recorded shortcut
+    /// posts the key it was recorded with.
+    pub(crate) fn find(&self, key: char, preferred: Option<u16>) -> Option<(u16, ModifierMask)> {
+        let key = key.to_lowercase().next().unwrap_or(key);
+        self.chars
+            .iter()
+            .zip(LAYOUT_MODIFIERS)
+            .find_map(|(chars, modifiers)| {
+                let keycode = preferred
+                    .filter(|keycode| chars.get(usize::from(*keycode)) == Some(&Some(key)))
+                    .or_else(|| {
+                        let index = chars.iter().position(|typed| *typed == Some(key))?;
+                        u16::try_from(index).ok()
+                    })?;
+                Some((keycode, modifier_mask(modifiers)))
+            })
+    }
+}
+
+/// The layout of the current input source, read on the main thread when
+/// `watch` starts and whenever the input source changes.
+static CURRENT: RwLock<Option<Arc<KeyLayout>>> = RwLock::new(None);
+
+pub(crate) fn current() -> Option<Arc<KeyLayout>> {
+    CURRENT.read().ok()?.clone()
+}
+
+/// The key and modifiers a shortcut posts: the key typing `key` on the
+/// current layout, with the modifiers it takes there; `keycode` when the
+/// layout has no such key or the shortcut has no character; or the key
+/// typing `key` on a US layout. None when none of those exist.
+pub(crate) fn resolve(
+    keycode: Option<u16>,
+    key: Option<char>,
+    modifiers: ModifierMask,
+) -> Option<(u16, ModifierMask)> {
+    let (keycode, needed) = key
+        .and_then(|key| current()?.find(key, keycode))
+        .or_else(|| Some((keycode?, 0)))
+        .or_else(|| KeyLayout::us().find(key?, None))?;
+    Some((keycode, modifiers | needed))
+}
+
+/// What `keycode` types on the current layout, stored with a recorded
+/// shortcut so it follows a change of layout.
+pub(crate) fn character(keycode: u16) -> Option<char> {
+    current()?.character(keycode)
+}
+
+/// Reads the current layout, and again on every change of input source.
+/// Must run on the main thread, where Text Input Sources must be asked.
+#[cfg(target_os = "macos")]
+pub(crate) fn watch() {
+    use block2::RcBlock;
+    use objc2_foundation::{NSDistributedNotificationCenter, NSNotification, NSString};
+    use std::ptr::NonNull;
+
+    fn reload() {
+        let layout = read();
+        if layout.is_none() {
+            log::warn!("keyboard: couldn't read the keyboard layout; shortcuts assume US");
+        }
+        if let Ok(mut current) = CURRENT.write() {
+            *current = layout.map(Arc::new);
+        }
+    }
+
+    reload();
+    let block = RcBlock::new(|_: NonNull<NSNotification>| {
+        log::info!("keyboard: input source changed");
+        reload();
+    });
+    let name = NSString::from_str("com.apple.Carbon.TISNotifySelectedKeyboardInputSourceChanged");
+    let observer =
+        unsafe {
+            NSDistributedNotificationCenter::defaultCenter()
+                .addObserverForName_object_queue_usingBlock(Some(&name), None, None, &block)
+        };
+    // Observed for the lifetime of the process.
+    std::mem::forget(observer);
+}
+
+/// Shortcuts are only posted on macOS, whose keycodes they store.
+#[cfg(not(target_os = "macos"))]
+pub(crate) fn watch() {}
+
+/// The current input source's layout as `UCKeyTranslate` reads it, or the
+/// ASCII-capable one an input method such as Japanese types with.
+#[cfg(target_os = "macos")]
+fn read() -> Option<KeyLayout> {
+    use core_foundation::base::CFRelease;
+    use core_foundation::data::{CFDataGetBytePtr, CFDataRef};
+    use core_foundation::string::CFStringRef;
+    use std::ffi::c_void;
+
+    type InputSource = *mut c_void;
+
+    /// `kUCKeyActionDisplay`, and `kUCKeyTranslateNoDeadKeysMask` so a dead
+    /// key reads as the accent it puts down.
+    const KEY_ACTION_DISPLAY: u16 = 3;
+    const NO_DEAD_KEYS: u32 = 1;
+    /// Carbon's `shiftKey` and `optionKey`, shifted as `UCKeyTranslate`
+    /// takes them.
+    const MODIFIER_STATE: [(Modifier, u32); 2] = [
+        (Modifier::Shift, 0x0200 >> 8),
+        (Modifier::Option, 0x0800 >> 8),
+    ];
+
+    #[link(name = "Carbon", kind = "framework")]
+    extern "C" {
+        static kTISPropertyUnicodeKeyLayoutData: CFStringRef;
+        fn TISCopyCurrentKeyboardLayoutInputSource() -> InputSource;
+        fn TISCopyCurrentASCIICapableKeyboardLayoutInputSource() -> InputSource;
+        fn TISGetInputSourceProperty(source: InputSource, key: CFStringRef) -> *const c_void;
+        fn LMGetKbdType() -> u8;
+        fn UCKeyTranslate(
+            layout: *const c_void,
+            keycode: u16,
+            action: u16,
+            modifier_state: u32,
+            keyboard_type: u32,
+            options: u32,
+            dead_key_state: *mut u32,
+            max_length: usize,
+            length: *mut usize,
+            text: *mut u16,
+        ) -> i32;
+    }
+
+    let copies: [unsafe extern "C" fn() -> InputSource; 2] = [
+        TISCopyCurrentKeyboardLayoutInputSource,
+        TISCopyCurrentASCIICapableKeyboardLayoutInputSource,
+    ];
+    copies.iter().find_map(|copy| unsafe {
+        let source = copy();
+        if source.is_null() {
+            return None;
+        }
+        let data = TISGetInputSourceProperty(source, kTISPropertyUnicodeKeyLayoutData);
+        let layout = (!data.is_null()).then(|| {
+            let layout = CFDataGetBytePtr(data as CFDataRef).cast::<c_void>();
+            let keyboard_type = u32::from(LMGetKbdType());
+            let mut chars = [[None; KEYCODES]; LAYOUT_MODIFIERS.len()];
+            for (row, modifiers) in chars.iter_mut().zip(LAYOUT_MODIFIERS) {
+                let held = modifier_mask(modifiers);
+                let state = MODIFIER_STATE
+                    .iter()
+                    .filter(|(modifier, _)| held & modifier_mask(&[*modifier]) != 0)
+                    .fold(0, |state, (_, bit)| state | bit);
+                for (keycode, typed) in (0..).zip(row.iter_mut()) {
+                    let mut dead_key_state = 0;
+                    let mut length = 0;
+                    let mut text = [0u16; 4];
+                    let status = UCKeyTranslate(
+                        layout,
+                        keycode,
+                        KEY_ACTION_DISPLAY,
+                        state,
+                        keyboard_type,
+                        NO_DEAD_KEYS,
+                        &mut dead_key_state,
+                        text.len(),
+                        &mut length,
+                        text.as_mut_ptr(),
+                    );
+                    if status != 0 {
+                        continue;
+                    }
+                    let mut decoded =
+                        char::decode_utf16(text[..length.min(text.len())].iter().copied());
+                    if let (Some(Ok(key)), None) = (decoded.next(), decoded.next()) {
+                        *typed = Some(key).filter(|key| !key.is_control());
+                    }
+                }
+            }
+            KeyLayout { chars }
+        });
+        CFRelease(source.cast_const());
+        layout
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A German layout's keys for the characters the tests use: Y and Z
+    /// swapped, and brackets on Option-5 and Option-6.
+    fn german() -> KeyLayout {
+        let mut chars = [[None; KEYCODES]; LAYOUT_MODIFIERS.len()];
+        chars[0][0x06] = Some('y');
+        chars[0][0x10] = Some('z');
+        chars[0][0x17] = Some('5');
+        chars[0][0x16] = Some('6');
+        chars[0][0x21] = Some('ü');
+        chars[1][0x17] = Some('%');
+        chars[2][0x17] = Some('[');
+        chars[2][0x16] = Some(']');
+        KeyLayout { chars }
+    }
+
+    #[test]
+    fn characters_find_their_key_on_the_layout() {
+        let us = KeyLayout::us();
+        let option = modifier_mask(&[Modifier::Option]);
+        let shift = modifier_mask(&[Modifier::Shift]);
+        assert_eq!(us.find('[', None), Some((0x21, 0)));
+        assert_eq!(us.find('Z', None), Some((0x06, 0)));
+        assert_eq!(us.character(0x1e), Some(']'));
+        assert_eq!(us.character(0x7a), None);
+
+        let german = german();
+        assert_eq!(german.find('[', Some(0x21)), Some((0x17, option)));
+        assert_eq!(german.find('z', Some(0x06)), Some((0x10, 0)));
+        assert_eq!(german.find('%', None), Some((0x17, shift)));
+        assert_eq!(german.find('`', None), None);
+        // The key it was recorded with wins, even after another that types it.
+        let mut doubled = german.clone();
+        doubled.chars[0][0x53] = Some('5');
+        assert_eq!(doubled.find('5', Some(0x53)), Some((0x53, 0)));
+        assert_eq!(doubled.find('5', None), Some((0x17, 0)));
+    }
+
+    #[test]
+    fn shortcuts_fall_back_to_their_keycode_then_the_us_key() {
+        let command = modifier_mask(&[Modifier::Command]);
+        // No layout is read in tests.
+        assert_eq!(resolve(Some(0x7a), None, command), Some((0x7a, command)));
+        assert_eq!(
+            resolve(Some(0x21), Some('ü'), command),
+            Some((0x21, command))
+        );
+        assert_eq!(resolve(None, Some(']'), command), Some((0x1e, command)));
+        assert_eq!(resolve(None, Some('ü'), command), None);
+        assert_eq!(resolve(None, None, command), None);
+    }
+}
@@ -0,0 +1,14157 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::ffi::CString;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Condvar, Mutex, RwLock};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tauri::menu::{CheckMenuItem, Menu, MenuItem, Submenu};
+use tauri::tray::{MouseButton, MouseButtonState, TrayIconEvent};
+use tauri::{Emitter, Listener, Manager, State, WindowEvent};
+#[cfg(target_os = "macos")]
+use tauri_plugin_autostart::MacosLauncher;
+use tauri_plugin_autostart::{Builder as AutostartBuilder, ManagerExt};
+use tauri_plugin_notification::NotificationExt;
+
+/// What a button can be mapped to, and the keys those actions press.
+mod actions;
+/// Other remapping software running alongside, which would remap the same
+/// presses again.
+mod conflicts;
+/// Listing mice over HID and reading their descriptors, batteries, and DPI.
+mod devices;
+/// The tray's and notifications' strings in each language they come in.
+mod i18n;
+/// The keys of the current keyboard layout, so shortcuts type the character
+/// they were entered with.
+mod keyboard_layout;
+/// Turning button events into actions, and each platform's engine.
+mod remap;
+/// Switching profiles at the times of the week their schedules give.
+mod schedule;
+/// The settings file: what it holds, how it's checked, and how it's kept.
+mod settings;
+
+use actions::*;
+use devices::*;
+use i18n::{Locale, Text, SYSTEM_LOCALE};
+use remap::*;
+use settings::*;
+
+/// Settings key of the wildcard profile that applies to whichever mouse is
+/// connected. Once events can be attributed to a specific device, an explicit
+/// config for that device should win over this one.
+const ANY_DEVICE_ID: &str = "*";
+
+/// Settings keys of the remappable buttons, indexed by CoreGraphics/HID
+/// button number minus one.
+const BUTTON_KEYS: &[&str] = &[
+    "left", "right", "middle", "button4", "button5", "button6", "button7", "button8", "button9",
+    "button10",
+];
+
+/// Button count assumed when a device's report descriptor can't be read.
+const DEFAULT_BUTTON_COUNT: u8 = 5;
+
+/// Settings key of a zero-based button number.
+fn button_key(button: i64) -> Option<&'static str> {
+    usize::try_from(button)
+        .ok()
+        .and_then(|index| BUTTON_KEYS.get(index).copied())
+}
+
+/// Default mapping for the first `count` buttons.
+fn default_buttons(count: u8) -> HashMap<String, ActionValue> {
+    BUTTON_KEYS
+        .iter()
+        .take(usize::from(count))
+        .map(|key| (key.to_string(), ActionValue::default()))
+        .collect()
+}
+
+/// How long `start_button_capture` waits for a press before disarming.
+const BUTTON_CAPTURE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// An armed "press a button to identify it" request.
+#[derive(Debug, Clone)]
+struct ButtonCapture {
+    device_id: String,
+    expires_at: Instant,
+    /// Button whose press was captured; its release is swallowed as well.
+    pressed: Option<i64>,
+}
+
+/// What the remap engine should do with a button event while capturing.
+#[derive(Debug, PartialEq, Eq)]
+enum CaptureOutcome {
+    /// Not capturing this event; remap it as usual.
+    Pass,
+    /// The press that was captured for `device_id`; report and suppress it.
+    Captured { device_id: String },
+    /// The release of a captured press; suppress it.
+    Suppress,
+}
+
+/// Payload of the `button-captured` event.
+#[derive(Debug, Serialize, Clone)]
+struct ButtonCaptured {
+    device_id: String,
+    /// Settings key of the button, if it is one that can be remapped.
+    key: Option<&'static str>,
+    button: i64,
+}
+
+/// Minimum spacing of `button-event` emissions while monitoring.
+const BUTTON_EVENT_INTERVAL: Duration = Duration::from_millis(30);
+
+/// Diagnostics switch for the `button-event` stream; off unless the
+/// frontend asks for it through `set_event_monitoring`.
+#[derive(Debug, Default)]
+struct EventMonitor {
+    enabled: bool,
+    last_emit: Option<Instant>,
+}
+
+/// The input overlay's share of button events, for as long as
+/// `show_input_overlay` keeps it open.
+#[derive(Debug, Default)]
+struct InputOverlayStream {
+    /// Wakes `watch_input_overlay` at each emission; `None` while the
+    /// overlay is closed.
+    wake: Option<std::sync::mpsc::Sender<()>>,
+    last_emit: Option<Instant>,
+}
+
+/// Lifecycle of the event tap, reported as `remap-engine-state`.
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case")]
+enum EngineState {
+    #[default]
+    Starting,
+    Running,
+    /// No mapping, capture, or monitor needs the tap, so none exists.
+    Idle,
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+    PermissionDenied,
+    Failed,
+    /// The system can't be remapped at all, such as without uinput.
+    #[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+    Unsupported,
+}
+
+/// Shortest and longest wait between attempts to create the event tap.
+const ENGINE_RETRY_MIN: Duration = Duration::from_secs(1);
+const ENGINE_RETRY_MAX: Duration = Duration::from_secs(30);
+
+/// Shared between the remap thread and `restart_remap_engine`.
+#[derive(Debug, Default)]
+struct RemapEngine {
+    state: Mutex<EngineState>,
+    /// Set by `restart_remap_engine` to cut a retry wait short.
+    restart: Mutex<bool>,
+    /// Signalled on a restart request and whenever the tap may have become
+    /// wanted.
+    wake: Condvar,
+    /// The selected device is connected and remaps at least one button.
+    mappings_need_tap: AtomicBool,
+    /// When the engine last reached `Running`, while it still is.
+    running_since: Mutex<Option<Instant>>,
+    /// False from a tap-disabled event until the tap is re-enabled.
+    tap_enabled: AtomicBool,
+    /// Times macOS disabled the tap for taking too long, since launch.
+    tap_timeouts: AtomicU64,
+}
+
+/// Reply of `get_remap_engine_status`.
+#[derive(Debug, Serialize, Clone, PartialEq)]
+struct RemapEngineStatus {
+    state: EngineState,
+    tap_enabled: bool,
+    tap_timeouts: u64,
+    /// Seconds the tap has been running, when it is.
+    uptime_secs: Option<u64>,
+    last_error: Option<RemapError>,
+    /// The selected device, while it isn't connected.
+    missing_device: Option<MissingDevice>,
+    /// Remapping is suspended until the next input; see
+    /// `Settings::idle_suspend_minutes`.
+    idle_suspended: bool,
+    /// Button events are only reported; see `set_monitor_mode`.
+    monitor_only: bool,
+}
+
+/// Payload of `selected-device-missing`.
+#[derive(Debug, Serialize, Clone, PartialEq)]
+struct MissingDevice {
+    id: String,
+    /// Name from the device's config, or its id without one.
+    name: String,
+    /// Name of the connected device remapped in its place, if any; see
+    /// `fallback_device`.
+    fallback: Option<String>,
+}
+
+/// Payload of the `fallback-device-changed` event.
+#[derive(Debug, Serialize, Clone)]
+struct FallbackDeviceChanged {
+    selected: Option<String>,
+    /// Device remapped in place of `selected`; `None` once none is.
+    fallback: Option<String>,
+}
+
+/// Time spent in the event tap callback, for `get_remap_diagnostics` and
+/// `get_latency_stats`.
+#[derive(Debug, Default)]
+struct CallbackTiming {
+    events: AtomicU64,
+    total_nanos: AtomicU64,
+    max_nanos: AtomicU64,
+    /// Events passed on unchanged.
+    pass_through: LatencyHistogram,
+    /// Events a mapping swallowed.
+    remapped: LatencyHistogram,
+}
+
+impl CallbackTiming {
+    fn record(&self, elapsed: Duration, remapped: bool) {
+        let nanos = u64::try_from(elapsed.as_nanos()).unwrap_or(u64::MAX);
+        self.events.fetch_add(1, Ordering::Relaxed);
+        self.total_nanos.fetch_add(nanos, Ordering::Relaxed);
+        self.max_nanos.fetch_max(nanos, Ordering::Relaxed);
+        if remapped {
+            self.remapped.record(nanos);
+        } else {
+            self.pass_through.record(nanos);
+        }
+    }
+}
+
+/// Records the time until it is dropped, so every return of the callback
+/// is counted.
+struct CallbackTimer<'a> {
+    timing: &'a CallbackTiming,
+    started: Instant,
+    remapped: bool,
+}
+
+impl CallbackTimer<'_> {
+    /// Counts the event as remapped when it was swallowed, and hands the
+    /// flag back.
+    fn remapped(&mut self, swallowed: bool) -> bool {
+        self.remapped = swallowed;
+        swallowed
+    }
+}
+
+impl Drop for CallbackTimer<'_> {
+    fn drop(&mut self) {
+        self.timing.record(self.started.elapsed(), self.remapped);
+    }
+}
+
+/// Thousandths of an event, the unit `OutputGovernor` keeps its budget in.
+const GOVERNOR_UNIT: u64 = 1000;
+
+/// Token bucket every synthetic event is taken from, so a bad mapping or a
+/// bug can't flood the system. It refills at
+/// `Settings::synthetic_events_per_sec` and holds a second's worth. Only
+/// atomics, since every post goes through it.
+#[derive(Debug)]
+struct OutputGovernor {
+    /// Events a second it refills at.
+    limit: AtomicU64,
+    /// What's left to post, in `GOVERNOR_UNIT`s.
+    budget: AtomicU64,
+    /// Nanoseconds after `epoch` the budget is refilled up to.
+    refilled: AtomicU64,
+    epoch: Instant,
+    posted: AtomicU64,
+    dropped: AtomicU64,
+    /// Set from a drop until an event gets through again.
+    flooding: AtomicBool,
+    /// Set by the first drop of a flood, until `take_warning`.
+    warning_due: AtomicBool,
+}
+
+impl Default for OutputGovernor {
+    fn default() -> Self {
+        let limit = u64::from(DEFAULT_SYNTHETIC_EVENTS_PER_SEC);
+        Self {
+            limit: AtomicU64::new(limit),
+            budget: AtomicU64::new(limit * GOVERNOR_UNIT),
+            refilled: AtomicU64::new(0),
+            epoch: Instant::now(),
+            posted: AtomicU64::new(0),
+            dropped: AtomicU64::new(0),
+            flooding: AtomicBool::new(false),
+            warning_due: AtomicBool::new(false),
+        }
+    }
+}
+
+impl OutputGovernor {
+    fn set_limit(&self, per_sec: u32) {
+        self.limit
+            .store(u64::from(per_sec.max(1)), Ordering::Relaxed);
+    }
+
+    fn limit(&self) -> u64 {
+        self.limit.load(Ordering::Relaxed)
+    }
+
+    /// Takes an event from the budget as it stands at `now`, or counts it
+    /// dropped when none is left.
+    fn admit(&self, now: Instant) -> bool {
+        let limit = self.limit();
+        let nanos =
+            u64::try_from(now.saturating_duration_since(self.epoch).as_nanos()).unwrap_or(u64::MAX);
+        // Only the time the refill accounts for is used up, so frequent
+        // calls don't round their refills away.
+        let mut earned = 0;
+        let _ = self
+            .refilled
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |last| {
+                earned = nanos.saturating_sub(last).saturating_mul(limit) / 1_000_000;
+                (earned > 0).then(|| last + earned * 1_000_000 / limit)
+            });
+        let mut admitted = false;
+        let _ = self
+            .budget
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |budget| {
+                let budget = budget.saturating_add(earned).min(limit * GOVERNOR_UNIT);
+                admitted = budget >= GOVERNOR_UNIT;
+                Some(if admitted {
+                    budget - GOVERNOR_UNIT
+                } else {
+                    budget
+                })
+            });
+        if admitted {
+            self.posted.fetch_add(1, Ordering::Relaxed);
+            self.flooding.store(false, Ordering::Relaxed);
+        } else {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+            if !self.flooding.swap(true, Ordering::Relaxed) {
+                self.warning_due.store(true, Ordering::Relaxed);
+            }
+        }
+        admitted
+    }
+
+    /// Whether a flood started since the last call, so it's warned about
+    /// once.
+    fn take_warning(&self) -> bool {
+        self.warning_due.swap(false, Ordering::Relaxed)
+    }
+}
+
+/// Sub-buckets per power of two, so a bucket is at most about 6% wide.
+const LATENCY_SUB_BITS: u32 = 4;
+const LATENCY_SUB_BUCKETS: usize = 1 << LATENCY_SUB_BITS;
+/// Longer callbacks, past about 68 seconds, share the last bucket.
+const LATENCY_MAX_EXPONENT: u32 = 35;
+const LATENCY_BUCKETS: usize =
+    (LATENCY_MAX_EXPONENT - LATENCY_SUB_BITS + 2) as usize * LATENCY_SUB_BUCKETS;
+
+/// Callback durations in log-linear buckets, recorded with relaxed atomic
+/// adds so the callback never waits on a reader.
+#[derive(Debug)]
+struct LatencyHistogram {
+    buckets: [AtomicU64; LATENCY_BUCKETS],
+    max_nanos: AtomicU64,
+}
+
+impl Default for LatencyHistogram {
+    fn default() -> Self {
+        Self {
+            buckets: std::array::from_fn(|_| AtomicU64::new(0)),
+            max_nanos: AtomicU64::new(0),
+        }
+    }
+}
+
+/// The bucket counting `nanos`: exact below 16, then 16 per power of two.
+fn latency_bucket(nanos: u64) -> usize {
+    if nanos < LATENCY_SUB_BUCKETS as u64 {
+        return nanos as usize;
+    }
+    let nanos = nanos.min((1 << (LATENCY_MAX_EXPONENT + 1)) - 1);
+    let exponent = 63 - nanos.leading_zeros();
+    let sub = (nanos >> (exponent - LATENCY_SUB_BITS)) as usize - LATENCY_SUB_BUCKETS;
+    (exponent - LATENCY_SUB_BITS + 1) as usize * LATENCY_SUB_BUCKETS + sub
+}
+
+/// The smallest duration `latency_bucket` puts in `bucket`.
+fn latency_bucket_floor(bucket: usize) -> u64 {
+    if bucket < LATENCY_SUB_BUCKETS {
+        return bucket as u64;
+    }
+    let exponent = (bucket / LATENCY_SUB_BUCKETS) as u32 + LATENCY_SUB_BITS - 1;
+    let sub = (bucket % LATENCY_SUB_BUCKETS + LATENCY_SUB_BUCKETS) as u64;
+    sub << (exponent - LATENCY_SUB_BITS)
+}
+
+impl LatencyHistogram {
+    fn record(&self, nanos: u64) {
+        self.buckets[latency_bucket(nanos)].fetch_add(1, Ordering::Relaxed);
+        self.max_nanos.fetch_max(nanos, Ordering::Relaxed);
+    }
+
+    /// Counts since the last reset, with each percentile reported as the
+    /// top of its bucket but never above the maximum.
+    fn stats(&self) -> LatencyStats {
+        let counts: Vec<u64> = self
+            .buckets
+            .iter()
+            .map(|bucket| bucket.load(Ordering::Relaxed))
+            .collect();
+        let count: u64 = counts.iter().sum();
+        let max = self.max_nanos.load(Ordering::Relaxed);
+        let percentile = |fraction: f64| {
+            if count == 0 {
+                return 0.0;
+            }
+            let rank = ((count as f64 * fraction).ceil() as u64).max(1);
+            let mut seen = 0;
+            let bucket = counts
+                .iter()
+                .position(|&bucket| {
+                    seen += bucket;
+                    seen >= rank
+                })
+                .unwrap_or(LATENCY_BUCKETS - 1);
+            let top = latency_bucket_floor(bucket + 1).saturating_sub(1);
+            top.min(max) as f64 / 1000.0
+        };
+        LatencyStats {
+            count,
+            p50_micros: percentile(0.5),
+            p99_micros: percentile(0.99),
+            max_micros: max as f64 / 1000.0,
+        }
+    }
+
+    fn reset(&self) {
+        for bucket in &self.buckets {
+            bucket.store(0, Ordering::Relaxed);
+        }
+        self.max_nanos.store(0, Ordering::Relaxed);
+    }
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+struct LatencyStats {
+    count: u64,
+    p50_micros: f64,
+    p99_micros: f64,
+    max_micros: f64,
+}
+
+/// Payload of `get_latency_stats`.
+#[derive(Debug, Serialize, Clone, PartialEq)]
+struct LatencyReport {
+    pass_through: LatencyStats,
+    remapped: LatencyStats,
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+struct RemapDiagnostics {
+    engine_state: EngineState,
+    callback_events: u64,
+    callback_mean_micros: f64,
+    callback_max_micros: f64,
+    /// Synthetic events `OutputGovernor` let through and dropped.
+    synthetic_posted: u64,
+    synthetic_dropped: u64,
+}
+
+/// How often a running tap checks whether it's still wanted.
+const TAP_LIFECYCLE_CHECK: Duration = Duration::from_secs(1);
+
+/// How many `remap-error`s `get_remap_errors` keeps.
+const REMAP_ERROR_HISTORY: usize = 20;
+
+/// A failure in the remap engine, emitted as `remap-error`.
+#[derive(Debug, Serialize, Clone, PartialEq)]
+struct RemapError {
+    /// Stable identifier such as `"tap-create-failed"`.
+    code: &'static str,
+    message: String,
+    /// Seconds since the Unix epoch of the latest occurrence.
+    timestamp: u64,
+    /// Consecutive occurrences folded into this entry.
+    count: u32,
+}
+
+/// Payload of the `button-event` event.
+#[derive(Debug, Serialize, Clone)]
+struct ButtonEvent {
+    /// Device whose mapping was consulted, when one is selected and connected.
+    device_id: Option<String>,
+    key: Option<&'static str>,
+    button: i64,
+    /// `"down"` or `"up"`.
+    event_type: &'static str,
+    action: &'static str,
+    /// Set in monitor mode, where `action` is what would have fired but
+    /// didn't.
+    monitor_only: bool,
+}
+
+/// Payload of the `synthetic-output-throttled` event.
+#[derive(Debug, Serialize, Clone)]
+struct OutputThrottled {
+    /// `Settings::synthetic_events_per_sec` in effect.
+    limit: u64,
+    /// Events dropped since launch.
+    dropped: u64,
+}
+
+/// Payload of the `primary-button-restored` event.
+#[derive(Debug, Serialize, Clone)]
+struct PrimaryButtonRestored {
+    device_id: Option<String>,
+    /// What the left button was mapped to, and does again once it's
+    /// chosen again or the app restarts.
+    action: &'static str,
+}
+
+/// Frontmost application as reported by the workspace, cached for the
+/// remap engine.
+#[derive(Debug, Default)]
+struct ForegroundApps {
+    /// Bundle id of the frontmost application.
+    current: Option<String>,
+    /// Most recent frontmost application other than Edit Mouse itself, so
+    /// the settings window can offer "the app you were just using".
+    last_external: Option<frontmost::AppInfo>,
+    /// `Settings::excluded_apps`, kept here so the per-event check is a
+    /// single lookup under one lock.
+    excluded: HashSet<String>,
+    /// Whether the frontmost app has a window covering a whole display.
+    fullscreen: bool,
+    /// `Settings::game_mode`.
+    game_mode: bool,
+}
+
+/// Reads `lock` even after a thread panicked holding it. Failing instead
+/// would leave every later reader with defaults, and remapping would stop
+/// without an error. What was stored may be half-updated, so recovering is
+/// logged, once.
+fn read_recovering<'a, T>(lock: &'a RwLock<T>, name: &str) -> std::sync::RwLockReadGuard<'a, T> {
+    lock.read().unwrap_or_else(|poisoned| {
+        log::error!(
+            "state: {} was held by a thread that panicked; carrying on with it",
+            name
+        );
+        lock.clear_poison();
+        poisoned.into_inner()
+    })
+}
+
+/// `read_recovering` for writers.
+fn write_recovering<'a, T>(lock: &'a RwLock<T>, name: &str) -> std::sync::RwLockWriteGuard<'a, T> {
+    lock.write().unwrap_or_else(|poisoned| {
+        log::error!(
+            "state: {} was held by a thread that panicked; carrying on with it",
+            name
+        );
+        lock.clear_poison();
+        poisoned.into_inner()
+    })
+}
+
+/// `read_recovering` for a `Mutex`.
+fn lock_recovering<'a, T>(lock: &'a Mutex<T>, name: &str) -> std::sync::MutexGuard<'a, T> {
+    lock.lock().unwrap_or_else(|poisoned| {
+        log::error!(
+            "state: {} was held by a thread that panicked; carrying on with it",
+            name
+        );
+        lock.clear_poison();
+        poisoned.into_inner()
+    })
+}
+
+#[derive(Clone, Default)]
+struct AppState {
+    /// Written only by `update_settings`; every other access reads a copy.
+    settings: Arc<RwLock<Settings>>,
+    /// The connected devices as last listed. The engine and the window
+    /// both go by it, so they agree on what's connected.
+    devices: Arc<RwLock<DeviceCache>>,
+    /// Ids and names of the connected devices, in the order they were
+    /// listed, for the tray.
+    device_names: Arc<Mutex<Vec<(String, String)>>>,
+    /// Connected device standing in for the selected one; set by
+    /// `settle_fallback_device`.
+    fallback: Arc<Mutex<Option<String>>>,
+    battery_cache: Arc<Mutex<HashMap<String, (Instant, BatteryReport)>>>,
+    /// Latest `measure_report_rate` result per device.
+    report_rates: Arc<Mutex<HashMap<String, ReportRate>>>,
+    /// The running `start_hid_debug` session, cleared by its thread as it
+    /// ends.
+    hid_debug: Arc<Mutex<Option<HidDebugSession>>>,
+    capture: Arc<Mutex<Option<ButtonCapture>>>,
+    monitor: Arc<Mutex<EventMonitor>>,
+    input_overlay: Arc<Mutex<InputOverlayStream>>,
+    gesture: Arc<Mutex<Option<ActiveGesture>>>,
+    foreground: Arc<Mutex<ForegroundApps>>,
+    /// Mapping each held button was pressed with, so its release is handled
+    /// the same way even if the frontmost app changed in between.
+    held: Arc<Mutex<[Option<HeldMapping>; BUTTON_SLOTS]>>,
+    /// Each button's last press let through, for `DeviceConfig::debounce_ms`.
+    bounces: Arc<Mutex<[ButtonBounce; BUTTON_SLOTS]>>,
+    primary_escape: Arc<Mutex<PrimaryEscape>>,
+    /// Rebuilt by `refresh_mappings`; read on every button event. Only
+    /// held to swap in a finished table, so readers never wait on a
+    /// rebuild.
+    resolved: Arc<RwLock<Arc<ResolvedMappings>>>,
+    /// Held by `refresh_mappings` throughout, so a slower rebuild can't
+    /// store a stale table over a newer one.
+    mappings_refresh: Arc<Mutex<()>>,
+    engine: Arc<RemapEngine>,
+    last_profile_cycle: Arc<Mutex<Option<Instant>>>,
+    auto_profile: Arc<Mutex<AutoProfile>>,
+    profile_schedule: Arc<Mutex<schedule::ProfileScheduler>>,
+    /// Has the thread `schedule::watch` runs evaluate now.
+    schedule_nudge: Arc<std::sync::OnceLock<std::sync::mpsc::Sender<()>>>,
+    /// Most recent `remap-error`s, oldest first.
+    remap_errors: Arc<Mutex<VecDeque<RemapError>>>,
+    /// Inverse of `Settings::remap_enabled`, read on every event.
+    remap_disabled: Arc<AtomicBool>,
+    /// Set while the screen is locked or another user's session is in
+    /// front, when everything passes through.
+    session_inactive: Arc<AtomicBool>,
+    /// Set while in safe mode, when everything passes through and settings
+    /// aren't saved; see `enter_safe_mode`.
+    safe_mode: Arc<AtomicBool>,
+    /// Unix seconds of the last input the engine saw from the user.
+    last_input: Arc<AtomicU64>,
+    /// Set once `Settings::idle_suspend_minutes` pass without input, when
+    /// everything passes through; the next input clears it.
+    idle_suspended: Arc<AtomicBool>,
+    /// Set by `set_monitor_mode`: button events are resolved and reported,
+    /// but nothing is swallowed or posted.
+    monitor_only: Arc<AtomicBool>,
+    /// Tells the thread `watch_idle` runs that input ended a suspension.
+    idle_wake: Arc<std::sync::OnceLock<std::sync::mpsc::Sender<()>>>,
+    /// Inverse of `Settings::collect_stats`, read on every counted use.
+    stats_disabled: Arc<AtomicBool>,
+    /// Inverse of `Settings::record_history`.
+    history_disabled: Arc<AtomicBool>,
+    /// Inverse of `Settings::pause_in_own_window`.
+    own_window_remapped: Arc<AtomicBool>,
+    /// Whether the main window has focus, from its window events.
+    own_window_focused: Arc<AtomicBool>,
+    /// Mirrors `Settings::mute_feedback`.
+    feedback_muted: Arc<AtomicBool>,
+    /// Mirrors `Settings::double_click_interval_ms`, capped.
+    double_click_interval_ms: Arc<AtomicU64>,
+    /// Who holds secure input, from when it first kept a keyboard action
+    /// from being posted until it ends.
+    secure_input: Arc<Mutex<Option<SecureInputHolder>>>,
+    /// Set while `watch_secure_input` waits for secure input to end.
+    secure_input_watched: Arc<AtomicBool>,
+    /// When secure input was last logged.
+    secure_input_logged: Arc<Mutex<Option<Instant>>>,
+    /// Posts system notifications, once the app is set up.
+    notifier: Arc<std::sync::OnceLock<tauri::AppHandle>>,
+    /// Hands feedback to the thread `feedback::start` runs.
+    feedback: Arc<std::sync::OnceLock<std::sync::mpsc::Sender<Feedback>>>,
+    /// Last `ACTION_HISTORY` fired actions, oldest first.
+    history: Arc<Mutex<VecDeque<FiredAction>>>,
+    /// Hands uses to the thread `start_usage_stats` runs.
+    usage: Arc<std::sync::OnceLock<std::sync::mpsc::Sender<UsageMessage>>>,
+    /// Hands settings to the thread `start_settings_writer` runs.
+    settings_writer: Arc<std::sync::OnceLock<std::sync::mpsc::Sender<SettingsWrite>>>,
+    /// Set by `start_mouse_remap` when its engine can post outside the
+    /// event path, for `test_action`.
+    action_tester: Arc<std::sync::OnceLock<ActionTester>>,
+    pause: Arc<Mutex<RemapPause>>,
+    /// Physical button currently held as a middle button by a Middle Click
+    /// mapping.
+    middle_drag: Arc<Mutex<Option<i64>>>,
+    key_repeat: Arc<Mutex<KeyRepeat>>,
+    callback_timing: Arc<CallbackTiming>,
+    output_governor: Arc<OutputGovernor>,
+    /// Set when the settings file is newer than `SETTINGS_VERSION`, so it
+    /// isn't overwritten with fewer fields than it has.
+    settings_read_only: Arc<AtomicBool>,
+    /// Set when a corrupt settings file was moved aside, until it's restored.
+    settings_recovery: Arc<Mutex<Option<SettingsRecovered>>>,
+    /// Set when this launch brought over an earlier build's settings.
+    settings_migration: Arc<Mutex<Option<SettingsMigrated>>>,
+    /// What `parse_settings_leniently` left out of the settings last loaded.
+    settings_warnings: Arc<Mutex<Vec<SettingsWarning>>>,
+    /// Digest of the settings file as the app last wrote or read it, so
+    /// `watch_settings_file` can tell an outside edit from the app's own save.
+    settings_file: Arc<Mutex<Option<u64>>>,
+    /// The same for the sync folder's copy of the settings.
+    synced_file: Arc<Mutex<Option<u64>>>,
+    /// Set while the sync folder can't be reached, so the local copy is used.
+    sync_unavailable: Arc<Mutex<Option<SettingsSyncUnavailable>>>,
+    /// When Reset To Defaults was first picked in the tray; see
+    /// `confirm_tray_reset`.
+    tray_reset_armed: Arc<Mutex<Option<Instant>>>,
+    /// Held by `edit_settings` from reading settings to storing the change.
+    settings_edit: Arc<Mutex<()>>,
+    sightings: Arc<Mutex<DeviceSightings>>,
+    /// Changes `undo_settings_change` can take back, oldest first.
+    settings_history: Arc<Mutex<VecDeque<SettingsChange>>>,
+    /// Set once the window has been shown or put back where it last was;
+    /// until then a tray click places it next to the icon.
+    window_revealed: Arc<AtomicBool>,
+    /// When the quick panel last closed for losing focus; see
+    /// `QUICK_PANEL_REOPEN_GUARD`.
+    quick_panel_blurred: Arc<Mutex<Option<Instant>>>,
+    /// Set by the first `update_devices`, so devices present at launch
+    /// aren't announced as connected.
+    devices_listed: Arc<AtomicBool>,
+    /// When each device was last notified about; see
+    /// `DEVICE_NOTICE_INTERVAL`.
+    device_notices: Arc<Mutex<HashMap<String, Instant>>>,
+    connect_rules: Arc<Mutex<ConnectRuleQueue>>,
+    hud: Arc<Mutex<HudState>>,
+    /// Last time the window moved or resized, while its geometry waits to
+    /// be saved.
+    window_moved: Arc<Mutex<Option<Instant>>>,
+    /// The crash report the previous run left, for `get_previous_crash`.
+    previous_crash: Arc<Mutex<Option<String>>>,
+    /// The system theme last reported by `system-theme-changed`.
+    system_theme: Arc<Mutex<Option<SystemTheme>>>,
+    hid: SharedHidApi,
+    integration: Arc<Mutex<IntegrationRunner>>,
+}
+
+/// Longest device sightings wait in `DeviceSightings` before they're written
+/// to settings, so enumerating devices doesn't rewrite the file every time.
+const SIGHTINGS_FLUSH_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// When devices were seen connected since sightings were last written.
+#[derive(Debug, Default)]
+struct DeviceSightings {
+    /// Device id to unix time, in seconds.
+    pending: HashMap<String, u64>,
+    /// Unix time of the last write; `None` until the first one, which comes
+    /// with the first enumeration after launch.
+    flushed: Option<u64>,
+}
+
+/// A temporary pause of remapping. Not persisted; expiry is wall-clock time
+/// so a pause that ends while the Mac sleeps is over once it wakes.
+#[derive(Debug, Default)]
+struct RemapPause {
+    paused: bool,
+    /// `None` pauses until `resume_remapping`.
+    until: Option<SystemTime>,
+    /// Bumped on every change so a stale expiry timer does nothing.
+    generation: u64,
+}
+
+/// Payload of the `remap-pause-changed` event.
+#[derive(Debug, Serialize, Clone, PartialEq)]
+struct RemapPauseStatus {
+    paused: bool,
+    /// Seconds since the Unix epoch the pause ends at, if it does.
+    until: Option<u64>,
+}
+
+/// Event source user data set on every event Edit Mouse posts.
+const SYNTHETIC_EVENT_TAG: i64 = 0x4544_4954;
+
+/// Longest the expiry timer sleeps between checks of the wall clock.
+const PAUSE_POLL: Duration = Duration::from_secs(15);
+
+/// Bookkeeping for `profile_app_rules`.
+#[derive(Debug, Default)]
+struct AutoProfile {
+    /// Profile that was active before a rule switched away from it, restored
+    /// once an application without a rule activates.
+    previous: Option<String>,
+}
+
+impl AutoProfile {
+    /// The profile to switch to now that `bundle_id` is frontmost, if any.
+    fn on_activate(&mut self, settings: &Settings, bundle_id: &str) -> Option<String> {
+        let rule = settings.profile_app_rules.iter().find(|rule| {
+            rule.bundle_id == bundle_id && settings.profiles.contains_key(&rule.profile)
+        });
+        let target = match rule {
+            Some(rule) => {
+                if self.previous.is_none() {
+                    self.previous = Some(settings.active_profile.clone());
+                }
+                Some(rule.profile.clone())
+            }
+            None => self
+                .previous
+                .take()
+                .filter(|name| settings.profiles.contains_key(name)),
+        };
+        target.filter(|name| *name != settings.active_profile)
+    }
+
+    /// A switch the user made by hand wins over the rules: nothing is
+    /// restored until the next activation decides again.
+    fn on_manual_switch(&mut self) {
+        self.previous = None;
+    }
+}
+
+/// The mapping a button was pressed with; `None` when it was unmapped.
+type HeldMapping = Option<ResolvedAction>;
+
+/// How long holding the left button while it's remapped takes to give it
+/// its click back.
+const PRIMARY_ESCAPE_HOLD: Duration = Duration::from_secs(5);
+
+/// The way back to a left click; see `track_primary_hold`.
+#[derive(Debug, Default)]
+struct PrimaryEscape {
+    /// When the left button went down, remapped to what.
+    held_since: Option<(Instant, ResolvedAction)>,
+    /// The left button's mapping, set aside until the settings change it.
+    escaped: Option<ResolvedAction>,
+}
+
+/// A button's debounce state.
+#[derive(Debug, Default, Clone, Copy)]
+struct ButtonBounce {
+    last_press: Option<Instant>,
+    /// A press was swallowed as bounce and its release is still to come.
+    bouncing: bool,
+}
+
+/// The button a held key action repeats for, and how many holds started
+/// so far.
+#[derive(Debug, Default)]
+struct KeyRepeat {
+    button: Option<i64>,
+    holds: u64,
+}
+
+/// Minimum time between two profile cycles from a mouse button.
+const PROFILE_CYCLE_DEBOUNCE: Duration = Duration::from_millis(400);
+
+impl AppState {
+    fn set_settings_read_only(&self, read_only: bool) {
+        self.settings_read_only.store(read_only, Ordering::Relaxed);
+    }
+
+    fn is_settings_read_only(&self) -> bool {
+        self.settings_read_only.load(Ordering::Relaxed)
+    }
+
+    /// Records `data` as the settings file's content, returning whether it
+    /// differs from what was recorded before.
+    fn note_settings_file(&self, data: &str) -> bool {
+        let digest = Some(settings_digest(data));
+        match self.settings_file.lock() {
+            Ok(mut guard) => std::mem::replace(&mut *guard, digest) != digest,
+            Err(_) => true,
+        }
+    }
+
+    /// Records `data` as the synced copy's content.
+    fn note_synced_file(&self, data: &str) {
+        *lock_recovering(&self.synced_file, "the synced settings digest") =
+            Some(settings_digest(data));
+    }
+
+    /// Whether the synced copy holds something other than `data`, which
+    /// it always does before it was first read or written.
+    fn is_synced_file_changed(&self, data: &str) -> bool {
+        *lock_recovering(&self.synced_file, "the synced settings digest")
+            != Some(settings_digest(data))
+    }
+
+    /// Returns whether that's a change.
+    fn set_sync_unavailable(&self, unavailable: Option<SettingsSyncUnavailable>) -> bool {
+        let mut guard = lock_recovering(&self.sync_unavailable, "the sync folder status");
+        if *guard == unavailable {
+            return false;
+        }
+        *guard = unavailable;
+        true
+    }
+
+    fn sync_unavailable(&self) -> Option<SettingsSyncUnavailable> {
+        lock_recovering(&self.sync_unavailable, "the sync folder status").clone()
+    }
+
+    fn set_settings_recovery(&self, recovery: Option<SettingsRecovered>) {
+        if let Ok(mut guard) = self.settings_recovery.lock() {
+            *guard = recovery;
+        }
+    }
+
+    /// Keeps the warnings of the settings just loaded, returning whether
+    /// they differ from the last load's.
+    fn set_settings_warnings(&self, warnings: Vec<SettingsWarning>) -> bool {
+        let mut guard = lock_recovering(&self.settings_warnings, "settings warnings");
+        if *guard == warnings {
+            return false;
+        }
+        *guard = warnings;
+        true
+    }
+
+    fn settings_warnings(&self) -> Vec<SettingsWarning> {
+        lock_recovering(&self.settings_warnings, "settings warnings").clone()
+    }
+
+    fn settings_recovery(&self) -> Option<SettingsRecovered> {
+        self.settings_recovery
+            .lock()
+            .ok()
+            .and_then(|guard| guard.clone())
+    }
+
+    fn set_settings_migration(&self, migration: Option<SettingsMigrated>) {
+        *lock_recovering(&self.settings_migration, "the settings migration") = migration;
+    }
+
+    fn settings_migration(&self) -> Option<SettingsMigrated> {
+        lock_recovering(&self.settings_migration, "the settings migration").clone()
+    }
+
+    fn update_settings(&self, settings: Settings) {
+        if let Ok(mut guard) = self.foreground.lock() {
+            guard.excluded = settings.excluded_apps.iter().cloned().collect();
+            guard.game_mode = settings.game_mode;
+        }
+        self.remap_disabled
+            .store(!settings.remap_enabled, Ordering::Relaxed);
+        self.stats_disabled
+            .store(!settings.collect_stats, Ordering::Relaxed);
+        self.history_disabled
+            .store(!settings.record_history, Ordering::Relaxed);
+        self.own_window_remapped
+            .store(!settings.pause_in_own_window, Ordering::Relaxed);
+        self.feedback_muted
+            .store(settings.mute_feedback, Ordering::Relaxed);
+        self.double_click_interval_ms.store(
+            u64::from(
+                settings
+                    .double_click_interval_ms
+                    .min(MAX_DOUBLE_CLICK_INTERVAL_MS),
+            ),
+            Ordering::Relaxed,
+        );
+        self.output_governor
+            .set_limit(settings.synthetic_events_per_sec);
+        if !settings.record_history {
+            if let Ok(mut guard) = self.history.lock() {
+                guard.clear();
+            }
+        }
+        *write_recovering(&self.settings, "settings") = settings;
+        self.refresh_mappings();
+    }
+
+    /// Applies `change` to a copy of the current settings and hands the
+    /// result to `commit`, which saves and publishes it. Edits go through one
+    /// at a time, so two made at once from different places both land
+    /// instead of the later one overwriting the earlier.
+    fn edit_settings(
+        &self,
+        change: impl FnOnce(&mut Settings) -> Result<(), String>,
+        commit: impl FnOnce(Settings) -> Result<(), String>,
+    ) -> Result<Settings, String> {
+        let _edit = lock_recovering(&self.settings_edit, "the settings edit lock");
+        let previous = self.snapshot_settings();
+        let mut settings = previous.clone();
+        change(&mut settings)?;
+        settings.settle_pristine_devices(&previous);
+        commit(settings.clone())?;
+        let summary = describe_settings_changes(&previous, &settings);
+        if !summary.is_empty() {
+            if let Ok(mut history) = self.settings_history.lock() {
+                if history.len() == SETTINGS_HISTORY {
+                    history.pop_front();
+                }
+                history.push_back(SettingsChange {
+                    previous,
+                    changed: SystemTime::now()
+                        .duration_since(UNIX_EPOCH)
+                        .map(|elapsed| elapsed.as_secs())
+                        .unwrap_or_default(),
+                    summary,
+                });
+            }
+        }
+        Ok(settings)
+    }
+
+    /// Hands the settings from before the latest change to `commit`. The
+    /// change is forgotten once `commit` succeeds, so undoing again goes
+    /// further back.
+    fn undo_settings_change(
+        &self,
+        commit: impl FnOnce(Settings) -> Result<(), String>,
+    ) -> Result<SettingsChange, String> {
+        let _edit = lock_recovering(&self.settings_edit, "the settings edit lock");
+        let mut history = self
+            .settings_history
+            .lock()
+            .map_err(|_| "settings history is unavailable".to_string())?;
+        let change = history
+            .pop_back()
+            .ok_or_else(|| "nothing to undo".to_string())?;
+        if let Err(err) = commit(change.previous.clone()) {
+            history.push_back(change);
+            return Err(err);
+        }
+        Ok(change)
+    }
+
+    /// Undoable changes, newest first.
+    fn settings_history(&self) -> Vec<SettingsHistoryEntry> {
+        self.settings_history
+            .lock()
+            .map(|history| {
+                history
+                    .iter()
+                    .rev()
+                    .map(|change| SettingsHistoryEntry {
+                        changed: change.changed,
+                        summary: change.summary.clone(),
+                    })
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Notes that `devices` are connected at unix time `now`, returning
+    /// whether it's time to write sightings with `take_sightings`.
+    fn note_devices_seen(&self, devices: &[MouseDevice], now: u64) -> bool {
+        let Ok(mut guard) = self.sightings.lock() else {
+            return false;
+        };
+        for device in devices {
+            guard.pending.insert(device.id.clone(), now);
+        }
+        guard
+            .flushed
+            .is_none_or(|flushed| now.saturating_sub(flushed) >= SIGHTINGS_FLUSH_INTERVAL.as_secs())
+    }
+
+    fn take_sightings(&self, now: u64) -> HashMap<String, u64> {
+        let Ok(mut guard) = self.sightings.lock() else {
+            return HashMap::new();
+        };
+        guard.flushed = Some(now);
+        std::mem::take(&mut guard.pending)
+    }
+
+    /// Sightings not yet written, for showing settings that are up to date.
+    fn pending_sightings(&self) -> HashMap<String, u64> {
+        self.sightings
+            .lock()
+            .map(|guard| guard.pending.clone())
+            .unwrap_or_default()
+    }
+
+    /// Records the connected devices; true when they changed.
+    fn update_devices(&self, devices: &[MouseDevice]) -> bool {
+        let counts = |devices: &[MouseDevice]| -> HashMap<String, u8> {
+            devices
+                .iter()
+                .map(|device| (device.id.clone(), device.button_count))
+                .collect()
+        };
+        let listed = DeviceCache {
+            devices: devices.to_vec(),
+            listed_at: Some(Instant::now()),
+        };
+        let previous = std::mem::replace(&mut *write_recovering(&self.devices, "devices"), listed);
+        let changed = counts(&previous.devices) != counts(devices);
+        let names: Vec<(String, String)> = devices
+            .iter()
+            .map(|device| (device.id.clone(), device.name.clone()))
+            .collect();
+        let before = match self.device_names.lock() {
+            Ok(mut guard) => std::mem::replace(&mut *guard, names.clone()),
+            Err(_) => Vec::new(),
+        };
+        if self.devices_listed.swap(true, Ordering::Relaxed) {
+            self.notify_device_changes(&before, &names);
+            let settings = self.snapshot_settings();
+            self.queue_connect_rules(&settings, &before, &names);
+            if settings.show_connect_hud {
+                if let Some(hud) = connect_hud(&settings, &before, &names) {
+                    self.queue_hud(Hud::Connect(hud));
+                }
+            }
+        }
+        self.refresh_mappings();
+        changed
+    }
+
+    /// Makes `settings`, replacing `previous`, current. A newly selected
+    /// device missing from the last listing is listed with `list` first, as
+    /// `relist_for_selection` does; returns whether that listing changed.
+    fn take_settings(
+        &self,
+        previous: &Settings,
+        settings: &Settings,
+        list: impl FnOnce() -> Option<Vec<MouseDevice>>,
+    ) -> bool {
+        let reselected = settings.selected_device != previous.selected_device;
+        self.update_settings(settings.clone());
+        reselected && self.relist_for_selection(settings, list)
+    }
+
+    /// Lists the devices again with `list` when `settings` select one the
+    /// last listing doesn't have, such as one plugged in since the last
+    /// poll, so its mappings are in effect before the command that selected
+    /// it returns. Returns whether the listing changed.
+    fn relist_for_selection(
+        &self,
+        settings: &Settings,
+        list: impl FnOnce() -> Option<Vec<MouseDevice>>,
+    ) -> bool {
+        let Some(id) = settings.selected_device.as_deref() else {
+            return false;
+        };
+        if self.is_selected_device_available(id) {
+            return false;
+        }
+        list().is_some_and(|devices| self.update_devices(&devices))
+    }
+
+    /// The devices as last listed, and whether that was within
+    /// `DEVICE_CACHE_TTL`.
+    fn listed_devices(&self) -> (Vec<MouseDevice>, bool) {
+        let cache = read_recovering(&self.devices, "devices");
+        let fresh = cache
+            .listed_at
+            .is_some_and(|listed_at| listed_at.elapsed() < DEVICE_CACHE_TTL);
+        (cache.devices.clone(), fresh)
+    }
+
+    /// Makes the next `get_mouse_devices` list again.
+    fn invalidate_devices(&self) {
+        write_recovering(&self.devices, "devices").listed_at = None;
+    }
+
+    /// Posts the notifications `device_change_notices` finds, at most one
+    /// per device every `DEVICE_NOTICE_INTERVAL`.
+    fn notify_device_changes(&self, before: &[(String, String)], after: &[(String, String)]) {
+        let settings = self.snapshot_settings();
+        if !settings.notify_device_changes {
+            return;
+        }
+        let Ok(mut notices) = self.device_notices.lock() else {
+            return;
+        };
+        let now = Instant::now();
+        let locale = Locale::from_setting(&settings.locale);
+        for (id, message) in device_change_notices(&settings, before, after, locale) {
+            if take_notice_slot(&mut notices, &id, now) {
+                self.post_notification(message);
+            }
+        }
+    }
+
+    /// Queues the `on_connect` rules of devices in `after` that weren't in
+    /// `before` for `run_connect_rules`, each device's at most once every
+    /// `DEVICE_NOTICE_INTERVAL`.
+    fn queue_connect_rules(
+        &self,
+        settings: &Settings,
+        before: &[(String, String)],
+        after: &[(String, String)],
+    ) {
+        let mut queue = lock_recovering(&self.connect_rules, "the connect rules");
+        let now = Instant::now();
+        for (id, rules) in connect_rules(settings, before, after) {
+            if take_notice_slot(&mut queue.last_run, &id, now) {
+                queue.pending.push((id, rules));
+            }
+        }
+    }
+
+    fn take_connect_rules(&self) -> Vec<(String, Vec<ConnectRule>)> {
+        std::mem::take(&mut lock_recovering(&self.connect_rules, "the connect rules").pending)
+    }
+
+    /// Queues `hud` for the next `show_hud`, replacing one not shown yet.
+    fn queue_hud(&self, hud: Hud) {
+        lock_recovering(&self.hud, "the HUD").pending = Some(hud);
+    }
+
+    /// Takes the queued HUD as the one showing, numbered for `dismiss_hud`.
+    fn take_hud(&self) -> Option<(u64, Hud)> {
+        let mut guard = lock_recovering(&self.hud, "the HUD");
+        let hud = guard.pending.take()?;
+        guard.shown += 1;
+        guard.showing = Some(hud.clone());
+        Some((guard.shown, hud))
+    }
+
+    /// Forgets the HUD numbered `shown`, returning false when a newer one
+    /// replaced it.
+    fn dismiss_hud(&self, shown: u64) -> bool {
+        let mut guard = lock_recovering(&self.hud, "the HUD");
+        if guard.shown != shown {
+            return false;
+        }
+        guard.showing = None;
+        true
+    }
+
+    fn showing_hud(&self) -> Option<Hud> {
+        lock_recovering(&self.hud, "the HUD").showing.clone()
+    }
+
+    /// Rebuilds the table `resolved_mappings` returns. Holding the write lock
+    /// throughout keeps concurrent refreshes from storing a stale table.
+    fn refresh_mappings(&self) {
+        let _refresh = lock_recovering(&self.mappings_refresh, "the mappings refresh lock");
+        let settings = self.snapshot_settings();
+        let fallback = self.fallback_device();
+        let selected = fallback.as_deref().or(settings.selected_device.as_deref());
+        let ignored_connected = self.is_ignored_device_connected(&settings);
+        let needs_tap = selected
+            .filter(|id| !settings.leaves_untouched(id, ignored_connected))
+            .filter(|id| self.is_selected_device_available(id))
+            .and_then(|id| match fallback {
+                Some(_) => settings.fallback_config(id),
+                None => settings.device_config(id),
+            })
+            .is_some_and(DeviceConfig::has_remapping);
+        self.engine
+            .mappings_need_tap
+            .store(needs_tap, Ordering::Relaxed);
+        if needs_tap {
+            self.wake_engine();
+        }
+        let app = self.frontmost_app();
+        let context = MappingContext {
+            device_available: selected.is_some_and(|id| self.is_selected_device_available(id)),
+            fallback: fallback.as_deref(),
+            suspended: self.is_remapping_suspended(),
+            ignored_connected,
+            button_count: selected.map_or(DEFAULT_BUTTON_COUNT, |id| self.button_count(id)),
+            app: app.as_deref(),
+        };
+        let mut resolved = ResolvedMappings::resolve(&settings, &context);
+        resolved.escape_primary(
+            &mut lock_recovering(&self.primary_escape, "the primary button escape").escaped,
+        );
+        *write_recovering(&self.resolved, "resolved mappings") = Arc::new(resolved);
+    }
+
+    /// Mappings in effect right now. Runs on every button event, so it
+    /// neither locks `Settings` nor allocates.
+    fn resolved_mappings(&self) -> Arc<ResolvedMappings> {
+        Arc::clone(&read_recovering(&self.resolved, "resolved mappings"))
+    }
+
+    /// Button count of a connected device; the wildcard takes the largest
+    /// count among connected devices.
+    fn button_count(&self, device_id: &str) -> u8 {
+        let cache = read_recovering(&self.devices, "devices");
+        let mut devices = cache.devices.iter();
+        let count = if device_id == ANY_DEVICE_ID {
+            devices.map(|device| device.button_count).max()
+        } else {
+            devices
+                .find(|device| device.id == device_id)
+                .map(|device| device.button_count)
+        };
+        count.unwrap_or(DEFAULT_BUTTON_COUNT)
+    }
+
+    fn cached_battery(&self, device_id: &str) -> Option<BatteryReport> {
+        let guard = self.battery_cache.lock().ok()?;
+        let (read_at, report) = guard.get(device_id)?;
+        (read_at.elapsed() < BATTERY_CACHE_TTL).then(|| report.clone())
+    }
+
+    /// The latest reading of `device_id`'s battery, past `BATTERY_CACHE_TTL`
+    /// too.
+    fn last_battery(&self, device_id: &str) -> Option<LastBattery> {
+        let guard = self.battery_cache.lock().ok()?;
+        let (read_at, report) = guard.get(device_id)?;
+        Some(LastBattery {
+            report: report.clone(),
+            seconds_ago: read_at.elapsed().as_secs(),
+        })
+    }
+
+    /// Keeps `rate` for `get_device_details`, returning the one it replaces.
+    fn cache_report_rate(&self, device_id: &str, rate: ReportRate) -> Option<ReportRate> {
+        lock_recovering(&self.report_rates, "report rates").insert(device_id.to_string(), rate)
+    }
+
+    fn report_rate(&self, device_id: &str) -> Option<ReportRate> {
+        lock_recovering(&self.report_rates, "report rates")
+            .get(device_id)
+            .copied()
+    }
+
+    fn cache_battery(&self, device_id: &str, report: BatteryReport) {
+        if let Ok(mut guard) = self.battery_cache.lock() {
+            guard.insert(device_id.to_string(), (Instant::now(), report));
+        }
+    }
+
+    fn snapshot_settings(&self) -> Settings {
+        read_recovering(&self.settings, "settings").clone()
+    }
+
+    fn is_selected_device_available(&self, device_id: &str) -> bool {
+        let cache = read_recovering(&self.devices, "devices");
+        if device_id == ANY_DEVICE_ID {
+            !cache.devices.is_empty()
+        } else {
+            // Units that can't be told apart stand in for each other, as
+            // their numbers follow plugging order.
+            let base = unit_base_id(device_id);
+            cache
+                .devices
+                .iter()
+                .any(|device| device.id == device_id || unit_base_id(&device.id) == base)
+        }
+    }
+
+    /// Whether a device in `Settings::ignored_devices` is connected.
+    fn is_ignored_device_connected(&self, settings: &Settings) -> bool {
+        let cache = read_recovering(&self.devices, "devices");
+        cache
+            .devices
+            .iter()
+            .any(|device| settings.is_device_ignored(&device.id))
+    }
+
+    fn set_event_monitoring(&self, enabled: bool) {
+        if let Ok(mut guard) = self.monitor.lock() {
+            *guard = EventMonitor {
+                enabled,
+                last_emit: None,
+            };
+        }
+        if enabled {
+            self.wake_engine();
+        }
+    }
+
+    /// True when monitoring is on and the previous `button-event` is at least
+    /// `BUTTON_EVENT_INTERVAL` old; records the emission.
+    fn should_emit_button_event(&self) -> bool {
+        let Ok(mut guard) = self.monitor.lock() else {
+            return false;
+        };
+        if !guard.enabled {
+            return false;
+        }
+        let now = Instant::now();
+        if guard
+            .last_emit
+            .is_some_and(|last| now.duration_since(last) < BUTTON_EVENT_INTERVAL)
+        {
+            return false;
+        }
+        guard.last_emit = Some(now);
+        true
+    }
+
+    /// Starts the input overlay's stream, and wakes the engine for it.
+    /// Returns the end `watch_input_overlay` waits on, or None when the
+    /// stream was already running.
+    fn open_input_overlay_stream(&self) -> Option<std::sync::mpsc::Receiver<()>> {
+        let mut guard = lock_recovering(&self.input_overlay, "the input overlay");
+        if guard.wake.is_some() {
+            return None;
+        }
+        let (sender, receiver) = std::sync::mpsc::channel();
+        *guard = InputOverlayStream {
+            wake: Some(sender),
+            last_emit: None,
+        };
+        drop(guard);
+        self.wake_engine();
+        Some(receiver)
+    }
+
+    /// Ends the stream, which disconnects `watch_input_overlay`.
+    fn close_input_overlay_stream(&self) {
+        lock_recovering(&self.input_overlay, "the input overlay").wake = None;
+    }
+
+    fn is_input_overlay_shown(&self) -> bool {
+        self.input_overlay
+            .lock()
+            .is_ok_and(|guard| guard.wake.is_some())
+    }
+
+    /// True when the overlay is open and, for a press, the previous event
+    /// it was sent is at least `BUTTON_EVENT_INTERVAL` old. Releases always
+    /// go through, so no button stays lit. Records the emission and wakes
+    /// `watch_input_overlay`.
+    fn should_emit_overlay_event(&self, down: bool) -> bool {
+        let Ok(mut guard) = self.input_overlay.lock() else {
+            return false;
+        };
+        let Some(wake) = &guard.wake else {
+            return false;
+        };
+        let now = Instant::now();
+        if down
+            && guard
+                .last_emit
+                .is_some_and(|last| now.duration_since(last) < BUTTON_EVENT_INTERVAL)
+        {
+            return false;
+        }
+        let _ = wake.send(());
+        guard.last_emit = Some(now);
+        true
+    }
+
+    /// Shows the overlay again if it hid itself, and restarts its time to
+    /// hide.
+    fn nudge_input_overlay(&self) {
+        if let Some(wake) = &lock_recovering(&self.input_overlay, "the input overlay").wake {
+            let _ = wake.send(());
+        }
+    }
+
+    fn time_callback(&self) -> CallbackTimer<'_> {
+        CallbackTimer {
+            timing: &self.callback_timing,
+            started: Instant::now(),
+            remapped: false,
+        }
+    }
+
+    fn latency_report(&self) -> LatencyReport {
+        LatencyReport {
+            pass_through: self.callback_timing.pass_through.stats(),
+            remapped: self.callback_timing.remapped.stats(),
+        }
+    }
+
+    fn reset_latency_stats(&self) {
+        self.callback_timing.pass_through.reset();
+        self.callback_timing.remapped.reset();
+    }
+
+    fn remap_diagnostics(&self) -> RemapDiagnostics {
+        let timing = &self.callback_timing;
+        let events = timing.events.load(Ordering::Relaxed);
+        let total = timing.total_nanos.load(Ordering::Relaxed);
+        RemapDiagnostics {
+            engine_state: self.engine_state(),
+            callback_events: events,
+            callback_mean_micros: if events == 0 {
+                0.0
+            } else {
+                total as f64 / events as f64 / 1000.0
+            },
+            callback_max_micros: timing.max_nanos.load(Ordering::Relaxed) as f64 / 1000.0,
+            synthetic_posted: self.output_governor.posted.load(Ordering::Relaxed),
+            synthetic_dropped: self.output_governor.dropped.load(Ordering::Relaxed),
+        }
+    }
+
+    fn engine_state(&self) -> EngineState {
+        self.engine
+            .state
+            .lock()
+            .map(|guard| *guard)
+            .unwrap_or_default()
+    }
+
+    fn set_engine_state(&self, engine_state: EngineState) {
+        if let Ok(mut guard) = self.engine.state.lock() {
+            *guard = engine_state;
+        }
+        let running = engine_state == EngineState::Running;
+        if let Ok(mut guard) = self.engine.running_since.lock() {
+            *guard = running.then(Instant::now);
+        }
+        self.engine.tap_enabled.store(running, Ordering::Relaxed);
+    }
+
+    /// Records a tap-disabled event, or the re-enable that follows it.
+    fn set_tap_enabled(&self, enabled: bool, timed_out: bool) {
+        self.engine.tap_enabled.store(enabled, Ordering::Relaxed);
+        if timed_out {
+            self.engine.tap_timeouts.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    fn engine_status(&self) -> RemapEngineStatus {
+        let uptime = self
+            .engine
+            .running_since
+            .lock()
+            .ok()
+            .and_then(|guard| *guard)
+            .map(|since| since.elapsed().as_secs());
+        RemapEngineStatus {
+            state: self.engine_state(),
+            tap_enabled: self.engine.tap_enabled.load(Ordering::Relaxed),
+            tap_timeouts: self.engine.tap_timeouts.load(Ordering::Relaxed),
+            uptime_secs: uptime,
+            last_error: self
+                .remap_errors
+                .lock()
+                .ok()
+                .and_then(|guard| guard.back().cloned()),
+            missing_device: self.missing_device(),
+            idle_suspended: self.is_idle_suspended(),
+            monitor_only: self.is_monitor_only(),
+        }
+    }
+
+    /// The selected device when it isn't connected. "Any mouse" is never
+    /// missing, since it waits for whichever mouse comes along.
+    fn missing_device(&self) -> Option<MissingDevice> {
+        let settings = self.snapshot_settings();
+        let id = settings.selected_device.as_deref()?;
+        if id == ANY_DEVICE_ID || self.is_selected_device_available(id) {
+            return None;
+        }
+        let name = settings
+            .device_config(id)
+            .map_or(id, |config| config.display_name(id));
+        let fallback = self.fallback_device().map(|fallback| {
+            let cache = read_recovering(&self.devices, "devices");
+            cache
+                .devices
+                .iter()
+                .find(|device| device.id == fallback)
+                .map_or_else(|| fallback.clone(), |device| device.name.clone())
+        });
+        Some(MissingDevice {
+            id: id.to_string(),
+            name: name.to_string(),
+            fallback,
+        })
+    }
+
+    fn fallback_device(&self) -> Option<String> {
+        lock_recovering(&self.fallback, "the fallback device").clone()
+    }
+
+    /// Stores the device standing in for the selected one, returning the
+    /// one before.
+    fn swap_fallback_device(&self, fallback: Option<String>) -> Option<String> {
+        std::mem::replace(
+            &mut *lock_recovering(&self.fallback, "the fallback device"),
+            fallback,
+        )
+    }
+
+    /// Records an engine error, folding it into the previous entry when it
+    /// repeats. Returns the entry as stored.
+    fn record_remap_error(&self, code: &'static str, message: String) -> Option<RemapError> {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_secs())
+            .unwrap_or_default();
+        let mut guard = self.remap_errors.lock().ok()?;
+        match guard.back_mut() {
+            Some(last) if last.code == code && last.message == message => {
+                last.timestamp = timestamp;
+                last.count += 1;
+            }
+            _ => {
+                if guard.len() == REMAP_ERROR_HISTORY {
+                    guard.pop_front();
+                }
+                guard.push_back(RemapError {
+                    code,
+                    message,
+                    timestamp,
+                    count: 1,
+                });
+            }
+        }
+        guard.back().cloned()
+    }
+
+    fn remap_errors(&self) -> Vec<RemapError> {
+        self.remap_errors
+            .lock()
+            .map(|guard| guard.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    fn request_engine_restart(&self) {
+        if let Ok(mut guard) = self.engine.restart.lock() {
+            *guard = true;
+            self.engine.wake.notify_all();
+        }
+    }
+
+    /// Whether the event tap should exist: something is remapped, a button
+    /// capture, the event monitor or the input overlay is listening, or a
+    /// translated middle button still has to be released.
+    fn is_event_tap_wanted(&self) -> bool {
+        self.engine.mappings_need_tap.load(Ordering::Relaxed)
+            || self.is_capture_armed()
+            || self.monitor.lock().is_ok_and(|guard| guard.enabled)
+            || self.is_input_overlay_shown()
+            || self.middle_drag_button().is_some()
+    }
+
+    /// The events the event tap must listen for right now; a running tap
+    /// is recreated when they change.
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+    fn tap_events(&self) -> TapEvents {
+        let listening = self.is_capture_armed()
+            || self.monitor.lock().is_ok_and(|guard| guard.enabled)
+            || self.is_input_overlay_shown()
+            || self.is_monitor_only();
+        let events = TapEvents::needed(&self.resolved_mappings(), listening);
+        // Idle time only resets on input the tap sees.
+        if read_recovering(&self.settings, "settings")
+            .idle_suspend_minutes
+            .is_some()
+        {
+            events.watching_idle()
+        } else {
+            events
+        }
+    }
+
+    fn wake_engine(&self) {
+        if let Ok(_guard) = self.engine.restart.lock() {
+            self.engine.wake.notify_all();
+        }
+    }
+
+    /// Blocks while the tap isn't wanted, or until a restart is requested.
+    fn wait_for_event_tap(&self) {
+        let Ok(guard) = self.engine.restart.lock() else {
+            return;
+        };
+        if let Ok(mut guard) = self
+            .engine
+            .wake
+            .wait_while(guard, |restart| !*restart && !self.is_event_tap_wanted())
+        {
+            *guard = false;
+        }
+    }
+
+    /// Consumes a pending restart request; the running tap polls this.
+    fn take_engine_restart(&self) -> bool {
+        self.engine
+            .restart
+            .lock()
+            .map(|mut guard| std::mem::take(&mut *guard))
+            .unwrap_or(false)
+    }
+
+    /// Sleeps up to `timeout` between tap attempts. Returns true when a
+    /// restart was requested, which ends the wait early.
+    fn wait_for_engine_restart(&self, timeout: Duration) -> bool {
+        let Ok(guard) = self.engine.restart.lock() else {
+            return false;
+        };
+        let Ok((mut guard, _)) = self
+            .engine
+            .wake
+            .wait_timeout_while(guard, timeout, |restart| !*restart)
+        else {
+            return false;
+        };
+        std::mem::take(&mut *guard)
+    }
+
+    /// True when the previous profile cycle is at least
+    /// `PROFILE_CYCLE_DEBOUNCE` old; records the cycle.
+    fn should_cycle_profile(&self) -> bool {
+        let Ok(mut guard) = self.last_profile_cycle.lock() else {
+            return false;
+        };
+        let now = Instant::now();
+        if guard.is_some_and(|last| now.duration_since(last) < PROFILE_CYCLE_DEBOUNCE) {
+            return false;
+        }
+        *guard = Some(now);
+        true
+    }
+
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+    fn auto_profile_for(&self, settings: &Settings, bundle_id: &str) -> Option<String> {
+        self.auto_profile
+            .lock()
+            .ok()?
+            .on_activate(settings, bundle_id)
+    }
+
+    fn note_manual_profile_switch(&self) {
+        if let Ok(mut guard) = self.auto_profile.lock() {
+            guard.on_manual_switch();
+        }
+        lock_recovering(&self.profile_schedule, "the profile schedule").on_manual_switch();
+    }
+
+    fn scheduled_profile_switch(
+        &self,
+        settings: &Settings,
+        at: schedule::LocalTime,
+    ) -> Option<String> {
+        lock_recovering(&self.profile_schedule, "the profile schedule").evaluate(settings, at)
+    }
+
+    /// Has the schedules evaluated now rather than at the next minute, as
+    /// after a wake from sleep, when the clock has jumped.
+    fn nudge_profile_schedule(&self) {
+        if let Some(sender) = self.schedule_nudge.get() {
+            let _ = sender.send(());
+        }
+    }
+
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+    fn set_frontmost_app(&self, app: Option<frontmost::AppInfo>, is_self: bool) {
+        if let Ok(mut guard) = self.foreground.lock() {
+            guard.current = app.as_ref().map(|app| app.bundle_id.clone());
+            if !is_self && app.is_some() {
+                guard.last_external = app;
+            }
+        }
+        self.refresh_mappings();
+    }
+
+    fn frontmost_app(&self) -> Option<String> {
+        self.foreground.lock().ok()?.current.clone()
+    }
+
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+    fn set_frontmost_fullscreen(&self, fullscreen: bool) {
+        if let Ok(mut guard) = self.foreground.lock() {
+            guard.fullscreen = fullscreen;
+        }
+        self.refresh_mappings();
+    }
+
+    fn is_remap_enabled(&self) -> bool {
+        !self.remap_disabled.load(Ordering::Relaxed)
+    }
+
+    /// The frontmost application by name when known, else by bundle id.
+    fn frontmost_app_name(&self) -> Option<String> {
+        let guard = self.foreground.lock().ok()?;
+        let current = guard.current.clone()?;
+        Some(
+            guard
+                .last_external
+                .as_ref()
+                .filter(|app| app.bundle_id == current)
+                .map_or(current, |app| app.name.clone()),
+        )
+    }
+
+    /// Hands `action` firing from `button` under `mappings` to the usage
+    /// thread, for the counts and the history, unless both are off. Runs on
+    /// the event path, so it only sends.
+    fn record_use(&self, mappings: &Arc<ResolvedMappings>, button: i64, action: Action) {
+        if self.stats_disabled.load(Ordering::Relaxed)
+            && self.history_disabled.load(Ordering::Relaxed)
+        {
+            return;
+        }
+        if let Some(usage) = self.usage.get() {
+            let _ = usage.send(UsageMessage::Used {
+                mappings: Arc::clone(mappings),
+                button,
+                action,
+            });
+        }
+    }
+
+    /// Hands a press of `button` swallowed as switch bounce to the usage
+    /// thread, to be counted unless the counts are off.
+    fn record_bounce(&self, mappings: &Arc<ResolvedMappings>, button: i64) {
+        if self.stats_disabled.load(Ordering::Relaxed) {
+            return;
+        }
+        if let Some(usage) = self.usage.get() {
+            let _ = usage.send(UsageMessage::Bounced {
+                mappings: Arc::clone(mappings),
+                button,
+            });
+        }
+    }
+
+    /// Hands the feedback `button` gives under `mappings` to the feedback
+    /// thread, unless it gives none or feedback is muted. Called once the
+    /// action is posted, and only sends, so the action never waits on it.
+    fn give_feedback(&self, mappings: &ResolvedMappings, button: i64) {
+        let feedback = mappings.feedback(button);
+        if feedback == Feedback::None || self.feedback_muted.load(Ordering::Relaxed) {
+            return;
+        }
+        if let Some(sender) = self.feedback.get() {
+            let _ = sender.send(feedback);
+        }
+    }
+
+    /// Pause between the clicks of a Double Click fired by `button` under
+    /// `mappings`, or by no button at all.
+    fn double_click_interval(&self, mappings: &ResolvedMappings, button: Option<i64>) -> Duration {
+        let interval = button
+            .and_then(|button| mappings.double_click_interval_ms(button))
+            .map_or_else(
+                || self.double_click_interval_ms.load(Ordering::Relaxed),
+                u64::from,
+            );
+        Duration::from_millis(interval)
+    }
+
+    fn secure_input(&self) -> Option<SecureInputHolder> {
+        lock_recovering(&self.secure_input, "secure input").clone()
+    }
+
+    /// Records who holds secure input, None once it ended. Returns whether
+    /// that changed.
+    fn set_secure_input(&self, holder: Option<SecureInputHolder>) -> bool {
+        let mut guard = lock_recovering(&self.secure_input, "secure input");
+        let changed = *guard != holder;
+        *guard = holder;
+        changed
+    }
+
+    /// Whether secure input should be logged at `now`: at most once per
+    /// `SECURE_INPUT_LOG_INTERVAL`, however often it comes and goes.
+    fn should_log_secure_input(&self, now: Instant) -> bool {
+        let mut logged = lock_recovering(&self.secure_input_logged, "secure input log time");
+        if logged.is_some_and(|at| now.saturating_duration_since(at) < SECURE_INPUT_LOG_INTERVAL) {
+            return false;
+        }
+        *logged = Some(now);
+        true
+    }
+
+    fn push_history(&self, entry: FiredAction) {
+        if let Ok(mut guard) = self.history.lock() {
+            if guard.len() == ACTION_HISTORY {
+                guard.pop_front();
+            }
+            guard.push_back(entry);
+        }
+    }
+
+    fn action_history(&self) -> Vec<FiredAction> {
+        self.history
+            .lock()
+            .map(|guard| guard.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// Shows `message` as a system notification.
+    fn post_notification(&self, message: String) {
+        log::info!("notifications: {}", message);
+        let Some(app) = self.notifier.get() else {
+            return;
+        };
+        let posted = app
+            .notification()
+            .builder()
+            .title("Edit Mouse")
+            .body(message)
+            .show();
+        if let Err(err) = posted {
+            log::error!("notifications: failed to post: {}", err);
+        }
+    }
+
+    /// Has the usage thread write what it has counted, waiting up to
+    /// `STATS_FLUSH_WAIT`. False when it isn't running or didn't answer.
+    fn flush_usage_stats(&self) -> bool {
+        let Some(usage) = self.usage.get() else {
+            return false;
+        };
+        let (done, flushed) = std::sync::mpsc::channel();
+        usage.send(UsageMessage::Flush(done)).is_ok()
+            && flushed.recv_timeout(STATS_FLUSH_WAIT).is_ok()
+    }
+
+    /// Hands `settings` to the settings writer to save once changes settle.
+    /// False when it isn't running, so they're written right away instead.
+    fn queue_settings_write(&self, settings: &Settings) -> bool {
+        self.settings_writer.get().is_some_and(|writer| {
+            writer
+                .send(SettingsWrite::Changed(Box::new(settings.clone())))
+                .is_ok()
+        })
+    }
+
+    /// Has the settings writer save what's waiting, waiting up to
+    /// `SETTINGS_FLUSH_WAIT`. Fails when it didn't answer or its latest
+    /// write failed; with no writer running nothing waits.
+    fn flush_settings(&self) -> Result<(), String> {
+        let Some(writer) = self.settings_writer.get() else {
+            return Ok(());
+        };
+        let (done, flushed) = std::sync::mpsc::channel();
+        if writer.send(SettingsWrite::Flush(done)).is_err() {
+            return Ok(());
+        }
+        flushed
+            .recv_timeout(SETTINGS_FLUSH_WAIT)
+            .unwrap_or_else(|_| {
+                Err("settings are taking long to save; they'll be written shortly".to_string())
+            })
+    }
+
+    /// Has the settings writer save what's waiting without waiting for it.
+    fn flush_settings_later(&self) {
+        if let Some(writer) = self.settings_writer.get() {
+            let (done, _) = std::sync::mpsc::channel();
+            let _ = writer.send(SettingsWrite::Flush(done));
+        }
+    }
+
+    /// Whether an event whose source user data is `user_data` goes through
+    /// remapping. Events Edit Mouse posted itself never do, so a synthetic
+    /// middle click isn't remapped again.
+    fn should_remap(&self, user_data: i64) -> bool {
+        if user_data == SYNTHETIC_EVENT_TAG {
+            return false;
+        }
+        self.note_input();
+        !self.is_passing_through()
+    }
+
+    /// Notes input from the user. One ending an idle suspension is remapped
+    /// like any other, so the press that wakes remapping isn't lost.
+    fn note_input(&self) {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_secs())
+            .unwrap_or_default();
+        self.last_input.store(now, Ordering::Relaxed);
+        if self.end_idle_suspension() {
+            if let Some(sender) = self.idle_wake.get() {
+                let _ = sender.send(());
+            }
+        }
+    }
+
+    fn is_idle_suspended(&self) -> bool {
+        self.idle_suspended.load(Ordering::Relaxed)
+    }
+
+    /// Seconds until `minutes` without input have passed at `now`, or
+    /// `None` once they have and remapping is suspended. Returns `Some(0)`
+    /// when this call suspended it.
+    fn suspend_if_idle(&self, now: u64, minutes: u32) -> Option<u64> {
+        if self.is_idle_suspended() {
+            return None;
+        }
+        let idle = now.saturating_sub(self.last_input.load(Ordering::Relaxed));
+        let timeout = u64::from(minutes) * 60;
+        if idle < timeout {
+            return Some(timeout - idle);
+        }
+        self.idle_suspended.store(true, Ordering::Relaxed);
+        Some(0)
+    }
+
+    /// Returns whether remapping was suspended for being idle.
+    fn end_idle_suspension(&self) -> bool {
+        self.idle_suspended.swap(false, Ordering::Relaxed)
+    }
+
+    fn is_monitor_only(&self) -> bool {
+        self.monitor_only.load(Ordering::Relaxed)
+    }
+
+    /// Turns monitor mode on or off, restarting the engine so its tap is
+    /// recreated to match. Returns whether that changed anything.
+    fn set_monitor_only(&self, enabled: bool) -> bool {
+        if self.monitor_only.swap(enabled, Ordering::Relaxed) == enabled {
+            return false;
+        }
+        self.request_engine_restart();
+        true
+    }
+
+    fn note_quick_panel_blurred(&self) {
+        *lock_recovering(&self.quick_panel_blurred, "the quick panel") = Some(Instant::now());
+    }
+
+    /// Whether the quick panel closed for losing focus a moment ago.
+    fn quick_panel_just_blurred(&self) -> bool {
+        lock_recovering(&self.quick_panel_blurred, "the quick panel")
+            .is_some_and(|blurred| blurred.elapsed() < QUICK_PANEL_REOPEN_GUARD)
+    }
+
+    fn set_own_window_focused(&self, focused: bool) {
+        self.own_window_focused.store(focused, Ordering::Relaxed);
+    }
+
+    /// True while presses should keep their usual meaning because the main
+    /// window has focus; see `Settings::pause_in_own_window`. Unlike
+    /// passing through, button capture and `button-event` still see them.
+    fn is_own_window_guarded(&self) -> bool {
+        self.own_window_focused.load(Ordering::Relaxed)
+            && !self.own_window_remapped.load(Ordering::Relaxed)
+    }
+
+    /// True when events should reach applications untouched: in safe mode,
+    /// while the session is inactive, or with remapping off or paused and
+    /// no button capture waiting for a press.
+    fn is_passing_through(&self) -> bool {
+        self.is_safe_mode()
+            || self.session_inactive.load(Ordering::Relaxed)
+            || (self.is_idle_suspended() && !self.is_capture_armed())
+            || ((!self.is_remap_enabled() || self.is_remap_paused()) && !self.is_capture_armed())
+    }
+
+    /// Notes whether this user's session is unlocked and in front; returns
+    /// whether that changed. Leaving it lets go of held input, so nothing
+    /// is stuck once the session is back.
+    fn set_session_active(&self, active: bool) -> bool {
+        if self.session_inactive.swap(!active, Ordering::Relaxed) != active {
+            return false;
+        }
+        if !active {
+            self.let_go_of_input();
+        }
+        true
+    }
+
+    fn is_safe_mode(&self) -> bool {
+        self.safe_mode.load(Ordering::Relaxed)
+    }
+
+    /// Turns safe mode on or off; returns whether that changed. Entering it
+    /// lets go of held input like leaving the session does.
+    fn set_safe_mode(&self, on: bool) -> bool {
+        if self.safe_mode.swap(on, Ordering::Relaxed) == on {
+            return false;
+        }
+        if on {
+            self.let_go_of_input();
+        }
+        true
+    }
+
+    /// Drops held gestures and mappings and restarts the engine, whose
+    /// teardown releases a middle button held for a drag.
+    fn let_go_of_input(&self) {
+        self.cancel_capture();
+        if let Ok(mut guard) = self.gesture.lock() {
+            *guard = None;
+        }
+        if let Ok(mut guard) = self.held.lock() {
+            *guard = Default::default();
+        }
+        self.request_engine_restart();
+    }
+
+    /// Pauses remapping for `duration`, or until resumed when `None`.
+    /// Returns the pause's generation for `end_pause`.
+    fn pause_remapping(&self, duration: Option<Duration>) -> u64 {
+        let Ok(mut guard) = self.pause.lock() else {
+            return 0;
+        };
+        guard.paused = true;
+        guard.until = duration.map(|duration| SystemTime::now() + duration);
+        guard.generation += 1;
+        guard.generation
+    }
+
+    /// Ends any pause; returns whether there was one.
+    fn resume_remapping(&self) -> bool {
+        let Ok(mut guard) = self.pause.lock() else {
+            return false;
+        };
+        guard.generation += 1;
+        std::mem::replace(&mut guard.paused, false)
+    }
+
+    /// Ends the pause started as `generation` once it has expired, returning
+    /// whether it did, or `None` once another pause or a resume replaced it.
+    fn end_pause(&self, generation: u64) -> Option<bool> {
+        let mut guard = self.pause.lock().ok()?;
+        if !guard.paused || guard.generation != generation {
+            return None;
+        }
+        let expired = guard.until.is_some_and(|until| SystemTime::now() >= until);
+        if expired {
+            guard.paused = false;
+            guard.generation += 1;
+        }
+        Some(expired)
+    }
+
+    fn is_remap_paused(&self) -> bool {
+        self.pause
+            .lock()
+            .map(|guard| guard.paused && guard.until.is_none_or(|until| SystemTime::now() < until))
+            .unwrap_or(false)
+    }
+
+    fn remap_pause_status(&self) -> RemapPauseStatus {
+        let until = self.pause.lock().ok().and_then(|guard| guard.until);
+        let paused = self.is_remap_paused();
+        RemapPauseStatus {
+            paused,
+            until: until.filter(|_| paused).map(|until| {
+                until
+                    .duration_since(UNIX_EPOCH)
+                    .map(|elapsed| elapsed.as_secs())
+                    .unwrap_or_default()
+            }),
+        }
+    }
+
+    /// True while game mode has suspended remapping.
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+    fn is_game_mode_paused(&self) -> bool {
+        self.foreground
+            .lock()
+            .map(|guard| guard.game_mode && guard.fullscreen)
+            .unwrap_or(false)
+    }
+
+    /// True while the frontmost application is excluded, either by name or
+    /// by game mode.
+    fn is_remapping_suspended(&self) -> bool {
+        self.foreground
+            .lock()
+            .map(|guard| {
+                (guard.game_mode && guard.fullscreen)
+                    || guard
+                        .current
+                        .as_ref()
+                        .is_some_and(|app| guard.excluded.contains(app))
+            })
+            .unwrap_or(false)
+    }
+
+    fn last_external_app(&self) -> Option<frontmost::AppInfo> {
+        self.foreground.lock().ok()?.last_external.clone()
+    }
+
+    fn hold_mapping(&self, button: i64, mapping: HeldMapping) {
+        let Ok(slot) = usize::try_from(button) else {
+            return;
+        };
+        if let Some(held) = self
+            .held
+            .lock()
+            .ok()
+            .as_mut()
+            .and_then(|guard| guard.get_mut(slot))
+        {
+            *held = Some(mapping);
+        }
+    }
+
+    /// Whether `button` going `down` at `now` is switch bounce: a press
+    /// within the button's debounce window of the last press let through,
+    /// or the release of such a press.
+    fn is_bounce(
+        &self,
+        mappings: &ResolvedMappings,
+        button: i64,
+        down: bool,
+        now: Instant,
+    ) -> bool {
+        let Ok(index) = usize::try_from(button) else {
+            return false;
+        };
+        let mut bounces = lock_recovering(&self.bounces, "the debounce state");
+        let Some(slot) = bounces.get_mut(index) else {
+            return false;
+        };
+        if !down {
+            return std::mem::take(&mut slot.bouncing);
+        }
+        let window = mappings.debounce(button);
+        if !window.is_zero()
+            && slot
+                .last_press
+                .is_some_and(|last| now.saturating_duration_since(last) < window)
+        {
+            slot.bouncing = true;
+            return true;
+        }
+        *slot = ButtonBounce {
+            last_press: Some(now),
+            bouncing: false,
+        };
+        false
+    }
+
+    /// Times a hold of the left button while `mappings` remap it. A release
+    /// at least `PRIMARY_ESCAPE_HOLD` after the press sets the mapping it
+    /// had aside and returns it; `refresh_mappings` then resolves the left
+    /// button to Default until the settings change it.
+    fn track_primary_hold(
+        &self,
+        mappings: &ResolvedMappings,
+        down: bool,
+        now: Instant,
+    ) -> Option<ResolvedAction> {
+        let mut escape = lock_recovering(&self.primary_escape, "the primary button escape");
+        if down {
+            escape.held_since = mappings.primary_remap().map(|remap| (now, remap));
+            return None;
+        }
+        let (since, remap) = escape.held_since.take()?;
+        if now.saturating_duration_since(since) < PRIMARY_ESCAPE_HOLD {
+            return None;
+        }
+        escape.escaped = Some(remap);
+        Some(remap)
+    }
+
+    /// Puts the left button's mapping back in effect after an escape.
+    fn end_primary_escape(&self) {
+        let mut escape = lock_recovering(&self.primary_escape, "the primary button escape");
+        if escape.escaped.take().is_none() {
+            return;
+        }
+        drop(escape);
+        self.refresh_mappings();
+    }
+
+    /// The mapping `button` was pressed with, if its press was seen.
+    fn release_mapping(&self, button: i64) -> Option<HeldMapping> {
+        let slot = usize::try_from(button).ok()?;
+        self.held.lock().ok()?.get_mut(slot)?.take()
+    }
+
+    fn begin_gesture(&self, button: i64, config: ResolvedGesture) {
+        if let Ok(mut guard) = self.gesture.lock() {
+            *guard = Some(ActiveGesture {
+                button,
+                config,
+                dx: 0.0,
+                dy: 0.0,
+            });
+        }
+    }
+
+    /// Adds pointer movement to the held gesture. Returns true when the
+    /// movement should be kept from moving the cursor.
+    fn track_gesture(&self, dx: f64, dy: f64) -> bool {
+        let Ok(mut guard) = self.gesture.lock() else {
+            return false;
+        };
+        let Some(gesture) = guard.as_mut() else {
+            return false;
+        };
+        gesture.dx += dx;
+        gesture.dy += dy;
+        gesture.config.suppress_cursor
+    }
+
+    /// Ends the gesture held on `button`, returning the action it resolved to.
+    fn finish_gesture(&self, button: i64) -> Option<Action> {
+        let mut guard = self.gesture.lock().ok()?;
+        if guard.as_ref()?.button != button {
+            return None;
+        }
+        let gesture = guard.take()?;
+        Some(gesture.config.resolve(gesture.dx, gesture.dy))
+    }
+
+    fn arm_capture(&self, device_id: &str) {
+        if let Ok(mut guard) = self.capture.lock() {
+            *guard = Some(ButtonCapture {
+                device_id: device_id.to_string(),
+                expires_at: Instant::now() + BUTTON_CAPTURE_TIMEOUT,
+                pressed: None,
+            });
+        }
+        self.wake_engine();
+    }
+
+    fn cancel_capture(&self) {
+        if let Ok(mut guard) = self.capture.lock() {
+            *guard = None;
+        }
+    }
+
+    /// Starts translating `button` into the middle button; false when
+    /// another button already is.
+    fn begin_middle_drag(&self, button: i64) -> bool {
+        let Ok(mut guard) = self.middle_drag.lock() else {
+            return false;
+        };
+        if guard.is_some() {
+            return false;
+        }
+        *guard = Some(button);
+        true
+    }
+
+    /// Starts repeating for a press of `button`, returning the hold for
+    /// `is_key_repeating`. Stops any repeat before it.
+    fn begin_key_repeat(&self, button: i64) -> u64 {
+        let mut guard = lock_recovering(&self.key_repeat, "the key repeat");
+        guard.holds += 1;
+        guard.button = Some(button);
+        guard.holds
+    }
+
+    fn end_key_repeat(&self, button: i64) {
+        let mut guard = lock_recovering(&self.key_repeat, "the key repeat");
+        if guard.button == Some(button) {
+            guard.button = None;
+        }
+    }
+
+    /// Whether `hold` is still held and remapping still on.
+    fn is_key_repeating(&self, hold: u64) -> bool {
+        let guard = lock_recovering(&self.key_repeat, "the key repeat");
+        guard.button.is_some() && guard.holds == hold && self.is_remap_enabled()
+    }
+
+    fn middle_drag_button(&self) -> Option<i64> {
+        self.middle_drag.lock().ok().and_then(|guard| *guard)
+    }
+
+    /// Ends the middle-button translation if `button`, or any button when
+    /// `None`, started it. Returns whether the middle button must be
+    /// released.
+    fn end_middle_drag(&self, button: Option<i64>) -> bool {
+        let Ok(mut guard) = self.middle_drag.lock() else {
+            return false;
+        };
+        if guard.is_none() || button.is_some_and(|button| *guard != Some(button)) {
+            return false;
+        }
+        *guard = None;
+        true
+    }
+
+    fn is_capture_armed(&self) -> bool {
+        self.capture
+            .lock()
+            .map(|guard| guard.is_some())
+            .unwrap_or(false)
+    }
+
+    /// Feeds a button event through the capture state. The first press after
+    /// arming is captured and the capture disarms once that button is released;
+    /// releases of buttons held before arming pass through untouched.
+    fn capture_button(&self, button: i64, down: bool) -> CaptureOutcome {
+        let Ok(mut guard) = self.capture.lock() else {
+            return CaptureOutcome::Pass;
+        };
+        let Some(capture) = guard.as_mut() else {
+            return CaptureOutcome::Pass;
+        };
+        match capture.pressed {
+            None if Instant::now() >= capture.expires_at => {
+                *guard = None;
+                CaptureOutcome::Pass
+            }
+            None if down => {
+                capture.pressed = Some(button);
+                CaptureOutcome::Captured {
+                    device_id: capture.device_id.clone(),
+                }
+            }
+            Some(pressed) if pressed == button && !down => {
+                *guard = None;
+                CaptureOutcome::Suppress
+            }
+            _ => CaptureOutcome::Pass,
+        }
+    }
+}
+
+/// How often `watch_devices` lists the connected devices.
+const DEVICE_POLL: Duration = Duration::from_secs(2);
+
+/// Polls the connected devices so the tray and the settings window follow
+/// plugging and unplugging, emitting `devices-changed` with the new list.
+fn watch_devices(app: tauri::AppHandle, state: AppState) {
+    std::thread::spawn(move || loop {
+        std::thread::sleep(DEVICE_POLL);
+        let filter = DeviceFilter::from(&state.snapshot_settings());
+        let Ok(devices) = list_mouse_devices(&state.hid, filter) else {
+            continue;
+        };
+        let changed = state.update_devices(&devices);
+        run_connect_rules(&app, &state);
+        if !changed {
+            continue;
+        }
+        adopt_new_devices(&app, &state, &devices);
+        settle_fallback_device(&app, &state);
+        show_hud(&app, &state);
+        log::info!("devices: {} connected", devices.len());
+        refresh_tray_menu(&app);
+        if let Err(err) = app.emit("devices-changed", &devices) {
+            log::error!("devices: failed to emit devices-changed: {}", err);
+        }
+    });
+}
+
+/// Saves a config for each of the connected `devices` that has none, in one
+/// write however many there are. Safe mode and read-only settings go
+/// without, as they save nothing.
+fn adopt_new_devices(app: &tauri::AppHandle, state: &AppState, devices: &[MouseDevice]) {
+    if state.is_safe_mode() || state.is_settings_read_only() {
+        return;
+    }
+    if !settle_device_configs(&mut state.snapshot_settings(), devices) {
+        return;
+    }
+    let result = state.change_settings(app, None, |settings| {
+        settle_device_configs(settings, devices);
+        Ok(())
+    });
+    if let Err(err) = result {
+        log::error!(
+            "devices: failed to save the configs of new devices: {}",
+            err
+        );
+    }
+}
+
+/// Remaps the device `fallback_device` picks in place of the selected one,
+/// and tells the user when one starts or stops standing in. Runs as devices
+/// come and go and as settings change, not when the window lists devices.
+fn settle_fallback_device(app: &tauri::AppHandle, state: &AppState) {
+    let settings = state.snapshot_settings();
+    let (devices, _) = state.listed_devices();
+    let fallback = fallback_device(&settings, &devices);
+    let previous = state.swap_fallback_device(fallback.clone());
+    if previous == fallback {
+        return;
+    }
+    state.refresh_mappings();
+    let name = |id: &str| {
+        settings
+            .device_config(id)
+            .filter(|config| !config.name.is_empty())
+            .map(|config| config.name.clone())
+            .or_else(|| {
+                devices
+                    .iter()
+                    .find(|device| device.id == id)
+                    .map(|device| device.name.clone())
+            })
+            .unwrap_or_else(|| id.to_string())
+    };
+    let selected = settings.selected_device.as_deref().unwrap_or_default();
+    let locale = Locale::from_setting(&settings.locale);
+    let message = match (&previous, &fallback) {
+        (_, Some(fallback)) => {
+            log::info!("devices: remapping {} in place of {}", fallback, selected);
+            locale.format(
+                Text::NoticeFallbackActive,
+                &[&name(selected), &name(fallback)],
+            )
+        }
+        (Some(previous), None) => {
+            log::info!(
+                "devices: no longer remapping {} in place of {}",
+                previous,
+                selected
+            );
+            if state.is_selected_device_available(selected) {
+                locale.format(
+                    Text::NoticeFallbackEnded,
+                    &[&name(selected), &name(previous)],
+                )
+            } else {
+                locale.format(
+                    Text::NoticeFallbackStopped,
+                    &[&name(previous), &name(selected)],
+                )
+            }
+        }
+        (None, None) => return,
+    };
+    state.post_notification(message);
+    let payload = FallbackDeviceChanged {
+        selected: settings.selected_device.clone(),
+        fallback,
+    };
+    if let Err(err) = app.emit("fallback-device-changed", payload) {
+        log::error!("devices: failed to emit fallback-device-changed: {}", err);
+    }
+}
+
+/// Least time between two notifications about the same device, or two runs
+/// of its `on_connect` rules, so a dongle dropping in and out doesn't post
+/// one every few seconds.
+const DEVICE_NOTICE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// `on_connect` rules waiting for `run_connect_rules`, and when each
+/// device's last ran.
+#[derive(Debug, Default)]
+struct ConnectRuleQueue {
+    pending: Vec<(String, Vec<ConnectRule>)>,
+    last_run: HashMap<String, Instant>,
+}
+
+/// The `on_connect` rules of the devices in the `after` listing of ids and
+/// names that weren't in `before`, keyed by device id.
+fn connect_rules(
+    settings: &Settings,
+    before: &[(String, String)],
+    after: &[(String, String)],
+) -> Vec<(String, Vec<ConnectRule>)> {
+    after
+        .iter()
+        .filter(|(id, _)| !before.iter().any(|(other, _)| other == id))
+        .filter_map(|(id, _)| {
+            let rules = &settings.device_config(id)?.on_connect;
+            (!rules.is_empty()).then(|| (id.clone(), rules.clone()))
+        })
+        .collect()
+}
+
+/// Carries out the queued `on_connect` rules. Safe mode drops them, as it
+/// leaves everything as it was at launch.
+fn run_connect_rules(app: &tauri::AppHandle, state: &AppState) {
+    for (id, rules) in state.take_connect_rules() {
+        if state.is_safe_mode() {
+            log::info!("devices: {} connected; skipping its rules in safe mode", id);
+            continue;
+        }
+        for rule in rules {
+            log::info!("devices: {} connected, running {:?}", id, rule);
+            let result = match &rule {
+                ConnectRule::SwitchProfile { profile } => activate_profile(app, profile).map(drop),
+                ConnectRule::SetRemapEnabled { enabled } => {
+                    if *enabled && state.resume_remapping() {
+                        announce_remap_pause(app);
+                    }
+                    if state.is_remap_enabled() == *enabled {
+                        Ok(())
+                    } else {
+                        change_remap_enabled(app, *enabled)
+                    }
+                }
+                ConnectRule::RunAction {
+                    action: ActionValue::Action(action),
+                } => test_with_engine(app, state, None, ActionTest::Action(*action), Some(true))
+                    .map(drop),
+                ConnectRule::RunAction { action } => Err(format!("can't run {:?}", action)),
+            };
+            if let Err(err) = result {
+                log::error!("devices: on-connect rule of {} failed: {}", id, err);
+            }
+        }
+    }
+}
+
+/// What the HUD window shows; the `hud` event carries it, tagged by `kind`.
+#[derive(Debug, Serialize, Clone, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+enum Hud {
+    /// The selected device just connected.
+    Connect(ConnectHud),
+    /// The active profile changed, from any source.
+    Profile { profile: String },
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+struct ConnectHud {
+    device: String,
+    profile: String,
+    /// Buttons that don't do Default, in `BUTTON_KEYS` order.
+    mappings: Vec<HudMapping>,
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+struct HudMapping {
+    button: &'static str,
+    action: String,
+}
+
+/// The HUD about to show or showing, and how many were shown so far.
+#[derive(Debug, Default)]
+struct HudState {
+    pending: Option<Hud>,
+    showing: Option<Hud>,
+    shown: u64,
+}
+
+/// The HUD for the selected device when it's among the `after` listing of
+/// ids and names but wasn't in `before`. None when it didn't just connect
+/// or has no config.
+fn connect_hud(
+    settings: &Settings,
+    before: &[(String, String)],
+    after: &[(String, String)],
+) -> Option<ConnectHud> {
+    let selected = settings.selected_device.as_deref()?;
+    if before.iter().any(|(id, _)| id == selected) {
+        return None;
+    }
+    let (id, name) = after.iter().find(|(id, _)| id == selected)?;
+    let config = settings.device_config(id)?;
+    let mappings = BUTTON_KEYS
+        .iter()
+        .filter_map(|key| {
+            let value = config.buttons.get(*key)?;
+            let mapped =
+                matches!(value, ActionValue::Gesture { .. }) || value.action() != Action::Default;
+            mapped.then(|| HudMapping {
+                button: key,
+                action: value.label(),
+            })
+        })
+        .collect();
+    Some(ConnectHud {
+        device: config.display_name(name).to_string(),
+        profile: settings.active_profile.clone(),
+        mappings,
+    })
+}
+
+/// Notifications for configured devices that came or went between the
+/// `before` and `after` listings of ids and names, keyed by device id.
+fn device_change_notices(
+    settings: &Settings,
+    before: &[(String, String)],
+    after: &[(String, String)],
+    locale: Locale,
+) -> Vec<(String, String)> {
+    let listed =
+        |devices: &[(String, String)], id: &str| devices.iter().any(|(other, _)| other == id);
+    let remapped =
+        |id: &str| settings.remap_enabled && settings.selected_device.as_deref() == Some(id);
+    let gone = before
+        .iter()
+        .filter(|(id, _)| !listed(after, id))
+        .map(|(id, name)| {
+            let text = if remapped(id) {
+                Text::NoticeDisconnectedPaused
+            } else {
+                Text::NoticeDisconnected
+            };
+            let message = locale.format(text, &[name]);
+            (id, message)
+        });
+    let came = after
+        .iter()
+        .filter(|(id, _)| !listed(before, id))
+        .map(|(id, name)| {
+            let text = if remapped(id) {
+                Text::NoticeReconnectedResumed
+            } else {
+                Text::NoticeReconnected
+            };
+            let message = locale.format(text, &[name]);
+            (id, message)
+        });
+    gone.chain(came)
+        .filter(|(id, _)| {
+            settings
+                .device_config(id)
+                .is_some_and(|config| !config.pristine)
+        })
+        .map(|(id, message)| (id.clone(), message))
+        .collect()
+}
+
+/// Claims the slot of `id` in `notices`, unless it was claimed less than
+/// `DEVICE_NOTICE_INTERVAL` before `now`.
+fn take_notice_slot(notices: &mut HashMap<String, Instant>, id: &str, now: Instant) -> bool {
+    let due = notices
+        .get(id)
+        .is_none_or(|last| now.duration_since(*last) >= DEVICE_NOTICE_INTERVAL);
+    if due {
+        notices.insert(id.to_string(), now);
+    }
+    due
+}
+
+#[tauri::command]
+fn start_button_capture(state: State<AppState>, device_id: String) -> Result<(), String> {
+    if !state.is_selected_device_available(&device_id) {
+        return Err(format!("device not connected: {}", device_id));
+    }
+    if shortcut_capture::is_active() {
+        return Err("a shortcut is being recorded; finish that first".to_string());
+    }
+    state.arm_capture(&device_id);
+    Ok(())
+}
+
+#[tauri::command]
+fn cancel_button_capture(state: State<AppState>) {
+    state.cancel_capture();
+}
+
+/// Payload of `shortcut-captured`.
+#[derive(Debug, Serialize, Clone)]
+struct ShortcutCaptured {
+    keycode: u16,
+    /// What the key types on the current layout, stored alongside the
+    /// keycode so the shortcut follows a change of layout.
+    key: Option<char>,
+    #[serde(with = "modifier_list")]
+    modifiers: ModifierMask,
+    /// As macOS menus show it, such as "⇧⌘K".
+    display: String,
+}
+
+/// Records the next key combo pressed anywhere and emits
+/// `shortcut-captured` with it. Escape, `cancel_shortcut_capture`, and
+/// `shortcut_capture::TIMEOUT` end it with `shortcut-capture-cancelled`,
+/// whose payload says which: "escape", "cancelled", or "timeout".
+#[tauri::command]
+fn start_shortcut_capture(app: tauri::AppHandle, state: State<AppState>) -> Result<(), String> {
+    if state.is_capture_armed() {
+        return Err("a button is being learned; finish that first".to_string());
+    }
+    shortcut_capture::start(move |outcome| {
+        let emitted = match outcome {
+            shortcut_capture::Outcome::Captured { keycode, modifiers } => {
+                let key = keyboard_layout::character(keycode);
+                let payload = ShortcutCaptured {
+                    keycode,
+                    key,
+                    modifiers,
+                    display: shortcut_label(Some(keycode), key, modifiers),
+                };
+                log::info!("shortcuts: recorded {}", payload.display);
+                app.emit("shortcut-captured", payload)
+            }
+            shortcut_capture::Outcome::Escaped => app.emit("shortcut-capture-cancelled", "escape"),
+            shortcut_capture::Outcome::Cancelled => {
+                app.emit("shortcut-capture-cancelled", "cancelled")
+            }
+            shortcut_capture::Outcome::TimedOut => {
+                app.emit("shortcut-capture-cancelled", "timeout")
+            }
+        };
+        if let Err(err) = emitted {
+            log::error!("shortcuts: failed to emit the recorded shortcut: {}", err);
+        }
+    })
+}
+
+#[tauri::command]
+fn cancel_shortcut_capture() {
+    shortcut_capture::cancel();
+}
+
+/// Presses and releases `button` of `device_id`, which must be the selected
+/// device, as if it had been clicked, running whatever it maps to for the
+/// frontmost app. Returns what ran.
+#[tauri::command]
+fn test_action(
+    app: tauri::AppHandle,
+    state: State<AppState>,
+    device_id: String,
+    button: String,
+    confirm: Option<bool>,
+) -> Result<ActionTested, String> {
+    let number = BUTTON_KEYS
+        .iter()
+        .position(|key| *key == button)
+        .ok_or_else(|| format!("unknown button {}", button))?;
+    let test = ActionTest::Button(number as i64);
+    test_with_engine(&app, &state, Some(&device_id), test, confirm)
+}
+
+/// What pressing `button` of `device_id` with `modifiers` held would fire
+/// while `app_bundle_id` is frontmost, or no app in particular, and which
+/// rule decided; nothing is pressed.
+#[tauri::command]
+fn resolve_mapping(
+    state: State<AppState>,
+    device_id: String,
+    button: String,
+    modifiers: Option<Vec<Modifier>>,
+    app_bundle_id: Option<String>,
+) -> Result<MappingResolution, String> {
+    let number = BUTTON_KEYS
+        .iter()
+        .position(|key| *key == button)
+        .ok_or_else(|| format!("unknown button {}", button))?;
+    let settings = state.snapshot_settings();
+    let fallback = state.fallback_device();
+    let query = MappingQuery {
+        device_id: &device_id,
+        button: number as i64,
+        held: modifier_mask(&modifiers.unwrap_or_default()),
+        app: app_bundle_id.as_deref(),
+        connected: state.is_selected_device_available(&device_id),
+        ignored_connected: state.is_ignored_device_connected(&settings),
+        button_count: state.button_count(&device_id),
+        fallback: fallback.as_deref(),
+    };
+    Ok(explain_mapping(&settings, &query))
+}
+
+/// Runs `action` as a mapped button would, without any button.
+#[tauri::command]
+fn test_action_payload(
+    app: tauri::AppHandle,
+    state: State<AppState>,
+    action: Action,
+    confirm: Option<bool>,
+) -> Result<ActionTested, String> {
+    test_with_engine(&app, &state, None, ActionTest::Action(action), confirm)
+}
+
+/// Middle-clicks at `x`, `y` the way a mapped click would, to check where
+/// clicks land across displays. The point is in global display coordinates,
+/// or in Cocoa's bottom-up ones when `flipped`. Returns where the click
+/// went: the point kept on a display.
+#[tauri::command]
+fn test_click_at(
+    app: tauri::AppHandle,
+    state: State<AppState>,
+    x: f64,
+    y: f64,
+    flipped: Option<bool>,
+) -> Result<ClickTarget, String> {
+    let displays = active_displays();
+    let Some(main) = displays.first() else {
+        return Err("clicks land at the pointer on this platform".to_string());
+    };
+    let point = if flipped.unwrap_or(false) {
+        global_from_cocoa((x, y), main.height)
+    } else {
+        (x, y)
+    };
+    let target = ClickTarget::resolve(point, &displays).ok_or("no display to click on")?;
+    let test = ActionTest::ClickAt {
+        x: target.x,
+        y: target.y,
+    };
+    test_with_engine(&app, &state, None, test, None)?;
+    Ok(target)
+}
+
+fn test_with_engine(
+    app: &tauri::AppHandle,
+    state: &AppState,
+    device_id: Option<&str>,
+    test: ActionTest,
+    confirm: Option<bool>,
+) -> Result<ActionTested, String> {
+    let tester = state
+        .action_tester
+        .get()
+        .ok_or("actions can't be tested with this session's remap engine")?;
+    let tested = plan_action_test(state, device_id, test, confirm.unwrap_or(false))?;
+    log::info!("mouse-remap: testing {}", tested.action);
+    tester(app, state, test);
+    Ok(tested)
+}
+
+#[tauri::command]
+fn get_frontmost_app(state: State<AppState>) -> Option<frontmost::AppInfo> {
+    state.last_external_app()
+}
+
+#[tauri::command]
+fn list_running_apps() -> Vec<frontmost::AppInfo> {
+    frontmost::running_apps()
+}
+
+#[tauri::command]
+fn list_installed_browsers() -> Vec<frontmost::AppInfo> {
+    frontmost::installed_browsers()
+}
+
+#[derive(Serialize, Clone)]
+struct EngineStateChanged {
+    state: EngineState,
+}
+
+fn report_engine_state(app: &tauri::AppHandle, state: &AppState, engine_state: EngineState) {
+    if state.engine_state() == engine_state {
+        return;
+    }
+    state.set_engine_state(engine_state);
+    log::info!("mouse-remap: engine {:?}", engine_state);
+    refresh_tray_icon(app);
+    let payload = EngineStateChanged {
+        state: engine_state,
+    };
+    if let Err(err) = app.emit("remap-engine-state", payload) {
+        log::error!("mouse-remap: failed to emit remap-engine-state: {}", err);
+    }
+}
+
+fn report_remap_error(
+    host: &impl RemapHost,
+    state: &AppState,
+    code: &'static str,
+    message: String,
+) {
+    log::error!("mouse-remap: {}: {}", code, message);
+    let Some(error) = state.record_remap_error(code, message) else {
+        return;
+    };
+    if let Err(err) = host.emit_event(None, "remap-error", error) {
+        log::error!("mouse-remap: failed to emit remap-error: {}", err);
+    }
+}
+
+/// Handles one event with `handle`, or returns `passed()` when it panics,
+/// so a bad event is let through instead of ending remapping. The panic
+/// hook has written the crash file by then.
+fn guard_event<T>(
+    host: &impl RemapHost,
+    state: &AppState,
+    handle: impl FnOnce() -> T,
+    passed: impl FnOnce() -> T,
+) -> T {
+    match std::panic::catch_unwind(std::panic::AssertUnwindSafe(handle)) {
+        Ok(result) => result,
+        Err(_) => {
+            report_remap_error(
+                host,
+                state,
+                "callback-panicked",
+                "handling an event panicked; it was passed through".to_string(),
+            );
+            passed()
+        }
+    }
+}
+
+/// Event tap callback timings, to spot a slow hot path, and how much
+/// synthetic output was posted and dropped.
+#[tauri::command]
+fn get_remap_diagnostics(state: State<AppState>) -> RemapDiagnostics {
+    state.remap_diagnostics()
+}
+
+/// Callback latency percentiles since the last reset, split by whether a
+/// mapping handled the event.
+#[tauri::command]
+fn get_latency_stats(state: State<AppState>) -> LatencyReport {
+    state.latency_report()
+}
+
+#[tauri::command]
+fn reset_latency_stats(state: State<AppState>) {
+    state.reset_latency_stats();
+    log::info!("diagnostics: latency stats reset");
+}
+
+/// Recent engine errors, oldest first, for a window opened after they were
+/// emitted.
+#[tauri::command]
+fn get_remap_errors(state: State<AppState>) -> Vec<RemapError> {
+    state.remap_errors()
+}
+
+#[tauri::command]
+fn get_remap_engine_state(state: State<AppState>) -> EngineState {
+    state.engine_state()
+}
+
+#[tauri::command]
+fn get_remap_engine_status(state: State<AppState>) -> RemapEngineStatus {
+    state.engine_status()
+}
+
+/// Replaces the running event tap with a fresh one, or retries now instead
+/// of at the next backoff step. The old tap's thread has exited before the
+/// new one starts.
+#[tauri::command]
+fn restart_remap_engine(state: State<AppState>) {
+    log::info!("mouse-remap: restart requested");
+    state.request_engine_restart();
+}
+
+#[tauri::command]
+fn get_permission_status() -> permissions::PermissionStatus {
+    permissions::status()
+}
+
+/// Opens the Privacy & Security pane where `kind` is granted.
+#[tauri::command]
+fn open_privacy_settings(kind: permissions::PermissionKind) -> Result<(), String> {
+    log::info!("permissions: opening the settings for {:?}", kind);
+    permissions::open_settings(kind)
+}
+
+#[tauri::command]
+fn request_permission(kind: permissions::PermissionKind) -> Result<(), String> {
+    log::info!("permissions: requesting {:?}", kind);
+    permissions::request(kind)
+}
+
+/// What `get_action_capabilities` returns.
+#[derive(Debug, Serialize, Clone, PartialEq)]
+struct ActionCapabilities {
+    swipes: SwipeStrategy,
+    /// Names of the swipe actions by id, with the shortcut they press when
+    /// they can't be gestures.
+    swipe_labels: BTreeMap<String, String>,
+}
+
+fn action_capabilities(swipes: SwipeStrategy) -> ActionCapabilities {
+    let swipe_labels = PLAIN_ACTIONS
+        .into_iter()
+        .filter(|action| action.swipe().is_some())
+        .map(|action| {
+            let id = action.id();
+            let keys = SWIPE_KEYS.iter().find(|(swipe, _)| *swipe == id);
+            let label = match (swipes, keys) {
+                (SwipeStrategy::Keys, Some((_, keys))) => format!("{} ({})", action.name(), keys),
+                _ => action.name().to_string(),
+            };
+            (id, label)
+        })
+        .collect();
+    ActionCapabilities {
+        swipes,
+        swipe_labels,
+    }
+}
+
+/// How the actions that differ by system work on this one, so they can be
+/// labelled for what they'll do.
+#[tauri::command]
+fn get_action_capabilities() -> ActionCapabilities {
+    action_capabilities(swipe_strategy())
+}
+
+/// What `get_app_info` returns.
+#[derive(Debug, Serialize, Clone, PartialEq)]
+struct AppDetails {
+    version: String,
+    /// Onboarding hasn't been finished yet.
+    first_run: bool,
+    /// Onboarding was finished by an earlier version, `last_seen_version`.
+    whats_new: bool,
+    last_seen_version: Option<String>,
+    permissions_granted: bool,
+}
+
+fn app_details(settings: &Settings, version: &str, permissions_granted: bool) -> AppDetails {
+    let first_run = !settings.onboarding_completed;
+    let whats_new = !first_run && settings.last_seen_version != version;
+    AppDetails {
+        version: version.to_string(),
+        first_run,
+        whats_new,
+        last_seen_version: Some(settings.last_seen_version.clone()).filter(|seen| !seen.is_empty()),
+        permissions_granted,
+    }
+}
+
+/// Whether to show onboarding or what's new. The settings file decides
+/// rather than the webview's storage, so clearing that changes nothing.
+#[tauri::command]
+fn get_app_info(app: tauri::AppHandle, state: State<AppState>) -> AppDetails {
+    app_details(
+        &state.snapshot_settings(),
+        &app.package_info().version.to_string(),
+        permissions::status().all_granted(),
+    )
+}
+
+/// Records onboarding, or this version's what's new, as seen.
+#[tauri::command]
+fn mark_onboarding_complete(
+    app: tauri::AppHandle,
+    state: State<AppState>,
+) -> Result<AppDetails, String> {
+    let version = app.package_info().version.to_string();
+    let settings = state.change_settings(&app, None, |settings| {
+        settings.onboarding_completed = true;
+        settings.last_seen_version = version.clone();
+        Ok(())
+    })?;
+    Ok(app_details(
+        &settings,
+        &version,
+        permissions::status().all_granted(),
+    ))
+}
+
+#[tauri::command]
+fn set_event_monitoring(state: State<AppState>, enabled: bool) {
+    state.set_event_monitoring(enabled);
+}
+
+/// Turns monitor mode on or off. While it's on, button events still
+/// resolve and show in the `button-event` stream with the action they
+/// would have fired, but every event reaches apps untouched and nothing is
+/// posted. On macOS the event tap is recreated listen-only, which needs no
+/// permission to post.
+#[tauri::command]
+fn set_monitor_mode(state: State<AppState>, enabled: bool) {
+    if state.set_monitor_only(enabled) {
+        log::info!(
+            "mouse-remap: monitor mode {}",
+            if enabled { "on" } else { "off" }
+        );
+    }
+}
+
+#[tauri::command]
+fn get_device_details(state: State<AppState>, device_id: String) -> Result<DeviceDetails, String> {
+    let filter = DeviceFilter::from(&state.snapshot_settings());
+    let details = state.hid.with(|api| {
+        let entries = hid_entries(api);
+        mouse_device_groups(&entries, filter, descriptor_probe(api))
+            .iter()
+            .find(|group| group.device.id == device_id)
+            .map(DeviceDetails::from)
+            .ok_or_else(|| format!("device not connected: {}", device_id))
+    })?;
+    Ok(DeviceDetails {
+        last_battery: state.last_battery(&device_id),
+        report_rate: state.report_rate(&device_id),
+        ..details
+    })
+}
+
+/// Frontmost-application tracking. The workspace posts activation
+/// notifications, so the input path only ever reads a cached bundle id.
+mod frontmost {
+    use super::AppState;
+    use serde::Serialize;
+
+    #[derive(Debug, Default, Serialize, Clone, PartialEq)]
+    pub struct AppInfo {
+        pub bundle_id: String,
+        pub name: String,
+        /// The app bundle on disk.
+        pub path: Option<String>,
+        /// The bundle's `.icns` file, for pickers.
+        pub icon_path: Option<String>,
+    }
+
+    #[cfg(target_os = "macos")]
+    use objc2_app_kit::{NSApplicationActivationPolicy, NSRunningApplication, NSWorkspace};
+
+    /// Seeds the cache and keeps it current: the frontmost app on every
+    /// activation, and its fullscreen state on activation and on space
+    /// changes (entering native fullscreen moves the app to its own space).
+    /// Must run on the main thread.
+    #[cfg(target_os = "macos")]
+    pub fn watch(handle: tauri::AppHandle, state: AppState) {
+        use block2::RcBlock;
+        use objc2::rc::Retained;
+        use objc2_app_kit::{
+            NSWorkspaceActiveSpaceDidChangeNotification, NSWorkspaceApplicationKey,
+            NSWorkspaceDidActivateApplicationNotification,
+        };
+        use objc2_foundation::{NSNotification, NSNotificationName};
+        use std::ptr::NonNull;
+        use std::rc::Rc;
+
+        let own_bundle_id = NSRunningApplication::currentApplication()
+            .bundleIdentifier()
+            .map(|id| id.to_string());
+        let workspace = NSWorkspace::sharedWorkspace();
+        // `activated` is false for the initial seed and for space changes,
+        // which must not trigger profile rules.
+        let update = Rc::new(
+            move |app: Option<Retained<NSRunningApplication>>, activated: bool| {
+                let fullscreen = app
+                    .as_ref()
+                    .is_some_and(|app| covers_display(app.processIdentifier()));
+                let app = app.and_then(|app| app_info(&app));
+                let is_self = app.as_ref().map(|app| &app.bundle_id) == own_bundle_id.as_ref();
+                let bundle_id = app.as_ref().map(|app| app.bundle_id.clone());
+                let was_paused = state.is_game_mode_paused();
+                state.set_frontmost_app(app, is_self);
+                state.set_frontmost_fullscreen(fullscreen);
+                let paused = state.is_game_mode_paused();
+                if paused != was_paused {
+                    super::announce_game_mode(&handle, paused);
+                }
+                if let Some(bundle_id) = bundle_id.filter(|_| activated && !is_self) {
+                    super::apply_profile_rules(&handle, &bundle_id);
+                }
+            },
+        );
+        update(workspace.frontmostApplication(), false);
+
+        let observe = |name: &NSNotificationName,
+                       block: &RcBlock<dyn Fn(NonNull<NSNotification>)>| {
+            let observer = unsafe {
+                workspace
+                    .notificationCenter()
+                    .addObserverForName_object_queue_usingBlock(Some(name), None, None, block)
+            };
+            // Observed for the lifetime of the process.
+            std::mem::forget(observer);
+        };
+
+        let on_activate = Rc::clone(&update);
+        observe(
+            unsafe { NSWorkspaceDidActivateApplicationNotification },
+            &RcBlock::new(move |notification: NonNull<NSNotification>| {
+                let notification = unsafe { notification.as_ref() };
+                let key = unsafe { NSWorkspaceApplicationKey };
+                let app = notification
+                    .userInfo()
+                    .and_then(|info| info.objectForKey(key))
+                    .and_then(|object| object.downcast::<NSRunningApplication>().ok());
+                on_activate(app, true);
+            }),
+        );
+        observe(
+            unsafe { NSWorkspaceActiveSpaceDidChangeNotification },
+            &RcBlock::new(move |_: NonNull<NSNotification>| {
+                update(NSWorkspace::sharedWorkspace().frontmostApplication(), false);
+            }),
+        );
+    }
+
+    #[cfg(not(target_os = "macos"))]
+    pub fn watch(_handle: tauri::AppHandle, _state: AppState) {}
+
+    /// Whether `pid` has an on-screen window exactly covering a display:
+    /// native fullscreen, a captured display, or borderless fullscreen.
+    #[cfg(target_os = "macos")]
+    fn covers_display(pid: i32) -> bool {
+        use core_foundation::base::{CFType, TCFType};
+        use core_foundation::dictionary::{CFDictionary, CFDictionaryRef};
+        use core_foundation::number::CFNumber;
+        use core_foundation::string::CFString;
+        use core_graphics::display::{CGDisplay, CGRect};
+        use core_graphics::window::{
+            copy_window_info, kCGNullWindowID, kCGWindowBounds, kCGWindowLayer,
+            kCGWindowListExcludeDesktopElements, kCGWindowListOptionOnScreenOnly,
+            kCGWindowOwnerPID,
+        };
+
+        let displays: Vec<CGRect> = CGDisplay::active_displays()
+            .unwrap_or_default()
+            .into_iter()
+            .map(|id| CGDisplay::new(id).bounds())
+            .collect();
+        let Some(windows) = copy_window_info(
+            kCGWindowListOptionOnScreenOnly | kCGWindowListExcludeDesktopElements,
+            kCGNullWindowID,
+        ) else {
+            return false;
+        };
+        let (pid_key, layer_key, bounds_key) = unsafe {
+            (
+                CFString::wrap_under_get_rule(kCGWindowOwnerPID),
+                CFString::wrap_under_get_rule(kCGWindowLayer),
+                CFString::wrap_under_get_rule(kCGWindowBounds),
+            )
+        };
+        windows.iter().any(|window| {
+            let window: CFDictionary<CFString, CFType> =
+                unsafe { CFDictionary::wrap_under_get_rule(*window as CFDictionaryRef) };
+            let number = |key: &CFString| {
+                window
+                    .find(key)
+                    .and_then(|value| value.downcast::<CFNumber>())
+                    .and_then(|value| value.to_i64())
+            };
+            // Negative layers are desktop-level windows.
+            if number(&pid_key) != Some(pid.into())
+                || number(&layer_key).is_none_or(|layer| layer < 0)
+            {
+                return false;
+            }
+            let Some(bounds) = window
+                .find(&bounds_key)
+                .and_then(|value| value.downcast::<CFDictionary>())
+                .and_then(|bounds| CGRect::from_dict_representation(&bounds))
+            else {
+                return false;
+            };
+            displays.iter().any(|display| {
+                display.origin.x == bounds.origin.x
+                    && display.origin.y == bounds.origin.y
+                    && display.size.width == bounds.size.width
+                    && display.size.height == bounds.size.height
+            })
+        })
+    }
+
+    /// Regular (Dock-visible) applications that are currently running.
+    #[cfg(target_os = "macos")]
+    pub fn running_apps() -> Vec<AppInfo> {
+        let mut apps: Vec<AppInfo> = NSWorkspace::sharedWorkspace()
+            .runningApplications()
+            .iter()
+            .filter(|app| app.activationPolicy() == NSApplicationActivationPolicy::Regular)
+            .filter_map(|app| app_info(&app))
+            .collect();
+        apps.sort_by_key(|app| app.name.to_lowercase());
+        apps
+    }
+
+    #[cfg(not(target_os = "macos"))]
+    pub fn running_apps() -> Vec<AppInfo> {
+        Vec::new()
+    }
+
+    /// Applications that open web links, the default one included. Empty
+    /// before macOS 12, which can't list them.
+    #[cfg(target_os = "macos")]
+    pub fn installed_browsers() -> Vec<AppInfo> {
+        use objc2::runtime::NSObjectProtocol;
+        use objc2::sel;
+        use objc2_foundation::{NSBundle, NSString, NSURL};
+
+        let workspace = NSWorkspace::sharedWorkspace();
+        let Some(web) = NSURL::URLWithString(&NSString::from_str("https://example.com")) else {
+            return Vec::new();
+        };
+        if !workspace.respondsToSelector(sel!(URLsForApplicationsToOpenURL:)) {
+            return Vec::new();
+        }
+        let mut apps: Vec<AppInfo> = workspace
+            .URLsForApplicationsToOpenURL(&web)
+            .iter()
+            .filter_map(|url| {
+                let bundle = NSBundle::bundleWithURL(&url)?;
+                let bundle_id = bundle.bundleIdentifier()?.to_string();
+                let name = bundle_string(&bundle, "CFBundleDisplayName")
+                    .or_else(|| bundle_string(&bundle, "CFBundleName"))
+                    .unwrap_or_else(|| bundle_id.clone());
+                Some(AppInfo {
+                    bundle_id,
+                    name,
+                    path: url.path().map(|path| path.to_string()),
+                    icon_path: icon_path(&bundle),
+                })
+            })
+            .collect();
+        // The same browser can be installed twice, say in ~/Applications.
+        apps.sort_by_key(|app| app.name.to_lowercase());
+        apps.dedup_by(|a, b| a.bundle_id == b.bundle_id);
+        apps
+    }
+
+    #[cfg(not(target_os = "macos"))]
+    pub fn installed_browsers() -> Vec<AppInfo> {
+        Vec::new()
+    }
+
+    #[cfg(target_os = "macos")]
+    fn app_info(app: &NSRunningApplication) -> Option<AppInfo> {
+        use objc2_foundation::NSBundle;
+
+        let bundle_id = app.bundleIdentifier()?.to_string();
+        let name = app
+            .localizedName()
+            .map(|name| name.to_string())
+            .unwrap_or_else(|| bundle_id.clone());
+        let url = app.bundleURL();
+        let icon_path = url
+            .as_deref()
+            .and_then(NSBundle::bundleWithURL)
+            .and_then(|bundle| icon_path(&bundle));
+        Some(AppInfo {
+            bundle_id,
+            name,
+            path: url.and_then(|url| url.path()).map(|path| path.to_string()),
+            icon_path,
+        })
+    }
+
+    #[cfg(target_os = "macos")]
+    fn bundle_string(bundle: &objc2_foundation::NSBundle, key: &str) -> Option<String> {
+        use objc2_foundation::NSString;
+
+        let value = bundle.objectForInfoDictionaryKey(&NSString::from_str(key))?;
+        Some(value.downcast_ref::<NSString>()?.to_string())
+    }
+
+    /// The icon `CFBundleIconFile` names, which may leave off `.icns`.
+    #[cfg(target_os = "macos")]
+    fn icon_path(bundle: &objc2_foundation::NSBundle) -> Option<String> {
+        use objc2_foundation::NSString;
+
+        let file = bundle_string(bundle, "CFBundleIconFile")?;
+        let name = NSString::from_str(file.trim_end_matches(".icns"));
+        let path = bundle.pathForResource_ofType(Some(&name), Some(&NSString::from_str("icns")))?;
+        Some(path.to_string())
+    }
+}
+
+/// Sounds and trackpad taps confirming a mapping fired, played on a
+/// thread of their own so the event path only sends.
+mod feedback {
+    use super::Feedback;
+    use std::sync::mpsc::Sender;
+
+    /// System sound `Feedback::Sound` plays.
+    #[cfg(target_os = "macos")]
+    const SOUND: &str = "Tink";
+
+    /// Starts the feedback thread, which runs until the sender is dropped.
+    pub fn start() -> Sender<Feedback> {
+        let (sender, receiver) = std::sync::mpsc::channel();
+        std::thread::spawn(move || {
+            for feedback in receiver {
+                play(feedback);
+            }
+        });
+        sender
+    }
+
+    /// Plays `feedback` without waiting for it to finish. A sound that is
+    /// still playing isn't started over, and trackpads without an actuator
+    /// stay still.
+    #[cfg(target_os = "macos")]
+    fn play(feedback: Feedback) {
+        use objc2_app_kit::{
+            NSHapticFeedbackManager, NSHapticFeedbackPattern, NSHapticFeedbackPerformanceTime,
+            NSHapticFeedbackPerformer, NSSound,
+        };
+        use objc2_foundation::NSString;
+
+        objc2::rc::autoreleasepool(|_| match feedback {
+            Feedback::None => {}
+            Feedback::Sound => match NSSound::soundNamed(&NSString::from_str(SOUND)) {
+                Some(sound) => {
+                    sound.play();
+                }
+                None => log::warn!("feedback: no system sound named {}", SOUND),
+            },
+            Feedback::Haptic => NSHapticFeedbackManager::defaultPerformer()
+                .performFeedbackPattern_performanceTime(
+                    NSHapticFeedbackPattern::Generic,
+                    NSHapticFeedbackPerformanceTime::Now,
+                ),
+        });
+    }
+
+    #[cfg(not(target_os = "macos"))]
+    fn play(feedback: Feedback) {
+        if feedback != Feedback::None {
+            log::debug!("feedback: {:?} is only played on macOS", feedback);
+        }
+    }
+}
+
+/// Secure Keyboard Entry, which password fields and some terminals turn on
+/// and which drops or garbles posted keystrokes while it lasts. Clicks get
+/// through.
+mod secure_input {
+    use super::SecureInputHolder;
+
+    #[cfg(target_os = "macos")]
+    #[link(name = "Carbon", kind = "framework")]
+    extern "C" {
+        fn IsSecureEventInputEnabled() -> u8;
+    }
+
+    #[cfg(target_os = "macos")]
+    #[link(name = "CoreGraphics", kind = "framework")]
+    extern "C" {
+        fn CGSessionCopyCurrentDictionary() -> core_foundation::dictionary::CFDictionaryRef;
+    }
+
+    /// Whether any process has secure input on. Cheap enough for the event
+    /// path.
+    #[cfg(target_os = "macos")]
+    pub fn is_enabled() -> bool {
+        unsafe { IsSecureEventInputEnabled() != 0 }
+    }
+
+    #[cfg(not(target_os = "macos"))]
+    pub fn is_enabled() -> bool {
+        false
+    }
+
+    /// The process holding secure input, from the login session's
+    /// `kCGSSessionSecureInputPID`. That key isn't documented, so either
+    /// field may be missing.
+    #[cfg(target_os = "macos")]
+    pub fn holder() -> SecureInputHolder {
+        use core_foundation::base::{CFType, TCFType};
+        use core_foundation::dictionary::CFDictionary;
+        use core_foundation::number::CFNumber;
+        use core_foundation::string::CFString;
+        use objc2_app_kit::NSRunningApplication;
+
+        let session = unsafe { CGSessionCopyCurrentDictionary() };
+        let pid = (!session.is_null())
+            .then(|| {
+                let session: CFDictionary<CFString, CFType> =
+                    unsafe { CFDictionary::wrap_under_create_rule(session) };
+                session
+                    .find(CFString::from_static_string("kCGSSessionSecureInputPID"))
+                    .and_then(|value| value.downcast::<CFNumber>())
+                    .and_then(|value| value.to_i32())
+            })
+            .flatten();
+        let name = pid
+            .and_then(NSRunningApplication::runningApplicationWithProcessIdentifier)
+            .and_then(|app| app.localizedName())
+            .map(|name| name.to_string());
+        SecureInputHolder { pid, name }
+    }
+
+    #[cfg(not(target_os = "macos"))]
+    pub fn holder() -> SecureInputHolder {
+        SecureInputHolder {
+            pid: None,
+            name: None,
+        }
+    }
+}
+
+/// Do Not Disturb, which macOS 12 folded into Focus. Each release needs its
+/// own way in; `strategy` picks it once.
+mod focus {
+    use std::process::Command;
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+    pub enum Strategy {
+        /// macOS 12 and later: Focus has no public switch, so the Shortcuts
+        /// command line runs a shortcut the user made to toggle it.
+        Shortcuts,
+        /// macOS 10.15 and earlier: Notification Center reads Do Not Disturb
+        /// from its defaults as it starts.
+        NotificationCenterDefaults,
+    }
+
+    /// Defaults domain of Notification Center, under `-currentHost`.
+    const DOMAIN: &str = "com.apple.notificationcenterui";
+
+    /// The strategy for macOS `major`.`minor`. Big Sur neither reads those
+    /// defaults nor has Shortcuts, so it gets none.
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+    pub fn strategy_for(major: isize, minor: isize) -> Option<Strategy> {
+        match (major, minor) {
+            (12.., _) => Some(Strategy::Shortcuts),
+            (10, ..=15) => Some(Strategy::NotificationCenterDefaults),
+            _ => None,
+        }
+    }
+
+    /// This system's strategy, worked out on first use.
+    #[cfg(target_os = "macos")]
+    pub fn strategy() -> Option<Strategy> {
+        static STRATEGY: std::sync::OnceLock<Option<Strategy>> = std::sync::OnceLock::new();
+        *STRATEGY.get_or_init(|| {
+            let version = objc2_foundation::NSProcessInfo::processInfo().operatingSystemVersion();
+            strategy_for(version.majorVersion, version.minorVersion)
+        })
+    }
+
+    #[cfg(not(target_os = "macos"))]
+    pub fn strategy() -> Option<Strategy> {
+        None
+    }
+
+    /// The strategy, or why Toggle Focus can't run here.
+    pub fn check() -> Result<Strategy, String> {
+        strategy().ok_or_else(|| {
+            if cfg!(target_os = "macos") {
+                "Toggle Focus is unsupported on this macOS version".to_string()
+            } else {
+                "Toggle Focus only works on macOS".to_string()
+            }
+        })
+    }
+
+    /// Turns Do Not Disturb on or off, through the shortcut named
+    /// `shortcut` where that's the way. Returns what it did, for the log.
+    pub fn toggle(shortcut: &str) -> Result<String, String> {
+        match check()? {
+            Strategy::Shortcuts => {
+                run(Command::new("shortcuts").args(["run", shortcut]))?;
+                Ok(format!("ran the {} shortcut", shortcut))
+            }
+            Strategy::NotificationCenterDefaults => {
+                let read = defaults()
+                    .args(["read", DOMAIN, "doNotDisturb"])
+                    .output()
+                    .map_err(|err| format!("couldn't run defaults: {}", err))?;
+                let on = String::from_utf8_lossy(&read.stdout).trim() == "1";
+                let wanted = if on { "false" } else { "true" };
+                run(defaults().args(["write", DOMAIN, "doNotDisturb", "-boolean", wanted]))?;
+                run(Command::new("killall").arg("NotificationCenter"))?;
+                Ok(format!(
+                    "turned Do Not Disturb {}",
+                    if on { "off" } else { "on" }
+                ))
+            }
+        }
+    }
+
+    fn defaults() -> Command {
+        let mut command = Command::new("defaults");
+        command.arg("-currentHost");
+        command
+    }
+
+    fn run(command: &mut Command) -> Result<(), String> {
+        let program = command.get_program().to_string_lossy().into_owned();
+        let output = command
+            .output()
+            .map_err(|err| format!("couldn't run {}: {}", program, err))?;
+        if output.status.success() {
+            return Ok(());
+        }
+        Err(format!(
+            "{} failed: {}",
+            program,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ))
+    }
+}
+
+mod permissions {
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+    #[serde(rename_all = "snake_case")]
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+    pub enum PermissionState {
+        Granted,
+        Denied,
+        /// The user hasn't been asked yet.
+        NotDetermined,
+    }
+
+    /// Privacy permissions the remap engine depends on: Input Monitoring to
+    /// see mouse events, Accessibility to post and swallow them.
+    #[derive(Debug, Serialize, Clone, PartialEq)]
+    pub struct PermissionStatus {
+        pub input_monitoring: PermissionState,
+        pub accessibility: PermissionState,
+    }
+
+    impl PermissionStatus {
+        pub fn all_granted(&self) -> bool {
+            self.input_monitoring == PermissionState::Granted
+                && self.accessibility == PermissionState::Granted
+        }
+    }
+
+    #[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+    #[serde(rename_all = "snake_case")]
+    pub enum PermissionKind {
+        InputMonitoring,
+        Accessibility,
+    }
+
+    /// First macOS version with System Settings, whose Privacy & Security
+    /// panes have their own URL scheme.
+    const SYSTEM_SETTINGS_MAJOR: isize = 13;
+
+    /// URL of the Privacy & Security pane listing apps with `kind` on macOS
+    /// `major`: System Settings from Ventura on, System Preferences before.
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+    pub fn settings_url(kind: PermissionKind, major: isize) -> String {
+        let anchor = match kind {
+            PermissionKind::InputMonitoring => "Privacy_ListenEvent",
+            PermissionKind::Accessibility => "Privacy_Accessibility",
+        };
+        let pane = if major >= SYSTEM_SETTINGS_MAJOR {
+            "com.apple.settings.PrivacySecurity.extension"
+        } else {
+            "com.apple.preference.security"
+        };
+        format!("x-apple.systempreferences:{}?{}", pane, anchor)
+    }
+
+    /// Opens the pane where `kind` is granted, for when the system prompt
+    /// was already shown once.
+    #[cfg(target_os = "macos")]
+    pub fn open_settings(kind: PermissionKind) -> Result<(), String> {
+        let major = objc2_foundation::NSProcessInfo::processInfo()
+            .operatingSystemVersion()
+            .majorVersion;
+        std::process::Command::new("open")
+            .arg(settings_url(kind, major))
+            .spawn()
+            .map(|_| ())
+            .map_err(|err| err.to_string())
+    }
+
+    /// There's no settings pane; `request` says what to do instead.
+    #[cfg(not(target_os = "macos"))]
+    pub fn open_settings(kind: PermissionKind) -> Result<(), String> {
+        request(kind)?;
+        Err("there are no privacy settings to open".to_string())
+    }
+
+    #[cfg(target_os = "macos")]
+    mod ffi {
+        use core_foundation::dictionary::CFDictionaryRef;
+        use core_foundation::string::CFStringRef;
+
+        pub const IOHID_REQUEST_TYPE_LISTEN_EVENT: u32 = 1;
+        pub const IOHID_ACCESS_TYPE_GRANTED: u32 = 0;
+        pub const IOHID_ACCESS_TYPE_DENIED: u32 = 1;
+
+        #[link(name = "IOKit", kind = "framework")]
+        extern "C" {
+            pub fn IOHIDCheckAccess(request_type: u32) -> u32;
+            pub fn IOHIDRequestAccess(request_type: u32) -> bool;
+        }
+
+        #[link(name = "ApplicationServices", kind = "framework")]
+        extern "C" {
+            pub static kAXTrustedCheckOptionPrompt: CFStringRef;
+            pub fn AXIsProcessTrusted() -> bool;
+            pub fn AXIsProcessTrustedWithOptions(options: CFDictionaryRef) -> bool;
+        }
+    }
+
+    #[cfg(target_os = "macos")]
+    pub fn status() -> PermissionStatus {
+        let input_monitoring =
+            match unsafe { ffi::IOHIDCheckAccess(ffi::IOHID_REQUEST_TYPE_LISTEN_EVENT) } {
+                ffi::IOHID_ACCESS_TYPE_GRANTED => PermissionState::Granted,
+                ffi::IOHID_ACCESS_TYPE_DENIED => PermissionState::Denied,
+                _ => PermissionState::NotDetermined,
+            };
+        // Accessibility has no "not asked yet" state.
+        let accessibility = if unsafe { ffi::AXIsProcessTrusted() } {
+            PermissionState::Granted
+        } else {
+            PermissionState::Denied
+        };
+        PermissionStatus {
+            input_monitoring,
+            accessibility,
+        }
+    }
+
+    /// Shows the system prompt when the user hasn't been asked yet, and
+    /// opens the Privacy pane otherwise, since macOS never asks twice.
+    #[cfg(target_os = "macos")]
+    pub fn request(kind: PermissionKind) -> Result<(), String> {
+        use core_foundation::base::TCFType;
+        use core_foundation::boolean::CFBoolean;
+        use core_foundation::dictionary::CFDictionary;
+        use core_foundation::string::CFString;
+
+        match kind {
+            PermissionKind::InputMonitoring => {
+                if status().input_monitoring != PermissionState::NotDetermined {
+                    return open_settings(kind);
+                }
+                unsafe { ffi::IOHIDRequestAccess(ffi::IOHID_REQUEST_TYPE_LISTEN_EVENT) };
+            }
+            PermissionKind::Accessibility => {
+                let key =
+                    unsafe { CFString::wrap_under_get_rule(ffi::kAXTrustedCheckOptionPrompt) };
+                let options = CFDictionary::from_CFType_pairs(&[(key, CFBoolean::true_value())]);
+                unsafe { ffi::AXIsProcessTrustedWithOptions(options.as_concrete_TypeRef()) };
+            }
+        }
+        Ok(())
+    }
+
+    /// Outside X11 the engine reads the mouse's event node, standing in for
+    /// Input Monitoring, and posts through uinput, standing in for
+    /// Accessibility.
+    #[cfg(target_os = "linux")]
+    pub fn status() -> PermissionStatus {
+        let (readable, writable) = if super::non_x11_session().is_some() {
+            super::evdev::access()
+        } else {
+            (true, true)
+        };
+        let state = |granted| {
+            if granted {
+                PermissionState::Granted
+            } else {
+                PermissionState::Denied
+            }
+        };
+        PermissionStatus {
+            input_monitoring: state(readable),
+            accessibility: state(writable),
+        }
+    }
+
+    /// Device access can only be granted by the administrator, so this just
+    /// says how.
+    #[cfg(target_os = "linux")]
+    pub fn request(kind: PermissionKind) -> Result<(), String> {
+        Err(match kind {
+            PermissionKind::InputMonitoring => {
+                "add your user to the input group, then log in again".to_string()
+            }
+            PermissionKind::Accessibility => {
+                "add a udev rule giving your user access to /dev/uinput, then reload udev"
+                    .to_string()
+            }
+        })
+    }
+
+    #[cfg(not(any(target_os = "macos", target_os = "linux")))]
+    pub fn status() -> PermissionStatus {
+        PermissionStatus {
+            input_monitoring: PermissionState::Granted,
+            accessibility: PermissionState::Granted,
+        }
+    }
+
+    #[cfg(not(any(target_os = "macos", target_os = "linux")))]
+    pub fn request(_kind: PermissionKind) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+/// Reads LinearMouse's `linearmouse.json`. Its schemes pair a condition
+/// (device, application, display, ...) with settings; only button mappings
+/// with an Edit Mouse equivalent are converted, everything else is reported
+/// as skipped.
+mod linearmouse {
+    use super::{
+        button_key, vendor_product, Action, ActionValue, DeviceConfig, Modifier, ModifierMapping,
+        MouseDevice, ANY_DEVICE_ID,
+    };
+    use serde::Serialize;
+    use serde_json::Value;
+    use std::collections::HashMap;
+    use std::path::PathBuf;
+
+    /// What `import_linearmouse` did, for the settings window to show.
+    #[derive(Debug, Serialize, Clone, Default)]
+    pub struct ImportReport {
+        /// Device ids that got a config.
+        pub imported: Vec<String>,
+        /// One line per setting, scheme, or device left out, with why.
+        pub skipped: Vec<String>,
+    }
+
+    pub fn config_path() -> Option<PathBuf> {
+        let home = std::env::var_os("HOME")?;
+        Some(PathBuf::from(home).join(".config/linearmouse/linearmouse.json"))
+    }
+
+    /// Converts a parsed `linearmouse.json` into device configs keyed by
+    /// device id. A device matcher maps onto every connected device with its
+    /// vendor and product id; when none is connected the config is stored
+    /// under the legacy `vid:pid:noserial` key, which `reconcile_device_ids`
+    /// moves onto the device once it shows up. Schemes without a device
+    /// apply to every device.
+    pub fn convert(
+        config: &Value,
+        devices: &[MouseDevice],
+        report: &mut ImportReport,
+    ) -> HashMap<String, DeviceConfig> {
+        let mut configs: HashMap<String, DeviceConfig> = HashMap::new();
+        let schemes = config
+            .get("schemes")
+            .and_then(Value::as_array)
+            .map(Vec::as_slice)
+            .unwrap_or_default();
+        for (index, scheme) in schemes.iter().enumerate() {
+            let label = format!("scheme {}", index + 1);
+            if scheme.get("scrolling").is_some() {
+                report
+                    .skipped
+                    .push(format!("{}: scrolling settings aren't supported", label));
+            }
+            if scheme.get("pointer").is_some() {
+                report
+                    .skipped
+                    .push(format!("{}: pointer settings aren't supported", label));
+            }
+            let Some(buttons) = scheme.get("buttons") else {
+                continue;
+            };
+            let conditions = match scheme.get("if") {
+                None => vec![&Value::Null],
+                Some(Value::Array(conditions)) => conditions.iter().collect(),
+                Some(condition) => vec![condition],
+            };
+            for condition in conditions {
+                let Some((targets, app)) = targets(condition, devices, &label, report) else {
+                    continue;
+                };
+                for id in targets {
+                    let config = configs.entry(id).or_default();
+                    apply_buttons(config, buttons, app.as_deref(), &label, report);
+                    if let Some(name) = condition
+                        .pointer("/device/productName")
+                        .and_then(Value::as_str)
+                    {
+                        config.name = name.to_string();
+                    }
+                }
+            }
+        }
+        configs
+    }
+
+    /// Device ids and the application (bundle id) a scheme condition
+    /// applies to, or `None` when it has conditions Edit Mouse can't express.
+    fn targets(
+        condition: &Value,
+        devices: &[MouseDevice],
+        label: &str,
+        report: &mut ImportReport,
+    ) -> Option<(Vec<String>, Option<String>)> {
+        let app = condition
+            .get("app")
+            .and_then(Value::as_str)
+            .map(str::to_string);
+        if let Some(object) = condition.as_object() {
+            if let Some(key) = object
+                .keys()
+                .find(|key| !matches!(key.as_str(), "device" | "app"))
+            {
+                report.skipped.push(format!(
+                    "{}: \"{}\" conditions aren't supported",
+                    label, key
+                ));
+                return None;
+            }
+        }
+        let Some(device) = condition.get("device") else {
+            return Some((vec![ANY_DEVICE_ID.to_string()], app));
+        };
+        let (Some(vendor), Some(product)) = (
+            device.get("vendorID").and_then(parse_id),
+            device.get("productID").and_then(parse_id),
+        ) else {
+            report.skipped.push(format!(
+                "{}: devices are only matched by vendor and product id",
+                label
+            ));
+            return None;
+        };
+        let prefix = format!("{:04x}:{:04x}", vendor, product);
+        let connected: Vec<String> = devices
+            .iter()
+            .filter(|device| vendor_product(&device.id) == Some(prefix.as_str()))
+            .map(|device| device.id.clone())
+            .collect();
+        if connected.is_empty() {
+            return Some((vec![format!("{}:noserial", prefix)], app));
+        }
+        Some((connected, app))
+    }
+
+    /// LinearMouse writes ids as numbers or as `"0x046d"` strings.
+    fn parse_id(value: &Value) -> Option<u16> {
+        match value {
+            Value::Number(number) => number.as_u64().and_then(|id| u16::try_from(id).ok()),
+            Value::String(text) => {
+                let text = text.trim();
+                match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+                    Some(hex) => u16::from_str_radix(hex, 16).ok(),
+                    None => text.parse().ok(),
+                }
+            }
+            _ => None,
+        }
+    }
+
+    fn apply_buttons(
+        config: &mut DeviceConfig,
+        buttons: &Value,
+        app: Option<&str>,
+        label: &str,
+        report: &mut ImportReport,
+    ) {
+        let set = |config: &mut DeviceConfig, key: &str, action: Action| {
+            let value = ActionValue::Action(action);
+            match app {
+                Some(app) => {
+                    config
+                        .app_overrides
+                        .entry(app.to_string())
+                        .or_default()
+                        .insert(key.to_string(), value);
+                }
+                None => {
+                    config.buttons.insert(key.to_string(), value);
+                }
+            }
+        };
+        match buttons.get("universalBackForward") {
+            Some(Value::Bool(true)) => {
+                set(config, "button4", Action::Back);
+                set(config, "button5", Action::Forward);
+            }
+            Some(Value::String(mode)) => match mode.as_str() {
+                "both" => {
+                    set(config, "button4", Action::Back);
+                    set(config, "button5", Action::Forward);
+                }
+                "backOnly" => set(config, "button4", Action::Back),
+                "forwardOnly" => set(config, "button5", Action::Forward),
+                _ => {}
+            },
+            _ => {}
+        }
+        if buttons.get("switchPrimaryButtonAndSecondaryButtons") == Some(&Value::Bool(true)) {
+            report.skipped.push(format!(
+                "{}: swapping the primary and secondary buttons isn't supported",
+                label
+            ));
+        }
+        let mappings = buttons
+            .get("mappings")
+            .and_then(Value::as_array)
+            .map(Vec::as_slice)
+            .unwrap_or_default();
+        for mapping in mappings {
+            let Some(button) = mapping.get("button").and_then(Value::as_i64) else {
+                report.skipped.push(format!(
+                    "{}: only mouse button mappings are supported, not scroll mappings",
+                    label
+                ));
+                continue;
+            };
+            let Some(key) = button_key(button) else {
+                report.skipped.push(format!(
+                    "{}: button {} has no Edit Mouse equivalent",
+                    label, button
+                ));
+                continue;
+            };
+            let Some(action) = mapping.get("action").and_then(action) else {
+                report.skipped.push(format!(
+                    "{}: the action of {} has no Edit Mouse equivalent",
+                    label, key
+                ));
+                continue;
+            };
+            let modifiers: Vec<Modifier> = [
+                ("shift", Modifier::Shift),
+                ("control", Modifier::Control),
+                ("option", Modifier::Option),
+                ("command", Modifier::Command),
+            ]
+            .into_iter()
+            .filter(|(name, _)| mapping.get(*name) == Some(&Value::Bool(true)))
+            .map(|(_, modifier)| modifier)
+            .collect();
+            if modifiers.is_empty() {
+                set(config, key, action);
+            } else if app.is_some() {
+                report.skipped.push(format!(
+                    "{}: modifier mappings of {} can't be limited to an application",
+                    label, key
+                ));
+            } else {
+                let layers = config.modifier_mappings.entry(key.to_string()).or_default();
+                layers.retain(|layer| layer.modifiers != modifiers);
+                layers.push(ModifierMapping {
+                    modifiers,
+                    action: ActionValue::Action(action),
+                });
+            }
+        }
+    }
+
+    /// Edit Mouse equivalent of a LinearMouse button action.
+    fn action(action: &Value) -> Option<Action> {
+        match action.as_str()? {
+            "auto" => Some(Action::Default),
+            "none" => Some(Action::Disabled),
+            "mouse.button.back" => Some(Action::Back),
+            "mouse.button.forward" => Some(Action::Forward),
+            "mouse.button.middle" => Some(Action::MiddleClick),
+            _ => None,
+        }
+    }
+}
+
+/// Button maps for popular mice, compiled in so a new user can start from
+/// sensible mappings in one click.
+mod presets {
+    use super::{
+        default_buttons, hid_vendor_product, Action, ActionValue, DeviceConfig, IssueSeverity,
+        Modifier, BUTTON_KEYS,
+    };
+    use serde::{Deserialize, Serialize};
+    use std::collections::HashMap;
+
+    /// macOS virtual keycode of the Up Arrow; Control with it opens Mission
+    /// Control.
+    const KEYCODE_UP_ARROW: u16 = 0x7e;
+
+    pub struct Preset {
+        pub id: &'static str,
+        pub name: &'static str,
+        /// Vendor and product ids of every model and connection it fits.
+        models: &'static [(u16, u16)],
+        buttons: &'static [(&'static str, Action)],
+        /// Replaces the device's DPI stops unless empty.
+        dpi_stops: &'static [u32],
+        extended_buttons: bool,
+    }
+
+    pub const PRESETS: &[Preset] = &[
+        Preset {
+            id: "mx-master-3",
+            name: "Logitech MX Master 3",
+            models: &[
+                (0x046d, 0x4082),
+                (0x046d, 0xb023),
+                (0x046d, 0xb028),
+                (0x046d, 0xb034),
+            ],
+            buttons: &[
+                ("button4", Action::Back),
+                ("button5", Action::Forward),
+                (
+                    "button6",
+                    Action::Shortcut {
+                        keycode: Some(KEYCODE_UP_ARROW),
+                        key: None,
+                        modifiers: 1 << Modifier::Control as u8,
+                    },
+                ),
+            ],
+            dpi_stops: &[],
+            // The thumb button only shows up in the HID reports.
+            extended_buttons: true,
+        },
+        Preset {
+            id: "g502",
+            name: "Logitech G502",
+            models: &[
+                (0x046d, 0xc07d),
+                (0x046d, 0xc08b),
+                (0x046d, 0xc08d),
+                (0x046d, 0xc332),
+            ],
+            buttons: &[
+                ("button4", Action::Back),
+                ("button5", Action::Forward),
+                ("button6", Action::CycleDpi),
+            ],
+            dpi_stops: &[400, 800, 1600, 3200],
+            extended_buttons: false,
+        },
+    ];
+
+    /// A preset as `list_presets` returns it.
+    #[derive(Debug, Serialize, Clone)]
+    pub struct PresetInfo {
+        pub id: &'static str,
+        pub name: &'static str,
+        /// `vendor:product` in hex, as device ids start.
+        pub models: Vec<String>,
+        pub buttons: HashMap<String, ActionValue>,
+        pub dpi_stops: Vec<u32>,
+    }
+
+    /// `vendor:product` in hex.
+    fn model_id((vendor, product): (u16, u16)) -> String {
+        format!("{:04x}:{:04x}", vendor, product)
+    }
+
+    pub fn find(id: &str) -> Option<&'static Preset> {
+        PRESETS.iter().find(|preset| preset.id == id)
+    }
+
+    /// The preset made for the model of `device_id`, if there is one.
+    pub fn matching(device_id: &str) -> Option<&'static Preset> {
+        let model = hid_vendor_product(device_id)?;
+        PRESETS.iter().find(|preset| preset.models.contains(&model))
+    }
+
+    impl Preset {
+        pub fn info(&self) -> PresetInfo {
+            PresetInfo {
+                id: self.id,
+                name: self.name,
+                models: self.models.iter().copied().map(model_id).collect(),
+                buttons: self.mappings(),
+                dpi_stops: self.dpi_stops.to_vec(),
+            }
+        }
+
+        fn mappings(&self) -> HashMap<String, ActionValue> {
+            self.buttons
+                .iter()
+                .map(|(key, action)| (key.to_string(), ActionValue::Action(*action)))
+                .collect()
+        }
+
+        /// `config` with its mappings replaced by this preset's, keeping the
+        /// name, the sightings, and, when the preset has none, the DPI stops.
+        pub fn apply(&self, config: DeviceConfig, button_count: u8) -> DeviceConfig {
+            let mut buttons = default_buttons(button_count);
+            buttons.extend(self.mappings());
+            DeviceConfig {
+                name: config.name,
+                buttons,
+                extended_buttons: config.extended_buttons || self.extended_buttons,
+                dpi_stops: if self.dpi_stops.is_empty() {
+                    config.dpi_stops
+                } else {
+                    self.dpi_stops.to_vec()
+                },
+                first_seen: config.first_seen,
+                last_seen: config.last_seen,
+                ..DeviceConfig::default()
+            }
+        }
+    }
+
+    /// Version of the preset file format this build writes.
+    pub const FILE_VERSION: u32 = 1;
+
+    /// One device's mappings as `export_device_preset` writes them, to share
+    /// and import onto another device.
+    #[derive(Debug, Serialize, Deserialize, Clone)]
+    pub struct PresetFile {
+        #[serde(default)]
+        pub version: u32,
+        pub name: String,
+        /// Model of the device it was exported from, when known.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub model: Option<String>,
+        pub buttons: HashMap<String, ActionValue>,
+        #[serde(default)]
+        pub dpi_stops: Vec<u32>,
+        #[serde(default)]
+        pub extended_buttons: bool,
+    }
+
+    impl PresetFile {
+        pub fn export(config: &DeviceConfig, device_id: &str) -> Self {
+            Self {
+                version: FILE_VERSION,
+                name: config.display_name(device_id).to_string(),
+                model: hid_vendor_product(device_id).map(model_id),
+                buttons: config.buttons.clone(),
+                dpi_stops: config.dpi_stops.clone(),
+                extended_buttons: config.extended_buttons,
+            }
+        }
+
+        pub fn parse(data: &str) -> Result<Self, String> {
+            let file: Self = serde_json::from_str(data).map_err(|err| err.to_string())?;
+            if file.version > FILE_VERSION {
+                return Err(format!(
+                    "preset was exported by a newer version of Edit Mouse (format {})",
+                    file.version
+                ));
+            }
+            let config = DeviceConfig {
+                buttons: file.buttons.clone(),
+                dpi_stops: file.dpi_stops.clone(),
+                ..DeviceConfig::default()
+            };
+            match config
+                .issues()
+                .into_iter()
+                .find(|issue| issue.severity == IssueSeverity::Error)
+            {
+                Some(issue) => Err(issue.to_string()),
+                None => Ok(file),
+            }
+        }
+
+        /// `config` with its mappings replaced by this file's, like
+        /// `Preset::apply`, and the keys of the buttons a device with
+        /// `button_count` buttons doesn't have, which are left out.
+        pub fn apply(&self, config: DeviceConfig, button_count: u8) -> (DeviceConfig, Vec<String>) {
+            let mut buttons = default_buttons(button_count);
+            let mut skipped = Vec::new();
+            for (button, key) in (0..).zip(BUTTON_KEYS) {
+                if let Some(value) = self.buttons.get(*key) {
+                    if button < i64::from(button_count) {
+                        buttons.insert(key.to_string(), value.clone());
+                    } else {
+                        skipped.push(key.to_string());
+                    }
+                }
+            }
+            let config = DeviceConfig {
+                name: config.name,
+                buttons,
+                extended_buttons: config.extended_buttons || self.extended_buttons,
+                dpi_stops: if self.dpi_stops.is_empty() {
+                    config.dpi_stops
+                } else {
+                    self.dpi_stops.clone()
+                },
+                first_seen: config.first_seen,
+                last_seen: config.last_seen,
+                ..DeviceConfig::default()
+            };
+            (config, skipped)
+        }
+    }
+}
+
+/// Log lines go through a channel to a writer thread, which appends them to
+/// `FILE` in the log directory and echoes them to the console, so logging
+/// never blocks the caller, not even the event tap callback. The file is
+/// rotated to `edit-mouse.1.log` and on once it passes `MAX_BYTES`.
+mod logging {
+    use log::{Level, LevelFilter, Log, Metadata, Record};
+    use std::fs;
+    use std::io::Write;
+    use std::path::{Path, PathBuf};
+    use std::sync::mpsc::{self, Receiver, Sender};
+    use std::sync::{Mutex, OnceLock};
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    pub const FILE: &str = "edit-mouse.log";
+    const MAX_BYTES: u64 = 1024 * 1024;
+    /// Rotated files kept besides the current one.
+    const KEEP: usize = 2;
+    /// Where panics are written, until the next launch reports them.
+    pub const CRASH_FILE: &str = "crash.log";
+    /// Reported crash files kept, as `crash-<time>.log`.
+    const KEEP_CRASHES: usize = 5;
+
+    /// The folder `start` writes to, for the panic hook.
+    static DIR: OnceLock<PathBuf> = OnceLock::new();
+
+    /// A formatted line and its level.
+    type Line = (Level, String);
+
+    struct Logger {
+        sender: Sender<Line>,
+    }
+
+    impl Log for Logger {
+        fn enabled(&self, metadata: &Metadata) -> bool {
+            metadata.level() <= log::max_level()
+        }
+
+        fn log(&self, record: &Record) {
+            if !self.enabled(record.metadata()) {
+                return;
+            }
+            let line = format!(
+                "{} {:<5} {}",
+                timestamp(SystemTime::now()),
+                record.level(),
+                record.args()
+            );
+            let _ = self.sender.send((record.level(), line));
+        }
+
+        fn flush(&self) {}
+    }
+
+    /// Lines logged before `start`, waiting for the writer thread.
+    static PENDING: OnceLock<Mutex<Option<Receiver<Line>>>> = OnceLock::new();
+
+    /// Installs the logger. Lines are kept until `start` says where to
+    /// write them.
+    pub fn init() {
+        let (sender, receiver) = mpsc::channel();
+        if log::set_boxed_logger(Box::new(Logger { sender })).is_err() {
+            return;
+        }
+        log::set_max_level(LevelFilter::Info);
+        let _ = PENDING.set(Mutex::new(Some(receiver)));
+        install_panic_hook();
+    }
+
+    /// Appends each panic's message and backtrace to `CRASH_FILE`, once
+    /// `start` has said where, before the default hook prints it.
+    fn install_panic_hook() {
+        let default = std::panic::take_hook();
+        std::panic::set_hook(Box::new(move |info| {
+            let payload = info.payload();
+            let message = payload
+                .downcast_ref::<&str>()
+                .copied()
+                .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+                .unwrap_or("no message");
+            let thread = std::thread::current();
+            let thread = thread.name().unwrap_or("unnamed");
+            let location = info
+                .location()
+                .map_or_else(|| "an unknown location".to_string(), ToString::to_string);
+            log::error!("panic: thread {} at {}: {}", thread, location, message);
+            if let Some(dir) = DIR.get() {
+                let report = format!(
+                    "{} thread {} panicked at {}: {}\n{}\n",
+                    timestamp(SystemTime::now()),
+                    thread,
+                    location,
+                    message,
+                    std::backtrace::Backtrace::force_capture()
+                );
+                let _ = fs::create_dir_all(dir).and_then(|()| {
+                    fs::OpenOptions::new()
+                        .create(true)
+                        .append(true)
+                        .open(dir.join(CRASH_FILE))?
+                        .write_all(report.as_bytes())
+                });
+            }
+            default(info);
+        }));
+    }
+
+    /// The crash report an earlier run left in `dir`, if any. It's renamed
+    /// so it's only reported once, and the oldest renamed reports past
+    /// `KEEP_CRASHES` are removed.
+    pub fn take_crash(dir: &Path) -> Option<String> {
+        let path = dir.join(CRASH_FILE);
+        let report = fs::read_to_string(&path).ok()?;
+        let stamp = timestamp(SystemTime::now())[..19].replace([' ', ':'], "-");
+        if fs::rename(&path, dir.join(format!("crash-{}.log", stamp))).is_err() {
+            let _ = fs::remove_file(&path);
+        }
+        let mut reported: Vec<PathBuf> = fs::read_dir(dir)
+            .into_iter()
+            .flatten()
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|path| {
+                path.file_name()
+                    .and_then(|name| name.to_str())
+                    .is_some_and(|name| name.starts_with("crash-"))
+            })
+            .collect();
+        reported.sort();
+        let excess = reported.len().saturating_sub(KEEP_CRASHES);
+        for old in &reported[..excess] {
+            let _ = fs::remove_file(old);
+        }
+        Some(report)
+    }
+
+    /// Starts writing to `dir`. Lines still reach the console when the file
+    /// can't be opened.
+    pub fn start(dir: PathBuf) {
+        let _ = DIR.set(dir.clone());
+        let Some(receiver) = PENDING
+            .get()
+            .and_then(|pending| pending.lock().ok()?.take())
+        else {
+            return;
+        };
+        std::thread::spawn(move || {
+            let open = || {
+                fs::create_dir_all(&dir)?;
+                fs::OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(dir.join(FILE))
+            };
+            let mut file = match open() {
+                Ok(file) => Some(file),
+                Err(err) => {
+                    eprintln!(
+                        "logging: failed to open {}: {}",
+                        dir.join(FILE).display(),
+                        err
+                    );
+                    None
+                }
+            };
+            let mut size = file
+                .as_ref()
+                .and_then(|file| file.metadata().ok())
+                .map_or(0, |metadata| metadata.len());
+            for (level, line) in receiver {
+                if level <= Level::Warn {
+                    eprintln!("{}", line);
+                } else {
+                    println!("{}", line);
+                }
+                let Some(current) = file.as_mut() else {
+                    continue;
+                };
+                if writeln!(current, "{}", line).is_ok() {
+                    size += line.len() as u64 + 1;
+                }
+                if size >= MAX_BYTES {
+                    drop(file.take());
+                    rotate(&dir);
+                    file = open().ok();
+                    size = 0;
+                }
+            }
+        });
+    }
+
+    /// The `n`th rotated file, 0 being the current one.
+    fn rotated(dir: &Path, n: usize) -> PathBuf {
+        if n == 0 {
+            dir.join(FILE)
+        } else {
+            dir.join(format!("edit-mouse.{}.log", n))
+        }
+    }
+
+    /// Shifts every file one along, dropping the oldest past `KEEP`.
+    pub fn rotate(dir: &Path) {
+        let _ = fs::remove_file(rotated(dir, KEEP));
+        for n in (0..KEEP).rev() {
+            let _ = fs::rename(rotated(dir, n), rotated(dir, n + 1));
+        }
+    }
+
+    /// The last `lines` lines logged to `dir`, oldest first, reading into
+    /// the rotated files when the current one is shorter.
+    pub fn recent(dir: &Path, lines: usize) -> Vec<String> {
+        let mut recent = Vec::new();
+        for n in 0..=KEEP {
+            if recent.len() >= lines {
+                break;
+            }
+            let Ok(text) = fs::read_to_string(rotated(dir, n)) else {
+                break;
+            };
+            let wanted = lines - recent.len();
+            recent.extend(text.lines().rev().take(wanted).map(str::to_string));
+        }
+        recent.reverse();
+        recent
+    }
+
+    /// `time` in UTC, to the millisecond.
+    pub fn timestamp(time: SystemTime) -> String {
+        let millis = time
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_millis())
+            .unwrap_or_default();
+        let [year, month, day, hour, minute, second] =
+            civil(i64::try_from(millis / 1000).unwrap_or_default());
+        format!(
+            "{:04}-{:02}-{:02} {:02}:{:02}:{:02}.{:03}",
+            year,
+            month,
+            day,
+            hour,
+            minute,
+            second,
+            millis % 1000
+        )
+    }
+
+    /// `secs` since the Unix epoch as UTC year, month, day, hour, minute,
+    /// and second.
+    pub fn civil(secs: i64) -> [i64; 6] {
+        let (days, day_secs) = (secs.div_euclid(86_400), secs.rem_euclid(86_400));
+        // Howard Hinnant's civil_from_days.
+        let z = days + 719_468;
+        let era = z.div_euclid(146_097);
+        let doe = z.rem_euclid(146_097);
+        let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = doy - (153 * mp + 2) / 5 + 1;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 };
+        let year = yoe + era * 400 + i64::from(month <= 2);
+        [
+            year,
+            month,
+            day,
+            day_secs / 3600,
+            day_secs / 60 % 60,
+            day_secs % 60,
+        ]
+    }
+}
+
+/// Writes zip archives of deflated files, for `export_diagnostics`.
+mod zip {
+    use flate2::write::DeflateEncoder;
+    use flate2::{Compression, Crc};
+    use std::io::{self, Write};
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    const LOCAL_HEADER: u32 = 0x0403_4b50;
+    const CENTRAL_HEADER: u32 = 0x0201_4b50;
+    const END_OF_DIRECTORY: u32 = 0x0605_4b50;
+    /// Version 2.0, the first with deflate.
+    const VERSION: u16 = 20;
+    const DEFLATED: u16 = 8;
+
+    /// `time` as an MS-DOS date and time, which count from 1980 in
+    /// two-second steps. Zip readers show it as local time; this is UTC.
+    fn dos_time(time: SystemTime) -> (u16, u16) {
+        let secs = time
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_secs())
+            .unwrap_or_default();
+        let [year, month, day, hour, minute, second] =
+            super::logging::civil(i64::try_from(secs).unwrap_or_default());
+        if !(1980..2108).contains(&year) {
+            return ((1 << 5) | 1, 0);
+        }
+        let date = ((year - 1980) << 9) | (month << 5) | day;
+        let time = (hour << 11) | (minute << 5) | (second / 2);
+        (date as u16, time as u16)
+    }
+
+    fn size(len: usize) -> io::Result<u32> {
+        u32::try_from(len).map_err(|_| io::Error::other("archive too large"))
+    }
+
+    /// Writes `files`, as name and contents, all modified at `modified`.
+    pub fn write(
+        out: &mut impl Write,
+        files: &[(&str, Vec<u8>)],
+        modified: SystemTime,
+    ) -> io::Result<()> {
+        let (date, time) = dos_time(modified);
+        let mut directory = Vec::new();
+        let mut offset = 0;
+        for (name, contents) in files {
+            let mut crc = Crc::new();
+            crc.update(contents);
+            let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(contents)?;
+            let compressed = encoder.finish()?;
+            // Fields shared by the local and the central header, from the
+            // version needed to the name's length.
+            let mut common = Vec::new();
+            common.extend(VERSION.to_le_bytes());
+            common.extend(0u16.to_le_bytes());
+            common.extend(DEFLATED.to_le_bytes());
+            common.extend(time.to_le_bytes());
+            common.extend(date.to_le_bytes());
+            common.extend(crc.sum().to_le_bytes());
+            common.extend(size(compressed.len())?.to_le_bytes());
+            common.extend(size(contents.len())?.to_le_bytes());
+            let name_len =
+                u16::try_from(name.len()).map_err(|_| io::Error::other("file name too long"))?;
+            common.extend(name_len.to_le_bytes());
+
+            let mut local = LOCAL_HEADER.to_le_bytes().to_vec();
+            local.extend(&common);
+            local.extend(0u16.to_le_bytes());
+            local.extend(name.as_bytes());
+            out.write_all(&local)?;
+            out.write_all(&compressed)?;
+
+            directory.extend(CENTRAL_HEADER.to_le_bytes());
+            directory.extend(VERSION.to_le_bytes());
+            directory.extend(&common);
+            // Extra field and comment lengths, disk, and attributes.
+            directory.extend([0; 12]);
+            directory.extend(size(offset)?.to_le_bytes());
+            directory.extend(name.as_bytes());
+            offset += local.len() + compressed.len();
+        }
+        let count = u16::try_from(files.len()).map_err(|_| io::Error::other("too many files"))?;
+        let mut end = END_OF_DIRECTORY.to_le_bytes().to_vec();
+        end.extend([0; 4]);
+        end.extend(count.to_le_bytes());
+        end.extend(count.to_le_bytes());
+        end.extend(size(directory.len())?.to_le_bytes());
+        end.extend(size(offset)?.to_le_bytes());
+        end.extend(0u16.to_le_bytes());
+        out.write_all(&directory)?;
+        out.write_all(&end)
+    }
+}
+
+/// Keeps one instance running per config directory. The first instance
+/// claims it by locking a file there, and listens on a loopback port it
+/// records there with a secret only this user can read; later launches
+/// hand it their arguments and exit, so there is only ever one tray icon
+/// and one event tap.
+mod single_instance {
+    use super::write_atomically;
+    use serde::{Deserialize, Serialize};
+    use std::fs;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::{Ipv4Addr, SocketAddr, TcpListener, TcpStream};
+    use std::path::Path;
+    use std::time::{Duration, Instant, SystemTime};
+
+    /// File in the config directory the running instance holds locked.
+    pub const LOCK_FILE: &str = "instance.lock";
+    /// File in the config directory holding the running instance's port
+    /// and the token later launches must send it.
+    pub const PORT_FILE: &str = "instance.port";
+    /// Reply of a running instance, so a stale port reused by some other
+    /// program isn't mistaken for one.
+    const REPLY: &str = "edit-mouse";
+    const TIMEOUT: Duration = Duration::from_secs(2);
+    /// How often `claim` and `forward` try again while waiting.
+    const RETRY: Duration = Duration::from_millis(100);
+
+    /// A later launch's arguments, with the token that shows it could read
+    /// `PORT_FILE`.
+    #[derive(Serialize, Deserialize)]
+    struct Handoff {
+        token: String,
+        args: Vec<String>,
+    }
+
+    /// Held by the instance running for a config directory; dropping it
+    /// lets a later launch take over.
+    pub struct Claim {
+        _lock: fs::File,
+    }
+
+    /// Claims `config_dir` for this launch, waiting up to `timeout` for the
+    /// instance holding it to exit, as the one a relaunch replaces does.
+    /// `None` when it still runs.
+    pub fn claim(config_dir: &Path, timeout: Duration) -> Result<Option<Claim>, String> {
+        fs::create_dir_all(config_dir).map_err(|err| err.to_string())?;
+        let lock = fs::OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(config_dir.join(LOCK_FILE))
+            .map_err(|err| err.to_string())?;
+        let deadline = Instant::now() + timeout;
+        loop {
+            match lock.try_lock() {
+                Ok(()) => return Ok(Some(Claim { _lock: lock })),
+                Err(fs::TryLockError::WouldBlock) if Instant::now() < deadline => {
+                    std::thread::sleep(RETRY);
+                }
+                Err(fs::TryLockError::WouldBlock) => return Ok(None),
+                Err(fs::TryLockError::Error(err)) => return Err(err.to_string()),
+            }
+        }
+    }
+
+    /// The running instance's port and token, from `PORT_FILE`.
+    fn read_port_file(config_dir: &Path) -> Option<(u16, String)> {
+        let contents = fs::read_to_string(config_dir.join(PORT_FILE)).ok()?;
+        let (port, token) = contents.trim().split_once(' ')?;
+        Some((port.parse().ok()?, token.to_string()))
+    }
+
+    /// Hands `args` to the instance running for `config_dir`, waiting up to
+    /// `timeout` for it to start listening. False when nothing answers.
+    pub fn forward(config_dir: &Path, args: &[String], timeout: Duration) -> bool {
+        let deadline = Instant::now() + timeout;
+        loop {
+            if try_forward(config_dir, args) {
+                return true;
+            }
+            if Instant::now() >= deadline {
+                return false;
+            }
+            std::thread::sleep(RETRY);
+        }
+    }
+
+    fn try_forward(config_dir: &Path, args: &[String]) -> bool {
+        let Some((port, token)) = read_port_file(config_dir) else {
+            return false;
+        };
+        let address = SocketAddr::from((Ipv4Addr::LOCALHOST, port));
+        let Ok(mut stream) = TcpStream::connect_timeout(&address, TIMEOUT) else {
+            return false;
+        };
+        let _ = stream.set_read_timeout(Some(TIMEOUT));
+        let handoff = Handoff {
+            token,
+            args: args.to_vec(),
+        };
+        let Ok(message) = serde_json::to_string(&handoff) else {
+            return false;
+        };
+        if writeln!(stream, "{}", message).is_err() {
+            return false;
+        }
+        let mut reply = String::new();
+        BufReader::new(stream).read_line(&mut reply).is_ok() && reply.trim() == REPLY
+    }
+
+    /// A token nobody else can guess, from the OS-seeded keys std hashes
+    /// with.
+    fn new_token() -> String {
+        use std::hash::{BuildHasher, RandomState};
+        let seed = (std::process::id(), SystemTime::now());
+        (0..2u8)
+            .map(|part| format!("{:016x}", RandomState::new().hash_one((part, seed))))
+            .collect()
+    }
+
+    /// Listens for later launches for `config_dir`, which `claim` holds,
+    /// and calls `launched` on a background thread with the arguments of
+    /// each that sends the token.
+    pub fn listen(
+        config_dir: &Path,
+        launched: impl Fn(Vec<String>) + Send + 'static,
+    ) -> Result<(), String> {
+        let listener =
+            TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).map_err(|err| err.to_string())?;
+        let port = listener.local_addr().map_err(|err| err.to_string())?.port();
+        let token = new_token();
+        write_atomically(&config_dir.join(PORT_FILE), |file| {
+            // Before the token is in it, so no other user ever reads it.
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::PermissionsExt;
+                file.set_permissions(fs::Permissions::from_mode(0o600))?;
+            }
+            write!(file, "{} {}", port, token)
+        })
+        .map_err(|err| err.to_string())?;
+        std::thread::spawn(move || {
+            for mut stream in listener.incoming().flatten() {
+                let _ = stream.set_read_timeout(Some(TIMEOUT));
+                let mut line = String::new();
+                if BufReader::new(&stream).read_line(&mut line).is_err() {
+                    continue;
+                }
+                let Ok(handoff) = serde_json::from_str::<Handoff>(&line) else {
+                    continue;
+                };
+                if handoff.token != token {
+                    log::warn!("instance: ignored a launch without the token");
+                    continue;
+                }
+                let _ = writeln!(stream, "{}", REPLY);
+                launched(handoff.args);
+            }
+        });
+        Ok(())
+    }
+}
+
+/// Control socket scripts drive the running app through: one JSON request
+/// per line, such as `{"command": "profile", "name": "Work"}`, each answered
+/// with a line `{"ok": true, "result": ...}` or `{"ok": false, "error":
+/// ...}`. It lives in the config directory and only the user running the
+/// app may connect. `edit-mouse ctl` is its client.
+#[cfg_attr(not(unix), allow(dead_code))]
+mod control {
+    use super::CheatsheetFormat;
+    use serde::{Deserialize, Serialize};
+    #[cfg(unix)]
+    use std::io::{BufRead, BufReader, Write};
+    #[cfg(unix)]
+    use std::os::unix::net::UnixStream;
+    #[cfg(unix)]
+    use std::path::Path;
+
+    /// File in the config directory the socket is bound to.
+    pub const SOCKET_FILE: &str = "control.sock";
+    /// How long the client waits for a reply.
+    #[cfg(unix)]
+    const TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
+    #[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+    #[serde(tag = "command", rename_all = "snake_case")]
+    pub enum Request {
+        Status,
+        Toggle,
+        Profile {
+            name: String,
+        },
+        /// Pauses for `seconds`, or until `Resume` without.
+        Pause {
+            #[serde(default)]
+            seconds: Option<u64>,
+        },
+        Resume,
+        Devices,
+        /// `render_cheatsheet` of `profile`, the active one without.
+        Cheatsheet {
+            #[serde(default)]
+            profile: Option<String>,
+            #[serde(default)]
+            format: CheatsheetFormat,
+            #[serde(default)]
+            include_sensitive: bool,
+        },
+    }
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    pub struct Reply {
+        pub ok: bool,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub result: Option<serde_json::Value>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub error: Option<String>,
+    }
+
+    impl From<Result<serde_json::Value, String>> for Reply {
+        fn from(result: Result<serde_json::Value, String>) -> Self {
+            match result {
+                Ok(result) => Reply {
+                    ok: true,
+                    result: Some(result),
+                    error: None,
+                },
+                Err(error) => Reply {
+                    ok: false,
+                    result: None,
+                    error: Some(error),
+                },
+            }
+        }
+    }
+
+    /// Binds the socket at `path` and answers every request through
+    /// `handle` on background threads, one per connection.
+    #[cfg(unix)]
+    pub fn listen(
+        path: &Path,
+        handle: impl Fn(Request) -> Result<serde_json::Value, String> + Send + Sync + 'static,
+    ) -> Result<(), String> {
+        use std::fs;
+        use std::os::unix::fs::PermissionsExt;
+        use std::os::unix::net::UnixListener;
+        use std::sync::Arc;
+
+        // Left behind by an instance that crashed; the single-instance
+        // check has already ruled out one that's running.
+        let _ = fs::remove_file(path);
+        let listener =
+            UnixListener::bind(path).map_err(|err| format!("{}: {}", path.display(), err))?;
+        fs::set_permissions(path, fs::Permissions::from_mode(0o600))
+            .map_err(|err| format!("{}: {}", path.display(), err))?;
+        let handle = Arc::new(handle);
+        std::thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let handle = Arc::clone(&handle);
+                std::thread::spawn(move || serve(stream, &*handle));
+            }
+        });
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    fn serve(stream: UnixStream, handle: &dyn Fn(Request) -> Result<serde_json::Value, String>) {
+        let Ok(mut replies) = stream.try_clone() else {
+            return;
+        };
+        for line in BufReader::new(stream).lines() {
+            let Ok(line) = line else {
+                return;
+            };
+            if line.trim().is_empty() {
+                continue;
+            }
+            let request = serde_json::from_str::<Request>(&line)
+                .map_err(|err| format!("bad request: {}", err));
+            let Ok(reply) = serde_json::to_string(&Reply::from(request.and_then(handle))) else {
+                return;
+            };
+            if writeln!(replies, "{}", reply).is_err() {
+                return;
+            }
+        }
+    }
+
+    /// Sends one request to the app listening at `path`.
+    #[cfg(unix)]
+    pub fn send(path: &Path, request: &Request) -> Result<Reply, String> {
+        let mut stream = UnixStream::connect(path)
+            .map_err(|err| format!("Edit Mouse isn't running ({}: {})", path.display(), err))?;
+        let _ = stream.set_read_timeout(Some(TIMEOUT));
+        let message = serde_json::to_string(request).map_err(|err| err.to_string())?;
+        writeln!(stream, "{}", message).map_err(|err| err.to_string())?;
+        let mut line = String::new();
+        BufReader::new(&stream)
+            .read_line(&mut line)
+            .map_err(|err| format!("no reply: {}", err))?;
+        serde_json::from_str(&line).map_err(|err| format!("bad reply: {}", err))
+    }
+}
+
+/// The opt-in WebSocket server on 127.0.0.1 that stream decks, Home
+/// Assistant and the like drive the app through. A client connects to
+/// `ws://127.0.0.1:<port>/?token=<token>`, sends the control socket's
+/// requests as text messages, gets a `control::Reply` for each, and is sent
+/// every event in `INTEGRATION_EVENTS` as `{"event": ..., "payload": ...}`.
+/// Small enough to speak the protocol itself: one frame per message, and
+/// no extensions.
+mod integration {
+    use super::control;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::{Ipv4Addr, Shutdown, TcpListener, TcpStream};
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::mpsc::{self, Sender};
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    /// Appended to a client's key to answer the handshake (RFC 6455 1.3).
+    const ACCEPT_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+    /// Longest message a client may send; requests are a few dozen bytes.
+    const MAX_MESSAGE: u64 = 64 * 1024;
+    /// Longest a client may take over its handshake.
+    const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+    /// How long a write may block before the client is dropped, so one
+    /// stalled client can't hold up events for the others.
+    const WRITE_TIMEOUT: Duration = Duration::from_secs(1);
+    /// How often the accept loop checks whether it was stopped.
+    const ACCEPT_POLL: Duration = Duration::from_millis(200);
+
+    const OP_TEXT: u8 = 0x1;
+    const OP_CLOSE: u8 = 0x8;
+    const OP_PING: u8 = 0x9;
+    const OP_PONG: u8 = 0xa;
+
+    type Client = Arc<Mutex<TcpStream>>;
+    type Handler = dyn Fn(control::Request) -> Result<serde_json::Value, String> + Send + Sync;
+
+    /// A running server; dropping it closes the listener and every
+    /// connection.
+    pub struct Server {
+        port: u16,
+        token: String,
+        stopped: Arc<AtomicBool>,
+        clients: Arc<Mutex<Vec<Client>>>,
+        /// Messages for the thread writing them to every client, so whoever
+        /// emits an event never waits on a slow one.
+        events: Sender<String>,
+    }
+
+    impl Server {
+        /// Listens on 127.0.0.1:`port` and answers requests through
+        /// `handle`, on background threads.
+        pub fn start(
+            port: u16,
+            token: String,
+            handle: impl Fn(control::Request) -> Result<serde_json::Value, String>
+                + Send
+                + Sync
+                + 'static,
+        ) -> Result<Server, String> {
+            let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, port))
+                .map_err(|err| format!("couldn't listen on port {}: {}", port, err))?;
+            // Nonblocking so the loop notices `stopped` and lets go of the
+            // port without another connection arriving.
+            listener
+                .set_nonblocking(true)
+                .map_err(|err| err.to_string())?;
+            let (events, messages) = mpsc::channel::<String>();
+            let server = Server {
+                port,
+                token: token.clone(),
+                stopped: Arc::new(AtomicBool::new(false)),
+                clients: Arc::new(Mutex::new(Vec::new())),
+                events,
+            };
+            let clients = Arc::clone(&server.clients);
+            // Ends when the server is dropped along with its sender.
+            std::thread::spawn(move || {
+                for message in messages {
+                    let frame = encode_frame(OP_TEXT, message.as_bytes());
+                    let Ok(mut clients) = clients.lock() else {
+                        return;
+                    };
+                    clients.retain(|client| match client.lock() {
+                        Ok(mut stream) => stream.write_all(&frame).is_ok(),
+                        Err(_) => false,
+                    });
+                }
+            });
+            let stopped = Arc::clone(&server.stopped);
+            let clients = Arc::clone(&server.clients);
+            let handle: Arc<Handler> = Arc::new(handle);
+            std::thread::spawn(move || {
+                while !stopped.load(Ordering::Relaxed) {
+                    match listener.accept() {
+                        Ok((stream, _)) => {
+                            let (token, clients, handle) =
+                                (token.clone(), Arc::clone(&clients), Arc::clone(&handle));
+                            std::thread::spawn(move || {
+                                if let Err(err) = serve(stream, &token, &clients, &*handle) {
+                                    log::debug!("integration: client dropped: {}", err);
+                                }
+                            });
+                        }
+                        Err(err) if err.kind() == std::io::ErrorKind::WouldBlock => {
+                            std::thread::sleep(ACCEPT_POLL);
+                        }
+                        Err(err) => {
+                            log::warn!("integration: accept failed: {}", err);
+                            std::thread::sleep(ACCEPT_POLL);
+                        }
+                    }
+                }
+            });
+            Ok(server)
+        }
+
+        /// Whether this server is the one `port` and `token` ask for.
+        pub fn serves(&self, port: u16, token: &str) -> bool {
+            self.port == port && self.token == token
+        }
+
+        /// How many clients are connected.
+        pub fn clients(&self) -> usize {
+            self.clients
+                .lock()
+                .map(|clients| clients.len())
+                .unwrap_or(0)
+        }
+
+        /// Queues `event` for every connected client; those that can't
+        /// take it are dropped.
+        pub fn broadcast(&self, event: &str, payload: serde_json::Value) {
+            let message = serde_json::json!({ "event": event, "payload": payload });
+            let _ = self.events.send(message.to_string());
+        }
+    }
+
+    impl Drop for Server {
+        fn drop(&mut self) {
+            self.stopped.store(true, Ordering::Relaxed);
+            if let Ok(mut clients) = self.clients.lock() {
+                for client in clients.drain(..) {
+                    if let Ok(stream) = client.lock() {
+                        let _ = stream.shutdown(Shutdown::Both);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Runs one connection: the handshake, then requests until the client
+    /// closes or the server stops.
+    fn serve(
+        stream: TcpStream,
+        token: &str,
+        clients: &Mutex<Vec<Client>>,
+        handle: &Handler,
+    ) -> Result<(), String> {
+        // Some platforms hand out accepted sockets nonblocking like their
+        // listener.
+        stream
+            .set_nonblocking(false)
+            .map_err(|err| err.to_string())?;
+        stream
+            .set_read_timeout(Some(HANDSHAKE_TIMEOUT))
+            .map_err(|err| err.to_string())?;
+        stream
+            .set_write_timeout(Some(WRITE_TIMEOUT))
+            .map_err(|err| err.to_string())?;
+        let mut reader = BufReader::new(stream.try_clone().map_err(|err| err.to_string())?);
+        let mut writer = stream;
+        let key = match read_handshake(&mut reader, token) {
+            Ok(key) => key,
+            Err(refusal) => {
+                let _ = write!(
+                    writer,
+                    "HTTP/1.1 {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                    refusal
+                );
+                return Err(refusal.to_string());
+            }
+        };
+        write!(
+            writer,
+            "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Accept: {}\r\n\r\n",
+            accept_key(&key)
+        )
+        .map_err(|err| err.to_string())?;
+        reader
+            .get_ref()
+            .set_read_timeout(None)
+            .map_err(|err| err.to_string())?;
+        let client: Client = Arc::new(Mutex::new(writer));
+        if let Ok(mut clients) = clients.lock() {
+            clients.push(Arc::clone(&client));
+        }
+        let served = serve_messages(&mut reader, &client, handle);
+        if let Ok(mut clients) = clients.lock() {
+            clients.retain(|other| !Arc::ptr_eq(other, &client));
+        }
+        if let Ok(stream) = client.lock() {
+            let _ = stream.shutdown(Shutdown::Both);
+        }
+        served
+    }
+
+    fn serve_messages(
+        reader: &mut impl Read,
+        client: &Client,
+        handle: &Handler,
+    ) -> Result<(), String> {
+        let send = |opcode: u8, payload: &[u8]| -> Result<(), String> {
+            let mut stream = client.lock().map_err(|_| "client lock poisoned")?;
+            stream
+                .write_all(&encode_frame(opcode, payload))
+                .map_err(|err| err.to_string())
+        };
+        loop {
+            let (opcode, payload) = read_frame(reader)?;
+            match opcode {
+                OP_TEXT => {
+                    let request = std::str::from_utf8(&payload)
+                        .map_err(|err| err.to_string())
+                        .and_then(|text| {
+                            serde_json::from_str::<control::Request>(text)
+                                .map_err(|err| format!("bad request: {}", err))
+                        });
+                    let reply = control::Reply::from(request.and_then(handle));
+                    let reply = serde_json::to_string(&reply).map_err(|err| err.to_string())?;
+                    send(OP_TEXT, reply.as_bytes())?;
+                }
+                OP_PING => send(OP_PONG, &payload)?,
+                OP_PONG => {}
+                OP_CLOSE => {
+                    let _ = send(OP_CLOSE, &payload);
+                    return Ok(());
+                }
+                other => {
+                    // 1003: unsupported data, binary messages here.
+                    let _ = send(OP_CLOSE, &1003u16.to_be_bytes());
+                    return Err(format!("unsupported opcode {:#x}", other));
+                }
+            }
+        }
+    }
+
+    /// Reads the upgrade request and returns its `Sec-WebSocket-Key`, or
+    /// the status to refuse it with.
+    fn read_handshake(reader: &mut impl BufRead, token: &str) -> Result<String, &'static str> {
+        let mut request_line = String::new();
+        reader
+            .read_line(&mut request_line)
+            .map_err(|_| "400 Bad Request")?;
+        let mut parts = request_line.split_whitespace();
+        let (Some("GET"), Some(target)) = (parts.next(), parts.next()) else {
+            return Err("400 Bad Request");
+        };
+        let given = target
+            .split_once('?')
+            .map(|(_, query)| query)
+            .unwrap_or_default()
+            .split('&')
+            .find_map(|pair| pair.strip_prefix("token="));
+        let mut key = None;
+        let mut upgrade = false;
+        // Capped, so a client can't keep sending headers forever.
+        for _ in 0..64 {
+            let mut line = String::new();
+            reader.read_line(&mut line).map_err(|_| "400 Bad Request")?;
+            let line = line.trim_end();
+            if line.is_empty() {
+                break;
+            }
+            let Some((name, value)) = line.split_once(':') else {
+                continue;
+            };
+            let value = value.trim();
+            if name.eq_ignore_ascii_case("sec-websocket-key") {
+                key = Some(value.to_string());
+            } else if name.eq_ignore_ascii_case("upgrade") {
+                upgrade = value.eq_ignore_ascii_case("websocket");
+            }
+        }
+        if !upgrade {
+            return Err("426 Upgrade Required");
+        }
+        let key = key.ok_or("400 Bad Request")?;
+        if token.is_empty() || given.map(str::as_bytes) != Some(token.as_bytes()) {
+            return Err("401 Unauthorized");
+        }
+        Ok(key)
+    }
+
+    /// Reads one frame, unmasking it; client frames are always masked.
+    fn read_frame(reader: &mut impl Read) -> Result<(u8, Vec<u8>), String> {
+        let mut head = [0u8; 2];
+        reader
+            .read_exact(&mut head)
+            .map_err(|err| err.to_string())?;
+        let opcode = head[0] & 0x0f;
+        if head[0] & 0x80 == 0 {
+            return Err("fragmented message".to_string());
+        }
+        if head[1] & 0x80 == 0 {
+            return Err("unmasked client frame".to_string());
+        }
+        let len = match head[1] & 0x7f {
+            126 => {
+                let mut len = [0u8; 2];
+                reader.read_exact(&mut len).map_err(|err| err.to_string())?;
+                u64::from(u16::from_be_bytes(len))
+            }
+            127 => {
+                let mut len = [0u8; 8];
+                reader.read_exact(&mut len).map_err(|err| err.to_string())?;
+                u64::from_be_bytes(len)
+            }
+            len => u64::from(len),
+        };
+        if len > MAX_MESSAGE {
+            return Err(format!("{} byte message", len));
+        }
+        let mut mask = [0u8; 4];
+        reader
+            .read_exact(&mut mask)
+            .map_err(|err| err.to_string())?;
+        let mut payload = vec![0u8; len as usize];
+        reader
+            .read_exact(&mut payload)
+            .map_err(|err| err.to_string())?;
+        for (i, byte) in payload.iter_mut().enumerate() {
+            *byte ^= mask[i % 4];
+        }
+        Ok((opcode, payload))
+    }
+
+    /// A whole, unmasked frame, as servers send them.
+    pub(super) fn encode_frame(opcode: u8, payload: &[u8]) -> Vec<u8> {
+        let mut frame = vec![0x80 | opcode];
+        match payload.len() {
+            len @ 0..=125 => frame.push(len as u8),
+            len @ 126..=0xffff => {
+                frame.push(126);
+                frame.extend_from_slice(&(len as u16).to_be_bytes());
+            }
+            len => {
+                frame.push(127);
+                frame.extend_from_slice(&(len as u64).to_be_bytes());
+            }
+        }
+        frame.extend_from_slice(payload);
+        frame
+    }
+
+    /// `Sec-WebSocket-Accept` for a client's `key`.
+    pub(super) fn accept_key(key: &str) -> String {
+        base64(&sha1(format!("{}{}", key, ACCEPT_GUID).as_bytes()))
+    }
+
+    fn sha1(data: &[u8]) -> [u8; 20] {
+        let mut h: [u32; 5] = [
+            0x6745_2301,
+            0xefcd_ab89,
+            0x98ba_dcfe,
+            0x1032_5476,
+            0xc3d2_e1f0,
+        ];
+        let mut message = data.to_vec();
+        message.push(0x80);
+        while message.len() % 64 != 56 {
+            message.push(0);
+        }
+        message.extend_from_slice(&((data.len() as u64) * 8).to_be_bytes());
+        for block in message.chunks(64) {
+            let mut w = [0u32; 80];
+            for (i, word) in block.chunks(4).enumerate() {
+                w[i] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+            }
+            for i in 16..80 {
+                w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1);
+            }
+            let [mut a, mut b, mut c, mut d, mut e] = h;
+            for (i, word) in w.iter().enumerate() {
+                let (f, k) = match i {
+                    0..=19 => ((b & c) | (!b & d), 0x5a82_7999),
+                    20..=39 => (b ^ c ^ d, 0x6ed9_eba1),
+                    40..=59 => ((b & c) | (b & d) | (c & d), 0x8f1b_bcdc),
+                    _ => (b ^ c ^ d, 0xca62_c1d6),
+                };
+                let next = a
+                    .rotate_left(5)
+                    .wrapping_add(f)
+                    .wrapping_add(e)
+                    .wrapping_add(k)
+                    .wrapping_add(*word);
+                e = d;
+                d = c;
+                c = b.rotate_left(30);
+                b = a;
+                a = next;
+            }
+            for (state, value) in h.iter_mut().zip([a, b, c, d, e]) {
+                *state = state.wrapping_add(value);
+            }
+        }
+        let mut digest = [0u8; 20];
+        for (bytes, word) in digest.chunks_mut(4).zip(h) {
+            bytes.copy_from_slice(&word.to_be_bytes());
+        }
+        digest
+    }
+
+    fn base64(data: &[u8]) -> String {
+        const ALPHABET: &[u8; 64] =
+            b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+        let mut out = String::new();
+        for chunk in data.chunks(3) {
+            let bytes = [
+                chunk[0],
+                *chunk.get(1).unwrap_or(&0),
+                *chunk.get(2).unwrap_or(&0),
+            ];
+            let bits = u32::from_be_bytes([0, bytes[0], bytes[1], bytes[2]]);
+            for i in 0..4 {
+                if i <= chunk.len() {
+                    out.push(ALPHABET[(bits >> (18 - 6 * i) & 0x3f) as usize] as char);
+                } else {
+                    out.push('=');
+                }
+            }
+        }
+        out
+    }
+}
+
+/// System-wide hotkeys of the app, registered through Carbon's hotkey API,
+/// which needs no permission. Other platforms can't register any yet.
+/// Registering and the presses both happen on the main thread.
+mod hotkeys {
+    use super::{Hotkey, HotkeyPurpose};
+    use std::sync::OnceLock;
+
+    /// Called with the purpose of every hotkey pressed.
+    static HANDLER: OnceLock<Box<dyn Fn(HotkeyPurpose) + Send + Sync>> = OnceLock::new();
+
+    pub fn set_handler(handler: impl Fn(HotkeyPurpose) + Send + Sync + 'static) {
+        let _ = HANDLER.set(Box::new(handler));
+    }
+
+    #[cfg(target_os = "macos")]
+    pub use carbon::{register, registered};
+
+    #[cfg(not(target_os = "macos"))]
+    pub fn register(_purpose: HotkeyPurpose, hotkey: Option<Hotkey>) -> Result<(), String> {
+        match hotkey {
+            Some(hotkey) => Err(format!(
+                "global hotkeys are only supported on macOS ({})",
+                hotkey.label()
+            )),
+            None => Ok(()),
+        }
+    }
+
+    #[cfg(not(target_os = "macos"))]
+    pub fn registered(_purpose: HotkeyPurpose) -> Option<Hotkey> {
+        None
+    }
+
+    #[cfg(target_os = "macos")]
+    mod carbon {
+        use super::super::{modifier_mask, Modifier};
+        use super::{Hotkey, HotkeyPurpose, HANDLER};
+        use std::ffi::c_void;
+        use std::ptr;
+        use std::sync::atomic::{AtomicBool, Ordering};
+        use std::sync::Mutex;
+
+        type OSStatus = i32;
+        type EventRef = *mut c_void;
+        type EventTargetRef = *mut c_void;
+        type EventHandlerCallRef = *mut c_void;
+        type EventHandlerRef = *mut c_void;
+        type EventHotKeyRef = *mut c_void;
+        type EventHandlerUPP =
+            extern "C" fn(EventHandlerCallRef, EventRef, *mut c_void) -> OSStatus;
+
+        #[repr(C)]
+        struct EventTypeSpec {
+            event_class: u32,
+            event_kind: u32,
+        }
+
+        #[repr(C)]
+        #[derive(Default)]
+        struct EventHotKeyID {
+            signature: u32,
+            id: u32,
+        }
+
+        const EVENT_CLASS_KEYBOARD: u32 = u32::from_be_bytes(*b"keyb");
+        const EVENT_HOT_KEY_PRESSED: u32 = 5;
+        const EVENT_PARAM_DIRECT_OBJECT: u32 = u32::from_be_bytes(*b"----");
+        const TYPE_EVENT_HOT_KEY_ID: u32 = u32::from_be_bytes(*b"hkid");
+        /// Fails the registration when another app holds the same combo.
+        const HOT_KEY_EXCLUSIVE: u32 = 1;
+        const HOT_KEY_EXISTS: OSStatus = -9878;
+        /// Tells this app's hotkeys apart from those of other code in the
+        /// process.
+        const SIGNATURE: u32 = u32::from_be_bytes(*b"EdMs");
+        /// Carbon's `cmdKey`, `shiftKey`, `optionKey`, and `controlKey`.
+        const MODIFIER_BITS: [(Modifier, u32); 4] = [
+            (Modifier::Command, 1 << 8),
+            (Modifier::Shift, 1 << 9),
+            (Modifier::Option, 1 << 11),
+            (Modifier::Control, 1 << 12),
+        ];
+
+        #[link(name = "Carbon", kind = "framework")]
+        extern "C" {
+            fn GetApplicationEventTarget() -> EventTargetRef;
+            fn InstallEventHandler(
+                target: EventTargetRef,
+                handler: EventHandlerUPP,
+                type_count: usize,
+                types: *const EventTypeSpec,
+                user_data: *mut c_void,
+                handler_ref: *mut EventHandlerRef,
+            ) -> OSStatus;
+            fn GetEventParameter(
+                event: EventRef,
+                name: u32,
+                desired_type: u32,
+                actual_type: *mut u32,
+                size: usize,
+                actual_size: *mut usize,
+                data: *mut c_void,
+            ) -> OSStatus;
+            fn RegisterEventHotKey(
+                keycode: u32,
+                modifiers: u32,
+                id: EventHotKeyID,
+                target: EventTargetRef,
+                options: u32,
+                hotkey_ref: *mut EventHotKeyRef,
+            ) -> OSStatus;
+            fn UnregisterEventHotKey(hotkey_ref: EventHotKeyRef) -> OSStatus;
+        }
+
+        static INSTALLED: AtomicBool = AtomicBool::new(false);
+        /// Registered hotkeys with their Carbon refs, stored as addresses.
+        static REGISTERED: Mutex<Vec<(HotkeyPurpose, Hotkey, usize)>> = Mutex::new(Vec::new());
+
+        extern "C" fn pressed(
+            _call: EventHandlerCallRef,
+            event: EventRef,
+            _user_data: *mut c_void,
+        ) -> OSStatus {
+            let mut id = EventHotKeyID::default();
+            let status = unsafe {
+                GetEventParameter(
+                    event,
+                    EVENT_PARAM_DIRECT_OBJECT,
+                    TYPE_EVENT_HOT_KEY_ID,
+                    ptr::null_mut(),
+                    std::mem::size_of::<EventHotKeyID>(),
+                    ptr::null_mut(),
+                    (&mut id as *mut EventHotKeyID).cast(),
+                )
+            };
+            if status == 0 && id.signature == SIGNATURE {
+                if let (Some(purpose), Some(handler)) =
+                    (HotkeyPurpose::from_id(id.id), HANDLER.get())
+                {
+                    handler(purpose);
+                }
+            }
+            0
+        }
+
+        fn install() -> Result<(), String> {
+            if INSTALLED.load(Ordering::Acquire) {
+                return Ok(());
+            }
+            let spec = EventTypeSpec {
+                event_class: EVENT_CLASS_KEYBOARD,
+                event_kind: EVENT_HOT_KEY_PRESSED,
+            };
+            let status = unsafe {
+                InstallEventHandler(
+                    GetApplicationEventTarget(),
+                    pressed,
+                    1,
+                    &spec,
+                    ptr::null_mut(),
+                    ptr::null_mut(),
+                )
+            };
+            if status != 0 {
+                return Err(format!("couldn't listen for hotkeys (error {})", status));
+            }
+            INSTALLED.store(true, Ordering::Release);
+            Ok(())
+        }
+
+        /// Makes `hotkey` the one of `purpose`, replacing the previous one;
+        /// `None` only unregisters. When registering fails the previous one
+        /// stays.
+        pub fn register(purpose: HotkeyPurpose, hotkey: Option<Hotkey>) -> Result<(), String> {
+            let mut registered = REGISTERED
+                .lock()
+                .map_err(|_| "hotkeys are unavailable after a crash".to_string())?;
+            let index = registered.iter().position(|(held, ..)| *held == purpose);
+            if index.map(|index| registered[index].1) == hotkey {
+                return Ok(());
+            }
+            let added = match hotkey {
+                Some(hotkey) => Some((hotkey, register_carbon(purpose, hotkey)?)),
+                None => None,
+            };
+            if let Some(index) = index {
+                let (_, _, hotkey_ref) = registered.remove(index);
+                unsafe { UnregisterEventHotKey(hotkey_ref as EventHotKeyRef) };
+            }
+            if let Some((hotkey, hotkey_ref)) = added {
+                registered.push((purpose, hotkey, hotkey_ref));
+            }
+            Ok(())
+        }
+
+        fn register_carbon(purpose: HotkeyPurpose, hotkey: Hotkey) -> Result<usize, String> {
+            install()?;
+            let modifiers = MODIFIER_BITS
+                .iter()
+                .filter(|(modifier, _)| hotkey.modifiers & modifier_mask(&[*modifier]) != 0)
+                .fold(0, |bits, (_, bit)| bits | bit);
+            let id = EventHotKeyID {
+                signature: SIGNATURE,
+                id: purpose.id(),
+            };
+            let mut hotkey_ref = ptr::null_mut();
+            let status = unsafe {
+                RegisterEventHotKey(
+                    u32::from(hotkey.keycode),
+                    modifiers,
+                    id,
+                    GetApplicationEventTarget(),
+                    HOT_KEY_EXCLUSIVE,
+                    &mut hotkey_ref,
+                )
+            };
+            match status {
+                0 => Ok(hotkey_ref as usize),
+                HOT_KEY_EXISTS => Err(format!(
+                    "{} is already taken by another app",
+                    hotkey.label()
+                )),
+                status => Err(format!(
+                    "couldn't register {} (error {})",
+                    hotkey.label(),
+                    status
+                )),
+            }
+        }
+
+        pub fn registered(purpose: HotkeyPurpose) -> Option<Hotkey> {
+            let registered = REGISTERED.lock().ok()?;
+            registered
+                .iter()
+                .find(|(held, ..)| *held == purpose)
+                .map(|(_, hotkey, _)| *hotkey)
+        }
+    }
+}
+
+/// Records the next key combo typed anywhere, for a shortcut action. A
+/// keyboard event tap of its own runs only while recording, and keeps the
+/// combo from reaching other apps.
+mod shortcut_capture {
+    use super::ModifierMask;
+    use std::sync::Mutex;
+    #[cfg(target_os = "macos")]
+    use std::time::Duration;
+
+    /// Longest a capture waits for a combo.
+    #[cfg(target_os = "macos")]
+    pub const TIMEOUT: Duration = Duration::from_secs(10);
+
+    /// How a capture ended.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+    pub enum Outcome {
+        Captured {
+            keycode: u16,
+            modifiers: ModifierMask,
+        },
+        /// Escape was pressed without modifiers.
+        Escaped,
+        TimedOut,
+        Cancelled,
+    }
+
+    /// Generation of the running capture and the last one started;
+    /// `cancel` clears the first.
+    static ACTIVE: Mutex<(Option<u64>, u64)> = Mutex::new((None, 0));
+
+    pub fn is_active() -> bool {
+        ACTIVE.lock().is_ok_and(|guard| guard.0.is_some())
+    }
+
+    /// Stops the running capture, which then ends as `Cancelled`.
+    pub fn cancel() {
+        if let Ok(mut guard) = ACTIVE.lock() {
+            guard.0 = None;
+        }
+    }
+
+    #[cfg(target_os = "macos")]
+    fn is_current(generation: u64) -> bool {
+        ACTIVE.lock().is_ok_and(|guard| guard.0 == Some(generation))
+    }
+
+    #[cfg(target_os = "macos")]
+    const KEYCODE_ESCAPE: u16 = 0x35;
+
+    /// Starts recording on a thread of its own and calls `finished` with how
+    /// it ended. Fails when a capture is already running or the tap can't
+    /// be created.
+    #[cfg(target_os = "macos")]
+    pub fn start(finished: impl FnOnce(Outcome) + Send + 'static) -> Result<(), String> {
+        let generation = {
+            let mut guard = ACTIVE.lock().map_err(|err| err.to_string())?;
+            if guard.0.is_some() {
+                return Err("a shortcut is already being recorded".to_string());
+            }
+            guard.1 += 1;
+            guard.0 = Some(guard.1);
+            guard.1
+        };
+        let (ready, started) = std::sync::mpsc::channel();
+        std::thread::spawn(move || {
+            let outcome = run(generation, ready);
+            if let Ok(mut guard) = ACTIVE.lock() {
+                if guard.0 == Some(generation) {
+                    guard.0 = None;
+                }
+            }
+            if let Some(outcome) = outcome {
+                finished(outcome);
+            }
+        });
+        started
+            .recv()
+            .unwrap_or_else(|_| Err("shortcut capture stopped".to_string()))
+    }
+
+    /// Runs the tap until a combo is pressed and released, Escape, the
+    /// timeout, or `cancel`. `None` when the tap never started, which
+    /// `ready` has been told.
+    #[cfg(target_os = "macos")]
+    fn run(generation: u64, ready: std::sync::mpsc::Sender<Result<(), String>>) -> Option<Outcome> {
+        use core_foundation::base::TCFType;
+        use core_foundation::mach_port::CFMachPortInvalidate;
+        use core_foundation::runloop::{
+            kCFRunLoopCommonModes, kCFRunLoopDefaultMode, CFRunLoop, CFRunLoopSourceInvalidate,
+        };
+        use core_graphics::event::{
+            CGEventFlags, CGEventTap, CGEventTapLocation, CGEventTapOptions, CGEventTapPlacement,
+            CGEventType, EventField,
+        };
+        use std::cell::Cell;
+        use std::time::Instant;
+
+        const MODIFIER_FLAGS: [(CGEventFlags, super::Modifier); 4] = [
+            (CGEventFlags::CGEventFlagShift, super::Modifier::Shift),
+            (CGEventFlags::CGEventFlagControl, super::Modifier::Control),
+            (CGEventFlags::CGEventFlagAlternate, super::Modifier::Option),
+            (CGEventFlags::CGEventFlagCommand, super::Modifier::Command),
+        ];
+        /// How long the captured key may stay down before recording ends
+        /// anyway, letting its release through.
+        const RELEASE_WAIT: Duration = Duration::from_secs(1);
+
+        let outcome = Cell::new(None);
+        // The key that ended the capture, until its release is swallowed too.
+        let pressed: Cell<Option<u16>> = Cell::new(None);
+        let tap = CGEventTap::new(
+            CGEventTapLocation::HID,
+            CGEventTapPlacement::HeadInsertEventTap,
+            CGEventTapOptions::Default,
+            vec![CGEventType::KeyDown, CGEventType::KeyUp],
+            |_proxy, event_type, event| {
+                let handle = || {
+                    let keycode =
+                        event.get_integer_value_field(EventField::KEYBOARD_EVENT_KEYCODE) as u16;
+                    match event_type {
+                        CGEventType::KeyDown => {
+                            if pressed.get().is_none() {
+                                let flags = event.get_flags();
+                                let modifiers = MODIFIER_FLAGS
+                                    .iter()
+                                    .filter(|(flag, _)| flags.contains(*flag))
+                                    .fold(0, |mask, (_, modifier)| {
+                                        mask | super::modifier_mask(&[*modifier])
+                                    });
+                                outcome.set(Some(if keycode == KEYCODE_ESCAPE && modifiers == 0 {
+                                    Outcome::Escaped
+                                } else {
+                                    Outcome::Captured { keycode, modifiers }
+                                }));
+                                pressed.set(Some(keycode));
+                            }
+                            // Key repeats of the combo are swallowed too.
+                            None
+                        }
+                        CGEventType::KeyUp if pressed.get() == Some(keycode) => {
+                            pressed.set(None);
+                            None
+                        }
+                        _ => Some(event.clone()),
+                    }
+                };
+                std::panic::catch_unwind(std::panic::AssertUnwindSafe(handle))
+                    .unwrap_or_else(|_| Some(event.clone()))
+            },
+        );
+        let Ok(tap) = tap else {
+            let _ = ready.send(Err(
+                "could not create the keyboard event tap: grant Input Monitoring".to_string(),
+            ));
+            return None;
+        };
+        let Ok(loop_source) = tap.mach_port.create_runloop_source(0) else {
+            let _ = ready.send(Err("could not attach the keyboard event tap".to_string()));
+            return None;
+        };
+        let runloop = CFRunLoop::get_current();
+        unsafe {
+            runloop.add_source(&loop_source, kCFRunLoopCommonModes);
+        }
+        tap.enable();
+        let _ = ready.send(Ok(()));
+
+        let deadline = Instant::now() + TIMEOUT;
+        let mut captured_at = None;
+        let ended = loop {
+            CFRunLoop::run_in_mode(
+                unsafe { kCFRunLoopDefaultMode },
+                Duration::from_millis(100),
+                false,
+            );
+            if let Some(ended) = outcome.get() {
+                let since = *captured_at.get_or_insert_with(Instant::now);
+                if pressed.get().is_none() || since.elapsed() > RELEASE_WAIT {
+                    break ended;
+                }
+            } else if !is_current(generation) {
+                break Outcome::Cancelled;
+            } else if Instant::now() >= deadline {
+                break Outcome::TimedOut;
+            }
+        };
+        unsafe {
+            runloop.remove_source(&loop_source, kCFRunLoopCommonModes);
+            CFRunLoopSourceInvalidate(loop_source.as_concrete_TypeRef());
+            CFMachPortInvalidate(tap.mach_port.as_concrete_TypeRef());
+        }
+        Some(ended)
+    }
+
+    #[cfg(not(target_os = "macos"))]
+    pub fn start(_finished: impl FnOnce(Outcome) + Send + 'static) -> Result<(), String> {
+        Err("recording shortcuts is only supported on macOS".to_string())
+    }
+}
+
+/// Minimal Logitech HID++ 2.0 client: feature lookup through the root
+/// feature and single request/response calls on long reports.
+mod hidpp {
+    use super::{BatteryReport, HidEntry, Transport};
+    use std::ffi::CString;
+    use std::time::{Duration, Instant};
+
+    const REPORT_LONG: u8 = 0x11;
+    const DIRECT_INDEX: u8 = 0xff;
+    const SOFTWARE_ID: u8 = 0x0a;
+    const RESPONSE_TIMEOUT: Duration = Duration::from_millis(500);
+
+    pub const FEATURE_BATTERY_STATUS: u16 = 0x1000;
+    pub const FEATURE_UNIFIED_BATTERY: u16 = 0x1004;
+    pub const FEATURE_ADJUSTABLE_DPI: u16 = 0x2201;
+
+    pub struct Device {
+        handle: hidapi::HidDevice,
+        index: u8,
+    }
+
+    impl Device {
+        /// Opens the HID++ interface and finds the device index that answers:
+        /// `0xff` for directly connected devices, 1-6 behind a receiver.
+        pub fn open(api: &hidapi::HidApi, entry: &HidEntry) -> Option<Self> {
+            let path = CString::new(entry.path.as_str()).ok()?;
+            let handle = api.open_path(&path).ok()?;
+            let indices: Vec<u8> = if entry.transport == Transport::Dongle {
+                (1..=6).collect()
+            } else {
+                vec![DIRECT_INDEX]
+            };
+            let mut device = Self { handle, index: 0 };
+            for index in indices {
+                device.index = index;
+                if device.call(0, 0x1, &[]).is_some() {
+                    return Some(device);
+                }
+            }
+            None
+        }
+
+        pub fn feature_index(&self, feature: u16) -> Option<u8> {
+            let response = self.call(0, 0x0, &feature.to_be_bytes())?;
+            Some(response[0]).filter(|index| *index != 0)
+        }
+
+        /// Sends `function` of the feature at `feature_index` and returns the
+        /// 16 response parameter bytes.
+        pub fn call(&self, feature_index: u8, function: u8, params: &[u8]) -> Option<[u8; 16]> {
+            let mut request = [0u8; 20];
+            request[0] = REPORT_LONG;
+            request[1] = self.index;
+            request[2] = feature_index;
+            request[3] = (function << 4) | SOFTWARE_ID;
+            let len = params.len().min(16);
+            request[4..4 + len].copy_from_slice(&params[..len]);
+            self.handle.write(&request).ok()?;
+
+            let deadline = Instant::now() + RESPONSE_TIMEOUT;
+            let mut buf = [0u8; 20];
+            while let Some(remaining) = deadline.checked_duration_since(Instant::now()) {
+                let read = self
+                    .handle
+                    .read_timeout(&mut buf, remaining.as_millis() as i32)
+                    .ok()?;
+                if read < 4 || buf[1] != self.index {
+                    continue;
+                }
+                // 0x8f (HID++ 1.0) and 0xff (2.0) are error responses.
+                if buf[2] == 0x8f || buf[2] == 0xff {
+                    if buf[3] == feature_index {
+                        return None;
+                    }
+                    continue;
+                }
+                if buf[0] == REPORT_LONG && buf[2] == feature_index && buf[3] == request[3] {
+                    let mut response = [0u8; 16];
+                    response.copy_from_slice(&buf[4..20]);
+                    return Some(response);
+                }
+            }
+            None
+        }
+    }
+
+    /// Sensor 0 of a device with the Adjustable DPI feature.
+    pub struct DpiSensor {
+        device: Device,
+        feature_index: u8,
+    }
+
+    impl DpiSensor {
+        pub fn open(api: &hidapi::HidApi, entry: &HidEntry) -> Option<Self> {
+            let device = Device::open(api, entry)?;
+            let feature_index = device.feature_index(FEATURE_ADJUSTABLE_DPI)?;
+            Some(Self {
+                device,
+                feature_index,
+            })
+        }
+
+        /// get_sensor_dpi: sensor, dpi (big endian), default dpi
+        pub fn dpi(&self) -> Option<u32> {
+            let response = self.device.call(self.feature_index, 0x2, &[0])?;
+            Some(u32::from(u16::from_be_bytes([response[1], response[2]])))
+        }
+
+        /// set_sensor_dpi, returning the DPI the device confirmed.
+        pub fn set_dpi(&self, dpi: u32) -> Option<u32> {
+            let [high, low] = u16::try_from(dpi).ok()?.to_be_bytes();
+            let response = self.device.call(self.feature_index, 0x3, &[0, high, low])?;
+            Some(u32::from(u16::from_be_bytes([response[1], response[2]])))
+        }
+    }
+
+    pub fn read_battery(api: &hidapi::HidApi, entry: &HidEntry) -> Option<BatteryReport> {
+        let device = Device::open(api, entry)?;
+        if let Some(index) = device.feature_index(FEATURE_UNIFIED_BATTERY) {
+            // get_status: state of charge, level, charging status, external power
+            let response = device.call(index, 0x1, &[])?;
+            return Some(BatteryReport::Available {
+                percentage: response[0].min(100),
+                charging: Some(matches!(response[2], 1..=3)),
+            });
+        }
+        if let Some(index) = device.feature_index(FEATURE_BATTERY_STATUS) {
+            // get_battery_level_status: level, next level, status
+            let response = device.call(index, 0x0, &[])?;
+            return Some(BatteryReport::Available {
+                percentage: response[0].min(100),
+                charging: Some(matches!(response[2], 1..=4)),
+            });
+        }
+        None
+    }
+}
+
+/// Printed with an unknown flag.
+const USAGE: &str =
+    "usage: edit-mouse [--hidden] [--safe-mode] [--list-devices] [--toggle] [--profile <name>] [--config-dir <dir>]";
+
+/// Printed for `edit-mouse ctl` without a command it knows.
+const CTL_USAGE: &str = "usage: edit-mouse ctl [--config-dir <dir>] [--json] \
+    <status | toggle | profile <name> | pause [<n>s|<n>m|<n>h] | resume | devices | \
+    cheatsheet [<profile>] [--html] [--include-sensitive]>";
+
+/// Bundle identifier from tauri.conf.json, which names the default config
+/// directory.
+#[cfg_attr(not(unix), allow(dead_code))]
+const BUNDLE_IDENTIFIER: &str = "com.betr.editmouse";
+
+/// Command-line flags. A launch while the app is already running hands them
+/// to the running instance.
+#[derive(Debug, Default, Clone, PartialEq)]
+struct LaunchArgs {
+    /// Start in the tray without showing the window.
+    hidden: bool,
+    /// Print the connected mice as JSON and exit.
+    list_devices: bool,
+    /// Turn remapping off if it's on, and on if it's off.
+    toggle: bool,
+    /// Switch to this profile.
+    profile: Option<String>,
+    /// Pass every event through and leave settings unsaved until
+    /// `exit_safe_mode`. Also set by holding Shift during launch.
+    safe_mode: bool,
+    /// Started by `relaunch_app`; wait for the instance it replaces to exit.
+    relaunch: bool,
+    /// `editmouse://` URLs the launch opens, on Windows and Linux.
+    links: Vec<String>,
+}
+
+impl LaunchArgs {
+    fn parse(args: &[String]) -> Result<Self, String> {
+        let mut launch = Self::default();
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--hidden" => launch.hidden = true,
+                "--list-devices" => launch.list_devices = true,
+                "--toggle" => launch.toggle = true,
+                "--safe-mode" => launch.safe_mode = true,
+                // Internal, so it's not in `USAGE`.
+                "--relaunch" => launch.relaunch = true,
+                "--profile" => {
+                    let name = args.next().ok_or("--profile needs a profile name")?;
+                    launch.profile = Some(name.clone());
+                }
+                // Read by `configured_config_dir`.
+                "--config-dir" => {
+                    args.next().ok_or("--config-dir needs a directory")?;
+                }
+                arg if arg.starts_with("--config-dir=") => {}
+                arg if arg.starts_with("--profile=") => {
+                    launch.profile = arg.strip_prefix("--profile=").map(str::to_string);
+                }
+                // Process serial number older macOS passes to apps opened
+                // from the Finder.
+                arg if arg.starts_with("-psn_") => {}
+                arg if DeepLink::is_link(arg) => launch.links.push(arg.to_string()),
+                arg => return Err(format!("unknown option {}", arg)),
+            }
+        }
+        Ok(launch)
+    }
+
+    /// True when the launch asks for something to be done rather than for
+    /// the window.
+    fn is_command(&self) -> bool {
+        self.toggle || self.profile.is_some() || !self.links.is_empty()
+    }
+}
+
+/// Scheme of the URLs other tools control the app with.
+const URL_SCHEME: &str = "editmouse";
+
+/// What an `editmouse://` URL asks for: `profile/<name>`, `toggle`, or
+/// `show`.
+#[derive(Debug, Clone, PartialEq)]
+enum DeepLink {
+    Profile(String),
+    Toggle,
+    Show,
+}
+
+impl DeepLink {
+    fn is_link(arg: &str) -> bool {
+        arg.split_once("://")
+            .is_some_and(|(scheme, _)| scheme.eq_ignore_ascii_case(URL_SCHEME))
+    }
+
+    /// Reads a URL, ignoring any query, fragment, or trailing slash.
+    fn parse(url: &str) -> Result<Self, String> {
+        let rest = url
+            .split_once("://")
+            .filter(|(scheme, _)| scheme.eq_ignore_ascii_case(URL_SCHEME))
+            .map(|(_, rest)| rest)
+            .ok_or_else(|| format!("not an {}:// URL", URL_SCHEME))?;
+        let path = rest.split(['?', '#']).next().unwrap_or_default();
+        let segments = path
+            .trim_end_matches('/')
+            .split('/')
+            .map(percent_decode)
+            .collect::<Result<Vec<_>, _>>()?;
+        match segments.iter().map(String::as_str).collect::<Vec<_>>()[..] {
+            ["toggle"] => Ok(DeepLink::Toggle),
+            ["show"] => Ok(DeepLink::Show),
+            ["profile", name] if !name.trim().is_empty() => Ok(DeepLink::Profile(name.to_string())),
+            ["profile", ..] => Err("profile needs a single profile name".to_string()),
+            _ => Err(format!("unknown action {}", path)),
+        }
+    }
+}
+
+/// Decodes the `%XX` escapes of a URL path segment.
+fn percent_decode(segment: &str) -> Result<String, String> {
+    let mut decoded = Vec::with_capacity(segment.len());
+    let mut bytes = segment.bytes();
+    while let Some(byte) = bytes.next() {
+        if byte != b'%' {
+            decoded.push(byte);
+            continue;
+        }
+        let escape = [bytes.next(), bytes.next()];
+        let value = escape
+            .iter()
+            .map(|digit| digit.and_then(|digit| char::from(digit).to_digit(16)))
+            .try_fold(0, |value, digit| Some(value * 16 + digit?))
+            .ok_or_else(|| format!("bad escape in {}", segment))?;
+        decoded.push(value as u8);
+    }
+    String::from_utf8(decoded).map_err(|_| format!("{} isn't UTF-8", segment))
+}
+
+/// Reads the arguments after `edit-mouse ctl` into a request, and whether
+/// to print the reply as JSON.
+fn parse_ctl(args: &[String]) -> Result<(control::Request, bool), String> {
+    let mut json = false;
+    let mut words = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--json" => json = true,
+            // Read by `configured_config_dir`.
+            "--config-dir" => {
+                args.next().ok_or("--config-dir needs a directory")?;
+            }
+            arg if arg.starts_with("--config-dir=") => {}
+            arg => words.push(arg),
+        }
+    }
+    let request = match words[..] {
+        ["status"] => control::Request::Status,
+        ["toggle"] => control::Request::Toggle,
+        ["profile", name] => control::Request::Profile {
+            name: name.to_string(),
+        },
+        ["pause"] => control::Request::Pause { seconds: None },
+        ["pause", duration] => control::Request::Pause {
+            seconds: Some(parse_pause_duration(duration)?),
+        },
+        ["resume"] => control::Request::Resume,
+        ["devices"] => control::Request::Devices,
+        ["cheatsheet", ref options @ ..] => {
+            let mut profile = None;
+            let mut format = CheatsheetFormat::Markdown;
+            let mut include_sensitive = false;
+            for option in options {
+                match *option {
+                    "--html" => format = CheatsheetFormat::Html,
+                    "--include-sensitive" => include_sensitive = true,
+                    name if profile.is_none() && !name.starts_with("--") => {
+                        profile = Some(name.to_string());
+                    }
+                    _ => return Err(CTL_USAGE.to_string()),
+                }
+            }
+            control::Request::Cheatsheet {
+                profile,
+                format,
+                include_sensitive,
+            }
+        }
+        _ => return Err(CTL_USAGE.to_string()),
+    };
+    Ok((request, json))
+}
+
+/// Reads a pause length like `90s`, `30m`, or `2h` into seconds.
+fn parse_pause_duration(duration: &str) -> Result<u64, String> {
+    let scale = match duration.chars().last() {
+        Some('s') => 1,
+        Some('m') => 60,
+        Some('h') => 3600,
+        _ => 0,
+    };
+    duration
+        .get(..duration.len().saturating_sub(1))
+        .and_then(|count| count.parse::<u64>().ok())
+        .filter(|count| *count > 0 && scale > 0)
+        .and_then(|count| count.checked_mul(scale))
+        .ok_or_else(|| {
+            format!(
+                "pause takes a length like 90s, 30m, or 2h, not {}",
+                duration
+            )
+        })
+}
+
+/// A reply's result for people: `key: value` lines, and for devices a line
+/// with the id and name of each.
+#[cfg_attr(not(unix), allow(dead_code))]
+fn ctl_output(result: &serde_json::Value) -> String {
+    let plain = |value: &serde_json::Value| match value {
+        serde_json::Value::String(text) => text.clone(),
+        serde_json::Value::Null => "-".to_string(),
+        value => value.to_string(),
+    };
+    match result {
+        serde_json::Value::Object(fields) => fields
+            .iter()
+            .map(|(key, value)| format!("{}: {}\n", key, plain(value)))
+            .collect(),
+        serde_json::Value::Array(items) => items
+            .iter()
+            .map(|item| format!("{}\t{}\n", plain(&item["id"]), plain(&item["name"])))
+            .collect(),
+        value => format!("{}\n", plain(value)),
+    }
+}
+
+/// Carries out the commands of this launch or of one handed over.
+fn apply_launch_args(app: &tauri::AppHandle, launch: &LaunchArgs) {
+    if launch.safe_mode {
+        enter_safe_mode(app);
+    }
+    if let Some(name) = &launch.profile {
+        if let Err(err) = switch_profile(app, name) {
+            log::error!("launch: failed to switch to {}: {}", name, err);
+        }
+    }
+    if launch.toggle {
+        let enabled = !app.state::<AppState>().is_remap_enabled();
+        if let Err(err) = change_remap_enabled(app, enabled) {
+            log::error!("launch: failed to toggle remapping: {}", err);
+        }
+    }
+    for link in &launch.links {
+        open_link(app, link);
+    }
+}
+
+/// Carries out an `editmouse://` URL the way the matching launch flag
+/// would. A URL it can't read is logged and otherwise ignored.
+fn open_link(app: &tauri::AppHandle, url: &str) {
+    log::info!("links: opening {}", url);
+    let launch = match DeepLink::parse(url) {
+        Ok(DeepLink::Show) => return show_main_window(app),
+        Ok(DeepLink::Profile(name)) => LaunchArgs {
+            profile: Some(name),
+            ..LaunchArgs::default()
+        },
+        Ok(DeepLink::Toggle) => LaunchArgs {
+            toggle: true,
+            ..LaunchArgs::default()
+        },
+        Err(err) => return log::error!("links: ignoring {}: {}", url, err),
+    };
+    apply_launch_args(app, &launch);
+}
+
+/// What `edit-mouse ctl status` prints; every other control request
+/// answers with it too, once it's done.
+#[derive(Debug, Serialize)]
+struct ControlStatus {
+    remap_enabled: bool,
+    paused: RemapPauseStatus,
+    active_profile: String,
+    selected_device: Option<String>,
+    engine: EngineState,
+}
+
+/// Carries out a request from the control socket or the integration
+/// server.
+fn control_request(
+    app: &tauri::AppHandle,
+    request: control::Request,
+) -> Result<serde_json::Value, String> {
+    log::info!("control: {:?}", request);
+    let state = app.state::<AppState>();
+    match request {
+        control::Request::Status => {}
+        control::Request::Toggle => change_remap_enabled(app, !state.is_remap_enabled())?,
+        control::Request::Profile { name } => {
+            switch_profile(app, &name)?;
+        }
+        control::Request::Pause { seconds } => {
+            pause_remapping_for(app, seconds.map(Duration::from_secs));
+        }
+        control::Request::Resume => {
+            if state.resume_remapping() {
+                announce_remap_pause(app);
+            }
+        }
+        control::Request::Devices => {
+            let filter = DeviceFilter::from(&state.snapshot_settings());
+            let devices = list_mouse_devices(&state.hid, filter)?;
+            return serde_json::to_value(devices).map_err(|err| err.to_string());
+        }
+        control::Request::Cheatsheet {
+            profile,
+            format,
+            include_sensitive,
+        } => {
+            let settings = state.snapshot_settings();
+            let profile = profile.unwrap_or_else(|| settings.active_profile.clone());
+            let sheet = render_cheatsheet(&settings, &profile, format, include_sensitive)?;
+            return Ok(sheet.into());
+        }
+    }
+    let settings = state.snapshot_settings();
+    let status = ControlStatus {
+        remap_enabled: settings.remap_enabled,
+        paused: state.remap_pause_status(),
+        active_profile: settings.active_profile,
+        selected_device: settings.selected_device,
+        engine: state.engine_state(),
+    };
+    serde_json::to_value(status).map_err(|err| err.to_string())
+}
+
+/// `edit-mouse ctl`: sends one request to the running app, prints the
+/// reply, and returns the exit code.
+#[cfg(unix)]
+fn run_ctl(args: &[String]) -> i32 {
+    let (request, json) = match parse_ctl(args) {
+        Ok(parsed) => parsed,
+        Err(err) => {
+            eprintln!("{}", err);
+            return 2;
+        }
+    };
+    let configured = configured_config_dir(
+        std::env::args_os().skip(1),
+        std::env::var_os(CONFIG_DIR_ENV),
+        std::env::current_exe().ok().as_deref(),
+        Path::exists,
+    );
+    let Some(dir) = configured.map(|(dir, _)| dir).or_else(default_config_dir) else {
+        eprintln!("HOME isn't set; pass --config-dir");
+        return 1;
+    };
+    match control::send(&dir.join(control::SOCKET_FILE), &request) {
+        Ok(control::Reply {
+            ok: true, result, ..
+        }) => {
+            let result = result.unwrap_or_default();
+            if json {
+                println!(
+                    "{}",
+                    serde_json::to_string_pretty(&result).unwrap_or_default()
+                );
+            } else {
+                print!("{}", ctl_output(&result));
+            }
+            0
+        }
+        Ok(reply) => {
+            eprintln!("{}", reply.error.unwrap_or_default());
+            1
+        }
+        Err(err) => {
+            eprintln!("{}", err);
+            1
+        }
+    }
+}
+
+#[cfg(not(unix))]
+fn run_ctl(args: &[String]) -> i32 {
+    if let Err(err) = parse_ctl(args) {
+        eprintln!("{}", err);
+        return 2;
+    }
+    eprintln!("edit-mouse ctl is only supported on macOS and Linux");
+    1
+}
+
+/// The config directory Tauri picks when nothing moves it, found without a
+/// running app: the platform's config directory plus `BUNDLE_IDENTIFIER`.
+#[cfg(unix)]
+fn default_config_dir() -> Option<PathBuf> {
+    let home = std::env::var_os("HOME").map(PathBuf::from);
+    #[cfg(target_os = "macos")]
+    let base = home?.join("Library/Application Support");
+    #[cfg(not(target_os = "macos"))]
+    let base = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_absolute())
+        .or_else(|| home.map(|home| home.join(".config")))?;
+    Some(base.join(BUNDLE_IDENTIFIER))
+}
+
+/// Events the integration server passes on to its clients.
+const INTEGRATION_EVENTS: [&str; 11] = [
+    "action-fired",
+    "devices-changed",
+    "dpi-changed",
+    "game-mode-changed",
+    "profile-changed",
+    "remap-enabled-changed",
+    "remap-engine-state",
+    "remap-error",
+    "remap-pause-changed",
+    "secure-input-changed",
+    "selected-device-missing",
+];
+
+/// The integration server `Settings::integration_server` last asked for.
+#[derive(Default)]
+struct IntegrationRunner {
+    server: Option<integration::Server>,
+    /// Why the server that's turned on isn't running.
+    error: Option<String>,
+}
+
+/// What `get_integration_server` returns.
+#[derive(Debug, Serialize)]
+struct IntegrationServerStatus {
+    enabled: bool,
+    port: u16,
+    token: String,
+    running: bool,
+    clients: usize,
+    error: Option<String>,
+}
+
+/// Starts, restarts or stops the integration server to match `config`.
+/// Changing the port or the token restarts it, which drops every client.
+fn sync_integration_server(app: &tauri::AppHandle, config: &IntegrationServer) {
+    let state = app.state::<AppState>();
+    let Ok(mut runner) = state.integration.lock() else {
+        return;
+    };
+    let wanted = config.enabled && !config.token.is_empty();
+    let current = runner.server.as_ref();
+    if wanted && current.is_some_and(|server| server.serves(config.port, &config.token)) {
+        return;
+    }
+    runner.error = None;
+    if runner.server.take().is_some() {
+        log::info!("integration: stopped");
+    }
+    if !config.enabled {
+        return;
+    }
+    if config.token.is_empty() {
+        runner.error = Some("no token; regenerate one to start the server".to_string());
+        return log::error!("integration: not starting without a token");
+    }
+    let handle = app.clone();
+    let requested = move |request| control_request(&handle, request);
+    match integration::Server::start(config.port, config.token.clone(), requested) {
+        Ok(server) => {
+            log::info!("integration: listening on 127.0.0.1:{}", config.port);
+            runner.server = Some(server);
+        }
+        Err(err) => {
+            log::error!("integration: {}", err);
+            runner.error = Some(err);
+        }
+    }
+}
+
+/// Passes each of `INTEGRATION_EVENTS` on to the integration server's
+/// clients, while it runs.
+fn forward_integration_events(app: &tauri::AppHandle) {
+    for event in INTEGRATION_EVENTS {
+        let handle = app.clone();
+        app.listen_any(event, move |emitted| {
+            let state = handle.state::<AppState>();
+            let Ok(runner) = state.integration.lock() else {
+                return;
+            };
+            if let Some(server) = &runner.server {
+                let payload = serde_json::from_str(emitted.payload()).unwrap_or_default();
+                server.broadcast(event, payload);
+            }
+        });
+    }
+}
+
+/// 128 bits as hex. `RandomState` keys come from the system's random
+/// source, which is plenty for a token that only guards 127.0.0.1.
+fn generate_integration_token() -> String {
+    use std::hash::BuildHasher;
+    (0..2)
+        .map(|_| {
+            let mut hasher = std::collections::hash_map::RandomState::new().build_hasher();
+            hasher.write_u128(
+                SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map(|elapsed| elapsed.as_nanos())
+                    .unwrap_or_default(),
+            );
+            format!("{:016x}", hasher.finish())
+        })
+        .collect()
+}
+
+fn integration_status(state: &AppState) -> IntegrationServerStatus {
+    let config = state.snapshot_settings().integration_server;
+    let (running, clients, error) = match state.integration.lock() {
+        Ok(runner) => (
+            runner.server.is_some(),
+            runner
+                .server
+                .as_ref()
+                .map_or(0, integration::Server::clients),
+            runner.error.clone(),
+        ),
+        Err(_) => (false, 0, None),
+    };
+    IntegrationServerStatus {
+        enabled: config.enabled,
+        port: config.port,
+        token: config.token,
+        running,
+        clients,
+        error,
+    }
+}
+
+#[tauri::command]
+fn get_integration_server(state: State<AppState>) -> IntegrationServerStatus {
+    integration_status(&state)
+}
+
+/// Turns the integration server on or off and sets its port, generating
+/// a token the first time it's turned on.
+#[tauri::command]
+fn set_integration_server(
+    app: tauri::AppHandle,
+    state: State<AppState>,
+    enabled: bool,
+    port: u16,
+) -> Result<IntegrationServerStatus, String> {
+    state.change_settings(&app, None, |settings| {
+        let config = &mut settings.integration_server;
+        config.enabled = enabled;
+        config.port = port;
+        if enabled && config.token.is_empty() {
+            config.token = generate_integration_token();
+        }
+        Ok(())
+    })?;
+    log::info!(
+        "integration: {} on port {}",
+        if enabled { "on" } else { "off" },
+        port
+    );
+    Ok(integration_status(&state))
+}
+
+/// Replaces the integration token; clients connected with the old one are
+/// dropped.
+#[tauri::command]
+fn regenerate_integration_token(
+    app: tauri::AppHandle,
+    state: State<AppState>,
+) -> Result<IntegrationServerStatus, String> {
+    state.change_settings(&app, None, |settings| {
+        settings.integration_server.token = generate_integration_token();
+        Ok(())
+    })?;
+    log::info!("integration: token regenerated");
+    Ok(integration_status(&state))
+}
+
+/// Makes this binary the handler of `editmouse://` URLs for the current
+/// user, which Windows and Linux then pass as an argument. macOS reads the
+/// scheme from Info.plist instead.
+#[cfg(windows)]
+fn register_url_scheme(app: &tauri::AppHandle) -> Result<(), String> {
+    use windows_sys::Win32::System::Registry::{RegSetKeyValueW, HKEY_CURRENT_USER, REG_SZ};
+
+    let binary = tauri::process::current_binary(&app.env()).map_err(|err| err.to_string())?;
+    let wide = |text: &str| text.encode_utf16().chain(Some(0)).collect::<Vec<u16>>();
+    let key = format!(r"Software\Classes\{}", URL_SCHEME);
+    let command = format!("\"{}\" \"%1\"", binary.display());
+    let values = [
+        (key.clone(), None, "URL:Edit Mouse".to_string()),
+        (key.clone(), Some("URL Protocol"), String::new()),
+        (format!(r"{}\shell\open\command", key), None, command),
+    ];
+    for (key, name, data) in values {
+        let (key, data) = (wide(&key), wide(&data));
+        let name = name.map(wide);
+        let status = unsafe {
+            RegSetKeyValueW(
+                HKEY_CURRENT_USER,
+                key.as_ptr(),
+                name.as_ref().map_or(std::ptr::null(), |name| name.as_ptr()),
+                REG_SZ,
+                data.as_ptr().cast(),
+                (data.len() * 2) as u32,
+            )
+        };
+        if status != 0 {
+            return Err(format!(
+                "could not register the URL scheme (error {})",
+                status
+            ));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+fn register_url_scheme(app: &tauri::AppHandle) -> Result<(), String> {
+    // An AppImage runs from a mount that changes with every launch.
+    let binary = match std::env::var_os("APPIMAGE") {
+        Some(image) => PathBuf::from(image),
+        None => tauri::process::current_binary(&app.env()).map_err(|err| err.to_string())?,
+    };
+    let dir = std::env::var_os("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".local/share")))
+        .ok_or("neither XDG_DATA_HOME nor HOME is set")?
+        .join("applications");
+    let name = format!("{}-url.desktop", app.config().identifier);
+    let entry = format!(
+        "[Desktop Entry]\nType=Application\nName=Edit Mouse\nExec=\"{}\" %u\nNoDisplay=true\nMimeType=x-scheme-handler/{};\n",
+        binary.display(),
+        URL_SCHEME
+    );
+    let path = dir.join(&name);
+    if fs::read_to_string(&path).is_ok_and(|existing| existing == entry) {
+        return Ok(());
+    }
+    fs::create_dir_all(&dir).map_err(|err| err.to_string())?;
+    fs::write(&path, entry).map_err(|err| err.to_string())?;
+    let status = std::process::Command::new("xdg-mime")
+        .args(["default", &name])
+        .arg(format!("x-scheme-handler/{}", URL_SCHEME))
+        .status()
+        .map_err(|err| format!("xdg-mime: {}", err))?;
+    if !status.success() {
+        return Err(format!("xdg-mime exited with {}", status));
+    }
+    Ok(())
+}
+
+#[cfg(not(any(windows, target_os = "linux")))]
+fn register_url_scheme(_app: &tauri::AppHandle) -> Result<(), String> {
+    Ok(())
+}
+
+/// Runs the app; `main` only calls this.
+pub fn run() {
+    let args: Vec<String> = std::env::args_os()
+        .skip(1)
+        .map(|arg| arg.to_string_lossy().into_owned())
+        .collect();
+    if args.first().is_some_and(|arg| arg == "ctl") {
+        std::process::exit(run_ctl(&args[1..]));
+    }
+    // Passed on as the flag, so a running instance enters safe mode too.
+    let mut args = args;
+    if shift_held() && !args.iter().any(|arg| arg == "--safe-mode") {
+        args.push("--safe-mode".to_string());
+    }
+    let launch = match LaunchArgs::parse(&args) {
+        Ok(launch) => launch,
+        Err(err) => {
+            eprintln!("{}\n{}", err, USAGE);
+            std::process::exit(2);
+        }
+    };
+    if launch.list_devices {
+        let devices = mouse_interfaces(&SharedHidApi::default()).and_then(|devices| {
+            serde_json::to_string_pretty(&devices).map_err(|err| err.to_string())
+        });
+        match devices {
+            Ok(devices) => println!("{}", devices),
+            Err(err) => {
+                eprintln!("mouse-scan error: {}", err);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
+    logging::init();
+    tauri::Builder::default()
+        .manage(AppState::default())
+        .on_window_event(|window, event| match event {
+            WindowEvent::CloseRequested { api, .. } => {
+                api.prevent_close();
+                let behavior = window
+                    .state::<AppState>()
+                    .snapshot_settings()
+                    .close_behavior;
+                close_main_window(window.app_handle(), behavior);
+            }
+            WindowEvent::Focused(focused) if window.label() == "main" => {
+                window.state::<AppState>().set_own_window_focused(*focused);
+            }
+            WindowEvent::Focused(false) if window.label() == QUICK_PANEL_WINDOW => {
+                window.state::<AppState>().note_quick_panel_blurred();
+                if let Err(err) = window.destroy() {
+                    log::error!("quick-panel: failed to close: {}", err);
+                }
+            }
+            WindowEvent::Moved(_) | WindowEvent::Resized(_) if window.label() == "main" => {
+                window_geometry_changed(window.app_handle());
+            }
+            WindowEvent::ThemeChanged(_) if window.label() == "main" => {
+                system_theme_changed(window.app_handle());
+            }
+            _ => {}
+        })
+        .plugin(autostart_plugin())
+        .plugin(tauri_plugin_notification::init())
+        .on_tray_icon_event(tray_icon_clicked)
+        .on_menu_event(|app, event| {
+            let item_id = event.id().as_ref();
+            if item_id == "tray_show" {
+                show_main_window(app);
+            } else if item_id == "tray_hide" {
+                hide_main_window(app);
+            } else if item_id == "tray_quick_panel" {
+                if let Err(err) = open_quick_panel(app, None) {
+                    log::error!("tray: failed to open the quick panel: {}", err);
+                }
+            } else if item_id == "tray_remap_enabled" {
+                let enabled = !app.state::<AppState>().is_remap_enabled();
+                if let Err(err) = change_remap_enabled(app, enabled) {
+                    log::error!("tray: failed to toggle remapping: {}", err);
+                }
+            } else if let Some(seconds) = item_id.strip_prefix("tray_pause:") {
+                let duration = seconds
+                    .parse()
+                    .ok()
+                    .filter(|seconds| *seconds > 0)
+                    .map(Duration::from_secs);
+                pause_remapping_for(app, duration);
+            } else if item_id == "tray_resume" {
+                if app.state::<AppState>().resume_remapping() {
+                    announce_remap_pause(app);
+                }
+            } else if item_id == "tray_exclude_app" {
+                exclude_last_external_app(app);
+            } else if item_id == "tray_config_reveal" {
+                if let Err(err) = reveal_config_dir(app) {
+                    log::error!("tray: failed to reveal the config folder: {}", err);
+                }
+            } else if item_id == "tray_config_reload" {
+                if let Err(err) = reload_settings_from_disk(app) {
+                    log::error!("tray: failed to reload settings: {}", err);
+                }
+            } else if item_id == "tray_config_reset" {
+                reset_from_tray(app);
+            } else if item_id == "tray_safe_mode" {
+                enter_safe_mode(app);
+            } else if item_id == "tray_safe_mode_exit" {
+                leave_safe_mode(app);
+            } else if item_id == "tray_restart" {
+                if let Err(err) = relaunch_app(app.clone()) {
+                    log::error!("tray: failed to restart: {}", err);
+                }
+            } else if item_id == "tray_quit" {
+                quit_app(app);
+            } else if let Some(device_id) = item_id.strip_prefix(TRAY_DEVICE_PREFIX) {
+                if let Err(err) = select_device(app, device_id) {
+                    log::error!("tray: failed to select {}: {}", device_id, err);
+                }
+            } else if let Some(name) = item_id.strip_prefix(TRAY_PROFILE_PREFIX) {
+                if let Err(err) = switch_profile(app, name) {
+                    log::error!("tray: failed to switch to {}: {}", name, err);
+                }
+            }
+        })
+        .invoke_handler(tauri::generate_handler![
+            get_mouse_devices,
+            get_device_details,
+            start_button_capture,
+            cancel_button_capture,
+            start_shortcut_capture,
+            cancel_shortcut_capture,
+            test_action,
+            resolve_mapping,
+            test_action_payload,
+            test_click_at,
+            set_event_monitoring,
+            get_frontmost_app,
+            list_running_apps,
+            list_installed_browsers,
+            get_permission_status,
+            get_action_capabilities,
+            get_app_info,
+            mark_onboarding_complete,
+            open_privacy_settings,
+            request_permission,
+            get_remap_engine_state,
+            get_remap_engine_status,
+            restart_remap_engine,
+            get_remap_errors,
+            get_integration_server,
+            set_integration_server,
+            regenerate_integration_token,
+            get_settings_recovery,
+            get_settings_migration,
+            get_settings_sync,
+            get_settings_warnings,
+            set_dock_icon_visible,
+            answer_close_request,
+            relaunch_app,
+            get_recent_logs,
+            set_log_level,
+            export_diagnostics,
+            get_previous_crash,
+            get_system_theme,
+            get_available_locales,
+            get_usage_stats,
+            reset_usage_stats,
+            get_action_history,
+            dismiss_previous_crash,
+            detect_conflicts,
+            ignore_conflict,
+            check_for_updates,
+            list_settings_backups,
+            restore_settings_backup,
+            reset_settings,
+            reset_device,
+            reset_button,
+            forget_device,
+            copy_device_config,
+            get_device_predecessors,
+            set_device_disabled,
+            set_device_ignored,
+            export_settings,
+            export_cheatsheet,
+            import_settings,
+            import_linearmouse,
+            list_presets,
+            apply_preset,
+            export_device_preset,
+            import_device_preset,
+            get_remap_diagnostics,
+            set_monitor_mode,
+            get_latency_stats,
+            reset_latency_stats,
+            get_device_battery,
+            measure_report_rate,
+            start_hid_debug,
+            stop_hid_debug,
+            #[cfg(feature = "mock-engine")]
+            mock_engine::simulate_button_event,
+            #[cfg(feature = "mock-engine")]
+            mock_engine::get_simulated_output,
+            probe_device_capabilities,
+            get_autostart_enabled,
+            set_autostart_enabled,
+            hide_window,
+            flush_settings,
+            get_settings,
+            validate_settings,
+            save_settings,
+            undo_settings_change,
+            get_settings_history,
+            get_config_paths,
+            reveal_config_folder,
+            reload_settings,
+            set_button_action,
+            set_selected_device,
+            set_remap_enabled,
+            get_hotkeys,
+            set_toggle_hotkey,
+            set_window_hotkey,
+            set_quick_panel_hotkey,
+            set_cycle_device_hotkey,
+            show_quick_panel,
+            pause_remapping,
+            resume_remapping,
+            get_remap_pause,
+            get_safe_mode,
+            exit_safe_mode,
+            get_secure_input,
+            get_hud,
+            show_input_overlay,
+            list_profiles,
+            create_profile,
+            duplicate_profile,
+            delete_profile,
+            set_active_profile
+        ])
+        .setup(move |app| {
+            match config_dir(app.handle()) {
+                Ok((dir, _)) => {
+                    let wait = if launch.relaunch {
+                        RELAUNCH_WAIT
+                    } else {
+                        Duration::ZERO
+                    };
+                    match single_instance::claim(&dir, wait) {
+                        Ok(Some(claim)) => {
+                            app.manage(claim);
+                        }
+                        Ok(None) => {
+                            if launch.relaunch {
+                                eprintln!("instance: the instance this replaces is still running");
+                            }
+                            if single_instance::forward(&dir, &args, INSTANCE_HANDOFF_WAIT) {
+                                eprintln!("instance: handed off to the running instance");
+                                std::process::exit(0);
+                            }
+                            // Carrying on would add a second tap and tray icon.
+                            eprintln!("instance: another instance runs but doesn't answer");
+                            std::process::exit(1);
+                        }
+                        Err(err) => {
+                            log::error!("instance: failed to claim the config folder: {}", err)
+                        }
+                    }
+                    let handle = app.handle().clone();
+                    let launched = move |args: Vec<String>| handle_relaunch(&handle, &args);
+                    if let Err(err) = single_instance::listen(&dir, launched) {
+                        log::error!("instance: failed to listen for later launches: {}", err);
+                    }
+                    #[cfg(unix)]
+                    {
+                        let handle = app.handle().clone();
+                        let socket = dir.join(control::SOCKET_FILE);
+                        let requested = move |request| control_request(&handle, request);
+                        if let Err(err) = control::listen(&socket, requested) {
+                            log::error!("control: failed to listen: {}", err);
+                        }
+                    }
+                }
+                Err(err) => log::error!("instance: {}", err),
+            }
+            // Only once this is the instance that stays, so a launch that hands
+            // off neither writes to its log nor takes its crash report.
+            match log_dir(app.handle()) {
+                Ok(dir) => {
+                    let crash = logging::take_crash(&dir);
+                    logging::start(dir);
+                    if let Some(report) = crash {
+                        report_previous_crash(app.handle(), report);
+                    }
+                }
+                Err(err) => eprintln!("logging: {}", err),
+            }
+            log_mouse_devices(&app.state::<AppState>().hid);
+            match stats_path(app.handle()) {
+                Ok(path) => start_usage_stats(app.handle(), path),
+                Err(err) => log::error!("stats: {}", err),
+            }
+            start_settings_writer(app.handle());
+
+            let state = app.state::<AppState>().inner().clone();
+            let _ = state.notifier.set(app.handle().clone());
+            let _ = state.feedback.set(feedback::start());
+            // Before settings load, so what loading changes isn't saved.
+            if launch.safe_mode {
+                log::warn!("safe mode: remapping is off and settings are read-only");
+                state.set_safe_mode(true);
+            }
+            if let Ok(mut settings) = load_settings(app.handle()) {
+                if let Ok(devices) = list_mouse_devices(&state.hid, DeviceFilter::from(&settings)) {
+                    state.update_devices(&devices);
+                    let now = SystemTime::now()
+                        .duration_since(UNIX_EPOCH)
+                        .map(|elapsed| elapsed.as_secs())
+                        .unwrap_or_default();
+                    state.note_devices_seen(&devices, now);
+                    let settled = settle_device_configs(&mut settings, &devices);
+                    let recorded = settings.record_sightings(&state.take_sightings(now));
+                    if settled || recorded {
+                        let _ = persist_settings(app.handle(), settings.clone());
+                    }
+                }
+                state.update_settings(settings);
+            }
+            log::set_max_level(state.snapshot_settings().log_level.filter());
+            // Before the window shows, so its title bar starts out right.
+            apply_window_theme(app.handle(), &state.snapshot_settings().theme);
+            watch_system_theme(app.handle());
+            watch_session(app.handle());
+            keyboard_layout::watch();
+            warn_missing_device(app.handle(), &state);
+            warn_conflicts(app.handle().clone(), &state);
+            let handle = app.handle().clone();
+            hotkeys::set_handler(move |purpose| hotkey_pressed(&handle, purpose));
+            sync_hotkeys(app.handle(), &state.snapshot_settings());
+            forward_integration_events(app.handle());
+            sync_integration_server(app.handle(), &state.snapshot_settings().integration_server);
+            migrate_autostart(app.handle());
+            if let Err(err) = register_url_scheme(app.handle()) {
+                log::error!("links: {}", err);
+            }
+            watch_settings_file(app.handle().clone(), state.clone());
+            watch_devices(app.handle().clone(), state.clone());
+            watch_for_updates(app.handle().clone(), state.clone());
+            watch_battery(state.clone());
+            schedule::watch(app.handle().clone(), state.clone());
+            watch_idle(app.handle().clone(), state.clone());
+            frontmost::watch(app.handle().clone(), state.clone());
+            let permissions = permissions::status();
+            if !permissions.all_granted() {
+                log::info!("permissions: {:?}", permissions);
+            }
+            if let Err(err) = app.emit("permission-status", permissions) {
+                log::error!("permissions: failed to emit permission-status: {}", err);
+            }
+            // Probed once, before a swipe action needs it.
+            swipe_strategy();
+            #[cfg(feature = "mock-engine")]
+            {
+                let engine = mock_engine::MockEngine::default();
+                engine.start(app.handle().clone(), state.clone());
+                app.manage(engine);
+            }
+            start_mouse_remap(app.handle().clone(), state);
+
+            let show_dock_icon = app.state::<AppState>().snapshot_settings().show_dock_icon;
+            if let Err(err) = apply_dock_icon(app.handle(), show_dock_icon) {
+                log::error!("dock: {}", err);
+            }
+
+            if let Some(tray) = app.handle().tray_by_id("main") {
+                tray.set_menu(Some(tray_menu(app.handle())?))?;
+            }
+            refresh_tray_icon(app.handle());
+            apply_tray_click_action(
+                app.handle(),
+                app.state::<AppState>()
+                    .snapshot_settings()
+                    .tray_click_action,
+            );
+            apply_tray_title(app.handle(), &app.state::<AppState>().snapshot_settings());
+            restore_window_state(app.handle());
+            apply_launch_args(app.handle(), &launch);
+            // The window is created hidden so it doesn't flash before this.
+            let start_hidden = app.state::<AppState>().snapshot_settings().start_hidden;
+            if launch.hidden || launch.is_command() || start_hidden || launched_hidden() {
+                app.state::<AppState>().set_event_monitoring(false);
+            } else {
+                show_main_window(app.handle());
+            }
+            Ok(())
+        })
+        .build(tauri::generate_context!())
+        .expect("error while building tauri application")
+        .run(handle_run_event);
+}
+
+/// Writes the usage counts and settings on the way out. On macOS, clicking the Dock
+/// icon, when it's shown, brings the window back, and `editmouse://` URLs
+/// arrive here whether or not they launched the app.
+fn handle_run_event(app: &tauri::AppHandle, event: tauri::RunEvent) {
+    match event {
+        tauri::RunEvent::Exit => {
+            let state = app.state::<AppState>();
+            state.flush_usage_stats();
+            save_settings_before_exit(app);
+        }
+        #[cfg(target_os = "macos")]
+        tauri::RunEvent::Reopen { .. } => show_main_window(app),
+        #[cfg(target_os = "macos")]
+        tauri::RunEvent::Opened { urls } => {
+            for url in urls {
+                open_link(app, url.as_str());
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Switches between a regular app with a Dock icon and a menu bar one.
+#[cfg(target_os = "macos")]
+fn apply_dock_icon(app: &tauri::AppHandle, visible: bool) -> Result<(), String> {
+    let policy = if visible {
+        tauri::ActivationPolicy::Regular
+    } else {
+        tauri::ActivationPolicy::Accessory
+    };
+    app.set_activation_policy(policy)
+        .map_err(|err| format!("failed to set the activation policy: {}", err))
+}
+
+#[cfg(not(target_os = "macos"))]
+fn apply_dock_icon(_app: &tauri::AppHandle, visible: bool) -> Result<(), String> {
+    if visible {
+        Err("the Dock icon is only supported on macOS".to_string())
+    } else {
+        Ok(())
+    }
+}
+
+/// Light or dark, as the system is or as a `theme` setting comes out.
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+enum SystemTheme {
+    Light,
+    Dark,
+}
+
+/// What `theme` shows: "light" and "dark" as they are, and anything else,
+/// meaning "system", as `system`.
+fn effective_theme(theme: &str, system: SystemTheme) -> SystemTheme {
+    match theme {
+        "light" => SystemTheme::Light,
+        "dark" => SystemTheme::Dark,
+        _ => system,
+    }
+}
+
+/// Dark Mode, or Auto while it's dark.
+#[cfg(target_os = "macos")]
+fn system_theme() -> SystemTheme {
+    use objc2_foundation::{NSString, NSUserDefaults};
+
+    let style = NSUserDefaults::standardUserDefaults()
+        .stringForKey(&NSString::from_str("AppleInterfaceStyle"));
+    if style.is_some_and(|style| style.to_string() == "Dark") {
+        SystemTheme::Dark
+    } else {
+        SystemTheme::Light
+    }
+}
+
+/// GNOME's color scheme, or the GTK theme's name when the scheme is left
+/// at its default.
+#[cfg(target_os = "linux")]
+fn system_theme() -> SystemTheme {
+    let setting = |key| {
+        let output = std::process::Command::new("gsettings")
+            .args(["get", "org.gnome.desktop.interface", key])
+            .output()
+            .ok()
+            .filter(|output| output.status.success())?;
+        Some(String::from_utf8_lossy(&output.stdout).to_lowercase())
+    };
+    let dark = match setting("color-scheme") {
+        Some(scheme) if scheme.contains("prefer-") => scheme.contains("dark"),
+        _ => setting("gtk-theme").is_some_and(|theme| theme.contains("dark")),
+    };
+    if dark {
+        SystemTheme::Dark
+    } else {
+        SystemTheme::Light
+    }
+}
+
+/// The app mode chosen under Personalization > Colors.
+#[cfg(windows)]
+fn system_theme() -> SystemTheme {
+    use windows_sys::Win32::System::Registry::{RegGetValueW, HKEY_CURRENT_USER, RRF_RT_REG_DWORD};
+
+    let wide = |text: &str| text.encode_utf16().chain(Some(0)).collect::<Vec<u16>>();
+    let key = wide(r"Software\Microsoft\Windows\CurrentVersion\Themes\Personalize");
+    let value = wide("AppsUseLightTheme");
+    let mut light: u32 = 1;
+    let mut size = std::mem::size_of::<u32>() as u32;
+    let status = unsafe {
+        RegGetValueW(
+            HKEY_CURRENT_USER,
+            key.as_ptr(),
+            value.as_ptr(),
+            RRF_RT_REG_DWORD,
+            std::ptr::null_mut(),
+            (&mut light as *mut u32).cast(),
+            &mut size,
+        )
+    };
+    if status == 0 && light == 0 {
+        SystemTheme::Dark
+    } else {
+        SystemTheme::Light
+    }
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "linux", windows)))]
+fn system_theme() -> SystemTheme {
+    SystemTheme::Light
+}
+
+/// Gives the window the theme of `theme`, which its title bar and the
+/// webview's `prefers-color-scheme` follow; "system" leaves it following
+/// the system.
+fn apply_window_theme(app: &tauri::AppHandle, theme: &str) {
+    let Some(window) = app.get_webview_window("main") else {
+        return;
+    };
+    let hint = match theme {
+        "light" => Some(tauri::Theme::Light),
+        "dark" => Some(tauri::Theme::Dark),
+        _ => None,
+    };
+    if let Err(err) = window.set_theme(hint) {
+        log::error!("theme: failed to set the window theme: {}", err);
+    }
+}
+
+/// Emits `system-theme-changed` when the system's theme isn't the one last
+/// reported.
+fn system_theme_changed(app: &tauri::AppHandle) {
+    let theme = system_theme();
+    let state = app.state::<AppState>();
+    let Ok(mut reported) = state.system_theme.lock() else {
+        return;
+    };
+    if reported.replace(theme) == Some(theme) {
+        return;
+    }
+    drop(reported);
+    log::info!("theme: system theme is now {:?}", theme);
+    if let Err(err) = app.emit("system-theme-changed", theme) {
+        log::error!("theme: failed to emit system-theme-changed: {}", err);
+    }
+}
+
+/// Notes the system's theme and reports its changes. macOS announces them
+/// with a distributed notification, even while the window has a theme of
+/// its own; elsewhere the window's `ThemeChanged`, forwarded by
+/// `on_window_event`, comes while it follows the system. Must run on the
+/// main thread.
+fn watch_system_theme(app: &tauri::AppHandle) {
+    if let Ok(mut reported) = app.state::<AppState>().system_theme.lock() {
+        *reported = Some(system_theme());
+    }
+    #[cfg(target_os = "macos")]
+    {
+        use block2::RcBlock;
+        use objc2_foundation::{NSDistributedNotificationCenter, NSNotification, NSString};
+        use std::ptr::NonNull;
+
+        let handle = app.clone();
+        let block = RcBlock::new(move |_: NonNull<NSNotification>| system_theme_changed(&handle));
+        let name = NSString::from_str("AppleInterfaceThemeChangedNotification");
+        let observer = unsafe {
+            NSDistributedNotificationCenter::defaultCenter()
+                .addObserverForName_object_queue_usingBlock(Some(&name), None, None, &block)
+        };
+        // Observed for the lifetime of the process.
+        std::mem::forget(observer);
+    }
+}
+
+#[tauri::command]
+fn get_system_theme() -> SystemTheme {
+    system_theme()
+}
+
+/// A language `Settings::locale` can name.
+#[derive(Debug, Serialize, Clone, PartialEq)]
+struct AvailableLocale {
+    code: &'static str,
+    /// In the language itself, such as "Deutsch".
+    name: &'static str,
+}
+
+/// The languages of the tray and notifications, besides following the
+/// system's with `SYSTEM_LOCALE`.
+#[tauri::command]
+fn get_available_locales() -> Vec<AvailableLocale> {
+    Locale::ALL
+        .into_iter()
+        .map(|locale| AvailableLocale {
+            code: locale.code(),
+            name: locale.name(),
+        })
+        .collect()
+}
+
+/// Pauses remapping while the session is locked or switched away from, and
+/// resumes it once it's back. Synthetic input at the login window is
+/// useless at best, and may be replayed after unlocking.
+fn session_changed(app: &tauri::AppHandle, active: bool) {
+    let state = app.state::<AppState>();
+    if active {
+        state.nudge_profile_schedule();
+    }
+    if state.set_session_active(active) {
+        if active {
+            log::info!("session: back in front, remapping resumes");
+        } else {
+            log::info!("session: locked or switched away from, remapping paused");
+        }
+    }
+}
+
+/// Follows screen locks and fast user switching: distributed
+/// notifications announce locks, the workspace session switches. Must run
+/// on the main thread.
+#[cfg(target_os = "macos")]
+fn watch_session(app: &tauri::AppHandle) {
+    use block2::RcBlock;
+    use objc2_app_kit::{
+        NSWorkspace, NSWorkspaceDidWakeNotification, NSWorkspaceSessionDidBecomeActiveNotification,
+        NSWorkspaceSessionDidResignActiveNotification,
+    };
+    use objc2_foundation::{
+        NSDistributedNotificationCenter, NSNotification, NSNotificationCenter, NSNotificationName,
+        NSString,
+    };
+    use std::cell::Cell;
+    use std::ptr::NonNull;
+    use std::rc::Rc;
+
+    // Locked, and switched away from.
+    let inactive = Rc::new(Cell::new((false, false)));
+    let observe = |center: &NSNotificationCenter,
+                   name: &NSNotificationName,
+                   locked: Option<bool>,
+                   away: Option<bool>| {
+        let handle = app.clone();
+        let inactive = Rc::clone(&inactive);
+        let block = RcBlock::new(move |_: NonNull<NSNotification>| {
+            let (was_locked, was_away) = inactive.get();
+            let now = (locked.unwrap_or(was_locked), away.unwrap_or(was_away));
+            inactive.set(now);
+            session_changed(&handle, !now.0 && !now.1);
+        });
+        let observer = unsafe {
+            center.addObserverForName_object_queue_usingBlock(Some(name), None, None, &block)
+        };
+        // Observed for the lifetime of the process.
+        std::mem::forget(observer);
+    };
+    let distributed = NSDistributedNotificationCenter::defaultCenter();
+    observe(
+        &distributed,
+        &NSString::from_str("com.apple.screenIsLocked"),
+        Some(true),
+        None,
+    );
+    observe(
+        &distributed,
+        &NSString::from_str("com.apple.screenIsUnlocked"),
+        Some(false),
+        None,
+    );
+    let workspace = NSWorkspace::sharedWorkspace().notificationCenter();
+    observe(
+        &workspace,
+        unsafe { NSWorkspaceSessionDidResignActiveNotification },
+        None,
+        Some(true),
+    );
+    observe(
+        &workspace,
+        unsafe { NSWorkspaceSessionDidBecomeActiveNotification },
+        None,
+        Some(false),
+    );
+    // Changes neither, but has the schedules catch up with the clock.
+    observe(
+        &workspace,
+        unsafe { NSWorkspaceDidWakeNotification },
+        None,
+        None,
+    );
+}
+
+/// Follows screen locks and session switches through a message-only window
+/// registered for this session's `WM_WTSSESSION_CHANGE`.
+#[cfg(windows)]
+fn watch_session(app: &tauri::AppHandle) {
+    use std::cell::Cell;
+    use std::sync::OnceLock;
+    use windows_sys::Win32::Foundation::{HWND, LPARAM, LRESULT, WPARAM};
+    use windows_sys::Win32::System::LibraryLoader::GetModuleHandleW;
+    use windows_sys::Win32::System::RemoteDesktop::{
+        WTSRegisterSessionNotification, NOTIFY_FOR_THIS_SESSION,
+    };
+    use windows_sys::Win32::UI::WindowsAndMessaging::{
+        CreateWindowExW, DefWindowProcW, DispatchMessageW, GetMessageW, RegisterClassW,
+        HWND_MESSAGE, MSG, WM_WTSSESSION_CHANGE, WNDCLASSW, WTS_CONSOLE_CONNECT,
+        WTS_CONSOLE_DISCONNECT, WTS_REMOTE_CONNECT, WTS_REMOTE_DISCONNECT, WTS_SESSION_LOCK,
+        WTS_SESSION_UNLOCK,
+    };
+
+    static APP: OnceLock<tauri::AppHandle> = OnceLock::new();
+    thread_local! {
+        // Locked, and disconnected from the console or remote desktop.
+        static INACTIVE: Cell<(bool, bool)> = const { Cell::new((false, false)) };
+    }
+
+    unsafe extern "system" fn window_proc(
+        hwnd: HWND,
+        message: u32,
+        wparam: WPARAM,
+        lparam: LPARAM,
+    ) -> LRESULT {
+        if message != WM_WTSSESSION_CHANGE {
+            return unsafe { DefWindowProcW(hwnd, message, wparam, lparam) };
+        }
+        let Some(app) = APP.get() else {
+            return 0;
+        };
+        let (mut locked, mut away) = INACTIVE.get();
+        match wparam as u32 {
+            WTS_SESSION_LOCK => locked = true,
+            WTS_SESSION_UNLOCK => locked = false,
+            WTS_CONSOLE_DISCONNECT | WTS_REMOTE_DISCONNECT => away = true,
+            WTS_CONSOLE_CONNECT | WTS_REMOTE_CONNECT => away = false,
+            _ => return 0,
+        }
+        INACTIVE.set((locked, away));
+        let state = app.state::<AppState>();
+        guard_event(
+            app,
+            &state,
+            || session_changed(app, !locked && !away),
+            || (),
+        );
+        0
+    }
+
+    if APP.set(app.clone()).is_err() {
+        return;
+    }
+    std::thread::spawn(|| {
+        let wide = |text: &str| text.encode_utf16().chain(Some(0)).collect::<Vec<u16>>();
+        let class_name = wide("EditMouseSession");
+        let instance = unsafe { GetModuleHandleW(std::ptr::null()) };
+        let class = WNDCLASSW {
+            lpfnWndProc: Some(window_proc),
+            hInstance: instance,
+            lpszClassName: class_name.as_ptr(),
+            ..unsafe { std::mem::zeroed() }
+        };
+        let hwnd = unsafe {
+            RegisterClassW(&class);
+            CreateWindowExW(
+                0,
+                class_name.as_ptr(),
+                class_name.as_ptr(),
+                0,
+                0,
+                0,
+                0,
+                0,
+                HWND_MESSAGE,
+                std::ptr::null_mut(),
+                instance,
+                std::ptr::null(),
+            )
+        };
+        if hwnd.is_null()
+            || unsafe { WTSRegisterSessionNotification(hwnd, NOTIFY_FOR_THIS_SESSION) } == 0
+        {
+            log::warn!("session: can't follow screen locks, remapping continues while locked");
+            return;
+        }
+        let mut msg: MSG = unsafe { std::mem::zeroed() };
+        while unsafe { GetMessageW(&mut msg, std::ptr::null_mut(), 0, 0) } > 0 {
+            unsafe { DispatchMessageW(&msg) };
+        }
+    });
+}
+
+/// Follows logind's view of this session, `XDG_SESSION_ID`, through
+/// `gdbus monitor`: its `LockedHint` and `Active` properties and its lock
+/// requests.
+#[cfg(target_os = "linux")]
+fn watch_session(app: &tauri::AppHandle) {
+    use std::io::BufRead;
+    use std::process::{Command, Stdio};
+
+    let Ok(id) = std::env::var("XDG_SESSION_ID") else {
+        log::info!("session: XDG_SESSION_ID isn't set, so screen locks go unnoticed");
+        return;
+    };
+    let path = logind_session_path(&id);
+    let app = app.clone();
+    std::thread::spawn(move || {
+        let child = Command::new("gdbus")
+            .args(["monitor", "--system", "--dest", "org.freedesktop.login1"])
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn();
+        let mut child = match child {
+            Ok(child) => child,
+            Err(err) => {
+                log::info!(
+                    "session: can't run gdbus, so screen locks go unnoticed: {}",
+                    err
+                );
+                return;
+            }
+        };
+        let Some(stdout) = child.stdout.take() else {
+            return;
+        };
+        let (mut locked, mut active) = (false, true);
+        for line in std::io::BufReader::new(stdout).lines() {
+            let Ok(line) = line else {
+                break;
+            };
+            let (now_locked, now_active) = logind_session_change(&line, &path);
+            if now_locked.is_none() && now_active.is_none() {
+                continue;
+            }
+            locked = now_locked.unwrap_or(locked);
+            active = now_active.unwrap_or(active);
+            session_changed(&app, !locked && active);
+        }
+        let _ = child.kill();
+        let _ = child.wait();
+        log::warn!("session: gdbus monitor stopped, so screen locks go unnoticed");
+    });
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "linux", windows)))]
+fn watch_session(_app: &tauri::AppHandle) {}
+
+/// Object path logind gives session `id`: letters as they are, digits too
+/// except first, and every other byte as `_` and its hex.
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+fn logind_session_path(id: &str) -> String {
+    let mut path = "/org/freedesktop/login1/session/".to_string();
+    for (index, byte) in id.bytes().enumerate() {
+        if byte.is_ascii_alphabetic() || (index > 0 && byte.is_ascii_digit()) {
+            path.push(char::from(byte));
+        } else {
+            path.push_str(&format!("_{:02x}", byte));
+        }
+    }
+    path
+}
+
+/// Whether a line of `gdbus monitor` has the session at `path` locked and
+/// whether in front, each when the line says.
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+fn logind_session_change(line: &str, path: &str) -> (Option<bool>, Option<bool>) {
+    let Some(signal) = line
+        .strip_prefix(path)
+        .and_then(|rest| rest.strip_prefix(':'))
+        .map(str::trim_start)
+    else {
+        return (None, None);
+    };
+    if signal.starts_with("org.freedesktop.login1.Session.Lock ") {
+        return (Some(true), None);
+    }
+    if signal.starts_with("org.freedesktop.login1.Session.Unlock ") {
+        return (Some(false), None);
+    }
+    if !signal.starts_with("org.freedesktop.DBus.Properties.PropertiesChanged ") {
+        return (None, None);
+    }
+    let property = |name: &str| {
+        let value = &signal[signal.find(&format!("'{}': <", name))? + name.len() + 5..];
+        Some(value.starts_with("true"))
+    };
+    (property("LockedHint"), property("Active"))
+}
+
+/// Shows or hides the Dock icon and saves the choice. Showing it also shows
+/// the window, so the icon doesn't stand for a hidden one.
+#[tauri::command]
+fn set_dock_icon_visible(
+    app: tauri::AppHandle,
+    state: State<AppState>,
+    visible: bool,
+) -> Result<Settings, String> {
+    apply_dock_icon(&app, visible)?;
+    let settings = state.change_settings(&app, None, |settings| {
+        settings.show_dock_icon = visible;
+        Ok(())
+    })?;
+    if visible {
+        show_main_window(&app);
+    }
+    Ok(settings)
+}
+
+fn show_main_window(app: &tauri::AppHandle) {
+    app.state::<AppState>()
+        .window_revealed
+        .store(true, Ordering::Relaxed);
+    if let Some(window) = app.get_webview_window("main") {
+        let _ = window.show();
+        let _ = window.unminimize();
+        let _ = window.set_focus();
+    }
+}
+
+fn hide_main_window(app: &tauri::AppHandle) {
+    app.state::<AppState>().set_event_monitoring(false);
+    app.state::<AppState>().set_own_window_focused(false);
+    app.state::<AppState>().flush_settings_later();
+    save_window_state(app);
+    if let Some(window) = app.get_webview_window("main") {
+        let _ = window.hide();
+    }
+}
+
+/// Label of the HUD's window, which is only open while it shows.
+const HUD_WINDOW: &str = "hud";
+
+/// How long the connect HUD and the profile toast stay up.
+const CONNECT_HUD_DURATION: Duration = Duration::from_secs(3);
+const PROFILE_TOAST_DURATION: Duration = Duration::from_millis(1500);
+
+/// Width of the HUD, and the height of the connect HUD's header and of each
+/// mapping's row, and of the profile toast, in logical pixels.
+const HUD_WIDTH: f64 = 280.0;
+const HUD_HEADER_HEIGHT: f64 = 64.0;
+const HUD_ROW_HEIGHT: f64 = 22.0;
+const PROFILE_TOAST_HEIGHT: f64 = 44.0;
+
+/// Gap between the top of the display and the HUD.
+const HUD_TOP_MARGIN: f64 = 48.0;
+
+/// Shows the queued HUD, if any, and dismisses it once its time is up
+/// unless a newer one replaced it. The window can't take focus or clicks,
+/// so the frontmost app keeps both.
+fn show_hud(app: &tauri::AppHandle, state: &AppState) {
+    let Some((shown, hud)) = state.take_hud() else {
+        return;
+    };
+    let (height, duration) = match &hud {
+        Hud::Connect(connect) => (
+            HUD_HEADER_HEIGHT + HUD_ROW_HEIGHT * connect.mappings.len() as f64,
+            CONNECT_HUD_DURATION,
+        ),
+        Hud::Profile { .. } => (PROFILE_TOAST_HEIGHT, PROFILE_TOAST_DURATION),
+    };
+    let result = match app.get_webview_window(HUD_WINDOW) {
+        Some(window) => window
+            .set_size(tauri::LogicalSize::new(HUD_WIDTH, height))
+            .and_then(|_| window.show()),
+        None => open_hud_window(app, height),
+    };
+    if let Err(err) = result {
+        log::error!("hud: failed to show the HUD: {}", err);
+        return;
+    }
+    // A window that's still loading asks with `get_hud` instead.
+    if let Err(err) = app.emit_to(HUD_WINDOW, "hud", &hud) {
+        log::error!("hud: failed to emit hud: {}", err);
+    }
+    let app = app.clone();
+    let state = state.clone();
+    std::thread::spawn(move || {
+        std::thread::sleep(duration);
+        if state.dismiss_hud(shown) {
+            if let Some(window) = app.get_webview_window(HUD_WINDOW) {
+                let _ = window.destroy();
+            }
+        }
+    });
+}
+
+/// Opens the HUD window `height` logical pixels tall, at the top of the
+/// display the cursor is on.
+fn open_hud_window(app: &tauri::AppHandle, height: f64) -> tauri::Result<()> {
+    let mut builder = tauri::WebviewWindowBuilder::new(
+        app,
+        HUD_WINDOW,
+        tauri::WebviewUrl::App("hud.html".into()),
+    )
+    .title("Edit Mouse")
+    .inner_size(HUD_WIDTH, height)
+    .decorations(false)
+    .resizable(false)
+    .always_on_top(true)
+    .visible_on_all_workspaces(true)
+    .skip_taskbar(true)
+    .focused(false)
+    .focusable(false);
+    let monitor = app
+        .cursor_position()
+        .ok()
+        .and_then(|cursor| app.monitor_from_point(cursor.x, cursor.y).ok().flatten())
+        .or_else(|| app.primary_monitor().ok().flatten());
+    if let Some(monitor) = monitor {
+        let scale = monitor.scale_factor();
+        let origin = monitor.position().to_logical::<f64>(scale);
+        let size = monitor.size().to_logical::<f64>(scale);
+        builder = builder.position(
+            origin.x + (size.width - HUD_WIDTH) / 2.0,
+            origin.y + HUD_TOP_MARGIN,
+        );
+    }
+    builder.build()?.set_ignore_cursor_events(true)
+}
+
+/// Label of the input overlay's window, open while `show_input_overlay`
+/// keeps it shown.
+const INPUT_OVERLAY_WINDOW: &str = "input-overlay";
+
+/// Size of the input overlay, and its gap from the edges of the display,
+/// in logical pixels.
+const INPUT_OVERLAY_WIDTH: f64 = 160.0;
+const INPUT_OVERLAY_HEIGHT: f64 = 200.0;
+const INPUT_OVERLAY_MARGIN: f64 = 24.0;
+
+/// Shows a mouse lighting up each button pressed, and the action it fired,
+/// over everything, for recording screencasts; or closes it. Like the HUD
+/// it takes neither focus nor clicks, and sits on the display the cursor is
+/// on, in `InputOverlaySettings::corner`.
+#[tauri::command]
+fn show_input_overlay(
+    app: tauri::AppHandle,
+    state: State<AppState>,
+    show: bool,
+) -> Result<(), String> {
+    if !show {
+        state.close_input_overlay_stream();
+        if let Some(window) = app.get_webview_window(INPUT_OVERLAY_WINDOW) {
+            window
+                .destroy()
+                .map_err(|err| format!("Failed to close the input overlay: {}", err))?;
+        }
+        return Ok(());
+    }
+    let corner = state.snapshot_settings().input_overlay.corner;
+    let result = match app.get_webview_window(INPUT_OVERLAY_WINDOW) {
+        Some(window) => window.show(),
+        None => open_input_overlay_window(&app, corner),
+    };
+    result.map_err(|err| format!("Failed to show the input overlay: {}", err))?;
+    if let Some(wake) = state.open_input_overlay_stream() {
+        let state = state.inner().clone();
+        std::thread::spawn(move || watch_input_overlay(&app, &state, wake));
+    }
+    Ok(())
+}
+
+/// Opens the input overlay's window in `corner`.
+fn open_input_overlay_window(app: &tauri::AppHandle, corner: ScreenCorner) -> tauri::Result<()> {
+    let mut builder = tauri::WebviewWindowBuilder::new(
+        app,
+        INPUT_OVERLAY_WINDOW,
+        tauri::WebviewUrl::App("overlay.html".into()),
+    )
+    .title("Edit Mouse")
+    .inner_size(INPUT_OVERLAY_WIDTH, INPUT_OVERLAY_HEIGHT)
+    .decorations(false)
+    .resizable(false)
+    .always_on_top(true)
+    .visible_on_all_workspaces(true)
+    .skip_taskbar(true)
+    .focused(false)
+    .focusable(false);
+    if let Some((x, y)) = input_overlay_position(app, corner) {
+        builder = builder.position(x, y);
+    }
+    builder.build()?.set_ignore_cursor_events(true)
+}
+
+/// Moves an open input overlay to `corner`.
+fn move_input_overlay(app: &tauri::AppHandle, corner: ScreenCorner) {
+    let Some(window) = app.get_webview_window(INPUT_OVERLAY_WINDOW) else {
+        return;
+    };
+    if let Some((x, y)) = input_overlay_position(app, corner) {
+        if let Err(err) = window.set_position(tauri::LogicalPosition::new(x, y)) {
+            log::error!("overlay: failed to move the input overlay: {}", err);
+        }
+    }
+}
+
+/// Top-left corner of the input overlay in `corner` of the display the
+/// cursor is on, in logical pixels.
+fn input_overlay_position(app: &tauri::AppHandle, corner: ScreenCorner) -> Option<(f64, f64)> {
+    let monitor = app
+        .cursor_position()
+        .ok()
+        .and_then(|cursor| app.monitor_from_point(cursor.x, cursor.y).ok().flatten())
+        .or_else(|| app.primary_monitor().ok().flatten())?;
+    let scale = monitor.scale_factor();
+    let origin = monitor.position().to_logical::<f64>(scale);
+    let size = monitor.size().to_logical::<f64>(scale);
+    let left = origin.x + INPUT_OVERLAY_MARGIN;
+    let right = origin.x + size.width - INPUT_OVERLAY_WIDTH - INPUT_OVERLAY_MARGIN;
+    let top = origin.y + INPUT_OVERLAY_MARGIN;
+    let bottom = origin.y + size.height - INPUT_OVERLAY_HEIGHT - INPUT_OVERLAY_MARGIN;
+    Some(match corner {
+        ScreenCorner::TopLeft => (left, top),
+        ScreenCorner::TopRight => (right, top),
+        ScreenCorner::BottomLeft => (left, bottom),
+        ScreenCorner::BottomRight => (right, bottom),
+    })
+}
+
+/// Hides the input overlay once `InputOverlaySettings::auto_hide_secs`
+/// pass without a press, and shows it again at the next, until
+/// `show_input_overlay` ends the stream `wake` comes from.
+fn watch_input_overlay(
+    app: &tauri::AppHandle,
+    state: &AppState,
+    wake: std::sync::mpsc::Receiver<()>,
+) {
+    use std::sync::mpsc::RecvTimeoutError;
+
+    let mut hidden = false;
+    loop {
+        let auto_hide = state.snapshot_settings().input_overlay.auto_hide_secs;
+        let woken = if hidden || auto_hide == 0 {
+            wake.recv().map_err(|_| RecvTimeoutError::Disconnected)
+        } else {
+            wake.recv_timeout(Duration::from_secs(auto_hide.into()))
+        };
+        if woken == Err(RecvTimeoutError::Disconnected) {
+            return;
+        }
+        let Some(window) = app.get_webview_window(INPUT_OVERLAY_WINDOW) else {
+            state.close_input_overlay_stream();
+            return;
+        };
+        let result = match woken {
+            Ok(()) if hidden => window.show(),
+            Ok(()) => Ok(()),
+            Err(_) => window.hide(),
+        };
+        match result {
+            Ok(()) => hidden = woken.is_err(),
+            Err(err) => log::error!("overlay: failed to show or hide the input overlay: {}", err),
+        }
+    }
+}
+
+/// Tells the user at launch that the selected device isn't connected, so
+/// remapping doing nothing doesn't look like a bug. The window, which
+/// usually loads after this, reads the same from `get_remap_engine_status`.
+fn warn_missing_device(app: &tauri::AppHandle, state: &AppState) {
+    let Some(missing) = state.missing_device() else {
+        return;
+    };
+    log::info!("devices: selected device {} isn't connected", missing.id);
+    let settings = state.snapshot_settings();
+    if settings.notify_device_changes {
+        state.post_notification(
+            Locale::from_setting(&settings.locale).format(Text::NoticeMissing, &[&missing.name]),
+        );
+    }
+    if let Err(err) = app.emit("selected-device-missing", missing) {
+        log::error!("devices: failed to emit selected-device-missing: {}", err);
+    }
+}
+
+/// Keeps the crash report of the previous run for the window, which asks
+/// with `get_previous_crash` when it loads after this, and emits it as
+/// `previous-crash`.
+fn report_previous_crash(app: &tauri::AppHandle, report: String) {
+    log::warn!("panic: the previous run crashed");
+    let state = app.state::<AppState>();
+    if let Ok(mut guard) = state.previous_crash.lock() {
+        *guard = Some(report.clone());
+    }
+    if let Err(err) = app.emit("previous-crash", report) {
+        log::error!("panic: failed to emit previous-crash: {}", err);
+    }
+}
+
+/// The crash report the previous run left, until `dismiss_previous_crash`.
+#[tauri::command]
+fn get_previous_crash(state: State<AppState>) -> Option<String> {
+    state.previous_crash.lock().ok()?.clone()
+}
+
+#[tauri::command]
+fn dismiss_previous_crash(state: State<AppState>) {
+    if let Ok(mut guard) = state.previous_crash.lock() {
+        *guard = None;
+    }
+}
+
+/// Logs the other remapping software running at launch and emits the
+/// conflicts not ignored as `conflicts-detected`. The window, which usually
+/// loads after this, asks `detect_conflicts` itself.
+fn warn_conflicts(app: tauri::AppHandle, state: &AppState) {
+    let ignored = state.snapshot_settings().ignored_conflicts;
+    std::thread::spawn(move || {
+        let conflicts: Vec<conflicts::Conflict> = conflicts::detect(&ignored)
+            .into_iter()
+            .inspect(|conflict| {
+                log::warn!(
+                    "conflicts: {} is running{}{}",
+                    conflict.name,
+                    if conflict.event_tap {
+                        " with a mouse event tap"
+                    } else {
+                        ""
+                    },
+                    if conflict.ignored { " (ignored)" } else { "" }
+                )
+            })
+            .filter(|conflict| !conflict.ignored)
+            .collect();
+        if conflicts.is_empty() {
+            return;
+        }
+        if let Err(err) = app.emit("conflicts-detected", conflicts) {
+            log::error!("conflicts: failed to emit conflicts-detected: {}", err);
+        }
+    });
+}
+
+/// Remapping software running now that may remap presses again, the
+/// ignored ones included.
+#[tauri::command]
+async fn detect_conflicts(state: State<'_, AppState>) -> Result<Vec<conflicts::Conflict>, String> {
+    let ignored = state.snapshot_settings().ignored_conflicts;
+    tauri::async_runtime::spawn_blocking(move || conflicts::detect(&ignored))
+        .await
+        .map_err(|err| err.to_string())
+}
+
+/// Stops the launch warning about the conflict `id`.
+#[tauri::command]
+fn ignore_conflict(
+    app: tauri::AppHandle,
+    state: State<AppState>,
+    id: String,
+) -> Result<(), String> {
+    state
+        .change_settings(&app, None, |settings| {
+            if !settings.ignored_conflicts.contains(&id) {
+                settings.ignored_conflicts.push(id);
+            }
+            Ok(())
+        })
+        .map(drop)
+}
+
+/// File in the config directory with the window's last size and position.
+const WINDOW_STATE_FILE: &str = "window-state.json";
+
+/// How long the window has to stay put before a move or resize is saved.
+const WINDOW_STATE_DEBOUNCE: Duration = Duration::from_millis(500);
+
+/// Size and position of the main window, in physical pixels.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+struct WindowGeometry {
+    x: i32,
+    y: i32,
+    width: u32,
+    height: u32,
+    /// Name of the display the window was on.
+    monitor: Option<String>,
+}
+
+/// A connected display's name and work area (x, y, width, height).
+type DisplayArea = (Option<String>, (i32, i32, u32, u32));
+
+/// Where to restore `saved` on the connected `displays`: on its own display
+/// when that's still connected, otherwise on the one whose work area is
+/// nearest, shrunk and moved to fit inside the work area.
+fn fit_window_geometry(saved: &WindowGeometry, displays: &[DisplayArea]) -> Option<WindowGeometry> {
+    let centre = (
+        i64::from(saved.x) + i64::from(saved.width / 2),
+        i64::from(saved.y) + i64::from(saved.height / 2),
+    );
+    let distance = |(x, y, width, height): (i32, i32, u32, u32)| {
+        let (left, top) = (i64::from(x), i64::from(y));
+        let dx = centre.0 - centre.0.clamp(left, left + i64::from(width));
+        let dy = centre.1 - centre.1.clamp(top, top + i64::from(height));
+        dx * dx + dy * dy
+    };
+    let (name, area) = displays
+        .iter()
+        .find(|(name, _)| name.is_some() && *name == saved.monitor)
+        .or_else(|| displays.iter().min_by_key(|(_, area)| distance(*area)))?;
+    let (x, y, width, height) = *area;
+    let fitted_width = saved.width.min(width);
+    let fitted_height = saved.height.min(height);
+    let room = |extent: u32, fitted: u32| i32::try_from(extent - fitted).unwrap_or(i32::MAX);
+    Some(WindowGeometry {
+        x: saved
+            .x
+            .clamp(x, x.saturating_add(room(width, fitted_width))),
+        y: saved
+            .y
+            .clamp(y, y.saturating_add(room(height, fitted_height))),
+        width: fitted_width,
+        height: fitted_height,
+        monitor: name.clone(),
+    })
+}
+
+fn window_state_path(app: &tauri::AppHandle) -> Result<PathBuf, String> {
+    Ok(config_dir(app)?.0.join(WINDOW_STATE_FILE))
+}
+
+/// Writes the window's geometry to `WINDOW_STATE_FILE`. Full-screen and
+/// minimized windows are left alone, so leaving those states restores the
+/// size the window had before.
+fn save_window_state(app: &tauri::AppHandle) {
+    let Some(window) = app.get_webview_window("main") else {
+        return;
+    };
+    if window.is_fullscreen().unwrap_or(false) || window.is_minimized().unwrap_or(false) {
+        return;
+    }
+    let (Ok(position), Ok(size)) = (window.outer_position(), window.inner_size()) else {
+        return;
+    };
+    let geometry = WindowGeometry {
+        x: position.x,
+        y: position.y,
+        width: size.width,
+        height: size.height,
+        monitor: window
+            .current_monitor()
+            .ok()
+            .flatten()
+            .and_then(|monitor| monitor.name().cloned()),
+    };
+    let saved = window_state_path(app).and_then(|path| {
+        let data = serde_json::to_vec_pretty(&geometry).map_err(|err| err.to_string())?;
+        write_atomically(&path, |file| file.write_all(&data)).map_err(|err| err.to_string())
+    });
+    if let Err(err) = saved {
+        log::error!("window: failed to save its size and position: {}", err);
+    }
+}
+
+/// Saves the geometry once the window has stopped moving and resizing for
+/// `WINDOW_STATE_DEBOUNCE`, from a thread started by the first change.
+fn window_geometry_changed(app: &tauri::AppHandle) {
+    let state = app.state::<AppState>().inner().clone();
+    let Ok(mut guard) = state.window_moved.lock() else {
+        return;
+    };
+    let pending = guard.replace(Instant::now()).is_some();
+    drop(guard);
+    if pending {
+        return;
+    }
+    let app = app.clone();
+    std::thread::spawn(move || loop {
+        std::thread::sleep(WINDOW_STATE_DEBOUNCE);
+        let Ok(mut guard) = state.window_moved.lock() else {
+            return;
+        };
+        if guard.is_some_and(|moved| moved.elapsed() >= WINDOW_STATE_DEBOUNCE) {
+            *guard = None;
+            drop(guard);
+            save_window_state(&app);
+            return;
+        }
+    });
+}
+
+/// Puts the window back where `save_window_state` last saw it, fitted to
+/// the displays connected now. Called before the window is first shown.
+fn restore_window_state(app: &tauri::AppHandle) {
+    let Some(window) = app.get_webview_window("main") else {
+        return;
+    };
+    let Ok(path) = window_state_path(app) else {
+        return;
+    };
+    let Ok(data) = fs::read_to_string(&path) else {
+        return;
+    };
+    let saved: WindowGeometry = match serde_json::from_str(&data) {
+        Ok(saved) => saved,
+        Err(err) => {
+            log::warn!("window: ignoring {}: {}", path.display(), err);
+            return;
+        }
+    };
+    let displays: Vec<DisplayArea> = window
+        .available_monitors()
+        .unwrap_or_default()
+        .iter()
+        .map(|monitor| {
+            let area = monitor.work_area();
+            (
+                monitor.name().cloned(),
+                (
+                    area.position.x,
+                    area.position.y,
+                    area.size.width,
+                    area.size.height,
+                ),
+            )
+        })
+        .collect();
+    let Some(geometry) = fit_window_geometry(&saved, &displays) else {
+        return;
+    };
+    let _ = window.set_size(tauri::PhysicalSize::new(geometry.width, geometry.height));
+    let _ = window.set_position(tauri::PhysicalPosition::new(geometry.x, geometry.y));
+    // A placed window shouldn't be moved next to the tray icon.
+    app.state::<AppState>()
+        .window_revealed
+        .store(true, Ordering::Relaxed);
+}
+
+/// Quits from the tray or the close button. The event tap or hook goes away
+/// with the process, so no button stays remapped.
+fn quit_app(app: &tauri::AppHandle) {
+    log::info!("app: quitting");
+    save_settings_before_exit(app);
+    save_window_state(app);
+    app.exit(0);
+}
+
+/// Longest a relaunched instance waits for the one it replaces to exit
+/// before handing its arguments to it instead.
+const RELAUNCH_WAIT: Duration = Duration::from_secs(10);
+
+/// Longest a later launch waits for the running instance to answer, which
+/// may still be starting up when both launch at once.
+const INSTANCE_HANDOFF_WAIT: Duration = Duration::from_secs(5);
+
+/// Arguments for the instance `relaunch_app` starts: the config directory
+/// this one was given, `--relaunch`, and `--hidden` unless the window is
+/// showing. Commands like `--toggle` aren't carried out a second time.
+fn relaunch_args(args: &[String], hidden: bool) -> Vec<String> {
+    let mut relaunch = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if arg == "--config-dir" {
+            relaunch.push(arg.clone());
+            relaunch.extend(args.next().cloned());
+        } else if arg.starts_with("--config-dir=") {
+            relaunch.push(arg.clone());
+        }
+    }
+    relaunch.push("--relaunch".to_string());
+    if hidden {
+        relaunch.push("--hidden".to_string());
+    }
+    relaunch
+}
+
+/// Quits and starts again, for permissions macOS only applies to a new
+/// process. The new instance waits for this one to exit, so the event tap
+/// and hotkeys are free before it sets up its own.
+#[tauri::command]
+fn relaunch_app(app: tauri::AppHandle) -> Result<(), String> {
+    let binary = tauri::process::current_binary(&app.env()).map_err(|err| err.to_string())?;
+    let args: Vec<String> = std::env::args_os()
+        .skip(1)
+        .map(|arg| arg.to_string_lossy().into_owned())
+        .collect();
+    let hidden = !app
+        .get_webview_window("main")
+        .is_some_and(|window| window.is_visible().unwrap_or(false));
+    let args = relaunch_args(&args, hidden);
+    std::process::Command::new(&binary)
+        .args(&args)
+        .spawn()
+        .map_err(|err| format!("couldn't start {}: {}", binary.display(), err))?;
+    log::info!("app: relaunching with {:?}", args);
+    quit_app(&app);
+    Ok(())
+}
+
+/// Where the log files go: the platform's app log directory, or a `logs`
+/// folder in a config directory chosen by flag, environment, or
+/// `portable.flag`, so a portable setup keeps them beside its settings.
+fn log_dir(app: &tauri::AppHandle) -> Result<PathBuf, String> {
+    let (dir, source) = config_dir(app)?;
+    if source != ConfigDirSource::Default {
+        return Ok(dir.join("logs"));
+    }
+    app.path().app_log_dir().map_err(|err| err.to_string())
+}
+
+/// Most lines `get_recent_logs` returns.
+const MAX_RECENT_LOGS: usize = 2000;
+
+/// The last `lines` lines of the log, oldest first, for the log view.
+#[tauri::command]
+fn get_recent_logs(app: tauri::AppHandle, lines: usize) -> Result<Vec<String>, String> {
+    Ok(logging::recent(&log_dir(&app)?, lines.min(MAX_RECENT_LOGS)))
+}
+
+#[tauri::command]
+fn set_log_level(
+    app: tauri::AppHandle,
+    state: State<AppState>,
+    level: LogLevel,
+) -> Result<Settings, String> {
+    let settings = state.change_settings(&app, None, |settings| {
+        settings.log_level = level;
+        Ok(())
+    })?;
+    log::info!("logging: level set to {:?}", level);
+    Ok(settings)
+}
+
+/// Log lines `export_diagnostics` includes.
+const DIAGNOSTICS_LOG_LINES: usize = 1000;
+
+/// Serials shorter than this are too likely to turn up in unrelated text to
+/// be replaced everywhere; only the device list's `serial` field hides them.
+const MIN_REDACTED_SERIAL: usize = 4;
+
+/// `status.json` of a diagnostics archive.
+#[derive(Debug, Serialize)]
+struct DiagnosticsStatus {
+    app_version: String,
+    os: &'static str,
+    os_version: Option<String>,
+    arch: &'static str,
+    permissions: permissions::PermissionStatus,
+    engine: RemapEngineStatus,
+    /// Recent engine errors, oldest first.
+    errors: Vec<RemapError>,
+    /// What loading the settings left out.
+    settings_warnings: Vec<SettingsWarning>,
+    latency: LatencyReport,
+}
+
+/// Pairs each distinct serial with `serial-1`, `serial-2`, and so on,
+/// longest serial first so one inside another isn't replaced on its own.
+fn serial_placeholders(serials: impl IntoIterator<Item = String>) -> Vec<(String, String)> {
+    let mut unique: Vec<String> = Vec::new();
+    for serial in serials {
+        if serial.len() >= MIN_REDACTED_SERIAL && !unique.contains(&serial) {
+            unique.push(serial);
+        }
+    }
+    let mut placeholders: Vec<(String, String)> = unique
+        .into_iter()
+        .enumerate()
+        .map(|(index, serial)| (serial, format!("serial-{}", index + 1)))
+        .collect();
+    placeholders.sort_by_key(|(serial, _)| std::cmp::Reverse(serial.len()));
+    placeholders
+}
+
+fn redact_serials(text: &str, placeholders: &[(String, String)]) -> String {
+    placeholders
+        .iter()
+        .fold(text.to_string(), |text, (serial, placeholder)| {
+            text.replace(serial, placeholder)
+        })
+}
+
+/// Strips all but the type from actions this version doesn't know, since a
+/// newer version's action may hold a command to run or text to type.
+fn redact_unknown_actions(value: &mut serde_json::Value) {
+    match value {
+        serde_json::Value::Object(object) => {
+            let unknown = object
+                .get("type")
+                .is_some_and(|kind| kind.is_string() && kind != "gesture")
+                && matches!(
+                    ActionValue::from_json(serde_json::Value::Object(object.clone())),
+                    Ok(ActionValue::Unknown(_))
+                );
+            if unknown {
+                object.retain(|key, _| key == "type");
+                object.insert("redacted".to_string(), true.into());
+            } else {
+                object.values_mut().for_each(redact_unknown_actions);
+            }
+        }
+        serde_json::Value::Array(items) => items.iter_mut().for_each(redact_unknown_actions),
+        _ => {}
+    }
+}
+
+/// Format `render_cheatsheet` writes.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+enum CheatsheetFormat {
+    #[default]
+    Markdown,
+    /// A standalone page, styles included.
+    Html,
+}
+
+impl CheatsheetFormat {
+    fn extension(self) -> &'static str {
+        match self {
+            CheatsheetFormat::Markdown => "md",
+            CheatsheetFormat::Html => "html",
+        }
+    }
+}
+
+/// One table of a cheat sheet: a device's buttons, or an app's overrides of
+/// them, as button, action, and details.
+struct CheatsheetTable {
+    heading: String,
+    rows: Vec<[String; 3]>,
+}
+
+/// A button key as the window labels it.
+fn button_title(key: &str) -> String {
+    match key {
+        "left" => "Left".to_string(),
+        "right" => "Right".to_string(),
+        "middle" => "Middle".to_string(),
+        key => key
+            .strip_prefix("button")
+            .map_or_else(|| key.to_string(), |number| format!("Button {}", number)),
+    }
+}
+
+/// How `value` reads on a cheat sheet: its action and what that carries.
+fn cheatsheet_action(value: &ActionValue, include_sensitive: bool) -> (String, String) {
+    match value {
+        ActionValue::Action(Action::SetDpi { dpi }) => (
+            Action::SetDpi { dpi: *dpi }.name().to_string(),
+            format!("{} DPI", dpi),
+        ),
+        ActionValue::Action(Action::Shortcut {
+            keycode,
+            key,
+            modifiers,
+        }) => (
+            "Shortcut".to_string(),
+            shortcut_label(*keycode, *key, *modifiers),
+        ),
+        ActionValue::Action(action) => (action.name().to_string(), String::new()),
+        ActionValue::Gesture { gesture } => {
+            let directions = [
+                ("Tap", &gesture.tap),
+                ("Up", &gesture.up),
+                ("Down", &gesture.down),
+                ("Left", &gesture.left),
+                ("Right", &gesture.right),
+            ];
+            let details: Vec<String> = directions
+                .iter()
+                .filter(|(_, action)| **action != ActionValue::default())
+                .map(|(direction, action)| {
+                    format!(
+                        "{}: {}",
+                        direction,
+                        cheatsheet_action(action, include_sensitive).0
+                    )
+                })
+                .collect();
+            ("Gesture".to_string(), details.join(", "))
+        }
+        ActionValue::Unknown(value) => match value.get("type").and_then(|kind| kind.as_str()) {
+            Some(kind) if !include_sensitive => {
+                (kind.to_string(), "Unknown action, redacted".to_string())
+            }
+            _ => ("Unknown".to_string(), value.to_string()),
+        },
+    }
+}
+
+/// Rows for `buttons` in `BUTTON_KEYS` order. With `config`, a button's
+/// modifier mappings and trigger are listed in its details too.
+fn cheatsheet_rows(
+    buttons: &HashMap<String, ActionValue>,
+    config: Option<&DeviceConfig>,
+    include_sensitive: bool,
+) -> Vec<[String; 3]> {
+    let layers = |key: &str| config.and_then(|config| config.modifier_mappings.get(key));
+    BUTTON_KEYS
+        .iter()
+        .filter(|key| buttons.contains_key(**key) || layers(key).is_some())
+        .map(|key| {
+            let (action, details) = cheatsheet_action(
+                buttons.get(*key).unwrap_or(&ActionValue::default()),
+                include_sensitive,
+            );
+            let mut details: Vec<String> = Some(details)
+                .filter(|details| !details.is_empty())
+                .into_iter()
+                .collect();
+            for mapping in layers(key).into_iter().flatten() {
+                let (action, payload) = cheatsheet_action(&mapping.action, include_sensitive);
+                let action = if payload.is_empty() {
+                    action
+                } else {
+                    format!("{} ({})", action, payload)
+                };
+                let held = modifier_symbols(modifier_mask(&mapping.modifiers));
+                details.push(format!("{}: {}", held, action));
+            }
+            if config.is_some_and(|config| config.triggers.get(*key) == Some(&Trigger::Release)) {
+                details.push("on release".to_string());
+            }
+            [button_title(key), action, details.join("; ")]
+        })
+        .collect()
+}
+
+/// Renders `profile`'s mappings for printing or a wiki: a table for each
+/// device and for each of its app overrides, after the apps that switch to
+/// the profile. Payloads of unknown actions are left out, as in
+/// diagnostics, unless `include_sensitive`.
+fn render_cheatsheet(
+    settings: &Settings,
+    profile: &str,
+    format: CheatsheetFormat,
+    include_sensitive: bool,
+) -> Result<String, String> {
+    let config = settings
+        .profiles
+        .get(profile)
+        .ok_or_else(|| format!("profile not found: {}", profile))?;
+    let mut notes = Vec::new();
+    if let Some(label) = &config.tray_label {
+        notes.push(format!("Shown in the menu bar as {}.", label));
+    }
+    let apps: Vec<&str> = settings
+        .profile_app_rules
+        .iter()
+        .filter(|rule| rule.profile == profile)
+        .map(|rule| rule.bundle_id.as_str())
+        .collect();
+    if !apps.is_empty() {
+        notes.push(format!("Switched to in {}.", apps.join(", ")));
+    }
+
+    let mut devices: Vec<(&String, &DeviceConfig)> = config
+        .devices
+        .iter()
+        .filter(|(_, device)| !device.pristine)
+        .collect();
+    devices.sort_by(|(a_id, a), (b_id, b)| (&a.name, a_id).cmp(&(&b.name, b_id)));
+    if devices.is_empty() {
+        notes.push("No devices are set up in this profile.".to_string());
+    }
+    let mut tables = Vec::new();
+    for (id, device) in devices {
+        let name = if device.name.is_empty() {
+            id.clone()
+        } else {
+            format!("{} ({})", device.name, id)
+        };
+        tables.push(CheatsheetTable {
+            heading: name.clone(),
+            rows: cheatsheet_rows(&device.buttons, Some(device), include_sensitive),
+        });
+        let mut overrides: Vec<_> = device.app_overrides.iter().collect();
+        overrides.sort_by_key(|(app, _)| *app);
+        for (app, buttons) in overrides {
+            tables.push(CheatsheetTable {
+                heading: format!("{} in {}", name, app),
+                rows: cheatsheet_rows(buttons, None, include_sensitive),
+            });
+        }
+    }
+
+    let title = format!("Edit Mouse: {}", profile);
+    let header = ["Button", "Action", "Details"];
+    Ok(match format {
+        CheatsheetFormat::Markdown => {
+            let cell = |text: &str| text.replace('|', "\\|").replace('\n', " ");
+            let mut blocks = vec![format!("# {}", cell(&title))];
+            blocks.extend(notes.iter().map(|note| cell(note)));
+            for table in &tables {
+                let mut block = format!(
+                    "## {}\n\n| {} |\n| --- | --- | --- |",
+                    cell(&table.heading),
+                    header.join(" | ")
+                );
+                for row in &table.rows {
+                    let row: Vec<String> = row.iter().map(|text| cell(text)).collect();
+                    block.push_str(&format!("\n| {} |", row.join(" | ")));
+                }
+                blocks.push(block);
+            }
+            blocks.join("\n\n") + "\n"
+        }
+        CheatsheetFormat::Html => {
+            let escape = |text: &str| {
+                text.replace('&', "&amp;")
+                    .replace('<', "&lt;")
+                    .replace('>', "&gt;")
+                    .replace('"', "&quot;")
+            };
+            let cells = |tag: &str, row: &[String]| -> String {
+                row.iter()
+                    .map(|text| format!("<{0}>{1}</{0}>", tag, escape(text)))
+                    .collect()
+            };
+            let mut out = format!(
+                "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n<title>{0}</title>\n<style>{1}</style>\n</head>\n<body>\n<h1>{0}</h1>\n",
+                escape(&title),
+                CHEATSHEET_STYLE
+            );
+            for note in &notes {
+                out.push_str(&format!("<p>{}</p>\n", escape(note)));
+            }
+            let header = header.map(str::to_string);
+            for table in &tables {
+                out.push_str(&format!(
+                    "<h2>{}</h2>\n<table>\n<tr>{}</tr>\n",
+                    escape(&table.heading),
+                    cells("th", &header)
+                ));
+                for row in &table.rows {
+                    out.push_str(&format!("<tr>{}</tr>\n", cells("td", row)));
+                }
+                out.push_str("</table>\n");
+            }
+            out.push_str("</body>\n</html>\n");
+            out
+        }
+    })
+}
+
+/// Styles of an HTML cheat sheet, plain enough to print.
+const CHEATSHEET_STYLE: &str =
+    "body{font-family:-apple-system,system-ui,sans-serif;margin:2em;color:#222}\
+    table{border-collapse:collapse;margin-bottom:1.5em}\
+    th,td{border:1px solid #ccc;padding:4px 10px;text-align:left}th{background:#f2f2f2}";
+
+/// The OS release, as the system words it.
+#[cfg(target_os = "macos")]
+fn os_version() -> Option<String> {
+    Some(
+        objc2_foundation::NSProcessInfo::processInfo()
+            .operatingSystemVersionString()
+            .to_string(),
+    )
+}
+
+/// The distribution's name and release from os-release.
+#[cfg(target_os = "linux")]
+fn os_version() -> Option<String> {
+    let release = fs::read_to_string("/etc/os-release").ok()?;
+    release.lines().find_map(|line| {
+        let name = line.strip_prefix("PRETTY_NAME=")?;
+        Some(name.trim_matches('"').to_string())
+    })
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "linux")))]
+fn os_version() -> Option<String> {
+    None
+}
+
+/// Writes a zip for bug reports: the settings and what loading them left
+/// out, the connected devices, permissions, engine status and recent
+/// errors, versions, and the end of the log. Serials and the payloads of unknown actions are redacted.
+/// `path` may be a folder, which gets a dated file name. Returns where the
+/// archive was written.
+#[tauri::command]
+fn export_diagnostics(
+    app: tauri::AppHandle,
+    state: State<AppState>,
+    path: PathBuf,
+) -> Result<PathBuf, String> {
+    state.flush_settings()?;
+    let now = SystemTime::now();
+    let path = if path.is_dir() {
+        let date = &logging::timestamp(now)[..10];
+        path.join(format!("edit-mouse-diagnostics-{}.zip", date))
+    } else {
+        path
+    };
+
+    let settings = Settings {
+        version: SETTINGS_VERSION,
+        ..state.snapshot_settings()
+    };
+    let warnings = state.settings_warnings();
+    let devices: Result<Vec<DeviceDetails>, String> = state.hid.with(|api| {
+        let entries = hid_entries(api);
+        Ok(mouse_device_groups(
+            &entries,
+            DeviceFilter::from(&settings),
+            descriptor_probe(api),
+        )
+        .iter()
+        .map(DeviceDetails::from)
+        .collect())
+    });
+    let mut serials: Vec<String> = settings
+        .profiles
+        .values()
+        .flat_map(|profile| profile.devices.keys())
+        .chain(&settings.selected_device)
+        .chain(
+            warnings
+                .iter()
+                .filter_map(|warning| warning.device_id.as_ref()),
+        )
+        .filter_map(|id| device_id_serial(id))
+        .map(str::to_string)
+        .collect();
+    if let Ok(devices) = &devices {
+        serials.extend(devices.iter().filter_map(|device| device.serial.clone()));
+    }
+    let placeholders = serial_placeholders(serials);
+
+    let mut settings = serde_json::to_value(&settings).map_err(|err| err.to_string())?;
+    redact_unknown_actions(&mut settings);
+    if let Some(token) = settings.pointer_mut("/integration_server/token") {
+        *token = "redacted".into();
+    }
+    let devices = match devices {
+        Ok(mut devices) => {
+            for device in &mut devices {
+                if device.serial.is_some() {
+                    device.serial = Some("redacted".to_string());
+                }
+            }
+            serde_json::to_value(devices).map_err(|err| err.to_string())?
+        }
+        Err(err) => serde_json::json!({ "error": err }),
+    };
+    let status = DiagnosticsStatus {
+        app_version: app.package_info().version.to_string(),
+        os: std::env::consts::OS,
+        os_version: os_version(),
+        arch: std::env::consts::ARCH,
+        permissions: permissions::status(),
+        engine: state.engine_status(),
+        errors: state.remap_errors(),
+        settings_warnings: warnings,
+        latency: state.latency_report(),
+    };
+    let status = serde_json::to_value(status).map_err(|err| err.to_string())?;
+    let log = match log_dir(&app) {
+        Ok(dir) => logging::recent(&dir, DIAGNOSTICS_LOG_LINES).join("\n"),
+        Err(err) => format!("no log: {}", err),
+    };
+
+    let json = |value: &serde_json::Value| {
+        serde_json::to_string_pretty(value)
+            .map(|text| redact_serials(&text, &placeholders).into_bytes())
+            .map_err(|err| err.to_string())
+    };
+    let files = [
+        ("settings.json", json(&settings)?),
+        ("devices.json", json(&devices)?),
+        ("status.json", json(&status)?),
+        (
+            logging::FILE,
+            redact_serials(&log, &placeholders).into_bytes(),
+        ),
+    ];
+    write_atomically(&path, |file| zip::write(file, &files, now)).map_err(|err| err.to_string())?;
+    log::info!("diagnostics: exported to {}", path.display());
+    Ok(path)
+}
+
+/// GitHub's record of the newest release.
+const LATEST_RELEASE_URL: &str =
+    "https://api.github.com/repos/Just-Betr/Edit-Mouse/releases/latest";
+/// How often `auto_check_updates` checks.
+const UPDATE_CHECK_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+/// Wait after launch before the first automatic check, to stay out of
+/// startup's way.
+const UPDATE_CHECK_DELAY: Duration = Duration::from_secs(60);
+/// How often the update watcher wakes to see whether a check is due, and
+/// how soon a failed check is retried.
+const UPDATE_POLL: Duration = Duration::from_secs(15 * 60);
+/// Longest an update check may take, connection included.
+const UPDATE_TIMEOUT_SECS: u32 = 15;
+
+/// Result of `check_for_updates`, and the payload of `update-available`.
+#[derive(Debug, Serialize, Clone, PartialEq)]
+struct UpdateInfo {
+    current: String,
+    latest: String,
+    /// The release's page.
+    url: String,
+    notes: String,
+    /// Whether `latest` is newer than `current`.
+    available: bool,
+}
+
+/// The fields read of GitHub's release JSON.
+#[derive(Debug, Deserialize)]
+struct Release {
+    tag_name: String,
+    html_url: String,
+    #[serde(default)]
+    body: Option<String>,
+}
+
+/// `version`'s numbers, and whether it's a release rather than a
+/// pre-release such as `1.2.0-beta.1`. A leading `v` is ignored.
+fn version_numbers(version: &str) -> Option<(Vec<u64>, bool)> {
+    let version = version.trim().trim_start_matches('v');
+    let (numbers, pre_release) = match version.split_once('-') {
+        Some((numbers, _)) => (numbers, true),
+        None => (version, false),
+    };
+    let numbers = numbers
+        .split('.')
+        .map(|part| part.parse().ok())
+        .collect::<Option<Vec<u64>>>()?;
+    Some((numbers, !pre_release))
+}
+
+/// Whether `latest` is a later version than `current`. `1.2` and `1.2.0`
+/// are the same version, and a release is later than its pre-releases.
+fn is_newer_version(latest: &str, current: &str) -> bool {
+    let (Some((mut latest, latest_release)), Some((mut current, current_release))) =
+        (version_numbers(latest), version_numbers(current))
+    else {
+        return false;
+    };
+    let len = latest.len().max(current.len());
+    latest.resize(len, 0);
+    current.resize(len, 0);
+    (latest, latest_release) > (current, current_release)
+}
+
+/// Reads curl's output, the response body followed by the HTTP status on a
+/// line of its own.
+fn parse_release_response(output: &str, current: &str) -> Result<UpdateInfo, String> {
+    let (body, status) = output
+        .rsplit_once('\n')
+        .ok_or_else(|| "GitHub sent no status".to_string())?;
+    match status.trim() {
+        "200" => {}
+        "403" | "429" => {
+            return Err("GitHub is limiting update checks; try again in an hour".to_string())
+        }
+        "404" => return Err("no release has been published yet".to_string()),
+        status => return Err(format!("GitHub answered with status {}", status)),
+    }
+    let release: Release = serde_json::from_str(body).map_err(|err| err.to_string())?;
+    let latest = release.tag_name.trim_start_matches('v').to_string();
+    Ok(UpdateInfo {
+        available: is_newer_version(&latest, current),
+        current: current.to_string(),
+        latest,
+        url: release.html_url,
+        notes: release.body.unwrap_or_default(),
+    })
+}
+
+/// Asks GitHub for the latest release through curl, which ships with macOS
+/// and Windows and brings its own TLS. Blocks for up to
+/// `UPDATE_TIMEOUT_SECS`.
+fn fetch_latest_release(current: &str) -> Result<UpdateInfo, String> {
+    let mut command = std::process::Command::new("curl");
+    command.args([
+        "--silent",
+        "--show-error",
+        "--location",
+        "--max-time",
+        &UPDATE_TIMEOUT_SECS.to_string(),
+        "--header",
+        "Accept: application/vnd.github+json",
+        "--user-agent",
+        "edit-mouse",
+        "--write-out",
+        "\n%{http_code}",
+        LATEST_RELEASE_URL,
+    ]);
+    // Keeps a console window from flashing up.
+    #[cfg(windows)]
+    std::os::windows::process::CommandExt::creation_flags(&mut command, 0x0800_0000);
+    let output = command
+        .output()
+        .map_err(|err| format!("couldn't run curl: {}", err))?;
+    if !output.status.success() {
+        // curl exits with 6 when the host doesn't resolve, 7 when it can't
+        // connect, and 28 on a timeout.
+        return Err(match output.status.code() {
+            Some(6 | 7 | 28) => "GitHub can't be reached; check the connection".to_string(),
+            _ => String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        });
+    }
+    parse_release_response(&String::from_utf8_lossy(&output.stdout), current)
+}
+
+/// Compares this build with the latest release on GitHub. Nothing is
+/// downloaded.
+#[tauri::command]
+async fn check_for_updates(app: tauri::AppHandle) -> Result<UpdateInfo, String> {
+    let current = app.package_info().version.to_string();
+    let update = tauri::async_runtime::spawn_blocking(move || fetch_latest_release(&current))
+        .await
+        .map_err(|err| err.to_string())??;
+    log::info!(
+        "updates: latest is {}, running {}",
+        update.latest,
+        update.current
+    );
+    Ok(update)
+}
+
+/// Checks once a day while `auto_check_updates` is on, emitting
+/// `update-available` the first time each newer release is seen. Nothing
+/// is fetched while it's off.
+fn watch_for_updates(app: tauri::AppHandle, state: AppState) {
+    std::thread::spawn(move || {
+        let current = app.package_info().version.to_string();
+        let mut checked: Option<Instant> = None;
+        let mut announced: Option<String> = None;
+        std::thread::sleep(UPDATE_CHECK_DELAY);
+        loop {
+            let due = checked.is_none_or(|at| at.elapsed() >= UPDATE_CHECK_INTERVAL);
+            if due && state.snapshot_settings().auto_check_updates {
+                match fetch_latest_release(&current) {
+                    Ok(update) => {
+                        checked = Some(Instant::now());
+                        if update.available && announced.as_ref() != Some(&update.latest) {
+                            log::info!("updates: {} is available", update.latest);
+                            announced = Some(update.latest.clone());
+                            if let Err(err) = app.emit("update-available", &update) {
+                                log::error!("updates: failed to emit update-available: {}", err);
+                            }
+                        }
+                    }
+                    Err(err) => log::info!("updates: check failed: {}", err),
+                }
+            }
+            std::thread::sleep(UPDATE_POLL);
+        }
+    });
+}
+
+/// File in the config directory with the usage counts.
+const STATS_FILE: &str = "stats.json";
+/// Most time counted uses wait before they're written.
+const STATS_FLUSH_INTERVAL: Duration = Duration::from_secs(5 * 60);
+/// Longest `flush_usage_stats` waits for the write.
+const STATS_FLUSH_WAIT: Duration = Duration::from_secs(1);
+/// Longest `AppState::flush_settings` waits; the sync folder's copy can
+/// take a while.
+const SETTINGS_FLUSH_WAIT: Duration = Duration::from_secs(2);
+/// Days the daily breakdown covers, today included.
+const STATS_DAYS: u64 = 30;
+
+/// Uses per device id, button key, and action id.
+type UsageCounts = BTreeMap<String, BTreeMap<String, BTreeMap<String, u64>>>;
+
+/// How often mappings fired, as kept in `STATS_FILE`.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+#[serde(default)]
+struct UsageStats {
+    totals: UsageCounts,
+    /// The same by UTC day, `"YYYY-MM-DD"`, for the last `STATS_DAYS` days.
+    days: BTreeMap<String, UsageCounts>,
+    /// Presses swallowed as switch bounce per device id and button key, all
+    /// time, for tuning `DeviceConfig::debounce_ms`.
+    bounces: BTreeMap<String, BTreeMap<String, u64>>,
+}
+
+impl UsageStats {
+    fn record(&mut self, day: &str, device_id: &str, button: &str, action: &str) {
+        for counts in [
+            &mut self.totals,
+            self.days.entry(day.to_string()).or_default(),
+        ] {
+            *counts
+                .entry(device_id.to_string())
+                .or_default()
+                .entry(button.to_string())
+                .or_default()
+                .entry(action.to_string())
+                .or_default() += 1;
+        }
+    }
+
+    fn record_bounce(&mut self, device_id: &str, button: &str) {
+        *self
+            .bounces
+            .entry(device_id.to_string())
+            .or_default()
+            .entry(button.to_string())
+            .or_default() += 1;
+    }
+
+    /// Drops the days before the last `STATS_DAYS` up to `now`.
+    fn prune(&mut self, now: SystemTime) {
+        let first = now - Duration::from_secs((STATS_DAYS - 1) * 86_400);
+        let first = &logging::timestamp(first)[..10];
+        self.days.retain(|day, _| day.as_str() >= first);
+    }
+}
+
+/// Fired actions `get_action_history` keeps.
+const ACTION_HISTORY: usize = 200;
+
+/// An entry of the action history, and the `action-fired` payload.
+#[derive(Debug, Serialize, Clone, PartialEq)]
+struct FiredAction {
+    /// Seconds since the Unix epoch.
+    timestamp: u64,
+    device_id: Option<String>,
+    button: &'static str,
+    /// Only the action's name; what a shortcut types isn't kept.
+    action: &'static str,
+    /// The frontmost application.
+    app: Option<String>,
+}
+
+/// What the remap engine tells the usage thread.
+enum UsageMessage {
+    /// `action` fired from `button` while `mappings` applied. The mappings
+    /// come whole, for their device id, so the event path doesn't allocate.
+    Used {
+        mappings: Arc<ResolvedMappings>,
+        button: i64,
+        action: Action,
+    },
+    /// A press of `button` was swallowed as switch bounce.
+    Bounced {
+        mappings: Arc<ResolvedMappings>,
+        button: i64,
+    },
+    /// Write what's been counted, then answer.
+    Flush(std::sync::mpsc::Sender<()>),
+    /// Forget every count.
+    Reset,
+}
+
+fn stats_path(app: &tauri::AppHandle) -> Result<PathBuf, String> {
+    Ok(config_dir(app)?.0.join(STATS_FILE))
+}
+
+fn load_usage_stats(path: &Path) -> UsageStats {
+    match fs::read_to_string(path) {
+        Ok(data) => serde_json::from_str(&data).unwrap_or_else(|err| {
+            log::warn!("stats: ignoring {}: {}", path.display(), err);
+            UsageStats::default()
+        }),
+        Err(_) => UsageStats::default(),
+    }
+}
+
+/// Adds a fired action to the history and emits `action-fired` for it.
+fn record_fired_action(
+    app: &tauri::AppHandle,
+    state: &AppState,
+    device_id: Option<&str>,
+    button: &'static str,
+    action: Action,
+) {
+    let entry = FiredAction {
+        timestamp: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_secs())
+            .unwrap_or_default(),
+        device_id: device_id.map(str::to_string),
+        button,
+        action: action.name(),
+        app: state.frontmost_app_name(),
+    };
+    state.push_history(entry.clone());
+    if let Err(err) = app.emit("action-fired", entry) {
+        log::error!("history: failed to emit action-fired: {}", err);
+    }
+}
+
+/// Starts the thread that keeps the action history and counts uses,
+/// writing the counts to `path` when a flush is asked for or
+/// `STATS_FLUSH_INTERVAL` after the first unsaved use; never once per click.
+fn start_usage_stats(app: &tauri::AppHandle, path: PathBuf) {
+    use std::sync::mpsc::RecvTimeoutError;
+
+    let app = app.clone();
+    let state = app.state::<AppState>().inner().clone();
+    let (sender, receiver) = std::sync::mpsc::channel();
+    if state.usage.set(sender).is_err() {
+        return;
+    }
+    std::thread::spawn(move || {
+        let mut stats = load_usage_stats(&path);
+        // When the oldest unsaved change was made.
+        let mut unsaved: Option<Instant> = None;
+        loop {
+            let timeout = unsaved.map_or(STATS_FLUSH_INTERVAL, |since| {
+                STATS_FLUSH_INTERVAL.saturating_sub(since.elapsed())
+            });
+            let mut answer = None;
+            match receiver.recv_timeout(timeout) {
+                Ok(UsageMessage::Used {
+                    mappings,
+                    button,
+                    action,
+                }) => {
+                    let Some(key) = button_key(button) else {
+                        continue;
+                    };
+                    let device_id = mappings.device_id.as_deref();
+                    if let Some(device_id) =
+                        device_id.filter(|_| !state.stats_disabled.load(Ordering::Relaxed))
+                    {
+                        let today = &logging::timestamp(SystemTime::now())[..10];
+                        stats.record(today, device_id, key, &action.id());
+                        unsaved.get_or_insert_with(Instant::now);
+                    }
+                    if !state.history_disabled.load(Ordering::Relaxed) {
+                        record_fired_action(&app, &state, device_id, key, action);
+                    }
+                }
+                Ok(UsageMessage::Bounced { mappings, button }) => {
+                    let (Some(device_id), Some(key)) =
+                        (mappings.device_id.as_deref(), button_key(button))
+                    else {
+                        continue;
+                    };
+                    stats.record_bounce(device_id, key);
+                    unsaved.get_or_insert_with(Instant::now);
+                }
+                Ok(UsageMessage::Flush(done)) => answer = Some(done),
+                Ok(UsageMessage::Reset) => {
+                    stats = UsageStats::default();
+                    unsaved.get_or_insert_with(Instant::now);
+                }
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+            let due = unsaved.is_some_and(|since| since.elapsed() >= STATS_FLUSH_INTERVAL);
+            if unsaved.is_some() && (due || answer.is_some()) {
+                stats.prune(SystemTime::now());
+                let written = serde_json::to_string_pretty(&stats)
+                    .map_err(std::io::Error::other)
+                    .and_then(|data| {
+                        write_atomically(&path, |file| file.write_all(data.as_bytes()))
+                    });
+                if let Err(err) = written {
+                    log::error!("stats: failed to write {}: {}", path.display(), err);
+                }
+                unsaved = None;
+            }
+            if let Some(done) = answer {
+                let _ = done.send(());
+            }
+        }
+    });
+}
+
+/// How often each mapping fired, all time and for each of the last
+/// `STATS_DAYS` days, counts not yet written included.
+#[tauri::command]
+fn get_usage_stats(app: tauri::AppHandle, state: State<AppState>) -> Result<UsageStats, String> {
+    state.flush_usage_stats();
+    let mut stats = load_usage_stats(&stats_path(&app)?);
+    stats.prune(SystemTime::now());
+    Ok(stats)
+}
+
+/// The last `ACTION_HISTORY` actions remaps fired, oldest first.
+#[tauri::command]
+fn get_action_history(state: State<AppState>) -> Vec<FiredAction> {
+    state.action_history()
+}
+
+#[tauri::command]
+fn reset_usage_stats(app: tauri::AppHandle, state: State<AppState>) -> Result<(), String> {
+    if let Some(usage) = state.usage.get() {
+        usage
+            .send(UsageMessage::Reset)
+            .map_err(|_| "the usage counter has stopped".to_string())?;
+    }
+    if !state.flush_usage_stats() {
+        match fs::remove_file(stats_path(&app)?) {
+            Err(err) if err.kind() != std::io::ErrorKind::NotFound => return Err(err.to_string()),
+            _ => {}
+        }
+    }
+    log::info!("stats: reset");
+    Ok(())
+}
+
+/// Carries out `behavior` for a click on the close button. `Ask` leaves the
+/// window open and has it ask through `close-requested`.
+fn close_main_window(app: &tauri::AppHandle, behavior: CloseBehavior) {
+    match behavior {
+        CloseBehavior::Hide => hide_main_window(app),
+        CloseBehavior::Quit => quit_app(app),
+        CloseBehavior::Ask => {
+            if let Err(err) = app.emit("close-requested", ()) {
+                log::error!("window: failed to emit close-requested: {}", err);
+                hide_main_window(app);
+            }
+        }
+    }
+}
+
+/// The window's answer to `close-requested`: saves it as the close
+/// behavior and closes the window that way.
+#[tauri::command]
+fn answer_close_request(
+    app: tauri::AppHandle,
+    state: State<AppState>,
+    behavior: CloseBehavior,
+) -> Result<(), String> {
+    if behavior == CloseBehavior::Ask {
+        return Err("answer with hide or quit".to_string());
+    }
+    state.change_settings(&app, None, |settings| {
+        settings.close_behavior = behavior;
+        Ok(())
+    })?;
+    close_main_window(&app, behavior);
+    Ok(())
+}
+
+/// Another launch of the app handed over `args` and exited. It shows the
+/// window unless it came with commands or `--hidden`.
+fn handle_relaunch(app: &tauri::AppHandle, args: &[String]) {
+    log::info!("instance: launched again with {:?}", args);
+    match LaunchArgs::parse(args) {
+        Ok(launch) => {
+            apply_launch_args(app, &launch);
+            if !launch.hidden && !launch.is_command() {
+                show_main_window(app);
+            }
+        }
+        Err(err) => log::error!("instance: {}", err),
+    }
+}
+
+/// Arguments autostart launches the app with, so login launches go
+/// straight to the tray.
+const AUTOSTART_ARGS: &[&str] = &["--hidden"];
+
+/// File in the config directory holding the arguments autostart was last
+/// registered with. Registrations without it, like those of versions before
+/// `AUTOSTART_ARGS`, are made again.
+const AUTOSTART_REGISTRATION: &str = "autostart-args";
+
+fn autostart_plugin() -> tauri::plugin::TauriPlugin<tauri::Wry> {
+    let builder = AutostartBuilder::new()
+        .app_name("Edit Mouse")
+        .args(AUTOSTART_ARGS.iter().copied());
+    // The AppleScript login item can't pass arguments; it launches the app
+    // hidden instead, which `launched_hidden` picks up.
+    #[cfg(target_os = "macos")]
+    let builder = builder.macos_launcher(MacosLauncher::AppleScript);
+    builder.build()
+}
+
+/// Whether macOS launched the app hidden, as it does login items.
+#[cfg(target_os = "macos")]
+fn launched_hidden() -> bool {
+    objc2_app_kit::NSRunningApplication::currentApplication().isHidden()
+}
+
+/// Whether Shift is held as the app launches, which starts it in safe mode.
+#[cfg(target_os = "macos")]
+fn shift_held() -> bool {
+    use core_graphics::event::CGEventFlags;
+    use core_graphics::event_source::CGEventSourceStateID;
+
+    #[link(name = "CoreGraphics", kind = "framework")]
+    extern "C" {
+        fn CGEventSourceFlagsState(state: CGEventSourceStateID) -> CGEventFlags;
+    }
+
+    unsafe { CGEventSourceFlagsState(CGEventSourceStateID::CombinedSessionState) }
+        .contains(CGEventFlags::CGEventFlagShift)
+}
+
+#[cfg(windows)]
+fn shift_held() -> bool {
+    use windows_sys::Win32::UI::Input::KeyboardAndMouse::{GetAsyncKeyState, VK_SHIFT};
+    // The high bit is set while the key is down.
+    unsafe { GetAsyncKeyState(i32::from(VK_SHIFT)) < 0 }
+}
+
+/// Without focus, no display server says what's held; `--safe-mode` it is.
+#[cfg(not(any(target_os = "macos", windows)))]
+fn shift_held() -> bool {
+    false
+}
+
+#[cfg(not(target_os = "macos"))]
+fn launched_hidden() -> bool {
+    false
+}
+
+fn autostart_registration(app: &tauri::AppHandle) -> Result<PathBuf, String> {
+    Ok(config_dir(app)?.0.join(AUTOSTART_REGISTRATION))
+}
+
+/// Turns autostart on with `AUTOSTART_ARGS`, replacing a registration made
+/// with other arguments. Does nothing when it's already current.
+fn enable_autostart(app: &tauri::AppHandle) -> Result<(), String> {
+    let manager = app.autolaunch();
+    let registration = autostart_registration(app)?;
+    let args = AUTOSTART_ARGS.join(" ");
+    let enabled = manager.is_enabled().map_err(|err| err.to_string())?;
+    if enabled && fs::read_to_string(&registration).is_ok_and(|registered| registered == args) {
+        return Ok(());
+    }
+    if enabled {
+        manager.disable().map_err(|err| err.to_string())?;
+    }
+    manager.enable().map_err(|err| err.to_string())?;
+    fs::write(&registration, args).map_err(|err| err.to_string())?;
+    log::info!("autostart: registered with {:?}", AUTOSTART_ARGS);
+    Ok(())
+}
+
+/// Registers an existing autostart entry again if an older version made it,
+/// so it gains `AUTOSTART_ARGS`.
+fn migrate_autostart(app: &tauri::AppHandle) {
+    if !app.autolaunch().is_enabled().unwrap_or(false) {
+        return;
+    }
+    if let Err(err) = enable_autostart(app) {
+        log::error!("autostart: failed to register again: {}", err);
+    }
+}
+
+#[tauri::command]
+fn get_autostart_enabled(app: tauri::AppHandle) -> Result<bool, String> {
+    let manager = app.autolaunch();
+    manager.is_enabled().map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+fn set_autostart_enabled(app: tauri::AppHandle, enabled: bool) -> Result<(), String> {
+    if enabled {
+        return enable_autostart(&app);
+    }
+    app.autolaunch().disable().map_err(|err| err.to_string())?;
+    if let Ok(registration) = autostart_registration(&app) {
+        let _ = fs::remove_file(registration);
+    }
+    Ok(())
+}
+
+#[tauri::command]
+fn hide_window(app: tauri::AppHandle, state: State<AppState>) -> Result<(), String> {
+    state.set_event_monitoring(false);
+    state.flush_settings_later();
+    if let Some(window) = app.get_webview_window("main") {
+        window.hide().map_err(|err| err.to_string())?;
+    }
+    Ok(())
+}
+
+/// What `get_settings` returns.
+#[derive(Debug, Serialize)]
+struct SettingsSnapshot {
+    #[serde(flatten)]
+    settings: Settings,
+    /// The theme `theme` comes out as on this system right now.
+    effective_theme: SystemTheme,
+}
+
+#[tauri::command]
+fn get_settings(app: tauri::AppHandle, state: State<AppState>) -> Result<SettingsSnapshot, String> {
+    // A change still waiting to be written would be read back as it was.
+    // One that can't be is still current, and kept below.
+    let _ = state.flush_settings();
+    let mut settings = load_settings(&app)?;
+    settings.record_sightings(&state.pending_sightings());
+    state.update_settings(settings.clone());
+    Ok(SettingsSnapshot {
+        effective_theme: effective_theme(&settings.theme, system_theme()),
+        settings,
+    })
+}
+
+/// Menu item id prefix of the tray's profile entries.
+const TRAY_PROFILE_PREFIX: &str = "tray_profile:";
+
+/// Menu item id prefix of the tray's device entries, followed by the id.
+const TRAY_DEVICE_PREFIX: &str = "tray_select_device:";
+
+/// An entry of the tray's Device submenu.
+#[derive(Debug, Clone, PartialEq)]
+struct TrayDevice {
+    id: String,
+    name: String,
+    /// Configured devices that aren't connected are listed greyed out.
+    connected: bool,
+}
+
+/// Any mouse, then the connected devices, then those configured in the
+/// active profile that aren't connected, by name.
+fn tray_devices(
+    settings: &Settings,
+    connected: &[(String, String)],
+    locale: Locale,
+) -> Vec<TrayDevice> {
+    let mut entries = vec![TrayDevice {
+        id: ANY_DEVICE_ID.to_string(),
+        name: locale.text(Text::AnyMouse).to_string(),
+        connected: !connected.is_empty(),
+    }];
+    entries.extend(connected.iter().map(|(id, name)| TrayDevice {
+        id: id.clone(),
+        name: name.clone(),
+        connected: true,
+    }));
+    let mut offline: Vec<TrayDevice> = settings
+        .profiles
+        .get(&settings.active_profile)
+        .into_iter()
+        .flat_map(|profile| &profile.devices)
+        .filter(|(id, config)| {
+            *id != ANY_DEVICE_ID
+                && !config.pristine
+                && connected.iter().all(|(known, _)| known != *id)
+        })
+        .map(|(id, config)| TrayDevice {
+            id: id.clone(),
+            name: config.display_name(id).to_string(),
+            connected: false,
+        })
+        .collect();
+    offline.sort_by(|a, b| a.name.cmp(&b.name));
+    entries.extend(offline);
+    for entry in &mut entries {
+        if settings
+            .device_config(&entry.id)
+            .is_some_and(|config| config.disabled)
+        {
+            entry.name = locale.format(Text::DeviceDisabled, &[&entry.name]);
+        }
+    }
+    entries
+}
+
+/// Selects `device_id` from the tray.
+fn select_device(app: &tauri::AppHandle, device_id: &str) -> Result<(), String> {
+    app.state::<AppState>()
+        .change_settings(app, None, |settings| {
+            settings.selected_device = Some(device_id.to_string());
+            Ok(())
+        })
+        .map(drop)?;
+    log::info!("tray: selected {}", device_id);
+    Ok(())
+}
+
+/// The tray's line about the selected device, such as "MX Master 3 —
+/// disconnected".
+fn tray_device_label(settings: &Settings, connected: bool, locale: Locale) -> String {
+    let Some(id) = settings.selected_device.as_deref() else {
+        return locale.text(Text::NoDeviceSelected).to_string();
+    };
+    let name = match settings.device_config(id) {
+        Some(config) if !config.name.trim().is_empty() => config.name.clone(),
+        _ if id == ANY_DEVICE_ID => locale.text(Text::AnyMouse).to_string(),
+        _ => id.to_string(),
+    };
+    match (connected, settings.device_config(id)) {
+        (false, _) => locale.format(Text::DeviceDisconnected, &[&name]),
+        (true, Some(config)) if config.disabled => locale.format(Text::DeviceDisabled, &[&name]),
+        (true, _) => name,
+    }
+}
+
+fn tray_menu(app: &tauri::AppHandle) -> tauri::Result<Menu<tauri::Wry>> {
+    let state = app.state::<AppState>();
+    let settings = state.snapshot_settings();
+    let locale = Locale::from_setting(&settings.locale);
+    let text = |text| locale.text(text);
+    let show = MenuItem::with_id(app, "tray_show", text(Text::Show), true, None::<&str>)?;
+    let hide = MenuItem::with_id(app, "tray_hide", text(Text::Hide), true, None::<&str>)?;
+    let quick_panel = MenuItem::with_id(
+        app,
+        "tray_quick_panel",
+        text(Text::QuickPanel),
+        true,
+        None::<&str>,
+    )?;
+    let connected = settings
+        .selected_device
+        .as_deref()
+        .is_some_and(|id| state.is_selected_device_available(id));
+    let device = MenuItem::with_id(
+        app,
+        "tray_device",
+        tray_device_label(&settings, connected, locale),
+        false,
+        None::<&str>,
+    )?;
+    let names = state
+        .device_names
+        .lock()
+        .map(|guard| guard.clone())
+        .unwrap_or_default();
+    let devices = tray_devices(&settings, &names, locale)
+        .into_iter()
+        .map(|entry| {
+            let id = format!("{}{}", TRAY_DEVICE_PREFIX, entry.id);
+            let selected = settings.selected_device.as_deref() == Some(entry.id.as_str());
+            CheckMenuItem::with_id(
+                app,
+                id,
+                &entry.name,
+                entry.connected,
+                selected,
+                None::<&str>,
+            )
+        })
+        .collect::<tauri::Result<Vec<_>>>()?;
+    let device_items: Vec<&dyn tauri::menu::IsMenuItem<tauri::Wry>> = devices
+        .iter()
+        .map(|item| item as &dyn tauri::menu::IsMenuItem<tauri::Wry>)
+        .collect();
+    let device_menu = Submenu::with_items(app, text(Text::Device), true, &device_items)?;
+    let profiles = settings
+        .profile_names()
+        .into_iter()
+        .map(|name| {
+            let id = format!("{}{}", TRAY_PROFILE_PREFIX, name);
+            let active = name == settings.active_profile;
+            CheckMenuItem::with_id(app, id, &name, true, active, None::<&str>)
+        })
+        .collect::<tauri::Result<Vec<_>>>()?;
+    let profile_items: Vec<&dyn tauri::menu::IsMenuItem<tauri::Wry>> = profiles
+        .iter()
+        .map(|item| item as &dyn tauri::menu::IsMenuItem<tauri::Wry>)
+        .collect();
+    let profile_menu = Submenu::with_items(app, text(Text::Profile), true, &profile_items)?;
+    let paused = app.state::<AppState>().is_remap_paused();
+    let pause_menu = Submenu::with_items(
+        app,
+        text(Text::PauseRemapping),
+        true,
+        &[
+            &MenuItem::with_id(
+                app,
+                "tray_pause:900",
+                text(Text::PauseQuarterHour),
+                true,
+                None::<&str>,
+            )?,
+            &MenuItem::with_id(
+                app,
+                "tray_pause:3600",
+                text(Text::PauseHour),
+                true,
+                None::<&str>,
+            )?,
+            &MenuItem::with_id(
+                app,
+                "tray_pause:0",
+                text(Text::PauseUntilResumed),
+                true,
+                None::<&str>,
+            )?,
+            &MenuItem::with_id(app, "tray_resume", text(Text::Resume), paused, None::<&str>)?,
+        ],
+    )?;
+    let remap_enabled = CheckMenuItem::with_id(
+        app,
+        "tray_remap_enabled",
+        text(Text::EnableRemapping),
+        true,
+        settings.remap_enabled,
+        None::<&str>,
+    )?;
+    let exclude = MenuItem::with_id(
+        app,
+        "tray_exclude_app",
+        text(Text::NeverRemapInCurrentApp),
+        true,
+        None::<&str>,
+    )?;
+    let reset_label = if state.tray_reset_armed() {
+        text(Text::ClickAgainToReset)
+    } else {
+        text(Text::ResetToDefaults)
+    };
+    let config_menu = Submenu::with_items(
+        app,
+        text(Text::Configuration),
+        true,
+        &[
+            &MenuItem::with_id(
+                app,
+                "tray_config_reveal",
+                text(Text::RevealConfigFolder),
+                true,
+                None::<&str>,
+            )?,
+            &MenuItem::with_id(
+                app,
+                "tray_config_reload",
+                text(Text::ReloadFromDisk),
+                true,
+                None::<&str>,
+            )?,
+            &MenuItem::with_id(app, "tray_config_reset", reset_label, true, None::<&str>)?,
+        ],
+    )?;
+    let safe_mode = if state.is_safe_mode() {
+        MenuItem::with_id(
+            app,
+            "tray_safe_mode_exit",
+            text(Text::ExitSafeMode),
+            true,
+            None::<&str>,
+        )?
+    } else {
+        MenuItem::with_id(
+            app,
+            "tray_safe_mode",
+            text(Text::EnterSafeMode),
+            true,
+            None::<&str>,
+        )?
+    };
+    let restart = MenuItem::with_id(app, "tray_restart", text(Text::Restart), true, None::<&str>)?;
+    let quit = MenuItem::with_id(app, "tray_quit", text(Text::Quit), true, None::<&str>)?;
+    Menu::with_items(
+        app,
+        &[
+            &device,
+            &show,
+            &hide,
+            &quick_panel,
+            &remap_enabled,
+            &pause_menu,
+            &device_menu,
+            &profile_menu,
+            &exclude,
+            &config_menu,
+            &safe_mode,
+            &restart,
+            &quit,
+        ],
+    )
+}
+
+/// How long a first pick of the tray's Reset To Defaults waits for the
+/// second that resets.
+const TRAY_RESET_CONFIRM: Duration = Duration::from_secs(10);
+
+impl AppState {
+    fn tray_reset_armed(&self) -> bool {
+        lock_recovering(&self.tray_reset_armed, "the tray reset")
+            .is_some_and(|armed| armed.elapsed() < TRAY_RESET_CONFIRM)
+    }
+
+    /// True for a pick that confirms an armed reset. Any other pick arms it
+    /// and returns false.
+    fn confirm_tray_reset(&self) -> bool {
+        let mut armed = lock_recovering(&self.tray_reset_armed, "the tray reset");
+        match armed.take() {
+            Some(at) if at.elapsed() < TRAY_RESET_CONFIRM => true,
+            _ => {
+                *armed = Some(Instant::now());
+                false
+            }
+        }
+    }
+}
+
+/// Resets settings on the second pick of Reset To Defaults; the first
+/// relabels the item until `TRAY_RESET_CONFIRM` passes.
+fn reset_from_tray(app: &tauri::AppHandle) {
+    if app.state::<AppState>().confirm_tray_reset() {
+        if let Err(err) = reset_to_defaults(app) {
+            log::error!("tray: failed to reset settings: {}", err);
+        }
+        refresh_tray_menu(app);
+        return;
+    }
+    refresh_tray_menu(app);
+    let app = app.clone();
+    std::thread::spawn(move || {
+        std::thread::sleep(TRAY_RESET_CONFIRM);
+        refresh_tray_menu(&app);
+    });
+}
+
+/// Rebuilds the tray menu so the profile list and checkmark are current.
+fn refresh_tray_menu(app: &tauri::AppHandle) {
+    let Some(tray) = app.tray_by_id("main") else {
+        return;
+    };
+    if let Err(err) = tray_menu(app).and_then(|menu| tray.set_menu(Some(menu))) {
+        log::error!("tray: failed to rebuild menu: {}", err);
+    }
+}
+
+/// Which of the tray icons is showing.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum TrayIconKind {
+    Active,
+    /// Remapping is turned off or paused, or safe mode is on.
+    Paused,
+    /// The engine can't remap at all.
+    Error,
+}
+
+/// The icon and tooltip for the tray. An engine that can't run outranks a
+/// pause, since turning remapping back on wouldn't help; safe mode outranks
+/// the rest, as it has to be left before anything else applies.
+fn tray_status(
+    engine_state: EngineState,
+    enabled: bool,
+    paused: bool,
+    game_mode: bool,
+    idle: bool,
+    safe_mode: bool,
+    locale: Locale,
+) -> (TrayIconKind, &'static str) {
+    let (kind, text) = match engine_state {
+        EngineState::PermissionDenied => (TrayIconKind::Error, Text::NeedsAccessibility),
+        EngineState::Failed => (TrayIconKind::Error, Text::CouldntStart),
+        EngineState::Unsupported => (TrayIconKind::Error, Text::CantRemap),
+        _ if safe_mode => (TrayIconKind::Paused, Text::SafeMode),
+        _ if !enabled => (TrayIconKind::Paused, Text::RemappingOff),
+        _ if paused => (TrayIconKind::Paused, Text::Paused),
+        _ if game_mode => (TrayIconKind::Paused, Text::PausedGameMode),
+        _ if idle => (TrayIconKind::Paused, Text::PausedIdle),
+        _ => return (TrayIconKind::Active, "Edit Mouse"),
+    };
+    (kind, locale.text(text))
+}
+
+/// Most characters of the tray's title before it's cut off, so a long
+/// profile name doesn't crowd the menu bar.
+const TRAY_TITLE_MAX_CHARS: usize = 12;
+
+/// The text next to the tray icon, when `show_profile_in_tray` is on.
+fn tray_title(settings: &Settings) -> Option<String> {
+    if !settings.show_profile_in_tray {
+        return None;
+    }
+    let title = settings
+        .profiles
+        .get(&settings.active_profile)
+        .and_then(|profile| profile.tray_label.as_deref())
+        .map(str::trim)
+        .filter(|label| !label.is_empty())
+        .unwrap_or(&settings.active_profile);
+    if title.chars().count() <= TRAY_TITLE_MAX_CHARS {
+        return Some(title.to_string());
+    }
+    let kept: String = title.chars().take(TRAY_TITLE_MAX_CHARS - 1).collect();
+    Some(format!("{}…", kept.trim_end()))
+}
+
+/// Switches the tray icon and tooltip to match the engine and whether
+/// remapping is on.
+fn refresh_tray_icon(app: &tauri::AppHandle) {
+    let Some(tray) = app.tray_by_id("main") else {
+        return;
+    };
+    let state = app.state::<AppState>();
+    let (kind, tooltip) = tray_status(
+        state.engine_state(),
+        state.is_remap_enabled(),
+        state.is_remap_paused(),
+        state.is_game_mode_paused(),
+        state.is_idle_suspended(),
+        state.is_safe_mode(),
+        Locale::from_setting(&state.snapshot_settings().locale),
+    );
+    let icon = match kind {
+        TrayIconKind::Active => tauri::include_image!("icons/icon-tray.png"),
+        TrayIconKind::Paused => tauri::include_image!("icons/icon-tray-paused.png"),
+        TrayIconKind::Error => tauri::include_image!("icons/icon-tray-error.png"),
+    };
+    if let Err(err) = tray.set_icon(Some(icon)) {
+        log::error!("tray: failed to set icon: {}", err);
+    }
+    // Setting the icon resets the template flag on macOS.
+    let _ = tray.set_icon_as_template(true);
+    let _ = tray.set_tooltip(Some(tooltip));
+}
+
+#[derive(Serialize, Clone)]
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+struct GameModeChanged {
+    paused: bool,
+}
+
+/// Shows the game-mode pause in the tray and tells the settings window
+/// about it.
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+fn announce_game_mode(app: &tauri::AppHandle, paused: bool) {
+    log::info!(
+        "game mode: remapping {}",
+        if paused { "paused" } else { "resumed" }
+    );
+    refresh_tray_icon(app);
+    if let Err(err) = app.emit("game-mode-changed", GameModeChanged { paused }) {
+        log::error!("game mode: failed to emit game-mode-changed: {}", err);
+    }
+}
+
+/// Longest `watch_idle` sleeps, so a change of `idle_suspend_minutes`
+/// applies within it.
+const IDLE_POLL: Duration = Duration::from_secs(60);
+
+/// Payload of `idle-suspend-changed`.
+#[derive(Serialize, Clone)]
+struct IdleSuspendChanged {
+    suspended: bool,
+}
+
+/// Suspends remapping once `Settings::idle_suspend_minutes` pass without
+/// input, and announces it and the input that ends it. Starting counts as
+/// input, so a launch isn't suspended straight away.
+fn watch_idle(app: tauri::AppHandle, state: AppState) {
+    use std::sync::mpsc::RecvTimeoutError;
+
+    let (sender, receiver) = std::sync::mpsc::channel();
+    if state.idle_wake.set(sender).is_err() {
+        return;
+    }
+    state.note_input();
+    std::thread::spawn(move || loop {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_secs())
+            .unwrap_or_default();
+        let remaining = match state.snapshot_settings().idle_suspend_minutes {
+            Some(minutes) => state.suspend_if_idle(now, minutes),
+            None => {
+                if state.end_idle_suspension() {
+                    announce_idle_suspend(&app, false);
+                }
+                None
+            }
+        };
+        if remaining == Some(0) {
+            announce_idle_suspend(&app, true);
+        }
+        let wait = remaining
+            .filter(|secs| *secs > 0)
+            .map_or(IDLE_POLL, |secs| Duration::from_secs(secs).min(IDLE_POLL));
+        match receiver.recv_timeout(wait) {
+            Ok(()) => announce_idle_suspend(&app, false),
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => return,
+        }
+    });
+}
+
+fn announce_idle_suspend(app: &tauri::AppHandle, suspended: bool) {
+    log::info!(
+        "idle: remapping {}",
+        if suspended {
+            "suspended until the next input"
+        } else {
+            "resumed"
+        }
+    );
+    refresh_tray_icon(app);
+    if let Err(err) = app.emit("idle-suspend-changed", IdleSuspendChanged { suspended }) {
+        log::error!("idle: failed to emit idle-suspend-changed: {}", err);
+    }
+}
+
+/// Adds the application that was frontmost before the tray menu opened to
+/// `excluded_apps`, and tells the settings window about it.
+fn exclude_last_external_app(app: &tauri::AppHandle) {
+    let state = app.state::<AppState>();
+    let Some(frontmost) = state.last_external_app() else {
+        return;
+    };
+    let excluded = state.change_settings(app, None, |settings| {
+        if !settings.excluded_apps.contains(&frontmost.bundle_id) {
+            settings.excluded_apps.push(frontmost.bundle_id.clone());
+        }
+        Ok(())
+    });
+    if let Err(err) = excluded {
+        log::error!("tray: failed to save settings: {}", err);
+        return;
+    }
+    log::info!("tray: excluded {}", frontmost.bundle_id);
+}
+
+#[tauri::command]
+fn get_settings_recovery(state: State<AppState>) -> Option<SettingsRecovered> {
+    state.settings_recovery()
+}
+
+/// The sync folder while it can't be reached and the local settings stand in.
+#[tauri::command]
+fn get_settings_sync(state: State<AppState>) -> Option<SettingsSyncUnavailable> {
+    state.sync_unavailable()
+}
+
+/// Where this launch's settings were brought over from, when they were.
+#[tauri::command]
+fn get_settings_migration(state: State<AppState>) -> Option<SettingsMigrated> {
+    state.settings_migration()
+}
+
+#[tauri::command]
+fn get_settings_warnings(state: State<AppState>) -> Vec<SettingsWarning> {
+    state.settings_warnings()
+}
+
+#[tauri::command]
+fn list_settings_backups(
+    app: tauri::AppHandle,
+    state: State<AppState>,
+) -> Result<Vec<SettingsBackup>, String> {
+    // Each write backs up the one before, so one still waiting is listed.
+    let _ = state.flush_settings();
+    let path = settings_path(&app)?;
+    settings_backups(&settings_backups_dir(&path)).map_err(|err| err.to_string())
+}
+
+/// Replaces the current settings with the backup `name` from
+/// `list_settings_backups`, which for one `recover_settings` moved aside
+/// works once it's been fixed by hand. The settings it replaces are backed
+/// up in turn, so a restore can be undone.
+#[tauri::command]
+fn restore_settings_backup(
+    app: tauri::AppHandle,
+    state: State<AppState>,
+    name: String,
+) -> Result<Settings, String> {
+    if Path::new(&name).file_name() != Some(name.as_ref()) {
+        return Err(format!("not a backup name: {}", name));
+    }
+    state.flush_settings()?;
+    let path = settings_backups_dir(&settings_path(&app)?).join(&name);
+    let data = fs::read_to_string(&path).map_err(|err| err.to_string())?;
+    let settings = parse_settings(&data).map_err(|err| format!("backup is unreadable: {}", err))?;
+    if settings.version > SETTINGS_VERSION {
+        return Err(format!(
+            "backup is from a newer version of Edit Mouse (format {})",
+            settings.version
+        ));
+    }
+    settings.validate()?;
+    state.apply_settings(&app, settings.clone(), None)?;
+    state.set_settings_recovery(None);
+    log::info!("settings: restored {}", name);
+    Ok(settings)
+}
+
+/// Payload of `settings-changed`.
+#[derive(Debug, Serialize, Clone)]
+struct SettingsChanged {
+    settings: Settings,
+    /// Label of the window the change came from, which already shows it.
+    source: Option<String>,
+}
+
+impl AppState {
+    /// Saves `settings` and makes them current, then brings the engine, the
+    /// tray, and every window in line with them. Whatever changes settings,
+    /// a window, the tray, or a mouse button, goes through here so no window
+    /// is left showing stale settings. `source` is the window that made the
+    /// change, if one did.
+    fn apply_settings(
+        &self,
+        app: &tauri::AppHandle,
+        settings: Settings,
+        source: Option<&str>,
+    ) -> Result<(), String> {
+        self.change_settings(app, source, |current| {
+            *current = settings;
+            Ok(())
+        })
+        .map(drop)
+    }
+
+    /// Like `apply_settings`, for an edit of the current settings rather
+    /// than a replacement; returns the edited settings.
+    fn change_settings(
+        &self,
+        app: &tauri::AppHandle,
+        source: Option<&str>,
+        change: impl FnOnce(&mut Settings) -> Result<(), String>,
+    ) -> Result<Settings, String> {
+        self.edit_settings(change, |settings| {
+            persist_settings(app, settings.clone())?;
+            self.publish_settings(app, settings, source);
+            Ok(())
+        })
+    }
+
+    /// Makes settings read from disk current, as if a window had saved
+    /// them; returns them.
+    fn adopt_settings(
+        &self,
+        app: &tauri::AppHandle,
+        settings: Settings,
+    ) -> Result<Settings, String> {
+        self.edit_settings(
+            |current| {
+                *current = settings;
+                Ok(())
+            },
+            |settings| {
+                self.publish_settings(app, settings, None);
+                Ok(())
+            },
+        )
+    }
+
+    /// Makes `settings` current and tells the engine, the tray, and every
+    /// window, without saving them.
+    fn publish_settings(&self, app: &tauri::AppHandle, settings: Settings, source: Option<&str>) {
+        log::set_max_level(settings.log_level.filter());
+        apply_window_theme(app, &settings.theme);
+        sync_hotkeys(app, &settings);
+        apply_tray_click_action(app, settings.tray_click_action);
+        apply_tray_title(app, &settings);
+        sync_integration_server(app, &settings.integration_server);
+        let previous = self.snapshot_settings();
+        if settings.input_overlay != previous.input_overlay {
+            move_input_overlay(app, settings.input_overlay.corner);
+            self.nudge_input_overlay();
+        }
+        if DeviceFilter::from(&settings) != DeviceFilter::from(&previous) {
+            self.invalidate_devices();
+        }
+        let toggled = settings.remap_enabled != self.is_remap_enabled();
+        let switched = settings.active_profile != previous.active_profile;
+        let relabeled = settings.locale != previous.locale;
+        let relisted = self.take_settings(&previous, &settings, || {
+            list_mouse_devices_promptly(&self.hid, DeviceFilter::from(&settings))
+        });
+        if relisted {
+            show_hud(app, self);
+            let (devices, _) = self.listed_devices();
+            if let Err(err) = app.emit("devices-changed", &devices) {
+                log::error!("devices: failed to emit devices-changed: {}", err);
+            }
+        }
+        settle_fallback_device(app, self);
+        if switched && settings.show_profile_toast {
+            self.queue_hud(Hud::Profile {
+                profile: settings.active_profile.clone(),
+            });
+            show_hud(app, self);
+        }
+        if toggled {
+            announce_remap_enabled(app, settings.remap_enabled);
+        } else {
+            refresh_tray_menu(app);
+            if relabeled {
+                refresh_tray_icon(app);
+            }
+        }
+        let payload = SettingsChanged {
+            settings,
+            source: source.map(str::to_string),
+        };
+        if let Err(err) = app.emit("settings-changed", payload) {
+            log::error!("settings: failed to emit settings-changed: {}", err);
+        }
+    }
+}
+
+/// Converts LinearMouse's button mappings into device configs of the active
+/// profile. Devices that already have a config there are left alone unless
+/// `overwrite` is set.
+#[tauri::command]
+fn import_linearmouse(
+    app: tauri::AppHandle,
+    state: State<AppState>,
+    overwrite: bool,
+) -> Result<linearmouse::ImportReport, String> {
+    let path = linearmouse::config_path().ok_or_else(|| "HOME is not set".to_string())?;
+    let data = fs::read_to_string(&path)
+        .map_err(|err| format!("couldn't read {}: {}", path.display(), err))?;
+    let config: serde_json::Value = serde_json::from_str(&data).map_err(|err| err.to_string())?;
+    let mut settings = state.snapshot_settings();
+    let devices = list_mouse_devices(&state.hid, DeviceFilter::from(&settings)).unwrap_or_default();
+    let mut report = linearmouse::ImportReport::default();
+    let configs = linearmouse::convert(&config, &devices, &mut report);
+    let Some(profile) = settings.profiles.get_mut(&settings.active_profile) else {
+        return Err(format!("profile not found: {}", settings.active_profile));
+    };
+    for (id, config) in configs {
+        if profile.devices.contains_key(&id) && !overwrite {
+            report.skipped.push(format!("{}: already configured", id));
+            continue;
+        }
+        profile.devices.insert(id.clone(), config);
+        report.imported.push(id);
+    }
+    report.imported.sort();
+    if !report.imported.is_empty() {
+        state.apply_settings(&app, settings, None)?;
+    }
+    log::info!(
+        "settings: imported {} device(s) from LinearMouse, skipped {} item(s)",
+        report.imported.len(),
+        report.skipped.len()
+    );
+    Ok(report)
+}
+
+/// Reads the settings file again, for an edit the watcher hasn't picked up
+/// or one it refused.
+fn reload_settings_from_disk(app: &tauri::AppHandle) -> Result<Settings, String> {
+    let settings = load_settings(app)?;
+    let settings = app.state::<AppState>().adopt_settings(app, settings)?;
+    log::info!("settings: reloaded from disk");
+    Ok(settings)
+}
+
+#[tauri::command]
+fn reload_settings(app: tauri::AppHandle) -> Result<Settings, String> {
+    reload_settings_from_disk(&app)
+}
+
+/// Replaces all settings with the defaults, turning off run at startup to
+/// match.
+#[tauri::command]
+fn reset_settings(app: tauri::AppHandle) -> Result<Settings, String> {
+    reset_to_defaults(&app)
+}
+
+fn reset_to_defaults(app: &tauri::AppHandle) -> Result<Settings, String> {
+    let settings = Settings::default();
+    app.state::<AppState>()
+        .apply_settings(app, settings.clone(), None)?;
+    let autolaunch = app.autolaunch();
+    if autolaunch.is_enabled().unwrap_or(false) {
+        if let Err(err) = autolaunch.disable() {
+            log::error!("settings: failed to turn off run at startup: {}", err);
+        }
+    }
+    log::info!("settings: reset to defaults");
+    Ok(settings)
+}
+
+#[tauri::command]
+fn list_presets() -> Vec<presets::PresetInfo> {
+    presets::PRESETS.iter().map(presets::Preset::info).collect()
+}
+
+/// Replaces the mappings of `device_id` with those of the built-in preset
+/// `preset_id`.
+#[tauri::command]
+fn apply_preset(
+    app: tauri::AppHandle,
+    state: State<AppState>,
+    device_id: String,
+    preset_id: String,
+) -> Result<Settings, String> {
+    let preset =
+        presets::find(&preset_id).ok_or_else(|| format!("unknown preset: {}", preset_id))?;
+    let button_count = state.button_count(&device_id);
+    let settings = state.change_settings(&app, None, |settings| {
+        settings.apply_preset(&device_id, preset, button_count);
+        Ok(())
+    })?;
+    log::info!("settings: applied preset {} to {}", preset.id, device_id);
+    Ok(settings)
+}
+
+/// Writes the mappings of `device_id` to `path` as a preset file.
+#[tauri::command]
+fn export_device_preset(
+    state: State<AppState>,
+    device_id: String,
+    path: PathBuf,
+) -> Result<(), String> {
+    let settings = state.snapshot_settings();
+    let config = settings
+        .device_config(&device_id)
+        .ok_or_else(|| format!("{} has no mappings to export", device_id))?;
+    let file = presets::PresetFile::export(config, &device_id);
+    let data = serde_json::to_string_pretty(&file).map_err(|err| err.to_string())?;
+    write_atomically(&path, |file| file.write_all(data.as_bytes()))
+        .map_err(|err| err.to_string())?;
+    log::info!("settings: exported {} to {}", device_id, path.display());
+    Ok(())
+}
+
+/// Result of `import_device_preset`.
+#[derive(Debug, Serialize, Clone)]
+struct ImportedPreset {
+    settings: Settings,
+    name: String,
+    /// Buttons in the preset the target device doesn't have.
+    skipped: Vec<String>,
+}
+
+/// Replaces the mappings of the connected `target_device_id` with those of
+/// a preset file, which may come from a different model.
+#[tauri::command]
+fn import_device_preset(
+    app: tauri::AppHandle,
+    state: State<AppState>,
+    path: PathBuf,
+    target_device_id: String,
+) -> Result<ImportedPreset, String> {
+    let data = fs::read_to_string(&path).map_err(|err| err.to_string())?;
+    let file = presets::PresetFile::parse(&data)?;
+    let connected =
+        target_device_id == ANY_DEVICE_ID || state.is_selected_device_available(&target_device_id);
+    if !connected {
+        return Err(format!("{} isn't connected", target_device_id));
+    }
+    let button_count = state.button_count(&target_device_id);
+    let mut skipped = Vec::new();
+    let settings = state.change_settings(&app, None, |settings| {
+        skipped = settings.apply_preset_file(&target_device_id, &file, button_count);
+        Ok(())
+    })?;
+    log::info!(
+        "settings: imported {} onto {}",
+        path.display(),
+        target_device_id
+    );
+    if !skipped.is_empty() {
+        log::info!("settings: skipped {}", skipped.join(", "));
+    }
+    Ok(ImportedPreset {
+        settings,
+        name: file.name,
+        skipped,
+    })
+}
+
+#[tauri::command]
+fn reset_device(
+    app: tauri::AppHandle,
+    state: State<AppState>,
+    device_id: String,
+) -> Result<Settings, String> {
+    let button_count = state.button_count(&device_id);
+    let settings = state.change_settings(&app, None, |settings| {
+        settings.reset_device(&device_id, button_count)
+    })?;
+    log::info!("settings: reset {}", device_id);
+    Ok(settings)
+}
+
+/// Maps one button of `device_id` back to Default and returns what it was,
+/// so the window can offer to undo it; see `Settings::reset_button`.
+#[tauri::command]
+fn reset_button(
+    app: tauri::AppHandle,
+    state: State<AppState>,
+    device_id: String,
+    button: String,
+) -> Result<ActionValue, String> {
+    let button_count = state
+        .is_selected_device_available(&device_id)
+        .then(|| state.button_count(&device_id));
+    let mut previous = ActionValue::default();
+    state.change_settings(&app, None, |settings| {
+        previous = settings.reset_button(&device_id, &button, button_count)?;
+        Ok(())
+    })?;
+    log::info!("settings: reset {} of {}", button, device_id);
+    Ok(previous)
+}
+
+/// Copies the config of `from_id` onto `to_id`, optionally removing it from
+/// `from_id`; see `Settings::copy_device_config`.
+#[tauri::command]
+fn copy_device_config(
+    app: tauri::AppHandle,
+    state: State<AppState>,
+    from_id: String,
+    to_id: String,
+    remove_source: bool,
+) -> Result<Settings, String> {
+    let settings = state.change_settings(&app, None, |settings| {
+        settings.copy_device_config(&from_id, &to_id, remove_source)
+    })?;
+    log::info!(
+        "settings: copied {} onto {}{}",
+        from_id,
+        to_id,
+        if remove_source { " and removed it" } else { "" }
+    );
+    Ok(settings)
+}
+
+/// Drops everything stored about `device_id`, in every profile, and the
+/// configs the app added for devices unseen for `STALE_PRISTINE_DEVICE`. A
+/// connected device starts over with a pristine config.
+#[tauri::command]
+fn forget_device(
+    app: tauri::AppHandle,
+    state: State<AppState>,
+    device_id: String,
+) -> Result<Settings, String> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or_default();
+    let (connected, _) = state.listed_devices();
+    let mut forgotten = Vec::new();
+    let settings = state.change_settings(&app, None, |settings| {
+        forgotten = settings.forget_device(&device_id, now)?;
+        settle_device_configs(settings, &connected);
+        Ok(())
+    })?;
+    log::info!("settings: forgot {}", forgotten.join(", "));
+    Ok(settings)
+}
+
+/// Swallows every button of `device_id` while `disabled`, leaving its
+/// mappings as they are for when it's turned back on.
+#[tauri::command]
+fn set_device_disabled(
+    app: tauri::AppHandle,
+    state: State<AppState>,
+    device_id: String,
+    disabled: bool,
+) -> Result<Settings, String> {
+    let settings = state.change_settings(&app, None, |settings| {
+        settings.set_device_disabled(&device_id, disabled);
+        Ok(())
+    })?;
+    refresh_tray_menu(&app);
+    log::info!(
+        "settings: {} {}",
+        if disabled { "disabled" } else { "enabled" },
+        device_id
+    );
+    Ok(settings)
+}
+
+/// Lets every event of `device_id` reach apps untouched while `ignored`,
+/// whichever device is selected.
+#[tauri::command]
+fn set_device_ignored(
+    app: tauri::AppHandle,
+    state: State<AppState>,
+    device_id: String,
+    ignored: bool,
+) -> Result<Settings, String> {
+    let settings = state.change_settings(&app, None, |settings| {
+        settings.set_device_ignored(&device_id, ignored)
+    })?;
+    refresh_tray_menu(&app);
+    log::info!(
+        "settings: {} {}",
+        if ignored {
+            "ignoring"
+        } else {
+            "no longer ignoring"
+        },
+        device_id
+    );
+    Ok(settings)
+}
+
+#[tauri::command]
+fn export_settings(state: State<AppState>, path: PathBuf) -> Result<(), String> {
+    state.flush_settings()?;
+    let settings = Settings {
+        version: SETTINGS_VERSION,
+        ..state.snapshot_settings()
+    };
+    let data = serde_json::to_string_pretty(&settings).map_err(|err| err.to_string())?;
+    write_atomically(&path, |file| file.write_all(data.as_bytes()))
+        .map_err(|err| err.to_string())?;
+    log::info!("settings: exported to {}", path.display());
+    Ok(())
+}
+
+/// Writes `render_cheatsheet` of `profile`, the active one without, to
+/// `path`, which may be a folder that gets a file named after the profile.
+/// Returns where the sheet was written.
+#[tauri::command]
+fn export_cheatsheet(
+    state: State<AppState>,
+    path: PathBuf,
+    format: CheatsheetFormat,
+    profile: Option<String>,
+    include_sensitive: Option<bool>,
+) -> Result<PathBuf, String> {
+    let settings = state.snapshot_settings();
+    let profile = profile.unwrap_or_else(|| settings.active_profile.clone());
+    let sheet = render_cheatsheet(
+        &settings,
+        &profile,
+        format,
+        include_sensitive.unwrap_or(false),
+    )?;
+    let path = if path.is_dir() {
+        let name: String = profile
+            .chars()
+            .map(|c| if c.is_alphanumeric() { c } else { '-' })
+            .collect();
+        path.join(format!("edit-mouse-{}.{}", name, format.extension()))
+    } else {
+        path
+    };
+    write_atomically(&path, |file| file.write_all(sheet.as_bytes()))
+        .map_err(|err| err.to_string())?;
+    log::info!(
+        "settings: exported the {} cheat sheet to {}",
+        profile,
+        path.display()
+    );
+    Ok(path)
+}
+
+/// Loads settings exported by `export_settings`, on this Mac or another.
+/// With `merge` the file's device configs, profile rules, and excluded apps
+/// are added to the current settings; without it the file replaces them.
+/// Either way `startup` stays as is, since it mirrors this machine's login
+/// item. Configs of devices that aren't connected are kept for when they
+/// are.
+#[tauri::command]
+fn import_settings(
+    app: tauri::AppHandle,
+    state: State<AppState>,
+    path: PathBuf,
+    merge: bool,
+) -> Result<Settings, String> {
+    let data = fs::read_to_string(&path).map_err(|err| err.to_string())?;
+    let imported = parse_settings(&data)?;
+    if imported.version > SETTINGS_VERSION {
+        return Err(format!(
+            "settings were exported by a newer version of Edit Mouse (format {})",
+            imported.version
+        ));
+    }
+    imported.validate()?;
+    let current = state.snapshot_settings();
+    let mut settings = if merge {
+        let mut settings = current.clone();
+        settings.merge(imported);
+        settings
+    } else {
+        imported
+    };
+    settings.startup = current.startup;
+    settings.normalize_profiles();
+    state.apply_settings(&app, settings.clone(), None)?;
+    log::info!(
+        "settings: {} {}",
+        if merge { "merged" } else { "imported" },
+        path.display()
+    );
+    Ok(settings)
+}
+
+/// Restores the settings from before the latest change and returns them.
+#[tauri::command]
+fn undo_settings_change(app: tauri::AppHandle, state: State<AppState>) -> Result<Settings, String> {
+    let change = state.undo_settings_change(|settings| {
+        persist_settings(&app, settings.clone())?;
+        state.publish_settings(&app, settings, None);
+        Ok(())
+    })?;
+    log::info!("settings: undid {}", change.summary.join("; "));
+    Ok(change.previous)
+}
+
+#[tauri::command]
+fn get_settings_history(state: State<AppState>) -> Vec<SettingsHistoryEntry> {
+    state.settings_history()
+}
+
+/// Maps one button of one device without sending the rest of the settings,
+/// so quick edits in a row can't undo each other.
+#[tauri::command]
+fn set_button_action(
+    app: tauri::AppHandle,
+    window: tauri::WebviewWindow,
+    state: State<AppState>,
+    device_id: String,
+    button: String,
+    action: ActionValue,
+    acknowledge_primary_remap: Option<bool>,
+) -> Result<(), String> {
+    let acknowledged = acknowledge_primary_remap.unwrap_or(false);
+    state.change_settings(&app, Some(window.label()), |settings| {
+        let previous = settings.clone();
+        settings.set_button_action(&device_id, &button, action)?;
+        settings.check_primary_remap(&previous, acknowledged)?;
+        settings.validate()
+    })?;
+    if acknowledged && button == "left" {
+        state.end_primary_escape();
+    }
+    Ok(())
+}
+
+/// Selects `device_id`, off the main thread in case it has to be listed
+/// first.
+#[tauri::command(async)]
+fn set_selected_device(
+    app: tauri::AppHandle,
+    window: tauri::WebviewWindow,
+    state: State<AppState>,
+    device_id: Option<String>,
+) -> Result<(), String> {
+    state
+        .change_settings(&app, Some(window.label()), |settings| {
+            settings.selected_device = device_id;
+            Ok(())
+        })
+        .map(drop)
+}
+
+#[tauri::command]
+fn validate_settings(mut settings: Settings) -> Vec<SettingsIssue> {
+    settings.normalize_profiles();
+    settings.issues()
+}
+
+/// Writes settings changes still waiting out their quiet time, for a
+/// window about to go away.
+#[tauri::command]
+fn flush_settings(state: State<AppState>) -> Result<(), String> {
+    state.flush_settings()
+}
+
+/// Saves the settings a window edited. Runs off the main thread, since a
+/// device it newly selects may be listed before it returns.
+#[tauri::command(async)]
+fn save_settings(
+    app: tauri::AppHandle,
+    window: tauri::WebviewWindow,
+    state: State<AppState>,
+    mut settings: Settings,
+    acknowledge_primary_remap: Option<bool>,
+) -> Result<(), String> {
+    let acknowledged = acknowledge_primary_remap.unwrap_or(false);
+    settings.normalize_profiles();
+    let (connected, _) = state.listed_devices();
+    state.change_settings(&app, Some(window.label()), |current| {
+        settings
+            .settle_selected_device(current, &connected)
+            .map_err(|issue| issue.to_string())?;
+        settings.check_primary_remap(current, acknowledged)?;
+        settings.validate()?;
+        *current = settings;
+        Ok(())
+    })?;
+    if acknowledged {
+        state.end_primary_escape();
+    }
+    Ok(())
+}
+
+#[derive(Serialize, Clone)]
+struct RemapEnabledChanged {
+    enabled: bool,
+}
+
+/// Reflects `remap_enabled` in the tray and the settings window after it
+/// changed from any of them.
+fn announce_remap_enabled(app: &tauri::AppHandle, enabled: bool) {
+    log::info!(
+        "mouse-remap: remapping {}",
+        if enabled { "enabled" } else { "disabled" }
+    );
+    refresh_tray_menu(app);
+    refresh_tray_icon(app);
+    if let Err(err) = app.emit("remap-enabled-changed", RemapEnabledChanged { enabled }) {
+        log::error!("mouse-remap: failed to emit remap-enabled-changed: {}", err);
+    }
+}
+
+fn change_remap_enabled(app: &tauri::AppHandle, enabled: bool) -> Result<(), String> {
+    app.state::<AppState>()
+        .change_settings(app, None, |settings| {
+            settings.remap_enabled = enabled;
+            Ok(())
+        })
+        .map(drop)
+}
+
+#[tauri::command]
+fn set_remap_enabled(app: tauri::AppHandle, enabled: bool) -> Result<(), String> {
+    change_remap_enabled(&app, enabled)
+}
+
+fn hotkey_pressed(app: &tauri::AppHandle, purpose: HotkeyPurpose) {
+    match purpose {
+        HotkeyPurpose::ToggleRemapping => {
+            let enabled = !app.state::<AppState>().is_remap_enabled();
+            if let Err(err) = change_remap_enabled(app, enabled) {
+                log::error!("hotkeys: failed to toggle remapping: {}", err);
+            }
+        }
+        HotkeyPurpose::ToggleWindow => toggle_main_window(app),
+        HotkeyPurpose::QuickPanel => toggle_quick_panel(app, None),
+        HotkeyPurpose::CycleDevice => cycle_selected_device(app),
+    }
+}
+
+/// Selects the next connected device that's set up, showing the connect
+/// HUD for it, or a notification with the HUD off. Without another device
+/// to switch to the selection stays.
+fn cycle_selected_device(app: &tauri::AppHandle) {
+    let state = app.state::<AppState>();
+    let (devices, _) = state.listed_devices();
+    let Some(next) = next_selected_device(&state.snapshot_settings(), &devices) else {
+        log::info!("hotkeys: no other connected device to select");
+        return;
+    };
+    let settings = match state.change_settings(app, None, |settings| {
+        settings.selected_device = Some(next.clone());
+        Ok(())
+    }) {
+        Ok(settings) => settings,
+        Err(err) => {
+            log::error!("hotkeys: failed to select {}: {}", next, err);
+            return;
+        }
+    };
+    log::info!("hotkeys: selected {}", next);
+    let names: Vec<(String, String)> = devices
+        .iter()
+        .filter(|device| device.id == next)
+        .map(|device| (device.id.clone(), device.name.clone()))
+        .collect();
+    match connect_hud(&settings, &[], &names) {
+        Some(hud) if settings.show_connect_hud => {
+            state.queue_hud(Hud::Connect(hud));
+            show_hud(app, &state);
+        }
+        hud => {
+            let name = hud.map_or(next, |hud| hud.device);
+            let locale = Locale::from_setting(&settings.locale);
+            state.post_notification(locale.format(Text::NoticeDeviceSelected, &[&name]));
+        }
+    }
+}
+
+/// Hides the window when it's in front, and otherwise shows it.
+fn toggle_main_window(app: &tauri::AppHandle) {
+    let showing = app.get_webview_window("main").is_some_and(|window| {
+        window.is_visible().unwrap_or(false) && window.is_focused().unwrap_or(false)
+    });
+    if showing {
+        hide_main_window(app);
+    } else {
+        show_main_window(app);
+    }
+}
+
+/// Label of the quick panel's window, open until it loses focus.
+const QUICK_PANEL_WINDOW: &str = "quick-panel";
+
+/// Size of the quick panel, in logical pixels.
+const QUICK_PANEL_WIDTH: f64 = 260.0;
+const QUICK_PANEL_HEIGHT: f64 = 236.0;
+
+/// How soon after the quick panel closed for losing focus a tray click or
+/// the hotkey leaves it closed. Clicking the icon takes the focus first, so
+/// without this the click that should close the panel opens it again.
+const QUICK_PANEL_REOPEN_GUARD: Duration = Duration::from_millis(300);
+
+/// Opens the quick panel under the tray icon, with remapping on or off, the
+/// active profile, the selected device, and a pause. It runs the main
+/// window's commands and listens to its events, so the two never disagree,
+/// and it closes once it loses focus.
+#[tauri::command]
+fn show_quick_panel(app: tauri::AppHandle) -> Result<(), String> {
+    open_quick_panel(&app, None).map_err(|err| format!("Failed to open the quick panel: {}", err))
+}
+
+/// Closes the quick panel when it's open, and otherwise opens it by the tray
+/// icon at `rect`.
+fn toggle_quick_panel(app: &tauri::AppHandle, rect: Option<tauri::Rect>) {
+    if let Some(window) = app.get_webview_window(QUICK_PANEL_WINDOW) {
+        if let Err(err) = window.destroy() {
+            log::error!("quick-panel: failed to close: {}", err);
+        }
+        return;
+    }
+    if app.state::<AppState>().quick_panel_just_blurred() {
+        return;
+    }
+    if let Err(err) = open_quick_panel(app, rect) {
+        log::error!("quick-panel: failed to open: {}", err);
+    }
+}
+
+/// Opens the quick panel, or brings it forward, next to the tray icon at
+/// `rect` or where the tray says its icon is. Without either, as on Linux,
+/// it opens in the middle of the display.
+fn open_quick_panel(app: &tauri::AppHandle, rect: Option<tauri::Rect>) -> tauri::Result<()> {
+    if let Some(window) = app.get_webview_window(QUICK_PANEL_WINDOW) {
+        return window.set_focus();
+    }
+    let window = tauri::WebviewWindowBuilder::new(
+        app,
+        QUICK_PANEL_WINDOW,
+        tauri::WebviewUrl::App("quick-panel.html".into()),
+    )
+    .title("Edit Mouse")
+    .inner_size(QUICK_PANEL_WIDTH, QUICK_PANEL_HEIGHT)
+    .decorations(false)
+    .resizable(false)
+    .always_on_top(true)
+    .visible_on_all_workspaces(true)
+    .skip_taskbar(true)
+    .visible(false)
+    .build()?;
+    let rect = rect.or_else(|| app.tray_by_id("main")?.rect().ok().flatten());
+    match rect {
+        Some(rect) => {
+            let corner = rect.position.to_physical::<f64>(1.0);
+            place_window_near_tray(&window, rect, corner);
+        }
+        None => window.center()?,
+    }
+    window.show()?;
+    window.set_focus()
+}
+
+/// Top-left corner, in physical pixels, for a window of `window` size
+/// hanging off the tray icon at `tray` (x, y, width, height) within
+/// `area`: centred on the icon, below it when the tray runs along the top
+/// half of the screen and above it otherwise, and kept inside `area`.
+fn window_position_near_tray(
+    tray: (i32, i32, i32, i32),
+    window: (i32, i32),
+    area: (i32, i32, i32, i32),
+) -> (i32, i32) {
+    let (tray_x, tray_y, tray_width, tray_height) = tray;
+    let (width, height) = window;
+    let (area_x, area_y, area_width, area_height) = area;
+    let clamp = |value: i32, start: i32, room: i32| value.min(start + room).max(start);
+    let x = tray_x + tray_width / 2 - width / 2;
+    let y = if tray_y + tray_height / 2 < area_y + area_height / 2 {
+        tray_y + tray_height
+    } else {
+        tray_y - height
+    };
+    (
+        clamp(x, area_x, area_width - width),
+        clamp(y, area_y, area_height - height),
+    )
+}
+
+/// Moves the window next to the tray icon at `rect`, on the display
+/// `click` happened on.
+fn place_window_near_tray(
+    window: &tauri::WebviewWindow,
+    rect: tauri::Rect,
+    click: tauri::PhysicalPosition<f64>,
+) {
+    let Ok(Some(monitor)) = window.monitor_from_point(click.x, click.y) else {
+        return;
+    };
+    let Ok(size) = window.outer_size() else {
+        return;
+    };
+    let scale = monitor.scale_factor();
+    let position = rect.position.to_physical::<i32>(scale);
+    let tray_size = rect.size.to_physical::<i32>(scale);
+    let area = monitor.work_area();
+    let (x, y) = window_position_near_tray(
+        (position.x, position.y, tray_size.width, tray_size.height),
+        (
+            i32::try_from(size.width).unwrap_or(i32::MAX),
+            i32::try_from(size.height).unwrap_or(i32::MAX),
+        ),
+        (
+            area.position.x,
+            area.position.y,
+            i32::try_from(area.size.width).unwrap_or(i32::MAX),
+            i32::try_from(area.size.height).unwrap_or(i32::MAX),
+        ),
+    );
+    if let Err(err) = window.set_position(tauri::PhysicalPosition::new(x, y)) {
+        log::error!("tray: failed to place window: {}", err);
+    }
+}
+
+/// Left clicks on the tray icon toggle the window unless the menu is set
+/// to open instead. The first reveal puts the window by the icon.
+fn tray_icon_clicked(app: &tauri::AppHandle, event: TrayIconEvent) {
+    let TrayIconEvent::Click {
+        button: MouseButton::Left,
+        button_state: MouseButtonState::Up,
+        rect,
+        position,
+        ..
+    } = event
+    else {
+        return;
+    };
+    let state = app.state::<AppState>();
+    match state.snapshot_settings().tray_click_action {
+        TrayClickAction::ToggleWindow => {}
+        TrayClickAction::ShowMenu => return,
+        TrayClickAction::QuickPanel => {
+            toggle_quick_panel(app, Some(rect));
+            return;
+        }
+    }
+    if !state.window_revealed.load(Ordering::Relaxed) {
+        if let Some(window) = app.get_webview_window("main") {
+            place_window_near_tray(&window, rect, position);
+        }
+    }
+    toggle_main_window(app);
+}
+
+/// Titles the tray icon with the active profile, or clears the title.
+fn apply_tray_title(app: &tauri::AppHandle, settings: &Settings) {
+    let Some(tray) = app.tray_by_id("main") else {
+        return;
+    };
+    if let Err(err) = tray.set_title(tray_title(settings)) {
+        log::error!("tray: failed to set title: {}", err);
+    }
+}
+
+/// Lets the tray menu open on a left click only when that's the setting.
+fn apply_tray_click_action(app: &tauri::AppHandle, action: TrayClickAction) {
+    let Some(tray) = app.tray_by_id("main") else {
+        return;
+    };
+    if let Err(err) = tray.set_show_menu_on_left_click(action == TrayClickAction::ShowMenu) {
+        log::error!("tray: failed to set click action: {}", err);
+    }
+}
+
+/// Registers the hotkeys of `settings` in place of the current ones, on the
+/// main thread.
+fn sync_hotkeys(app: &tauri::AppHandle, settings: &Settings) {
+    let wanted = HotkeyPurpose::ALL.map(|purpose| (purpose, settings.hotkey(purpose)));
+    let registered = app.run_on_main_thread(move || {
+        for (purpose, hotkey) in wanted {
+            if let Err(err) = hotkeys::register(purpose, hotkey) {
+                log::error!("hotkeys: {}", err);
+            }
+        }
+    });
+    if let Err(err) = registered {
+        log::error!("hotkeys: failed to reach the main thread: {}", err);
+    }
+}
+
+/// An app hotkey as `get_hotkeys` reports it.
+#[derive(Debug, Serialize, Clone)]
+struct HotkeyStatus {
+    purpose: HotkeyPurpose,
+    hotkey: Option<Hotkey>,
+    label: Option<String>,
+    /// False when the hotkey is set but couldn't be registered.
+    registered: bool,
+}
+
+#[tauri::command]
+fn get_hotkeys(state: State<AppState>) -> Vec<HotkeyStatus> {
+    let settings = state.snapshot_settings();
+    HotkeyPurpose::ALL
+        .into_iter()
+        .map(|purpose| {
+            let hotkey = settings.hotkey(purpose);
+            HotkeyStatus {
+                purpose,
+                hotkey,
+                label: hotkey.map(|hotkey| hotkey.label()),
+                registered: hotkey.is_none() || hotkeys::registered(purpose) == hotkey,
+            }
+        })
+        .collect()
+}
+
+/// Registers `hotkey` for `purpose` and saves it once that worked, so a
+/// combo another app holds comes back as an error.
+fn change_hotkey(
+    app: &tauri::AppHandle,
+    purpose: HotkeyPurpose,
+    hotkey: Option<Hotkey>,
+) -> Result<Settings, String> {
+    let state = app.state::<AppState>();
+    if let Some(hotkey) = hotkey {
+        state.snapshot_settings().check_hotkey(purpose, hotkey)?;
+    }
+    hotkeys::register(purpose, hotkey)?;
+    let settings =
+        state.change_settings(app, None, |settings| settings.set_hotkey(purpose, hotkey))?;
+    log::info!(
+        "hotkeys: {} for {}",
+        hotkey.map_or("none".to_string(), |hotkey| hotkey.label()),
+        purpose.describe()
+    );
+    Ok(settings)
+}
+
+/// Sets the hotkey turning remapping on and off; `None` turns it off.
+#[tauri::command]
+fn set_toggle_hotkey(app: tauri::AppHandle, hotkey: Option<Hotkey>) -> Result<Settings, String> {
+    change_hotkey(&app, HotkeyPurpose::ToggleRemapping, hotkey)
+}
+
+/// Sets the hotkey showing and hiding the window; `None` turns it off.
+#[tauri::command]
+fn set_window_hotkey(app: tauri::AppHandle, hotkey: Option<Hotkey>) -> Result<Settings, String> {
+    change_hotkey(&app, HotkeyPurpose::ToggleWindow, hotkey)
+}
+
+/// Sets the hotkey selecting the next connected device; `None` turns it
+/// off.
+#[tauri::command]
+fn set_cycle_device_hotkey(
+    app: tauri::AppHandle,
+    hotkey: Option<Hotkey>,
+) -> Result<Settings, String> {
+    change_hotkey(&app, HotkeyPurpose::CycleDevice, hotkey)
+}
+
+/// Sets the hotkey opening the quick panel; `None` turns it off.
+#[tauri::command]
+fn set_quick_panel_hotkey(
+    app: tauri::AppHandle,
+    hotkey: Option<Hotkey>,
+) -> Result<Settings, String> {
+    change_hotkey(&app, HotkeyPurpose::QuickPanel, hotkey)
+}
+
+fn announce_remap_pause(app: &tauri::AppHandle) {
+    let status = app.state::<AppState>().remap_pause_status();
+    log::info!(
+        "mouse-remap: remapping {}",
+        if status.paused { "paused" } else { "resumed" }
+    );
+    refresh_tray_menu(app);
+    refresh_tray_icon(app);
+    if let Err(err) = app.emit("remap-pause-changed", status) {
+        log::error!("mouse-remap: failed to emit remap-pause-changed: {}", err);
+    }
+}
+
+/// Pauses remapping and, for a timed pause, starts a thread that resumes it
+/// once the wall clock passes the end. The thread wakes at least every
+/// `PAUSE_POLL`, so time spent asleep counts towards the pause.
+fn pause_remapping_for(app: &tauri::AppHandle, duration: Option<Duration>) {
+    let state = app.state::<AppState>().inner().clone();
+    let generation = state.pause_remapping(duration);
+    announce_remap_pause(app);
+    let Some(duration) = duration else {
+        return;
+    };
+    let app = app.clone();
+    let until = SystemTime::now() + duration;
+    std::thread::spawn(move || loop {
+        let remaining = until.duration_since(SystemTime::now()).unwrap_or_default();
+        std::thread::sleep(remaining.min(PAUSE_POLL));
+        match state.end_pause(generation) {
+            Some(true) => {
+                announce_remap_pause(&app);
+                return;
+            }
+            Some(false) => {}
+            None => return,
+        }
+    });
+}
+
+/// Pauses remapping for `seconds`, or until `resume_remapping` when omitted.
+#[tauri::command]
+fn pause_remapping(app: tauri::AppHandle, seconds: Option<u64>) -> RemapPauseStatus {
+    pause_remapping_for(&app, seconds.map(Duration::from_secs));
+    app.state::<AppState>().remap_pause_status()
+}
+
+#[tauri::command]
+fn resume_remapping(app: tauri::AppHandle, state: State<AppState>) -> RemapPauseStatus {
+    if state.resume_remapping() {
+        announce_remap_pause(&app);
+    }
+    state.remap_pause_status()
+}
+
+#[tauri::command]
+fn get_remap_pause(state: State<AppState>) -> RemapPauseStatus {
+    state.remap_pause_status()
+}
+
+/// Payload of `safe-mode-changed`, and what `get_safe_mode` returns.
+#[derive(Debug, Serialize, Clone)]
+struct SafeModeStatus {
+    active: bool,
+}
+
+/// Enters safe mode, as `--safe-mode`, Shift at launch, or the tray asks:
+/// every event passes through and settings aren't saved until
+/// `exit_safe_mode`.
+fn enter_safe_mode(app: &tauri::AppHandle) {
+    if app.state::<AppState>().set_safe_mode(true) {
+        announce_safe_mode(app);
+    }
+}
+
+fn leave_safe_mode(app: &tauri::AppHandle) {
+    if app.state::<AppState>().set_safe_mode(false) {
+        announce_safe_mode(app);
+    }
+}
+
+fn announce_safe_mode(app: &tauri::AppHandle) {
+    let active = app.state::<AppState>().is_safe_mode();
+    if active {
+        log::warn!("safe mode: remapping is off and settings are read-only");
+    } else {
+        log::info!("safe mode: left; remapping as configured");
+    }
+    refresh_tray_menu(app);
+    refresh_tray_icon(app);
+    if let Err(err) = app.emit("safe-mode-changed", SafeModeStatus { active }) {
+        log::error!("safe mode: failed to emit safe-mode-changed: {}", err);
+    }
+}
+
+#[tauri::command]
+fn get_safe_mode(state: State<AppState>) -> SafeModeStatus {
+    SafeModeStatus {
+        active: state.is_safe_mode(),
+    }
+}
+
+/// Leaves safe mode; the only way out besides the tray and quitting.
+#[tauri::command]
+fn exit_safe_mode(app: tauri::AppHandle) -> SafeModeStatus {
+    leave_safe_mode(&app);
+    get_safe_mode(app.state::<AppState>())
+}
+
+#[tauri::command]
+fn get_hud(state: State<AppState>) -> Option<Hud> {
+    state.showing_hud()
+}
+
+#[tauri::command]
+fn get_secure_input(state: State<AppState>) -> Option<SecureInputHolder> {
+    state.secure_input()
+}
+
+#[derive(Serialize)]
+struct ProfileList {
+    profiles: Vec<String>,
+    active: String,
+}
+
+#[tauri::command]
+fn list_profiles(state: State<AppState>) -> ProfileList {
+    let settings = state.snapshot_settings();
+    ProfileList {
+        profiles: settings.profile_names(),
+        active: settings.active_profile,
+    }
+}
+
+#[derive(Serialize, Clone)]
+struct ProfileChanged {
+    active_profile: String,
+}
+
+/// A profile switch the user asked for, from the settings window, the tray,
+/// or a mouse button.
+fn switch_profile(app: &tauri::AppHandle, name: &str) -> Result<Settings, String> {
+    app.state::<AppState>().note_manual_profile_switch();
+    activate_profile(app, name)
+}
+
+/// Switches profiles for `profile_app_rules` when `bundle_id` activates.
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+fn apply_profile_rules(app: &tauri::AppHandle, bundle_id: &str) {
+    let state = app.state::<AppState>();
+    let settings = state.snapshot_settings();
+    let Some(name) = state.auto_profile_for(&settings, bundle_id) else {
+        return;
+    };
+    log::info!("profiles: {} activated", bundle_id);
+    if let Err(err) = activate_profile(app, &name) {
+        log::error!("profiles: failed to switch to {}: {}", name, err);
+    }
+}
+
+/// Makes `name` the active profile, saves it, and tells the tray and the
+/// settings window. The next event resolves against the new profile.
+fn activate_profile(app: &tauri::AppHandle, name: &str) -> Result<Settings, String> {
+    let state = app.state::<AppState>();
+    let settings =
+        state.change_settings(app, None, |settings| settings.set_active_profile(name))?;
+    log::info!("profiles: switched to {}", name);
+    let payload = ProfileChanged {
+        active_profile: settings.active_profile.clone(),
+    };
+    if let Err(err) = app.emit("profile-changed", payload) {
+        log::error!("profiles: failed to emit profile-changed: {}", err);
+    }
+    Ok(settings)
+}
+
+#[tauri::command]
+fn create_profile(
+    app: tauri::AppHandle,
+    state: State<AppState>,
+    name: String,
+) -> Result<Settings, String> {
+    state.change_settings(&app, None, |settings| {
+        settings.create_profile(&name, Profile::default())
+    })
+}
+
+#[tauri::command]
+fn duplicate_profile(
+    app: tauri::AppHandle,
+    state: State<AppState>,
+    name: String,
+    new_name: String,
+) -> Result<Settings, String> {
+    state.change_settings(&app, None, |settings| {
+        settings.duplicate_profile(&name, &new_name)
+    })
+}
+
+#[tauri::command]
+fn delete_profile(
+    app: tauri::AppHandle,
+    state: State<AppState>,
+    name: String,
+) -> Result<Settings, String> {
+    state.change_settings(&app, None, |settings| settings.delete_profile(&name))
+}
+
+#[tauri::command]
+fn set_active_profile(app: tauri::AppHandle, name: String) -> Result<Settings, String> {
+    switch_profile(&app, &name)
+}
+
+/// Who holds secure input, as `get_secure_input` returns it and the
+/// `secure-input-changed` event carries it; null when nothing does.
+#[derive(Debug, Serialize, Clone, PartialEq)]
+struct SecureInputHolder {
+    pid: Option<i32>,
+    /// The application's name, when it has one.
+    name: Option<String>,
+}
+
+impl std::fmt::Display for SecureInputHolder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (&self.name, self.pid) {
+            (Some(name), Some(pid)) => write!(f, "{} (pid {})", name, pid),
+            (None, Some(pid)) => write!(f, "pid {}", pid),
+            _ => write!(f, "an unknown process"),
+        }
+    }
+}
+
+/// Least time between log lines about secure input.
+const SECURE_INPUT_LOG_INTERVAL: Duration = Duration::from_secs(60);
+
+/// How often `watch_secure_input` checks whether secure input ended.
+const SECURE_INPUT_POLL: Duration = Duration::from_secs(1);
+
+/// Reports who holds secure input once a keyboard action was skipped for
+/// it, and clears the report when it ends. Returns at once; one thread does
+/// the looking up and waiting, however many actions are skipped meanwhile.
+fn watch_secure_input(app: &tauri::AppHandle, state: &AppState) {
+    if state.secure_input_watched.swap(true, Ordering::AcqRel) {
+        return;
+    }
+    let app = app.clone();
+    let state = state.clone();
+    std::thread::spawn(move || {
+        let holder = secure_input::holder();
+        if state.should_log_secure_input(Instant::now()) {
+            log::warn!(
+                "secure input: {} has secure keyboard entry on; keyboard actions are skipped until it ends",
+                holder
+            );
+        }
+        report_secure_input(&app, &state, Some(holder));
+        while secure_input::is_enabled() {
+            std::thread::sleep(SECURE_INPUT_POLL);
+        }
+        log::debug!("secure input: ended");
+        report_secure_input(&app, &state, None);
+        state.secure_input_watched.store(false, Ordering::Release);
+    });
+}
+
+fn report_secure_input(
+    app: &tauri::AppHandle,
+    state: &AppState,
+    holder: Option<SecureInputHolder>,
+) {
+    if state.set_secure_input(holder.clone()) {
+        if let Err(err) = app.emit("secure-input-changed", holder) {
+            log::error!("secure input: failed to emit secure-input-changed: {}", err);
+        }
+    }
+}
+
+/// A system-wide shortcut of the app itself: a macOS virtual keycode with
+/// modifiers, stored like a shortcut action's, `{"keycode": 46,
+/// "modifiers": ["control", "option", "command"]}`.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+struct Hotkey {
+    keycode: u16,
+    #[serde(default, with = "modifier_list")]
+    modifiers: ModifierMask,
+}
+
+/// Ctrl+Option+Cmd+M.
+const DEFAULT_TOGGLE_HOTKEY: Hotkey = Hotkey {
+    keycode: 0x2e,
+    modifiers: 1 << Modifier::Control as u8
+        | 1 << Modifier::Option as u8
+        | 1 << Modifier::Command as u8,
+};
+
+/// Combos macOS keeps for itself: Cmd+Tab, Cmd+Space, Ctrl+Space, Cmd+Q,
+/// and Option+Cmd+Escape.
+const RESERVED_HOTKEYS: &[(u16, &[Modifier])] = &[
+    (0x30, &[Modifier::Command]),
+    (0x31, &[Modifier::Command]),
+    (0x31, &[Modifier::Control]),
+    (0x0c, &[Modifier::Command]),
+    (0x35, &[Modifier::Option, Modifier::Command]),
+];
+
+impl Hotkey {
+    fn label(&self) -> String {
+        shortcut_label(Some(self.keycode), None, self.modifiers)
+    }
+
+    /// Rejects keycodes out of range, combos without Control, Option, or
+    /// Command, which would take the key away from typing, and combos the
+    /// system keeps.
+    fn validate(&self) -> Result<(), String> {
+        if self.keycode > MAX_KEYCODE {
+            return Err(format!(
+                "keycode {} is outside 0-{}",
+                self.keycode, MAX_KEYCODE
+            ));
+        }
+        let shift = modifier_mask(&[Modifier::Shift]);
+        if self.modifiers & !shift == 0 {
+            return Err(format!(
+                "{} needs Control, Option, or Command",
+                self.label()
+            ));
+        }
+        let reserved = RESERVED_HOTKEYS.iter().any(|(keycode, modifiers)| {
+            *keycode == self.keycode && modifier_mask(modifiers) == self.modifiers
+        });
+        if reserved {
+            return Err(format!("{} is reserved by macOS", self.label()));
+        }
+        Ok(())
+    }
+}
+
+/// What an app hotkey does.
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+enum HotkeyPurpose {
+    ToggleRemapping,
+    ToggleWindow,
+    QuickPanel,
+    CycleDevice,
+}
+
+impl HotkeyPurpose {
+    const ALL: [HotkeyPurpose; 4] = [
+        HotkeyPurpose::ToggleRemapping,
+        HotkeyPurpose::ToggleWindow,
+        HotkeyPurpose::QuickPanel,
+        HotkeyPurpose::CycleDevice,
+    ];
+
+    /// Settings field holding the hotkey.
+    fn field(self) -> &'static str {
+        match self {
+            HotkeyPurpose::ToggleRemapping => "toggle_hotkey",
+            HotkeyPurpose::ToggleWindow => "window_hotkey",
+            HotkeyPurpose::QuickPanel => "quick_panel_hotkey",
+            HotkeyPurpose::CycleDevice => "cycle_device_hotkey",
+        }
+    }
+
+    fn describe(self) -> &'static str {
+        match self {
+            HotkeyPurpose::ToggleRemapping => "turning remapping on and off",
+            HotkeyPurpose::ToggleWindow => "showing and hiding the window",
+            HotkeyPurpose::QuickPanel => "opening the quick panel",
+            HotkeyPurpose::CycleDevice => "switching to the next device",
+        }
+    }
+
+    /// Nonzero id the hotkey is registered under.
+    #[cfg(target_os = "macos")]
+    fn id(self) -> u32 {
+        self as u32 + 1
+    }
+
+    #[cfg(target_os = "macos")]
+    fn from_id(id: u32) -> Option<Self> {
+        Self::ALL.into_iter().find(|purpose| purpose.id() == id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::alloc::{GlobalAlloc, Layout, System};
+    use std::cell::Cell;
+
+    /// Counts allocations per thread, so a test can check a code path
+    /// without interference from tests running in parallel.
+    struct CountingAllocator;
+
+    thread_local! {
+        static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+    }
+
+    unsafe impl GlobalAlloc for CountingAllocator {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            let _ = ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+            unsafe { System.alloc(layout) }
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            unsafe { System.dealloc(ptr, layout) }
+        }
+    }
+
+    #[global_allocator]
+    static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+    /// A folder of its own under the system temp folder, for a test that
+    /// works with files. It's removed when dropped, so also when the test
+    /// panics.
+    pub(crate) struct TempDir(PathBuf);
+
+    impl TempDir {
+        /// An empty `edit-mouse-<name>-<pid>`.
+        pub(crate) fn new(name: &str) -> Self {
+            let path =
+                std::env::temp_dir().join(format!("edit-mouse-{}-{}", name, std::process::id()));
+            let _ = fs::remove_dir_all(&path);
+            fs::create_dir_all(&path).unwrap();
+            Self(path)
+        }
+    }
+
+    impl std::ops::Deref for TempDir {
+        type Target = Path;
+
+        fn deref(&self) -> &Path {
+            &self.0
+        }
+    }
+
+    impl AsRef<Path> for TempDir {
+        fn as_ref(&self) -> &Path {
+            &self.0
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn allocations_during(run: impl FnOnce()) -> usize {
+        let before = ALLOCATIONS.with(Cell::get);
+        run();
+        ALLOCATIONS.with(Cell::get) - before
+    }
+
+    fn collect(entries: &[HidEntry]) -> Vec<MouseDevice> {
+        collect_mouse_devices(entries, DeviceFilter::default(), |_| None)
+    }
+
+    fn entry(name: &str, serial: Option<&str>, usage: u16, interface: i32, path: &str) -> HidEntry {
+        HidEntry {
+            vendor_id: 0x046d,
+            product_id: 0xc08b,
+            serial: serial.map(str::to_string),
+            name: name.to_string(),
+            manufacturer: None,
+            release_number: 0x0100,
+            usage_page: 0x01,
+            usage,
+            interface_number: interface,
+            transport: Transport::Usb,
+            path: path.to_string(),
+        }
+    }
+
+    fn action_of(config: &DeviceConfig, button: i64, button_count: u8) -> Action {
+        config
+            .mapping(button, button_count, None, 0)
+            .map_or(Action::Default, ActionValue::action)
+    }
+
+    #[test]
+    fn identical_units_get_unit_numbers() {
+        // Two of a model that report the same serial.
+        let devices = collect(&[
+            entry("Office Mouse", Some("0000"), 0x02, 0, "p1"),
+            entry("Office Mouse", Some("0000"), 0x02, 0, "p0"),
+        ]);
+        let ids: Vec<(&str, bool)> = devices
+            .iter()
+            .map(|device| (device.id.as_str(), device.ambiguous))
+            .collect();
+        assert_eq!(
+            ids,
+            vec![("046d:c08b:0000~2", true), ("046d:c08b:0000", true)]
+        );
+        let single = collect(&[entry("Office Mouse", Some("0000"), 0x02, 0, "p1")]);
+        assert_eq!(single[0].id, "046d:c08b:0000");
+        assert!(!single[0].ambiguous);
+        assert_eq!(unit_base_id("046d:c08b:0000~2"), "046d:c08b:0000");
+        assert_eq!(unit_base_id("046d:c08b:A~B"), "046d:c08b:A~B");
+
+        let mut settings = Settings::default();
+        settings
+            .profiles
+            .get_mut(DEFAULT_PROFILE)
+            .unwrap()
+            .devices
+            .insert("046d:c08b:0000".to_string(), DeviceConfig::default());
+        assert!(settings.device_config("046d:c08b:0000~2").is_some());
+
+        // Whichever unit is left answers for the other.
+        let state = AppState::default();
+        state.update_devices(&devices[..1]);
+        assert!(state.is_selected_device_available("046d:c08b:0000"));
+        state.update_devices(&single);
+        assert!(state.is_selected_device_available("046d:c08b:0000~2"));
+    }
+
+    #[test]
+    fn button_capture_takes_one_press() {
+        let state = AppState::default();
+        assert_eq!(state.capture_button(3, true), CaptureOutcome::Pass);
+
+        state.arm_capture("046d:c08b:A");
+        // Release of the click that armed the capture.
+        assert_eq!(state.capture_button(0, false), CaptureOutcome::Pass);
+        assert_eq!(
+            state.capture_button(3, true),
+            CaptureOutcome::Captured {
+                device_id: "046d:c08b:A".to_string()
+            }
+        );
+        assert_eq!(state.capture_button(1, true), CaptureOutcome::Pass);
+        assert_eq!(state.capture_button(3, false), CaptureOutcome::Suppress);
+        assert_eq!(state.capture_button(3, true), CaptureOutcome::Pass);
+
+        state.arm_capture("046d:c08b:A");
+        state.cancel_capture();
+        assert_eq!(state.capture_button(3, true), CaptureOutcome::Pass);
+    }
+
+    #[test]
+    fn outside_settings_edits_reload_and_own_writes_do_not() {
+        let state = AppState::default();
+        let saved = serde_json::to_string_pretty(&Settings::default()).unwrap();
+        state.note_settings_file(&saved);
+        assert!(external_settings(&state, &saved).is_none());
+
+        let edited = saved.replace(r#""theme": "system""#, r#""theme": "dark""#);
+        let reloaded = external_settings(&state, &edited).unwrap().unwrap();
+        assert_eq!(reloaded.theme, "dark");
+        assert!(external_settings(&state, &edited).is_none());
+
+        // A half-typed edit is reported once and then left until it changes.
+        let partial = &edited[..edited.len() / 2];
+        assert!(external_settings(&state, partial).unwrap().is_err());
+        assert!(external_settings(&state, partial).is_none());
+
+        let invalid =
+            r#"{"version": 3, "profile_app_rules": [{"bundle_id": "", "profile": "Default"}]}"#;
+        assert!(external_settings(&state, invalid).unwrap().is_err());
+        let newer = r#"{"version": 999}"#;
+        assert!(external_settings(&state, newer).unwrap().is_err());
+    }
+
+    #[test]
+    fn synced_settings_are_only_saved_over_when_unchanged_since_last_seen() {
+        let local = Path::new("/Users/a/Library/Application Support/Edit Mouse/settings.json");
+        let mut settings = Settings::default();
+        assert_eq!(synced_settings_path(&settings, local), None);
+        settings.sync_dir = Some(PathBuf::from("/Users/a/Dropbox/Edit Mouse"));
+        assert_eq!(
+            synced_settings_path(&settings, local),
+            Some(PathBuf::from("/Users/a/Dropbox/Edit Mouse/settings.json"))
+        );
+        settings.sync_dir = local.parent().map(Path::to_path_buf);
+        assert_eq!(synced_settings_path(&settings, local), None);
+        settings.sync_dir = Some(PathBuf::from("Dropbox"));
+        assert!(settings.validate().is_err());
+
+        let state = AppState::default();
+        let data = r#"{"theme": "dark"}"#;
+        assert!(state.is_synced_file_changed(data));
+        state.note_synced_file(data);
+        assert!(!state.is_synced_file_changed(data));
+        assert!(state.is_synced_file_changed(r#"{"theme": "light"}"#));
+        assert_eq!(checked_settings(data).unwrap().theme, "dark");
+        assert!(checked_settings(r#"{"version": 999}"#).is_err());
+
+        let dir = TempDir::new("sync");
+        let path = dir.join("settings.json");
+        fs::write(&path, data).unwrap();
+        let mut modified = None;
+        assert_eq!(
+            read_if_modified(&path, &mut modified).as_deref(),
+            Some(data)
+        );
+        assert_eq!(read_if_modified(&path, &mut modified), None);
+        assert_eq!(
+            read_if_modified(&dir.join("missing.json"), &mut modified),
+            None
+        );
+    }
+
+    #[test]
+    fn recorded_shortcuts_come_in_the_shape_shortcut_actions_store() {
+        let modifiers = modifier_mask(&[Modifier::Shift, Modifier::Command]);
+        let captured = ShortcutCaptured {
+            keycode: 0x28,
+            key: Some('k'),
+            modifiers,
+            display: shortcut_label(Some(0x28), Some('k'), modifiers),
+        };
+        let mut payload = serde_json::to_value(&captured).unwrap();
+        assert_eq!(
+            payload,
+            serde_json::json!({"keycode": 40, "key": "k", "modifiers": ["shift", "command"], "display": "⇧⌘K"})
+        );
+        payload["type"] = "shortcut".into();
+        let action: ActionValue = serde_json::from_value(payload).unwrap();
+        assert_eq!(
+            action.action(),
+            Action::Shortcut {
+                keycode: Some(0x28),
+                key: Some('k'),
+                modifiers
+            }
+        );
+        if !cfg!(target_os = "macos") {
+            assert!(shortcut_capture::start(|_| {}).is_err());
+            assert!(!shortcut_capture::is_active());
+        }
+    }
+
+    #[test]
+    fn hotkeys_need_a_modifier_and_stay_off_system_combos() {
+        let hotkey = |keycode, modifiers: &[Modifier]| Hotkey {
+            keycode,
+            modifiers: modifier_mask(modifiers),
+        };
+        assert_eq!(DEFAULT_TOGGLE_HOTKEY.label(), "⌃⌥⌘M");
+        assert!(DEFAULT_TOGGLE_HOTKEY.validate().is_ok());
+        assert_eq!(
+            hotkey(0x7a, &[Modifier::Shift, Modifier::Command]).label(),
+            "⇧⌘F1"
+        );
+        assert_eq!(
+            hotkey(0x2e, &[Modifier::Shift]).validate().unwrap_err(),
+            "⇧M needs Control, Option, or Command"
+        );
+        assert!(hotkey(0x2e, &[]).validate().is_err());
+        assert!(hotkey(0x80, &[Modifier::Command]).validate().is_err());
+        assert_eq!(
+            hotkey(0x31, &[Modifier::Command]).validate().unwrap_err(),
+            "⌘Space is reserved by macOS"
+        );
+        assert!(hotkey(0x31, &[Modifier::Shift, Modifier::Command])
+            .validate()
+            .is_ok());
+
+        let mut settings = Settings::default();
+        assert!(settings
+            .set_hotkey(
+                HotkeyPurpose::ToggleRemapping,
+                Some(hotkey(0x0c, &[Modifier::Command]))
+            )
+            .is_err());
+        assert_eq!(settings.toggle_hotkey, Some(DEFAULT_TOGGLE_HOTKEY));
+        assert_eq!(
+            settings
+                .set_hotkey(HotkeyPurpose::ToggleWindow, Some(DEFAULT_TOGGLE_HOTKEY))
+                .unwrap_err(),
+            "⌃⌥⌘M is already used for turning remapping on and off"
+        );
+        let comma = hotkey(
+            0x2b,
+            &[Modifier::Control, Modifier::Option, Modifier::Command],
+        );
+        settings
+            .set_hotkey(HotkeyPurpose::ToggleWindow, Some(comma))
+            .unwrap();
+        assert_eq!(settings.hotkey(HotkeyPurpose::ToggleWindow), Some(comma));
+        assert_eq!(
+            settings
+                .set_hotkey(HotkeyPurpose::QuickPanel, Some(comma))
+                .unwrap_err(),
+            "⌃⌥⌘, is already used for showing and hiding the window"
+        );
+        assert!(settings.validate().is_ok());
+        settings
+            .set_hotkey(HotkeyPurpose::ToggleRemapping, None)
+            .unwrap();
+        // Turned off stays off instead of coming back as the default.
+        let saved = serde_json::to_string(&settings).unwrap();
+        assert_eq!(parse_settings(&saved).unwrap().toggle_hotkey, None);
+
+        let parsed =
+            parse_settings(r#"{"toggle_hotkey": {"keycode": 46, "modifiers": ["shift"]}}"#)
+                .unwrap();
+        let issues = parsed.issues();
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].field, "toggle_hotkey");
+        assert!(parsed.validate().is_err());
+    }
+
+    #[test]
+    fn tray_names_the_selected_device_and_whether_it_is_connected() {
+        let mut settings = Settings::default();
+        assert_eq!(
+            tray_device_label(&settings, false, Locale::En),
+            "No device selected"
+        );
+        settings.selected_device = Some("046d:b023:A".to_string());
+        assert_eq!(
+            tray_device_label(&settings, true, Locale::En),
+            "046d:b023:A"
+        );
+        settings.apply_preset("046d:b023:A", presets::find("mx-master-3").unwrap(), 5);
+        assert_eq!(
+            tray_device_label(&settings, true, Locale::En),
+            "Logitech MX Master 3"
+        );
+        assert_eq!(
+            tray_device_label(&settings, false, Locale::En),
+            "Logitech MX Master 3 — disconnected"
+        );
+        settings.selected_device = Some(ANY_DEVICE_ID.to_string());
+        assert_eq!(tray_device_label(&settings, true, Locale::En), "Any mouse");
+
+        settings.apply_preset("046d:c08b:B", presets::find("g502").unwrap(), 5);
+        let connected = vec![("046d:c08b:B".to_string(), "G502 HERO".to_string())];
+        let ids: Vec<(String, bool)> = tray_devices(&settings, &connected, Locale::En)
+            .into_iter()
+            .map(|entry| (entry.id, entry.connected))
+            .collect();
+        assert_eq!(
+            ids,
+            vec![
+                (ANY_DEVICE_ID.to_string(), true),
+                ("046d:c08b:B".to_string(), true),
+                ("046d:b023:A".to_string(), false),
+            ]
+        );
+        assert!(!tray_devices(&settings, &[], Locale::En)[0].connected);
+        settings.set_device_disabled("046d:c08b:B", true);
+        assert_eq!(
+            tray_devices(&settings, &connected, Locale::En)[1].name,
+            "G502 HERO — disabled"
+        );
+        assert_eq!(
+            tray_device_label(&settings, false, Locale::De),
+            "Jede Maus — getrennt"
+        );
+
+        let state = AppState::default();
+        let device = MouseDevice {
+            id: "046d:b023:A".to_string(),
+            name: "MX Master 3".to_string(),
+            kind: DeviceKind::Mouse,
+            button_count: 5,
+            preset: None,
+            first_seen: None,
+            ambiguous: false,
+            disabled: false,
+            ignored: false,
+        };
+        assert!(state.update_devices(std::slice::from_ref(&device)));
+        assert!(!state.update_devices(std::slice::from_ref(&device)));
+        assert!(state.update_devices(&[]));
+    }
+
+    #[test]
+    fn state_survives_a_panic_while_settings_are_held() {
+        let state = AppState::default();
+        state.update_settings(Settings {
+            active_profile: "Editing".to_string(),
+            ..Settings::default()
+        });
+        let settings = Arc::clone(&state.settings);
+        let panicked = std::thread::spawn(move || {
+            let _held = settings.write().unwrap();
+            panic!("while holding settings");
+        })
+        .join();
+        assert!(panicked.is_err());
+        assert!(state.settings.is_poisoned());
+
+        assert_eq!(state.snapshot_settings().active_profile, "Editing");
+        assert!(!state.settings.is_poisoned());
+        state.update_settings(Settings::default());
+        assert_eq!(state.snapshot_settings().active_profile, DEFAULT_PROFILE);
+    }
+
+    #[test]
+    fn device_listings_are_reused_until_invalidated() {
+        let state = AppState::default();
+        assert!(!state.listed_devices().1);
+        let device = MouseDevice {
+            id: "046d:b023:A".to_string(),
+            name: "MX Master 3".to_string(),
+            kind: DeviceKind::Mouse,
+            button_count: 7,
+            preset: None,
+            first_seen: None,
+            ambiguous: false,
+            disabled: false,
+            ignored: false,
+        };
+        state.update_devices(std::slice::from_ref(&device));
+        let (devices, fresh) = state.listed_devices();
+        assert!(fresh);
+        assert_eq!(devices.len(), 1);
+        assert!(state.is_selected_device_available("046d:b023:A"));
+        assert_eq!(state.button_count(ANY_DEVICE_ID), 7);
+
+        state.invalidate_devices();
+        let (devices, fresh) = state.listed_devices();
+        assert!(!fresh);
+        // Still what the engine goes by until the next listing.
+        assert_eq!(devices.len(), 1);
+        assert!(state.is_selected_device_available("046d:b023:A"));
+    }
+
+    #[test]
+    fn tray_icon_shows_pauses_and_engine_errors() {
+        let status = |engine_state, enabled, paused, game_mode| {
+            tray_status(
+                engine_state,
+                enabled,
+                paused,
+                game_mode,
+                false,
+                false,
+                Locale::En,
+            )
+            .0
+        };
+        assert_eq!(
+            status(EngineState::Running, true, false, false),
+            TrayIconKind::Active
+        );
+        assert_eq!(
+            status(EngineState::Idle, true, false, false),
+            TrayIconKind::Active
+        );
+        assert_eq!(
+            status(EngineState::Running, false, false, false),
+            TrayIconKind::Paused
+        );
+        assert_eq!(
+            status(EngineState::Running, true, true, false),
+            TrayIconKind::Paused
+        );
+        assert_eq!(
+            status(EngineState::Running, true, false, true),
+            TrayIconKind::Paused
+        );
+        assert_eq!(
+            status(EngineState::PermissionDenied, false, true, false),
+            TrayIconKind::Error
+        );
+        let (_, tooltip) = tray_status(
+            EngineState::PermissionDenied,
+            true,
+            false,
+            false,
+            false,
+            false,
+            Locale::En,
+        );
+        assert!(tooltip.contains("Accessibility"));
+        let (kind, tooltip) = tray_status(
+            EngineState::Running,
+            true,
+            false,
+            false,
+            false,
+            true,
+            Locale::En,
+        );
+        assert_eq!(kind, TrayIconKind::Paused);
+        assert_eq!(tooltip, "Edit Mouse (safe mode)");
+        let (kind, tooltip) = tray_status(
+            EngineState::Running,
+            true,
+            false,
+            false,
+            true,
+            false,
+            Locale::En,
+        );
+        assert_eq!(kind, TrayIconKind::Paused);
+        assert_eq!(tooltip, "Edit Mouse (paused: idle)");
+    }
+
+    #[test]
+    fn tray_title_shows_the_profile_label_or_a_shortened_name() {
+        let mut settings = parse_settings(
+            r#"{"active_profile": "Photo Editing Suite",
+                "profiles": {"Photo Editing Suite": {}, "Work": {"tray_label": " 💼 "}}}"#,
+        )
+        .unwrap();
+        assert_eq!(tray_title(&settings), None);
+        settings.show_profile_in_tray = true;
+        assert_eq!(tray_title(&settings).as_deref(), Some("Photo Editi…"));
+        settings.active_profile = "Work".to_string();
+        assert_eq!(tray_title(&settings).as_deref(), Some("💼"));
+    }
+
+    #[test]
+    fn first_reveal_hangs_the_window_off_the_tray_icon() {
+        let area = (0, 25, 1440, 875);
+        // A menu bar icon: below it, centred, and pulled in from the edge.
+        assert_eq!(
+            window_position_near_tray((700, 0, 40, 24), (400, 600), area),
+            (520, 25)
+        );
+        assert_eq!(
+            window_position_near_tray((1420, 0, 40, 24), (400, 600), area),
+            (1040, 25)
+        );
+        // A taskbar icon at the bottom: above it.
+        assert_eq!(
+            window_position_near_tray((1300, 860, 40, 40), (400, 600), area),
+            (1040, 260)
+        );
+    }
+
+    #[test]
+    fn saved_window_geometry_is_fitted_to_the_displays_connected_now() {
+        let saved = WindowGeometry {
+            x: 2200,
+            y: 100,
+            width: 900,
+            height: 700,
+            monitor: Some("DELL U2720Q".to_string()),
+        };
+        let laptop = (
+            Some("Built-in Retina Display".to_string()),
+            (0, 25, 1440, 875),
+        );
+        let external = (Some("DELL U2720Q".to_string()), (1440, 0, 2560, 1440));
+        assert_eq!(
+            fit_window_geometry(&saved, &[laptop.clone(), external]),
+            Some(saved.clone())
+        );
+        // The external display is gone: pulled onto the laptop's screen.
+        assert_eq!(
+            fit_window_geometry(&saved, std::slice::from_ref(&laptop)),
+            Some(WindowGeometry {
+                x: 540,
+                y: 100,
+                width: 900,
+                height: 700,
+                monitor: laptop.0.clone(),
+            })
+        );
+        // Too big for the display it lands on: shrunk to its work area.
+        let small = (None, (0, 0, 800, 600));
+        assert_eq!(
+            fit_window_geometry(&saved, &[small]),
+            Some(WindowGeometry {
+                x: 0,
+                y: 0,
+                width: 800,
+                height: 600,
+                monitor: None,
+            })
+        );
+        assert_eq!(fit_window_geometry(&saved, &[]), None);
+    }
+
+    #[test]
+    fn connect_hud_lists_the_reconnected_devices_mappings() {
+        let mut settings = parse_settings(
+            r#"{"selected_device": "m", "active_profile": "Work",
+                "profiles": {"Work": {"devices": {"m": {"name": "Desk Mouse", "buttons": {
+                    "left": "Default", "button5": "Forward", "button4": "Back"}}}}}}"#,
+        )
+        .unwrap();
+        let mouse = ("m".to_string(), "MX Anywhere".to_string());
+        let other = ("o".to_string(), "Other".to_string());
+        let hud = connect_hud(
+            &settings,
+            std::slice::from_ref(&other),
+            &[other.clone(), mouse.clone()],
+        );
+        assert_eq!(
+            hud,
+            Some(ConnectHud {
+                device: "Desk Mouse".to_string(),
+                profile: "Work".to_string(),
+                mappings: vec![
+                    HudMapping {
+                        button: "button4",
+                        action: "Back".to_string(),
+                    },
+                    HudMapping {
+                        button: "button5",
+                        action: "Forward".to_string(),
+                    },
+                ],
+            })
+        );
+        // Only as it connects, and only the selected device.
+        assert_eq!(
+            connect_hud(
+                &settings,
+                std::slice::from_ref(&mouse),
+                std::slice::from_ref(&mouse)
+            ),
+            None
+        );
+        assert_eq!(
+            connect_hud(&settings, &[], std::slice::from_ref(&other)),
+            None
+        );
+        settings.selected_device = None;
+        assert_eq!(
+            connect_hud(&settings, &[], std::slice::from_ref(&mouse)),
+            None
+        );
+
+        // A newer HUD keeps the older one's timer from dismissing it.
+        let state = AppState::default();
+        assert_eq!(state.take_hud(), None);
+        state.queue_hud(Hud::Connect(hud.unwrap()));
+        let (first, _) = state.take_hud().unwrap();
+        let toast = Hud::Profile {
+            profile: "Editing".to_string(),
+        };
+        state.queue_hud(toast.clone());
+        let (second, _) = state.take_hud().unwrap();
+        assert!(!state.dismiss_hud(first));
+        assert_eq!(state.showing_hud(), Some(toast.clone()));
+        assert!(state.dismiss_hud(second));
+        assert_eq!(state.showing_hud(), None);
+        assert_eq!(
+            serde_json::to_value(&toast).unwrap(),
+            serde_json::json!({"kind": "profile", "profile": "Editing"})
+        );
+    }
+
+    #[test]
+    fn device_notices_cover_configured_devices_and_are_rate_limited() {
+        let mut settings = Settings::default();
+        settings.apply_preset("046d:b023:A", presets::find("mx-master-3").unwrap(), 5);
+        settings.selected_device = Some("046d:b023:A".to_string());
+        let mx = ("046d:b023:A".to_string(), "MX Master 3".to_string());
+        let other = ("1234:5678:A".to_string(), "Generic Mouse".to_string());
+        let before = vec![mx.clone(), other.clone()];
+        assert_eq!(
+            device_change_notices(&settings, &before, &[], Locale::En),
+            vec![(
+                mx.0.clone(),
+                "MX Master 3 disconnected — remapping paused".to_string()
+            )]
+        );
+        assert_eq!(
+            device_change_notices(&settings, std::slice::from_ref(&other), &before, Locale::En),
+            vec![(
+                mx.0.clone(),
+                "MX Master 3 reconnected — remapping resumed".to_string()
+            )]
+        );
+        settings.remap_enabled = false;
+        assert_eq!(
+            device_change_notices(&settings, &before, &[], Locale::En)[0].1,
+            "MX Master 3 disconnected"
+        );
+        assert!(device_change_notices(&settings, &before, &before, Locale::En).is_empty());
+
+        let mut notices = HashMap::new();
+        let start = Instant::now();
+        assert!(take_notice_slot(&mut notices, &mx.0, start));
+        assert!(!take_notice_slot(
+            &mut notices,
+            &mx.0,
+            start + Duration::from_secs(5)
+        ));
+        assert!(take_notice_slot(
+            &mut notices,
+            &other.0,
+            start + Duration::from_secs(5)
+        ));
+        assert!(take_notice_slot(
+            &mut notices,
+            &mx.0,
+            start + DEVICE_NOTICE_INTERVAL
+        ));
+    }
+
+    #[test]
+    fn connect_rules_run_once_for_devices_that_just_connected() {
+        let mut settings = Settings::default();
+        settings.create_profile("Art", Profile::default()).unwrap();
+        let rules: Vec<ConnectRule> = serde_json::from_str(
+            r#"[{"type": "switch_profile", "profile": "Art"},
+                {"type": "set_remap_enabled", "enabled": true},
+                {"type": "run_action", "action": {"type": "toggle_focus"}}]"#,
+        )
+        .unwrap();
+        assert_eq!(
+            rules[2],
+            ConnectRule::RunAction {
+                action: ActionValue::Action(Action::ToggleFocus)
+            }
+        );
+        settings
+            .profiles
+            .get_mut(DEFAULT_PROFILE)
+            .unwrap()
+            .devices
+            .insert(
+                "056a:0374:A".to_string(),
+                DeviceConfig {
+                    name: "Intuos".to_string(),
+                    on_connect: rules.clone(),
+                    ..DeviceConfig::default()
+                },
+            );
+        assert!(settings.validate().is_ok());
+        let tablet = ("056a:0374:A".to_string(), "Intuos".to_string());
+        let other = ("046d:c08b:A".to_string(), "G502".to_string());
+        let both = [tablet.clone(), other.clone()];
+        assert_eq!(
+            connect_rules(&settings, std::slice::from_ref(&other), &both),
+            vec![(tablet.0.clone(), rules)]
+        );
+        assert!(connect_rules(&settings, std::slice::from_ref(&tablet), &both).is_empty());
+
+        // A dongle dropping in and out runs them once.
+        let state = AppState::default();
+        state.queue_connect_rules(&settings, &[], std::slice::from_ref(&tablet));
+        state.queue_connect_rules(&settings, &[], std::slice::from_ref(&tablet));
+        assert_eq!(state.take_connect_rules().len(), 1);
+        assert!(state.take_connect_rules().is_empty());
+
+        let config = settings
+            .profiles
+            .get_mut(DEFAULT_PROFILE)
+            .unwrap()
+            .devices
+            .get_mut("056a:0374:A")
+            .unwrap();
+        config.on_connect = vec![
+            ConnectRule::SwitchProfile {
+                profile: "Games".to_string(),
+            },
+            ConnectRule::RunAction {
+                action: ActionValue::Action(Action::Disabled),
+            },
+            ConnectRule::SetRemapEnabled { enabled: false },
+        ];
+        let codes: Vec<&str> = settings.issues().iter().map(|issue| issue.code).collect();
+        assert_eq!(codes, ["unknown-profile", "unsupported-action"]);
+        assert!(settings.validate().is_err());
+    }
+
+    #[test]
+    fn launch_flags_parse_and_unknown_ones_are_rejected() {
+        let parse = |args: &[&str]| {
+            LaunchArgs::parse(&args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>())
+        };
+        assert_eq!(parse(&[]).unwrap(), LaunchArgs::default());
+        let launch = parse(&["--toggle", "--profile", "Work", "--config-dir", "/tmp/x"]).unwrap();
+        assert!(launch.toggle && launch.is_command() && !launch.hidden);
+        assert_eq!(launch.profile.as_deref(), Some("Work"));
+        assert_eq!(
+            parse(&["--profile=Games", "--config-dir=/tmp/x", "-psn_0_4711"])
+                .unwrap()
+                .profile
+                .as_deref(),
+            Some("Games")
+        );
+        let launch = parse(&["--hidden", "--list-devices"]).unwrap();
+        assert!(launch.hidden && launch.list_devices && !launch.is_command());
+        let launch = parse(&["--safe-mode"]).unwrap();
+        assert!(launch.safe_mode && !launch.is_command());
+        assert!(parse(&["--profile"]).is_err());
+        assert_eq!(
+            parse(&["--verbose"]).unwrap_err(),
+            "unknown option --verbose"
+        );
+        assert!(parse(&["Work"]).is_err());
+
+        let launch = parse(&["editmouse://toggle"]).unwrap();
+        assert!(launch.is_command() && !launch.toggle);
+        assert_eq!(launch.links, ["editmouse://toggle"]);
+    }
+
+    #[test]
+    fn ctl_commands_parse_into_requests() {
+        let parse =
+            |args: &[&str]| parse_ctl(&args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>());
+        assert_eq!(
+            parse(&["profile", "Work"]).unwrap(),
+            (
+                control::Request::Profile {
+                    name: "Work".to_string()
+                },
+                false
+            )
+        );
+        assert_eq!(
+            parse(&["--config-dir", "/tmp/x", "status", "--json"]).unwrap(),
+            (control::Request::Status, true)
+        );
+        assert_eq!(
+            parse(&["pause", "30m"]).unwrap().0,
+            control::Request::Pause {
+                seconds: Some(1800)
+            }
+        );
+        assert_eq!(
+            parse(&["pause"]).unwrap().0,
+            control::Request::Pause { seconds: None }
+        );
+        assert_eq!(
+            parse(&["cheatsheet", "Work", "--html"]).unwrap().0,
+            control::Request::Cheatsheet {
+                profile: Some("Work".to_string()),
+                format: CheatsheetFormat::Html,
+                include_sensitive: false,
+            }
+        );
+        for args in [
+            &["pause", "30"][..],
+            &["pause", "0s"],
+            &["pause", "m"],
+            &["profile"],
+            &["cheatsheet", "Work", "Home"],
+            &[],
+        ] {
+            assert!(parse(args).is_err(), "{:?}", args);
+        }
+        assert_eq!(parse_pause_duration("2h"), Ok(7200));
+        assert!(parse_pause_duration("2é").is_err());
+
+        let status = serde_json::json!({"remap_enabled": true, "selected_device": null});
+        assert_eq!(
+            ctl_output(&status),
+            "remap_enabled: true\nselected_device: -\n"
+        );
+        let devices = serde_json::json!([{"id": "046d:c08b:A", "name": "G502"}]);
+        assert_eq!(ctl_output(&devices), "046d:c08b:A\tG502\n");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn control_socket_answers_each_request_line() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = TempDir::new("control");
+        let path = dir.join(control::SOCKET_FILE);
+        control::listen(&path, |request| match request {
+            control::Request::Profile { name } if name == "Missing" => {
+                Err("no profile named Missing".to_string())
+            }
+            request => serde_json::to_value(request).map_err(|err| err.to_string()),
+        })
+        .unwrap();
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+
+        let reply = control::send(&path, &control::Request::Toggle).unwrap();
+        assert!(reply.ok);
+        assert_eq!(reply.result, Some(serde_json::json!({"command": "toggle"})));
+        let missing = control::Request::Profile {
+            name: "Missing".to_string(),
+        };
+        let reply = control::send(&path, &missing).unwrap();
+        assert!(!reply.ok);
+        assert_eq!(reply.error.as_deref(), Some("no profile named Missing"));
+    }
+
+    #[test]
+    fn integration_server_needs_the_token_and_speaks_websocket() {
+        use std::io::{BufRead, BufReader, Read};
+
+        assert_eq!(
+            integration::accept_key("dGhlIHNhbXBsZSBub25jZQ=="),
+            "s3pPLMBiTxaQ9kYGzzhZRbK+xOo="
+        );
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let server = integration::Server::start(port, "secret".to_string(), |request| {
+            serde_json::to_value(request).map_err(|err| err.to_string())
+        })
+        .unwrap();
+        let connect = |target: &str| {
+            let mut stream = std::net::TcpStream::connect(("127.0.0.1", port)).unwrap();
+            write!(
+                stream,
+                "GET {} HTTP/1.1\r\nHost: localhost\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\nSec-WebSocket-Version: 13\r\n\r\n",
+                target
+            )
+            .unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut status = String::new();
+            reader.read_line(&mut status).unwrap();
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if line.trim().is_empty() {
+                    break;
+                }
+            }
+            (status, stream, reader)
+        };
+        let read_text = |reader: &mut BufReader<std::net::TcpStream>| {
+            let mut head = [0u8; 2];
+            reader.read_exact(&mut head).unwrap();
+            assert_eq!(head[0], 0x81);
+            let mut payload = vec![0u8; usize::from(head[1])];
+            reader.read_exact(&mut payload).unwrap();
+            serde_json::from_slice::<serde_json::Value>(&payload).unwrap()
+        };
+
+        assert!(connect("/?token=wrong").0.starts_with("HTTP/1.1 401"));
+        assert!(connect("/").0.starts_with("HTTP/1.1 401"));
+        let (status, mut stream, mut reader) = connect("/?token=secret");
+        assert!(status.starts_with("HTTP/1.1 101"));
+
+        let request = br#"{"command":"profile","name":"Editing"}"#;
+        let mask = [1u8, 2, 3, 4];
+        let mut frame = vec![0x81, 0x80 | request.len() as u8];
+        frame.extend_from_slice(&mask);
+        frame.extend(
+            request
+                .iter()
+                .enumerate()
+                .map(|(i, byte)| byte ^ mask[i % 4]),
+        );
+        stream.write_all(&frame).unwrap();
+        assert_eq!(
+            read_text(&mut reader),
+            serde_json::json!({"ok": true, "result": {"command": "profile", "name": "Editing"}})
+        );
+
+        server.broadcast("profile-changed", serde_json::json!({"profile": "Editing"}));
+        assert_eq!(
+            read_text(&mut reader),
+            serde_json::json!({"event": "profile-changed", "payload": {"profile": "Editing"}})
+        );
+        assert_eq!(server.clients(), 1);
+
+        drop(server);
+        let mut rest = Vec::new();
+        assert_eq!(reader.read_to_end(&mut rest).unwrap_or_default(), 0);
+        // The accept loop lets go of the port the next time it looks.
+        std::thread::sleep(Duration::from_millis(500));
+        assert!(std::net::TcpStream::connect(("127.0.0.1", port)).is_err());
+    }
+
+    #[test]
+    fn links_validate_their_path() {
+        assert_eq!(
+            DeepLink::parse("editmouse://profile/Editing"),
+            Ok(DeepLink::Profile("Editing".to_string()))
+        );
+        assert_eq!(
+            DeepLink::parse("EditMouse://profile/Photo%20Editing/?from=raycast"),
+            Ok(DeepLink::Profile("Photo Editing".to_string()))
+        );
+        assert_eq!(DeepLink::parse("editmouse://toggle"), Ok(DeepLink::Toggle));
+        assert_eq!(DeepLink::parse("editmouse://show/"), Ok(DeepLink::Show));
+        for url in [
+            "editmouse://profile",
+            "editmouse://profile/%20",
+            "editmouse://profile/a/b",
+            "editmouse://profile/%zz",
+            "editmouse://profile/%ff",
+            "editmouse://quit",
+            "https://profile/Editing",
+            "editmouse:toggle",
+        ] {
+            assert!(DeepLink::parse(url).is_err(), "{}", url);
+        }
+    }
+
+    #[test]
+    fn privacy_panes_follow_the_settings_app_of_the_macos_version() {
+        use permissions::{settings_url, PermissionKind};
+        assert_eq!(
+            settings_url(PermissionKind::InputMonitoring, 12),
+            "x-apple.systempreferences:com.apple.preference.security?Privacy_ListenEvent"
+        );
+        assert_eq!(
+            settings_url(PermissionKind::Accessibility, 14),
+            "x-apple.systempreferences:com.apple.settings.PrivacySecurity.extension?Privacy_Accessibility"
+        );
+    }
+
+    #[test]
+    fn relaunches_keep_the_config_dir_but_not_commands() {
+        let args: Vec<String> = [
+            "--toggle",
+            "--config-dir",
+            "/tmp/x",
+            "--hidden",
+            "--profile=Work",
+        ]
+        .iter()
+        .map(|arg| arg.to_string())
+        .collect();
+        assert_eq!(
+            relaunch_args(&args, false),
+            vec!["--config-dir", "/tmp/x", "--relaunch"]
+        );
+        let relaunch = relaunch_args(&["--config-dir=/tmp/y".to_string()], true);
+        assert_eq!(
+            relaunch,
+            vec!["--config-dir=/tmp/y", "--relaunch", "--hidden"]
+        );
+        let launch = LaunchArgs::parse(&relaunch).unwrap();
+        assert!(launch.relaunch && launch.hidden && !launch.is_command());
+    }
+
+    #[test]
+    fn logs_rotate_and_their_tail_spans_the_rotated_files() {
+        let dir = TempDir::new("logs");
+        fs::write(dir.join(logging::FILE), "one\ntwo\n").unwrap();
+        logging::rotate(&dir);
+        fs::write(dir.join(logging::FILE), "three\nfour\n").unwrap();
+        assert_eq!(logging::recent(&dir, 3), vec!["two", "three", "four"]);
+        assert_eq!(
+            logging::recent(&dir, 10),
+            vec!["one", "two", "three", "four"]
+        );
+        // Only two rotated files are kept.
+        for _ in 0..3 {
+            logging::rotate(&dir);
+        }
+        assert!(logging::recent(&dir, 10).is_empty());
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 0);
+
+        let time = UNIX_EPOCH + Duration::from_millis(1_791_980_096_789);
+        assert_eq!(logging::timestamp(time), "2026-10-14 12:14:56.789");
+    }
+
+    #[test]
+    fn settings_snapshots_say_what_the_theme_comes_out_as() {
+        assert_eq!(
+            effective_theme("dark", SystemTheme::Light),
+            SystemTheme::Dark
+        );
+        assert_eq!(
+            effective_theme("light", SystemTheme::Dark),
+            SystemTheme::Light
+        );
+        assert_eq!(
+            effective_theme("system", SystemTheme::Dark),
+            SystemTheme::Dark
+        );
+
+        let snapshot = SettingsSnapshot {
+            settings: Settings::default(),
+            effective_theme: SystemTheme::Dark,
+        };
+        let value = serde_json::to_value(&snapshot).unwrap();
+        assert_eq!(value["theme"], "system");
+        assert_eq!(value["effective_theme"], "dark");
+        // The frontend saves what it was given, snapshot field and all.
+        let saved: Settings = serde_json::from_value(value).unwrap();
+        assert_eq!(saved.theme, "system");
+    }
+
+    #[test]
+    fn crash_reports_are_taken_once_and_only_the_latest_kept() {
+        let dir = TempDir::new("crash");
+        assert_eq!(logging::take_crash(&dir), None);
+        for n in 0..6 {
+            fs::write(
+                dir.join(format!("crash-2026-01-0{}-00-00-00.log", n + 1)),
+                "old",
+            )
+            .unwrap();
+        }
+        fs::write(dir.join(logging::CRASH_FILE), "thread main panicked").unwrap();
+        assert_eq!(
+            logging::take_crash(&dir).as_deref(),
+            Some("thread main panicked")
+        );
+        assert_eq!(logging::take_crash(&dir), None);
+        let mut names: Vec<String> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect();
+        names.sort();
+        assert_eq!(names.len(), 5);
+        assert_eq!(names[0], "crash-2026-01-03-00-00-00.log");
+    }
+
+    #[test]
+    fn zip_archives_hold_deflated_files_listed_at_the_end() {
+        use std::io::Read;
+
+        let contents = "settings\n".repeat(100).into_bytes();
+        let mut archive = Vec::new();
+        let files = [
+            ("settings.json", contents.clone()),
+            ("empty.log", Vec::new()),
+        ];
+        let modified = UNIX_EPOCH + Duration::from_millis(1_791_980_096_789);
+        zip::write(&mut archive, &files, modified).unwrap();
+
+        let u16_at = |at: usize| u16::from_le_bytes([archive[at], archive[at + 1]]);
+        let u32_at = |at: usize| u32::from_le_bytes(archive[at..at + 4].try_into().unwrap());
+        assert_eq!(u32_at(0), 0x0403_4b50);
+        assert_eq!(u16_at(8), 8);
+        // 2026-10-14 12:14:56.
+        assert_eq!(u16_at(12), (46 << 9) | (10 << 5) | 14);
+        assert_eq!(u16_at(10), (12 << 11) | (14 << 5) | 28);
+        let compressed = u32_at(18) as usize;
+        assert!(compressed < contents.len());
+        assert_eq!(u32_at(22) as usize, contents.len());
+        assert_eq!(&archive[30..43], b"settings.json");
+        let mut inflated = Vec::new();
+        flate2::read::DeflateDecoder::new(&archive[43..43 + compressed])
+            .read_to_end(&mut inflated)
+            .unwrap();
+        assert_eq!(inflated, contents);
+        let mut crc = flate2::Crc::new();
+        crc.update(&contents);
+        assert_eq!(u32_at(14), crc.sum());
+
+        let end = archive.len() - 22;
+        assert_eq!(u32_at(end), 0x0605_4b50);
+        assert_eq!(u16_at(end + 10), 2);
+        let directory = u32_at(end + 16) as usize;
+        assert_eq!(directory + u32_at(end + 12) as usize, end);
+        assert_eq!(u32_at(directory), 0x0201_4b50);
+        assert_eq!(u32_at(directory + 42), 0);
+        let second = directory + 46 + "settings.json".len();
+        assert_eq!(u32_at(second), 0x0201_4b50);
+        assert_eq!(u32_at(second + 42) as usize, 43 + compressed);
+    }
+
+    #[test]
+    fn diagnostics_hide_serials_and_unknown_action_payloads() {
+        assert_eq!(device_id_serial("046d:c52b:ABC12345"), Some("ABC12345"));
+        assert_eq!(device_id_serial("046d:c52b:noserial"), None);
+        assert_eq!(device_id_serial("046d:c52b:bt-0a1b2c3d"), None);
+        assert_eq!(device_id_serial("046d:c52b:usb-i2-0a1b2c3d"), None);
+        assert_eq!(device_id_serial(ANY_DEVICE_ID), None);
+
+        let placeholders =
+            serial_placeholders(["ABC1", "ABC12345", "ABC1", "7"].map(str::to_string));
+        assert_eq!(
+            placeholders,
+            vec![
+                ("ABC12345".to_string(), "serial-2".to_string()),
+                ("ABC1".to_string(), "serial-1".to_string()),
+            ]
+        );
+        assert_eq!(
+            redact_serials("046d:c52b:ABC12345 and 046d:c52c:ABC1", &placeholders),
+            "046d:c52b:serial-2 and 046d:c52c:serial-1"
+        );
+
+        let mut settings = serde_json::json!({
+            "buttons": {
+                "button4": {"type": "back"},
+                "button5": {"type": "run_command", "command": "rm -rf ~"},
+                "button6": {
+                    "type": "gesture",
+                    "tap": {"type": "type_text", "text": "hunter2"},
+                    "up": {"type": "set_dpi", "dpi": 800},
+                },
+            },
+        });
+        redact_unknown_actions(&mut settings);
+        assert_eq!(
+            settings,
+            serde_json::json!({
+                "buttons": {
+                    "button4": {"type": "back"},
+                    "button5": {"type": "run_command", "redacted": true},
+                    "button6": {
+                        "type": "gesture",
+                        "tap": {"type": "type_text", "redacted": true},
+                        "up": {"type": "set_dpi", "dpi": 800},
+                    },
+                },
+            })
+        );
+    }
+
+    #[test]
+    fn cheatsheets_list_each_device_and_override_and_redact_unknown_payloads() {
+        let mut settings = Settings::default();
+        let device: DeviceConfig = serde_json::from_value(serde_json::json!({
+            "name": "G502",
+            "buttons": {
+                "button4": {"type": "shortcut", "keycode": 8, "modifiers": ["command"]},
+                "button5": {"type": "run_command", "command": "rm -rf ~"},
+                "middle": {"type": "gesture", "tap": "Middle Click", "up": "Next Tab"},
+            },
+            "modifier_mappings": {"button4": [{"modifiers": ["shift"], "action": "Forward"}]},
+            "triggers": {"button4": "release"},
+            "app_overrides": {"com.apple.Safari": {"button4": "Back"}},
+        }))
+        .unwrap();
+        let profile = settings.profiles.get_mut(DEFAULT_PROFILE).unwrap();
+        profile.devices.insert("046d:c08b:A".to_string(), device);
+        settings.profile_app_rules.push(ProfileAppRule {
+            bundle_id: "com.apple.mail".to_string(),
+            profile: DEFAULT_PROFILE.to_string(),
+        });
+
+        let sheet = render_cheatsheet(
+            &settings,
+            DEFAULT_PROFILE,
+            CheatsheetFormat::Markdown,
+            false,
+        )
+        .unwrap();
+        assert_eq!(
+            sheet,
+            "# Edit Mouse: Default\n\n\
+             Switched to in com.apple.mail.\n\n\
+             ## G502 (046d:c08b:A)\n\n\
+             | Button | Action | Details |\n\
+             | --- | --- | --- |\n\
+             | Middle | Gesture | Tap: Middle Click, Up: Next Tab |\n\
+             | Button 4 | Shortcut | ⌘C; ⇧: Forward; on release |\n\
+             | Button 5 | run_command | Unknown action, redacted |\n\n\
+             ## G502 (046d:c08b:A) in com.apple.Safari\n\n\
+             | Button | Action | Details |\n\
+             | --- | --- | --- |\n\
+             | Button 4 | Back |  |\n"
+        );
+        let sheet =
+            render_cheatsheet(&settings, DEFAULT_PROFILE, CheatsheetFormat::Html, true).unwrap();
+        assert!(sheet.starts_with("<!DOCTYPE html>"));
+        assert!(sheet.contains("<td>Button 5</td><td>Unknown</td>"));
+        assert!(sheet.contains("rm -rf ~"));
+        assert!(sheet.contains("&quot;run_command&quot;"));
+        assert!(
+            render_cheatsheet(&settings, "Missing", CheatsheetFormat::Markdown, false).is_err()
+        );
+    }
+
+    #[test]
+    fn update_checks_compare_release_tags_with_this_build() {
+        assert!(is_newer_version("v1.10.0", "1.9.3"));
+        assert!(is_newer_version("1.2.1", "1.2"));
+        assert!(!is_newer_version("1.2", "1.2.0"));
+        assert!(is_newer_version("1.2.0", "1.2.0-beta.2"));
+        assert!(!is_newer_version("1.3.0-beta.1", "1.3.0"));
+        assert!(!is_newer_version("nightly", "1.0.0"));
+
+        let body =
+            r#"{"tag_name": "v1.1.0", "html_url": "https://example.com/1.1.0", "body": "Fixes"}"#;
+        assert_eq!(
+            parse_release_response(&format!("{}\n200", body), "1.0.0"),
+            Ok(UpdateInfo {
+                current: "1.0.0".to_string(),
+                latest: "1.1.0".to_string(),
+                url: "https://example.com/1.1.0".to_string(),
+                notes: "Fixes".to_string(),
+                available: true,
+            })
+        );
+        let update = parse_release_response(&format!("{}\n200", body), "1.1.0").unwrap();
+        assert!(!update.available);
+        let limited =
+            parse_release_response("{\"message\": \"API rate limit exceeded\"}\n403", "1.0.0");
+        assert!(limited.unwrap_err().contains("limiting"));
+        assert!(parse_release_response("{}\n404", "1.0.0").is_err());
+    }
+
+    #[test]
+    fn locked_sessions_pass_everything_through() {
+        let path = logind_session_path("2");
+        assert_eq!(path, "/org/freedesktop/login1/session/_32");
+        assert_eq!(
+            logind_session_path("c2"),
+            "/org/freedesktop/login1/session/c2"
+        );
+        let line = |signal: &str| format!("{}: {}", path, signal);
+        let changed =
+            "org.freedesktop.DBus.Properties.PropertiesChanged ('org.freedesktop.login1.Session',";
+        assert_eq!(
+            logind_session_change(
+                &line(&format!("{} {{'LockedHint': <true>}}, @as [])", changed)),
+                &path
+            ),
+            (Some(true), None)
+        );
+        assert_eq!(
+            logind_session_change(
+                &line(&format!(
+                    "{} {{'Active': <false>, 'IdleHint': <true>}}, @as [])",
+                    changed
+                )),
+                &path
+            ),
+            (None, Some(false))
+        );
+        assert_eq!(
+            logind_session_change(&line("org.freedesktop.login1.Session.Unlock ()"), &path),
+            (Some(false), None)
+        );
+        let other = "/org/freedesktop/login1/session/_33: org.freedesktop.login1.Session.Lock ()";
+        assert_eq!(logind_session_change(other, &path), (None, None));
+
+        let state = AppState::default();
+        state.hold_mapping(3, None);
+        assert!(!state.is_passing_through());
+        assert!(state.set_session_active(false));
+        assert!(!state.set_session_active(false));
+        assert!(state.is_passing_through());
+        assert_eq!(state.release_mapping(3), None);
+        assert!(state.set_session_active(true));
+        assert!(!state.is_passing_through());
+    }
+
+    #[test]
+    fn safe_mode_passes_everything_through_until_left() {
+        let state = AppState::default();
+        state.hold_mapping(3, None);
+        assert!(state.set_safe_mode(true));
+        assert!(!state.set_safe_mode(true));
+        assert!(state.is_passing_through());
+        assert!(!state.should_remap(0));
+        assert_eq!(state.release_mapping(3), None);
+        // Turning remapping on doesn't leave it.
+        state.remap_disabled.store(false, Ordering::Relaxed);
+        assert!(state.is_passing_through());
+        assert!(state.set_safe_mode(false));
+        assert!(!state.is_passing_through());
+    }
+
+    #[test]
+    fn onboarding_shows_once_and_whats_new_once_per_version() {
+        let fresh = Settings::default();
+        let info = app_details(&fresh, "1.1.0", false);
+        assert!(info.first_run && !info.whats_new);
+        assert_eq!(info.last_seen_version, None);
+
+        let seen = Settings {
+            onboarding_completed: true,
+            last_seen_version: "1.1.0".to_string(),
+            ..Settings::default()
+        };
+        assert!(!app_details(&seen, "1.1.0", true).first_run);
+        assert!(!app_details(&seen, "1.1.0", true).whats_new);
+        let upgraded = app_details(&seen, "1.2.0", true);
+        assert!(upgraded.whats_new);
+        assert_eq!(upgraded.last_seen_version.as_deref(), Some("1.1.0"));
+
+        // Files from before onboarding was recorded skip it.
+        let settings = parse_settings(r#"{"version": 3}"#).unwrap();
+        assert!(settings.onboarding_completed);
+        assert!(app_details(&settings, "1.1.0", true).whats_new);
+    }
+
+    #[test]
+    fn action_history_keeps_the_latest_fired_actions() {
+        let state = AppState::default();
+        state.update_settings(Settings::default());
+        let safari = frontmost::AppInfo {
+            bundle_id: "com.apple.Safari".to_string(),
+            name: "Safari".to_string(),
+            ..Default::default()
+        };
+        state.set_frontmost_app(Some(safari), false);
+        assert_eq!(state.frontmost_app_name().as_deref(), Some("Safari"));
+        for timestamp in 0..ACTION_HISTORY as u64 + 5 {
+            state.push_history(FiredAction {
+                timestamp,
+                device_id: None,
+                button: "button4",
+                action: Action::Back.name(),
+                app: state.frontmost_app_name(),
+            });
+        }
+        let history = state.action_history();
+        assert_eq!(history.len(), ACTION_HISTORY);
+        assert_eq!(history[0].timestamp, 5);
+
+        state.update_settings(Settings {
+            record_history: false,
+            ..Settings::default()
+        });
+        assert!(state.action_history().is_empty());
+    }
+
+    #[test]
+    fn usage_stats_count_per_device_button_and_day() {
+        let mut stats = UsageStats::default();
+        stats.record("2026-01-01", "046d:c52b", "button4", "back");
+        stats.record("2026-01-01", "046d:c52b", "button4", "back");
+        stats.record("2026-01-31", "046d:c52b", "button4", "back");
+        stats.record("2026-01-31", "*", "middle", "middle_click");
+        stats.record_bounce("046d:c52b", "left");
+        stats.record_bounce("046d:c52b", "left");
+        assert_eq!(stats.totals["046d:c52b"]["button4"]["back"], 3);
+        assert_eq!(stats.days["2026-01-01"]["046d:c52b"]["button4"]["back"], 2);
+        assert_eq!(stats.days["2026-01-31"]["*"]["middle"]["middle_click"], 1);
+        assert_eq!(stats.bounces["046d:c52b"]["left"], 2);
+
+        let stored: UsageStats =
+            serde_json::from_str(&serde_json::to_string(&stats).unwrap()).unwrap();
+        assert_eq!(stored, stats);
+
+        // 2026-01-31 plus the 29 days before it, back to 2026-01-02.
+        stats.prune(UNIX_EPOCH + Duration::from_secs(1_769_860_800));
+        assert_eq!(stats.days.keys().collect::<Vec<_>>(), ["2026-01-31"]);
+        assert_eq!(stats.totals["046d:c52b"]["button4"]["back"], 3);
+        assert_eq!(
+            serde_json::from_str::<UsageStats>("{}").unwrap(),
+            UsageStats::default()
+        );
+    }
+
+    #[test]
+    fn action_tests_resolve_like_a_press_for_the_frontmost_app() {
+        let json = r#"{
+            "selected_device":"046d:c08b:A",
+            "devices":{"046d:c08b:A":{
+                "buttons":{"button4":"Back"},
+                "app_overrides":{"com.figma.Desktop":{"button4":"Disabled"}}
+            }}
+        }"#;
+        let state = AppState::default();
+        state.update_settings(parse_settings(json).unwrap());
+        state.update_devices(&[MouseDevice {
+            id: "046d:c08b:A".to_string(),
+            name: "G502".to_string(),
+            kind: DeviceKind::Mouse,
+            button_count: 5,
+            preset: None,
+            first_seen: None,
+            ambiguous: false,
+            disabled: false,
+            ignored: false,
+        }]);
+        let device = Some("046d:c08b:A");
+        let tested = plan_action_test(&state, device, ActionTest::Button(3), false).unwrap();
+        assert_eq!(tested.device_id.as_deref(), device);
+        assert_eq!((tested.button, tested.action), (Some("button4"), "Back"));
+        assert!(
+            plan_action_test(&state, Some("046d:c077:B"), ActionTest::Button(3), false).is_err()
+        );
+
+        let figma = frontmost::AppInfo {
+            bundle_id: "com.figma.Desktop".to_string(),
+            name: "Figma".to_string(),
+            ..Default::default()
+        };
+        state.set_frontmost_app(Some(figma), false);
+        let tested = plan_action_test(&state, device, ActionTest::Button(3), false).unwrap();
+        assert_eq!(tested.action, "Disabled");
+        assert_eq!(tested.app.as_deref(), Some("Figma"));
+
+        let payload = ActionTest::Action(Action::SetDpi { dpi: 1600 });
+        let tested = plan_action_test(&state, None, payload, false).unwrap();
+        assert_eq!((tested.device_id, tested.button), (None, None));
+        assert_eq!(tested.action, "Set DPI");
+        // Toggle Focus is refused where no strategy reaches Do Not Disturb.
+        let focus_test = ActionTest::Action(Action::ToggleFocus);
+        assert_eq!(
+            plan_action_test(&state, None, focus_test, false).is_ok(),
+            focus::strategy().is_some()
+        );
+        assert_eq!(focus::strategy_for(14, 2), Some(focus::Strategy::Shortcuts));
+        assert_eq!(focus::strategy_for(11, 7), None);
+        assert_eq!(
+            focus::strategy_for(10, 15),
+            Some(focus::Strategy::NotificationCenterDefaults)
+        );
+
+        state.arm_capture("046d:c08b:A");
+        assert!(plan_action_test(&state, None, payload, false).is_err());
+        state.cancel_capture();
+        assert!(state.set_session_active(false));
+        assert!(plan_action_test(&state, None, payload, true).is_err());
+    }
+
+    #[test]
+    fn later_launches_hand_their_arguments_to_the_first() {
+        let dir = TempDir::new("instance");
+        let args = vec!["--toggle".to_string()];
+        assert!(!single_instance::forward(&dir, &args, Duration::ZERO));
+
+        // Only one launch claims the folder, however close together.
+        let claim = single_instance::claim(&dir, Duration::ZERO).unwrap();
+        assert!(claim.is_some());
+        assert!(single_instance::claim(&dir, Duration::ZERO)
+            .unwrap()
+            .is_none());
+
+        let (sender, received) = std::sync::mpsc::channel();
+        single_instance::listen(&dir, move |args| sender.send(args).unwrap()).unwrap();
+        assert!(single_instance::forward(&dir, &args, Duration::ZERO));
+        assert_eq!(received.recv_timeout(Duration::from_secs(2)).unwrap(), args);
+
+        // Without the token, nothing is handed over.
+        let port_file = dir.join(single_instance::PORT_FILE);
+        let recorded = fs::read_to_string(&port_file).unwrap();
+        let port = recorded.split_once(' ').unwrap().0;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&port_file).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+        fs::write(&port_file, format!("{} guessed", port)).unwrap();
+        assert!(!single_instance::forward(&dir, &args, Duration::ZERO));
+        assert!(received.recv_timeout(Duration::from_millis(200)).is_err());
+
+        // A port nothing answers on, as after a crash, isn't a running instance.
+        let unused = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = unused.local_addr().unwrap().port();
+        drop(unused);
+        fs::write(&port_file, format!("{} token", port)).unwrap();
+        assert!(!single_instance::forward(&dir, &args, Duration::ZERO));
+
+        drop(claim);
+        assert!(single_instance::claim(&dir, Duration::ZERO)
+            .unwrap()
+            .is_some());
+    }
+
+    #[test]
+    fn presets_match_their_models_and_replace_mappings() {
+        assert_eq!(
+            presets::matching("046d:c08b:ABC").map(|preset| preset.id),
+            Some("g502")
+        );
+        assert_eq!(
+            presets::matching("046d:b023:#1a2b3c4d").map(|preset| preset.id),
+            Some("mx-master-3")
+        );
+        assert!(presets::matching("046d:c52b:serial").is_none());
+        assert!(presets::matching(ANY_DEVICE_ID).is_none());
+
+        let mut settings = Settings::default();
+        for preset in presets::PRESETS {
+            assert!(presets::find(preset.id).is_some());
+            settings.apply_preset(preset.id, preset, 8);
+        }
+        settings.validate().unwrap();
+
+        let mut config = DeviceConfig {
+            name: "My G502".to_string(),
+            dpi_stops: vec![1000],
+            ..DeviceConfig::default()
+        };
+        config
+            .app_overrides
+            .insert("com.apple.Safari".to_string(), HashMap::new());
+        settings
+            .profiles
+            .get_mut(DEFAULT_PROFILE)
+            .unwrap()
+            .devices
+            .insert("046d:c08b:A".to_string(), config);
+        settings.apply_preset("046d:c08b:A", presets::find("g502").unwrap(), 8);
+        let config = settings.device_config("046d:c08b:A").unwrap();
+        assert_eq!(config.name, "My G502");
+        assert_eq!(config.dpi_stops, vec![400, 800, 1600, 3200]);
+        assert!(config.app_overrides.is_empty());
+        assert_eq!(config.buttons["button6"].action(), Action::CycleDpi);
+        assert_eq!(config.buttons["button8"].action(), Action::Default);
+
+        let info = serde_json::to_value(presets::find("mx-master-3").unwrap().info()).unwrap();
+        assert_eq!(
+            info["buttons"]["button6"],
+            serde_json::json!({"type": "shortcut", "keycode": 126, "modifiers": ["control"]})
+        );
+        assert_eq!(info["models"][0], "046d:4082");
+    }
+
+    #[test]
+    fn preset_files_move_mappings_between_models() {
+        let mut settings = Settings::default();
+        settings.apply_preset("046d:b023:A", presets::find("mx-master-3").unwrap(), 8);
+        settings
+            .set_button_action(
+                "046d:b023:A",
+                "button8",
+                ActionValue::Action(Action::SetDpi { dpi: 1200 }),
+            )
+            .unwrap();
+        let exported = presets::PresetFile::export(
+            settings.device_config("046d:b023:A").unwrap(),
+            "046d:b023:A",
+        );
+        let data = serde_json::to_string(&exported).unwrap();
+        let file = presets::PresetFile::parse(&data).unwrap();
+        assert_eq!(file.name, "Logitech MX Master 3");
+        assert_eq!(file.model.as_deref(), Some("046d:b023"));
+        assert!(file.extended_buttons);
+
+        // A six-button mouse of another make gets what fits.
+        let skipped = settings.apply_preset_file("1532:0084:B", &file, 6);
+        assert_eq!(skipped, vec!["button7".to_string(), "button8".to_string()]);
+        let config = settings.device_config("1532:0084:B").unwrap();
+        assert_eq!(config.name, "Logitech MX Master 3");
+        assert_eq!(config.buttons.len(), 6);
+        assert_eq!(config.buttons["button6"], file.buttons["button6"],);
+        settings.validate().unwrap();
+
+        let parse = |json: &str| presets::PresetFile::parse(json);
+        assert!(parse(r#"{"name":"x","buttons":{"button4":{"type":"back"}}}"#).is_ok());
+        assert!(parse(r#"{"name":"x","buttons":{"button42":{"type":"back"}}}"#).is_err());
+        assert!(parse(r#"{"name":"x","buttons":{},"dpi_stops":[0]}"#).is_err());
+        assert!(parse(r#"{"version":99,"name":"x","buttons":{}}"#)
+            .unwrap_err()
+            .contains("newer version"));
+    }
+
+    #[test]
+    fn concurrent_button_edits_all_land() {
+        let state = AppState::default();
+        let saves = Arc::new(Mutex::new(Vec::new()));
+        let editors: Vec<_> = BUTTON_KEYS
+            .iter()
+            .map(|key| {
+                let state = state.clone();
+                let saves = Arc::clone(&saves);
+                std::thread::spawn(move || {
+                    state
+                        .edit_settings(
+                            |settings| {
+                                settings.set_button_action(
+                                    "046d:c08b:A",
+                                    key,
+                                    ActionValue::Action(Action::Disabled),
+                                )
+                            },
+                            |settings| {
+                                // Give the others every chance to interleave.
+                                std::thread::sleep(Duration::from_millis(2));
+                                saves.lock().unwrap().push(settings.clone());
+                                state.update_settings(settings);
+                                Ok(())
+                            },
+                        )
+                        .unwrap();
+                })
+            })
+            .collect();
+        for editor in editors {
+            editor.join().unwrap();
+        }
+        let settings = state.snapshot_settings();
+        let buttons = &settings.device_config("046d:c08b:A").unwrap().buttons;
+        assert_eq!(buttons.len(), BUTTON_KEYS.len());
+        assert!(buttons
+            .values()
+            .all(|action| action.action() == Action::Disabled));
+        // Each save had one more disabled button than the one before.
+        let saves = saves.lock().unwrap();
+        for (count, saved) in saves.iter().enumerate() {
+            let buttons = &saved.device_config("046d:c08b:A").unwrap().buttons;
+            let disabled = buttons
+                .values()
+                .filter(|action| action.action() == Action::Disabled)
+                .count();
+            assert_eq!(disabled, count + 1);
+        }
+
+        let mut settings = Settings::default();
+        assert!(settings
+            .set_button_action("046d:c08b:A", "button42", ActionValue::default())
+            .is_err());
+        // A failed edit or save leaves the settings as they were.
+        let before = state.snapshot_settings();
+        assert!(state
+            .edit_settings(
+                |settings| settings.set_button_action("x", "nope", ActionValue::default()),
+                |_| Ok(())
+            )
+            .is_err());
+        assert!(state
+            .edit_settings(
+                |settings| {
+                    settings.theme = "dark".to_string();
+                    Ok(())
+                },
+                |_| Err("disk full".to_string())
+            )
+            .is_err());
+        assert_eq!(state.snapshot_settings().theme, before.theme);
+    }
+
+    #[test]
+    fn settings_changes_can_be_undone_newest_first() {
+        let state = AppState::default();
+        let commit = |settings: Settings| {
+            state.update_settings(settings);
+            Ok(())
+        };
+        state
+            .edit_settings(
+                |settings| {
+                    let config = DeviceConfig {
+                        name: "G502".to_string(),
+                        ..DeviceConfig::default()
+                    };
+                    let profile = settings.profiles.get_mut(DEFAULT_PROFILE).unwrap();
+                    profile.devices.insert("046d:c08b:A".to_string(), config);
+                    Ok(())
+                },
+                commit,
+            )
+            .unwrap();
+        state
+            .edit_settings(
+                |settings| {
+                    settings.set_button_action(
+                        "046d:c08b:A",
+                        "button4",
+                        ActionValue::Action(Action::Back),
+                    )
+                },
+                commit,
+            )
+            .unwrap();
+        // Sightings alone aren't worth undoing.
+        state
+            .edit_settings(
+                |settings| {
+                    settings.record_sightings(&HashMap::from([("046d:c08b:A".to_string(), 5)]));
+                    Ok(())
+                },
+                commit,
+            )
+            .unwrap();
+        let history = state.settings_history();
+        assert_eq!(history.len(), 2);
+        assert_eq!(
+            history[0].summary,
+            vec!["changed button4 on G502 from Default to Back".to_string()]
+        );
+        assert_eq!(history[1].summary, vec!["added G502".to_string()]);
+
+        // A failed save keeps the change around to undo later.
+        assert!(state
+            .undo_settings_change(|_| Err("disk full".to_string()))
+            .is_err());
+        assert_eq!(state.settings_history().len(), 2);
+        let undone = state.undo_settings_change(commit).unwrap();
+        let buttons = &undone
+            .previous
+            .device_config("046d:c08b:A")
+            .unwrap()
+            .buttons;
+        assert_eq!(buttons["button4"].action(), Action::Default);
+        assert_eq!(
+            serde_json::to_value(state.snapshot_settings()).unwrap(),
+            serde_json::to_value(&undone.previous).unwrap()
+        );
+        state.undo_settings_change(commit).unwrap();
+        assert!(state
+            .snapshot_settings()
+            .device_config("046d:c08b:A")
+            .is_none());
+        assert_eq!(
+            state.undo_settings_change(commit).unwrap_err(),
+            "nothing to undo"
+        );
+
+        for theme in 0..SETTINGS_HISTORY + 5 {
+            state
+                .edit_settings(
+                    |settings| {
+                        settings.theme = theme.to_string();
+                        Ok(())
+                    },
+                    commit,
+                )
+                .unwrap();
+        }
+        assert_eq!(state.settings_history().len(), SETTINGS_HISTORY);
+        assert_eq!(
+            state.settings_history()[0].summary,
+            vec!["changed theme".to_string()]
+        );
+    }
+
+    #[test]
+    fn device_sightings_are_batched_into_every_profile() {
+        let state = AppState::default();
+        let device = |id: &str| MouseDevice {
+            id: id.to_string(),
+            name: id.to_string(),
+            kind: DeviceKind::Mouse,
+            button_count: 5,
+            preset: None,
+            first_seen: None,
+            ambiguous: false,
+            disabled: false,
+            ignored: false,
+        };
+        let hour = SIGHTINGS_FLUSH_INTERVAL.as_secs();
+        // The first enumeration after launch is written right away, later
+        // ones once an interval has passed.
+        assert!(state.note_devices_seen(&[device("046d:c08b:A")], 1000));
+        let first = state.take_sightings(1000);
+        assert!(!state.note_devices_seen(&[device("046d:c08b:A"), device("046d:b023:B")], 1060));
+        assert!(state.note_devices_seen(&[device("046d:c08b:A")], 1000 + hour));
+        assert_eq!(state.pending_sightings()["046d:b023:B"], 1060);
+        let later = state.take_sightings(1000 + hour);
+        assert!(state.pending_sightings().is_empty());
+
+        let mut settings = Settings::default();
+        settings
+            .profiles
+            .insert("Games".to_string(), Profile::default());
+        for profile in settings.profiles.values_mut() {
+            profile
+                .devices
+                .insert("046d:c08b:A".to_string(), DeviceConfig::default());
+        }
+        assert!(settings.record_sightings(&first));
+        assert!(!settings.record_sightings(&first));
+        assert!(settings.record_sightings(&later));
+        for profile in settings.profiles.values() {
+            let config = &profile.devices["046d:c08b:A"];
+            assert_eq!(config.first_seen, Some(1000));
+            assert_eq!(config.last_seen, Some(1000 + hour));
+            assert!(!profile.devices.contains_key("046d:b023:B"));
+        }
+        // A reset forgets mappings, not when the device was around.
+        settings.reset_device("046d:c08b:A", 5).unwrap();
+        assert_eq!(
+            settings.device_config("046d:c08b:A").unwrap().first_seen,
+            Some(1000)
+        );
+    }
+
+    #[test]
+    fn a_new_selection_is_in_effect_when_its_save_returns() {
+        let plugged = MouseDevice {
+            id: "046d:c08b:NEW".to_string(),
+            name: "G502".to_string(),
+            kind: DeviceKind::Mouse,
+            button_count: 5,
+            preset: None,
+            first_seen: None,
+            ambiguous: false,
+            disabled: false,
+            ignored: false,
+        };
+        let state = AppState::default();
+        state.update_devices(&[]);
+        let mut settings = Settings::default();
+        settings
+            .set_button_action(
+                "046d:c08b:NEW",
+                "button4",
+                ActionValue::Action(Action::Back),
+            )
+            .unwrap();
+        settings.selected_device = Some("046d:c08b:NEW".to_string());
+        state.update_settings(settings.clone());
+        // Plugged in after the last poll, so the cache doesn't have it yet.
+        assert_eq!(state.resolved_mappings().device_id, None);
+
+        assert!(state.relist_for_selection(&settings, || Some(vec![plugged.clone()])));
+        let resolved = state.resolved_mappings();
+        assert_eq!(resolved.device_id.as_deref(), Some("046d:c08b:NEW"));
+        assert_eq!(
+            resolved.mapping(3, 0).map(ResolvedAction::action),
+            Some(Action::Back)
+        );
+        assert!(state.engine.mappings_need_tap.load(Ordering::Relaxed));
+        assert!(state.should_remap(0));
+        // A device the cache has isn't listed again.
+        assert!(!state.relist_for_selection(&settings, || panic!("listed again")));
+    }
+
+    #[test]
+    fn tray_reset_needs_a_second_pick_before_it_expires() {
+        let state = AppState::default();
+        assert!(!state.tray_reset_armed());
+        assert!(!state.confirm_tray_reset());
+        assert!(state.tray_reset_armed());
+        assert!(state.confirm_tray_reset());
+        assert!(!state.tray_reset_armed());
+
+        let expired = Instant::now().checked_sub(TRAY_RESET_CONFIRM);
+        *state.tray_reset_armed.lock().unwrap() = expired;
+        assert!(!state.tray_reset_armed());
+        assert!(!state.confirm_tray_reset());
+        assert!(state.tray_reset_armed());
+    }
+
+    #[test]
+    fn linearmouse_schemes_become_device_configs() {
+        let config: serde_json::Value = serde_json::from_str(
+            r#"{
+                "$schema": "https://schema.linearmouse.app/0.10.0",
+                "schemes": [
+                    {
+                        "if": {"device": {"vendorID": "0x046d", "productID": "0xc52b", "productName": "MX Master 3"}},
+                        "scrolling": {"reverse": {"vertical": true}},
+                        "buttons": {
+                            "universalBackForward": true,
+                            "mappings": [
+                                {"button": 2, "action": "none"},
+                                {"button": 3, "command": true, "action": "mouse.button.middle"},
+                                {"button": 4, "action": "missionControl"},
+                                {"scroll": "up", "action": "mouse.button.back"}
+                            ]
+                        }
+                    },
+                    {
+                        "if": [{"device": {"vendorID": 5426, "productID": 132}, "app": "com.apple.Safari"}],
+                        "buttons": {"mappings": [{"button": 3, "action": "mouse.button.forward"}]}
+                    },
+                    {"if": {"display": "LG UltraFine"}, "buttons": {"universalBackForward": "backOnly"}},
+                    {"buttons": {"universalBackForward": "forwardOnly"}}
+                ]
+            }"#,
+        )
+        .unwrap();
+        let devices = vec![MouseDevice {
+            id: "046d:c52b:serial-1".to_string(),
+            name: "MX Master 3".to_string(),
+            kind: DeviceKind::Mouse,
+            button_count: 5,
+            preset: None,
+            first_seen: None,
+            ambiguous: false,
+            disabled: false,
+            ignored: false,
+        }];
+        let mut report = linearmouse::ImportReport::default();
+        let configs = linearmouse::convert(&config, &devices, &mut report);
+
+        let action = ActionValue::Action;
+        let connected = &configs["046d:c52b:serial-1"];
+        assert_eq!(connected.name, "MX Master 3");
+        assert_eq!(connected.buttons["middle"], action(Action::Disabled));
+        assert_eq!(connected.buttons["button4"], action(Action::Back));
+        assert_eq!(connected.buttons["button5"], action(Action::Forward));
+        assert_eq!(
+            connected.modifier_mappings["button4"],
+            vec![ModifierMapping {
+                modifiers: vec![Modifier::Command],
+                action: action(Action::MiddleClick),
+            }]
+        );
+        // Not connected: kept under a key reconcile_device_ids re-keys later.
+        let dormant = &configs["1532:0084:noserial"];
+        assert_eq!(
+            dormant.app_overrides["com.apple.Safari"]["button4"],
+            action(Action::Forward)
+        );
+        assert_eq!(
+            configs[ANY_DEVICE_ID].buttons["button5"],
+            action(Action::Forward)
+        );
+        assert_eq!(configs.len(), 3);
+
+        let skipped = report.skipped.join("\n");
+        for reason in [
+            "scrolling",
+            "action of button5",
+            "scroll mappings",
+            "\"display\"",
+        ] {
+            assert!(skipped.contains(reason), "{} not in {}", reason, skipped);
+        }
+    }
+
+    #[test]
+    fn profile_cycling_wraps_and_debounces() {
+        let mut settings = Settings::default();
+        settings
+            .create_profile("Gaming", Profile::default())
+            .unwrap();
+        settings.create_profile("Work", Profile::default()).unwrap();
+        assert_eq!(settings.adjacent_profile(true), "Gaming");
+        assert_eq!(settings.adjacent_profile(false), "Work");
+        settings.set_active_profile("Work").unwrap();
+        assert_eq!(settings.adjacent_profile(true), DEFAULT_PROFILE);
+        assert_eq!(Action::from_name("Next Profile"), Some(Action::NextProfile));
+        assert_eq!(Action::PreviousProfile.name(), "Previous Profile");
+
+        let state = AppState::default();
+        assert!(state.should_cycle_profile());
+        assert!(!state.should_cycle_profile());
+    }
+
+    #[test]
+    fn app_rules_switch_and_restore_profiles() {
+        let mut settings = Settings::default();
+        settings
+            .create_profile("Editing", Profile::default())
+            .unwrap();
+        settings.profile_app_rules.push(ProfileAppRule {
+            bundle_id: "com.adobe.Photoshop".to_string(),
+            profile: "Editing".to_string(),
+        });
+        assert!(settings.validate().is_ok());
+
+        let mut auto = AutoProfile::default();
+        assert_eq!(auto.on_activate(&settings, "com.apple.Safari"), None);
+        assert_eq!(
+            auto.on_activate(&settings, "com.adobe.Photoshop")
+                .as_deref(),
+            Some("Editing")
+        );
+        settings.set_active_profile("Editing").unwrap();
+        assert_eq!(
+            auto.on_activate(&settings, "com.apple.Safari").as_deref(),
+            Some(DEFAULT_PROFILE)
+        );
+        settings.set_active_profile(DEFAULT_PROFILE).unwrap();
+
+        // A manual switch while the rule is in effect is not undone.
+        auto.on_activate(&settings, "com.adobe.Photoshop");
+        settings.set_active_profile("Editing").unwrap();
+        auto.on_manual_switch();
+        assert_eq!(auto.on_activate(&settings, "com.apple.Safari"), None);
+
+        settings.profile_app_rules.push(ProfileAppRule {
+            bundle_id: "com.valvesoftware.steam".to_string(),
+            profile: "Gaming".to_string(),
+        });
+        assert!(settings.validate().is_err());
+        settings.delete_profile("Editing").unwrap();
+        assert_eq!(settings.profile_app_rules.len(), 1);
+    }
+
+    #[test]
+    fn gestures_resolve_by_dominant_direction() {
+        let json = r#"{"buttons":{
+            "button4":"Back",
+            "button5":{"gesture":{"tap":"Middle Click","up":"Forward","left":"Back","threshold":40}}
+        }}"#;
+        let config: DeviceConfig = serde_json::from_str(json).unwrap();
+        assert_eq!(action_of(&config, 3, 5), Action::Back);
+        let Some(ActionValue::Gesture { gesture }) = config.mapping(4, 5, None, 0) else {
+            panic!("button5 should be a gesture");
+        };
+        assert_eq!(action_of(&config, 4, 5), Action::Default);
+        let gesture = ResolvedGesture::from(gesture.as_ref());
+        assert!(gesture.suppress_cursor);
+
+        assert_eq!(gesture.resolve(10.0, -20.0), Action::MiddleClick);
+        assert_eq!(gesture.resolve(5.0, -60.0), Action::Forward);
+        assert_eq!(gesture.resolve(-45.0, 10.0), Action::Back);
+        assert_eq!(gesture.resolve(0.0, 80.0), Action::Default);
+
+        let state = AppState::default();
+        state.begin_gesture(4, gesture);
+        assert!(state.track_gesture(-30.0, 0.0));
+        assert!(state.track_gesture(-30.0, 5.0));
+        assert_eq!(state.finish_gesture(3), None);
+        assert_eq!(state.finish_gesture(4), Some(Action::Back));
+        assert!(!state.track_gesture(1.0, 1.0));
+
+        let round_trip: DeviceConfig =
+            serde_json::from_str(&serde_json::to_string(&config).unwrap()).unwrap();
+        assert_eq!(round_trip.buttons, config.buttons);
+    }
+
+    #[test]
+    fn app_overrides_take_precedence() {
+        let json = r#"{
+            "buttons":{"button4":"Back","button5":"Forward"},
+            "app_overrides":{"com.figma.Desktop":{"button4":"Disabled"}}
+        }"#;
+        let config: DeviceConfig = serde_json::from_str(json).unwrap();
+        let figma = Some("com.figma.Desktop");
+        let back = ActionValue::Action(Action::Back);
+        assert_eq!(config.mapping(3, 5, None, 0), Some(&back));
+        assert_eq!(
+            config.mapping(3, 5, Some("com.apple.Safari"), 0),
+            Some(&back)
+        );
+        assert_eq!(
+            config.mapping(3, 5, figma, 0),
+            Some(&ActionValue::Action(Action::Disabled))
+        );
+        assert_eq!(
+            config.mapping(4, 5, figma, 0),
+            Some(&ActionValue::Action(Action::Forward))
+        );
+
+        let state = AppState::default();
+        let back = ResolvedAction::from(&back);
+        state.hold_mapping(3, Some(back));
+        assert_eq!(state.release_mapping(3), Some(Some(back)));
+        assert_eq!(state.release_mapping(3), None);
+    }
+
+    #[test]
+    fn resolved_mappings_follow_state_without_allocating() {
+        let json = r#"{
+            "selected_device":"046d:c08b:A",
+            "devices":{"046d:c08b:A":{
+                "buttons":{"button4":"Back"},
+                "app_overrides":{"com.figma.Desktop":{"button4":"Disabled"}},
+                "modifier_mappings":{"button4":[{"modifiers":["shift"],"action":"Forward"}]}
+            }}
+        }"#;
+        let state = AppState::default();
+        state.update_settings(parse_settings(json).unwrap());
+        assert_eq!(state.resolved_mappings().mapping(3, 0), None);
+
+        state.update_devices(&[MouseDevice {
+            id: "046d:c08b:A".to_string(),
+            name: "G502".to_string(),
+            kind: DeviceKind::Mouse,
+            button_count: 5,
+            preset: None,
+            first_seen: None,
+            ambiguous: false,
+            disabled: false,
+            ignored: false,
+        }]);
+        let back = Some(ResolvedAction::Action(Action::Back));
+        assert_eq!(state.resolved_mappings().mapping(3, 0), back);
+        let shift = modifier_mask(&[Modifier::Shift]);
+        let forward = Some(ResolvedAction::Action(Action::Forward));
+        assert_eq!(state.resolved_mappings().mapping(3, shift), forward);
+        assert_eq!(state.resolved_mappings().mapping(42, 0), None);
+
+        let allocations = allocations_during(|| {
+            let _timer = state.time_callback();
+            let mapping = state.resolved_mappings().mapping(3, 0);
+            state.hold_mapping(3, mapping);
+            let _ = state.release_mapping(3);
+            let _ = state.resolved_mappings().mapping(3, shift);
+        });
+        assert_eq!(allocations, 0);
+        let diagnostics = state.remap_diagnostics();
+        assert_eq!(diagnostics.callback_events, 1);
+        assert!(diagnostics.callback_max_micros >= diagnostics.callback_mean_micros);
+        let latency = state.latency_report();
+        assert_eq!((latency.pass_through.count, latency.remapped.count), (1, 0));
+        {
+            let mut timer = state.time_callback();
+            assert!(timer.remapped(true));
+        }
+        assert_eq!(state.latency_report().remapped.count, 1);
+        state.reset_latency_stats();
+        assert_eq!(state.latency_report().pass_through.count, 0);
+        assert_eq!(state.remap_diagnostics().callback_events, 2);
+
+        state.set_frontmost_app(
+            Some(frontmost::AppInfo {
+                bundle_id: "com.figma.Desktop".to_string(),
+                name: "Figma".to_string(),
+                ..Default::default()
+            }),
+            false,
+        );
+        let disabled = Some(ResolvedAction::Action(Action::Disabled));
+        assert_eq!(state.resolved_mappings().mapping(3, 0), disabled);
+    }
+
+    #[test]
+    fn latency_histogram_reports_bucketed_percentiles() {
+        for nanos in [0, 15, 16, 31, 32, 1000, 123_456, 1 << 30] {
+            let bucket = latency_bucket(nanos);
+            assert!(latency_bucket_floor(bucket) <= nanos, "{}", nanos);
+            assert!(latency_bucket_floor(bucket + 1) > nanos, "{}", nanos);
+            assert_eq!(latency_bucket(latency_bucket_floor(bucket)), bucket);
+        }
+        assert_eq!(latency_bucket(u64::MAX), LATENCY_BUCKETS - 1);
+        let floor = latency_bucket_floor(latency_bucket(1_000_000));
+        assert!(1_000_000 - floor < 1_000_000 / 15);
+
+        let histogram = LatencyHistogram::default();
+        let empty = histogram.stats();
+        assert_eq!(
+            (empty.count, empty.p50_micros, empty.max_micros),
+            (0, 0.0, 0.0)
+        );
+        for _ in 0..98 {
+            histogram.record(10_000);
+        }
+        histogram.record(500_000);
+        histogram.record(2_000_000);
+        let stats = histogram.stats();
+        assert_eq!(stats.count, 100);
+        assert!((10.0..10.7).contains(&stats.p50_micros), "{:?}", stats);
+        assert!((500.0..532.0).contains(&stats.p99_micros), "{:?}", stats);
+        assert_eq!(stats.max_micros, 2000.0);
+
+        histogram.record(3);
+        histogram.reset();
+        histogram.record(3);
+        let stats = histogram.stats();
+        assert_eq!((stats.count, stats.p99_micros), (1, 0.003));
+    }
+
+    #[test]
+    fn engine_restart_cuts_the_retry_wait_short() {
+        let state = AppState::default();
+        assert_eq!(state.engine_state(), EngineState::Starting);
+        assert!(!state.wait_for_engine_restart(Duration::from_millis(5)));
+
+        let waiter = state.clone();
+        let handle =
+            std::thread::spawn(move || waiter.wait_for_engine_restart(Duration::from_secs(30)));
+        state.request_engine_restart();
+        assert!(handle.join().unwrap());
+        // The request is consumed by the wait that saw it.
+        assert!(!state.wait_for_engine_restart(Duration::from_millis(5)));
+
+        state.request_engine_restart();
+        assert!(state.take_engine_restart());
+        assert!(!state.take_engine_restart());
+    }
+
+    #[test]
+    fn engine_status_tracks_uptime_and_tap_timeouts() {
+        let state = AppState::default();
+        assert_eq!(state.engine_status().uptime_secs, None);
+        state.set_engine_state(EngineState::Running);
+        let status = state.engine_status();
+        assert_eq!(status.uptime_secs, Some(0));
+        assert!(status.tap_enabled);
+
+        state.set_tap_enabled(false, true);
+        state.record_remap_error("tap-timeout", "slow".to_string());
+        let status = state.engine_status();
+        assert!(!status.tap_enabled);
+        assert_eq!(status.tap_timeouts, 1);
+        assert_eq!(
+            status.last_error.map(|error| error.code),
+            Some("tap-timeout")
+        );
+
+        state.set_engine_state(EngineState::Failed);
+        assert_eq!(state.engine_status().uptime_secs, None);
+    }
+
+    #[test]
+    fn monitor_mode_recreates_the_tap_when_it_changes() {
+        let state = AppState::default();
+        assert!(!state.engine_status().monitor_only);
+        assert!(state.set_monitor_only(true));
+        assert!(state.take_engine_restart());
+        assert!(state.engine_status().monitor_only);
+        // Turning it on again leaves the running tap alone.
+        assert!(!state.set_monitor_only(true));
+        assert!(!state.take_engine_restart());
+        assert!(state.set_monitor_only(false));
+        assert!(state.take_engine_restart());
+        assert!(!state.engine_status().monitor_only);
+    }
+
+    #[test]
+    fn idle_suspension_ends_with_the_input_that_follows() {
+        let state = AppState::default();
+        state.last_input.store(1_000, Ordering::Relaxed);
+        assert_eq!(state.suspend_if_idle(1_299, 5), Some(1));
+        assert!(!state.is_passing_through());
+        assert_eq!(state.suspend_if_idle(1_300, 5), Some(0));
+        assert_eq!(state.suspend_if_idle(1_400, 5), None);
+        assert!(state.is_passing_through());
+        assert!(state.engine_status().idle_suspended);
+
+        // The event that wakes remapping is remapped itself.
+        assert!(state.should_remap(0));
+        assert!(!state.engine_status().idle_suspended);
+        let now = state.last_input.load(Ordering::Relaxed);
+        assert_eq!(state.suspend_if_idle(now, 5), Some(300));
+        // Events remapping posted itself aren't input.
+        state.last_input.store(1_000, Ordering::Relaxed);
+        assert!(!state.should_remap(SYNTHETIC_EVENT_TAG));
+        assert_eq!(state.suspend_if_idle(1_300, 5), Some(0));
+    }
+
+    #[test]
+    fn input_overlay_sees_every_release_and_spaced_presses() {
+        let state = AppState::default();
+        assert!(!state.should_emit_overlay_event(true));
+        assert!(!state.is_event_tap_wanted());
+
+        let wake = state.open_input_overlay_stream().unwrap();
+        assert!(state.open_input_overlay_stream().is_none());
+        assert!(state.is_event_tap_wanted());
+        assert!(state.should_emit_overlay_event(true));
+        assert!(!state.should_emit_overlay_event(true));
+        // A release right after its press still reaches the overlay.
+        assert!(state.should_emit_overlay_event(false));
+        assert_eq!(wake.try_iter().count(), 2);
+        // The event monitor keeps its own spacing.
+        state.set_event_monitoring(true);
+        assert!(state.should_emit_button_event());
+
+        state.close_input_overlay_stream();
+        assert!(!state.should_emit_overlay_event(false));
+        assert_eq!(
+            wake.try_recv(),
+            Err(std::sync::mpsc::TryRecvError::Disconnected)
+        );
+    }
+
+    #[test]
+    fn engine_status_names_a_selected_device_that_isnt_connected() {
+        let state = AppState::default();
+        assert_eq!(state.engine_status().missing_device, None);
+        let mut settings = Settings {
+            selected_device: Some("046d:b023:A".to_string()),
+            ..Settings::default()
+        };
+        settings.apply_preset("046d:b023:A", presets::find("mx-master-3").unwrap(), 5);
+        state.update_settings(settings.clone());
+        assert_eq!(
+            state.engine_status().missing_device,
+            Some(MissingDevice {
+                id: "046d:b023:A".to_string(),
+                name: "Logitech MX Master 3".to_string(),
+                fallback: None,
+            })
+        );
+        state.update_devices(&[MouseDevice {
+            id: "046d:b023:A".to_string(),
+            name: "MX Master 3".to_string(),
+            kind: DeviceKind::Mouse,
+            button_count: 5,
+            preset: None,
+            first_seen: None,
+            ambiguous: false,
+            disabled: false,
+            ignored: false,
+        }]);
+        assert_eq!(state.missing_device(), None);
+        settings.selected_device = Some(ANY_DEVICE_ID.to_string());
+        state.update_settings(settings);
+        state.update_devices(&[]);
+        assert_eq!(state.missing_device(), None);
+    }
+
+    #[test]
+    fn remap_enabled_defaults_on_and_follows_settings() {
+        let settings = parse_settings("{}").unwrap();
+        assert!(settings.remap_enabled);
+
+        let state = AppState::default();
+        state.update_settings(settings.clone());
+        assert!(state.is_remap_enabled());
+        state.update_settings(Settings {
+            remap_enabled: false,
+            ..settings
+        });
+        assert!(!state.is_remap_enabled());
+    }
+
+    #[test]
+    fn remap_pause_expires_and_ignores_stale_timers() {
+        let state = AppState::default();
+        let generation = state.pause_remapping(Some(Duration::from_secs(3600)));
+        assert!(state.is_remap_paused());
+        assert!(state.is_passing_through());
+        assert_eq!(state.end_pause(generation), Some(false));
+        assert!(state.remap_pause_status().until.is_some());
+
+        let expired = state.pause_remapping(Some(Duration::ZERO));
+        assert!(!state.is_remap_paused());
+        assert_eq!(state.end_pause(generation), None);
+        assert_eq!(state.end_pause(expired), Some(true));
+        assert_eq!(state.end_pause(expired), None);
+
+        let indefinite = state.pause_remapping(None);
+        assert!(state.is_remap_paused());
+        assert_eq!(state.end_pause(indefinite), Some(false));
+        assert!(state.resume_remapping());
+        assert!(!state.is_remap_paused());
+        assert!(!state.resume_remapping());
+    }
+
+    #[test]
+    fn feedback_is_sent_per_button_unless_muted() {
+        let config: DeviceConfig = serde_json::from_str(
+            r#"{"buttons": {"button4": "Cycle DPI", "button5": "Forward"},
+                "feedback": {"button4": "haptic", "button5": "none"}}"#,
+        )
+        .unwrap();
+        let resolved = ResolvedMappings::build(&config, "mouse", 5, None);
+        assert_eq!(resolved.feedback(3), Feedback::Haptic);
+        assert_eq!(resolved.feedback(4), Feedback::None);
+        assert_eq!(resolved.feedback(0), Feedback::None);
+        // Past the button count, like every other per-button option.
+        assert_eq!(resolved.feedback(5), Feedback::None);
+        assert_eq!(resolved.feedback(-1), Feedback::None);
+
+        let state = AppState::default();
+        let (sender, receiver) = std::sync::mpsc::channel();
+        state.feedback.set(sender).unwrap();
+        state.update_settings(Settings::default());
+        state.give_feedback(&resolved, 3);
+        state.give_feedback(&resolved, 4);
+        assert_eq!(receiver.try_iter().collect::<Vec<_>>(), [Feedback::Haptic]);
+
+        state.update_settings(Settings {
+            mute_feedback: true,
+            ..Settings::default()
+        });
+        state.give_feedback(&resolved, 3);
+        assert!(receiver.try_recv().is_err());
+
+        let settings = parse_settings(
+            r#"{"profiles": {"Default": {"devices": {"m": {"feedback": {"wheel": "sound"}}}}}}"#,
+        )
+        .unwrap();
+        assert!(settings.validate().is_err());
+        assert!(parse_settings(
+            r#"{"profiles": {"Default": {"devices": {"m": {"feedback": {"button4": "loud"}}}}}}"#
+        )
+        .is_err());
+    }
+
+    /// Keeps what a `RemapOutput` was asked to post.
+    #[derive(Default)]
+    struct RecordedOutput {
+        clicks: Mutex<Vec<(i64, bool, i64, ModifierMask)>>,
+    }
+
+    impl RemapOutput for RecordedOutput {
+        fn post_navigation(&self, _forward: bool, _merged: ModifierMask) -> Result<(), String> {
+            Ok(())
+        }
+
+        fn post_tab_switch(
+            &self,
+            _next: bool,
+            _keys: TabKeys,
+            _merged: ModifierMask,
+        ) -> Result<(), String> {
+            Ok(())
+        }
+
+        fn post_mouse_event(
+            &self,
+            button: i64,
+            down: bool,
+            click_state: i64,
+            modifiers: ModifierMask,
+        ) -> Result<(), String> {
+            self.clicks
+                .lock()
+                .unwrap()
+                .push((button, down, click_state, modifiers));
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn clicks_carry_held_modifiers_unless_stripped() {
+        let shift = modifier_mask(&[Modifier::Shift]);
+        let command = modifier_mask(&[Modifier::Command]);
+        let mut config = DeviceConfig {
+            strip_modifiers: vec!["button5".to_string()],
+            ..DeviceConfig::default()
+        };
+        config.modifier_mappings.insert(
+            "middle".to_string(),
+            vec![ModifierMapping {
+                modifiers: vec![Modifier::Shift],
+                action: ActionValue::Action(Action::DoubleClick),
+            }],
+        );
+        let resolved = ResolvedMappings::build(&config, "mouse", 5, None);
+
+        assert_eq!(resolved.click_modifiers(3, command), command);
+        assert_eq!(resolved.click_modifiers(4, command), 0);
+        // Modifiers that picked a modifier mapping aren't clicked with.
+        assert_eq!(resolved.click_modifiers(2, shift), 0);
+        assert_eq!(resolved.click_modifiers(2, command), command);
+        assert_eq!(
+            ActionModifiers::of(&resolved, 3, shift),
+            ActionModifiers {
+                merged: 0,
+                click: shift,
+            }
+        );
+
+        let output = RecordedOutput::default();
+        output
+            .post_mouse_click(ClickSequence::double(0, shift, Duration::ZERO))
+            .unwrap();
+        assert_eq!(
+            *output.clicks.lock().unwrap(),
+            vec![
+                (0, true, 1, shift),
+                (0, false, 1, shift),
+                (0, true, 2, shift),
+                (0, false, 2, shift),
+            ]
+        );
+
+        let settings = parse_settings(
+            r#"{"profiles": {"Default": {"devices": {"m": {"strip_modifiers": ["wheel"]}}}}}"#,
+        )
+        .unwrap();
+        assert!(settings.validate().is_err());
+    }
+
+    #[test]
+    fn synthetic_events_bypass_remapping() {
+        let state = AppState::default();
+        assert!(state.should_remap(0));
+        assert!(!state.should_remap(SYNTHETIC_EVENT_TAG));
+
+        state.pause_remapping(None);
+        assert!(!state.should_remap(0));
+    }
+
+    /// Records clicks like `RecordedOutput`, from whichever thread posts them.
+    #[derive(Clone, Default)]
+    struct SharedOutput(Arc<Mutex<Vec<(i64, bool)>>>);
+
+    impl RemapOutput for SharedOutput {
+        fn detached(&self) -> Option<Box<dyn RemapOutput + Send>> {
+            Some(Box::new(self.clone()))
+        }
+
+        fn post_navigation(&self, _forward: bool, _merged: ModifierMask) -> Result<(), String> {
+            Ok(())
+        }
+
+        fn post_tab_switch(
+            &self,
+            _next: bool,
+            _keys: TabKeys,
+            _merged: ModifierMask,
+        ) -> Result<(), String> {
+            Ok(())
+        }
+
+        fn post_mouse_event(
+            &self,
+            _button: i64,
+            down: bool,
+            click_state: i64,
+            _modifiers: ModifierMask,
+        ) -> Result<(), String> {
+            self.0.lock().unwrap().push((click_state, down));
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn double_clicks_pause_off_the_event_path() {
+        let config: DeviceConfig = serde_json::from_str(
+            r#"{"buttons": {"button4": "Double Click"},
+                "double_click_interval_ms": {"button4": 80, "button5": 9000}}"#,
+        )
+        .unwrap();
+        let resolved = ResolvedMappings::build(&config, "mouse", 5, None);
+        let state = AppState::default();
+        state.update_settings(Settings::default());
+        let interval = |button| state.double_click_interval(&resolved, button);
+        assert_eq!(interval(Some(3)), Duration::from_millis(80));
+        assert_eq!(
+            interval(Some(4)),
+            Duration::from_millis(MAX_DOUBLE_CLICK_INTERVAL_MS.into())
+        );
+        assert_eq!(
+            interval(None),
+            Duration::from_millis(DEFAULT_DOUBLE_CLICK_INTERVAL_MS.into())
+        );
+        state.update_settings(Settings {
+            double_click_interval_ms: 0,
+            ..Settings::default()
+        });
+        assert_eq!(interval(Some(2)), Duration::ZERO);
+
+        // The first click goes out at once, the second once the pause is
+        // over, on another thread.
+        let output = SharedOutput::default();
+        let started = Instant::now();
+        output
+            .post_mouse_click(ClickSequence::double(0, 0, Duration::from_millis(80)))
+            .unwrap();
+        assert!(started.elapsed() < Duration::from_millis(80));
+        assert_eq!(*output.0.lock().unwrap(), [(1, true), (1, false)]);
+        while output.0.lock().unwrap().len() < 4 {
+            assert!(started.elapsed() < Duration::from_secs(5));
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        assert!(started.elapsed() >= Duration::from_millis(80));
+        assert_eq!(output.0.lock().unwrap()[2..], [(2, true), (2, false)]);
+
+        // Too long is capped with a warning; not a button is an error.
+        let issues = Settings {
+            double_click_interval_ms: 501,
+            ..Settings::default()
+        }
+        .issues();
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].severity, IssueSeverity::Warning);
+        let issues = config.issues();
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].button.as_deref(), Some("button5"));
+        let settings = parse_settings(
+            r#"{"profiles": {"Default": {"devices": {"m": {"double_click_interval_ms": {"wheel": 40}}}}}}"#,
+        )
+        .unwrap();
+        assert!(settings.validate().is_err());
+    }
+
+    #[test]
+    fn settings_are_written_once_changes_settle() {
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let written = Arc::new(Mutex::new(Vec::new()));
+        let writer = {
+            let written = Arc::clone(&written);
+            std::thread::spawn(move || {
+                write_settled_settings(&receiver, Duration::from_millis(200), |settings| {
+                    written.lock().unwrap().push(settings.locale.clone());
+                    Ok(())
+                })
+            })
+        };
+        let state = AppState::default();
+        assert!(state.settings_writer.set(sender).is_ok());
+        let change = |locale: &str| Settings {
+            locale: locale.to_string(),
+            ..Settings::default()
+        };
+
+        // A run of changes is written once, as the last of them.
+        for locale in ["de", "fr", "es"] {
+            assert!(state.queue_settings_write(&change(locale)));
+        }
+        std::thread::sleep(Duration::from_millis(50));
+        assert!(written.lock().unwrap().is_empty());
+        let started = Instant::now();
+        while written.lock().unwrap().is_empty() {
+            assert!(started.elapsed() < Duration::from_secs(5));
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        assert_eq!(*written.lock().unwrap(), ["es"]);
+
+        // Quitting from the tray flushes what's waiting before it exits,
+        // and a flush with nothing waiting writes nothing.
+        assert!(state.queue_settings_write(&change("ja")));
+        assert!(state.flush_settings().is_ok());
+        assert_eq!(*written.lock().unwrap(), ["es", "ja"]);
+        assert!(state.flush_settings().is_ok());
+        assert_eq!(written.lock().unwrap().len(), 2);
+
+        // Whatever is still waiting when the app goes away is written.
+        assert!(state.queue_settings_write(&change("en")));
+        drop(state);
+        writer.join().unwrap();
+        assert_eq!(*written.lock().unwrap(), ["es", "ja", "en"]);
+    }
+
+    #[test]
+    fn a_flush_reports_the_latest_write_failing() {
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let writer = std::thread::spawn(move || {
+            write_settled_settings(&receiver, Duration::from_secs(60), |settings| {
+                if settings.locale == "de" {
+                    Err("disk full".to_string())
+                } else {
+                    Ok(())
+                }
+            })
+        });
+        let state = AppState::default();
+        assert!(state.settings_writer.set(sender).is_ok());
+        let change = |locale: &str| Settings {
+            locale: locale.to_string(),
+            ..Settings::default()
+        };
+
+        assert!(state.queue_settings_write(&change("de")));
+        assert_eq!(state.flush_settings(), Err("disk full".to_string()));
+        assert!(state.queue_settings_write(&change("fr")));
+        assert_eq!(state.flush_settings(), Ok(()));
+        drop(state);
+        writer.join().unwrap();
+    }
+
+    #[test]
+    fn synthetic_output_is_held_to_its_limit() {
+        let governor = OutputGovernor::default();
+        governor.set_limit(10);
+        let at = |millis| governor.epoch + Duration::from_millis(millis);
+        // A second's worth goes out at once; the rest is dropped, and the
+        // flood is warned about once.
+        assert!((0..10).all(|_| governor.admit(at(0))));
+        assert!(!governor.admit(at(0)));
+        assert!(!governor.admit(at(50)));
+        assert!(governor.take_warning());
+        assert!(!governor.take_warning());
+        // It refills at the limit, however often it's asked.
+        assert!((51..100).all(|millis| !governor.admit(at(millis))));
+        assert!(governor.admit(at(100)));
+        assert!(!governor.admit(at(120)));
+        assert!(governor.take_warning());
+        assert!(governor.admit(at(5000)));
+        assert_eq!(governor.posted.load(Ordering::Relaxed), 12);
+        assert_eq!(governor.dropped.load(Ordering::Relaxed), 52);
+
+        // Presses over the limit fail, releases still go out.
+        let state = AppState::default();
+        state.update_settings(Settings {
+            synthetic_events_per_sec: 10,
+            ..Settings::default()
+        });
+        let recorded = RecordedOutput::default();
+        let output = Governed::new(&state, &recorded);
+        for _ in 0..5 {
+            output
+                .post_mouse_click(ClickSequence::double(0, 0, Duration::ZERO))
+                .unwrap();
+        }
+        assert!(output.post_navigation(true, 0).is_err());
+        assert!(output.post_mouse_event(0, false, 1, 0).is_ok());
+        assert_eq!(recorded.clicks.lock().unwrap().len(), 21);
+        let diagnostics = state.remap_diagnostics();
+        assert_eq!(
+            (diagnostics.synthetic_posted, diagnostics.synthetic_dropped),
+            (10, 1)
+        );
+
+        let issues = Settings {
+            synthetic_events_per_sec: 1,
+            ..Settings::default()
+        }
+        .issues();
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].field, "synthetic_events_per_sec");
+    }
+
+    #[test]
+    fn bouncing_presses_are_swallowed_with_their_releases() {
+        let config: DeviceConfig = serde_json::from_str(
+            r#"{"buttons": {"button4": "Back"},
+                "debounce_ms": {"left": 30, "button4": 500}}"#,
+        )
+        .unwrap();
+        let resolved = ResolvedMappings::build(&config, "mouse", 5, None);
+        assert_eq!(resolved.debounce(0), Duration::from_millis(30));
+        assert_eq!(
+            resolved.debounce(3),
+            Duration::from_millis(MAX_DEBOUNCE_MS.into())
+        );
+        assert_eq!(resolved.debounce(1), Duration::ZERO);
+
+        let state = AppState::default();
+        let start = Instant::now();
+        let at = |millis| start + Duration::from_millis(millis);
+        let bounce = |button, down, millis| state.is_bounce(&resolved, button, down, at(millis));
+        // A press, a bounce 5ms later with its release, then a deliberate
+        // second click once the window is over.
+        assert!(!bounce(0, true, 0));
+        assert!(!bounce(0, false, 2));
+        assert!(bounce(0, true, 5));
+        assert!(bounce(0, false, 8));
+        assert!(!bounce(0, true, 40));
+        assert!(!bounce(0, false, 45));
+        // Buttons without a window are never held back.
+        assert!(!bounce(1, true, 0));
+        assert!(!bounce(1, true, 1));
+        assert!(!bounce(7, true, 1));
+
+        let issues = config.issues();
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].field, "debounce_ms");
+        assert_eq!(issues[0].severity, IssueSeverity::Warning);
+    }
+
+    #[test]
+    fn middle_drag_ends_only_for_its_button() {
+        let state = AppState::default();
+        assert!(state.begin_middle_drag(3));
+        assert!(!state.begin_middle_drag(4));
+        assert_eq!(state.middle_drag_button(), Some(3));
+        assert!(!state.end_middle_drag(Some(4)));
+        assert!(state.end_middle_drag(Some(3)));
+        assert!(!state.end_middle_drag(None));
+
+        assert!(state.begin_middle_drag(4));
+        assert!(state.end_middle_drag(None));
+        assert_eq!(state.middle_drag_button(), None);
+    }
+
+    #[test]
+    fn event_tap_is_wanted_only_when_something_listens() {
+        let mut config = DeviceConfig::default();
+        assert!(!config.has_remapping());
+        config.app_overrides.insert(
+            "com.apple.Safari".to_string(),
+            HashMap::from([("button4".to_string(), ActionValue::Action(Action::Disabled))]),
+        );
+        assert!(config.has_remapping());
+
+        let state = AppState::default();
+        assert!(!state.is_event_tap_wanted());
+        state.set_event_monitoring(true);
+        assert!(state.is_event_tap_wanted());
+        state.set_event_monitoring(false);
+        state.arm_capture("mouse");
+        assert!(state.is_event_tap_wanted());
+        state.cancel_capture();
+        assert!(!state.is_event_tap_wanted());
+    }
+
+    #[test]
+    fn remap_errors_fold_repeats_and_keep_recent() {
+        let state = AppState::default();
+        state.record_remap_error("tap-create-failed", "no tap".to_string());
+        let folded = state
+            .record_remap_error("tap-create-failed", "no tap".to_string())
+            .unwrap();
+        assert_eq!(folded.count, 2);
+        assert_eq!(state.remap_errors().len(), 1);
+
+        for index in 0..REMAP_ERROR_HISTORY {
+            state.record_remap_error("post-failed", format!("click {}", index));
+        }
+        let errors = state.remap_errors();
+        assert_eq!(errors.len(), REMAP_ERROR_HISTORY);
+        assert_eq!(errors[0].message, "click 0");
+        assert_eq!(
+            errors.last().unwrap().message,
+            format!("click {}", REMAP_ERROR_HISTORY - 1)
+        );
+    }
+
+    #[test]
+    fn excluded_apps_follow_settings_and_frontmost() {
+        let state = AppState::default();
+        let app = |bundle_id: &str| frontmost::AppInfo {
+            bundle_id: bundle_id.to_string(),
+            name: bundle_id.to_string(),
+            ..Default::default()
+        };
+        state.set_frontmost_app(Some(app("com.valvesoftware.steam")), false);
+        assert!(!state.is_remapping_suspended());
+
+        let settings = Settings {
+            excluded_apps: vec!["com.valvesoftware.steam".to_string()],
+            ..Settings::default()
+        };
+        state.update_settings(settings);
+        assert!(state.is_remapping_suspended());
+
+        state.set_frontmost_app(Some(app("com.apple.Safari")), false);
+        assert!(!state.is_remapping_suspended());
+        state.set_frontmost_app(None, false);
+        assert!(!state.is_remapping_suspended());
+    }
+
+    #[test]
+    fn own_window_focus_guards_presses_unless_turned_off() {
+        let state = AppState::default();
+        state.update_settings(Settings::default());
+        assert!(!state.is_own_window_guarded());
+        state.set_own_window_focused(true);
+        assert!(state.is_own_window_guarded());
+        // Only remapping is bypassed; events still reach capture.
+        assert!(!state.is_passing_through());
+
+        state.update_settings(Settings {
+            pause_in_own_window: false,
+            ..Settings::default()
+        });
+        assert!(!state.is_own_window_guarded());
+        state.update_settings(Settings::default());
+        state.set_own_window_focused(false);
+        assert!(!state.is_own_window_guarded());
+    }
+
+    #[test]
+    fn tab_switches_and_listed_keys_repeat_while_held() {
+        let mut settings = Settings::default();
+        assert_eq!(settings.tab_keys_for(None), TabKeys::ControlTab);
+        assert_eq!(
+            settings.tab_keys_for(Some("com.apple.Safari")),
+            TabKeys::CommandBrackets
+        );
+        assert_eq!(
+            settings.tab_keys_for(Some("com.apple.TextEdit")),
+            TabKeys::ControlTab
+        );
+        settings
+            .tab_keys
+            .insert("com.apple.Safari".to_string(), TabKeys::ControlTab);
+        assert_eq!(
+            settings.tab_keys_for(Some("com.apple.Safari")),
+            TabKeys::ControlTab
+        );
+        assert_eq!(Action::from_id("next_tab"), Some(Action::NextTab));
+        assert!(Action::PreviousTab.posts_keys());
+
+        // A Shortcut such as F13 repeats only on a listed button.
+        let config = DeviceConfig {
+            repeat_while_held: vec!["button4".to_string()],
+            ..DeviceConfig::default()
+        };
+        let mappings = ResolvedMappings::build(&config, "m", 5, None);
+        let f13 = Action::Shortcut {
+            keycode: Some(0x69),
+            key: None,
+            modifiers: 0,
+        };
+        assert_eq!(shortcut_label(Some(0x69), None, 0), "F13");
+        assert!(mappings.repeats_while_held(3, f13));
+        assert!(!mappings.repeats_while_held(4, f13));
+        assert!(mappings.repeats_while_held(4, Action::NextTab));
+        assert!(!mappings.repeats_while_held(3, Action::MiddleClick));
+
+        // Only the latest hold repeats, until its own button comes up.
+        let state = AppState::default();
+        let first = state.begin_key_repeat(3);
+        let second = state.begin_key_repeat(4);
+        assert!(!state.is_key_repeating(first));
+        assert!(state.is_key_repeating(second));
+        state.end_key_repeat(3);
+        assert!(state.is_key_repeating(second));
+        state.end_key_repeat(4);
+        assert!(!state.is_key_repeating(second));
+    }
+
+    #[test]
+    fn secure_input_skips_keys_and_is_logged_once_a_minute() {
+        assert!(Action::Back.posts_keys());
+        assert!(Action::Shortcut {
+            keycode: Some(0),
+            key: Some('a'),
+            modifiers: 0
+        }
+        .posts_keys());
+        assert!(Action::LookUp.posts_keys());
+        assert!(!Action::MiddleClick.posts_keys());
+        assert!(!Action::NextProfile.posts_keys());
+
+        let state = AppState::default();
+        let holder = SecureInputHolder {
+            pid: Some(412),
+            name: Some("Terminal".to_string()),
+        };
+        assert_eq!(holder.to_string(), "Terminal (pid 412)");
+        assert!(state.set_secure_input(Some(holder.clone())));
+        assert!(!state.set_secure_input(Some(holder.clone())));
+        assert_eq!(state.secure_input(), Some(holder));
+        assert!(state.set_secure_input(None));
+
+        let now = Instant::now();
+        assert!(state.should_log_secure_input(now));
+        assert!(!state.should_log_secure_input(now + Duration::from_secs(59)));
+        assert!(state.should_log_secure_input(now + SECURE_INPUT_LOG_INTERVAL));
+    }
+
+    #[test]
+    fn game_mode_pauses_only_fullscreen_apps() {
+        let state = AppState::default();
+        state.set_frontmost_fullscreen(true);
+        assert!(!state.is_remapping_suspended());
+
+        state.update_settings(Settings {
+            game_mode: true,
+            ..Settings::default()
+        });
+        assert!(state.is_game_mode_paused());
+        assert!(state.is_remapping_suspended());
+
+        state.set_frontmost_fullscreen(false);
+        assert!(!state.is_game_mode_paused());
+        assert!(!state.is_remapping_suspended());
+    }
+}
@@ -0,0 +1,1127 @@
+use super::*;
+
+pub(crate) fn start_mouse_remap(app: tauri::AppHandle, state: AppState) {
+    use std::os::raw::{c_int, c_uchar, c_uint, c_ulong};
+    use x11_dl::keysym::{
+        XK_Alt_L, XK_Control_L, XK_Left, XK_Right, XK_Shift_L, XK_Super_L, XK_Tab,
+    };
+    use x11_dl::xinput2::{
+        XIAllDevices, XIAllMasterDevices, XIAsyncDevice, XIDeviceEvent, XIEventMask,
+        XIGrabModeAsync, XIGrabModeSync, XIGrabModifiers, XIMaskIsSet, XIRawEvent, XIReplayDevice,
+        XISetMask, XISlavePointer, XI_ButtonPress as XI_BUTTON_PRESS,
+        XI_RawButtonRelease as XI_RAW_BUTTON_RELEASE, XI_RawMotion as XI_RAW_MOTION, XInput2,
+        XI_LASTEVENT,
+    };
+    use x11_dl::xlib::{
+        ControlMask, CurrentTime, Display, GenericEvent, Mod1Mask, Mod4Mask, ShiftMask, XEvent,
+        Xlib, XA_INTEGER,
+    };
+    use x11_dl::xtest::Xf86vmode as XTest;
+
+    /// X modifier bit and the key that is pressed to add each modifier.
+    const MODIFIER_MASKS: [(c_uint, c_uint, Modifier); 4] = [
+        (ShiftMask, XK_Shift_L, Modifier::Shift),
+        (ControlMask, XK_Control_L, Modifier::Control),
+        (Mod1Mask, XK_Alt_L, Modifier::Option),
+        (Mod4Mask, XK_Super_L, Modifier::Command),
+    ];
+    /// `XIAnyModifier`, which x11-dl doesn't define.
+    const XI_ANY_MODIFIER: c_int = 1 << 31;
+
+    /// One connection to the X server with the libraries it's used through.
+    struct XConnection {
+        xlib: Xlib,
+        xinput: XInput2,
+        xtest: XTest,
+        display: *mut Display,
+        root: c_ulong,
+        xinput_opcode: c_int,
+    }
+
+    impl XConnection {
+        /// Connects to `$DISPLAY` and checks for XInput 2 and XTest.
+        fn open() -> Result<Self, String> {
+            let xlib = Xlib::open().map_err(|err| format!("could not load Xlib: {}", err))?;
+            let xinput = XInput2::open().map_err(|err| format!("could not load libXi: {}", err))?;
+            let xtest = XTest::open().map_err(|err| format!("could not load libXtst: {}", err))?;
+            let display = unsafe { (xlib.XOpenDisplay)(std::ptr::null()) };
+            if display.is_null() {
+                return Err("could not connect to the X server".to_string());
+            }
+            let root = unsafe { (xlib.XDefaultRootWindow)(display) };
+            let mut connection = Self {
+                xlib,
+                xinput,
+                xtest,
+                display,
+                root,
+                xinput_opcode: 0,
+            };
+            let name = CString::new("XInputExtension").unwrap_or_default();
+            let (mut event, mut error) = (0, 0);
+            let has_xinput = unsafe {
+                (connection.xlib.XQueryExtension)(
+                    display,
+                    name.as_ptr(),
+                    &mut connection.xinput_opcode,
+                    &mut event,
+                    &mut error,
+                )
+            } != 0;
+            // 2.1 and later deliver raw events while another client grabs.
+            let (mut major, mut minor) = (2, 2);
+            if !has_xinput
+                || unsafe { (connection.xinput.XIQueryVersion)(display, &mut major, &mut minor) }
+                    != 0
+            {
+                return Err("the X server doesn't support XInput 2".to_string());
+            }
+            let (mut base, mut major, mut minor) = (0, 0, 0);
+            if unsafe {
+                (connection.xtest.XTestQueryExtension)(
+                    display, &mut base, &mut event, &mut major, &mut minor,
+                )
+            } == 0
+            {
+                return Err("the X server doesn't support XTest".to_string());
+            }
+            Ok(connection)
+        }
+
+        fn flush(&self) {
+            unsafe { (self.xlib.XFlush)(self.display) };
+        }
+
+        /// Modifiers held right now, which raw events don't carry.
+        fn held_modifiers(&self) -> ModifierMask {
+            let (mut root, mut child) = (0, 0);
+            let (mut root_x, mut root_y, mut x, mut y) = (0, 0, 0, 0);
+            let mut mask = 0;
+            unsafe {
+                (self.xlib.XQueryPointer)(
+                    self.display,
+                    self.root,
+                    &mut root,
+                    &mut child,
+                    &mut root_x,
+                    &mut root_y,
+                    &mut x,
+                    &mut y,
+                    &mut mask,
+                )
+            };
+            held_modifiers(mask)
+        }
+
+        fn keycode(&self, keysym: c_uint) -> Result<c_uint, String> {
+            match unsafe { (self.xlib.XKeysymToKeycode)(self.display, c_ulong::from(keysym)) } {
+                0 => Err(format!("no key produces keysym {:#x}", keysym)),
+                keycode => Ok(c_uint::from(keycode)),
+            }
+        }
+
+        /// Taps `keysym` with the `merged` modifiers the user isn't already
+        /// holding pressed around it.
+        fn post_key_combo(&self, keysym: c_uint, merged: ModifierMask) -> Result<(), String> {
+            let held = self.held_modifiers();
+            let mut added = Vec::new();
+            for (_, modifier_keysym, modifier) in MODIFIER_MASKS {
+                let mask = modifier_mask(&[modifier]);
+                if merged & mask != 0 && held & mask == 0 {
+                    added.push(self.keycode(modifier_keysym)?);
+                }
+            }
+            let keycode = self.keycode(keysym)?;
+            let presses = added
+                .iter()
+                .map(|keycode| (*keycode, true))
+                .chain([(keycode, true), (keycode, false)])
+                .chain(added.iter().rev().map(|keycode| (*keycode, false)));
+            for (keycode, down) in presses {
+                unsafe {
+                    (self.xtest.XTestFakeKeyEvent)(
+                        self.display,
+                        keycode,
+                        c_int::from(down),
+                        CurrentTime,
+                    )
+                };
+            }
+            self.flush();
+            Ok(())
+        }
+
+        /// The slave pointers of `device_id`, matched by the vendor and
+        /// product id the kernel driver reports, and the XTest pointers
+        /// whose events are our own.
+        fn input_sources(&self, device_id: Option<&str>) -> (Vec<c_int>, Vec<c_int>) {
+            let wanted = device_id.and_then(hid_vendor_product);
+            let property = CString::new("Device Product ID").unwrap_or_default();
+            let atom = unsafe { (self.xlib.XInternAtom)(self.display, property.as_ptr(), 1) };
+            let (mut targets, mut xtest) = (Vec::new(), Vec::new());
+            let mut count = 0;
+            let devices =
+                unsafe { (self.xinput.XIQueryDevice)(self.display, XIAllDevices, &mut count) };
+            if devices.is_null() {
+                return (targets, xtest);
+            }
+            let infos =
+                unsafe { std::slice::from_raw_parts(devices, usize::try_from(count).unwrap_or(0)) };
+            for info in infos.iter().filter(|info| info._use == XISlavePointer) {
+                let name = unsafe { std::ffi::CStr::from_ptr(info.name) }.to_string_lossy();
+                if name.contains("XTEST") {
+                    xtest.push(info.deviceid);
+                } else if wanted.is_some() && self.vendor_product(info.deviceid, atom) == wanted {
+                    targets.push(info.deviceid);
+                }
+            }
+            unsafe { (self.xinput.XIFreeDeviceInfo)(devices) };
+            (targets, xtest)
+        }
+
+        fn vendor_product(&self, deviceid: c_int, atom: c_ulong) -> Option<(u16, u16)> {
+            if atom == 0 {
+                return None;
+            }
+            let (mut kind, mut format, mut items, mut after) = (0, 0, 0, 0);
+            let mut data: *mut c_uchar = std::ptr::null_mut();
+            let status = unsafe {
+                (self.xinput.XIGetProperty)(
+                    self.display,
+                    deviceid,
+                    atom,
+                    0,
+                    2,
+                    0,
+                    XA_INTEGER,
+                    &mut kind,
+                    &mut format,
+                    &mut items,
+                    &mut after,
+                    &mut data,
+                )
+            };
+            if status != 0 || data.is_null() {
+                return None;
+            }
+            // XI2 returns 32-bit items packed, unlike core window properties.
+            let ids = (format == 32 && items == 2).then(|| unsafe {
+                let values = data.cast::<u32>();
+                (values.read_unaligned(), values.add(1).read_unaligned())
+            });
+            unsafe { (self.xlib.XFree)(data.cast()) };
+            let (vendor, product) = ids?;
+            Some((u16::try_from(vendor).ok()?, u16::try_from(product).ok()?))
+        }
+    }
+
+    impl Drop for XConnection {
+        fn drop(&mut self) {
+            // Closing the connection also drops its grabs, so the mouse
+            // never stays grabbed past the engine.
+            unsafe { (self.xlib.XCloseDisplay)(self.display) };
+        }
+    }
+
+    impl RemapOutput for XConnection {
+        fn post_navigation(&self, forward: bool, merged: ModifierMask) -> Result<(), String> {
+            let keysym = if forward { XK_Right } else { XK_Left };
+            self.post_key_combo(keysym, merged | modifier_mask(&[Modifier::Option]))
+        }
+
+        fn post_tab_switch(
+            &self,
+            next: bool,
+            _keys: TabKeys,
+            merged: ModifierMask,
+        ) -> Result<(), String> {
+            let modifiers: &[Modifier] = if next {
+                &[Modifier::Control]
+            } else {
+                &[Modifier::Control, Modifier::Shift]
+            };
+            self.post_key_combo(XK_Tab, merged | modifier_mask(modifiers))
+        }
+
+        /// Switches workspaces with Ctrl+Alt+Left and Right, which GNOME,
+        /// KDE and Xfce bind by default.
+        fn post_swipe(
+            &self,
+            swipe: Swipe,
+            forward: bool,
+            merged: ModifierMask,
+        ) -> Result<(), String> {
+            match swipe {
+                Swipe::Pages => self.post_navigation(forward, merged),
+                Swipe::Spaces => {
+                    let keysym = if forward { XK_Right } else { XK_Left };
+                    let modifiers = modifier_mask(&[Modifier::Control, Modifier::Option]);
+                    self.post_key_combo(keysym, merged | modifiers)
+                }
+            }
+        }
+
+        fn post_mouse_event(
+            &self,
+            button: i64,
+            down: bool,
+            _click_state: i64,
+            _modifiers: ModifierMask,
+        ) -> Result<(), String> {
+            // The X server numbers multi-clicks from their timing.
+            let x_button = x_button(button).ok_or_else(|| format!("no X button for {}", button))?;
+            let posted = unsafe {
+                (self.xtest.XTestFakeButtonEvent)(
+                    self.display,
+                    x_button,
+                    c_int::from(down),
+                    CurrentTime,
+                )
+            };
+            self.flush();
+            if posted == 0 {
+                return Err(format!("could not click button {}", button));
+            }
+            Ok(())
+        }
+    }
+
+    /// X button number of a zero-based button. X buttons 4 to 7 scroll, so
+    /// the side buttons start at 8.
+    fn x_button(button: i64) -> Option<c_uint> {
+        match button {
+            0 => Some(1),
+            1 => Some(3),
+            2 => Some(2),
+            3..=9 => c_uint::try_from(button + 5).ok(),
+            _ => None,
+        }
+    }
+
+    fn engine_button(x_button: c_int) -> Option<i64> {
+        match x_button {
+            1 => Some(0),
+            2 => Some(2),
+            3 => Some(1),
+            8..=14 => Some(i64::from(x_button) - 5),
+            _ => None,
+        }
+    }
+
+    fn held_modifiers(mask: c_uint) -> ModifierMask {
+        MODIFIER_MASKS
+            .iter()
+            .filter(|(bit, _, _)| mask & bit != 0)
+            .fold(0, |held, (_, _, modifier)| {
+                held | modifier_mask(&[*modifier])
+            })
+    }
+
+    /// Relative motion of a raw event along the first two axes.
+    fn raw_delta(raw: &XIRawEvent) -> (f64, f64) {
+        let len = usize::try_from(raw.valuators.mask_len).unwrap_or(0);
+        if raw.valuators.mask.is_null() || len == 0 {
+            return (0.0, 0.0);
+        }
+        let mask = unsafe { std::slice::from_raw_parts(raw.valuators.mask, len) };
+        let mut delta = [0.0; 2];
+        let mut value = 0;
+        for axis in 0..2 {
+            if XIMaskIsSet(mask, axis) {
+                delta[axis as usize] = unsafe { *raw.valuators.values.add(value) };
+                value += 1;
+            }
+        }
+        (delta[0], delta[1])
+    }
+
+    if let Some(session) = non_x11_session() {
+        // X grabs only reach XWayland apps here; grab the device instead.
+        log::info!(
+            "mouse-remap: {} session, reading the mouse through evdev",
+            session
+        );
+        supervise_engine(app, state, "evdev-input", evdev::run);
+        return;
+    }
+
+    let extended_app = app.clone();
+    let extended_state = state.clone();
+    start_extended_buttons(state.clone(), move |button, down| {
+        let ends_drag = !down && extended_state.middle_drag_button() == Some(button);
+        if !ends_drag && extended_state.is_passing_through() {
+            return;
+        }
+        match XConnection::open() {
+            Ok(output) => {
+                let held = output.held_modifiers();
+                dispatch_button(&extended_app, &extended_state, &output, button, down, held);
+            }
+            Err(message) => {
+                report_remap_error(&extended_app, &extended_state, "post-failed", message)
+            }
+        }
+    });
+
+    let _ = state
+        .action_tester
+        .set(|app, state, test| match XConnection::open() {
+            Ok(output) => run_action_test(app, state, &output, test),
+            Err(message) => report_remap_error(app, state, "post-failed", message),
+        });
+    supervise_engine(app, state, "x11-input", run_x11_input);
+
+    /// Grabs the mappable buttons and listens on this thread until the grab
+    /// stops being wanted. Presses arrive through a synchronous passive grab
+    /// on the root window, so each one is either replayed to the app under the
+    /// pointer or swallowed; releases and motion are read as raw events.
+    /// X has no way to swallow motion, so gestures move the pointer.
+    fn run_x11_input(app: &tauri::AppHandle, state: &AppState) -> EngineState {
+        let connection = match XConnection::open() {
+            Ok(connection) => connection,
+            Err(message) => {
+                report_remap_error(app, state, "tap-create-failed", message);
+                return EngineState::Failed;
+            }
+        };
+        let display = connection.display;
+        let xinput = &connection.xinput;
+
+        let mut raw_mask = [0u8; (XI_LASTEVENT as usize).div_ceil(8)];
+        XISetMask(&mut raw_mask, XI_RAW_BUTTON_RELEASE);
+        XISetMask(&mut raw_mask, XI_RAW_MOTION);
+        let mut raw_events = XIEventMask {
+            deviceid: XIAllMasterDevices,
+            mask_len: raw_mask.len() as c_int,
+            mask: raw_mask.as_mut_ptr(),
+        };
+        let mut press_mask = [0u8; (XI_LASTEVENT as usize).div_ceil(8)];
+        XISetMask(&mut press_mask, XI_BUTTON_PRESS);
+        let mut press_events = XIEventMask {
+            deviceid: XIAllMasterDevices,
+            mask_len: press_mask.len() as c_int,
+            mask: press_mask.as_mut_ptr(),
+        };
+        let mut modifiers = [XIGrabModifiers {
+            modifiers: XI_ANY_MODIFIER,
+            status: 0,
+        }];
+        unsafe { (xinput.XISelectEvents)(display, connection.root, &mut raw_events, 1) };
+        for button in (0..BUTTON_KEYS.len() as i64).filter_map(x_button) {
+            let failed = unsafe {
+                (xinput.XIGrabButton)(
+                    display,
+                    XIAllMasterDevices,
+                    button as c_int,
+                    connection.root,
+                    0,
+                    XIGrabModeSync,
+                    XIGrabModeAsync,
+                    0,
+                    &mut press_events,
+                    1,
+                    modifiers.as_mut_ptr(),
+                )
+            };
+            if failed != 0 {
+                log::warn!(
+                    "mouse-remap: another client already grabs X button {}",
+                    button
+                );
+            }
+        }
+        connection.flush();
+        state.set_tap_enabled(true, false);
+        report_engine_state(app, state, EngineState::Running);
+
+        let mut buttons = TapButtons::default();
+        let mut sources = connection.input_sources(state.resolved_mappings().device_id.as_deref());
+        let mut next_check = Instant::now() + TAP_LIFECYCLE_CHECK;
+        let mut poll = libc::pollfd {
+            fd: unsafe { (connection.xlib.XConnectionNumber)(display) },
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        let stopped = loop {
+            while unsafe { (connection.xlib.XPending)(display) } > 0 {
+                let mut event: XEvent = unsafe { std::mem::zeroed() };
+                unsafe { (connection.xlib.XNextEvent)(display, &mut event) };
+                if event.get_type() != GenericEvent {
+                    continue;
+                }
+                let cookie = unsafe { &mut event.generic_event_cookie };
+                if cookie.extension != connection.xinput_opcode
+                    || unsafe { (connection.xlib.XGetEventData)(display, cookie) } == 0
+                {
+                    continue;
+                }
+                let (targets, xtest) = &sources;
+                // Every device counts when the selected one has no X pointer,
+                // as with the event tap.
+                let is_user = |source: c_int| {
+                    !xtest.contains(&source) && (targets.is_empty() || targets.contains(&source))
+                };
+                match cookie.evtype {
+                    XI_BUTTON_PRESS => {
+                        let event = unsafe { &*cookie.data.cast::<XIDeviceEvent>() };
+                        let mut timer = state.time_callback();
+                        let swallowed = match engine_button(event.detail) {
+                            Some(button) if is_user(event.sourceid) => {
+                                let held = held_modifiers(event.mods.effective as c_uint);
+                                let swallowed = state.should_remap(0)
+                                    && guard_event(
+                                        app,
+                                        state,
+                                        || {
+                                            dispatch_button(
+                                                app,
+                                                state,
+                                                &connection,
+                                                button,
+                                                true,
+                                                held,
+                                            )
+                                        },
+                                        || false,
+                                    );
+                                buttons.press(button, swallowed);
+                                swallowed
+                            }
+                            _ => false,
+                        };
+                        unsafe {
+                            if timer.remapped(swallowed) {
+                                // Swallow the press, then let go so the posted
+                                // events and the release reach apps.
+                                (xinput.XIAllowEvents)(
+                                    display,
+                                    event.deviceid,
+                                    XIAsyncDevice,
+                                    CurrentTime,
+                                );
+                                (xinput.XIUngrabDevice)(display, event.deviceid, CurrentTime);
+                            } else {
+                                (xinput.XIAllowEvents)(
+                                    display,
+                                    event.deviceid,
+                                    XIReplayDevice,
+                                    CurrentTime,
+                                );
+                            }
+                        }
+                        connection.flush();
+                    }
+                    XI_RAW_BUTTON_RELEASE => {
+                        let event = unsafe { &*cookie.data.cast::<XIRawEvent>() };
+                        if let Some(button) =
+                            engine_button(event.detail).filter(|_| is_user(event.sourceid))
+                        {
+                            let mut timer = state.time_callback();
+                            let swallowed = buttons.release(button);
+                            if timer.remapped(swallowed) || state.should_remap(0) {
+                                let held = connection.held_modifiers();
+                                let dispatch = || {
+                                    dispatch_button(app, state, &connection, button, false, held)
+                                };
+                                guard_event(app, state, dispatch, || false);
+                            }
+                        }
+                    }
+                    XI_RAW_MOTION => {
+                        let event = unsafe { &*cookie.data.cast::<XIRawEvent>() };
+                        if is_user(event.sourceid) && state.should_remap(0) {
+                            let (dx, dy) = raw_delta(event);
+                            state.track_gesture(dx, dy);
+                        }
+                    }
+                    _ => {}
+                }
+                unsafe { (connection.xlib.XFreeEventData)(display, cookie) };
+            }
+
+            let now = Instant::now();
+            if now >= next_check {
+                if state.take_engine_restart() {
+                    break EngineState::Starting;
+                }
+                if !state.is_event_tap_wanted() {
+                    break EngineState::Idle;
+                }
+                sources = connection.input_sources(state.resolved_mappings().device_id.as_deref());
+                next_check = now + TAP_LIFECYCLE_CHECK;
+            }
+            let timeout = c_int::try_from(next_check.saturating_duration_since(now).as_millis())
+                .unwrap_or(c_int::MAX);
+            if unsafe { libc::poll(&mut poll, 1, timeout) } < 0
+                && std::io::Error::last_os_error().kind() != std::io::ErrorKind::Interrupted
+            {
+                break EngineState::Failed;
+            }
+            if poll.revents & (libc::POLLERR | libc::POLLHUP) != 0 {
+                break EngineState::Failed;
+            }
+        };
+        state.set_tap_enabled(false, false);
+        release_middle_drag(app, state, &connection);
+        match stopped {
+            EngineState::Idle => log::info!("mouse-remap: nothing to remap, releasing the X grabs"),
+            EngineState::Starting => log::info!("mouse-remap: restarting the X input listener"),
+            _ => report_remap_error(
+                app,
+                state,
+                "tap-stopped",
+                "the connection to the X server was lost".to_string(),
+            ),
+        }
+        stopped
+    }
+}
+
+/// Name of the session type when it isn't X11, judged from the environment
+/// the desktop sets up. XWayland's `DISPLAY` only reaches X11 apps, so a
+/// Wayland session can't be remapped through it.
+pub(crate) fn non_x11_session() -> Option<String> {
+    let session = std::env::var("XDG_SESSION_TYPE").unwrap_or_default();
+    if session == "wayland" || std::env::var_os("WAYLAND_DISPLAY").is_some() {
+        return Some("Wayland".to_string());
+    }
+    if std::env::var_os("DISPLAY").is_none() {
+        return Some(if session.is_empty() {
+            "non-graphical".to_string()
+        } else {
+            session
+        });
+    }
+    None
+}
+
+/// Remapping without a display server: the selected device's event node is
+/// grabbed with `EVIOCGRAB` so nothing else sees it, and everything that
+/// isn't remapped is re-emitted through a uinput virtual mouse. Needs read
+/// access to `/dev/input/event*` (the `input` group on most distributions)
+/// and write access to `/dev/uinput` (a udev rule).
+pub(crate) mod evdev {
+    use super::{
+        dispatch_button, guard_event, hid_vendor_product, modifier_mask, release_middle_drag,
+        report_engine_state, report_remap_error, AppState, EngineState, Modifier, ModifierMask,
+        RemapOutput, Swipe, TabKeys, TapButtons, BUTTON_KEYS, TAP_LIFECYCLE_CHECK,
+    };
+    use std::ffi::CString;
+    use std::io;
+    use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+    use std::path::{Path, PathBuf};
+    use std::time::Instant;
+
+    const EV_SYN: u16 = 0x00;
+    const EV_KEY: u16 = 0x01;
+    const EV_REL: u16 = 0x02;
+    const SYN_REPORT: u16 = 0;
+    const REL_X: u16 = 0x00;
+    const REL_Y: u16 = 0x01;
+    /// Relative axes the virtual mouse passes on: motion, both wheels, and
+    /// their high-resolution versions.
+    const RELATIVE_AXES: [u16; 6] = [REL_X, REL_Y, 0x06, 0x08, 0x0b, 0x0c];
+    /// `BTN_LEFT`; the remappable buttons follow it in `BUTTON_KEYS` order.
+    const BTN_LEFT: u16 = 0x110;
+    const KEY_LEFT: u16 = 105;
+    const KEY_RIGHT: u16 = 106;
+    const KEY_TAB: u16 = 15;
+    const KEY_A: u16 = 30;
+    /// Left and right key of each modifier; the left one is what gets posted.
+    const MODIFIER_KEYS: [([u16; 2], Modifier); 4] = [
+        ([42, 54], Modifier::Shift),
+        ([29, 97], Modifier::Control),
+        ([56, 100], Modifier::Option),
+        ([125, 126], Modifier::Command),
+    ];
+    const VIRTUAL_NAME: &str = "Edit Mouse virtual mouse";
+    const BUS_VIRTUAL: u16 = 0x06;
+
+    // ioctl requests from <linux/input.h> and <linux/uinput.h>.
+    const EVIOCGRAB: libc::Ioctl = 0x4004_4590;
+    const EVIOCGKEY_96: libc::Ioctl = 0x8060_4518;
+    const UI_SET_EVBIT: libc::Ioctl = 0x4004_5564;
+    const UI_SET_KEYBIT: libc::Ioctl = 0x4004_5565;
+    const UI_SET_RELBIT: libc::Ioctl = 0x4004_5566;
+    const UI_DEV_SETUP: libc::Ioctl = 0x405c_5503;
+    const UI_DEV_CREATE: libc::Ioctl = 0x5501;
+    const UI_DEV_DESTROY: libc::Ioctl = 0x5502;
+
+    /// The virtual mouse events are re-emitted and actions posted through.
+    struct VirtualMouse {
+        fd: OwnedFd,
+    }
+
+    impl VirtualMouse {
+        fn create() -> io::Result<Self> {
+            let fd = open("/dev/uinput", libc::O_WRONLY | libc::O_NONBLOCK)?;
+            let raw = fd.as_raw_fd();
+            let buttons = (BTN_LEFT..).take(BUTTON_KEYS.len());
+            let keys = MODIFIER_KEYS.iter().map(|(keys, _)| keys[0]);
+            ioctl_int(raw, UI_SET_EVBIT, EV_KEY)?;
+            for key in buttons.chain(keys).chain([KEY_LEFT, KEY_RIGHT, KEY_TAB]) {
+                ioctl_int(raw, UI_SET_KEYBIT, key)?;
+            }
+            ioctl_int(raw, UI_SET_EVBIT, EV_REL)?;
+            for axis in RELATIVE_AXES {
+                ioctl_int(raw, UI_SET_RELBIT, axis)?;
+            }
+            let mut setup: libc::uinput_setup = unsafe { std::mem::zeroed() };
+            setup.id.bustype = BUS_VIRTUAL;
+            for (slot, byte) in setup.name.iter_mut().zip(VIRTUAL_NAME.bytes()) {
+                *slot = byte as libc::c_char;
+            }
+            if unsafe { libc::ioctl(raw, UI_DEV_SETUP, &setup) } < 0
+                || unsafe { libc::ioctl(raw, UI_DEV_CREATE) } < 0
+            {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(Self { fd })
+        }
+
+        /// Writes `events` followed by a sync report.
+        fn emit(&self, events: &[(u16, u16, i32)]) -> io::Result<()> {
+            let frame: Vec<libc::input_event> = events
+                .iter()
+                .copied()
+                .chain([(EV_SYN, SYN_REPORT, 0)])
+                .map(|(kind, code, value)| libc::input_event {
+                    time: libc::timeval {
+                        tv_sec: 0,
+                        tv_usec: 0,
+                    },
+                    type_: kind,
+                    code,
+                    value,
+                })
+                .collect();
+            let len = std::mem::size_of_val(frame.as_slice());
+            let written = unsafe { libc::write(self.fd.as_raw_fd(), frame.as_ptr().cast(), len) };
+            if written < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(())
+        }
+
+        /// Taps `key` with the `merged` modifiers the user isn't already
+        /// holding pressed around it.
+        fn post_key_combo(&self, key: u16, merged: ModifierMask) -> Result<(), String> {
+            let held = held_modifiers();
+            let added: Vec<u16> = MODIFIER_KEYS
+                .iter()
+                .filter(|(_, modifier)| {
+                    let mask = modifier_mask(&[*modifier]);
+                    merged & mask != 0 && held & mask == 0
+                })
+                .map(|(keys, _)| keys[0])
+                .collect();
+            let presses = added
+                .iter()
+                .map(|key| (*key, 1))
+                .chain([(key, 1), (key, 0)])
+                .chain(added.iter().rev().map(|key| (*key, 0)));
+            for (key, value) in presses {
+                self.emit(&[(EV_KEY, key, value)])
+                    .map_err(|err| format!("could not send key {}: {}", key, err))?;
+            }
+            Ok(())
+        }
+    }
+
+    impl Drop for VirtualMouse {
+        fn drop(&mut self) {
+            unsafe { libc::ioctl(self.fd.as_raw_fd(), UI_DEV_DESTROY) };
+        }
+    }
+
+    impl RemapOutput for VirtualMouse {
+        fn post_navigation(&self, forward: bool, merged: ModifierMask) -> Result<(), String> {
+            let key = if forward { KEY_RIGHT } else { KEY_LEFT };
+            self.post_key_combo(key, merged | modifier_mask(&[Modifier::Option]))
+        }
+
+        fn post_tab_switch(
+            &self,
+            next: bool,
+            _keys: TabKeys,
+            merged: ModifierMask,
+        ) -> Result<(), String> {
+            let modifiers: &[Modifier] = if next {
+                &[Modifier::Control]
+            } else {
+                &[Modifier::Control, Modifier::Shift]
+            };
+            self.post_key_combo(KEY_TAB, merged | modifier_mask(modifiers))
+        }
+
+        fn post_swipe(
+            &self,
+            swipe: Swipe,
+            forward: bool,
+            merged: ModifierMask,
+        ) -> Result<(), String> {
+            match swipe {
+                Swipe::Pages => self.post_navigation(forward, merged),
+                Swipe::Spaces => {
+                    let key = if forward { KEY_RIGHT } else { KEY_LEFT };
+                    let modifiers = modifier_mask(&[Modifier::Control, Modifier::Option]);
+                    self.post_key_combo(key, merged | modifiers)
+                }
+            }
+        }
+
+        fn post_mouse_event(
+            &self,
+            button: i64,
+            down: bool,
+            _click_state: i64,
+            _modifiers: ModifierMask,
+        ) -> Result<(), String> {
+            let code =
+                button_code(button).ok_or_else(|| format!("no evdev button for {}", button))?;
+            self.emit(&[(EV_KEY, code, i32::from(down))])
+                .map_err(|err| format!("could not click button {}: {}", button, err))
+        }
+    }
+
+    /// An event node grabbed for exclusive reading. The kernel drops the grab
+    /// with the descriptor, so even a crash can't leave the mouse dead.
+    struct Grab {
+        fd: OwnedFd,
+        path: PathBuf,
+    }
+
+    impl Grab {
+        fn open(path: &Path) -> io::Result<Self> {
+            let fd = open(&path.to_string_lossy(), libc::O_RDONLY | libc::O_NONBLOCK)?;
+            ioctl_int(fd.as_raw_fd(), EVIOCGRAB, 1)?;
+            Ok(Self {
+                fd,
+                path: path.to_path_buf(),
+            })
+        }
+    }
+
+    impl Drop for Grab {
+        fn drop(&mut self) {
+            unsafe { libc::ioctl(self.fd.as_raw_fd(), EVIOCGRAB, 0) };
+        }
+    }
+
+    fn open(path: &str, flags: libc::c_int) -> io::Result<OwnedFd> {
+        let path = CString::new(path).map_err(|_| io::ErrorKind::InvalidInput)?;
+        let fd = unsafe { libc::open(path.as_ptr(), flags | libc::O_CLOEXEC) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(unsafe { OwnedFd::from_raw_fd(fd) })
+    }
+
+    fn ioctl_int(fd: libc::c_int, request: libc::Ioctl, value: u16) -> io::Result<()> {
+        if unsafe { libc::ioctl(fd, request, libc::c_int::from(value)) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    fn button_code(button: i64) -> Option<u16> {
+        let index = u16::try_from(button).ok()?;
+        (usize::from(index) < BUTTON_KEYS.len()).then_some(BTN_LEFT + index)
+    }
+
+    fn code_button(code: u16) -> Option<i64> {
+        let index = code.checked_sub(BTN_LEFT)?;
+        (usize::from(index) < BUTTON_KEYS.len()).then_some(i64::from(index))
+    }
+
+    /// Whether bit `code` is set in a sysfs capability bitmap, which lists
+    /// words of `long` in hex, most significant first.
+    pub(crate) fn has_capability(bitmap: &str, code: u16) -> bool {
+        let bits = usize::BITS as usize;
+        let words: Vec<&str> = bitmap.split_whitespace().rev().collect();
+        words
+            .get(usize::from(code) / bits)
+            .and_then(|word| usize::from_str_radix(word, 16).ok())
+            .is_some_and(|word| word & (1 << (usize::from(code) % bits)) != 0)
+    }
+
+    /// Event nodes with `code` among their key capabilities, with the
+    /// vendor and product id the kernel has for their device.
+    fn event_nodes(code: u16) -> Vec<(PathBuf, Option<(u16, u16)>)> {
+        let Ok(entries) = std::fs::read_dir("/sys/class/input") else {
+            return Vec::new();
+        };
+        let read = |path: PathBuf| std::fs::read_to_string(path).unwrap_or_default();
+        let mut nodes: Vec<_> = entries
+            .flatten()
+            .filter(|entry| entry.file_name().to_string_lossy().starts_with("event"))
+            .filter(|entry| {
+                let device = entry.path().join("device");
+                read(device.join("name")).trim() != VIRTUAL_NAME
+                    && has_capability(&read(device.join("capabilities/key")), code)
+            })
+            .map(|entry| {
+                let id = entry.path().join("device/id");
+                let hex = |name| u16::from_str_radix(read(id.join(name)).trim(), 16).ok();
+                let ids = hex("vendor").zip(hex("product"));
+                (Path::new("/dev/input").join(entry.file_name()), ids)
+            })
+            .collect();
+        nodes.sort();
+        nodes
+    }
+
+    /// Modifiers held on any keyboard, read from the key state of each
+    /// keyboard node since no display server reports them.
+    fn held_modifiers() -> ModifierMask {
+        let mut held = 0;
+        for (path, _) in event_nodes(KEY_A) {
+            let Ok(fd) = open(&path.to_string_lossy(), libc::O_RDONLY | libc::O_NONBLOCK) else {
+                continue;
+            };
+            let mut keys = [0u8; 96];
+            if unsafe { libc::ioctl(fd.as_raw_fd(), EVIOCGKEY_96, keys.as_mut_ptr()) } < 0 {
+                continue;
+            }
+            let is_down = |key: u16| keys[usize::from(key / 8)] & (1 << (key % 8)) != 0;
+            for (codes, modifier) in MODIFIER_KEYS {
+                if codes.iter().any(|code| is_down(*code)) {
+                    held |= modifier_mask(&[modifier]);
+                }
+            }
+        }
+        held
+    }
+
+    /// Whether the current user may read event nodes and create a uinput
+    /// device, for the permission commands.
+    pub(crate) fn access() -> (bool, bool) {
+        let readable = event_nodes(BTN_LEFT)
+            .first()
+            .is_some_and(|(path, _)| open(&path.to_string_lossy(), libc::O_RDONLY).is_ok());
+        let writable = open("/dev/uinput", libc::O_WRONLY).is_ok();
+        (readable, writable)
+    }
+
+    /// Grabs the selected device whenever it's plugged in and re-emits its
+    /// events until the engine stops being wanted.
+    pub(crate) fn run(app: &tauri::AppHandle, state: &AppState) -> EngineState {
+        let output = match VirtualMouse::create() {
+            Ok(output) => output,
+            Err(err) => {
+                let (engine_state, message) = match err.kind() {
+                    io::ErrorKind::NotFound => (
+                        EngineState::Unsupported,
+                        "/dev/uinput is missing: load the uinput kernel module".to_string(),
+                    ),
+                    io::ErrorKind::PermissionDenied => (
+                        EngineState::PermissionDenied,
+                        "no access to /dev/uinput: add a udev rule granting it to your user"
+                            .to_string(),
+                    ),
+                    _ => (
+                        EngineState::Failed,
+                        format!("could not create the virtual mouse: {}", err),
+                    ),
+                };
+                report_remap_error(app, state, "tap-create-failed", message);
+                return engine_state;
+            }
+        };
+        state.set_tap_enabled(true, false);
+        report_engine_state(app, state, EngineState::Running);
+
+        let mut grab: Option<Grab> = None;
+        let mut buttons = TapButtons::default();
+        let mut frame: Vec<(u16, u16, i32)> = Vec::new();
+        let mut motion = (0.0, 0.0);
+        let mut next_check = Instant::now();
+        let stopped = loop {
+            let now = Instant::now();
+            if now >= next_check {
+                if state.take_engine_restart() {
+                    break EngineState::Starting;
+                }
+                if !state.is_event_tap_wanted() {
+                    break EngineState::Idle;
+                }
+                if grab.is_none() {
+                    match grab_selected_device(state) {
+                        Ok(grabbed) => {
+                            if let Some(grabbed) = &grabbed {
+                                log::info!("mouse-remap: grabbed {}", grabbed.path.display());
+                            }
+                            grab = grabbed;
+                        }
+                        Err(err) if err.kind() == io::ErrorKind::PermissionDenied => {
+                            let message = format!(
+                                "no access to the mouse's event node: add your user to the input group ({})",
+                                err
+                            );
+                            report_remap_error(app, state, "tap-create-failed", message);
+                            break EngineState::PermissionDenied;
+                        }
+                        Err(err) => log::error!("mouse-remap: could not grab the mouse: {}", err),
+                    }
+                }
+                next_check = now + TAP_LIFECYCLE_CHECK;
+            }
+            let Some(device) = &grab else {
+                std::thread::sleep(next_check.saturating_duration_since(Instant::now()));
+                continue;
+            };
+            let mut poll = libc::pollfd {
+                fd: device.fd.as_raw_fd(),
+                events: libc::POLLIN,
+                revents: 0,
+            };
+            let timeout = libc::c_int::try_from(
+                next_check
+                    .saturating_duration_since(Instant::now())
+                    .as_millis(),
+            )
+            .unwrap_or(libc::c_int::MAX);
+            if unsafe { libc::poll(&mut poll, 1, timeout) } <= 0 {
+                continue;
+            }
+            let mut events = [libc::input_event {
+                time: libc::timeval {
+                    tv_sec: 0,
+                    tv_usec: 0,
+                },
+                type_: 0,
+                code: 0,
+                value: 0,
+            }; 64];
+            let read = unsafe {
+                libc::read(
+                    device.fd.as_raw_fd(),
+                    events.as_mut_ptr().cast(),
+                    std::mem::size_of_val(&events),
+                )
+            };
+            if read < 0 {
+                let err = io::Error::last_os_error();
+                if err.kind() == io::ErrorKind::WouldBlock
+                    || err.kind() == io::ErrorKind::Interrupted
+                {
+                    continue;
+                }
+                // Unplugged: let go and pick it up again once it's back.
+                log::info!("mouse-remap: lost {}: {}", device.path.display(), err);
+                grab = None;
+                release_middle_drag(app, state, &output);
+                continue;
+            }
+            let count =
+                usize::try_from(read).unwrap_or(0) / std::mem::size_of::<libc::input_event>();
+            for event in &events[..count] {
+                match (event.type_, code_button(event.code)) {
+                    (EV_SYN, _) if event.code == SYN_REPORT => {
+                        if state.should_remap(0) && state.track_gesture(motion.0, motion.1) {
+                            frame.retain(|(kind, code, _)| {
+                                *kind != EV_REL || (*code != REL_X && *code != REL_Y)
+                            });
+                        }
+                        motion = (0.0, 0.0);
+                        if !frame.is_empty() {
+                            if let Err(err) = output.emit(&frame) {
+                                log::error!("mouse-remap: could not re-emit events: {}", err);
+                            }
+                            frame.clear();
+                        }
+                    }
+                    (EV_KEY, Some(button)) if event.value != 2 => {
+                        let mut timer = state.time_callback();
+                        let down = event.value == 1;
+                        let held = held_modifiers();
+                        let swallow = if down {
+                            let swallowed = state.should_remap(0)
+                                && guard_event(
+                                    app,
+                                    state,
+                                    || dispatch_button(app, state, &output, button, down, held),
+                                    || false,
+                                );
+                            buttons.press(button, swallowed);
+                            swallowed
+                        } else {
+                            // A swallowed press is finished even if remapping
+                            // was turned off meanwhile, and its release
+                            // swallowed too.
+                            let swallowed = buttons.release(button);
+                            if swallowed || state.should_remap(0) {
+                                let dispatch =
+                                    || dispatch_button(app, state, &output, button, down, held);
+                                guard_event(app, state, dispatch, || false);
+                            }
+                            swallowed
+                        };
+                        if !timer.remapped(swallow) {
+                            frame.push((event.type_, event.code, event.value));
+                        }
+                    }
+                    (EV_REL, _) => {
+                        match event.code {
+                            REL_X => motion.0 += f64::from(event.value),
+                            REL_Y => motion.1 += f64::from(event.value),
+                            _ => {}
+                        }
+                        frame.push((event.type_, event.code, event.value));
+                    }
+                    // Scan codes and the like have no use on the virtual mouse.
+                    _ => {}
+                }
+            }
+        };
+        drop(grab);
+        state.set_tap_enabled(false, false);
+        release_middle_drag(app, state, &output);
+        match stopped {
+            EngineState::Idle => log::info!("mouse-remap: nothing to remap, releasing the mouse"),
+            EngineState::Starting => log::info!("mouse-remap: restarting the evdev listener"),
+            _ => {}
+        }
+        stopped
+    }
+
+    /// Grabs the event node of the selected device with mouse buttons, if
+    /// it's plugged in.
+    fn grab_selected_device(state: &AppState) -> io::Result<Option<Grab>> {
+        let wanted = state
+            .resolved_mappings()
+            .device_id
+            .as_deref()
+            .and_then(hid_vendor_product);
+        if wanted.is_none() {
+            return Ok(None);
+        }
+        match event_nodes(BTN_LEFT)
+            .into_iter()
+            .find(|(_, ids)| *ids == wanted)
+        {
+            Some((path, _)) => Grab::open(&path).map(Some),
+            None => Ok(None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn evdev_capabilities_are_read_from_sysfs_bitmaps() {
+        // A mouse with five buttons, and a keyboard with no buttons.
+        let mouse = "1f0000 0 0 0 0";
+        assert!(evdev::has_capability(mouse, 0x110));
+        assert!(evdev::has_capability(mouse, 0x114));
+        assert!(!evdev::has_capability(mouse, 0x115));
+        assert!(!evdev::has_capability(mouse, 30));
+        let keyboard = "1000000000007 ff9f207ac14057ff febeffdfffefffff fffffffffffffffe";
+        assert!(evdev::has_capability(keyboard, 30));
+        assert!(!evdev::has_capability(keyboard, 0x110));
+        assert!(!evdev::has_capability("", 0));
+    }
+}
@@ -0,0 +1,873 @@
+use super::*;
+
+pub(crate) fn start_mouse_remap(app: tauri::AppHandle, state: AppState) {
+    use core_foundation::base::TCFType;
+    use core_foundation::mach_port::{CFMachPort, CFMachPortInvalidate, CFMachPortRef};
+    use core_foundation::runloop::{
+        kCFRunLoopCommonModes, kCFRunLoopDefaultMode, CFRunLoop, CFRunLoopRunResult,
+        CFRunLoopSourceInvalidate,
+    };
+    use core_graphics::event::{
+        CGEvent, CGEventFlags, CGEventTap, CGEventTapLocation, CGEventTapOptions,
+        CGEventTapPlacement, CGEventType, CGMouseButton, EventField,
+    };
+    use core_graphics::event_source::{CGEventSource, CGEventSourceStateID};
+    use core_graphics::geometry::CGPoint;
+    use std::cell::{Cell, OnceCell};
+    use std::rc::Rc;
+
+    const KEYCODE_LEFT_BRACKET: u16 = 0x21;
+    const KEYCODE_RIGHT_BRACKET: u16 = 0x1E;
+    const KEYCODE_TAB: u16 = 0x30;
+    /// How long a Shortcut's key stays down.
+    const KEY_TAP_GAP: Duration = Duration::from_millis(30);
+    const KEYCODE_PAGE_UP: u16 = 0x74;
+    const KEYCODE_PAGE_DOWN: u16 = 0x79;
+    const KEYCODE_D: u16 = 0x02;
+    const KEYCODE_LEFT_ARROW: u16 = 0x7B;
+    const KEYCODE_RIGHT_ARROW: u16 = 0x7C;
+    /// `kCGScrollWheelEventScrollPhase` and
+    /// `kCGScrollWheelEventMomentumPhase`, which core-graphics doesn't name.
+    const SCROLL_WHEEL_EVENT_SCROLL_PHASE: u32 = 99;
+    const SCROLL_WHEEL_EVENT_MOMENTUM_PHASE: u32 = 123;
+    const MODIFIER_FLAGS: [(CGEventFlags, Modifier); 4] = [
+        (CGEventFlags::CGEventFlagShift, Modifier::Shift),
+        (CGEventFlags::CGEventFlagControl, Modifier::Control),
+        (CGEventFlags::CGEventFlagAlternate, Modifier::Option),
+        (CGEventFlags::CGEventFlagCommand, Modifier::Command),
+    ];
+    /// `TAP_TIMEOUT_LIMIT` timeouts within `TAP_TIMEOUT_WINDOW` are reported;
+    /// a single one is just re-enabled.
+    const TAP_TIMEOUT_LIMIT: u32 = 3;
+    const TAP_TIMEOUT_WINDOW: Duration = Duration::from_secs(60);
+
+    #[link(name = "CoreGraphics", kind = "framework")]
+    extern "C" {
+        fn CGEventTapEnable(tap: CFMachPortRef, enable: bool);
+        fn CGEventSourceButtonState(state: CGEventSourceStateID, button: u32) -> bool;
+    }
+
+    let extended_app = app.clone();
+    let extended_state = state.clone();
+    start_extended_buttons(state.clone(), move |button, down| {
+        let ends_drag = !down && extended_state.middle_drag_button() == Some(button);
+        if !ends_drag && extended_state.is_passing_through() {
+            return;
+        }
+        let Ok(source) = CGEventSource::new(CGEventSourceStateID::CombinedSessionState) else {
+            return;
+        };
+        let Ok(current) = CGEvent::new(source.clone()) else {
+            return;
+        };
+        let output = TapOutput {
+            source: &source,
+            location: current.location(),
+            flags: current.get_flags(),
+        };
+        let held = held_modifiers(current.get_flags());
+        dispatch_button(&extended_app, &extended_state, &output, button, down, held);
+    });
+
+    let _ = state.action_tester.set(post_action_test);
+    supervise_engine(app, state, "event-tap", run_event_tap);
+
+    /// Posts a test at the cursor, as the extended buttons do.
+    fn post_action_test(app: &tauri::AppHandle, state: &AppState, test: ActionTest) {
+        let Ok(source) = CGEventSource::new(CGEventSourceStateID::CombinedSessionState) else {
+            return;
+        };
+        let Ok(current) = CGEvent::new(source.clone()) else {
+            return;
+        };
+        let location = match test {
+            ActionTest::ClickAt { x, y } => CGPoint::new(x, y),
+            _ => current.location(),
+        };
+        let output = TapOutput {
+            source: &source,
+            location,
+            flags: current.get_flags(),
+        };
+        run_action_test(app, state, &output, test);
+    }
+
+    /// The event types the tap subscribes to for `events`.
+    fn event_types(events: TapEvents) -> Vec<CGEventType> {
+        let types: [(bool, &[CGEventType]); 8] = [
+            (
+                events.left,
+                &[CGEventType::LeftMouseDown, CGEventType::LeftMouseUp],
+            ),
+            (
+                events.right,
+                &[CGEventType::RightMouseDown, CGEventType::RightMouseUp],
+            ),
+            (
+                events.other,
+                &[CGEventType::OtherMouseDown, CGEventType::OtherMouseUp],
+            ),
+            (events.left_dragged, &[CGEventType::LeftMouseDragged]),
+            (events.right_dragged, &[CGEventType::RightMouseDragged]),
+            (events.other_dragged, &[CGEventType::OtherMouseDragged]),
+            (events.moved, &[CGEventType::MouseMoved]),
+            (events.scroll, &[CGEventType::ScrollWheel]),
+        ];
+        types
+            .into_iter()
+            .filter(|(wanted, _)| *wanted)
+            .flat_map(|(_, types)| types.iter().copied())
+            .collect()
+    }
+
+    /// Why a tap listening for `events` has to go, if it does: a restart
+    /// was asked for, nothing wants it, or it must listen for others.
+    fn tap_outdated(state: &AppState, events: TapEvents) -> Option<EngineState> {
+        if state.take_engine_restart() {
+            return Some(EngineState::Starting);
+        }
+        if !state.is_event_tap_wanted() {
+            return Some(EngineState::Idle);
+        }
+        if state.tap_events() != events {
+            log::info!("mouse-remap: the events to listen for changed");
+            return Some(EngineState::Starting);
+        }
+        None
+    }
+
+    /// Creates the event tap and runs it on this thread until it stops or
+    /// stops being wanted, then returns the state that leaves the engine in.
+    /// The tap and its run loop source are invalidated by the time this
+    /// returns, so a retry never adds a second tap.
+    fn run_event_tap(app: &tauri::AppHandle, state: &AppState) -> EngineState {
+        let events = state.tap_events();
+        if events == TapEvents::default() {
+            // Nothing to listen for, as while remapping is suspended for the
+            // frontmost app, so no tap until that changes.
+            report_engine_state(app, state, EngineState::Running);
+            loop {
+                if state.wait_for_engine_restart(TAP_LIFECYCLE_CHECK) {
+                    return EngineState::Starting;
+                }
+                if let Some(stopped) = tap_outdated(state, events) {
+                    return stopped;
+                }
+            }
+        }
+        let Ok(source) = CGEventSource::new(CGEventSourceStateID::CombinedSessionState) else {
+            report_remap_error(
+                app,
+                state,
+                "event-source-failed",
+                "could not create an event source".to_string(),
+            );
+            return EngineState::Failed;
+        };
+        let tap_app = app.clone();
+        let tap_state = state.clone();
+        // Filled in once the tap exists, so the callback can re-enable it.
+        let port: Rc<OnceCell<CFMachPort>> = Rc::new(OnceCell::new());
+        let tap_port = Rc::clone(&port);
+        let timeouts = Cell::new((Instant::now(), 0u32));
+        let buttons = Cell::new(TapButtons::default());
+        let scrolls = Cell::new(ScrollMomentum::default());
+        // In monitor mode the tap can see events but not change them, so
+        // nothing reaches apps any differently.
+        let options = if state.is_monitor_only() {
+            log::info!("mouse-remap: creating a listen-only event tap for monitor mode");
+            CGEventTapOptions::ListenOnly
+        } else {
+            CGEventTapOptions::Default
+        };
+        let tap = CGEventTap::new(
+            CGEventTapLocation::HID,
+            CGEventTapPlacement::HeadInsertEventTap,
+            options,
+            event_types(events),
+            move |_proxy, event_type, event| {
+                let mut timer = tap_state.time_callback();
+                let handle = || {
+                    if matches!(
+                        event_type,
+                        CGEventType::TapDisabledByTimeout | CGEventType::TapDisabledByUserInput
+                    ) {
+                        let timed_out = matches!(event_type, CGEventType::TapDisabledByTimeout);
+                        tap_state.set_tap_enabled(false, timed_out);
+                        if let Some(port) = tap_port.get() {
+                            unsafe { CGEventTapEnable(port.as_concrete_TypeRef(), true) };
+                            tap_state.set_tap_enabled(true, false);
+                        }
+                        // Events were dropped while the tap was off, maybe the
+                        // release of a translated middle button.
+                        if tap_state
+                            .middle_drag_button()
+                            .is_some_and(|button| !is_button_down(buttons.get(), button))
+                        {
+                            let output = TapOutput {
+                                source: &source,
+                                location: event.location(),
+                                flags: event.get_flags(),
+                            };
+                            release_middle_drag(&tap_app, &tap_state, &output);
+                        }
+                        if timed_out {
+                            let (since, count) = timeouts.get();
+                            let (since, count) = if since.elapsed() > TAP_TIMEOUT_WINDOW {
+                                (Instant::now(), 1)
+                            } else {
+                                (since, count + 1)
+                            };
+                            timeouts.set((since, count));
+                            if count == TAP_TIMEOUT_LIMIT {
+                                report_remap_error(
+                                &tap_app,
+                                &tap_state,
+                                "tap-timeout",
+                                "macOS keeps disabling the event tap because events took too long"
+                                    .to_string(),
+                            );
+                            }
+                        }
+                        return Some(event.clone());
+                    }
+                    let user_data =
+                        event.get_integer_value_field(EventField::EVENT_SOURCE_USER_DATA);
+                    if user_data == SYNTHETIC_EVENT_TAG {
+                        return Some(event.clone());
+                    }
+                    if matches!(event_type, CGEventType::ScrollWheel) {
+                        let disable = tap_state.should_remap(user_data)
+                            && tap_state.resolved_mappings().disable_scroll_inertia;
+                        let mut momentum = scrolls.get();
+                        let dropped = momentum.drop_event(
+                            event.get_integer_value_field(SCROLL_WHEEL_EVENT_SCROLL_PHASE),
+                            event.get_integer_value_field(SCROLL_WHEEL_EVENT_MOMENTUM_PHASE),
+                            disable,
+                        );
+                        scrolls.set(momentum);
+                        return (!dropped).then(|| event.clone());
+                    }
+                    if matches!(
+                        event_type,
+                        CGEventType::MouseMoved
+                            | CGEventType::LeftMouseDragged
+                            | CGEventType::RightMouseDragged
+                            | CGEventType::OtherMouseDragged
+                    ) {
+                        if let Some(button) = tap_state.middle_drag_button() {
+                            if !is_button_down(buttons.get(), button) {
+                                let output = TapOutput {
+                                    source: &source,
+                                    location: event.location(),
+                                    flags: event.get_flags(),
+                                };
+                                release_middle_drag(&tap_app, &tap_state, &output);
+                            }
+                        }
+                        let dragged = (!matches!(event_type, CGEventType::MouseMoved)).then(|| {
+                            let physical = event
+                                .get_integer_value_field(EventField::MOUSE_EVENT_BUTTON_NUMBER);
+                            let button = buttons.get().identity(physical);
+                            if button != physical {
+                                retarget(event, button, None);
+                            }
+                            button
+                        });
+                        match buttons.get().drag(dragged, tap_state.middle_drag_button()) {
+                            DragTranslation::Pass => {}
+                            DragTranslation::Moved => event.set_type(CGEventType::MouseMoved),
+                            DragTranslation::Dragged(button) => {
+                                retarget(event, button, None);
+                                // A translated middle button drags with the
+                                // modifiers its press was posted with.
+                                if let Some(pressed) = tap_state.middle_drag_button() {
+                                    let held = held_modifiers(event.get_flags());
+                                    let modifiers = tap_state
+                                        .resolved_mappings()
+                                        .click_modifiers(pressed, held);
+                                    event.set_flags(click_flags(event.get_flags(), modifiers));
+                                }
+                            }
+                        }
+                        if !tap_state.should_remap(user_data) {
+                            return Some(event.clone());
+                        }
+                        let dx = event.get_double_value_field(EventField::MOUSE_EVENT_DELTA_X);
+                        let dy = event.get_double_value_field(EventField::MOUSE_EVENT_DELTA_Y);
+                        return if tap_state.track_gesture(dx, dy) {
+                            None
+                        } else {
+                            Some(event.clone())
+                        };
+                    }
+                    let physical =
+                        event.get_integer_value_field(EventField::MOUSE_EVENT_BUTTON_NUMBER);
+                    let down = matches!(
+                        event_type,
+                        CGEventType::LeftMouseDown
+                            | CGEventType::RightMouseDown
+                            | CGEventType::OtherMouseDown
+                    );
+                    let mut pressed = buttons.get();
+                    // Swapped or not as the press was, so turning the swap
+                    // off mid-hold releases the button apps saw go down.
+                    let button = if down {
+                        let swap = tap_state.should_remap(user_data)
+                            && tap_state.resolved_mappings().swap_primary_buttons;
+                        pressed.swap_press(physical, swap)
+                    } else {
+                        pressed.swap_release(physical)
+                    };
+                    if button != physical {
+                        retarget(event, button, Some(down));
+                    }
+                    let output = TapOutput {
+                        source: &source,
+                        location: event.location(),
+                        flags: event.get_flags(),
+                    };
+                    let held = held_modifiers(event.get_flags());
+                    let swallow = if down {
+                        let swallowed = tap_state.should_remap(user_data)
+                            && dispatch_button(&tap_app, &tap_state, &output, button, down, held);
+                        pressed.press(button, swallowed);
+                        swallowed
+                    } else {
+                        // A swallowed press is finished even if remapping was
+                        // turned off meanwhile, and its release swallowed too.
+                        let swallowed = pressed.release(button);
+                        if swallowed || tap_state.should_remap(user_data) {
+                            dispatch_button(&tap_app, &tap_state, &output, button, down, held);
+                        }
+                        swallowed
+                    };
+                    buttons.set(pressed);
+                    if swallow {
+                        None
+                    } else {
+                        Some(event.clone())
+                    }
+                };
+                let result = guard_event(&tap_app, &tap_state, handle, || Some(event.clone()));
+                timer.remapped(result.is_none());
+                result
+            },
+        );
+
+        let Ok(tap) = tap else {
+            let permissions = permissions::status();
+            if permissions.all_granted() {
+                let message = "could not create the event tap".to_string();
+                report_remap_error(app, state, "tap-create-failed", message);
+                return EngineState::Failed;
+            }
+            let message =
+                "could not create the event tap: grant Input Monitoring and Accessibility";
+            report_remap_error(app, state, "tap-create-failed", message.to_string());
+            return EngineState::PermissionDenied;
+        };
+        let _ = port.set(tap.mach_port.clone());
+        let Ok(loop_source) = tap.mach_port.create_runloop_source(0) else {
+            report_remap_error(
+                app,
+                state,
+                "runloop-source-failed",
+                "could not attach the event tap to its run loop".to_string(),
+            );
+            return EngineState::Failed;
+        };
+        let runloop = CFRunLoop::get_current();
+        unsafe {
+            runloop.add_source(&loop_source, kCFRunLoopCommonModes);
+        }
+        tap.enable();
+        report_engine_state(app, state, EngineState::Running);
+        let stopped = loop {
+            let result = CFRunLoop::run_in_mode(
+                unsafe { kCFRunLoopDefaultMode },
+                TAP_LIFECYCLE_CHECK,
+                false,
+            );
+            if let Some(stopped) = tap_outdated(state, events) {
+                break stopped;
+            }
+            if matches!(
+                result,
+                CFRunLoopRunResult::Stopped | CFRunLoopRunResult::Finished
+            ) {
+                break EngineState::Failed;
+            }
+        };
+        unsafe {
+            runloop.remove_source(&loop_source, kCFRunLoopCommonModes);
+            CFRunLoopSourceInvalidate(loop_source.as_concrete_TypeRef());
+            CFMachPortInvalidate(tap.mach_port.as_concrete_TypeRef());
+        }
+        if let Ok(source) = CGEventSource::new(CGEventSourceStateID::CombinedSessionState) {
+            if let Ok(current) = CGEvent::new(source.clone()) {
+                let output = TapOutput {
+                    source: &source,
+                    location: current.location(),
+                    flags: current.get_flags(),
+                };
+                release_middle_drag(app, state, &output);
+            }
+        }
+        match stopped {
+            EngineState::Idle => {
+                log::info!("mouse-remap: nothing to remap, removing the event tap")
+            }
+            EngineState::Starting => log::info!("mouse-remap: restarting the event tap"),
+            _ => report_remap_error(
+                app,
+                state,
+                "tap-stopped",
+                "the event tap stopped".to_string(),
+            ),
+        }
+        stopped
+    }
+
+    /// Modifiers held according to an event's flags. Caps Lock is ignored.
+    fn held_modifiers(flags: CGEventFlags) -> ModifierMask {
+        MODIFIER_FLAGS
+            .iter()
+            .filter(|(flag, _)| flags.contains(*flag))
+            .fold(0, |mask, (_, modifier)| mask | modifier_mask(&[*modifier]))
+    }
+
+    fn modifier_flags(mask: ModifierMask) -> CGEventFlags {
+        MODIFIER_FLAGS
+            .iter()
+            .filter(|(_, modifier)| mask & modifier_mask(&[*modifier]) != 0)
+            .fold(CGEventFlags::empty(), |flags, (flag, _)| flags | *flag)
+    }
+
+    /// The key typing `]` or `[` on the current layout with the modifiers it
+    /// takes there, or the US one where the layout has none.
+    fn bracket(right: bool) -> (u16, ModifierMask) {
+        let (key, keycode) = if right {
+            (']', KEYCODE_RIGHT_BRACKET)
+        } else {
+            ('[', KEYCODE_LEFT_BRACKET)
+        };
+        keyboard_layout::resolve(Some(keycode), Some(key), 0).unwrap_or((keycode, 0))
+    }
+
+    /// Posts `keycode` with exactly `flags`. The events come from a private
+    /// source so modifiers the user is holding, such as the Shift of a
+    /// Shift+button mapping, only reach the combo through `flags`.
+    fn post_key_combo(keycode: u16, flags: CGEventFlags) -> Result<(), String> {
+        for down in [true, false] {
+            post_key(keycode, flags, down)?;
+        }
+        Ok(())
+    }
+
+    /// Accessibility role of the element at `point`, or None when nothing
+    /// there answers.
+    fn role_at(point: CGPoint) -> Option<String> {
+        use core_foundation::base::{CFType, CFTypeRef};
+        use core_foundation::string::{CFString, CFStringRef};
+
+        #[link(name = "ApplicationServices", kind = "framework")]
+        extern "C" {
+            fn AXUIElementCreateSystemWide() -> CFTypeRef;
+            fn AXUIElementCopyElementAtPosition(
+                application: CFTypeRef,
+                x: f32,
+                y: f32,
+                element: *mut CFTypeRef,
+            ) -> i32;
+            fn AXUIElementCopyAttributeValue(
+                element: CFTypeRef,
+                attribute: CFStringRef,
+                value: *mut CFTypeRef,
+            ) -> i32;
+        }
+
+        unsafe {
+            let system = CFType::wrap_under_create_rule(AXUIElementCreateSystemWide());
+            let mut element = std::ptr::null();
+            if AXUIElementCopyElementAtPosition(
+                system.as_CFTypeRef(),
+                point.x as f32,
+                point.y as f32,
+                &mut element,
+            ) != 0
+                || element.is_null()
+            {
+                return None;
+            }
+            let element = CFType::wrap_under_create_rule(element);
+            let attribute = CFString::from_static_string("AXRole");
+            let mut role = std::ptr::null();
+            if AXUIElementCopyAttributeValue(
+                element.as_CFTypeRef(),
+                attribute.as_concrete_TypeRef(),
+                &mut role,
+            ) != 0
+                || role.is_null()
+            {
+                return None;
+            }
+            CFType::wrap_under_create_rule(role)
+                .downcast::<CFString>()
+                .map(|role| role.to_string())
+        }
+    }
+
+    fn post_key(keycode: u16, flags: CGEventFlags, down: bool) -> Result<(), String> {
+        let source = CGEventSource::new(CGEventSourceStateID::Private)
+            .map_err(|_| "could not create a keyboard event source".to_string())?;
+        let event = CGEvent::new_keyboard_event(source, keycode, down)
+            .map_err(|_| format!("could not create a key event for keycode {}", keycode))?;
+        event.set_flags(flags);
+        event.set_integer_value_field(EventField::EVENT_SOURCE_USER_DATA, SYNTHETIC_EVENT_TAG);
+        event.post(CGEventTapLocation::HID);
+        Ok(())
+    }
+
+    /// Posts from the tap's event source at the location of the event that
+    /// triggered the action, with its flags.
+    struct TapOutput<'a> {
+        source: &'a CGEventSource,
+        location: CGPoint,
+        flags: CGEventFlags,
+    }
+
+    /// A `TapOutput` for another thread, posting from a source of its own.
+    struct DetachedTapOutput {
+        location: CGPoint,
+        flags: CGEventFlags,
+    }
+
+    impl DetachedTapOutput {
+        fn with_output(
+            &self,
+            post: impl FnOnce(&TapOutput) -> Result<(), String>,
+        ) -> Result<(), String> {
+            let source = CGEventSource::new(CGEventSourceStateID::CombinedSessionState)
+                .map_err(|_| "could not create an event source".to_string())?;
+            post(&TapOutput {
+                source: &source,
+                location: self.location,
+                flags: self.flags,
+            })
+        }
+    }
+
+    impl RemapOutput for DetachedTapOutput {
+        fn post_navigation(&self, forward: bool, merged: ModifierMask) -> Result<(), String> {
+            self.with_output(|output| output.post_navigation(forward, merged))
+        }
+
+        fn post_tab_switch(
+            &self,
+            next: bool,
+            keys: TabKeys,
+            merged: ModifierMask,
+        ) -> Result<(), String> {
+            self.with_output(|output| output.post_tab_switch(next, keys, merged))
+        }
+
+        fn post_shortcut(&self, keycode: u16, modifiers: ModifierMask) -> Result<(), String> {
+            self.with_output(|output| output.post_shortcut(keycode, modifiers))
+        }
+
+        fn post_look_up(&self, merged: ModifierMask) -> Result<(), String> {
+            self.with_output(|output| output.post_look_up(merged))
+        }
+
+        fn post_swipe(
+            &self,
+            swipe: Swipe,
+            forward: bool,
+            merged: ModifierMask,
+        ) -> Result<(), String> {
+            self.with_output(|output| output.post_swipe(swipe, forward, merged))
+        }
+
+        fn post_mouse_event(
+            &self,
+            button: i64,
+            down: bool,
+            click_state: i64,
+            modifiers: ModifierMask,
+        ) -> Result<(), String> {
+            self.with_output(|output| output.post_mouse_event(button, down, click_state, modifiers))
+        }
+    }
+
+    impl RemapOutput for TapOutput<'_> {
+        fn detached(&self) -> Option<Box<dyn RemapOutput + Send>> {
+            Some(Box::new(DetachedTapOutput {
+                location: self.location,
+                flags: self.flags,
+            }))
+        }
+
+        fn post_navigation(&self, forward: bool, merged: ModifierMask) -> Result<(), String> {
+            let (keycode, modifiers) = bracket(forward);
+            post_key_combo(
+                keycode,
+                modifier_flags(merged | modifiers | modifier_mask(&[Modifier::Command])),
+            )
+        }
+
+        fn post_tab_switch(
+            &self,
+            next: bool,
+            keys: TabKeys,
+            merged: ModifierMask,
+        ) -> Result<(), String> {
+            let (keycode, modifiers): (u16, &[Modifier]) = match (keys, next) {
+                (TabKeys::ControlTab, true) => (KEYCODE_TAB, &[Modifier::Control]),
+                (TabKeys::ControlTab, false) => {
+                    (KEYCODE_TAB, &[Modifier::Control, Modifier::Shift])
+                }
+                (TabKeys::ControlPage, true) => (KEYCODE_PAGE_DOWN, &[Modifier::Control]),
+                (TabKeys::ControlPage, false) => (KEYCODE_PAGE_UP, &[Modifier::Control]),
+                (TabKeys::CommandBrackets, next) => {
+                    let (keycode, needed) = bracket(next);
+                    let modifiers = modifier_mask(&[Modifier::Command, Modifier::Shift]) | needed;
+                    return post_key_combo(keycode, modifier_flags(merged | modifiers));
+                }
+            };
+            post_key_combo(keycode, modifier_flags(merged | modifier_mask(modifiers)))
+        }
+
+        /// Lets go of the key `KEY_TAP_GAP` after pressing it, off the event
+        /// path, so apps that act while a key is held, such as push-to-talk
+        /// in OBS or Zoom, see it held.
+        fn post_shortcut(&self, keycode: u16, modifiers: ModifierMask) -> Result<(), String> {
+            let flags = modifier_flags(modifiers);
+            post_key(keycode, flags, true)?;
+            run_deferred(Box::new(move || {
+                std::thread::sleep(KEY_TAP_GAP);
+                if let Err(err) = post_key(keycode, flags, false) {
+                    log::error!("mouse-remap: failed to release a shortcut's key: {}", err);
+                }
+            }));
+            Ok(())
+        }
+
+        /// Presses ⌃⌘D only over text, asking Accessibility off the event
+        /// path since a busy app can take a while to answer.
+        fn post_look_up(&self, merged: ModifierMask) -> Result<(), String> {
+            let location = self.location;
+            let flags =
+                modifier_flags(merged | modifier_mask(&[Modifier::Command, Modifier::Control]));
+            run_deferred(Box::new(move || {
+                let role = role_at(location);
+                if !role
+                    .as_deref()
+                    .is_some_and(|role| LOOK_UP_ROLES.contains(&role))
+                {
+                    log::debug!(
+                        "mouse-remap: nothing to look up under the pointer ({})",
+                        role.as_deref().unwrap_or("no element")
+                    );
+                    return;
+                }
+                if let Err(err) = post_key_combo(KEYCODE_D, flags) {
+                    log::error!("mouse-remap: failed to post Look Up: {}", err);
+                }
+            }));
+            Ok(())
+        }
+
+        /// Switches spaces with ⌃← and ⌃→, Mission Control's default
+        /// shortcuts, where gestures can't be posted.
+        fn post_swipe(
+            &self,
+            swipe: Swipe,
+            forward: bool,
+            merged: ModifierMask,
+        ) -> Result<(), String> {
+            match (swipe_strategy(), swipe) {
+                (SwipeStrategy::Gesture, _) => post_gesture_swipe(swipe, forward),
+                (SwipeStrategy::Keys, Swipe::Pages) => self.post_navigation(forward, merged),
+                (SwipeStrategy::Keys, Swipe::Spaces) => {
+                    let keycode = if forward {
+                        KEYCODE_RIGHT_ARROW
+                    } else {
+                        KEYCODE_LEFT_ARROW
+                    };
+                    let modifiers = merged | modifier_mask(&[Modifier::Control]);
+                    post_key_combo(keycode, modifier_flags(modifiers))
+                }
+            }
+        }
+
+        fn post_mouse_event(
+            &self,
+            button: i64,
+            down: bool,
+            click_state: i64,
+            modifiers: ModifierMask,
+        ) -> Result<(), String> {
+            let flags = click_flags(self.flags, modifiers);
+            // An event's location is already in global display coordinates,
+            // which new events take too; it's only kept on a display.
+            let point = (self.location.x, self.location.y);
+            let location = ClickTarget::resolve(point, &active_displays())
+                .map_or(self.location, |target| CGPoint::new(target.x, target.y));
+            post_mouse_event(self.source, location, flags, button, down, click_state)
+        }
+    }
+
+    /// `original`'s flags with its modifiers replaced by `modifiers`. Flags
+    /// that aren't modifiers, such as Caps Lock's, come along.
+    fn click_flags(original: CGEventFlags, modifiers: ModifierMask) -> CGEventFlags {
+        let all = MODIFIER_FLAGS
+            .iter()
+            .fold(CGEventFlags::empty(), |flags, (flag, _)| flags | *flag);
+        (original - all) | modifier_flags(modifiers)
+    }
+
+    fn post_mouse_event(
+        source: &CGEventSource,
+        location: CGPoint,
+        flags: CGEventFlags,
+        button: i64,
+        down: bool,
+        click_state: i64,
+    ) -> Result<(), String> {
+        let (mouse_button, event_type) = match (button, down) {
+            (0, true) => (CGMouseButton::Left, CGEventType::LeftMouseDown),
+            (0, false) => (CGMouseButton::Left, CGEventType::LeftMouseUp),
+            (1, true) => (CGMouseButton::Right, CGEventType::RightMouseDown),
+            (1, false) => (CGMouseButton::Right, CGEventType::RightMouseUp),
+            (_, true) => (CGMouseButton::Center, CGEventType::OtherMouseDown),
+            (_, false) => (CGMouseButton::Center, CGEventType::OtherMouseUp),
+        };
+        let event = CGEvent::new_mouse_event(source.clone(), event_type, location, mouse_button)
+            .map_err(|_| format!("could not create a click for button {}", button))?;
+        event.set_flags(flags);
+        event.set_integer_value_field(EventField::MOUSE_EVENT_BUTTON_NUMBER, button);
+        event.set_integer_value_field(EventField::MOUSE_EVENT_CLICK_STATE, click_state);
+        // kCGEventMouseSubtypeDefault: a plain mouse, not a tablet.
+        event.set_integer_value_field(EventField::MOUSE_EVENT_SUB_TYPE, 0);
+        event.set_double_value_field(
+            EventField::MOUSE_EVENT_PRESSURE,
+            if down { 1.0 } else { 0.0 },
+        );
+        event.set_integer_value_field(EventField::EVENT_SOURCE_USER_DATA, SYNTHETIC_EVENT_TAG);
+        event.post(CGEventTapLocation::HID);
+        Ok(())
+    }
+
+    /// Makes `event` a press (`Some(true)`), release, or drag (`None`) of
+    /// `button`.
+    fn retarget(event: &CGEvent, button: i64, down: Option<bool>) {
+        event.set_type(match (button, down) {
+            (0, Some(true)) => CGEventType::LeftMouseDown,
+            (0, Some(false)) => CGEventType::LeftMouseUp,
+            (0, None) => CGEventType::LeftMouseDragged,
+            (1, Some(true)) => CGEventType::RightMouseDown,
+            (1, Some(false)) => CGEventType::RightMouseUp,
+            (1, None) => CGEventType::RightMouseDragged,
+            (_, Some(true)) => CGEventType::OtherMouseDown,
+            (_, Some(false)) => CGEventType::OtherMouseUp,
+            (_, None) => CGEventType::OtherMouseDragged,
+        });
+        event.set_integer_value_field(EventField::MOUSE_EVENT_BUTTON_NUMBER, button);
+    }
+
+    /// Whether `button` is still held. Buttons the tap never saw go down,
+    /// such as extended buttons, are assumed held since the system doesn't
+    /// track them.
+    fn is_button_down(buttons: TapButtons, button: i64) -> bool {
+        if buttons.seen & button_bit(button) == 0 {
+            return true;
+        }
+        // The system tracks the physical button, not the one it acts as.
+        u32::try_from(buttons.physical(button)).is_ok_and(|button| unsafe {
+            CGEventSourceButtonState(CGEventSourceStateID::HIDSystemState, button)
+        })
+    }
+}
+
+/// Private `CGEvent` fields, and their values, of the gesture events a
+/// trackpad posts, which AppKit and the Dock read swipes from. None of
+/// them is documented.
+const GESTURE_EVENT_TYPE_FIELD: u32 = 55;
+const GESTURE_HID_TYPE_FIELD: u32 = 110;
+const GESTURE_SWIPE_DIRECTION_FIELD: u32 = 115;
+const GESTURE_DOCK_MOTION_FIELD: u32 = 123;
+const GESTURE_DOCK_PROGRESS_FIELD: u32 = 124;
+const GESTURE_PHASE_FIELD: u32 = 132;
+const GESTURE_DOCK_PHASE_FIELD: u32 = 134;
+/// `NSEventTypeGesture`, and the Dock control event a Dock swipe comes
+/// with.
+const EVENT_TYPE_GESTURE: i64 = 29;
+const EVENT_TYPE_DOCK_CONTROL: i64 = 30;
+const HID_NAVIGATION_SWIPE: i64 = 16;
+const HID_DOCK_SWIPE: i64 = 23;
+const HID_SWIPE_LEFT: i64 = 4;
+const HID_SWIPE_RIGHT: i64 = 8;
+const HID_PHASE_BEGAN: i64 = 1;
+const HID_PHASE_ENDED: i64 = 4;
+const DOCK_MOTION_HORIZONTAL: i64 = 1;
+
+/// Whether a gesture event can be made and keeps the type it's given,
+/// which `swipe_strategy` checks before any is posted.
+pub(crate) fn gesture_events_supported() -> bool {
+    gesture_event(EVENT_TYPE_GESTURE).is_some_and(|event| {
+        event.get_integer_value_field(GESTURE_EVENT_TYPE_FIELD) == EVENT_TYPE_GESTURE
+    })
+}
+
+fn gesture_event(event_type: i64) -> Option<core_graphics::event::CGEvent> {
+    use core_graphics::event::{CGEvent, EventField};
+    use core_graphics::event_source::{CGEventSource, CGEventSourceStateID};
+
+    let source = CGEventSource::new(CGEventSourceStateID::HIDSystemState).ok()?;
+    let event = CGEvent::new(source).ok()?;
+    event.set_integer_value_field(GESTURE_EVENT_TYPE_FIELD, event_type);
+    event.set_integer_value_field(EventField::EVENT_SOURCE_USER_DATA, SYNTHETIC_EVENT_TAG);
+    Some(event)
+}
+
+/// Posts the began and ended phases of a swipe: a navigation swipe for
+/// pages, and for spaces a Dock swipe the Dock finishes on its own.
+fn post_gesture_swipe(swipe: Swipe, forward: bool) -> Result<(), String> {
+    use core_graphics::event::CGEventTapLocation;
+
+    let created = |event_type| {
+        gesture_event(event_type).ok_or_else(|| "could not create a gesture event".to_string())
+    };
+    for phase in [HID_PHASE_BEGAN, HID_PHASE_ENDED] {
+        let gesture = created(EVENT_TYPE_GESTURE)?;
+        match swipe {
+            Swipe::Pages => {
+                let direction = if forward {
+                    HID_SWIPE_RIGHT
+                } else {
+                    HID_SWIPE_LEFT
+                };
+                gesture.set_integer_value_field(GESTURE_HID_TYPE_FIELD, HID_NAVIGATION_SWIPE);
+                gesture.set_integer_value_field(GESTURE_PHASE_FIELD, phase);
+                gesture.set_integer_value_field(GESTURE_SWIPE_DIRECTION_FIELD, direction);
+            }
+            Swipe::Spaces => {
+                let progress = match (phase, forward) {
+                    (HID_PHASE_BEGAN, _) => 0.0,
+                    (_, true) => 1.0,
+                    (_, false) => -1.0,
+                };
+                let dock = created(EVENT_TYPE_DOCK_CONTROL)?;
+                dock.set_integer_value_field(GESTURE_HID_TYPE_FIELD, HID_DOCK_SWIPE);
+                dock.set_integer_value_field(GESTURE_PHASE_FIELD, phase);
+                dock.set_integer_value_field(GESTURE_DOCK_PHASE_FIELD, phase);
+                dock.set_integer_value_field(GESTURE_DOCK_MOTION_FIELD, DOCK_MOTION_HORIZONTAL);
+                dock.set_double_value_field(GESTURE_DOCK_PROGRESS_FIELD, progress);
+                dock.post(CGEventTapLocation::Session);
+            }
+        }
+        gesture.post(CGEventTapLocation::Session);
+    }
+    Ok(())
+}
@@ -0,0 +1,403 @@
+use super::*;
+
+/// Most posts kept; the oldest go first.
+const POSTED_LIMIT: usize = 1000;
+
+/// Input the mock engine was asked to post.
+#[derive(Debug, Serialize, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub(crate) enum PostedInput {
+    Navigation {
+        forward: bool,
+        modifiers: ModifierMask,
+    },
+    TabSwitch {
+        next: bool,
+        keys: TabKeys,
+        modifiers: ModifierMask,
+    },
+    Shortcut {
+        keycode: u16,
+        modifiers: ModifierMask,
+    },
+    LookUp {
+        modifiers: ModifierMask,
+    },
+    Swipe {
+        swipe: Swipe,
+        forward: bool,
+        modifiers: ModifierMask,
+    },
+    MouseEvent {
+        button: i64,
+        down: bool,
+        click_state: i64,
+        modifiers: ModifierMask,
+    },
+}
+
+struct SimulatedEvent {
+    button: i64,
+    down: bool,
+    held: ModifierMask,
+    /// Told whether the real event would have been swallowed.
+    reply: std::sync::mpsc::Sender<bool>,
+}
+
+/// A mock engine: where simulated events are handed to its thread, and
+/// what it was asked to post. Managed by the app, so the commands find it.
+#[derive(Clone, Default)]
+pub(crate) struct MockEngine {
+    events: Arc<std::sync::OnceLock<std::sync::mpsc::Sender<SimulatedEvent>>>,
+    posted: Arc<Mutex<VecDeque<PostedInput>>>,
+}
+
+#[derive(Clone)]
+struct MockOutput {
+    posted: Arc<Mutex<VecDeque<PostedInput>>>,
+}
+
+impl MockOutput {
+    pub(crate) fn record(&self, input: PostedInput) -> Result<(), String> {
+        let mut posted = lock_recovering(&self.posted, "simulated output");
+        if posted.len() == POSTED_LIMIT {
+            posted.pop_front();
+        }
+        posted.push_back(input);
+        Ok(())
+    }
+}
+
+impl RemapOutput for MockOutput {
+    fn post_navigation(&self, forward: bool, merged: ModifierMask) -> Result<(), String> {
+        self.record(PostedInput::Navigation {
+            forward,
+            modifiers: merged,
+        })
+    }
+
+    fn post_tab_switch(
+        &self,
+        next: bool,
+        keys: TabKeys,
+        merged: ModifierMask,
+    ) -> Result<(), String> {
+        self.record(PostedInput::TabSwitch {
+            next,
+            keys,
+            modifiers: merged,
+        })
+    }
+
+    fn post_shortcut(&self, keycode: u16, modifiers: ModifierMask) -> Result<(), String> {
+        self.record(PostedInput::Shortcut { keycode, modifiers })
+    }
+
+    fn post_look_up(&self, merged: ModifierMask) -> Result<(), String> {
+        self.record(PostedInput::LookUp { modifiers: merged })
+    }
+
+    fn post_swipe(&self, swipe: Swipe, forward: bool, merged: ModifierMask) -> Result<(), String> {
+        self.record(PostedInput::Swipe {
+            swipe,
+            forward,
+            modifiers: merged,
+        })
+    }
+
+    fn post_mouse_event(
+        &self,
+        button: i64,
+        down: bool,
+        click_state: i64,
+        modifiers: ModifierMask,
+    ) -> Result<(), String> {
+        self.record(PostedInput::MouseEvent {
+            button,
+            down,
+            click_state,
+            modifiers,
+        })
+    }
+
+    fn detached(&self) -> Option<Box<dyn RemapOutput + Send>> {
+        Some(Box::new(self.clone()))
+    }
+}
+
+impl MockEngine {
+    /// Starts the thread that runs simulated events through
+    /// `dispatch_button`, reporting to `host` and swallowing releases the
+    /// way the platform engines do.
+    pub(crate) fn start(&self, host: impl RemapHost, state: AppState) {
+        let (sender, receiver) = std::sync::mpsc::channel::<SimulatedEvent>();
+        if self.events.set(sender).is_err() {
+            return;
+        }
+        log::info!("mouse-remap: mock engine running");
+        let output = MockOutput {
+            posted: Arc::clone(&self.posted),
+        };
+        std::thread::spawn(move || run(&host, &state, &output, receiver));
+    }
+
+    /// Hands a press or release to the engine thread, returning where its
+    /// answer arrives.
+    fn simulate(
+        &self,
+        button: i64,
+        down: bool,
+        held: ModifierMask,
+    ) -> Result<std::sync::mpsc::Receiver<bool>, String> {
+        let events = self.events.get().ok_or("the mock engine isn't running")?;
+        let (reply, answer) = std::sync::mpsc::channel();
+        let event = SimulatedEvent {
+            button,
+            down,
+            held,
+            reply,
+        };
+        events
+            .send(event)
+            .map_err(|_| "the mock engine stopped".to_string())?;
+        Ok(answer)
+    }
+
+    /// What was posted, oldest first, forgetting it when `clear`.
+    pub(crate) fn posted(&self, clear: bool) -> Vec<PostedInput> {
+        let mut posted = lock_recovering(&self.posted, "simulated output");
+        let list = posted.iter().cloned().collect();
+        if clear {
+            posted.clear();
+        }
+        list
+    }
+}
+
+/// The engine thread: dispatches each event `receiver` gets until the
+/// engine is dropped.
+fn run(
+    host: &impl RemapHost,
+    state: &AppState,
+    output: &MockOutput,
+    receiver: std::sync::mpsc::Receiver<SimulatedEvent>,
+) {
+    let mut buttons = TapButtons::default();
+    for event in receiver {
+        let SimulatedEvent {
+            button,
+            down,
+            held,
+            reply,
+        } = event;
+        let dispatch = || dispatch_button(host, state, output, button, down, held);
+        let swallowed = if down {
+            let swallowed = state.should_remap(0) && guard_event(host, state, dispatch, || false);
+            buttons.press(button, swallowed);
+            swallowed
+        } else {
+            let swallowed = buttons.release(button);
+            if swallowed || state.should_remap(0) {
+                guard_event(host, state, dispatch, || false);
+            }
+            swallowed
+        };
+        let _ = reply.send(swallowed);
+    }
+}
+
+/// Presses or releases `button` of `device_id`, which must be the selected
+/// device, with `modifiers` held, through the mock engine. Returns whether
+/// the real event would have been swallowed.
+#[tauri::command]
+pub(crate) async fn simulate_button_event(
+    state: State<'_, AppState>,
+    engine: State<'_, MockEngine>,
+    device_id: String,
+    button: String,
+    down: bool,
+    modifiers: Option<Vec<Modifier>>,
+) -> Result<bool, String> {
+    let number = BUTTON_KEYS
+        .iter()
+        .position(|key| *key == button)
+        .ok_or_else(|| format!("unknown button {}", button))?;
+    if state.resolved_mappings().device_id.as_deref() != Some(device_id.as_str()) {
+        return Err(format!(
+            "{} isn't the selected device or isn't connected",
+            device_id
+        ));
+    }
+    let held = modifier_mask(&modifiers.unwrap_or_default());
+    let answer = engine.simulate(number as i64, down, held)?;
+    tauri::async_runtime::spawn_blocking(move || answer.recv())
+        .await
+        .map_err(|err| err.to_string())?
+        .map_err(|_| "the mock engine stopped".to_string())
+}
+
+/// What the mock engine would have posted, oldest first; `clear` starts
+/// the list over.
+#[tauri::command]
+pub(crate) fn get_simulated_output(
+    engine: State<MockEngine>,
+    clear: Option<bool>,
+) -> Vec<PostedInput> {
+    engine.posted(clear.unwrap_or(false))
+}
+
+#[cfg(all(test, feature = "mock-engine"))]
+mod tests {
+    use super::*;
+
+    /// Stands in for the app, keeping the names of the events emitted.
+    #[derive(Clone, Default)]
+    struct RecordedHost {
+        emitted: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl RemapHost for RecordedHost {
+        fn emit_event<S: Serialize + Clone>(
+            &self,
+            _target: Option<&str>,
+            event: &str,
+            _payload: S,
+        ) -> tauri::Result<()> {
+            self.emitted.lock().unwrap().push(event.to_string());
+            Ok(())
+        }
+
+        fn app(&self) -> Option<&tauri::AppHandle> {
+            None
+        }
+    }
+
+    const SETTINGS: &str = r#"{
+        "selected_device": "046d:c08b:A",
+        "active_profile": "Work",
+        "profiles": {
+            "Work": {"devices": {"046d:c08b:A": {
+                "buttons": {"button4": "Back", "button5": "Forward", "middle": "Back"},
+                "app_overrides": {"com.figma.Desktop": {"button4": "Forward"}},
+                "modifier_mappings": {"button5": [{"modifiers": ["shift"], "action": "Back"}]},
+                "triggers": {"middle": "release"}
+            }}},
+            "Games": {"devices": {"046d:c08b:A": {"buttons": {"button4": "Forward"}}}}
+        }
+    }"#;
+
+    /// A running engine for the selected device of `SETTINGS`.
+    fn engine() -> (MockEngine, AppState) {
+        let state = AppState::default();
+        state.update_settings(parse_settings(SETTINGS).unwrap());
+        state.update_devices(&[MouseDevice {
+            id: "046d:c08b:A".to_string(),
+            name: "G502".to_string(),
+            kind: DeviceKind::Mouse,
+            button_count: 5,
+            preset: None,
+            first_seen: None,
+            ambiguous: false,
+            disabled: false,
+            ignored: false,
+        }]);
+        let engine = MockEngine::default();
+        engine.start(RecordedHost::default(), state.clone());
+        (engine, state)
+    }
+
+    /// Presses and releases `button` with `held`, returning whether each
+    /// was swallowed.
+    fn click(engine: &MockEngine, button: i64, held: ModifierMask) -> (bool, bool) {
+        let press = engine.simulate(button, true, held).unwrap().recv().unwrap();
+        let release = engine
+            .simulate(button, false, held)
+            .unwrap()
+            .recv()
+            .unwrap();
+        (press, release)
+    }
+
+    fn navigation(forward: bool) -> PostedInput {
+        PostedInput::Navigation {
+            forward,
+            modifiers: 0,
+        }
+    }
+
+    #[test]
+    fn presses_post_the_active_profiles_mapping() {
+        let (engine, state) = engine();
+        assert_eq!(click(&engine, 3, 0), (true, true));
+        assert_eq!(engine.posted(true), [navigation(false)]);
+
+        let mut settings = state.snapshot_settings();
+        settings.active_profile = "Games".to_string();
+        state.update_settings(settings);
+        state.refresh_mappings();
+        click(&engine, 3, 0);
+        assert_eq!(engine.posted(true), [navigation(true)]);
+    }
+
+    #[test]
+    fn a_new_selection_is_remapped_as_soon_as_it_is_saved() {
+        let (engine, state) = engine();
+        let previous = state.snapshot_settings();
+        let mut settings = previous.clone();
+        settings
+            .set_button_action(
+                "046d:c08b:NEW",
+                "button4",
+                ActionValue::Action(Action::Forward),
+            )
+            .unwrap();
+        settings.selected_device = Some("046d:c08b:NEW".to_string());
+        // Plugged in after the last poll, so only the save lists it.
+        let plugged = MouseDevice {
+            id: "046d:c08b:NEW".to_string(),
+            ..state.listed_devices().0[0].clone()
+        };
+        assert!(state.take_settings(&previous, &settings, || Some(vec![plugged])));
+        click(&engine, 3, 0);
+        assert_eq!(engine.posted(false), [navigation(true)]);
+    }
+
+    #[test]
+    fn the_frontmost_apps_override_wins() {
+        let (engine, state) = engine();
+        let figma = frontmost::AppInfo {
+            bundle_id: "com.figma.Desktop".to_string(),
+            name: "Figma".to_string(),
+            path: None,
+            icon_path: None,
+        };
+        state.set_frontmost_app(Some(figma), false);
+        click(&engine, 3, 0);
+        state.set_frontmost_app(None, false);
+        click(&engine, 3, 0);
+        assert_eq!(engine.posted(false), [navigation(true), navigation(false)]);
+    }
+
+    #[test]
+    fn held_modifiers_pick_a_modifier_mapping() {
+        let (engine, _) = engine();
+        click(&engine, 4, modifier_mask(&[Modifier::Shift]));
+        click(&engine, 4, 0);
+        assert_eq!(engine.posted(false), [navigation(false), navigation(true)]);
+    }
+
+    #[test]
+    fn release_triggers_fire_as_the_button_comes_up() {
+        let (engine, _) = engine();
+        assert!(engine.simulate(2, true, 0).unwrap().recv().unwrap());
+        assert!(engine.posted(false).is_empty());
+        assert!(engine.simulate(2, false, 0).unwrap().recv().unwrap());
+        assert_eq!(engine.posted(false), [navigation(false)]);
+
+        // A press trigger has fired before its release.
+        assert!(engine.simulate(3, true, 0).unwrap().recv().unwrap());
+        assert_eq!(engine.posted(true), [navigation(false), navigation(false)]);
+        assert!(engine.simulate(3, false, 0).unwrap().recv().unwrap());
+        assert!(engine.posted(false).is_empty());
+    }
+}
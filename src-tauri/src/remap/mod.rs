@@ -0,0 +1,2633 @@
+use super::*;
+
+/// XInput2 grabs under X11, and evdev without a display server.
+#[cfg(target_os = "linux")]
+mod linux;
+/// The HID event tap.
+#[cfg(target_os = "macos")]
+mod macos;
+/// A remap engine fed by `simulate_button_event` rather than a device, for
+/// working on the remapping on machines where no platform engine can run.
+/// Built with the `mock-engine` feature, it runs next to the platform's
+/// engine and keeps what it would have posted for `get_simulated_output`
+/// instead of posting it.
+#[cfg(feature = "mock-engine")]
+pub(crate) mod mock_engine;
+/// The low-level mouse hook.
+#[cfg(windows)]
+mod windows;
+
+#[cfg(target_os = "linux")]
+pub(crate) use linux::*;
+#[cfg(target_os = "macos")]
+pub(crate) use macos::*;
+#[cfg(windows)]
+pub(crate) use windows::*;
+
+/// Number of mappable buttons, one slot per `BUTTON_KEYS` entry.
+pub(crate) const BUTTON_SLOTS: usize = BUTTON_KEYS.len();
+
+/// How one button maps right now.
+#[derive(Debug, Default, Clone)]
+pub(crate) struct ResolvedButton {
+    /// Modifier mappings in evaluation order.
+    pub(crate) layers: Vec<(ModifierMask, ResolvedAction)>,
+    /// Mapping while no modifier is held.
+    pub(crate) plain: Option<ResolvedAction>,
+    /// `DeviceConfig::merge_modifiers` lists this button.
+    pub(crate) merge_modifiers: bool,
+    /// `DeviceConfig::strip_modifiers` lists this button.
+    pub(crate) strip_modifiers: bool,
+    /// From `DeviceConfig::feedback`.
+    pub(crate) feedback: Feedback,
+    /// From `DeviceConfig::double_click_interval_ms`, capped at
+    /// `MAX_DOUBLE_CLICK_INTERVAL_MS`.
+    pub(crate) double_click_interval_ms: Option<u32>,
+    /// From `DeviceConfig::debounce_ms`, capped at `MAX_DEBOUNCE_MS`; 0 is
+    /// off.
+    pub(crate) debounce_ms: u32,
+    /// `DeviceConfig::repeat_while_held` lists this button.
+    pub(crate) repeat_while_held: bool,
+}
+
+impl ResolvedButton {
+    /// Whether a press of this button can do anything but pass through.
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+    pub(crate) fn is_active(&self) -> bool {
+        self.debounce_ms > 0
+            || self
+                .actions()
+                .any(|action| action != ResolvedAction::Action(Action::Default))
+    }
+
+    /// The plain mapping and every layer's.
+    fn actions(&self) -> impl Iterator<Item = ResolvedAction> + '_ {
+        self.plain
+            .into_iter()
+            .chain(self.layers.iter().map(|(_, action)| *action))
+    }
+}
+
+/// The selected device's mappings in the active profile for the frontmost
+/// app, resolved whenever settings, devices, or the frontmost app change so
+/// the event callback only loads a pointer and indexes by button number.
+#[derive(Debug, Default)]
+pub(crate) struct ResolvedMappings {
+    /// Selected device, when connected and remapping isn't suspended.
+    pub(crate) device_id: Option<String>,
+    pub(crate) buttons: [ResolvedButton; BUTTON_SLOTS],
+    /// From `DeviceConfig::swap_primary_buttons`.
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+    pub(crate) swap_primary_buttons: bool,
+    /// From `DeviceConfig::disable_scroll_inertia`.
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+    pub(crate) disable_scroll_inertia: bool,
+    /// `Settings::tab_keys_for` the frontmost app.
+    pub(crate) tab_keys: TabKeys,
+    /// Whether `Settings::quick_look_apps` lists the frontmost app.
+    pub(crate) quick_look: bool,
+}
+
+/// What the mappings in effect depend on besides the settings.
+#[derive(Debug, Default, Clone, Copy)]
+pub(crate) struct MappingContext<'a> {
+    /// Whether the selected device, or `fallback`, is connected.
+    pub(crate) device_available: bool,
+    /// Connected device remapped while the selected one is away; see
+    /// `fallback_device`.
+    pub(crate) fallback: Option<&'a str>,
+    /// Remapping is off for the frontmost app, as in Game Mode.
+    pub(crate) suspended: bool,
+    /// A device in `Settings::ignored_devices` is connected; see
+    /// `Settings::leaves_untouched`.
+    pub(crate) ignored_connected: bool,
+    /// Buttons of the selected device.
+    pub(crate) button_count: u8,
+    /// Bundle id of the frontmost app.
+    pub(crate) app: Option<&'a str>,
+}
+
+impl ResolvedMappings {
+    /// The selected device's mappings `settings` put in effect in
+    /// `context`, or its fallback's: none while it's left untouched, isn't
+    /// connected, or remapping is suspended.
+    pub(crate) fn resolve(settings: &Settings, context: &MappingContext) -> Self {
+        let resolved = context
+            .fallback
+            .or(settings.selected_device.as_deref())
+            .filter(|id| !settings.leaves_untouched(id, context.ignored_connected))
+            .filter(|_| context.device_available && !context.suspended)
+            .and_then(|id| {
+                let config = match context.fallback {
+                    Some(_) => settings.fallback_config(id)?,
+                    None => settings.device_config(id)?,
+                };
+                Some(Self::build(config, id, context.button_count, context.app))
+            })
+            .unwrap_or_default();
+        Self {
+            tab_keys: settings.tab_keys_for(context.app),
+            quick_look: context
+                .app
+                .is_some_and(|app| settings.quick_look_apps.iter().any(|listed| listed == app)),
+            ..resolved
+        }
+    }
+
+    pub(crate) fn build(
+        config: &DeviceConfig,
+        device_id: &str,
+        button_count: u8,
+        app: Option<&str>,
+    ) -> Self {
+        let mut resolved = Self {
+            device_id: Some(device_id.to_string()),
+            swap_primary_buttons: config.swap_primary_buttons,
+            disable_scroll_inertia: config.disable_scroll_inertia,
+            ..Self::default()
+        };
+        if config.disabled {
+            for slot in resolved.buttons.iter_mut().take(usize::from(button_count)) {
+                slot.plain = Some(ResolvedAction::Action(Action::Disabled));
+            }
+            return resolved;
+        }
+        for (button, slot) in (0..).zip(resolved.buttons.iter_mut()) {
+            slot.plain = config
+                .mapping(button, button_count, app, 0)
+                .map(ResolvedAction::from);
+            if button >= i64::from(button_count) {
+                continue;
+            }
+            let trigger = button_key(button)
+                .and_then(|key| config.triggers.get(key))
+                .copied()
+                .unwrap_or_default();
+            slot.plain = slot.plain.map(|action| action.triggered(trigger));
+            slot.merge_modifiers = button_key(button)
+                .is_some_and(|key| config.merge_modifiers.iter().any(|merged| merged == key));
+            slot.strip_modifiers = button_key(button).is_some_and(|key| {
+                config
+                    .strip_modifiers
+                    .iter()
+                    .any(|stripped| stripped == key)
+            });
+            slot.repeat_while_held = button_key(button)
+                .is_some_and(|key| config.repeat_while_held.iter().any(|listed| listed == key));
+            slot.feedback = button_key(button)
+                .and_then(|key| config.feedback.get(key))
+                .copied()
+                .unwrap_or_default();
+            slot.double_click_interval_ms = button_key(button)
+                .and_then(|key| config.double_click_interval_ms.get(key))
+                .map(|interval| (*interval).min(MAX_DOUBLE_CLICK_INTERVAL_MS));
+            slot.debounce_ms = button_key(button)
+                .and_then(|key| config.debounce_ms.get(key))
+                .map_or(0, |window| (*window).min(MAX_DEBOUNCE_MS));
+            if let Some(mappings) =
+                button_key(button).and_then(|key| config.modifier_mappings.get(key))
+            {
+                slot.layers = mappings
+                    .iter()
+                    .map(|mapping| {
+                        (
+                            modifier_mask(&mapping.modifiers),
+                            ResolvedAction::from(&mapping.action).triggered(trigger),
+                        )
+                    })
+                    .collect();
+            }
+        }
+        resolved
+    }
+
+    /// The mapping `button` fires with `held` down. Quick Look outside
+    /// `Settings::quick_look_apps` is left to the button's own function.
+    pub(crate) fn mapping(&self, button: i64, held: ModifierMask) -> Option<ResolvedAction> {
+        let slot = self.buttons.get(usize::try_from(button).ok()?)?;
+        let layer = (held != 0)
+            .then(|| slot.layers.iter().find(|(mask, _)| *mask == held))
+            .flatten();
+        let mapping = layer.map_or(slot.plain, |(_, action)| Some(*action));
+        mapping.filter(|mapping| self.quick_look || mapping.action() != Action::QuickLook)
+    }
+
+    /// What the left button does in place of a click while no modifier is
+    /// held, if anything.
+    pub(crate) fn primary_remap(&self) -> Option<ResolvedAction> {
+        self.buttons[0]
+            .plain
+            .filter(|action| *action != ResolvedAction::Action(Action::Default))
+    }
+
+    /// Lets the left button click again while its mapping is still
+    /// `escaped`, the one it had when held through `PRIMARY_ESCAPE_HOLD`.
+    /// Any other mapping means the settings changed since, so the escape
+    /// is forgotten.
+    pub(crate) fn escape_primary(&mut self, escaped: &mut Option<ResolvedAction>) {
+        let Some(remap) = self.primary_remap() else {
+            return;
+        };
+        if *escaped == Some(remap) {
+            self.buttons[0].plain = None;
+        } else {
+            *escaped = None;
+        }
+    }
+
+    /// Modifiers a key combo fired by `button` is posted with on top of its
+    /// own while `held` are down: all of them when the button merges
+    /// modifiers, none when it doesn't or when they selected a modifier
+    /// mapping instead.
+    pub(crate) fn merged_modifiers(&self, button: i64, held: ModifierMask) -> ModifierMask {
+        let Some(slot) = usize::try_from(button)
+            .ok()
+            .and_then(|index| self.buttons.get(index))
+        else {
+            return 0;
+        };
+        if !slot.merge_modifiers || slot.layers.iter().any(|(mask, _)| *mask == held) {
+            return 0;
+        }
+        held
+    }
+
+    /// Modifiers a click fired by `button` is posted with while `held` are
+    /// down: all of them, unless the button strips modifiers or they
+    /// selected a modifier mapping.
+    pub(crate) fn click_modifiers(&self, button: i64, held: ModifierMask) -> ModifierMask {
+        let Some(slot) = usize::try_from(button)
+            .ok()
+            .and_then(|index| self.buttons.get(index))
+        else {
+            return held;
+        };
+        if slot.strip_modifiers || slot.layers.iter().any(|(mask, _)| *mask == held) {
+            return 0;
+        }
+        held
+    }
+
+    /// Whether `action`, fired by `button`, repeats while the button is held.
+    pub(crate) fn repeats_while_held(&self, button: i64, action: Action) -> bool {
+        let listed = usize::try_from(button)
+            .ok()
+            .and_then(|index| self.buttons.get(index))
+            .is_some_and(|slot| slot.repeat_while_held);
+        action.repeats_while_held() || (listed && action.posts_keys())
+    }
+
+    pub(crate) fn feedback(&self, button: i64) -> Feedback {
+        usize::try_from(button)
+            .ok()
+            .and_then(|index| self.buttons.get(index))
+            .map_or(Feedback::None, |slot| slot.feedback)
+    }
+
+    pub(crate) fn double_click_interval_ms(&self, button: i64) -> Option<u32> {
+        usize::try_from(button)
+            .ok()
+            .and_then(|index| self.buttons.get(index))
+            .and_then(|slot| slot.double_click_interval_ms)
+    }
+
+    pub(crate) fn debounce(&self, button: i64) -> Duration {
+        usize::try_from(button)
+            .ok()
+            .and_then(|index| self.buttons.get(index))
+            .map_or(Duration::ZERO, |slot| {
+                Duration::from_millis(slot.debounce_ms.into())
+            })
+    }
+}
+
+/// How often the extended-buttons listener re-checks settings and retries a
+/// device that was unplugged.
+const EXTENDED_BUTTONS_RETRY: Duration = Duration::from_secs(1);
+
+/// One opened interface of the device the extended-buttons listener reads.
+struct ExtendedButtonsReader {
+    device: hidapi::HidDevice,
+    fields: Vec<ReportField>,
+    /// Held buttons per report id, to turn reports into press/release edges.
+    held: HashMap<u8, Vec<i64>>,
+}
+
+/// Runs the raw HID fallback for buttons the event tap never sees: while the
+/// selected device has `extended_buttons` enabled, its non-pointer reports are
+/// read and button edges are handed to `dispatch(button, down)`.
+pub(crate) fn start_extended_buttons(
+    state: AppState,
+    dispatch: impl Fn(i64, bool) + Send + 'static,
+) {
+    std::thread::spawn(move || loop {
+        if let Some(device_id) = extended_buttons_target(&state) {
+            listen_extended_buttons(&state, &device_id, &dispatch);
+        }
+        std::thread::sleep(EXTENDED_BUTTONS_RETRY);
+    });
+}
+
+pub(crate) fn extended_buttons_target(state: &AppState) -> Option<String> {
+    let settings = state.snapshot_settings();
+    let device_id = settings.selected_device.clone()?;
+    let enabled = settings.device_config(&device_id)?.extended_buttons;
+    (enabled && state.is_selected_device_available(&device_id)).then_some(device_id)
+}
+
+/// Opens each interface of `device_id` that reports extended buttons.
+fn open_extended_buttons(
+    api: &hidapi::HidApi,
+    filter: DeviceFilter,
+    device_id: &str,
+) -> Vec<ExtendedButtonsReader> {
+    let entries = hid_entries(api);
+    let groups = mouse_device_groups(&entries, filter, descriptor_probe(api));
+    let Some(group) = groups.iter().find(|group| group.device.id == device_id) else {
+        return Vec::new();
+    };
+
+    let mut paths: Vec<&str> = Vec::new();
+    let mut readers = Vec::new();
+    for member in &group.members {
+        if paths.contains(&member.path.as_str()) {
+            continue;
+        }
+        paths.push(&member.path);
+        let Some(descriptor) = read_report_descriptor(api, &member.path) else {
+            continue;
+        };
+        let fields = extended_button_fields(&descriptor);
+        if fields.is_empty() {
+            continue;
+        }
+        let Ok(path) = CString::new(member.path.as_str()) else {
+            continue;
+        };
+        match api.open_path(&path) {
+            Ok(device) => readers.push(ExtendedButtonsReader {
+                device,
+                fields,
+                held: HashMap::new(),
+            }),
+            Err(err) => log::error!("extended-buttons: failed to open {}: {}", member.path, err),
+        }
+    }
+    readers
+}
+
+/// Reads the device until it disappears or stops being the target.
+fn listen_extended_buttons(state: &AppState, device_id: &str, dispatch: &impl Fn(i64, bool)) {
+    let filter = DeviceFilter::from(&state.snapshot_settings());
+    let Ok(mut readers) = state
+        .hid
+        .with(|api| Ok(open_extended_buttons(api, filter, device_id)))
+    else {
+        return;
+    };
+    if readers.is_empty() {
+        return;
+    }
+    log::info!(
+        "extended-buttons: listening on {} interface(s) of {}",
+        readers.len(),
+        device_id
+    );
+
+    let mut buf = [0u8; 64];
+    while extended_buttons_target(state).as_deref() == Some(device_id) {
+        for reader in &mut readers {
+            let len = match reader.device.read_timeout(&mut buf, 50) {
+                Ok(len) => len,
+                Err(err) => {
+                    log::warn!("extended-buttons: {} disconnected: {}", device_id, err);
+                    return;
+                }
+            };
+            let Some((report_id, pressed)) = report_buttons(&reader.fields, &buf[..len]) else {
+                continue;
+            };
+            state.note_input();
+            let held = reader.held.entry(report_id).or_default();
+            for button in &pressed {
+                if !held.contains(button) {
+                    dispatch(*button, true);
+                }
+            }
+            for button in held.iter() {
+                if !pressed.contains(button) {
+                    dispatch(*button, false);
+                }
+            }
+            *held = pressed;
+        }
+    }
+}
+
+/// Keeps a platform's remap engine running: `run` installs it and drives it
+/// on a fresh thread named `thread` until it stops, and is retried with
+/// backoff when it fails or restarted on request. While nothing wants the
+/// engine it stays uninstalled.
+pub(crate) fn supervise_engine(
+    app: tauri::AppHandle,
+    state: AppState,
+    thread: &'static str,
+    run: fn(&tauri::AppHandle, &AppState) -> EngineState,
+) {
+    std::thread::spawn(move || {
+        let mut backoff = ENGINE_RETRY_MIN;
+        report_engine_state(&app, &state, EngineState::Starting);
+        loop {
+            if !state.is_event_tap_wanted() {
+                report_engine_state(&app, &state, EngineState::Idle);
+                state.wait_for_event_tap();
+                backoff = ENGINE_RETRY_MIN;
+                report_engine_state(&app, &state, EngineState::Starting);
+                continue;
+            }
+            // Each run gets its own thread, which has exited, run loop and
+            // all, by the time `join` returns.
+            let run_app = app.clone();
+            let run_state = state.clone();
+            let stopped = std::thread::Builder::new()
+                .name(thread.to_string())
+                .spawn(move || run(&run_app, &run_state))
+                .map_or(EngineState::Failed, |run| {
+                    run.join().unwrap_or_else(|_| {
+                        let message = format!("the {} thread panicked", thread);
+                        report_remap_error(&app, &state, "engine-panicked", message);
+                        EngineState::Failed
+                    })
+                });
+            if state.engine_state() == EngineState::Running {
+                backoff = ENGINE_RETRY_MIN;
+            }
+            // Idle is reported once the loop sees nothing wants the engine;
+            // Starting means a restart was requested while it ran.
+            if matches!(stopped, EngineState::Idle | EngineState::Starting) {
+                if stopped == EngineState::Starting {
+                    report_engine_state(&app, &state, stopped);
+                }
+                continue;
+            }
+            report_engine_state(&app, &state, stopped);
+            if state.wait_for_engine_restart(backoff) {
+                backoff = ENGINE_RETRY_MIN;
+                report_engine_state(&app, &state, EngineState::Starting);
+            } else {
+                backoff = (backoff * 2).min(ENGINE_RETRY_MAX);
+            }
+        }
+    });
+}
+
+/// Posts the input mapped actions turn into, for one platform's engine.
+/// Everything posted carries `SYNTHETIC_EVENT_TAG` so the engine lets it
+/// through.
+pub(crate) trait RemapOutput {
+    /// Navigates back or forward with the `merged` modifiers held.
+    fn post_navigation(&self, forward: bool, merged: ModifierMask) -> Result<(), String>;
+
+    /// Switches to the next or previous tab with `keys`, where the platform
+    /// tells applications apart, and the `merged` modifiers held.
+    fn post_tab_switch(
+        &self,
+        next: bool,
+        keys: TabKeys,
+        merged: ModifierMask,
+    ) -> Result<(), String>;
+
+    /// Taps the key with macOS virtual keycode `keycode` with `modifiers`
+    /// held.
+    fn post_shortcut(&self, keycode: u16, _modifiers: ModifierMask) -> Result<(), String> {
+        Err(format!(
+            "keyboard shortcuts are only supported on macOS (keycode {})",
+            keycode
+        ))
+    }
+
+    /// Looks up the word under the pointer, as a Force click does, with
+    /// the `merged` modifiers held.
+    fn post_look_up(&self, _merged: ModifierMask) -> Result<(), String> {
+        Err("Look Up is only supported on macOS".to_string())
+    }
+
+    /// Swipes to the next or previous page or space, as `swipe_strategy`
+    /// says. Keys go with the `merged` modifiers held; gestures without.
+    fn post_swipe(&self, swipe: Swipe, forward: bool, merged: ModifierMask) -> Result<(), String> {
+        match swipe {
+            Swipe::Pages => self.post_navigation(forward, merged),
+            Swipe::Spaces => Err("switching spaces isn't supported here".to_string()),
+        }
+    }
+
+    /// Presses or releases `button` with `modifiers` held; `click_state`
+    /// numbers the clicks of a multi-click.
+    fn post_mouse_event(
+        &self,
+        button: i64,
+        down: bool,
+        click_state: i64,
+        modifiers: ModifierMask,
+    ) -> Result<(), String>;
+
+    /// An output posting the same way from another thread, for the
+    /// clicks of a sequence that wait out its interval. None keeps them on
+    /// this thread, waiting there.
+    fn detached(&self) -> Option<Box<dyn RemapOutput + Send>> {
+        None
+    }
+
+    /// Posts the first click of `clicks` right away and the rest after
+    /// their interval, off the event path when the output can be detached.
+    fn post_mouse_click(&self, clicks: ClickSequence) -> Result<(), String> {
+        fn post_paced(
+            output: &(impl RemapOutput + ?Sized),
+            clicks: ClickSequence,
+            events: impl Iterator<Item = (i64, bool)>,
+        ) -> Result<(), String> {
+            for (click_state, down) in events {
+                if down {
+                    std::thread::sleep(clicks.interval);
+                }
+                output.post_mouse_event(clicks.button, down, click_state, clicks.modifiers)?;
+            }
+            Ok(())
+        }
+
+        let mut events = clicks.events();
+        for (click_state, down) in events.by_ref().take(2) {
+            self.post_mouse_event(clicks.button, down, click_state, clicks.modifiers)?;
+        }
+        if clicks.count < 2 || clicks.interval.is_zero() {
+            return post_paced(self, clicks, events);
+        }
+        let Some(output) = self.detached() else {
+            return post_paced(self, clicks, events);
+        };
+        run_deferred(Box::new(move || {
+            if let Err(err) = post_paced(&*output, clicks, events) {
+                log::error!("mouse-remap: failed to finish a click sequence: {}", err);
+            }
+        }));
+        Ok(())
+    }
+}
+
+/// Error of a post `Governed` dropped; see `report_post_error`.
+const OUTPUT_THROTTLED: &str = "synthetic output is over its limit";
+
+/// The output `output` points to, with each press and key tap taken from
+/// `OutputGovernor` first. A dropped one fails with `OUTPUT_THROTTLED`,
+/// which ends the action it belonged to. Releases always go through, so
+/// nothing is left held.
+pub(crate) struct Governed<P> {
+    output: P,
+    governor: Arc<OutputGovernor>,
+}
+
+impl<P> Governed<P> {
+    pub(crate) fn new(state: &AppState, output: P) -> Self {
+        Self {
+            output,
+            governor: Arc::clone(&state.output_governor),
+        }
+    }
+
+    fn admit(&self) -> Result<(), String> {
+        if self.governor.admit(Instant::now()) {
+            Ok(())
+        } else {
+            Err(OUTPUT_THROTTLED.to_string())
+        }
+    }
+}
+
+impl<P: std::ops::Deref> RemapOutput for Governed<P>
+where
+    P::Target: RemapOutput,
+{
+    fn post_navigation(&self, forward: bool, merged: ModifierMask) -> Result<(), String> {
+        self.admit()?;
+        self.output.post_navigation(forward, merged)
+    }
+
+    fn post_tab_switch(
+        &self,
+        next: bool,
+        keys: TabKeys,
+        merged: ModifierMask,
+    ) -> Result<(), String> {
+        self.admit()?;
+        self.output.post_tab_switch(next, keys, merged)
+    }
+
+    fn post_shortcut(&self, keycode: u16, modifiers: ModifierMask) -> Result<(), String> {
+        self.admit()?;
+        self.output.post_shortcut(keycode, modifiers)
+    }
+
+    fn post_look_up(&self, merged: ModifierMask) -> Result<(), String> {
+        self.admit()?;
+        self.output.post_look_up(merged)
+    }
+
+    fn post_swipe(&self, swipe: Swipe, forward: bool, merged: ModifierMask) -> Result<(), String> {
+        self.admit()?;
+        self.output.post_swipe(swipe, forward, merged)
+    }
+
+    fn post_mouse_event(
+        &self,
+        button: i64,
+        down: bool,
+        click_state: i64,
+        modifiers: ModifierMask,
+    ) -> Result<(), String> {
+        if down {
+            self.admit()?;
+        }
+        self.output
+            .post_mouse_event(button, down, click_state, modifiers)
+    }
+
+    fn detached(&self) -> Option<Box<dyn RemapOutput + Send>> {
+        Some(Box::new(Governed {
+            output: self.output.detached()?,
+            governor: Arc::clone(&self.governor),
+        }))
+    }
+}
+
+/// Work the event path hands off instead of waiting on.
+type DeferredJob = Box<dyn FnOnce() + Send>;
+
+/// Runs `job` on the deferred-work thread, started with the first job,
+/// after the jobs handed over before it.
+pub(crate) fn run_deferred(job: DeferredJob) {
+    static WORKER: std::sync::OnceLock<std::sync::mpsc::Sender<DeferredJob>> =
+        std::sync::OnceLock::new();
+    let worker = WORKER.get_or_init(|| {
+        let (sender, receiver) = std::sync::mpsc::channel::<DeferredJob>();
+        std::thread::spawn(move || {
+            for job in receiver {
+                job();
+            }
+        });
+        sender
+    });
+    if let Err(std::sync::mpsc::SendError(job)) = worker.send(job) {
+        job();
+    }
+}
+
+/// What the event path reports to, and hands actions that change more than
+/// what's posted to. The app, or a stand-in for driving a mock engine
+/// without one.
+pub(crate) trait RemapHost: Clone + Send + 'static {
+    /// Sends `event` to every window, or only to `target`.
+    fn emit_event<S: Serialize + Clone>(
+        &self,
+        target: Option<&str>,
+        event: &str,
+        payload: S,
+    ) -> tauri::Result<()>;
+
+    /// The app, for profile switches, DPI changes and the like; without
+    /// one they're left undone.
+    fn app(&self) -> Option<&tauri::AppHandle>;
+}
+
+impl RemapHost for tauri::AppHandle {
+    fn emit_event<S: Serialize + Clone>(
+        &self,
+        target: Option<&str>,
+        event: &str,
+        payload: S,
+    ) -> tauri::Result<()> {
+        match target {
+            Some(target) => self.emit_to(target, event, payload),
+            None => self.emit(event, payload),
+        }
+    }
+
+    fn app(&self) -> Option<&tauri::AppHandle> {
+        Some(self)
+    }
+}
+
+/// Runs a button event through capture, monitoring, and the mapped action.
+/// Returns true when the original event should be swallowed.
+pub(crate) fn dispatch_button(
+    host: &impl RemapHost,
+    state: &AppState,
+    output: &impl RemapOutput,
+    button: i64,
+    down: bool,
+    held: ModifierMask,
+) -> bool {
+    if state.is_monitor_only() {
+        report_monitored_button(host, state, button, down, held);
+        return false;
+    }
+    dispatch_governed(
+        host,
+        state,
+        &Governed::new(state, output),
+        button,
+        down,
+        held,
+    )
+}
+
+/// `dispatch_button`, posting through `output` as it's given.
+fn dispatch_governed(
+    host: &impl RemapHost,
+    state: &AppState,
+    output: &impl RemapOutput,
+    button: i64,
+    down: bool,
+    held: ModifierMask,
+) -> bool {
+    let mappings = state.resolved_mappings();
+    if state.is_bounce(&mappings, button, down, Instant::now()) {
+        if down {
+            state.record_bounce(&mappings, button);
+        }
+        return true;
+    }
+    match state.capture_button(button, down) {
+        CaptureOutcome::Pass => {}
+        CaptureOutcome::Captured { device_id } => {
+            let payload = ButtonCaptured {
+                device_id,
+                key: button_key(button),
+                button,
+            };
+            if let Err(err) = host.emit_event(None, "button-captured", payload) {
+                log::error!("mouse-remap: failed to emit button-captured: {}", err);
+            }
+            return true;
+        }
+        CaptureOutcome::Suppress => return true,
+    }
+    if button == 0 {
+        if let Some(remap) = state.track_primary_hold(&mappings, down, Instant::now()) {
+            state.refresh_mappings();
+            announce_primary_restored(host, state, &mappings, remap);
+        }
+    }
+    let mapping = if down {
+        // A release follows its press, so a button held into or out of the
+        // window is finished the way it started.
+        let mapping = if state.is_own_window_guarded() {
+            None
+        } else {
+            mappings.mapping(button, held)
+        };
+        state.hold_mapping(button, mapping);
+        mapping
+    } else {
+        // A release whose press was never handled doesn't fire the action
+        // a press would have left pending.
+        let mapping = state.release_mapping(button).unwrap_or_else(|| {
+            mappings
+                .mapping(button, held)
+                .filter(|mapping| !matches!(mapping, ResolvedAction::OnRelease(_)))
+        });
+        if let Some(action) = state.finish_gesture(button) {
+            emit_button_event(host, state, button, down, action.name());
+            state.record_use(&mappings, button, action);
+            let modifiers = ActionModifiers::of(&mappings, button, held);
+            let interval = state.double_click_interval(&mappings, Some(button));
+            perform_action(host, state, output, action, modifiers, interval);
+            state.give_feedback(&mappings, button);
+            return true;
+        }
+        mapping
+    };
+    if let Some(ResolvedAction::Gesture(gesture)) = mapping {
+        if down {
+            state.begin_gesture(button, gesture);
+            emit_button_event(host, state, button, down, "Gesture");
+        }
+        return true;
+    }
+    if let Some(ResolvedAction::OnRelease(action)) = mapping {
+        emit_button_event(host, state, button, down, action.name());
+        if !down {
+            state.record_use(&mappings, button, action);
+            let modifiers = ActionModifiers::of(&mappings, button, held);
+            let interval = state.double_click_interval(&mappings, Some(button));
+            perform_action(host, state, output, action, modifiers, interval);
+            state.give_feedback(&mappings, button);
+        }
+        return true;
+    }
+    let action = mapping.map_or(Action::Default, ResolvedAction::action);
+    emit_button_event(host, state, button, down, action.name());
+
+    if action == Action::Default {
+        return false;
+    }
+    if action == Action::MiddleClick {
+        // Held rather than clicked, so the button can drag.
+        let modifiers = mappings.click_modifiers(button, held);
+        let posted = if down && state.begin_middle_drag(button) {
+            state.record_use(&mappings, button, action);
+            let posted = output.post_mouse_event(2, true, 1, modifiers);
+            state.give_feedback(&mappings, button);
+            posted
+        } else if !down && state.end_middle_drag(Some(button)) {
+            output.post_mouse_event(2, false, 1, modifiers)
+        } else {
+            Ok(())
+        };
+        if let Err(message) = posted {
+            report_post_error(host, state, message);
+        }
+        return true;
+    }
+    if down {
+        state.record_use(&mappings, button, action);
+        let modifiers = ActionModifiers::of(&mappings, button, held);
+        let interval = state.double_click_interval(&mappings, Some(button));
+        perform_action(host, state, output, action, modifiers, interval);
+        state.give_feedback(&mappings, button);
+        if mappings.repeats_while_held(button, action) {
+            repeat_while_held(host, state, output, button, action, modifiers);
+        }
+    } else {
+        state.end_key_repeat(button);
+    }
+    true
+}
+
+fn emit_button_event(
+    host: &impl RemapHost,
+    state: &AppState,
+    button: i64,
+    down: bool,
+    action: &'static str,
+) {
+    let monitored = state.should_emit_button_event();
+    let overlaid = state.should_emit_overlay_event(down);
+    if !monitored && !overlaid {
+        return;
+    }
+    let payload = ButtonEvent {
+        device_id: state.resolved_mappings().device_id.clone(),
+        key: button_key(button),
+        button,
+        event_type: if down { "down" } else { "up" },
+        action,
+        monitor_only: state.is_monitor_only(),
+    };
+    if monitored {
+        if let Err(err) = host.emit_event(None, "button-event", &payload) {
+            log::error!("mouse-remap: failed to emit button-event: {}", err);
+        }
+    }
+    if overlaid {
+        if let Err(err) =
+            host.emit_event(Some(INPUT_OVERLAY_WINDOW), "input-overlay-event", &payload)
+        {
+            log::error!("mouse-remap: failed to emit input-overlay-event: {}", err);
+        }
+    }
+}
+
+/// Reports the action a button event would fire in monitor mode, without
+/// firing it or keeping anything about the press.
+fn report_monitored_button(
+    host: &impl RemapHost,
+    state: &AppState,
+    button: i64,
+    down: bool,
+    held: ModifierMask,
+) {
+    let action = match state.resolved_mappings().mapping(button, held) {
+        Some(ResolvedAction::Gesture(_)) => "Gesture",
+        mapping => mapping
+            .map_or(Action::Default, ResolvedAction::action)
+            .name(),
+    };
+    emit_button_event(host, state, button, down, action);
+}
+
+/// Tells the user the left button clicks again after being held through
+/// `PRIMARY_ESCAPE_HOLD`, and how to have its mapping back.
+fn announce_primary_restored(
+    host: &impl RemapHost,
+    state: &AppState,
+    mappings: &ResolvedMappings,
+    remap: ResolvedAction,
+) {
+    let action = match remap {
+        ResolvedAction::Gesture(_) => "Gesture",
+        ResolvedAction::Action(action) | ResolvedAction::OnRelease(action) => action.name(),
+    };
+    log::warn!(
+        "mouse-remap: left button held for {}s; it clicks instead of {} until its mapping is chosen again",
+        PRIMARY_ESCAPE_HOLD.as_secs(),
+        action
+    );
+    let locale = Locale::from_setting(&state.snapshot_settings().locale);
+    state.post_notification(locale.text(Text::NoticePrimaryRestored).to_string());
+    let payload = PrimaryButtonRestored {
+        device_id: mappings.device_id.clone(),
+        action,
+    };
+    if let Err(err) = host.emit_event(None, "primary-button-restored", payload) {
+        log::error!(
+            "mouse-remap: failed to emit primary-button-restored: {}",
+            err
+        );
+    }
+}
+
+/// Reports a post that failed, except one `Governed` dropped: those are
+/// counted instead, and only the first of a flood is warned about.
+fn report_post_error(host: &impl RemapHost, state: &AppState, message: String) {
+    if message != OUTPUT_THROTTLED {
+        report_remap_error(host, state, "post-failed", message);
+        return;
+    }
+    let governor = &state.output_governor;
+    if !governor.take_warning() {
+        return;
+    }
+    let payload = OutputThrottled {
+        limit: governor.limit(),
+        dropped: governor.dropped.load(Ordering::Relaxed),
+    };
+    log::warn!(
+        "mouse-remap: synthetic output passed {} events a second; dropping what's over it",
+        payload.limit
+    );
+    if let Err(err) = host.emit_event(None, "synthetic-output-throttled", payload) {
+        log::error!(
+            "mouse-remap: failed to emit synthetic-output-throttled: {}",
+            err
+        );
+    }
+}
+
+/// Modifiers a mapped action is posted with, from what the user holds as
+/// the button fires.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct ActionModifiers {
+    /// Added to key combos; see `ResolvedMappings::merged_modifiers`.
+    pub(crate) merged: ModifierMask,
+    /// Held through clicks; see `ResolvedMappings::click_modifiers`.
+    pub(crate) click: ModifierMask,
+}
+
+impl ActionModifiers {
+    pub(crate) fn of(mappings: &ResolvedMappings, button: i64, held: ModifierMask) -> Self {
+        Self {
+            merged: mappings.merged_modifiers(button, held),
+            click: mappings.click_modifiers(button, held),
+        }
+    }
+}
+
+/// Posts or carries out `action`; a Double Click pauses
+/// `double_click_interval` between its clicks.
+fn perform_action(
+    host: &impl RemapHost,
+    state: &AppState,
+    output: &impl RemapOutput,
+    action: Action,
+    modifiers: ActionModifiers,
+    double_click_interval: Duration,
+) {
+    let ActionModifiers { merged, click } = modifiers;
+    // Keystrokes posted under secure input are dropped or mangled, which
+    // looks like the mapping misfiring.
+    if action.posts_keys() && secure_input::is_enabled() {
+        if let Some(app) = host.app() {
+            watch_secure_input(app, state);
+        }
+        return;
+    }
+    let posted = match action {
+        Action::Disabled | Action::Default => Ok(()),
+        Action::Back => output.post_navigation(false, merged),
+        Action::Forward => output.post_navigation(true, merged),
+        Action::NextTab | Action::PreviousTab => output.post_tab_switch(
+            action == Action::NextTab,
+            state.resolved_mappings().tab_keys,
+            merged,
+        ),
+        Action::ToggleFocus => {
+            toggle_focus(state);
+            Ok(())
+        }
+        // A gesture direction resolves too late to leave the button alone.
+        Action::QuickLook if !state.resolved_mappings().quick_look => Ok(()),
+        Action::QuickLook => output.post_shortcut(KEYCODE_SPACE, merged),
+        Action::LookUp => output.post_look_up(merged),
+        Action::SwipeBack => output.post_swipe(Swipe::Pages, false, merged),
+        Action::SwipeForward => output.post_swipe(Swipe::Pages, true, merged),
+        Action::PreviousSpace => output.post_swipe(Swipe::Spaces, false, merged),
+        Action::NextSpace => output.post_swipe(Swipe::Spaces, true, merged),
+        Action::MiddleClick => output.post_mouse_click(ClickSequence::single(2, click)),
+        Action::DoubleClick => {
+            output.post_mouse_click(ClickSequence::double(0, click, double_click_interval))
+        }
+        Action::CycleDpi | Action::SetDpi { .. } => {
+            if let Some(app) = host.app() {
+                change_dpi(app.clone(), state.clone(), action);
+            }
+            Ok(())
+        }
+        Action::NextProfile | Action::PreviousProfile => {
+            if let Some(app) = host.app().filter(|_| state.should_cycle_profile()) {
+                let name = state
+                    .snapshot_settings()
+                    .adjacent_profile(action == Action::NextProfile);
+                if let Err(err) = switch_profile(app, &name) {
+                    log::error!("profiles: failed to switch to {}: {}", name, err);
+                }
+            }
+            Ok(())
+        }
+        Action::Shortcut {
+            keycode,
+            key,
+            modifiers,
+        } => post_layout_shortcut(output, keycode, key, modifiers | merged),
+    };
+    if let Err(message) = posted {
+        report_post_error(host, state, message);
+    }
+}
+
+/// Taps a shortcut's key as `keyboard_layout::resolve` finds it on the
+/// current layout.
+fn post_layout_shortcut(
+    output: &(impl RemapOutput + ?Sized),
+    keycode: Option<u16>,
+    key: Option<char>,
+    modifiers: ModifierMask,
+) -> Result<(), String> {
+    let (keycode, modifiers) =
+        keyboard_layout::resolve(keycode, key, modifiers).ok_or_else(|| match key {
+            Some(key) => format!("no key types {} on this keyboard layout", key),
+            None => "the shortcut has no key".to_string(),
+        })?;
+    output.post_shortcut(keycode, modifiers)
+}
+
+/// Runs Toggle Focus on a thread of its own, since `shortcuts` takes a
+/// moment, and logs how it went.
+pub(crate) fn toggle_focus(state: &AppState) {
+    let shortcut = state.snapshot_settings().focus_shortcut;
+    std::thread::spawn(move || {
+        let shortcut = match shortcut.trim() {
+            "" => DEFAULT_FOCUS_SHORTCUT,
+            name => name,
+        };
+        match focus::toggle(shortcut) {
+            Ok(done) => log::info!("focus: {}", done),
+            Err(err) => log::error!("focus: {}", err),
+        }
+    });
+}
+
+/// Wait before a held key action starts repeating, and between repeats.
+const KEY_REPEAT_DELAY: Duration = Duration::from_millis(400);
+const KEY_REPEAT_INTERVAL: Duration = Duration::from_millis(150);
+
+/// Most repeats of one hold, in case its release is never seen.
+const KEY_REPEAT_LIMIT: u32 = 100;
+
+/// Repeats the key action `action` fired while `button` stays held, off
+/// the event path. Outputs that can't be detached fire once per press.
+pub(crate) fn repeat_while_held(
+    host: &impl RemapHost,
+    state: &AppState,
+    output: &impl RemapOutput,
+    button: i64,
+    action: Action,
+    modifiers: ActionModifiers,
+) {
+    let Some(output) = output.detached() else {
+        return;
+    };
+    let hold = state.begin_key_repeat(button);
+    let host = host.clone();
+    let state = state.clone();
+    std::thread::spawn(move || {
+        std::thread::sleep(KEY_REPEAT_DELAY);
+        for _ in 0..KEY_REPEAT_LIMIT {
+            if !state.is_key_repeating(hold) || secure_input::is_enabled() {
+                return;
+            }
+            let merged = modifiers.merged;
+            let posted = match action {
+                Action::Back | Action::Forward => {
+                    output.post_navigation(action == Action::Forward, merged)
+                }
+                Action::NextTab | Action::PreviousTab => output.post_tab_switch(
+                    action == Action::NextTab,
+                    state.resolved_mappings().tab_keys,
+                    merged,
+                ),
+                Action::Shortcut {
+                    keycode,
+                    key,
+                    modifiers,
+                } => post_layout_shortcut(&*output, keycode, key, modifiers | merged),
+                _ => return,
+            };
+            if let Err(message) = posted {
+                report_post_error(&host, &state, message);
+                return;
+            }
+            std::thread::sleep(KEY_REPEAT_INTERVAL);
+        }
+    });
+}
+
+/// What `test_action` runs: a press and release of a button, or one action.
+/// `test_click_at` runs a middle click at a point in global display
+/// coordinates.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum ActionTest {
+    Button(i64),
+    Action(Action),
+    ClickAt { x: f64, y: f64 },
+}
+
+/// Builds a platform's output and runs a test through `run_action_test`.
+pub(crate) type ActionTester = fn(&tauri::AppHandle, &AppState, ActionTest);
+
+/// Reply of `test_action` and `test_action_payload`.
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub(crate) struct ActionTested {
+    /// Device whose mappings the button resolved through.
+    pub(crate) device_id: Option<String>,
+    /// Settings key of the pressed button.
+    pub(crate) button: Option<&'static str>,
+    /// Name of the action that ran, as in the `button-event` log.
+    pub(crate) action: &'static str,
+    /// Frontmost app the mappings were resolved for.
+    pub(crate) app: Option<String>,
+}
+
+/// Works out what `test` runs right now without running it. A button
+/// resolves the way an unmodified press would, a gesture to its tap action.
+/// Refuses while button events would pass through or be learned, and for
+/// actions needing `confirm`.
+pub(crate) fn plan_action_test(
+    state: &AppState,
+    device_id: Option<&str>,
+    test: ActionTest,
+    confirm: bool,
+) -> Result<ActionTested, String> {
+    if state.is_passing_through() {
+        return Err("remapping is paused, so nothing would run".to_string());
+    }
+    if state.is_capture_armed() {
+        return Err("a button is being learned; finish that first".to_string());
+    }
+    let mappings = state.resolved_mappings();
+    let (button, action) = match test {
+        ActionTest::Button(button) => {
+            if mappings.device_id.as_deref() != device_id {
+                return Err(format!(
+                    "{} isn't the selected device or isn't connected",
+                    device_id.unwrap_or(ANY_DEVICE_ID)
+                ));
+            }
+            let action = match mappings.mapping(button, 0) {
+                Some(ResolvedAction::Gesture(gesture)) => gesture.resolve(0.0, 0.0),
+                mapping => mapping.map_or(Action::Default, ResolvedAction::action),
+            };
+            (button_key(button), action)
+        }
+        ActionTest::Action(action) => (None, action),
+        ActionTest::ClickAt { .. } => (None, Action::MiddleClick),
+    };
+    if action.needs_confirmation() && !confirm {
+        return Err(format!("{} only runs with confirm: true", action.name()));
+    }
+    if action == Action::ToggleFocus {
+        focus::check()?;
+    }
+    Ok(ActionTested {
+        device_id: button.and(mappings.device_id.clone()),
+        button,
+        action: action.name(),
+        app: state.frontmost_app_name(),
+    })
+}
+
+/// A press `resolve_mapping` asks about: `button` of `device_id` with
+/// `held` down while `app` is frontmost, and what the engine knows of the
+/// devices right now.
+#[derive(Debug, Default, Clone, Copy)]
+pub(crate) struct MappingQuery<'a> {
+    pub(crate) device_id: &'a str,
+    pub(crate) button: i64,
+    pub(crate) held: ModifierMask,
+    pub(crate) app: Option<&'a str>,
+    /// Whether `device_id` is connected.
+    pub(crate) connected: bool,
+    /// `MappingContext::ignored_connected`.
+    pub(crate) ignored_connected: bool,
+    /// Buttons of `device_id`.
+    pub(crate) button_count: u8,
+    /// Device standing in for the selected one; see `fallback_device`.
+    pub(crate) fallback: Option<&'a str>,
+}
+
+/// One rule `explain_mapping` checked.
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub(crate) struct ResolutionStep {
+    /// Which rule, such as `"app-override"`; the ids don't change, so the
+    /// settings window can word them.
+    pub(crate) rule: &'static str,
+    /// This rule settled the outcome. Only the last step has it.
+    pub(crate) decided: bool,
+    pub(crate) detail: String,
+}
+
+/// Reply of `resolve_mapping`.
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub(crate) struct MappingResolution {
+    /// Name of the action the press fires, as in the `button-event` log;
+    /// "Default" when it reaches apps unchanged.
+    pub(crate) action: &'static str,
+    /// The action fires as the button comes up.
+    pub(crate) on_release: bool,
+    /// `rule` of the deciding step.
+    pub(crate) source: &'static str,
+    /// Rules checked, in the order the engine applies them.
+    pub(crate) trace: Vec<ResolutionStep>,
+}
+
+/// Works out what `query` fires and which rule decided it: whether the
+/// device is ignored, the selected device, whether it's connected, `excluded_apps`, the profile an app
+/// rule switches to, the device's config, then its modifier layers, the
+/// app's overrides and the button's own mapping. The action comes from
+/// `ResolvedMappings`, so it is what the engine would fire; Game Mode and
+/// whether remapping is on right now are left out.
+pub(crate) fn explain_mapping(settings: &Settings, query: &MappingQuery) -> MappingResolution {
+    let mut trace = Vec::new();
+    let id = query.device_id;
+    if settings.is_device_ignored(id) {
+        let detail = format!("{} is ignored, so its events always pass through", id);
+        return decided(trace, "ignored-device", detail, None);
+    }
+    if settings.leaves_untouched(id, query.ignored_connected) {
+        let detail = "an ignored device is connected, and Any mouse can't tell its events apart";
+        return decided(trace, "ignored-device", detail.to_string(), None);
+    }
+    passed(
+        &mut trace,
+        "ignored-device",
+        format!("{} isn't ignored", id),
+    );
+    let Some(current) = query.fallback.or(settings.selected_device.as_deref()) else {
+        return decided(
+            trace,
+            "selected-device",
+            "no device is selected".into(),
+            None,
+        );
+    };
+    if current != id {
+        let detail = format!("only {} is remapped", current);
+        return decided(trace, "selected-device", detail, None);
+    }
+    passed(
+        &mut trace,
+        "selected-device",
+        match (query.fallback, settings.selected_device.as_deref()) {
+            (Some(_), Some(selected)) if selected != id => {
+                format!("{} stands in for {}, which is away", id, selected)
+            }
+            _ => format!("{} is the selected device", id),
+        },
+    );
+    if !query.connected {
+        let detail = format!("{} isn't connected", id);
+        return decided(trace, "connected", detail, None);
+    }
+    passed(&mut trace, "connected", format!("{} is connected", id));
+    if let Some(app) = query.app {
+        if settings
+            .excluded_apps
+            .iter()
+            .any(|excluded| excluded == app)
+        {
+            let detail = format!("remapping is off in {}", app);
+            return decided(trace, "excluded-app", detail, None);
+        }
+        passed(
+            &mut trace,
+            "excluded-app",
+            format!("{} isn't excluded", app),
+        );
+    }
+    let rule = query.app.and_then(|app| {
+        settings
+            .profile_app_rules
+            .iter()
+            .find(|rule| rule.bundle_id == app && settings.profiles.contains_key(&rule.profile))
+    });
+    let switched;
+    let settings = match rule {
+        Some(rule) => {
+            passed(
+                &mut trace,
+                "profile",
+                format!("{} switches to profile {}", rule.bundle_id, rule.profile),
+            );
+            switched = Settings {
+                active_profile: rule.profile.clone(),
+                ..settings.clone()
+            };
+            &switched
+        }
+        None => {
+            passed(
+                &mut trace,
+                "profile",
+                format!("profile {} is active", settings.active_profile),
+            );
+            settings
+        }
+    };
+    let own = settings.device_config(id);
+    let config = match own {
+        Some(config) if query.fallback.is_none() || !config.pristine => {
+            passed(
+                &mut trace,
+                "device-config",
+                match settings.profiles.get(&settings.active_profile) {
+                    Some(profile) if !profile.devices.contains_key(id) => {
+                        format!("{} shares the mappings of {}", id, unit_base_id(id))
+                    }
+                    _ => format!("{} has its own mappings", id),
+                },
+            );
+            config
+        }
+        _ => match settings
+            .device_config(ANY_DEVICE_ID)
+            .filter(|_| query.fallback.is_some())
+        {
+            Some(config) => {
+                let detail = format!("{} was never set up, so Any mouse's mappings apply", id);
+                passed(&mut trace, "device-config", detail);
+                config
+            }
+            None => {
+                let detail = format!(
+                    "profile {} has no mappings for {}",
+                    settings.active_profile, id
+                );
+                return decided(trace, "device-config", detail, None);
+            }
+        },
+    };
+    let context = MappingContext {
+        device_available: true,
+        fallback: query.fallback,
+        suspended: false,
+        ignored_connected: query.ignored_connected,
+        button_count: query.button_count,
+        app: query.app,
+    };
+    let resolved = ResolvedMappings::resolve(settings, &context).mapping(query.button, query.held);
+    if config.disabled {
+        let detail = format!("every button of {} is disabled", id);
+        return decided(trace, "device-disabled", detail, resolved);
+    }
+    passed(
+        &mut trace,
+        "device-disabled",
+        format!("{} isn't disabled", id),
+    );
+    let key = match button_key(query.button) {
+        Some(key) if query.button < i64::from(query.button_count) => key,
+        _ => {
+            let detail = format!("{} has {} buttons", id, query.button_count);
+            return decided(trace, "button-count", detail, resolved);
+        }
+    };
+    let held = match modifier_symbols(query.held) {
+        symbols if symbols.is_empty() => "no modifiers".to_string(),
+        symbols => symbols,
+    };
+    let layer = |mask| {
+        config.modifier_mappings.get(key).and_then(|mappings| {
+            mappings
+                .iter()
+                .find(|mapping| modifier_mask(&mapping.modifiers) == mask)
+        })
+    };
+    let (rule, action, detail) = if let Some(layered) = layer(query.held) {
+        let detail = format!("{} has a mapping for {} held", key, held);
+        ("modifier-layer", Some(&layered.action), detail)
+    } else {
+        passed(
+            &mut trace,
+            "modifier-layer",
+            format!("{} has no mapping for {} held", key, held),
+        );
+        // A press with modifiers no layer has falls back to the button's
+        // unmodified mapping, and that starts with a layer for none.
+        let unmodified = layer(0).filter(|_| query.held != 0);
+        let overridden = query.app.and_then(|app| {
+            let overrides = config.app_overrides.get(app)?;
+            Some((app, overrides.get(key)))
+        });
+        match (unmodified, overridden) {
+            (Some(unmodified), _) => {
+                let detail = format!("{} has a mapping for no modifiers held", key);
+                ("modifier-layer", Some(&unmodified.action), detail)
+            }
+            (None, Some((app, Some(overridden)))) => {
+                let detail = format!("{} overrides {}", app, key);
+                ("app-override", Some(overridden), detail)
+            }
+            (None, _) => {
+                if let Some(app) = query.app {
+                    passed(
+                        &mut trace,
+                        "app-override",
+                        format!("{} doesn't override {}", app, key),
+                    );
+                }
+                match config.buttons.get(key) {
+                    Some(mapped) => {
+                        let detail = format!("{} is mapped for every app", key);
+                        ("button-mapping", Some(mapped), detail)
+                    }
+                    None => ("button-mapping", None, format!("{} isn't mapped", key)),
+                }
+            }
+        }
+    };
+    let quick_look =
+        action.is_some_and(|action| ResolvedAction::from(action).action() == Action::QuickLook);
+    if quick_look && resolved.is_none() {
+        passed(&mut trace, rule, detail);
+        let detail = "Quick Look only runs in the apps listed for it".to_string();
+        return decided(trace, "quick-look", detail, resolved);
+    }
+    decided(trace, rule, detail, resolved)
+}
+
+/// Adds a rule that was checked without settling anything to `trace`.
+fn passed(trace: &mut Vec<ResolutionStep>, rule: &'static str, detail: String) {
+    trace.push(ResolutionStep {
+        rule,
+        decided: false,
+        detail,
+    });
+}
+
+/// Ends `trace` with the step that settled on `mapping`.
+fn decided(
+    mut trace: Vec<ResolutionStep>,
+    rule: &'static str,
+    detail: String,
+    mapping: Option<ResolvedAction>,
+) -> MappingResolution {
+    trace.push(ResolutionStep {
+        rule,
+        decided: true,
+        detail,
+    });
+    MappingResolution {
+        action: match mapping {
+            Some(ResolvedAction::Gesture(_)) => "Gesture",
+            mapping => mapping
+                .map_or(Action::Default, ResolvedAction::action)
+                .name(),
+        },
+        on_release: matches!(mapping, Some(ResolvedAction::OnRelease(_))),
+        source: rule,
+        trace,
+    }
+}
+
+/// Runs `test` through the same code the engine runs button events
+/// through, with no modifiers held.
+pub(crate) fn run_action_test(
+    app: &tauri::AppHandle,
+    state: &AppState,
+    output: &impl RemapOutput,
+    test: ActionTest,
+) {
+    match test {
+        ActionTest::Button(button) => {
+            dispatch_button(app, state, output, button, true, 0);
+            dispatch_button(app, state, output, button, false, 0);
+        }
+        ActionTest::Action(action) => {
+            let interval = state.double_click_interval(&state.resolved_mappings(), None);
+            perform_action(
+                app,
+                state,
+                &Governed::new(state, output),
+                action,
+                ActionModifiers::default(),
+                interval,
+            )
+        }
+        ActionTest::ClickAt { .. } => {
+            let output = Governed::new(state, output);
+            if let Err(message) = output.post_mouse_click(ClickSequence::single(2, 0)) {
+                report_post_error(app, state, message);
+            }
+        }
+    }
+}
+
+/// Releases a translated middle button whose physical release never arrived.
+pub(crate) fn release_middle_drag(
+    app: &tauri::AppHandle,
+    state: &AppState,
+    output: &impl RemapOutput,
+) {
+    if !state.end_middle_drag(None) {
+        return;
+    }
+    log::info!("mouse-remap: releasing a middle button left held");
+    if let Err(message) = output.post_mouse_event(2, false, 1, 0) {
+        report_remap_error(app, state, "post-failed", message);
+    }
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "linux", windows)))]
+pub(crate) fn start_mouse_remap(_app: tauri::AppHandle, _state: AppState) {}
+
+/// Clicks a synthetic click action posts: `count` down/up pairs of `button`
+/// numbered the way the system numbers a real multi-click, so apps see a
+/// double click rather than two single ones. Each is posted with
+/// `modifiers` held, so Shift and a double click still extends a selection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct ClickSequence {
+    pub(crate) button: i64,
+    pub(crate) count: u8,
+    pub(crate) modifiers: ModifierMask,
+    /// Pause before each click after the first.
+    pub(crate) interval: Duration,
+}
+
+impl ClickSequence {
+    pub(crate) fn single(button: i64, modifiers: ModifierMask) -> Self {
+        Self {
+            button,
+            count: 1,
+            modifiers,
+            interval: Duration::ZERO,
+        }
+    }
+
+    pub(crate) fn double(button: i64, modifiers: ModifierMask, interval: Duration) -> Self {
+        Self {
+            button,
+            count: 2,
+            modifiers,
+            interval,
+        }
+    }
+
+    /// `(click state, down)` of each event to post, in order.
+    pub(crate) fn events(self) -> impl Iterator<Item = (i64, bool)> {
+        (1..=i64::from(self.count)).flat_map(|click| [(click, true), (click, false)])
+    }
+}
+
+/// A display's bounds in global display coordinates, the space mouse events
+/// are in: points with the origin at the top-left of the main display and y
+/// growing downward, so a display above or left of it has a negative
+/// origin.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct DisplayBounds {
+    pub(crate) x: f64,
+    pub(crate) y: f64,
+    pub(crate) width: f64,
+    pub(crate) height: f64,
+}
+
+impl DisplayBounds {
+    pub(crate) fn contains(&self, (x, y): (f64, f64)) -> bool {
+        x >= self.x && x < self.x + self.width && y >= self.y && y < self.y + self.height
+    }
+
+    /// The point of this display closest to `point`.
+    pub(crate) fn nearest(&self, (x, y): (f64, f64)) -> (f64, f64) {
+        (
+            x.clamp(self.x, self.x + (self.width - 1.0).max(0.0)),
+            y.clamp(self.y, self.y + (self.height - 1.0).max(0.0)),
+        )
+    }
+}
+
+/// Converts a point from Cocoa's screen coordinates, whose origin is the
+/// bottom-left of the main display with y growing upward, to global display
+/// coordinates. `main_height` is the main display's height in points.
+pub(crate) fn global_from_cocoa((x, y): (f64, f64), main_height: f64) -> (f64, f64) {
+    (x, main_height - y)
+}
+
+/// Where a synthetic click goes, as `test_click_at` reports it.
+#[derive(Debug, Serialize, Clone, Copy, PartialEq)]
+pub(crate) struct ClickTarget {
+    /// Global display coordinates.
+    pub(crate) x: f64,
+    pub(crate) y: f64,
+    /// Index of the display the click lands on, the main display being 0.
+    pub(crate) display: usize,
+}
+
+impl ClickTarget {
+    /// `point` on the display containing it, or else the nearest point of
+    /// the nearest display, so a location past an edge, such as that of a
+    /// display just unplugged, can't send a click to no display at all.
+    /// None without displays.
+    pub(crate) fn resolve(point: (f64, f64), displays: &[DisplayBounds]) -> Option<Self> {
+        let (display, (x, y)) = match displays.iter().position(|bounds| bounds.contains(point)) {
+            Some(index) => (index, point),
+            None => displays
+                .iter()
+                .map(|bounds| bounds.nearest(point))
+                .enumerate()
+                .min_by(|(_, a), (_, b)| {
+                    let distance = |(x, y): (f64, f64)| (x - point.0).hypot(y - point.1);
+                    distance(*a).total_cmp(&distance(*b))
+                })?,
+        };
+        Some(Self { x, y, display })
+    }
+}
+
+/// The `SwipeStrategy` that works on this system, worked out on first use,
+/// which is at launch.
+pub(crate) fn swipe_strategy() -> SwipeStrategy {
+    static STRATEGY: std::sync::OnceLock<SwipeStrategy> = std::sync::OnceLock::new();
+    *STRATEGY.get_or_init(|| {
+        #[cfg(target_os = "macos")]
+        let strategy = {
+            let version = objc2_foundation::NSProcessInfo::processInfo().operatingSystemVersion();
+            swipe_strategy_for(
+                version.majorVersion,
+                version.minorVersion,
+                gesture_events_supported(),
+            )
+        };
+        #[cfg(not(target_os = "macos"))]
+        let strategy = SwipeStrategy::Keys;
+        log::info!("mouse-remap: swipe actions post {:?}", strategy);
+        strategy
+    })
+}
+
+/// The strategy for macOS `major`.`minor`, where gesture events can be
+/// made with the fields a trackpad's have when `gestures` is true. Those
+/// fields are private; earlier than 10.13 they were laid out differently.
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+pub(crate) fn swipe_strategy_for(major: isize, minor: isize, gestures: bool) -> SwipeStrategy {
+    match (major, minor) {
+        (11.., _) | (10, 13..) if gestures => SwipeStrategy::Gesture,
+        _ => SwipeStrategy::Keys,
+    }
+}
+
+/// The shortcut each swipe action presses with `SwipeStrategy::Keys`, by
+/// action id, for labelling them.
+#[cfg(target_os = "macos")]
+pub(crate) const SWIPE_KEYS: [(&str, &str); 4] = [
+    ("swipe_back", "⌘["),
+    ("swipe_forward", "⌘]"),
+    ("previous_space", "⌃←"),
+    ("next_space", "⌃→"),
+];
+#[cfg(windows)]
+pub(crate) const SWIPE_KEYS: [(&str, &str); 4] = [
+    ("swipe_back", "Browser Back"),
+    ("swipe_forward", "Browser Forward"),
+    ("previous_space", "Ctrl+Win+Left"),
+    ("next_space", "Ctrl+Win+Right"),
+];
+#[cfg(not(any(target_os = "macos", windows)))]
+pub(crate) const SWIPE_KEYS: [(&str, &str); 4] = [
+    ("swipe_back", "Alt+Left"),
+    ("swipe_forward", "Alt+Right"),
+    ("previous_space", "Ctrl+Alt+Left"),
+    ("next_space", "Ctrl+Alt+Right"),
+];
+
+/// The active displays, main display first.
+#[cfg(target_os = "macos")]
+pub(crate) fn active_displays() -> Vec<DisplayBounds> {
+    use core_graphics::display::CGDisplay;
+
+    CGDisplay::active_displays()
+        .unwrap_or_default()
+        .into_iter()
+        .map(|id| {
+            let bounds = CGDisplay::new(id).bounds();
+            DisplayBounds {
+                x: bounds.origin.x,
+                y: bounds.origin.y,
+                width: bounds.size.width,
+                height: bounds.size.height,
+            }
+        })
+        .collect()
+}
+
+/// Other platforms click wherever the pointer is, so clicks are never
+/// placed by coordinates there.
+#[cfg(not(target_os = "macos"))]
+pub(crate) fn active_displays() -> Vec<DisplayBounds> {
+    Vec::new()
+}
+
+/// Events the event tap listens for: the fewest the mappings in effect
+/// need, since every event subscribed to waits on the callback. The tap is
+/// recreated when they change.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+pub(crate) struct TapEvents {
+    /// Presses and releases of the left button, the right, and the others.
+    pub(crate) left: bool,
+    pub(crate) right: bool,
+    pub(crate) other: bool,
+    /// Drags of each, for buttons whose presses may be swallowed or
+    /// swapped, so apps never see a drag of a button they don't know is
+    /// down.
+    pub(crate) left_dragged: bool,
+    pub(crate) right_dragged: bool,
+    pub(crate) other_dragged: bool,
+    /// Movement with no button the system knows of held, which gestures
+    /// and middle-button drags on extended buttons come as.
+    pub(crate) moved: bool,
+    /// For `ResolvedMappings::disable_scroll_inertia`.
+    pub(crate) scroll: bool,
+}
+
+impl TapEvents {
+    /// What `mappings` need, with every button's presses and releases when
+    /// `listening`: for a capture, the event monitor, the input overlay, or
+    /// monitor mode.
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+    pub(crate) fn needed(mappings: &ResolvedMappings, listening: bool) -> Self {
+        let active = |slot: &ResolvedButton| slot.is_active();
+        let [left, right, others @ ..] = &mappings.buttons;
+        let left = active(left) || mappings.swap_primary_buttons;
+        let right = active(right) || mappings.swap_primary_buttons;
+        let other = others.iter().any(active);
+        let moved = mappings
+            .buttons
+            .iter()
+            .flat_map(ResolvedButton::actions)
+            .any(|action| {
+                matches!(action, ResolvedAction::Gesture(_))
+                    || action.action() == Action::MiddleClick
+            });
+        Self {
+            left: left || listening,
+            right: right || listening,
+            other: other || listening,
+            left_dragged: left,
+            right_dragged: right,
+            other_dragged: other,
+            moved,
+            scroll: mappings.disable_scroll_inertia,
+        }
+    }
+
+    /// These plus the movement and clicks idle suspension counts as input,
+    /// so `AppState::note_input` hears of them whatever the mappings need.
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+    pub(crate) fn watching_idle(self) -> Self {
+        Self {
+            left: true,
+            right: true,
+            moved: true,
+            ..self
+        }
+    }
+}
+
+/// Buttons the event tap saw go down, and which of those downs it swallowed,
+/// as bit sets. Keeps what applications see consistent: a button's release
+/// reaches them exactly when its press did, and they never get drags of a
+/// button they don't know is down.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct TapButtons {
+    pub(crate) seen: u64,
+    pub(crate) suppressed: u64,
+    /// Physical buttons pressed while `DeviceConfig::swap_primary_buttons`
+    /// was on, which act as the other primary button until released.
+    /// `seen` and `suppressed` hold the buttons they act as.
+    pub(crate) swapped: u64,
+}
+
+/// `kCGMomentumScrollPhaseBegin`: the first momentum event after the
+/// fingers or the wheel let go.
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+pub(crate) const MOMENTUM_PHASE_BEGAN: i64 = 1;
+
+/// The event tap's view of scroll gestures, for dropping the momentum of a
+/// mouse's wheel but not a trackpad's. The tap can't tell devices apart,
+/// but a trackpad's scrolls come in phases and a wheel's don't.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct ScrollMomentum {
+    /// The last scroll before momentum had a scroll phase.
+    phased: bool,
+    /// The momentum under way is dropped, all of it, so apps never see its
+    /// start without its end.
+    dropping: bool,
+}
+
+impl ScrollMomentum {
+    /// Whether to drop a scroll event with CoreGraphics' `scroll_phase`
+    /// and `momentum_phase`, 0 for none, while `disable` is on. Scrolls
+    /// that aren't momentum always pass, so a new gesture is never cut off;
+    /// momentum is judged as it begins, and kept or dropped to its end.
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+    pub(crate) fn drop_event(
+        &mut self,
+        scroll_phase: i64,
+        momentum_phase: i64,
+        disable: bool,
+    ) -> bool {
+        if momentum_phase == 0 {
+            self.phased = scroll_phase != 0;
+            self.dropping = false;
+            return false;
+        }
+        if momentum_phase == MOMENTUM_PHASE_BEGAN {
+            self.dropping = disable && !self.phased;
+        }
+        self.dropping
+    }
+}
+
+/// What to turn a mouse-moved or dragged event into.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+pub(crate) enum DragTranslation {
+    Pass,
+    /// No button applications know about is down.
+    Moved,
+    Dragged(i64),
+}
+
+pub(crate) fn button_bit(button: i64) -> u64 {
+    u32::try_from(button)
+        .ok()
+        .and_then(|shift| 1u64.checked_shl(shift))
+        .unwrap_or(0)
+}
+
+impl TapButtons {
+    /// Button a press of physical `button` acts as: the other primary
+    /// button when `swap` is on, which its drags and release then follow.
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+    pub(crate) fn swap_press(&mut self, button: i64, swap: bool) -> i64 {
+        let bit = button_bit(button);
+        if swap && matches!(button, 0 | 1) {
+            self.swapped |= bit;
+        } else {
+            self.swapped &= !bit;
+        }
+        self.identity(button)
+    }
+
+    /// Button physical `button` acts as while held.
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+    pub(crate) fn identity(self, button: i64) -> i64 {
+        if self.swapped & button_bit(button) != 0 {
+            1 - button
+        } else {
+            button
+        }
+    }
+
+    /// Button a release of physical `button` acts as, however the swap is
+    /// set by now.
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+    pub(crate) fn swap_release(&mut self, button: i64) -> i64 {
+        let identity = self.identity(button);
+        self.swapped &= !button_bit(button);
+        identity
+    }
+
+    /// Physical button acting as `button`.
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+    pub(crate) fn physical(self, button: i64) -> i64 {
+        if matches!(button, 0 | 1) && self.swapped & button_bit(1 - button) != 0 {
+            1 - button
+        } else {
+            button
+        }
+    }
+
+    pub(crate) fn press(&mut self, button: i64, swallowed: bool) {
+        let bit = button_bit(button);
+        self.seen |= bit;
+        if swallowed {
+            self.suppressed |= bit;
+        } else {
+            self.suppressed &= !bit;
+        }
+    }
+
+    /// Forgets `button`, returning whether its release must be swallowed
+    /// because its press was.
+    pub(crate) fn release(&mut self, button: i64) -> bool {
+        let bit = button_bit(button);
+        let swallowed = self.suppressed & bit != 0;
+        self.seen &= !bit;
+        self.suppressed &= !bit;
+        swallowed
+    }
+
+    /// Translation of a motion event that drags `dragged` (`None` for a
+    /// plain move) while `middle_drag` holds the synthetic middle button.
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+    pub(crate) fn drag(self, dragged: Option<i64>, middle_drag: Option<i64>) -> DragTranslation {
+        if middle_drag.is_some() {
+            return DragTranslation::Dragged(2);
+        }
+        let Some(button) = dragged else {
+            return DragTranslation::Pass;
+        };
+        if self.suppressed & button_bit(button) == 0 {
+            return DragTranslation::Pass;
+        }
+        let visible = self.seen & !self.suppressed;
+        if visible == 0 {
+            DragTranslation::Moved
+        } else {
+            DragTranslation::Dragged(i64::from(visible.trailing_zeros()))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings_mapping(button: &str, action: Action) -> Settings {
+        let mut settings = Settings {
+            selected_device: Some("m".to_string()),
+            ..Settings::default()
+        };
+        settings
+            .set_button_action("m", button, ActionValue::Action(action))
+            .unwrap();
+        settings
+    }
+
+    fn connected(app: Option<&str>) -> MappingContext<'_> {
+        MappingContext {
+            device_available: true,
+            fallback: None,
+            suspended: false,
+            ignored_connected: false,
+            button_count: DEFAULT_BUTTON_COUNT,
+            app,
+        }
+    }
+
+    #[test]
+    fn the_tap_listens_only_for_what_the_mappings_need() {
+        let resolved = |json: &str| {
+            let config: DeviceConfig = serde_json::from_str(json).unwrap();
+            ResolvedMappings::build(&config, "m", DEFAULT_BUTTON_COUNT, None)
+        };
+        let events = TapEvents::needed(&resolved(r#"{"buttons":{"button4":"Back"}}"#), false);
+        assert_eq!(
+            events,
+            TapEvents {
+                other: true,
+                other_dragged: true,
+                ..TapEvents::default()
+            }
+        );
+        // Nothing mapped needs nothing, until something listens to every
+        // button's presses.
+        let passing = resolved(r#"{"buttons":{"button4":"Default"}}"#);
+        assert_eq!(TapEvents::needed(&passing, false), TapEvents::default());
+        let listening = TapEvents::needed(&passing, true);
+        assert!(listening.left && listening.right && listening.other);
+        assert!(!listening.left_dragged && !listening.moved && !listening.scroll);
+
+        let swapped = resolved(r#"{"swap_primary_buttons":true,"disable_scroll_inertia":true}"#);
+        let events = TapEvents::needed(&swapped, false);
+        assert!(events.left && events.left_dragged && events.right_dragged && events.scroll);
+        assert!(!events.other && !events.moved);
+        // A layer counts, and so does a gesture, which also tracks movement.
+        let layered = resolved(
+            r#"{"modifier_mappings":{"right":[{"modifiers":["shift"],"action":"Back"}]}}"#,
+        );
+        let events = TapEvents::needed(&layered, false);
+        assert!(events.right && !events.left && !events.moved);
+        let gesture = resolved(r#"{"buttons":{"button5":{"gesture":{"up":"Forward"}}}}"#);
+        assert!(TapEvents::needed(&gesture, false).moved);
+        let middle = resolved(r#"{"buttons":{"button4":"Middle Click"}}"#);
+        assert!(TapEvents::needed(&middle, false).moved);
+    }
+
+    #[test]
+    fn the_tap_hears_movement_and_clicks_while_idle_suspension_is_on() {
+        let config: DeviceConfig =
+            serde_json::from_str(r#"{"buttons":{"button4":"Back"}}"#).unwrap();
+        let resolved = ResolvedMappings::build(&config, "m", DEFAULT_BUTTON_COUNT, None);
+        let events = TapEvents::needed(&resolved, false).watching_idle();
+        assert!(events.left && events.right && events.moved);
+        // Only presses: drags of unmapped buttons still never reach the tap.
+        assert!(events.other && events.other_dragged);
+        assert!(!events.left_dragged && !events.right_dragged && !events.scroll);
+    }
+
+    #[test]
+    fn every_action_resolves_from_settings() {
+        let typed = [
+            Action::SetDpi { dpi: 1600 },
+            Action::Shortcut {
+                keycode: Some(0x28),
+                key: Some('k'),
+                modifiers: modifier_mask(&[Modifier::Command]),
+            },
+        ];
+        // Quick Look only resolves in its apps, which Finder is by default.
+        let context = connected(Some(FINDER_BUNDLE_ID));
+        for action in PLAIN_ACTIONS.into_iter().chain(typed) {
+            let settings = settings_mapping("button4", action);
+            let resolved = ResolvedMappings::resolve(&settings, &context);
+            assert_eq!(resolved.device_id.as_deref(), Some("m"));
+            assert_eq!(
+                resolved.mapping(3, 0).map(ResolvedAction::action),
+                Some(action),
+                "{:?}",
+                action
+            );
+        }
+
+        let settings = settings_mapping("button4", Action::QuickLook);
+        let resolved = ResolvedMappings::resolve(&settings, &connected(Some("com.example.app")));
+        assert_eq!(resolved.mapping(3, 0), None);
+        assert!(!resolved.quick_look);
+    }
+
+    #[test]
+    fn nothing_resolves_for_an_unavailable_device() {
+        let settings = settings_mapping("button4", Action::Back);
+        let unplugged = MappingContext {
+            device_available: false,
+            ..connected(None)
+        };
+        let suspended = MappingContext {
+            suspended: true,
+            ..connected(None)
+        };
+        for context in [unplugged, suspended] {
+            let resolved = ResolvedMappings::resolve(&settings, &context);
+            assert_eq!(resolved.device_id, None);
+            assert!((0..BUTTON_SLOTS as i64).all(|button| resolved.mapping(button, 0).is_none()));
+        }
+
+        // Nothing selected, or a selection without a config.
+        let unselected = Settings {
+            selected_device: None,
+            ..settings.clone()
+        };
+        let unconfigured = Settings {
+            selected_device: Some("other".to_string()),
+            ..settings
+        };
+        for settings in [unselected, unconfigured] {
+            let resolved = ResolvedMappings::resolve(&settings, &connected(None));
+            assert_eq!(resolved.device_id, None);
+            assert_eq!(resolved.mapping(3, 0), None);
+        }
+    }
+
+    #[test]
+    fn ignored_devices_always_pass_through() {
+        let mut settings = settings_mapping("button4", Action::Back);
+        settings.set_device_ignored("m", true).unwrap();
+        let resolved = ResolvedMappings::resolve(&settings, &connected(None));
+        assert_eq!(resolved.device_id, None);
+        assert_eq!(resolved.mapping(3, 0), None);
+        assert!(settings.set_device_ignored(ANY_DEVICE_ID, true).is_err());
+
+        // Any mouse can't tell an ignored device's events from the others'.
+        settings.set_device_ignored("m", false).unwrap();
+        settings.set_device_ignored("k", true).unwrap();
+        let config = settings.device_config("m").unwrap().clone();
+        let profile = settings.profiles.get_mut(DEFAULT_PROFILE).unwrap();
+        profile.devices.insert(ANY_DEVICE_ID.to_string(), config);
+        settings.selected_device = Some(ANY_DEVICE_ID.to_string());
+        let untouched = MappingContext {
+            ignored_connected: true,
+            ..connected(None)
+        };
+        assert_eq!(
+            ResolvedMappings::resolve(&settings, &untouched).device_id,
+            None
+        );
+        let resolved = ResolvedMappings::resolve(&settings, &connected(None));
+        assert_eq!(
+            resolved.mapping(3, 0).map(ResolvedAction::action),
+            Some(Action::Back)
+        );
+    }
+
+    #[test]
+    fn unknown_buttons_resolve_to_nothing() {
+        let settings = settings_mapping("button10", Action::Forward);
+        let resolved = ResolvedMappings::resolve(
+            &settings,
+            &MappingContext {
+                button_count: 10,
+                ..connected(None)
+            },
+        );
+        assert_eq!(
+            resolved.mapping(9, 0).map(ResolvedAction::action),
+            Some(Action::Forward)
+        );
+        for button in [-1, BUTTON_SLOTS as i64, 99, i64::MAX, i64::MIN] {
+            assert_eq!(resolved.mapping(button, 0), None, "{}", button);
+            assert_eq!(resolved.click_modifiers(button, 0), 0);
+            assert_eq!(resolved.feedback(button), Feedback::None);
+        }
+
+        // Past the device's button count, even when mapped.
+        let five = ResolvedMappings::resolve(&settings, &connected(None));
+        assert_eq!(five.mapping(9, 0), None);
+    }
+
+    #[test]
+    fn holding_a_remapped_left_button_gives_its_click_back() {
+        let settings = settings_mapping("left", Action::Disabled);
+        let resolved = ResolvedMappings::resolve(&settings, &connected(None));
+        let disabled = ResolvedAction::Action(Action::Disabled);
+        assert_eq!(resolved.primary_remap(), Some(disabled));
+
+        let state = AppState::default();
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+        // Clicks, however long, keep the mapping until one lasts the hold.
+        assert_eq!(state.track_primary_hold(&resolved, true, at(0)), None);
+        assert_eq!(state.track_primary_hold(&resolved, false, at(4)), None);
+        assert_eq!(state.track_primary_hold(&resolved, false, at(9)), None);
+        assert_eq!(state.track_primary_hold(&resolved, true, at(10)), None);
+        assert_eq!(
+            state.track_primary_hold(&resolved, false, at(15)),
+            Some(disabled)
+        );
+
+        let mut escaped = Some(disabled);
+        let mut restored = ResolvedMappings::resolve(&settings, &connected(None));
+        restored.escape_primary(&mut escaped);
+        assert_eq!(restored.mapping(0, 0), None);
+        assert_eq!(restored.primary_remap(), None);
+        assert_eq!(escaped, Some(disabled));
+
+        // Unchanged for a disconnected device, forgotten once the left
+        // button is mapped to something else.
+        ResolvedMappings::default().escape_primary(&mut escaped);
+        assert_eq!(escaped, Some(disabled));
+        let remapped = settings_mapping("left", Action::Back);
+        let mut resolved = ResolvedMappings::resolve(&remapped, &connected(None));
+        resolved.escape_primary(&mut escaped);
+        assert_eq!(escaped, None);
+        assert_eq!(
+            resolved.mapping(0, 0).map(ResolvedAction::action),
+            Some(Action::Back)
+        );
+
+        // Holding a left button that clicks anyway sets nothing aside.
+        let plain = ResolvedMappings::resolve(&Settings::default(), &connected(None));
+        assert_eq!(state.track_primary_hold(&plain, true, at(20)), None);
+        assert_eq!(state.track_primary_hold(&plain, false, at(30)), None);
+    }
+
+    #[test]
+    fn merged_modifiers_follow_the_mapping_option() {
+        let shift = modifier_mask(&[Modifier::Shift]);
+        let shift_command = modifier_mask(&[Modifier::Shift, Modifier::Command]);
+        let mut config = DeviceConfig {
+            merge_modifiers: vec!["button4".to_string()],
+            ..DeviceConfig::default()
+        };
+        config.modifier_mappings.insert(
+            "button4".to_string(),
+            vec![ModifierMapping {
+                modifiers: vec![Modifier::Shift, Modifier::Command],
+                action: ActionValue::Action(Action::Forward),
+            }],
+        );
+        let resolved = ResolvedMappings::build(&config, "mouse", 5, None);
+
+        assert_eq!(resolved.merged_modifiers(3, shift), shift);
+        assert_eq!(resolved.merged_modifiers(3, 0), 0);
+        // Modifiers that picked a modifier mapping aren't passed on.
+        assert_eq!(resolved.merged_modifiers(3, shift_command), 0);
+        // Button 5 doesn't merge.
+        assert_eq!(resolved.merged_modifiers(4, shift), 0);
+        assert_eq!(resolved.merged_modifiers(-1, shift), 0);
+    }
+
+    #[test]
+    fn release_triggers_defer_actions_but_not_defaults() {
+        let config: DeviceConfig = serde_json::from_str(
+            r#"{"buttons": {"button4": "Back", "button5": "Forward", "middle": "Default"},
+                "modifier_mappings": {"button4": [{"modifiers": ["shift"], "action": "Next Profile"}]},
+                "triggers": {"button4": "release", "middle": "release", "button5": "press"}}"#,
+        )
+        .unwrap();
+        let resolved = ResolvedMappings::build(&config, "mouse", 5, None);
+        let shift = modifier_mask(&[Modifier::Shift]);
+
+        assert_eq!(
+            resolved.mapping(3, 0),
+            Some(ResolvedAction::OnRelease(Action::Back))
+        );
+        assert_eq!(
+            resolved.mapping(3, shift),
+            Some(ResolvedAction::OnRelease(Action::NextProfile))
+        );
+        assert_eq!(
+            resolved.mapping(4, 0),
+            Some(ResolvedAction::Action(Action::Forward))
+        );
+        assert_eq!(
+            resolved.mapping(2, 0),
+            Some(ResolvedAction::Action(Action::Default))
+        );
+        assert_eq!(
+            ResolvedAction::OnRelease(Action::Back).action(),
+            Action::Back
+        );
+
+        // Unlisted buttons keep firing on press, and only buttons have
+        // triggers.
+        assert!(DeviceConfig::default().triggers.is_empty());
+        let settings = parse_settings(
+            r#"{"profiles": {"Default": {"devices": {"m": {"triggers": {"wheel": "release"}}}}}}"#,
+        )
+        .unwrap();
+        assert!(settings.validate().is_err());
+    }
+
+    #[test]
+    fn click_sequences_number_each_click() {
+        let events: Vec<_> = ClickSequence::single(2, 0).events().collect();
+        assert_eq!(events, vec![(1, true), (1, false)]);
+
+        let events: Vec<_> = ClickSequence::double(0, 0, Duration::ZERO)
+            .events()
+            .collect();
+        assert_eq!(events, vec![(1, true), (1, false), (2, true), (2, false)]);
+
+        let triple = ClickSequence {
+            button: 0,
+            count: 3,
+            modifiers: 0,
+            interval: Duration::ZERO,
+        };
+        let states: Vec<_> = triple
+            .events()
+            .filter(|(_, down)| *down)
+            .map(|(state, _)| state)
+            .collect();
+        assert_eq!(states, vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn clicks_stay_on_a_display_around_the_main_one() {
+        // The main display, one above and left of it, and a larger one to
+        // its right.
+        let displays = [
+            DisplayBounds {
+                x: 0.0,
+                y: 0.0,
+                width: 1920.0,
+                height: 1080.0,
+            },
+            DisplayBounds {
+                x: -1280.0,
+                y: -1024.0,
+                width: 1280.0,
+                height: 1024.0,
+            },
+            DisplayBounds {
+                x: 1920.0,
+                y: 0.0,
+                width: 2560.0,
+                height: 1440.0,
+            },
+        ];
+        let target = |x: f64, y: f64| {
+            let target = ClickTarget::resolve((x, y), &displays).unwrap();
+            (target.x, target.y, target.display)
+        };
+        assert_eq!(target(100.0, 200.0), (100.0, 200.0, 0));
+        assert_eq!(target(-100.0, -50.0), (-100.0, -50.0, 1));
+        assert_eq!(target(1920.0, 1200.0), (1920.0, 1200.0, 2));
+        // Past the right edge, and in the gap below the upper-left display.
+        assert_eq!(target(4480.0, 10.0), (4479.0, 10.0, 2));
+        assert_eq!(target(-500.0, 100.0), (-500.0, -1.0, 1));
+        assert_eq!(ClickTarget::resolve((0.0, 0.0), &[]), None);
+
+        // Cocoa counts up from the bottom of the main display, so a display
+        // above it is above 1080 there.
+        assert_eq!(global_from_cocoa((100.0, 1080.0), 1080.0), (100.0, 0.0));
+        assert_eq!(global_from_cocoa((-100.0, 1100.0), 1080.0), (-100.0, -20.0));
+        assert_eq!(target(-100.0, -20.0).2, 1);
+    }
+
+    #[test]
+    fn tap_buttons_pair_releases_with_presses() {
+        let mut buttons = TapButtons::default();
+        buttons.press(3, true);
+        buttons.press(0, false);
+        assert!(buttons.release(3));
+        assert!(!buttons.release(0));
+        // Released without a press the tap saw.
+        assert!(!buttons.release(4));
+        assert_eq!(buttons, TapButtons::default());
+    }
+
+    #[test]
+    fn swapped_buttons_stay_swapped_until_released() {
+        let mut buttons = TapButtons::default();
+        assert_eq!(buttons.swap_press(0, true), 1);
+        assert_eq!(buttons.identity(0), 1);
+        assert_eq!(buttons.physical(1), 0);
+        assert_eq!(buttons.physical(0), 0);
+        // Only the primary buttons swap.
+        assert_eq!(buttons.swap_press(2, true), 2);
+        assert_eq!(buttons.swap_release(2), 2);
+        // The release matches the press however the swap is set by then.
+        assert_eq!(buttons.swap_release(0), 1);
+        assert_eq!(buttons.identity(0), 0);
+        assert_eq!(buttons.swap_press(1, false), 1);
+        assert_eq!(buttons.swap_release(1), 1);
+        assert_eq!(buttons, TapButtons::default());
+
+        let config = DeviceConfig {
+            swap_primary_buttons: true,
+            ..DeviceConfig::default()
+        };
+        assert!(config.has_remapping());
+        assert!(ResolvedMappings::build(&config, "mouse", 5, None).swap_primary_buttons);
+        assert!(!ResolvedMappings::default().swap_primary_buttons);
+    }
+
+    #[test]
+    fn wheel_momentum_is_dropped_but_a_trackpads_is_not() {
+        let config = DeviceConfig {
+            disable_scroll_inertia: true,
+            ..DeviceConfig::default()
+        };
+        assert!(config.has_remapping());
+        assert!(ResolvedMappings::build(&config, "mouse", 5, None).disable_scroll_inertia);
+
+        // A flick of the wheel: unphased scrolls, then its momentum.
+        let mut momentum = ScrollMomentum::default();
+        assert!(!momentum.drop_event(0, 0, true));
+        assert!(momentum.drop_event(0, MOMENTUM_PHASE_BEGAN, true));
+        assert!(momentum.drop_event(0, 2, true));
+        // The next scroll stops it and gets through, even mid-momentum.
+        assert!(!momentum.drop_event(0, 0, true));
+        // Momentum that began before the option was on runs to its end.
+        assert!(!momentum.drop_event(0, MOMENTUM_PHASE_BEGAN, false));
+        assert!(!momentum.drop_event(0, 2, true));
+        assert!(!momentum.drop_event(0, 3, true));
+
+        // A trackpad's scrolls have phases; its momentum goes through.
+        for phase in [1, 2, 4] {
+            assert!(!momentum.drop_event(phase, 0, true));
+        }
+        assert!(!momentum.drop_event(0, MOMENTUM_PHASE_BEGAN, true));
+        assert!(!momentum.drop_event(0, 3, true));
+    }
+
+    #[test]
+    fn drags_of_swallowed_buttons_are_hidden() {
+        let mut buttons = TapButtons::default();
+        buttons.press(3, true);
+        assert_eq!(buttons.drag(Some(3), None), DragTranslation::Moved);
+        assert_eq!(buttons.drag(None, None), DragTranslation::Pass);
+
+        buttons.press(1, false);
+        assert_eq!(buttons.drag(Some(3), None), DragTranslation::Dragged(1));
+        assert_eq!(buttons.drag(Some(1), None), DragTranslation::Pass);
+
+        // A held Middle Click mapping drags the middle button.
+        assert_eq!(buttons.drag(Some(3), Some(3)), DragTranslation::Dragged(2));
+        assert_eq!(buttons.drag(None, Some(7)), DragTranslation::Dragged(2));
+    }
+
+    #[test]
+    fn mapping_resolution_names_the_rule_that_decided() {
+        let mut settings = Settings {
+            selected_device: Some("046d:c08b:A".to_string()),
+            excluded_apps: vec!["com.valvesoftware.steam".to_string()],
+            ..Settings::default()
+        };
+        let device: DeviceConfig = serde_json::from_value(serde_json::json!({
+            "name": "G502",
+            "buttons": {"button4": "Back", "button5": "Quick Look"},
+            "modifier_mappings": {"button4": [{"modifiers": ["shift"], "action": "Middle Click"}]},
+            "triggers": {"button4": "release"},
+            "app_overrides": {"com.adobe.Photoshop": {"button4": "Forward"}},
+        }))
+        .unwrap();
+        let profile = settings.profiles.get_mut(DEFAULT_PROFILE).unwrap();
+        profile.devices.insert("046d:c08b:A".to_string(), device);
+        let query = MappingQuery {
+            device_id: "046d:c08b:A",
+            button: 3,
+            connected: true,
+            button_count: 5,
+            ..MappingQuery::default()
+        };
+        let outcome = |settings: &Settings, query: MappingQuery| {
+            let resolution = explain_mapping(settings, &query);
+            assert_eq!(
+                resolution.trace.iter().filter(|step| step.decided).count(),
+                1
+            );
+            assert!(resolution.trace.last().unwrap().decided);
+            (resolution.action, resolution.source)
+        };
+
+        let plain = explain_mapping(&settings, &query);
+        assert!(plain.on_release);
+        let rules: Vec<&str> = plain.trace.iter().map(|step| step.rule).collect();
+        assert_eq!(
+            rules,
+            [
+                "ignored-device",
+                "selected-device",
+                "connected",
+                "profile",
+                "device-config",
+                "device-disabled",
+                "modifier-layer",
+                "button-mapping"
+            ]
+        );
+        assert_eq!(outcome(&settings, query), ("Back", "button-mapping"));
+        let photoshop = MappingQuery {
+            app: Some("com.adobe.Photoshop"),
+            ..query
+        };
+        assert_eq!(outcome(&settings, photoshop), ("Forward", "app-override"));
+        // A modifier layer comes before the app's override; modifiers no
+        // layer has are left to the unmodified mapping.
+        let shift = MappingQuery {
+            held: modifier_mask(&[Modifier::Shift]),
+            ..photoshop
+        };
+        assert_eq!(
+            outcome(&settings, shift),
+            ("Middle Click", "modifier-layer")
+        );
+        let command = MappingQuery {
+            held: modifier_mask(&[Modifier::Command]),
+            ..photoshop
+        };
+        assert_eq!(outcome(&settings, command), ("Forward", "app-override"));
+
+        let quick_look = MappingQuery { button: 4, ..query };
+        assert_eq!(outcome(&settings, quick_look), ("Default", "quick-look"));
+        let finder = MappingQuery {
+            app: Some(FINDER_BUNDLE_ID),
+            ..quick_look
+        };
+        assert_eq!(outcome(&settings, finder), ("Quick Look", "button-mapping"));
+        let steam = MappingQuery {
+            app: Some("com.valvesoftware.steam"),
+            ..query
+        };
+        assert_eq!(outcome(&settings, steam), ("Default", "excluded-app"));
+        let four_buttons = MappingQuery {
+            button_count: 4,
+            ..quick_look
+        };
+        assert_eq!(
+            outcome(&settings, four_buttons),
+            ("Default", "button-count")
+        );
+        let away = MappingQuery {
+            connected: false,
+            ..query
+        };
+        assert_eq!(outcome(&settings, away), ("Default", "connected"));
+
+        // A device standing in for the selected one takes Any mouse's
+        // mappings when it was never set up; any other device isn't
+        // remapped.
+        let spare = MappingQuery {
+            device_id: "1532:0084:1",
+            ..query
+        };
+        assert_eq!(outcome(&settings, spare), ("Default", "selected-device"));
+        let any: DeviceConfig =
+            serde_json::from_value(serde_json::json!({"buttons": {"button4": "Forward"}})).unwrap();
+        let profile = settings.profiles.get_mut(DEFAULT_PROFILE).unwrap();
+        profile.devices.insert(ANY_DEVICE_ID.to_string(), any);
+        let standing_in = MappingQuery {
+            fallback: Some("1532:0084:1"),
+            ..spare
+        };
+        let resolution = explain_mapping(&settings, &standing_in);
+        assert_eq!(resolution.action, "Forward");
+        assert!(resolution.trace[4].detail.contains("Any mouse"));
+        let untouched = MappingQuery {
+            device_id: ANY_DEVICE_ID,
+            ignored_connected: true,
+            ..query
+        };
+        assert_eq!(outcome(&settings, untouched), ("Default", "ignored-device"));
+
+        settings.set_device_disabled("046d:c08b:A", true);
+        assert_eq!(outcome(&settings, query), ("Disabled", "device-disabled"));
+        settings.set_device_disabled("046d:c08b:A", false);
+        // Photoshop's rule switches to a profile without this device.
+        settings
+            .create_profile("Editing", Profile::default())
+            .unwrap();
+        settings.profile_app_rules.push(ProfileAppRule {
+            bundle_id: "com.adobe.Photoshop".to_string(),
+            profile: "Editing".to_string(),
+        });
+        assert_eq!(outcome(&settings, photoshop), ("Default", "device-config"));
+        assert_eq!(outcome(&settings, query), ("Back", "button-mapping"));
+        settings.set_device_ignored("046d:c08b:A", true).unwrap();
+        assert_eq!(outcome(&settings, query), ("Default", "ignored-device"));
+    }
+
+    #[test]
+    fn quick_look_falls_through_outside_its_apps() {
+        let config = DeviceConfig {
+            buttons: HashMap::from([(
+                "button4".to_string(),
+                ActionValue::Action(Action::QuickLook),
+            )]),
+            ..DeviceConfig::default()
+        };
+        let mappings = ResolvedMappings::build(&config, "m", 5, None);
+        assert!(Action::QuickLook.posts_keys());
+        assert_eq!(mappings.mapping(3, 0), None);
+
+        let mappings = ResolvedMappings {
+            quick_look: true,
+            ..mappings
+        };
+        assert_eq!(
+            mappings.mapping(3, 0).map(|mapping| mapping.action()),
+            Some(Action::QuickLook)
+        );
+        assert!(Settings::default()
+            .quick_look_apps
+            .contains(&FINDER_BUNDLE_ID.to_string()));
+    }
+
+    #[test]
+    fn swipes_fall_back_to_keys_where_gestures_cant_be_posted() {
+        assert_eq!(swipe_strategy_for(14, 5, true), SwipeStrategy::Gesture);
+        assert_eq!(swipe_strategy_for(10, 13, true), SwipeStrategy::Gesture);
+        assert_eq!(swipe_strategy_for(10, 12, true), SwipeStrategy::Keys);
+        assert_eq!(swipe_strategy_for(14, 5, false), SwipeStrategy::Keys);
+        assert_eq!(Action::from_id("next_space"), Some(Action::NextSpace));
+        assert_eq!(Action::SwipeBack.swipe(), Some((Swipe::Pages, false)));
+        assert_eq!(Action::Back.swipe(), None);
+        // Only keys are kept from arriving by secure input.
+        assert_eq!(
+            Action::PreviousSpace.posts_keys(),
+            swipe_strategy() == SwipeStrategy::Keys
+        );
+
+        let gestures = action_capabilities(SwipeStrategy::Gesture);
+        assert_eq!(gestures.swipe_labels.len(), 4);
+        assert_eq!(gestures.swipe_labels["swipe_forward"], "Swipe Forward");
+        let keys = action_capabilities(SwipeStrategy::Keys);
+        let (_, shortcut) = SWIPE_KEYS[3];
+        assert_eq!(
+            keys.swipe_labels["next_space"],
+            format!("Next Space ({})", shortcut)
+        );
+    }
+}
@@ -0,0 +1,425 @@
+use super::*;
+
+pub(crate) fn start_mouse_remap(app: tauri::AppHandle, state: AppState) {
+    use std::cell::{Cell, OnceCell};
+    use windows_sys::Win32::Foundation::{LPARAM, LRESULT, POINT, WPARAM};
+    use windows_sys::Win32::System::LibraryLoader::GetModuleHandleW;
+    use windows_sys::Win32::UI::Input::KeyboardAndMouse::{
+        GetAsyncKeyState, SendInput, INPUT, INPUT_0, INPUT_KEYBOARD, INPUT_MOUSE, KEYBDINPUT,
+        KEYEVENTF_KEYUP, MOUSEEVENTF_LEFTDOWN, MOUSEEVENTF_LEFTUP, MOUSEEVENTF_MIDDLEDOWN,
+        MOUSEEVENTF_MIDDLEUP, MOUSEEVENTF_RIGHTDOWN, MOUSEEVENTF_RIGHTUP, MOUSEEVENTF_XDOWN,
+        MOUSEEVENTF_XUP, MOUSEINPUT, VIRTUAL_KEY, VK_BROWSER_BACK, VK_BROWSER_FORWARD, VK_LCONTROL,
+        VK_LEFT, VK_LMENU, VK_LSHIFT, VK_LWIN, VK_RCONTROL, VK_RIGHT, VK_RMENU, VK_RSHIFT, VK_RWIN,
+        VK_TAB,
+    };
+    use windows_sys::Win32::UI::WindowsAndMessaging::{
+        CallNextHookEx, GetCursorPos, GetMessageW, KillTimer, SetTimer, SetWindowsHookExW,
+        UnhookWindowsHookEx, HC_ACTION, HHOOK, MSG, MSLLHOOKSTRUCT, WH_MOUSE_LL, WM_LBUTTONDOWN,
+        WM_LBUTTONUP, WM_MBUTTONDOWN, WM_MBUTTONUP, WM_MOUSEMOVE, WM_RBUTTONDOWN, WM_RBUTTONUP,
+        WM_TIMER, WM_XBUTTONDOWN, WM_XBUTTONUP, XBUTTON1, XBUTTON2,
+    };
+
+    /// Left and right key of each modifier; the left one is what gets posted.
+    const MODIFIER_KEYS: [([VIRTUAL_KEY; 2], Modifier); 4] = [
+        ([VK_LSHIFT, VK_RSHIFT], Modifier::Shift),
+        ([VK_LCONTROL, VK_RCONTROL], Modifier::Control),
+        ([VK_LMENU, VK_RMENU], Modifier::Option),
+        ([VK_LWIN, VK_RWIN], Modifier::Command),
+    ];
+    /// `HOOK_REMOVED_LIMIT` removals within `HOOK_REMOVED_WINDOW` are
+    /// reported; a single one is just reinstalled.
+    const HOOK_REMOVED_LIMIT: u32 = 3;
+    const HOOK_REMOVED_WINDOW: Duration = Duration::from_secs(60);
+
+    /// What the hook procedure needs, set once per hook thread. The hook is
+    /// only ever called on the thread that installed it.
+    struct HookContext {
+        app: tauri::AppHandle,
+        state: AppState,
+        buttons: Cell<TapButtons>,
+        /// Where the cursor is after the last move, for gesture deltas.
+        cursor: Cell<Option<POINT>>,
+        /// Events seen, so the pump can tell a hook Windows removed.
+        events: Cell<u64>,
+    }
+
+    thread_local! {
+        static HOOK: OnceCell<HookContext> = const { OnceCell::new() };
+    }
+
+    /// Posts with `SendInput`, tagging every event through `dwExtraInfo`.
+    struct HookOutput;
+
+    impl RemapOutput for HookOutput {
+        fn detached(&self) -> Option<Box<dyn RemapOutput + Send>> {
+            Some(Box::new(HookOutput))
+        }
+
+        fn post_navigation(&self, forward: bool, merged: ModifierMask) -> Result<(), String> {
+            let key = if forward {
+                VK_BROWSER_FORWARD
+            } else {
+                VK_BROWSER_BACK
+            };
+            post_key_combo(key, merged)
+        }
+
+        fn post_tab_switch(
+            &self,
+            next: bool,
+            _keys: TabKeys,
+            merged: ModifierMask,
+        ) -> Result<(), String> {
+            let modifiers: &[Modifier] = if next {
+                &[Modifier::Control]
+            } else {
+                &[Modifier::Control, Modifier::Shift]
+            };
+            post_key_combo(VK_TAB, merged | modifier_mask(modifiers))
+        }
+
+        /// Switches virtual desktops with Ctrl+Win+Left and Right.
+        fn post_swipe(
+            &self,
+            swipe: Swipe,
+            forward: bool,
+            merged: ModifierMask,
+        ) -> Result<(), String> {
+            match swipe {
+                Swipe::Pages => self.post_navigation(forward, merged),
+                Swipe::Spaces => {
+                    let key = if forward { VK_RIGHT } else { VK_LEFT };
+                    let modifiers = modifier_mask(&[Modifier::Control, Modifier::Command]);
+                    post_key_combo(key, merged | modifiers)
+                }
+            }
+        }
+
+        fn post_mouse_event(
+            &self,
+            button: i64,
+            down: bool,
+            _click_state: i64,
+            _modifiers: ModifierMask,
+        ) -> Result<(), String> {
+            // Windows numbers multi-clicks itself from their timing.
+            let (flags, data) = match (button, down) {
+                (0, true) => (MOUSEEVENTF_LEFTDOWN, 0),
+                (0, false) => (MOUSEEVENTF_LEFTUP, 0),
+                (1, true) => (MOUSEEVENTF_RIGHTDOWN, 0),
+                (1, false) => (MOUSEEVENTF_RIGHTUP, 0),
+                (3, true) => (MOUSEEVENTF_XDOWN, XBUTTON1),
+                (3, false) => (MOUSEEVENTF_XUP, XBUTTON1),
+                (4, true) => (MOUSEEVENTF_XDOWN, XBUTTON2),
+                (4, false) => (MOUSEEVENTF_XUP, XBUTTON2),
+                (_, true) => (MOUSEEVENTF_MIDDLEDOWN, 0),
+                (_, false) => (MOUSEEVENTF_MIDDLEUP, 0),
+            };
+            let input = INPUT {
+                r#type: INPUT_MOUSE,
+                Anonymous: INPUT_0 {
+                    mi: MOUSEINPUT {
+                        mouseData: u32::from(data),
+                        dwFlags: flags,
+                        dwExtraInfo: SYNTHETIC_EVENT_TAG as usize,
+                        ..Default::default()
+                    },
+                },
+            };
+            send_inputs(&[input]).map_err(|_| format!("could not click button {}", button))
+        }
+    }
+
+    let extended_app = app.clone();
+    let extended_state = state.clone();
+    start_extended_buttons(state.clone(), move |button, down| {
+        let ends_drag = !down && extended_state.middle_drag_button() == Some(button);
+        if !ends_drag && extended_state.is_passing_through() {
+            return;
+        }
+        let held = held_modifiers();
+        dispatch_button(
+            &extended_app,
+            &extended_state,
+            &HookOutput,
+            button,
+            down,
+            held,
+        );
+    });
+
+    let _ = state.action_tester.set(|app, state, test| {
+        run_action_test(app, state, &HookOutput, test);
+    });
+    supervise_engine(app, state, "mouse-hook", run_mouse_hook);
+
+    fn install_hook() -> HHOOK {
+        unsafe {
+            SetWindowsHookExW(
+                WH_MOUSE_LL,
+                Some(mouse_hook),
+                GetModuleHandleW(std::ptr::null()),
+                0,
+            )
+        }
+    }
+
+    fn cursor_position() -> Option<POINT> {
+        let mut point = POINT { x: 0, y: 0 };
+        (unsafe { GetCursorPos(&mut point) } != 0).then_some(point)
+    }
+
+    /// Installs the low-level mouse hook and pumps this thread's messages,
+    /// which is where the hook gets called, until it stops being wanted.
+    /// Windows removes a hook that keeps taking too long without telling it,
+    /// so a cursor that moved while the hook saw nothing means it's gone and
+    /// it is installed again.
+    fn run_mouse_hook(app: &tauri::AppHandle, state: &AppState) -> EngineState {
+        HOOK.with(|hook| {
+            let _ = hook.set(HookContext {
+                app: app.clone(),
+                state: state.clone(),
+                buttons: Cell::new(TapButtons::default()),
+                cursor: Cell::new(None),
+                events: Cell::new(0),
+            });
+        });
+        let mut hook = install_hook();
+        if hook.is_null() {
+            report_remap_error(
+                app,
+                state,
+                "tap-create-failed",
+                "could not install the mouse hook".to_string(),
+            );
+            return EngineState::Failed;
+        }
+        let interval = u32::try_from(TAP_LIFECYCLE_CHECK.as_millis()).unwrap_or(u32::MAX);
+        let timer = unsafe { SetTimer(std::ptr::null_mut(), 0, interval, None) };
+        state.set_tap_enabled(true, false);
+        report_engine_state(app, state, EngineState::Running);
+
+        let mut removals = (Instant::now(), 0u32);
+        let mut checked = (
+            HOOK.with(|hook| hook.get().map_or(0, |hook| hook.events.get())),
+            cursor_position(),
+        );
+        let mut msg: MSG = unsafe { std::mem::zeroed() };
+        let stopped = loop {
+            let result = unsafe { GetMessageW(&mut msg, std::ptr::null_mut(), 0, 0) };
+            if result <= 0 {
+                break EngineState::Failed;
+            }
+            if msg.message != WM_TIMER {
+                continue;
+            }
+            if state.take_engine_restart() {
+                break EngineState::Starting;
+            }
+            if !state.is_event_tap_wanted() {
+                break EngineState::Idle;
+            }
+            let events = HOOK.with(|hook| hook.get().map_or(0, |hook| hook.events.get()));
+            let cursor = cursor_position();
+            let moved = match (checked.1, cursor) {
+                (Some(before), Some(now)) => before.x != now.x || before.y != now.y,
+                _ => false,
+            };
+            if moved && events == checked.0 {
+                state.set_tap_enabled(false, true);
+                unsafe { UnhookWindowsHookEx(hook) };
+                hook = install_hook();
+                if hook.is_null() {
+                    report_remap_error(
+                        app,
+                        state,
+                        "tap-create-failed",
+                        "could not reinstall the mouse hook".to_string(),
+                    );
+                    break EngineState::Failed;
+                }
+                state.set_tap_enabled(true, false);
+                log::info!("mouse-remap: reinstalled the mouse hook Windows removed");
+                // Its release may have been among the events missed.
+                release_middle_drag(app, state, &HookOutput);
+                removals = if removals.0.elapsed() > HOOK_REMOVED_WINDOW {
+                    (Instant::now(), 1)
+                } else {
+                    (removals.0, removals.1 + 1)
+                };
+                if removals.1 == HOOK_REMOVED_LIMIT {
+                    report_remap_error(
+                        app,
+                        state,
+                        "tap-timeout",
+                        "Windows keeps removing the mouse hook because events took too long"
+                            .to_string(),
+                    );
+                }
+            }
+            checked = (events, cursor);
+        };
+        unsafe {
+            KillTimer(std::ptr::null_mut(), timer);
+            if !hook.is_null() {
+                UnhookWindowsHookEx(hook);
+            }
+        }
+        state.set_tap_enabled(false, false);
+        release_middle_drag(app, state, &HookOutput);
+        match stopped {
+            EngineState::Idle => {
+                log::info!("mouse-remap: nothing to remap, removing the mouse hook")
+            }
+            EngineState::Starting => log::info!("mouse-remap: restarting the mouse hook"),
+            _ => report_remap_error(
+                app,
+                state,
+                "tap-stopped",
+                "the mouse hook's message loop stopped".to_string(),
+            ),
+        }
+        stopped
+    }
+
+    unsafe extern "system" fn mouse_hook(code: i32, wparam: WPARAM, lparam: LPARAM) -> LRESULT {
+        let swallow = code == HC_ACTION as i32
+            && HOOK.with(|hook| {
+                hook.get().is_some_and(|hook| {
+                    // For HC_ACTION, lparam points at the event's details.
+                    let info = &*(lparam as *const MSLLHOOKSTRUCT);
+                    let mut timer = hook.state.time_callback();
+                    timer.remapped(guard_event(
+                        &hook.app,
+                        &hook.state,
+                        || handle_mouse_event(hook, wparam as u32, info),
+                        || false,
+                    ))
+                })
+            });
+        if swallow {
+            1
+        } else {
+            CallNextHookEx(std::ptr::null_mut(), code, wparam, lparam)
+        }
+    }
+
+    /// Returns true when the event should be swallowed.
+    fn handle_mouse_event(hook: &HookContext, message: u32, info: &MSLLHOOKSTRUCT) -> bool {
+        let state = &hook.state;
+        hook.events.set(hook.events.get().wrapping_add(1));
+        let user_data = info.dwExtraInfo as i64;
+        if user_data == SYNTHETIC_EVENT_TAG {
+            return false;
+        }
+        if message == WM_MOUSEMOVE {
+            let last = hook.cursor.replace(Some(info.pt));
+            if !state.should_remap(user_data) {
+                return false;
+            }
+            let Some(last) = last else {
+                return false;
+            };
+            let dx = f64::from(info.pt.x - last.x);
+            let dy = f64::from(info.pt.y - last.y);
+            if state.track_gesture(dx, dy) {
+                // The cursor stays put, so the next delta starts from here.
+                hook.cursor.set(Some(last));
+                return true;
+            }
+            return false;
+        }
+        let (button, down) = match message {
+            WM_LBUTTONDOWN => (0, true),
+            WM_LBUTTONUP => (0, false),
+            WM_RBUTTONDOWN => (1, true),
+            WM_RBUTTONUP => (1, false),
+            WM_MBUTTONDOWN => (2, true),
+            WM_MBUTTONUP => (2, false),
+            WM_XBUTTONDOWN | WM_XBUTTONUP => {
+                let button = if (info.mouseData >> 16) as u16 == XBUTTON2 {
+                    4
+                } else {
+                    3
+                };
+                (button, message == WM_XBUTTONDOWN)
+            }
+            _ => return false,
+        };
+        let held = held_modifiers();
+        let mut pressed = hook.buttons.get();
+        let swallow = if down {
+            let swallowed = state.should_remap(user_data)
+                && dispatch_button(&hook.app, state, &HookOutput, button, down, held);
+            pressed.press(button, swallowed);
+            swallowed
+        } else {
+            // A swallowed press is finished even if remapping was turned off
+            // meanwhile, and its release swallowed too.
+            let swallowed = pressed.release(button);
+            if swallowed || state.should_remap(user_data) {
+                dispatch_button(&hook.app, state, &HookOutput, button, down, held);
+            }
+            swallowed
+        };
+        hook.buttons.set(pressed);
+        swallow
+    }
+
+    fn is_key_down(key: VIRTUAL_KEY) -> bool {
+        // The high bit is set while the key is down.
+        let state = unsafe { GetAsyncKeyState(i32::from(key)) };
+        state < 0
+    }
+
+    fn held_modifiers() -> ModifierMask {
+        MODIFIER_KEYS
+            .iter()
+            .filter(|(keys, _)| keys.iter().any(|key| is_key_down(*key)))
+            .fold(0, |mask, (_, modifier)| mask | modifier_mask(&[*modifier]))
+    }
+
+    fn key_input(key: VIRTUAL_KEY, down: bool) -> INPUT {
+        INPUT {
+            r#type: INPUT_KEYBOARD,
+            Anonymous: INPUT_0 {
+                ki: KEYBDINPUT {
+                    wVk: key,
+                    dwFlags: if down { 0 } else { KEYEVENTF_KEYUP },
+                    dwExtraInfo: SYNTHETIC_EVENT_TAG as usize,
+                    ..Default::default()
+                },
+            },
+        }
+    }
+
+    /// Taps `key` with the `merged` modifiers the user isn't already holding
+    /// pressed around it. Held modifiers are left alone: releasing the
+    /// Windows key on its own would open the Start menu.
+    fn post_key_combo(key: VIRTUAL_KEY, merged: ModifierMask) -> Result<(), String> {
+        let added: Vec<VIRTUAL_KEY> = MODIFIER_KEYS
+            .iter()
+            .filter(|(keys, modifier)| {
+                merged & modifier_mask(&[*modifier]) != 0
+                    && !keys.iter().any(|key| is_key_down(*key))
+            })
+            .map(|(keys, _)| keys[0])
+            .collect();
+        let mut inputs: Vec<INPUT> = added.iter().map(|key| key_input(*key, true)).collect();
+        inputs.push(key_input(key, true));
+        inputs.push(key_input(key, false));
+        inputs.extend(added.iter().rev().map(|key| key_input(*key, false)));
+        send_inputs(&inputs).map_err(|_| format!("could not send virtual key {}", key))
+    }
+
+    fn send_inputs(inputs: &[INPUT]) -> Result<(), ()> {
+        let count = u32::try_from(inputs.len()).map_err(|_| ())?;
+        let size = i32::try_from(std::mem::size_of::<INPUT>()).map_err(|_| ())?;
+        let sent = unsafe { SendInput(count, inputs.as_ptr(), size) };
+        if sent == count {
+            Ok(())
+        } else {
+            Err(())
+        }
+    }
+}
@@ -0,0 +1,382 @@
+use super::*;
+
+/// A moment as profile schedules see it: the local day and time of day.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct LocalTime {
+    pub(crate) weekday: Weekday,
+    /// Minutes since local midnight.
+    pub(crate) minute: u16,
+}
+
+impl LocalTime {
+    /// `unix` seconds since the epoch, in a zone `offset` seconds ahead of
+    /// UTC at that moment.
+    pub(crate) fn from_offset(unix: i64, offset: i64) -> Self {
+        let local = unix + offset;
+        // The epoch was a Thursday.
+        let day = (local.div_euclid(86_400) + 3).rem_euclid(7);
+        Self {
+            weekday: Weekday::ALL[day as usize],
+            minute: (local.rem_euclid(86_400) / 60) as u16,
+        }
+    }
+
+    /// Now, in the system's time zone as it is now; `None` when the zone
+    /// can't be read.
+    pub(crate) fn now() -> Option<Self> {
+        let unix = SystemTime::now().duration_since(UNIX_EPOCH).ok()?.as_secs();
+        let unix = i64::try_from(unix).ok()?;
+        Some(Self::from_offset(unix, utc_offset(unix)?))
+    }
+}
+
+/// Seconds the system's time zone is ahead of UTC at `unix`.
+#[cfg(target_os = "macos")]
+fn utc_offset(unix: i64) -> Option<i64> {
+    use core_foundation::date::CFDate;
+    use core_foundation::timezone::{CFTimeZone, CFTimeZoneResetSystem};
+
+    /// Seconds from the Unix epoch to Core Foundation's, 2001-01-01.
+    const CF_EPOCH: i64 = 978_307_200;
+
+    // Core Foundation caches the zone; this picks up a change since.
+    unsafe { CFTimeZoneResetSystem() };
+    let date = CFDate::new((unix - CF_EPOCH) as f64);
+    Some(CFTimeZone::system().seconds_from_gmt(date) as i64)
+}
+
+#[cfg(target_os = "linux")]
+fn utc_offset(unix: i64) -> Option<i64> {
+    let time = libc::time_t::try_from(unix).ok()?;
+    let mut local: libc::tm = unsafe { std::mem::zeroed() };
+    if unsafe { libc::localtime_r(&time, &mut local) }.is_null() {
+        return None;
+    }
+    Some(local.tm_gmtoff as i64)
+}
+
+#[cfg(windows)]
+fn utc_offset(unix: i64) -> Option<i64> {
+    use windows_sys::Win32::Foundation::SYSTEMTIME;
+    use windows_sys::Win32::System::Time::SystemTimeToTzSpecificLocalTime;
+
+    let [year, month, day, hour, minute, second] = logging::civil(unix);
+    let utc = SYSTEMTIME {
+        wYear: u16::try_from(year).ok()?,
+        wMonth: month as u16,
+        wDayOfWeek: 0,
+        wDay: day as u16,
+        wHour: hour as u16,
+        wMinute: minute as u16,
+        wSecond: second as u16,
+        wMilliseconds: 0,
+    };
+    let mut local = utc;
+    if unsafe { SystemTimeToTzSpecificLocalTime(std::ptr::null(), &utc, &mut local) } == 0 {
+        return None;
+    }
+    // No zone is a day away, so the dates differ by one at most.
+    let days = match (local.wYear, local.wMonth, local.wDay).cmp(&(utc.wYear, utc.wMonth, utc.wDay))
+    {
+        std::cmp::Ordering::Less => -1,
+        std::cmp::Ordering::Equal => 0,
+        std::cmp::Ordering::Greater => 1,
+    };
+    let seconds = |time: &SYSTEMTIME| {
+        i64::from(time.wHour) * 3600 + i64::from(time.wMinute) * 60 + i64::from(time.wSecond)
+    };
+    Some(days * 86_400 + seconds(&local) - seconds(&utc))
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "linux", windows)))]
+fn utc_offset(_unix: i64) -> Option<i64> {
+    None
+}
+
+impl ProfileSchedule {
+    /// Whether `at` falls in this time. One ending on the next day covers
+    /// the small hours after each of its days.
+    pub(crate) fn covers(&self, at: LocalTime) -> bool {
+        let (Some(start), Some(end)) = (minute_of_day(&self.start), minute_of_day(&self.end))
+        else {
+            return false;
+        };
+        let on = |day: Weekday| self.days.is_empty() || self.days.contains(&day);
+        if start < end {
+            on(at.weekday) && (start..end).contains(&at.minute)
+        } else {
+            (on(at.weekday) && at.minute >= start) || (on(at.weekday.previous()) && at.minute < end)
+        }
+    }
+}
+
+/// The profile the schedules pick at `at`: the first in `profile_names`
+/// order with a time covering it, so that one wins an overlap.
+pub(crate) fn scheduled_profile(settings: &Settings, at: LocalTime) -> Option<String> {
+    settings.profile_names().into_iter().find(|name| {
+        settings.profiles[name]
+            .schedule
+            .iter()
+            .any(|schedule| schedule.covers(at))
+    })
+}
+
+/// Bookkeeping for profile schedules.
+#[derive(Debug, Default)]
+pub(crate) struct ProfileScheduler {
+    /// What the schedules picked when last evaluated; `None` before the
+    /// first evaluation. Only a change of it switches, so a profile picked
+    /// by hand stays until a time starts or ends.
+    picked: Option<Option<String>>,
+    /// Profile that was active before a schedule switched away from it,
+    /// restored once no time covers the moment.
+    previous: Option<String>,
+}
+
+impl ProfileScheduler {
+    /// The profile to switch to at `at`, if the schedules now pick another
+    /// than when last evaluated.
+    pub(crate) fn evaluate(&mut self, settings: &Settings, at: LocalTime) -> Option<String> {
+        let picked = scheduled_profile(settings, at);
+        if self.picked.as_ref() == Some(&picked) {
+            return None;
+        }
+        self.picked = Some(picked.clone());
+        let target = match picked {
+            Some(name) => {
+                if self.previous.is_none() {
+                    self.previous = Some(settings.active_profile.clone());
+                }
+                Some(name)
+            }
+            None => self
+                .previous
+                .take()
+                .filter(|name| settings.profiles.contains_key(name)),
+        };
+        target.filter(|name| *name != settings.active_profile)
+    }
+
+    /// A switch the user made by hand wins over the schedules: it stays
+    /// until the next time starts or ends, and nothing is restored then.
+    pub(crate) fn on_manual_switch(&mut self) {
+        self.previous = None;
+    }
+}
+
+/// Evaluates the schedules at the turn of every minute, and whenever
+/// `AppState::nudge_profile_schedule` says the clock may have jumped.
+pub(crate) fn watch(app: tauri::AppHandle, state: AppState) {
+    use std::sync::mpsc::RecvTimeoutError;
+
+    let (sender, receiver) = std::sync::mpsc::channel();
+    if state.schedule_nudge.set(sender).is_err() {
+        return;
+    }
+    std::thread::spawn(move || loop {
+        evaluate(&app, &state);
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        let wait = Duration::from_secs(60 - now.as_secs() % 60);
+        if let Err(RecvTimeoutError::Disconnected) = receiver.recv_timeout(wait) {
+            return;
+        }
+    });
+}
+
+fn evaluate(app: &tauri::AppHandle, state: &AppState) {
+    let settings = state.snapshot_settings();
+    if settings
+        .profiles
+        .values()
+        .all(|profile| profile.schedule.is_empty())
+    {
+        return;
+    }
+    let Some(at) = LocalTime::now() else {
+        log::warn!("profiles: couldn't read the local time for schedules");
+        return;
+    };
+    let Some(name) = state.scheduled_profile_switch(&settings, at) else {
+        return;
+    };
+    log::info!("profiles: schedule picks {}", name);
+    if let Err(err) = activate_profile(app, &name) {
+        log::error!("profiles: failed to switch to {}: {}", name, err);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 2024-01-05, a Friday, at midnight UTC.
+    const FRIDAY: i64 = 1_704_412_800;
+
+    fn at(weekday: Weekday, time: &str) -> LocalTime {
+        LocalTime {
+            weekday,
+            minute: minute_of_day(time).unwrap(),
+        }
+    }
+
+    fn schedule(days: &[Weekday], start: &str, end: &str) -> ProfileSchedule {
+        ProfileSchedule {
+            days: days.to_vec(),
+            start: start.into(),
+            end: end.into(),
+        }
+    }
+
+    fn settings_with(schedules: &[(&str, ProfileSchedule)]) -> Settings {
+        let mut settings = Settings::default();
+        for (name, schedule) in schedules {
+            settings
+                .profiles
+                .entry(name.to_string())
+                .or_default()
+                .schedule
+                .push(schedule.clone());
+        }
+        settings
+    }
+
+    #[test]
+    fn times_ending_the_next_day_cover_the_small_hours_after_their_days() {
+        let late = schedule(&[Weekday::Friday], "22:00", "02:00");
+        assert!(late.covers(at(Weekday::Friday, "22:00")));
+        assert!(late.covers(at(Weekday::Saturday, "01:59")));
+        assert!(!late.covers(at(Weekday::Saturday, "02:00")));
+        assert!(!late.covers(at(Weekday::Friday, "01:00")));
+        assert!(!late.covers(at(Weekday::Sunday, "01:00")));
+        // Sunday's night runs into Monday.
+        let sunday = schedule(&[Weekday::Sunday], "23:00", "01:00");
+        assert!(sunday.covers(at(Weekday::Monday, "00:30")));
+
+        let whole_day = schedule(&[Weekday::Monday], "06:00", "06:00");
+        assert!(whole_day.covers(at(Weekday::Tuesday, "05:59")));
+        assert!(!whole_day.covers(at(Weekday::Tuesday, "06:00")));
+        let office = schedule(&[], "09:00", "17:30");
+        assert!(office.covers(at(Weekday::Sunday, "17:29")));
+        assert!(!office.covers(at(Weekday::Sunday, "17:30")));
+        assert!(!schedule(&[], "9", "17:30").covers(at(Weekday::Sunday, "12:00")));
+    }
+
+    #[test]
+    fn local_time_follows_the_offset_across_daylight_saving_changes() {
+        assert_eq!(
+            LocalTime::from_offset(FRIDAY, 0),
+            at(Weekday::Friday, "00:00")
+        );
+        assert_eq!(
+            LocalTime::from_offset(FRIDAY - 1, 0),
+            at(Weekday::Thursday, "23:59")
+        );
+        assert_eq!(
+            LocalTime::from_offset(FRIDAY, 14 * 3600),
+            at(Weekday::Friday, "14:00")
+        );
+        assert_eq!(
+            LocalTime::from_offset(0, -3600),
+            at(Weekday::Wednesday, "23:00")
+        );
+
+        // Spring forward: 01:59 at UTC-5 is followed by 03:00 at UTC-4, with
+        // no 02:30. A time from 02:00 to 03:00 is skipped that night.
+        let forward = FRIDAY + 2 * 86_400 + 7 * 3600;
+        let night = schedule(&[], "02:00", "03:00");
+        let before = LocalTime::from_offset(forward - 60, -5 * 3600);
+        let after = LocalTime::from_offset(forward, -4 * 3600);
+        assert_eq!(before, at(Weekday::Sunday, "01:59"));
+        assert_eq!(after, at(Weekday::Sunday, "03:00"));
+        assert!(!night.covers(before) && !night.covers(after));
+        let mut scheduler = ProfileScheduler::default();
+        let settings = settings_with(&[("Night", night.clone())]);
+        assert_eq!(scheduler.evaluate(&settings, before), None);
+        assert_eq!(scheduler.evaluate(&settings, after), None);
+
+        // Fall back: 01:00 to 02:00 happens twice, at UTC-4 then UTC-5.
+        // Schedules go by the clock, so a time ending at 01:30 covers both.
+        let back = forward + 7 * 86_400 - 3600;
+        let mut settings = settings_with(&[("Early", schedule(&[], "00:00", "01:30"))]);
+        let mut scheduler = ProfileScheduler::default();
+        let moments = [
+            (back - 3600, -4, "01:00", Some("Early")),
+            (back - 1800, -4, "01:30", Some(DEFAULT_PROFILE)),
+            (back, -5, "01:00", Some("Early")),
+            (back + 1800, -5, "01:30", Some(DEFAULT_PROFILE)),
+            (back + 3600, -5, "02:00", None),
+        ];
+        for (unix, offset, time, switch) in moments {
+            let local = LocalTime::from_offset(unix, offset * 3600);
+            assert_eq!(local, at(Weekday::Sunday, time));
+            let target = scheduler.evaluate(&settings, local);
+            assert_eq!(target.as_deref(), switch, "at {} UTC{}", time, offset);
+            if let Some(target) = target {
+                settings.active_profile = target;
+            }
+        }
+    }
+
+    #[test]
+    fn overlapping_times_go_to_the_first_profile() {
+        let settings = settings_with(&[
+            ("Work", schedule(&[Weekday::Monday], "09:00", "18:00")),
+            ("Demo", schedule(&[Weekday::Monday], "14:00", "15:00")),
+        ]);
+        let monday = |time| scheduled_profile(&settings, at(Weekday::Monday, time));
+        assert_eq!(monday("14:30"), Some("Demo".to_string()));
+        assert_eq!(monday("15:00"), Some("Work".to_string()));
+        assert_eq!(monday("18:00"), None);
+        assert_eq!(
+            scheduled_profile(&settings, at(Weekday::Tuesday, "14:30")),
+            None
+        );
+    }
+
+    #[test]
+    fn a_switch_by_hand_stays_until_the_next_boundary() {
+        let mut settings =
+            settings_with(&[("Work", schedule(&[Weekday::Monday], "09:00", "18:00"))]);
+        settings
+            .create_profile("Games", Profile::default())
+            .unwrap();
+        let mut scheduler = ProfileScheduler::default();
+        let monday = |time| at(Weekday::Monday, time);
+
+        assert_eq!(scheduler.evaluate(&settings, monday("08:59")), None);
+        assert_eq!(
+            scheduler.evaluate(&settings, monday("09:00")),
+            Some("Work".to_string())
+        );
+        settings.active_profile = "Work".into();
+        assert_eq!(scheduler.evaluate(&settings, monday("09:01")), None);
+
+        // Switched to Games by hand: the schedule leaves it be, even once
+        // Work's time is over.
+        scheduler.on_manual_switch();
+        settings.active_profile = "Games".into();
+        assert_eq!(scheduler.evaluate(&settings, monday("12:00")), None);
+        assert_eq!(scheduler.evaluate(&settings, monday("18:00")), None);
+
+        // The next start switches again, and its end restores the profile
+        // from before.
+        let tuesday = |time| at(Weekday::Tuesday, time);
+        settings
+            .profiles
+            .get_mut("Work")
+            .unwrap()
+            .schedule
+            .push(schedule(&[Weekday::Tuesday], "09:00", "18:00"));
+        assert_eq!(
+            scheduler.evaluate(&settings, tuesday("09:00")),
+            Some("Work".to_string())
+        );
+        settings.active_profile = "Work".into();
+        assert_eq!(
+            scheduler.evaluate(&settings, tuesday("18:00")),
+            Some("Games".to_string())
+        );
+    }
+}
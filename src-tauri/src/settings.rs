@@ -0,0 +1,3782 @@
+use super::*;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub(crate) struct DeviceConfig {
+    pub(crate) name: String,
+    pub(crate) buttons: HashMap<String, ActionValue>,
+    /// Read buttons that never reach the event tap straight from the
+    /// device's HID reports. Opt-in because it holds the device open.
+    pub(crate) extended_buttons: bool,
+    /// Exchange left and right click, for left-handed use. Mappings of
+    /// `left` and `right` apply to the button acting as that one, so the
+    /// physical right button fires `left`'s mapping. Only the macOS event
+    /// tap swaps them, and only while this device's mappings are in effect.
+    pub(crate) swap_primary_buttons: bool,
+    /// Drop the momentum scrolling that follows a flick of the wheel, so
+    /// scrolling stops when the wheel does. Like `swap_primary_buttons`,
+    /// only the macOS event tap does it, while this device's mappings are
+    /// in effect.
+    pub(crate) disable_scroll_inertia: bool,
+    /// Sensor DPI values `CycleDpi` steps through.
+    pub(crate) dpi_stops: Vec<u32>,
+    /// Swallow every button of the device, as if each were mapped to
+    /// Disabled, without touching the mappings themselves.
+    pub(crate) disabled: bool,
+    /// Per-application button maps keyed by bundle id, consulted before
+    /// `buttons` while that application is frontmost.
+    pub(crate) app_overrides: HashMap<String, HashMap<String, ActionValue>>,
+    /// Per-button mappings conditioned on held modifiers, checked in order
+    /// before any other mapping of that button.
+    pub(crate) modifier_mappings: HashMap<String, Vec<ModifierMapping>>,
+    /// Button keys whose key combos keep the modifiers the user is holding,
+    /// so Shift with a Back button sends Cmd+Shift+[. Other buttons send
+    /// their combo alone.
+    pub(crate) merge_modifiers: Vec<String>,
+    /// Button keys whose synthetic clicks are posted without the modifiers
+    /// the user is holding. Other buttons' clicks keep them, so Cmd with a
+    /// button mapped to Middle Click still Cmd-clicks. Only macOS posts
+    /// clicks with flags of their own; elsewhere the system applies the
+    /// held modifiers to every click.
+    pub(crate) strip_modifiers: Vec<String>,
+    /// Button keys whose mappings fire on release rather than press.
+    /// Unlisted buttons fire on press.
+    pub(crate) triggers: HashMap<String, Trigger>,
+    /// Button keys that play a sound or tap the trackpad when their
+    /// mapping fires, unless `Settings::mute_feedback` is on.
+    pub(crate) feedback: HashMap<String, Feedback>,
+    /// Per-button pause between the clicks of a Double Click mapping, in
+    /// place of `Settings::double_click_interval_ms`.
+    pub(crate) double_click_interval_ms: HashMap<String, u32>,
+    /// Per-button time after a press within which another press is taken
+    /// for switch bounce and swallowed along with its release, for a worn
+    /// switch that double-clicks. Applies to buttons left at Default too.
+    pub(crate) debounce_ms: HashMap<String, u32>,
+    /// Button keys whose key actions, such as a Shortcut, fire again and
+    /// again while held. Next Tab and Previous Tab always do.
+    pub(crate) repeat_while_held: Vec<String>,
+    /// Unix time, in seconds, the device was first and most recently seen
+    /// connected. Written in batches, so `last_seen` can trail by up to
+    /// `SIGHTINGS_FLUSH_INTERVAL`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) first_seen: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) last_seen: Option<u64>,
+    /// What happens, in order, when the device connects while the app is
+    /// running; at most once every `DEVICE_NOTICE_INTERVAL`.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub(crate) on_connect: Vec<ConnectRule>,
+    /// Added by the app when the device was first seen, and not changed
+    /// since; see `Settings::adopt_new_devices`.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub(crate) pristine: bool,
+}
+
+/// How long a device with a pristine config can go unseen before
+/// `Settings::forget_device` drops the config along the way.
+pub(crate) const STALE_PRISTINE_DEVICE: Duration = Duration::from_secs(90 * 24 * 60 * 60);
+
+impl Default for DeviceConfig {
+    fn default() -> Self {
+        Self {
+            name: String::new(),
+            buttons: default_buttons(DEFAULT_BUTTON_COUNT),
+            extended_buttons: false,
+            swap_primary_buttons: false,
+            disable_scroll_inertia: false,
+            dpi_stops: Vec::new(),
+            disabled: false,
+            app_overrides: HashMap::new(),
+            modifier_mappings: HashMap::new(),
+            merge_modifiers: Vec::new(),
+            strip_modifiers: Vec::new(),
+            triggers: HashMap::new(),
+            feedback: HashMap::new(),
+            double_click_interval_ms: HashMap::new(),
+            debounce_ms: HashMap::new(),
+            repeat_while_held: Vec::new(),
+            first_seen: None,
+            last_seen: None,
+            on_connect: Vec::new(),
+            pristine: false,
+        }
+    }
+}
+
+/// Something a device's connecting does; see `DeviceConfig::on_connect`.
+/// Stored tagged by `type`, such as `{"type": "switch_profile", "profile":
+/// "Art"}`.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub(crate) enum ConnectRule {
+    SwitchProfile {
+        profile: String,
+    },
+    /// Turns remapping on or off. Turning it on also ends a pause.
+    SetRemapEnabled {
+        enabled: bool,
+    },
+    /// Runs `action` as a button mapped to it would.
+    RunAction {
+        action: ActionValue,
+    },
+}
+
+/// How bad a `SettingsIssue` is: `save_settings` refuses settings with
+/// errors and saves them with warnings.
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum IssueSeverity {
+    Error,
+    Warning,
+}
+
+/// A problem `validate_settings` found, located precisely enough for the
+/// settings window to show it next to the control it's about.
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub(crate) struct SettingsIssue {
+    /// Settings field, such as `"buttons"` or `"selected_device"`.
+    pub(crate) field: &'static str,
+    pub(crate) profile: Option<String>,
+    pub(crate) device_id: Option<String>,
+    pub(crate) button: Option<String>,
+    /// Stable identifier such as `"unknown-action"`.
+    pub(crate) code: &'static str,
+    pub(crate) message: String,
+    pub(crate) severity: IssueSeverity,
+}
+
+impl SettingsIssue {
+    pub(crate) fn error(field: &'static str, code: &'static str, message: String) -> Self {
+        Self {
+            field,
+            profile: None,
+            device_id: None,
+            button: None,
+            code,
+            message,
+            severity: IssueSeverity::Error,
+        }
+    }
+
+    pub(crate) fn warning(field: &'static str, code: &'static str, message: String) -> Self {
+        Self {
+            severity: IssueSeverity::Warning,
+            ..Self::error(field, code, message)
+        }
+    }
+
+    fn on_button(self, button: &str) -> Self {
+        Self {
+            button: Some(button.to_string()),
+            ..self
+        }
+    }
+}
+
+impl std::fmt::Display for SettingsIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(device_id) = &self.device_id {
+            write!(f, "{}", device_id)?;
+            if let Some(profile) = &self.profile {
+                write!(f, " in profile {}", profile)?;
+            }
+            write!(f, ": ")?;
+        }
+        write!(f, "{}", self.message)
+    }
+}
+
+/// Highest DPI a `Set DPI` action or DPI stop can ask for; HID++ carries DPI
+/// in 16 bits.
+const MAX_DPI: u32 = u16::MAX as u32;
+
+/// Pause between the clicks of a Double Click unless settings say
+/// otherwise, well under the shortest double-click interval System
+/// Settings allows.
+pub(crate) const DEFAULT_DOUBLE_CLICK_INTERVAL_MS: u32 = 15;
+
+/// Longest pause between the clicks of a Double Click. Longer ones are
+/// capped to it, since most systems would see two single clicks by then.
+pub(crate) const MAX_DOUBLE_CLICK_INTERVAL_MS: u32 = 500;
+
+/// Synthetic events a second posted unless settings say otherwise, far
+/// more than any mapping posts on purpose.
+pub(crate) const DEFAULT_SYNTHETIC_EVENTS_PER_SEC: u32 = 200;
+
+/// Lowest `Settings::synthetic_events_per_sec`; less would drop the clicks
+/// of an ordinary Double Click.
+const MIN_SYNTHETIC_EVENTS_PER_SEC: u32 = 10;
+
+/// Warning that `interval` is capped, or None when it's in range.
+fn double_click_interval_issue(interval: u32) -> Option<SettingsIssue> {
+    (interval > MAX_DOUBLE_CLICK_INTERVAL_MS).then(|| {
+        SettingsIssue::warning(
+            "double_click_interval_ms",
+            "out-of-range",
+            format!(
+                "double click interval {}ms is above {}ms, which is used instead",
+                interval, MAX_DOUBLE_CLICK_INTERVAL_MS
+            ),
+        )
+    })
+}
+
+/// Longest debounce window. Longer ones are capped to it, since they would
+/// swallow the second click of a quick double click.
+pub(crate) const MAX_DEBOUNCE_MS: u32 = 100;
+
+/// Warning that `window` is capped, or None when it's in range.
+fn debounce_issue(window: u32) -> Option<SettingsIssue> {
+    (window > MAX_DEBOUNCE_MS).then(|| {
+        SettingsIssue::warning(
+            "debounce_ms",
+            "out-of-range",
+            format!(
+                "debounce {}ms is above {}ms, which is used instead",
+                window, MAX_DEBOUNCE_MS
+            ),
+        )
+    })
+}
+
+/// True when `value` does something other than Default.
+fn remaps(value: &ActionValue) -> bool {
+    match value {
+        ActionValue::Gesture { .. } => true,
+        ActionValue::Action(_) | ActionValue::Unknown(_) => value.action() != Action::Default,
+    }
+}
+
+impl DeviceConfig {
+    /// `name`, or `id` for a config without one.
+    pub(crate) fn display_name<'a>(&'a self, id: &'a str) -> &'a str {
+        if self.name.is_empty() {
+            id
+        } else {
+            &self.name
+        }
+    }
+
+    /// Button keys outside `BUTTON_KEYS`, actions that aren't known or are
+    /// out of range, and gestures inside gestures, as issues without a
+    /// device.
+    pub(crate) fn issues(&self) -> Vec<SettingsIssue> {
+        let mut issues = Vec::new();
+        let check_key = |issues: &mut Vec<SettingsIssue>, field, key: &str| {
+            let known = BUTTON_KEYS.contains(&key);
+            if !known {
+                issues.push(
+                    SettingsIssue::error(
+                        field,
+                        "unknown-button",
+                        format!("unknown button {}", key),
+                    )
+                    .on_button(key),
+                );
+            }
+            known
+        };
+        let check_value = |issues: &mut Vec<SettingsIssue>,
+                           field,
+                           key: &str,
+                           value: &ActionValue| {
+            if !check_key(issues, field, key) {
+                return;
+            }
+            let actions = match value {
+                ActionValue::Gesture { gesture } => {
+                    if !(gesture.threshold.is_finite() && gesture.threshold > 0.0) {
+                        issues.push(
+                            SettingsIssue::error(
+                                field,
+                                "out-of-range",
+                                format!("gesture on {} has an invalid threshold", key),
+                            )
+                            .on_button(key),
+                        );
+                    }
+                    vec![
+                        &gesture.tap,
+                        &gesture.up,
+                        &gesture.down,
+                        &gesture.left,
+                        &gesture.right,
+                    ]
+                }
+                value => vec![value],
+            };
+            for action in actions {
+                let issue = match action {
+                    ActionValue::Unknown(value) => SettingsIssue::warning(
+                        field,
+                        "unknown-action",
+                        format!("unknown action {} on {}", value, key),
+                    ),
+                    ActionValue::Gesture { .. } => SettingsIssue::error(
+                        field,
+                        "nested-gesture",
+                        format!("gesture on {} has a gesture as a direction", key),
+                    ),
+                    ActionValue::Action(Action::SetDpi { dpi }) if *dpi == 0 || *dpi > MAX_DPI => {
+                        SettingsIssue::error(
+                            field,
+                            "out-of-range",
+                            format!("Set DPI {} on {} is outside 1-{}", dpi, key, MAX_DPI),
+                        )
+                    }
+                    ActionValue::Action(Action::Shortcut {
+                        keycode: Some(keycode),
+                        ..
+                    }) if *keycode > MAX_KEYCODE => SettingsIssue::error(
+                        field,
+                        "out-of-range",
+                        format!(
+                            "shortcut keycode {} on {} is outside 0-{}",
+                            keycode, key, MAX_KEYCODE
+                        ),
+                    ),
+                    ActionValue::Action(Action::Shortcut {
+                        keycode: None,
+                        key: None,
+                        ..
+                    }) => SettingsIssue::error(
+                        field,
+                        "missing-key",
+                        format!("shortcut on {} has neither a key nor a keycode", key),
+                    ),
+                    ActionValue::Action(_) => continue,
+                };
+                issues.push(issue.on_button(key));
+            }
+        };
+        for (key, value) in &self.buttons {
+            check_value(&mut issues, "buttons", key, value);
+        }
+        for buttons in self.app_overrides.values() {
+            for (key, value) in buttons {
+                check_value(&mut issues, "app_overrides", key, value);
+            }
+        }
+        for (key, mappings) in &self.modifier_mappings {
+            for mapping in mappings {
+                check_value(&mut issues, "modifier_mappings", key, &mapping.action);
+            }
+        }
+        for key in &self.merge_modifiers {
+            check_key(&mut issues, "merge_modifiers", key);
+        }
+        for key in &self.strip_modifiers {
+            check_key(&mut issues, "strip_modifiers", key);
+        }
+        for key in self.triggers.keys() {
+            check_key(&mut issues, "triggers", key);
+        }
+        for key in &self.repeat_while_held {
+            check_key(&mut issues, "repeat_while_held", key);
+        }
+        for key in self.feedback.keys() {
+            check_key(&mut issues, "feedback", key);
+        }
+        for (key, interval) in &self.double_click_interval_ms {
+            if check_key(&mut issues, "double_click_interval_ms", key) {
+                issues.extend(
+                    double_click_interval_issue(*interval).map(|issue| issue.on_button(key)),
+                );
+            }
+        }
+        for (key, window) in &self.debounce_ms {
+            if check_key(&mut issues, "debounce_ms", key) {
+                issues.extend(debounce_issue(*window).map(|issue| issue.on_button(key)));
+            }
+        }
+        if let Some(dpi) = self
+            .dpi_stops
+            .iter()
+            .find(|dpi| **dpi == 0 || **dpi > MAX_DPI)
+        {
+            issues.push(SettingsIssue::error(
+                "dpi_stops",
+                "out-of-range",
+                format!("DPI stop {} is outside 1-{}", dpi, MAX_DPI),
+            ));
+        }
+        issues
+    }
+
+    /// Whether `other` sets the device up the same, whatever the sightings
+    /// and `pristine` of either say.
+    fn same_setup(&self, other: &Self) -> bool {
+        let setup = |config: &Self| {
+            serde_json::to_value(Self {
+                first_seen: None,
+                last_seen: None,
+                pristine: false,
+                ..config.clone()
+            })
+            .ok()
+        };
+        setup(self) == setup(other)
+    }
+
+    /// True when any mapping, in any application or modifier layer, does
+    /// something other than Default, the primary buttons are swapped, or
+    /// scroll momentum is dropped.
+    pub(crate) fn has_remapping(&self) -> bool {
+        self.disabled
+            || self.swap_primary_buttons
+            || self.disable_scroll_inertia
+            || self.buttons.values().any(remaps)
+            || self
+                .app_overrides
+                .values()
+                .flat_map(HashMap::values)
+                .any(remaps)
+            || self
+                .modifier_mappings
+                .values()
+                .flatten()
+                .any(|mapping| remaps(&mapping.action))
+    }
+
+    /// True when the left button, on its own or in any application, does
+    /// something other than click.
+    pub(crate) fn remaps_primary(&self) -> bool {
+        self.buttons.get("left").is_some_and(remaps)
+            || self
+                .app_overrides
+                .values()
+                .filter_map(|overrides| overrides.get("left"))
+                .any(remaps)
+    }
+
+    /// Mapping of a zero-based button number, for a device with
+    /// `button_count` buttons: the first modifier mapping matching `held`,
+    /// then `app`'s override, then the plain mapping.
+    pub(crate) fn mapping(
+        &self,
+        button: i64,
+        button_count: u8,
+        app: Option<&str>,
+        held: ModifierMask,
+    ) -> Option<&ActionValue> {
+        if button >= i64::from(button_count) {
+            return None;
+        }
+        let key = button_key(button)?;
+        let layered = self.modifier_mappings.get(key).and_then(|mappings| {
+            mappings
+                .iter()
+                .find(|mapping| modifier_mask(&mapping.modifiers) == held)
+        });
+        if let Some(mapping) = layered {
+            return Some(&mapping.action);
+        }
+        app.and_then(|app| self.app_overrides.get(app))
+            .and_then(|overrides| overrides.get(key))
+            .or_else(|| self.buttons.get(key))
+    }
+}
+
+/// Switches to `profile` while the application `bundle_id` is frontmost.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub(crate) struct ProfileAppRule {
+    pub(crate) bundle_id: String,
+    pub(crate) profile: String,
+}
+
+/// A day of the week, for `ProfileSchedule`.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Weekday {
+    Monday,
+    Tuesday,
+    Wednesday,
+    Thursday,
+    Friday,
+    Saturday,
+    Sunday,
+}
+
+impl Weekday {
+    /// Monday first, so `ALL[n]` comes `n` days after Monday.
+    pub(crate) const ALL: [Weekday; 7] = [
+        Weekday::Monday,
+        Weekday::Tuesday,
+        Weekday::Wednesday,
+        Weekday::Thursday,
+        Weekday::Friday,
+        Weekday::Saturday,
+        Weekday::Sunday,
+    ];
+
+    pub(crate) fn previous(self) -> Self {
+        Self::ALL[(self as usize + 6) % 7]
+    }
+}
+
+/// A time of the week, in local time, during which a profile is switched
+/// to on its own.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub(crate) struct ProfileSchedule {
+    /// Days the time starts on; empty for every day.
+    #[serde(default)]
+    pub(crate) days: Vec<Weekday>,
+    /// "HH:MM". An `end` before `start` is on the next day, as is one
+    /// equal to it, which makes a whole day.
+    pub(crate) start: String,
+    pub(crate) end: String,
+}
+
+/// Minutes since midnight of an "HH:MM" time.
+pub(crate) fn minute_of_day(time: &str) -> Option<u16> {
+    let (hour, minute) = time.trim().split_once(':')?;
+    let digits = hour
+        .bytes()
+        .chain(minute.bytes())
+        .all(|byte| byte.is_ascii_digit());
+    if !digits || !(1..=2).contains(&hour.len()) || minute.len() != 2 {
+        return None;
+    }
+    let hour: u16 = hour.parse().ok().filter(|hour| *hour < 24)?;
+    let minute: u16 = minute.parse().ok().filter(|minute| *minute < 60)?;
+    Some(hour * 60 + minute)
+}
+
+/// Name of the profile pre-profile settings are migrated into.
+pub(crate) const DEFAULT_PROFILE: &str = "Default";
+
+/// What a left click on the tray icon does. The menu always opens on a
+/// right click, and on Linux on any click.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum TrayClickAction {
+    #[default]
+    ToggleWindow,
+    ShowMenu,
+    /// Opens the quick panel under the icon; see `show_quick_panel`.
+    QuickPanel,
+}
+
+/// What the window's close button does.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum CloseBehavior {
+    /// Hide to the tray and keep remapping.
+    Hide,
+    Quit,
+    /// Let the window ask, once, and keep the answer.
+    #[default]
+    Ask,
+}
+
+/// Least severe log level written to the log file.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum LogLevel {
+    Error,
+    Warn,
+    #[default]
+    Info,
+    Debug,
+    Trace,
+}
+
+impl LogLevel {
+    pub(crate) fn filter(self) -> log::LevelFilter {
+        match self {
+            LogLevel::Error => log::LevelFilter::Error,
+            LogLevel::Warn => log::LevelFilter::Warn,
+            LogLevel::Info => log::LevelFilter::Info,
+            LogLevel::Debug => log::LevelFilter::Debug,
+            LogLevel::Trace => log::LevelFilter::Trace,
+        }
+    }
+}
+
+/// Port the integration server listens on unless one is set.
+const DEFAULT_INTEGRATION_PORT: u16 = 45781;
+
+/// The local WebSocket server other tools can drive the app through; see
+/// `mod integration`.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(default)]
+pub(crate) struct IntegrationServer {
+    pub(crate) enabled: bool,
+    pub(crate) port: u16,
+    /// Clients pass it as `?token=` when they connect. Generated the first
+    /// time the server is turned on.
+    pub(crate) token: String,
+}
+
+impl Default for IntegrationServer {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            port: DEFAULT_INTEGRATION_PORT,
+            token: String::new(),
+        }
+    }
+}
+
+/// Corner of the display the input overlay sits in.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum ScreenCorner {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    #[default]
+    BottomRight,
+}
+
+/// Longest `InputOverlaySettings::auto_hide_secs` takes.
+pub(crate) const MAX_OVERLAY_AUTO_HIDE_SECS: u32 = 3600;
+
+/// Where the input overlay `show_input_overlay` opens sits, and when it
+/// hides.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(default)]
+pub(crate) struct InputOverlaySettings {
+    pub(crate) corner: ScreenCorner,
+    /// Seconds without a press after which the overlay hides, until the
+    /// next. 0 keeps it up.
+    pub(crate) auto_hide_secs: u32,
+}
+
+/// Which connected mice `Settings::fallback_to_any_device` lets stand in
+/// for the selected one.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum FallbackDevices {
+    /// Mice set up in the active profile, with their own mappings.
+    #[default]
+    Configured,
+    /// Any mouse; one that isn't set up gets the "Any mouse" mappings.
+    Any,
+}
+
+/// A complete set of device button maps.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub(crate) struct Profile {
+    pub(crate) devices: HashMap<String, DeviceConfig>,
+    /// Short text, such as an emoji, the menu bar shows for this profile in
+    /// place of its name; see `Settings::show_profile_in_tray`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) tray_label: Option<String>,
+    /// When this profile is switched to on its own; see `schedule::watch`.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub(crate) schedule: Vec<ProfileSchedule>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub(crate) struct Settings {
+    /// Shape of the file on disk; see `SETTINGS_MIGRATIONS`.
+    pub(crate) version: u32,
+    pub(crate) theme: String,
+    /// Language of the tray menu and notifications: a code of
+    /// `Locale::ALL`, or `SYSTEM_LOCALE`.
+    pub(crate) locale: String,
+    pub(crate) startup: bool,
+    /// Start in the tray without showing the window, as `--hidden` does.
+    pub(crate) start_hidden: bool,
+    pub(crate) selected_device: Option<String>,
+    pub(crate) profiles: HashMap<String, Profile>,
+    /// Profile the remap engine resolves actions from.
+    pub(crate) active_profile: String,
+    /// Profiles to switch to automatically when an application activates.
+    pub(crate) profile_app_rules: Vec<ProfileAppRule>,
+    pub(crate) include_all_pointing_devices: bool,
+    pub(crate) show_non_mouse_devices: bool,
+    /// Bundle ids of applications in which no button is ever remapped.
+    pub(crate) excluded_apps: Vec<String>,
+    /// Devices whose events always reach apps untouched, whichever device
+    /// is selected; see `leaves_untouched`.
+    pub(crate) ignored_devices: Vec<String>,
+    /// Keys Next Tab and Previous Tab press per bundle id, over
+    /// `DEFAULT_TAB_KEYS`.
+    pub(crate) tab_keys: HashMap<String, TabKeys>,
+    /// Shortcut Toggle Focus runs on macOS 12 and later.
+    pub(crate) focus_shortcut: String,
+    /// Bundle ids of the applications Quick Look fires in. Anywhere else
+    /// Space would type a space, so the button does what it always does.
+    pub(crate) quick_look_apps: Vec<String>,
+    /// Suspend remapping while the frontmost app covers a whole display.
+    pub(crate) game_mode: bool,
+    /// Leave button presses alone while the Edit Mouse window has focus,
+    /// so a mapping such as a disabled left click can't lock the user out
+    /// of the window that undoes it.
+    pub(crate) pause_in_own_window: bool,
+    /// Suspend remapping once this many minutes pass without input from
+    /// the mouse, until the next. `None` never does.
+    pub(crate) idle_suspend_minutes: Option<u32>,
+    /// Silence every button's `DeviceConfig::feedback` at once.
+    pub(crate) mute_feedback: bool,
+    /// Pause between the clicks of a Double Click mapping, up to
+    /// `MAX_DOUBLE_CLICK_INTERVAL_MS`. Some apps and remote desktop clients
+    /// miss double clicks that come too fast.
+    pub(crate) double_click_interval_ms: u32,
+    /// Most synthetic events posted in a second, across every mapping;
+    /// anything over it is dropped. See `OutputGovernor`.
+    pub(crate) synthetic_events_per_sec: u32,
+    /// Master switch; when off every event passes through untouched.
+    pub(crate) remap_enabled: bool,
+    /// Hotkey flipping `remap_enabled`, for when the mouse itself is the
+    /// problem; `None` turns it off.
+    pub(crate) toggle_hotkey: Option<Hotkey>,
+    /// Hotkey showing the window, or hiding it when it's in front. Off
+    /// unless set.
+    pub(crate) window_hotkey: Option<Hotkey>,
+    /// Hotkey opening the quick panel, or closing it. Off unless set.
+    pub(crate) quick_panel_hotkey: Option<Hotkey>,
+    /// Hotkey selecting the next connected device; see
+    /// `next_selected_device`. Off unless set.
+    pub(crate) cycle_device_hotkey: Option<Hotkey>,
+    pub(crate) tray_click_action: TrayClickAction,
+    /// Post a notification when a configured device connects or
+    /// disconnects.
+    pub(crate) notify_device_changes: bool,
+    /// Minutes between battery checks of the selected device.
+    pub(crate) battery_check_minutes: u32,
+    /// Notify once a discharge when the selected device's battery falls
+    /// below this percentage. 0 turns the warning, and the checks, off.
+    pub(crate) low_battery_percent: u8,
+    /// Flash the selected device's mappings over everything for a few
+    /// seconds when it connects.
+    pub(crate) show_connect_hud: bool,
+    /// While the selected device isn't connected, remap the one other
+    /// connected mouse of `fallback_devices`, if there's exactly one; see
+    /// `fallback_device`.
+    pub(crate) fallback_to_any_device: bool,
+    pub(crate) fallback_devices: FallbackDevices,
+    /// Flash the new profile's name for a moment whenever the active
+    /// profile changes.
+    pub(crate) show_profile_toast: bool,
+    /// Title the tray icon with the active profile's `tray_label`, or its
+    /// name.
+    pub(crate) show_profile_in_tray: bool,
+    /// Show the app in the Dock and Cmd+Tab on macOS, instead of only in
+    /// the menu bar.
+    pub(crate) show_dock_icon: bool,
+    pub(crate) close_behavior: CloseBehavior,
+    pub(crate) log_level: LogLevel,
+    /// Check GitHub for a newer release once a day.
+    pub(crate) auto_check_updates: bool,
+    /// Count how often each mapping fires, for `get_usage_stats`. The
+    /// counts never leave this machine.
+    pub(crate) collect_stats: bool,
+    /// Keep the last actions remaps fired, for `get_action_history`.
+    pub(crate) record_history: bool,
+    /// Set once the first-run walkthrough was finished.
+    pub(crate) onboarding_completed: bool,
+    /// Version that last finished onboarding or showed its changes; a
+    /// different one means there's "what's new" to show.
+    pub(crate) last_seen_version: String,
+    /// Folder, such as one in iCloud Drive or Dropbox, the settings are read
+    /// from and saved to as well, with the config directory's copy as the
+    /// fallback. Left out of the synced copy, as the folder's path can
+    /// differ from one machine to the next.
+    pub(crate) sync_dir: Option<PathBuf>,
+    /// Ids of the `Conflict`s not to warn about at launch any more.
+    pub(crate) ignored_conflicts: Vec<String>,
+    /// The settings file of an earlier build these were first copied from;
+    /// see `migrate_legacy_settings`.
+    pub(crate) migrated_from: Option<PathBuf>,
+    pub(crate) integration_server: IntegrationServer,
+    pub(crate) input_overlay: InputOverlaySettings,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            version: SETTINGS_VERSION,
+            theme: "system".to_string(),
+            locale: SYSTEM_LOCALE.to_string(),
+            startup: false,
+            start_hidden: false,
+            selected_device: None,
+            profiles: HashMap::from([(DEFAULT_PROFILE.to_string(), Profile::default())]),
+            active_profile: DEFAULT_PROFILE.to_string(),
+            profile_app_rules: Vec::new(),
+            include_all_pointing_devices: false,
+            show_non_mouse_devices: false,
+            excluded_apps: Vec::new(),
+            ignored_devices: Vec::new(),
+            tab_keys: HashMap::new(),
+            focus_shortcut: DEFAULT_FOCUS_SHORTCUT.to_string(),
+            quick_look_apps: vec![FINDER_BUNDLE_ID.to_string()],
+            game_mode: false,
+            pause_in_own_window: true,
+            idle_suspend_minutes: None,
+            mute_feedback: false,
+            double_click_interval_ms: DEFAULT_DOUBLE_CLICK_INTERVAL_MS,
+            synthetic_events_per_sec: DEFAULT_SYNTHETIC_EVENTS_PER_SEC,
+            remap_enabled: true,
+            toggle_hotkey: Some(DEFAULT_TOGGLE_HOTKEY),
+            window_hotkey: None,
+            quick_panel_hotkey: None,
+            cycle_device_hotkey: None,
+            tray_click_action: TrayClickAction::default(),
+            notify_device_changes: false,
+            battery_check_minutes: DEFAULT_BATTERY_CHECK_MINUTES,
+            low_battery_percent: DEFAULT_LOW_BATTERY_PERCENT,
+            show_connect_hud: true,
+            fallback_to_any_device: false,
+            fallback_devices: FallbackDevices::default(),
+            show_profile_toast: true,
+            show_profile_in_tray: false,
+            show_dock_icon: false,
+            close_behavior: CloseBehavior::default(),
+            log_level: LogLevel::default(),
+            auto_check_updates: false,
+            collect_stats: true,
+            record_history: true,
+            onboarding_completed: false,
+            last_seen_version: String::new(),
+            sync_dir: None,
+            ignored_conflicts: Vec::new(),
+            migrated_from: None,
+            integration_server: IntegrationServer::default(),
+            input_overlay: InputOverlaySettings::default(),
+        }
+    }
+}
+
+impl Settings {
+    /// Config of `device_id` in the active profile. A unit that can't be
+    /// told from another without its own config shares the first's.
+    pub(crate) fn device_config(&self, device_id: &str) -> Option<&DeviceConfig> {
+        let devices = &self.profiles.get(&self.active_profile)?.devices;
+        devices
+            .get(device_id)
+            .or_else(|| devices.get(unit_base_id(device_id)))
+    }
+
+    /// Mappings a device standing in for the selected one is remapped
+    /// with: its own, unless it was never set up, and those of "Any mouse"
+    /// otherwise.
+    pub(crate) fn fallback_config(&self, device_id: &str) -> Option<&DeviceConfig> {
+        self.device_config(device_id)
+            .filter(|config| !config.pristine)
+            .or_else(|| self.device_config(ANY_DEVICE_ID))
+    }
+
+    pub(crate) fn hotkey(&self, purpose: HotkeyPurpose) -> Option<Hotkey> {
+        match purpose {
+            HotkeyPurpose::ToggleRemapping => self.toggle_hotkey,
+            HotkeyPurpose::ToggleWindow => self.window_hotkey,
+            HotkeyPurpose::QuickPanel => self.quick_panel_hotkey,
+            HotkeyPurpose::CycleDevice => self.cycle_device_hotkey,
+        }
+    }
+
+    /// Checks that `hotkey` is usable and no purpose but `purpose` has it.
+    pub(crate) fn check_hotkey(
+        &self,
+        purpose: HotkeyPurpose,
+        hotkey: Hotkey,
+    ) -> Result<(), String> {
+        hotkey.validate()?;
+        let taken = HotkeyPurpose::ALL
+            .into_iter()
+            .find(|other| *other != purpose && self.hotkey(*other) == Some(hotkey));
+        match taken {
+            Some(other) => Err(format!(
+                "{} is already used for {}",
+                hotkey.label(),
+                other.describe()
+            )),
+            None => Ok(()),
+        }
+    }
+
+    pub(crate) fn set_hotkey(
+        &mut self,
+        purpose: HotkeyPurpose,
+        hotkey: Option<Hotkey>,
+    ) -> Result<(), String> {
+        if let Some(hotkey) = hotkey {
+            self.check_hotkey(purpose, hotkey)?;
+        }
+        match purpose {
+            HotkeyPurpose::ToggleRemapping => self.toggle_hotkey = hotkey,
+            HotkeyPurpose::ToggleWindow => self.window_hotkey = hotkey,
+            HotkeyPurpose::QuickPanel => self.quick_panel_hotkey = hotkey,
+            HotkeyPurpose::CycleDevice => self.cycle_device_hotkey = hotkey,
+        }
+        Ok(())
+    }
+
+    /// Makes sure at least one profile exists and `active_profile` names
+    /// one, falling back to the first by name.
+    pub(crate) fn normalize_profiles(&mut self) {
+        if self.profiles.is_empty() {
+            self.profiles
+                .insert(DEFAULT_PROFILE.to_string(), Profile::default());
+        }
+        if !self.profiles.contains_key(&self.active_profile) {
+            if let Some(name) = self.profiles.keys().min() {
+                self.active_profile = name.clone();
+            }
+        }
+    }
+
+    /// How Next Tab and Previous Tab reach the application `app` names by
+    /// bundle id.
+    pub(crate) fn tab_keys_for(&self, app: Option<&str>) -> TabKeys {
+        let Some(app) = app else {
+            return TabKeys::default();
+        };
+        self.tab_keys
+            .get(app)
+            .copied()
+            .or_else(|| {
+                DEFAULT_TAB_KEYS
+                    .iter()
+                    .find(|(id, _)| *id == app)
+                    .map(|(_, keys)| *keys)
+            })
+            .unwrap_or_default()
+    }
+
+    pub(crate) fn profile_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.profiles.keys().cloned().collect();
+        names.sort();
+        names
+    }
+
+    pub(crate) fn create_profile(&mut self, name: &str, profile: Profile) -> Result<(), String> {
+        let name = name.trim();
+        if name.is_empty() {
+            return Err("profile name is empty".to_string());
+        }
+        if self.profiles.contains_key(name) {
+            return Err(format!("profile already exists: {}", name));
+        }
+        self.profiles.insert(name.to_string(), profile);
+        Ok(())
+    }
+
+    pub(crate) fn duplicate_profile(&mut self, name: &str, new_name: &str) -> Result<(), String> {
+        let profile = self
+            .profiles
+            .get(name)
+            .cloned()
+            .ok_or_else(|| format!("profile not found: {}", name))?;
+        self.create_profile(new_name, profile)
+    }
+
+    pub(crate) fn delete_profile(&mut self, name: &str) -> Result<(), String> {
+        if !self.profiles.contains_key(name) {
+            return Err(format!("profile not found: {}", name));
+        }
+        if self.profiles.len() == 1 {
+            return Err("cannot delete the only profile".to_string());
+        }
+        self.profiles.remove(name);
+        self.profile_app_rules.retain(|rule| rule.profile != name);
+        self.normalize_profiles();
+        if !self.selection_configured() {
+            self.selected_device = None;
+        }
+        Ok(())
+    }
+
+    /// Whether `selected_device` is unset, Any Device, or has a config in
+    /// some profile.
+    fn selection_configured(&self) -> bool {
+        match self.selected_device.as_deref() {
+            None | Some(ANY_DEVICE_ID) => true,
+            Some(selected) => self.profiles.values().any(|profile| {
+                profile.devices.contains_key(selected)
+                    || profile.devices.contains_key(unit_base_id(selected))
+            }),
+        }
+    }
+
+    /// Makes `selected_device` point at a stored config before a save. An
+    /// unconfigured selection carried over from `previous` (its config was
+    /// removed, or the id is stale) is cleared, and a newly selected
+    /// connected device gets a default config in the active profile. Any
+    /// other unconfigured id is an error, since remapping would quietly
+    /// fall back to Any Device for it.
+    pub(crate) fn settle_selected_device(
+        &mut self,
+        previous: &Settings,
+        connected: &[MouseDevice],
+    ) -> Result<(), Box<SettingsIssue>> {
+        if self.selection_configured() {
+            return Ok(());
+        }
+        if previous.selected_device == self.selected_device {
+            self.selected_device = None;
+            return Ok(());
+        }
+        let Some(selected) = self.selected_device.clone() else {
+            return Ok(());
+        };
+        let Some(device) = connected.iter().find(|device| device.id == selected) else {
+            return Err(Box::new(SettingsIssue {
+                device_id: Some(selected),
+                ..SettingsIssue::error(
+                    "selected_device",
+                    "unknown-selected-device",
+                    "selected device is neither configured nor connected".to_string(),
+                )
+            }));
+        };
+        self.profiles
+            .entry(self.active_profile.clone())
+            .or_default()
+            .devices
+            .insert(
+                selected,
+                DeviceConfig {
+                    name: device.name.clone(),
+                    buttons: default_buttons(device.button_count),
+                    ..DeviceConfig::default()
+                },
+            );
+        Ok(())
+    }
+
+    /// `on_connect` rules switching to profiles that don't exist, or
+    /// running what a rule can't run, as issues without a device.
+    fn connect_rule_issues(&self, rules: &[ConnectRule]) -> Vec<SettingsIssue> {
+        rules
+            .iter()
+            .filter_map(|rule| match rule {
+                ConnectRule::SwitchProfile { profile } if !self.profiles.contains_key(profile) => {
+                    Some(SettingsIssue::error(
+                        "on_connect",
+                        "unknown-profile",
+                        format!("on-connect rule names unknown profile {}", profile),
+                    ))
+                }
+                ConnectRule::RunAction {
+                    action: ActionValue::Action(Action::Default | Action::Disabled),
+                }
+                | ConnectRule::RunAction {
+                    action: ActionValue::Gesture { .. },
+                } => Some(SettingsIssue::error(
+                    "on_connect",
+                    "unsupported-action",
+                    "on-connect rules can only run actions that do something without a button"
+                        .to_string(),
+                )),
+                ConnectRule::RunAction {
+                    action: ActionValue::Unknown(value),
+                } => Some(SettingsIssue::error(
+                    "on_connect",
+                    "unknown-action",
+                    format!("on-connect rule runs unknown action {}", value),
+                )),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Everything wrong with these settings, in profile and device id
+    /// order: mappings the remap engine would silently ignore, profile rules
+    /// naming missing profiles, and references to unconfigured devices.
+    pub(crate) fn issues(&self) -> Vec<SettingsIssue> {
+        let mut issues = Vec::new();
+        let mut names: Vec<&String> = self.profiles.keys().collect();
+        names.sort();
+        for name in names {
+            let devices = &self.profiles[name].devices;
+            let mut ids: Vec<&String> = devices.keys().collect();
+            ids.sort();
+            for id in ids {
+                let config = &devices[id];
+                let mut device_issues = config.issues();
+                if id.trim().is_empty() {
+                    device_issues.push(SettingsIssue::error(
+                        "profiles",
+                        "empty-device-id",
+                        "device has no id".to_string(),
+                    ));
+                } else if config.name.trim().is_empty() && id != ANY_DEVICE_ID && !config.pristine {
+                    device_issues.push(SettingsIssue::warning(
+                        "name",
+                        "empty-device-name",
+                        "device has no name".to_string(),
+                    ));
+                }
+                device_issues.extend(self.connect_rule_issues(&config.on_connect));
+                issues.extend(device_issues.into_iter().map(|issue| SettingsIssue {
+                    profile: Some(name.clone()),
+                    device_id: Some(id.clone()),
+                    ..issue
+                }));
+            }
+            for schedule in &self.profiles[name].schedule {
+                for time in [&schedule.start, &schedule.end] {
+                    if minute_of_day(time).is_none() {
+                        issues.push(SettingsIssue {
+                            profile: Some(name.clone()),
+                            ..SettingsIssue::error(
+                                "schedule",
+                                "invalid-time",
+                                format!("schedule time {} isn't HH:MM", time),
+                            )
+                        });
+                    }
+                }
+            }
+        }
+        if let Some(selected) = &self.selected_device {
+            if !self.selection_configured() {
+                issues.push(SettingsIssue {
+                    device_id: Some(selected.clone()),
+                    ..SettingsIssue::warning(
+                        "selected_device",
+                        "unknown-selected-device",
+                        "selected device has no config in any profile".to_string(),
+                    )
+                });
+            }
+        }
+        for rule in &self.profile_app_rules {
+            if rule.bundle_id.trim().is_empty() {
+                issues.push(SettingsIssue::error(
+                    "profile_app_rules",
+                    "empty-rule-app",
+                    "profile rule has no application".to_string(),
+                ));
+            } else if !self.profiles.contains_key(&rule.profile) {
+                issues.push(SettingsIssue::error(
+                    "profile_app_rules",
+                    "unknown-profile",
+                    format!(
+                        "profile rule for {} names unknown profile {}",
+                        rule.bundle_id, rule.profile
+                    ),
+                ));
+            }
+        }
+        for purpose in HotkeyPurpose::ALL {
+            let invalid = self
+                .hotkey(purpose)
+                .and_then(|hotkey| self.check_hotkey(purpose, hotkey).err());
+            if let Some(err) = invalid {
+                issues.push(SettingsIssue::error(purpose.field(), "invalid-hotkey", err));
+            }
+        }
+        if self.sync_dir.as_ref().is_some_and(|dir| !dir.is_absolute()) {
+            issues.push(SettingsIssue::error(
+                "sync_dir",
+                "relative-path",
+                "the sync folder must be a full path".to_string(),
+            ));
+        }
+        if self.locale != SYSTEM_LOCALE && Locale::from_tag(&self.locale).is_none() {
+            issues.push(SettingsIssue::warning(
+                "locale",
+                "unknown-locale",
+                format!("unknown locale {}; using the system's", self.locale),
+            ));
+        }
+        issues.extend(double_click_interval_issue(self.double_click_interval_ms));
+        if self.synthetic_events_per_sec < MIN_SYNTHETIC_EVENTS_PER_SEC {
+            issues.push(SettingsIssue::error(
+                "synthetic_events_per_sec",
+                "out-of-range",
+                format!(
+                    "synthetic output needs at least {} events a second",
+                    MIN_SYNTHETIC_EVENTS_PER_SEC
+                ),
+            ));
+        }
+        if self.idle_suspend_minutes == Some(0) {
+            issues.push(SettingsIssue::error(
+                "idle_suspend_minutes",
+                "out-of-range",
+                "idle suspension needs at least a minute without input".to_string(),
+            ));
+        }
+        if self.battery_check_minutes == 0 {
+            issues.push(SettingsIssue::error(
+                "battery_check_minutes",
+                "out-of-range",
+                "battery checks need at least a minute between them".to_string(),
+            ));
+        }
+        if self.low_battery_percent > 100 {
+            issues.push(SettingsIssue::error(
+                "low_battery_percent",
+                "out-of-range",
+                format!(
+                    "low battery warning at {}% is above 100%",
+                    self.low_battery_percent
+                ),
+            ));
+        }
+        if self.input_overlay.auto_hide_secs > MAX_OVERLAY_AUTO_HIDE_SECS {
+            issues.push(SettingsIssue::error(
+                "input_overlay",
+                "out-of-range",
+                format!(
+                    "input overlay auto-hide after {}s is above {}s",
+                    self.input_overlay.auto_hide_secs, MAX_OVERLAY_AUTO_HIDE_SECS
+                ),
+            ));
+        }
+        if self.integration_server.port < 1024 {
+            issues.push(SettingsIssue::error(
+                "integration_server",
+                "invalid-port",
+                format!(
+                    "integration server port {} is below 1024",
+                    self.integration_server.port
+                ),
+            ));
+        }
+        issues
+    }
+
+    /// The first error among `issues`, for callers that take all or nothing.
+    pub(crate) fn validate(&self) -> Result<(), String> {
+        match self
+            .issues()
+            .into_iter()
+            .find(|issue| issue.severity == IssueSeverity::Error)
+        {
+            Some(issue) => Err(issue.to_string()),
+            None => Ok(()),
+        }
+    }
+
+    /// Refuses, unless `acknowledged`, settings that remap the left button of
+    /// a device whose left button `previous` left alone: on someone's only
+    /// mouse that takes away the click needed to undo it.
+    pub(crate) fn check_primary_remap(
+        &self,
+        previous: &Settings,
+        acknowledged: bool,
+    ) -> Result<(), String> {
+        if acknowledged {
+            return Ok(());
+        }
+        let mut remapped: Vec<&str> = self
+            .profiles
+            .iter()
+            .flat_map(|(name, profile)| {
+                profile
+                    .devices
+                    .iter()
+                    .filter(move |(id, config)| {
+                        config.remaps_primary()
+                            && !previous
+                                .profiles
+                                .get(name)
+                                .and_then(|profile| profile.devices.get(*id))
+                                .is_some_and(DeviceConfig::remaps_primary)
+                    })
+                    .map(|(id, config)| config.display_name(id))
+            })
+            .collect();
+        if remapped.is_empty() {
+            return Ok(());
+        }
+        remapped.sort_unstable();
+        remapped.dedup();
+        Err(format!(
+            "remapping the left button of {} can leave no way to click; pass acknowledge_primary_remap: true to do it anyway",
+            remapped.join(", ")
+        ))
+    }
+
+    /// Adds `imported`'s device configs, profile rules, excluded apps, and
+    /// ignored devices, replacing configs and rules for the same device or application.
+    /// Everything else, like the theme and the active profile, stays as is.
+    pub(crate) fn merge(&mut self, imported: Settings) {
+        for (name, profile) in imported.profiles {
+            self.profiles
+                .entry(name)
+                .or_default()
+                .devices
+                .extend(profile.devices);
+        }
+        for rule in imported.profile_app_rules {
+            self.profile_app_rules
+                .retain(|existing| existing.bundle_id != rule.bundle_id);
+            self.profile_app_rules.push(rule);
+        }
+        for app in imported.excluded_apps {
+            if !self.excluded_apps.contains(&app) {
+                self.excluded_apps.push(app);
+            }
+        }
+        for device_id in imported.ignored_devices {
+            if !self.ignored_devices.contains(&device_id) {
+                self.ignored_devices.push(device_id);
+            }
+        }
+    }
+
+    /// The profile after (or before) the active one, in name order.
+    pub(crate) fn adjacent_profile(&self, forward: bool) -> String {
+        let names = self.profile_names();
+        let Some(index) = names.iter().position(|name| *name == self.active_profile) else {
+            return self.active_profile.clone();
+        };
+        let next = if forward {
+            (index + 1) % names.len()
+        } else {
+            (index + names.len() - 1) % names.len()
+        };
+        names[next].clone()
+    }
+
+    /// Puts every mapping of `device_id` in the active profile back to
+    /// `default_buttons`, including its per-app and modifier mappings. Its
+    /// name, DPI stops, extended buttons setting, and sightings are kept.
+    pub(crate) fn reset_device(&mut self, device_id: &str, button_count: u8) -> Result<(), String> {
+        let config = self
+            .profiles
+            .get_mut(&self.active_profile)
+            .and_then(|profile| profile.devices.get_mut(device_id))
+            .ok_or_else(|| format!("device not in settings: {}", device_id))?;
+        *config = DeviceConfig {
+            name: std::mem::take(&mut config.name),
+            buttons: default_buttons(button_count),
+            extended_buttons: config.extended_buttons,
+            dpi_stops: std::mem::take(&mut config.dpi_stops),
+            disabled: config.disabled,
+            first_seen: config.first_seen,
+            last_seen: config.last_seen,
+            ..DeviceConfig::default()
+        };
+        Ok(())
+    }
+
+    /// Turns every button of `device_id` off or back on in the active
+    /// profile, adding a default config for a device without one.
+    pub(crate) fn set_device_disabled(&mut self, device_id: &str, disabled: bool) {
+        self.profiles
+            .entry(self.active_profile.clone())
+            .or_default()
+            .devices
+            .entry(device_id.to_string())
+            .or_default()
+            .disabled = disabled;
+    }
+
+    /// Whether `device_id`, or the unit it's taken for, is in
+    /// `ignored_devices`.
+    pub(crate) fn is_device_ignored(&self, device_id: &str) -> bool {
+        let base = unit_base_id(device_id);
+        self.ignored_devices
+            .iter()
+            .any(|ignored| ignored == device_id || ignored == base)
+    }
+
+    /// Whether events remapped as `device_id`'s must reach apps untouched:
+    /// it's ignored, or it's "Any mouse" while `ignored_connected` says an
+    /// ignored device is connected, whose events it couldn't tell apart.
+    pub(crate) fn leaves_untouched(&self, device_id: &str, ignored_connected: bool) -> bool {
+        self.is_device_ignored(device_id) || (device_id == ANY_DEVICE_ID && ignored_connected)
+    }
+
+    /// Adds `device_id` to `ignored_devices` or takes it out. "Any mouse"
+    /// stands for every device, so it can't be ignored.
+    pub(crate) fn set_device_ignored(
+        &mut self,
+        device_id: &str,
+        ignored: bool,
+    ) -> Result<(), String> {
+        if device_id == ANY_DEVICE_ID {
+            return Err("Any mouse can't be ignored; ignore the devices themselves".to_string());
+        }
+        self.ignored_devices.retain(|listed| listed != device_id);
+        if ignored {
+            self.ignored_devices.push(device_id.to_string());
+        }
+        Ok(())
+    }
+
+    /// Gives each of `devices` without a config in the active profile a
+    /// default one named after it, marked `pristine`, so every connected
+    /// device has a config to update. A unit sharing the first's config
+    /// keeps sharing it. Returns whether any was added.
+    pub(crate) fn adopt_new_devices(&mut self, devices: &[MouseDevice]) -> bool {
+        let mut adopted = false;
+        for device in devices {
+            if self.device_config(&device.id).is_some() {
+                continue;
+            }
+            log::info!("settings: adding a config for {}", device.id);
+            self.profiles
+                .entry(self.active_profile.clone())
+                .or_default()
+                .devices
+                .insert(
+                    device.id.clone(),
+                    DeviceConfig {
+                        name: device.name.clone(),
+                        buttons: default_buttons(device.button_count),
+                        pristine: true,
+                        ..DeviceConfig::default()
+                    },
+                );
+            adopted = true;
+        }
+        adopted
+    }
+
+    /// Clears `pristine` from the configs set up differently than in
+    /// `previous`, the settings these were edited from.
+    pub(crate) fn settle_pristine_devices(&mut self, previous: &Settings) {
+        for (name, profile) in &mut self.profiles {
+            for (id, config) in &mut profile.devices {
+                let before = previous
+                    .profiles
+                    .get(name)
+                    .and_then(|profile| profile.devices.get(id));
+                if config.pristine && before.is_some_and(|before| !before.same_setup(config)) {
+                    config.pristine = false;
+                }
+            }
+        }
+    }
+
+    /// Copies the config of `from_id` onto `to_id` in every profile that has
+    /// one, keeping the name and sightings `to_id` already has there. With
+    /// `remove_source`, `from_id`'s config goes, and a selection of it moves
+    /// to `to_id`, as for a mouse replaced by another of its kind.
+    pub(crate) fn copy_device_config(
+        &mut self,
+        from_id: &str,
+        to_id: &str,
+        remove_source: bool,
+    ) -> Result<(), String> {
+        if from_id == to_id {
+            return Err(format!("can't copy {} onto itself", from_id));
+        }
+        let mut copied = false;
+        for profile in self.profiles.values_mut() {
+            let config = if remove_source {
+                profile.devices.remove(from_id)
+            } else {
+                profile.devices.get(from_id).cloned()
+            };
+            let Some(mut config) = config else {
+                continue;
+            };
+            if let Some(target) = profile.devices.get(to_id) {
+                if !target.name.is_empty() {
+                    config.name = target.name.clone();
+                }
+                config.first_seen = target.first_seen;
+                config.last_seen = target.last_seen;
+            }
+            config.pristine = false;
+            profile.devices.insert(to_id.to_string(), config);
+            copied = true;
+        }
+        if !copied {
+            return Err(format!("device not in settings: {}", from_id));
+        }
+        if remove_source && self.selected_device.as_deref() == Some(from_id) {
+            self.selected_device = Some(to_id.to_string());
+        }
+        Ok(())
+    }
+
+    /// Drops the config of `device_id` from every profile, along with the
+    /// pristine configs of devices not seen for `STALE_PRISTINE_DEVICE`
+    /// before unix time `now`. Returns the ids dropped, sorted.
+    pub(crate) fn forget_device(
+        &mut self,
+        device_id: &str,
+        now: u64,
+    ) -> Result<Vec<String>, String> {
+        let stale = |config: &DeviceConfig| {
+            config.pristine
+                && config
+                    .last_seen
+                    .or(config.first_seen)
+                    .is_some_and(|seen| now.saturating_sub(seen) >= STALE_PRISTINE_DEVICE.as_secs())
+        };
+        let mut forgotten = Vec::new();
+        for profile in self.profiles.values_mut() {
+            profile.devices.retain(|id, config| {
+                let forget = id == device_id || stale(config);
+                if forget {
+                    forgotten.push(id.clone());
+                }
+                !forget
+            });
+        }
+        if !forgotten.iter().any(|id| id == device_id) {
+            return Err(format!("device not in settings: {}", device_id));
+        }
+        forgotten.sort();
+        forgotten.dedup();
+        Ok(forgotten)
+    }
+
+    /// Records when devices were seen, from `device id -> unix time`, in
+    /// every profile that has a config for them. Returns whether anything
+    /// changed.
+    pub(crate) fn record_sightings(&mut self, sightings: &HashMap<String, u64>) -> bool {
+        let mut changed = false;
+        for profile in self.profiles.values_mut() {
+            for (id, seen) in sightings {
+                let Some(config) = profile.devices.get_mut(id) else {
+                    continue;
+                };
+                let first_seen = Some(config.first_seen.map_or(*seen, |first| first.min(*seen)));
+                let last_seen = Some(config.last_seen.map_or(*seen, |last| last.max(*seen)));
+                changed |= first_seen != config.first_seen || last_seen != config.last_seen;
+                config.first_seen = first_seen;
+                config.last_seen = last_seen;
+            }
+        }
+        changed
+    }
+
+    /// Maps `button` of `device_id` in the active profile to `action`,
+    /// adding the device if it has no config yet.
+    pub(crate) fn set_button_action(
+        &mut self,
+        device_id: &str,
+        button: &str,
+        action: ActionValue,
+    ) -> Result<(), String> {
+        if !BUTTON_KEYS.contains(&button) {
+            return Err(format!("unknown button {}", button));
+        }
+        self.profiles
+            .entry(self.active_profile.clone())
+            .or_default()
+            .devices
+            .entry(device_id.to_string())
+            .or_default()
+            .buttons
+            .insert(button.to_string(), action);
+        Ok(())
+    }
+
+    /// Maps `button` of `device_id` in the active profile back to Default,
+    /// leaving its per-app and modifier mappings, and returns what it was
+    /// mapped to. `button_count` is the device's when it's connected;
+    /// otherwise it's taken to have `DEFAULT_BUTTON_COUNT` buttons, or as
+    /// many as its config maps.
+    pub(crate) fn reset_button(
+        &mut self,
+        device_id: &str,
+        button: &str,
+        button_count: Option<u8>,
+    ) -> Result<ActionValue, String> {
+        let index = BUTTON_KEYS
+            .iter()
+            .position(|key| *key == button)
+            .ok_or_else(|| format!("unknown button {}", button))?;
+        let config = self
+            .profiles
+            .get_mut(&self.active_profile)
+            .and_then(|profile| {
+                let id = if profile.devices.contains_key(device_id) {
+                    device_id
+                } else {
+                    unit_base_id(device_id)
+                };
+                profile.devices.get_mut(id)
+            })
+            .ok_or_else(|| format!("device not in settings: {}", device_id))?;
+        let count = button_count.map(usize::from).unwrap_or_else(|| {
+            BUTTON_KEYS
+                .iter()
+                .rposition(|key| config.buttons.contains_key(*key))
+                .map_or(0, |last| last + 1)
+                .max(usize::from(DEFAULT_BUTTON_COUNT))
+        });
+        if index >= count {
+            return Err(format!(
+                "{} has no {}",
+                config.display_name(device_id),
+                button
+            ));
+        }
+        Ok(config
+            .buttons
+            .insert(button.to_string(), ActionValue::default())
+            .unwrap_or_default())
+    }
+
+    /// Replaces the mappings of `device_id` in the active profile with
+    /// `preset`'s, adding the device if it has no config yet.
+    pub(crate) fn apply_preset(
+        &mut self,
+        device_id: &str,
+        preset: &presets::Preset,
+        button_count: u8,
+    ) {
+        let devices = &mut self
+            .profiles
+            .entry(self.active_profile.clone())
+            .or_default()
+            .devices;
+        let config = devices.remove(device_id).unwrap_or_else(|| DeviceConfig {
+            name: preset.name.to_string(),
+            ..DeviceConfig::default()
+        });
+        devices.insert(device_id.to_string(), preset.apply(config, button_count));
+    }
+
+    /// Like `apply_preset` for a preset file, returning the keys of the
+    /// buttons `device_id` doesn't have.
+    pub(crate) fn apply_preset_file(
+        &mut self,
+        device_id: &str,
+        file: &presets::PresetFile,
+        button_count: u8,
+    ) -> Vec<String> {
+        let devices = &mut self
+            .profiles
+            .entry(self.active_profile.clone())
+            .or_default()
+            .devices;
+        let config = devices.remove(device_id).unwrap_or_else(|| DeviceConfig {
+            name: file.name.clone(),
+            ..DeviceConfig::default()
+        });
+        let (config, skipped) = file.apply(config, button_count);
+        devices.insert(device_id.to_string(), config);
+        skipped
+    }
+
+    pub(crate) fn set_active_profile(&mut self, name: &str) -> Result<(), String> {
+        if !self.profiles.contains_key(name) {
+            return Err(format!("profile not found: {}", name));
+        }
+        self.active_profile = name.to_string();
+        Ok(())
+    }
+}
+
+/// How many settings changes can be undone.
+pub(crate) const SETTINGS_HISTORY: usize = 20;
+
+/// A settings change, with the settings from before it.
+#[derive(Debug, Clone)]
+pub(crate) struct SettingsChange {
+    pub(crate) previous: Settings,
+    /// Unix time, in seconds.
+    pub(crate) changed: u64,
+    pub(crate) summary: Vec<String>,
+}
+
+/// An entry of `get_settings_history`.
+#[derive(Debug, Serialize, Clone)]
+pub(crate) struct SettingsHistoryEntry {
+    pub(crate) changed: u64,
+    pub(crate) summary: Vec<String>,
+}
+
+/// One line per difference between `before` and `after`, such as "changed
+/// button4 on MX Master from Default to Back". Device sightings don't count.
+pub(crate) fn describe_settings_changes(before: &Settings, after: &Settings) -> Vec<String> {
+    let fields = |value: serde_json::Result<serde_json::Value>| match value {
+        Ok(serde_json::Value::Object(fields)) => fields,
+        _ => serde_json::Map::new(),
+    };
+    let mut changes = Vec::new();
+    let (old, new) = (
+        fields(serde_json::to_value(before)),
+        fields(serde_json::to_value(after)),
+    );
+    for (field, value) in &new {
+        if field != "profiles" && field != "version" && old.get(field) != Some(value) {
+            changes.push(format!("changed {}", field));
+        }
+    }
+    let mut names: Vec<&String> = before
+        .profiles
+        .keys()
+        .chain(after.profiles.keys())
+        .collect();
+    names.sort();
+    names.dedup();
+    for name in names {
+        let (old, new) = match (before.profiles.get(name), after.profiles.get(name)) {
+            (Some(old), Some(new)) => (old, new),
+            (None, _) => {
+                changes.push(format!("added profile {}", name));
+                continue;
+            }
+            (_, None) => {
+                changes.push(format!("removed profile {}", name));
+                continue;
+            }
+        };
+        let place = if after.profiles.len() > 1 {
+            format!(" in {}", name)
+        } else {
+            String::new()
+        };
+        let mut ids: Vec<&String> = old.devices.keys().chain(new.devices.keys()).collect();
+        ids.sort();
+        ids.dedup();
+        for id in ids {
+            let (old, new) = match (old.devices.get(id), new.devices.get(id)) {
+                (Some(old), Some(new)) => (old, new),
+                // Added by the app, not the user; see `adopt_new_devices`.
+                (None, Some(new)) if new.pristine => continue,
+                (None, Some(new)) => {
+                    changes.push(format!("added {}{}", new.display_name(id), place));
+                    continue;
+                }
+                (Some(old), None) => {
+                    changes.push(format!("removed {}{}", old.display_name(id), place));
+                    continue;
+                }
+                (None, None) => continue,
+            };
+            let device = new.display_name(id);
+            for key in BUTTON_KEYS {
+                let was = old.buttons.get(*key);
+                let is = new.buttons.get(*key);
+                if was != is {
+                    let label = |value: Option<&ActionValue>| {
+                        value.map_or("nothing".to_string(), ActionValue::label)
+                    };
+                    changes.push(format!(
+                        "changed {} on {}{} from {} to {}",
+                        key,
+                        device,
+                        place,
+                        label(was),
+                        label(is)
+                    ));
+                }
+            }
+            let (old, new) = (
+                fields(serde_json::to_value(old)),
+                fields(serde_json::to_value(new)),
+            );
+            for (field, value) in &new {
+                let tracked = !matches!(
+                    field.as_str(),
+                    "buttons" | "first_seen" | "last_seen" | "pristine"
+                );
+                if tracked && old.get(field) != Some(value) {
+                    changes.push(format!("changed {} on {}{}", field, device, place));
+                }
+            }
+        }
+    }
+    changes
+}
+
+/// Environment variable that moves the config directory.
+pub(crate) const CONFIG_DIR_ENV: &str = "EDIT_MOUSE_CONFIG_DIR";
+
+/// File next to the executable that keeps the config beside it instead, in
+/// `PORTABLE_CONFIG_DIR`.
+const PORTABLE_FLAG: &str = "portable.flag";
+pub(crate) const PORTABLE_CONFIG_DIR: &str = "Edit Mouse Config";
+
+/// Environment variable with more folders to look for an earlier build's
+/// settings in, separated like `PATH`. They're tried before
+/// `LEGACY_CONFIG_DIRS`.
+pub(crate) const LEGACY_CONFIG_DIRS_ENV: &str = "EDIT_MOUSE_LEGACY_CONFIG_DIRS";
+
+/// Config directories of earlier builds, in the platform's config
+/// directory: Tauri's template identifier, an older bundle identifier, and
+/// the app's name.
+const LEGACY_CONFIG_DIRS: &[&str] = &[
+    "com.tauri.dev",
+    "com.betr.edit-mouse",
+    "Edit Mouse",
+    "edit-mouse",
+];
+
+/// What chose the config directory.
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum ConfigDirSource {
+    /// `--config-dir <dir>` or `--config-dir=<dir>` on the command line.
+    Flag,
+    /// `EDIT_MOUSE_CONFIG_DIR`.
+    Environment,
+    /// A `portable.flag` next to the executable.
+    Portable,
+    /// The platform's usual app config directory.
+    Default,
+}
+
+/// The config directory asked for by the command line, the environment, or
+/// a portable flag, in that order; `None` means the platform default.
+/// `portable` tells whether the flag file exists in a directory.
+pub(crate) fn configured_config_dir(
+    args: impl IntoIterator<Item = std::ffi::OsString>,
+    env: Option<std::ffi::OsString>,
+    executable: Option<&Path>,
+    portable: impl Fn(&Path) -> bool,
+) -> Option<(PathBuf, ConfigDirSource)> {
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        if arg == "--config-dir" {
+            if let Some(dir) = args.next() {
+                return Some((PathBuf::from(dir), ConfigDirSource::Flag));
+            }
+        } else if let Some(dir) = arg
+            .to_str()
+            .and_then(|arg| arg.strip_prefix("--config-dir="))
+        {
+            return Some((PathBuf::from(dir), ConfigDirSource::Flag));
+        }
+    }
+    if let Some(dir) = env.filter(|dir| !dir.is_empty()) {
+        return Some((PathBuf::from(dir), ConfigDirSource::Environment));
+    }
+    let beside = executable?.parent()?;
+    portable(&beside.join(PORTABLE_FLAG))
+        .then(|| (beside.join(PORTABLE_CONFIG_DIR), ConfigDirSource::Portable))
+}
+
+pub(crate) fn config_dir(app: &tauri::AppHandle) -> Result<(PathBuf, ConfigDirSource), String> {
+    let configured = configured_config_dir(
+        std::env::args_os().skip(1),
+        std::env::var_os(CONFIG_DIR_ENV),
+        std::env::current_exe().ok().as_deref(),
+        Path::exists,
+    );
+    let (dir, source) = match configured {
+        Some(configured) => configured,
+        None => (
+            app.path().app_config_dir().map_err(|err| err.to_string())?,
+            ConfigDirSource::Default,
+        ),
+    };
+    fs::create_dir_all(&dir).map_err(|err| format!("{}: {}", dir.display(), err))?;
+    Ok((dir, source))
+}
+
+pub(crate) fn settings_path(app: &tauri::AppHandle) -> Result<PathBuf, String> {
+    Ok(config_dir(app)?.0.join("settings.json"))
+}
+
+/// Opens the folder holding the settings in the file manager.
+pub(crate) fn reveal_config_dir(app: &tauri::AppHandle) -> Result<(), String> {
+    let (dir, _) = config_dir(app)?;
+    #[cfg(target_os = "macos")]
+    let opener = "open";
+    #[cfg(target_os = "windows")]
+    let opener = "explorer";
+    #[cfg(not(any(target_os = "macos", target_os = "windows")))]
+    let opener = "xdg-open";
+    std::process::Command::new(opener)
+        .arg(&dir)
+        .spawn()
+        .map(drop)
+        .map_err(|err| format!("couldn't open {}: {}", dir.display(), err))
+}
+
+#[tauri::command]
+pub(crate) fn reveal_config_folder(app: tauri::AppHandle) -> Result<(), String> {
+    // Opened to look at the file, which should be what the app shows.
+    let _ = app.state::<AppState>().flush_settings();
+    reveal_config_dir(&app)
+}
+
+/// Payload of `get_config_paths`.
+#[derive(Debug, Serialize, Clone)]
+pub(crate) struct ConfigPaths {
+    pub(crate) config_dir: PathBuf,
+    pub(crate) source: ConfigDirSource,
+    pub(crate) settings: PathBuf,
+    pub(crate) backups: PathBuf,
+}
+
+/// Where settings and their backups actually live, for the settings window
+/// and diagnostics.
+#[tauri::command]
+pub(crate) fn get_config_paths(app: tauri::AppHandle) -> Result<ConfigPaths, String> {
+    let (config_dir, source) = config_dir(&app)?;
+    let settings = config_dir.join("settings.json");
+    Ok(ConfigPaths {
+        backups: settings_backups_dir(&settings),
+        settings,
+        config_dir,
+        source,
+    })
+}
+
+/// Version of the settings file this build writes. Bump it together with a
+/// new step in `SETTINGS_MIGRATIONS` whenever the shape on disk changes, so
+/// older files are upgraded instead of losing fields to `#[serde(default)]`.
+pub(crate) const SETTINGS_VERSION: u32 = 4;
+
+/// Step `n` upgrades settings JSON of version `n` to `n + 1`. Files from
+/// before versioning have no `version` and count as 0.
+const SETTINGS_MIGRATIONS: [fn(&mut serde_json::Map<String, serde_json::Value>);
+    SETTINGS_VERSION as usize] = [
+    migrate_settings_v0,
+    migrate_settings_v1,
+    migrate_settings_v2,
+    migrate_settings_v3,
+];
+
+/// Version 0 kept a top-level `devices` map from before profiles existed;
+/// version 1 keeps every device config in a profile, so those move into the
+/// "Default" profile without replacing configs it already has.
+fn migrate_settings_v0(settings: &mut serde_json::Map<String, serde_json::Value>) {
+    let Some(serde_json::Value::Object(devices)) = settings.remove("devices") else {
+        return;
+    };
+    let profiles = settings
+        .entry("profiles")
+        .or_insert_with(|| serde_json::json!({}));
+    let Some(profiles) = profiles.as_object_mut() else {
+        return;
+    };
+    let profile = profiles
+        .entry(DEFAULT_PROFILE)
+        .or_insert_with(|| serde_json::json!({}));
+    let Some(profile) = profile.as_object_mut() else {
+        return;
+    };
+    let profile_devices = profile
+        .entry("devices")
+        .or_insert_with(|| serde_json::json!({}));
+    if let Some(profile_devices) = profile_devices.as_object_mut() {
+        for (id, config) in devices {
+            profile_devices.entry(id).or_insert(config);
+        }
+    }
+}
+
+/// Version 2 stores actions by identifier instead of display name.
+/// `Action`'s deserializer still reads display names, so nothing needs
+/// rewriting; the bump keeps older builds from reading identifiers as
+/// unknown and saving over them.
+fn migrate_settings_v1(_settings: &mut serde_json::Map<String, serde_json::Value>) {}
+
+/// Version 3 stores actions as objects tagged by `type`, with parameters
+/// such as a shortcut's keycode alongside. `ActionValue` reads the plain
+/// strings and `{"gesture": {...}}` of version 2 and writes the new shape on
+/// the next save, so again nothing needs rewriting.
+fn migrate_settings_v2(_settings: &mut serde_json::Map<String, serde_json::Value>) {}
+
+/// Version 4 records whether onboarding was finished. Whoever has an older
+/// file already got past it, so they don't see it again.
+fn migrate_settings_v3(settings: &mut serde_json::Map<String, serde_json::Value>) {
+    settings.insert("onboarding_completed".to_string(), true.into());
+}
+
+/// Payload of `settings-migrated`.
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub(crate) struct SettingsMigrated {
+    /// The earlier build's settings file, left where it was.
+    pub(crate) from: PathBuf,
+}
+
+/// Settings files an earlier build may have left: those in the folders
+/// `env` lists, then in each of `LEGACY_CONFIG_DIRS` under `config_base`.
+/// `current` is never one of them.
+pub(crate) fn legacy_settings_paths(
+    env: Option<std::ffi::OsString>,
+    config_base: Option<&Path>,
+    current: &Path,
+) -> Vec<PathBuf> {
+    let listed = env
+        .iter()
+        .flat_map(std::env::split_paths)
+        .filter(|dir| !dir.as_os_str().is_empty());
+    let known = config_base
+        .into_iter()
+        .flat_map(|base| LEGACY_CONFIG_DIRS.iter().map(move |dir| base.join(dir)));
+    listed
+        .chain(known)
+        .map(|dir| dir.join("settings.json"))
+        .filter(|path| path != current)
+        .collect()
+}
+
+/// The first of `paths` this version can read, with its settings migrated
+/// and what reading them left out. Files it can't read, or from a newer
+/// version, are passed over.
+pub(crate) fn find_legacy_settings(
+    paths: &[PathBuf],
+) -> Option<(PathBuf, Settings, Vec<SettingsWarning>)> {
+    paths.iter().find_map(|path| {
+        let data = fs::read_to_string(path).ok()?;
+        match parse_settings_leniently(&data) {
+            Ok((settings, _)) if settings.version > SETTINGS_VERSION => {
+                log::warn!(
+                    "settings: not migrating {}; version {} is newer than {}",
+                    path.display(),
+                    settings.version,
+                    SETTINGS_VERSION
+                );
+                None
+            }
+            Ok((settings, warnings)) => Some((path.clone(), settings, warnings)),
+            Err(err) => {
+                log::warn!("settings: not migrating {}: {}", path.display(), err);
+                None
+            }
+        }
+    })
+}
+
+/// Without a settings file at `path`, brings over an earlier build's from
+/// `legacy_settings_paths` and saves it at `path`, noting where it came
+/// from in `migrated_from`. The old file is never touched. Defaults when
+/// there's nothing to bring over.
+fn migrate_legacy_settings(app: &tauri::AppHandle, path: &Path) -> Settings {
+    let config_base = app.path().config_dir().ok();
+    let paths = legacy_settings_paths(
+        std::env::var_os(LEGACY_CONFIG_DIRS_ENV),
+        config_base.as_deref(),
+        path,
+    );
+    let Some((from, settings, warnings)) = find_legacy_settings(&paths) else {
+        return Settings::default();
+    };
+    let settings = Settings {
+        migrated_from: Some(from.clone()),
+        ..settings
+    };
+    log::info!("settings: migrated from {}", from.display());
+    report_settings_warnings(app, warnings);
+    // Migrated again next launch when this fails, as in safe mode.
+    if let Err(err) = persist_settings(app, settings.clone()) {
+        log::error!("settings: failed to save the migrated settings: {}", err);
+    }
+    let migrated = SettingsMigrated { from };
+    app.state::<AppState>()
+        .set_settings_migration(Some(migrated.clone()));
+    if let Err(err) = app.emit("settings-migrated", migrated) {
+        log::error!("settings: failed to emit settings-migrated: {}", err);
+    }
+    settings
+}
+
+/// Payload of `settings-read-only`.
+#[derive(Debug, Serialize, Clone)]
+struct SettingsReadOnly {
+    version: u32,
+    supported: u32,
+}
+
+/// Payload of `settings-recovered`.
+#[derive(Debug, Serialize, Clone)]
+pub(crate) struct SettingsRecovered {
+    /// Where the unreadable file was moved.
+    pub(crate) backup: PathBuf,
+    /// Its name in `backups/`, for `restore_settings_backup`.
+    pub(crate) name: String,
+    pub(crate) error: String,
+}
+
+pub(crate) fn load_settings(app: &tauri::AppHandle) -> Result<Settings, String> {
+    let path = settings_path(app)?;
+    if !path.exists() {
+        return Ok(migrate_legacy_settings(app, &path));
+    }
+    let data = fs::read_to_string(&path).map_err(|err| err.to_string())?;
+    app.state::<AppState>().note_settings_file(&data);
+    let settings = match parse_settings_leniently(&data) {
+        Ok((settings, warnings)) => {
+            report_settings_warnings(app, warnings);
+            settings
+        }
+        Err(error) => return recover_settings(app, &path, error),
+    };
+    let read_only = settings.version > SETTINGS_VERSION;
+    app.state::<AppState>().set_settings_read_only(read_only);
+    if read_only {
+        log::warn!(
+            "settings: version {} is newer than {}; not saving over it",
+            settings.version,
+            SETTINGS_VERSION
+        );
+        let payload = SettingsReadOnly {
+            version: settings.version,
+            supported: SETTINGS_VERSION,
+        };
+        if let Err(err) = app.emit("settings-read-only", payload) {
+            log::error!("settings: failed to emit settings-read-only: {}", err);
+        }
+    }
+    Ok(load_synced_settings(app, &path, settings))
+}
+
+/// Where `settings` keep their synced copy, unless that's `local` itself.
+pub(crate) fn synced_settings_path(settings: &Settings, local: &Path) -> Option<PathBuf> {
+    let path = settings.sync_dir.as_ref()?.join("settings.json");
+    (path != local).then_some(path)
+}
+
+/// Payload of `settings-sync-unavailable`.
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub(crate) struct SettingsSyncUnavailable {
+    pub(crate) dir: PathBuf,
+    pub(crate) error: String,
+}
+
+/// The synced copy's path while its folder can be reached. The folder
+/// going missing, as iCloud Drive's can until it mounts after login, or
+/// coming back is logged and emitted as `settings-sync-unavailable`, with
+/// `null` once it's back; the local copy stands in until then.
+pub(crate) fn reachable_synced_path(
+    app: &tauri::AppHandle,
+    settings: &Settings,
+    local: &Path,
+) -> Option<PathBuf> {
+    let Some(path) = synced_settings_path(settings, local) else {
+        report_sync_unavailable(app, None);
+        return None;
+    };
+    let dir = path.parent().unwrap_or(&path);
+    let error = match fs::metadata(dir) {
+        Ok(metadata) if metadata.is_dir() => None,
+        Ok(_) => Some("not a folder".to_string()),
+        Err(err) => Some(err.to_string()),
+    };
+    let unavailable = error.map(|error| SettingsSyncUnavailable {
+        dir: dir.to_path_buf(),
+        error,
+    });
+    let reachable = unavailable.is_none();
+    report_sync_unavailable(app, unavailable);
+    reachable.then_some(path)
+}
+
+/// Tells the window about a change in whether the sync folder is reachable.
+fn report_sync_unavailable(app: &tauri::AppHandle, unavailable: Option<SettingsSyncUnavailable>) {
+    if !app
+        .state::<AppState>()
+        .set_sync_unavailable(unavailable.clone())
+    {
+        return;
+    }
+    match &unavailable {
+        Some(unavailable) => log::warn!(
+            "settings: sync folder {} is unavailable ({}); using the local copy",
+            unavailable.dir.display(),
+            unavailable.error
+        ),
+        None => log::info!("settings: no longer falling back to the local copy"),
+    }
+    if let Err(err) = app.emit("settings-sync-unavailable", unavailable) {
+        log::error!(
+            "settings: failed to emit settings-sync-unavailable: {}",
+            err
+        );
+    }
+}
+
+/// `local`, the settings read from the config directory at `path`, or the
+/// sync folder's copy when it has one this version can read. `local`'s
+/// `sync_dir` is kept either way.
+fn load_synced_settings(app: &tauri::AppHandle, path: &Path, local: Settings) -> Settings {
+    let Some(synced) = reachable_synced_path(app, &local, path) else {
+        return local;
+    };
+    // Not synced yet; the next save puts it there.
+    let Ok(data) = fs::read_to_string(&synced) else {
+        return local;
+    };
+    match checked_settings(&data) {
+        Ok(settings) => {
+            app.state::<AppState>().note_synced_file(&data);
+            log::info!("settings: loaded the synced copy in {}", synced.display());
+            Settings {
+                sync_dir: local.sync_dir,
+                ..settings
+            }
+        }
+        Err(err) => {
+            log::warn!("settings: ignoring {}: {}", synced.display(), err);
+            local
+        }
+    }
+}
+
+/// Logs and emits as `settings-warnings` what loading the settings left
+/// out, once per change since the settings window may load them again. Kept
+/// for `get_settings_warnings` too, as the first load comes before the
+/// window listens.
+fn report_settings_warnings(app: &tauri::AppHandle, warnings: Vec<SettingsWarning>) {
+    if !app
+        .state::<AppState>()
+        .set_settings_warnings(warnings.clone())
+    {
+        return;
+    }
+    for warning in &warnings {
+        log::warn!("settings: {}", warning.message);
+    }
+    if let Err(err) = app.emit("settings-warnings", warnings) {
+        log::error!("settings: failed to emit settings-warnings: {}", err);
+    }
+}
+
+/// Moves a settings file that doesn't parse out of the way so it isn't
+/// overwritten by the next save, and tells the settings window where it went.
+/// The notice is also kept for `get_settings_recovery`, since this usually
+/// happens during setup before the window listens.
+pub(crate) fn recover_settings(
+    app: &tauri::AppHandle,
+    path: &Path,
+    error: String,
+) -> Result<Settings, String> {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or_default();
+    let backup =
+        move_corrupt_settings(path, &settings_backups_dir(path), timestamp).map_err(|err| {
+            format!(
+                "settings are unreadable ({}) and couldn't be moved aside: {}",
+                error, err
+            )
+        })?;
+    log::error!(
+        "settings: {} is unreadable ({}); moved it to {} and using defaults",
+        path.display(),
+        error,
+        backup.display()
+    );
+    let name = backup
+        .file_name()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .into_owned();
+    let recovered = SettingsRecovered {
+        backup,
+        name,
+        error,
+    };
+    app.state::<AppState>()
+        .set_settings_recovery(Some(recovered.clone()));
+    if let Err(err) = app.emit("settings-recovered", recovered) {
+        log::error!("settings: failed to emit settings-recovered: {}", err);
+    }
+    Ok(Settings::default())
+}
+
+/// Moves `path` into `backups` as `<name>.corrupt-<timestamp>`, with a
+/// counter appended if an earlier backup from the same second exists.
+/// Pruning leaves these alone.
+pub(crate) fn move_corrupt_settings(
+    path: &Path,
+    backups: &Path,
+    timestamp: u64,
+) -> std::io::Result<PathBuf> {
+    fs::create_dir_all(backups)?;
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let mut backup = backups.join(format!("{}.corrupt-{}", name, timestamp));
+    let mut attempt = 1;
+    while backup.exists() {
+        attempt += 1;
+        backup = backups.join(format!("{}.corrupt-{}-{}", name, timestamp, attempt));
+    }
+    fs::rename(path, &backup)?;
+    Ok(backup)
+}
+
+/// How many copies of earlier settings `back_up_settings` keeps.
+pub(crate) const SETTINGS_BACKUPS: usize = 10;
+
+/// Folder next to the settings file that holds its backups.
+pub(crate) fn settings_backups_dir(path: &Path) -> PathBuf {
+    path.with_file_name("backups")
+}
+
+/// Copies the settings file at `path` into `backups` as
+/// `settings-<milliseconds>.json` before it's replaced, unless it's the same
+/// as the newest copy, and drops all but the newest `SETTINGS_BACKUPS`.
+pub(crate) fn back_up_settings(
+    path: &Path,
+    backups: &Path,
+    timestamp: u128,
+) -> std::io::Result<()> {
+    let current = match fs::read(path) {
+        Ok(current) => current,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(err) => return Err(err),
+    };
+    fs::create_dir_all(backups)?;
+    let mut names = rolling_backups(backups)?;
+    if let Some(newest) = names.last() {
+        if fs::read(backups.join(newest)).is_ok_and(|newest| newest == current) {
+            return Ok(());
+        }
+    }
+    let name = format!("settings-{:013}.json", timestamp);
+    write_atomically(&backups.join(&name), |file| file.write_all(&current))?;
+    names.push(name);
+    let excess = names.len().saturating_sub(SETTINGS_BACKUPS);
+    for name in &names[..excess] {
+        fs::remove_file(backups.join(name))?;
+    }
+    Ok(())
+}
+
+/// Names of the `back_up_settings` copies in `backups`, oldest first.
+pub(crate) fn rolling_backups(backups: &Path) -> std::io::Result<Vec<String>> {
+    let mut names: Vec<String> = fs::read_dir(backups)?
+        .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+        .filter(|name| is_rolling_backup(name))
+        .collect();
+    names.sort();
+    Ok(names)
+}
+
+fn is_rolling_backup(name: &str) -> bool {
+    name.starts_with("settings-") && name.ends_with(".json")
+}
+
+/// Entry of `list_settings_backups`.
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub(crate) struct SettingsBackup {
+    pub(crate) name: String,
+    /// Seconds since the Unix epoch the backup was made.
+    pub(crate) saved: u64,
+    /// Moved aside by `recover_settings` because it didn't parse.
+    pub(crate) corrupt: bool,
+}
+
+/// Every backup in `backups`, newest first.
+pub(crate) fn settings_backups(backups: &Path) -> std::io::Result<Vec<SettingsBackup>> {
+    let entries = match fs::read_dir(backups) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err),
+    };
+    let mut list: Vec<SettingsBackup> = entries
+        .filter_map(|entry| {
+            let entry = entry.ok()?;
+            let name = entry.file_name().into_string().ok()?;
+            let corrupt = name.starts_with("settings.json.corrupt-");
+            if !corrupt && !is_rolling_backup(&name) {
+                return None;
+            }
+            let saved = entry
+                .metadata()
+                .and_then(|metadata| metadata.modified())
+                .ok()
+                .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+                .map(|elapsed| elapsed.as_secs())
+                .unwrap_or_default();
+            Some(SettingsBackup {
+                name,
+                saved,
+                corrupt,
+            })
+        })
+        .collect();
+    list.sort_by(|a, b| b.saved.cmp(&a.saved).then_with(|| b.name.cmp(&a.name)));
+    Ok(list)
+}
+
+/// Parses a settings file, upgrading older versions step by step. A file
+/// from a newer version is read as well as this version understands it and
+/// keeps its `version`, which `load_settings` checks.
+pub(crate) fn parse_settings(data: &str) -> Result<Settings, String> {
+    let object = migrated_settings(data)?;
+    let mut settings: Settings =
+        serde_json::from_value(serde_json::Value::Object(object)).map_err(|err| err.to_string())?;
+    settings.normalize_profiles();
+    Ok(settings)
+}
+
+/// Something `parse_settings_leniently` left out to load a settings file.
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub(crate) struct SettingsWarning {
+    /// Top-level settings field, such as `"profiles"`.
+    pub(crate) field: String,
+    pub(crate) profile: Option<String>,
+    pub(crate) device_id: Option<String>,
+    pub(crate) message: String,
+}
+
+/// Like `parse_settings` for the file the app starts with, which a typo
+/// or a wrong type shouldn't empty: a device config or profile that
+/// doesn't fit is dropped and any other field that doesn't is reset to its
+/// default, each with a warning. Only a file that isn't a JSON object with
+/// a usable version is an error.
+pub(crate) fn parse_settings_leniently(
+    data: &str,
+) -> Result<(Settings, Vec<SettingsWarning>), String> {
+    let mut object = migrated_settings(data)?;
+    let mut warnings = Vec::new();
+    if let Some(profiles) = object
+        .get_mut("profiles")
+        .and_then(serde_json::Value::as_object_mut)
+    {
+        profiles.retain(|name, profile| {
+            if let Some(devices) = profile
+                .get_mut("devices")
+                .and_then(serde_json::Value::as_object_mut)
+            {
+                devices.retain(|id, config| {
+                    let Some(reason) = misfit::<DeviceConfig>(config) else {
+                        return true;
+                    };
+                    warnings.push(SettingsWarning {
+                        field: "profiles".to_string(),
+                        profile: Some(name.clone()),
+                        device_id: Some(id.clone()),
+                        message: format!(
+                            "devices entry '{}' in profile {} dropped: {}",
+                            id, name, reason
+                        ),
+                    });
+                    false
+                });
+            }
+            let Some(reason) = misfit::<Profile>(profile) else {
+                return true;
+            };
+            warnings.push(SettingsWarning {
+                field: "profiles".to_string(),
+                profile: Some(name.clone()),
+                device_id: None,
+                message: format!("profile {} dropped: {}", name, reason),
+            });
+            false
+        });
+    }
+
+    let newer = object
+        .get("version")
+        .and_then(serde_json::Value::as_u64)
+        .is_some_and(|version| version > u64::from(SETTINGS_VERSION));
+    let known = match serde_json::to_value(Settings::default()) {
+        Ok(serde_json::Value::Object(known)) => known,
+        _ => serde_json::Map::new(),
+    };
+    object.retain(|field, value| {
+        if !known.contains_key(field) {
+            // A newer version's fields are expected, and kept for its sake.
+            if !newer {
+                warnings.push(SettingsWarning {
+                    field: field.clone(),
+                    profile: None,
+                    device_id: None,
+                    message: format!("unknown field '{}' ignored", field),
+                });
+            }
+            return true;
+        }
+        let single = serde_json::Map::from_iter([(field.clone(), value.clone())]);
+        let Err(err) = serde_json::from_value::<Settings>(serde_json::Value::Object(single)) else {
+            return true;
+        };
+        warnings.push(SettingsWarning {
+            field: field.clone(),
+            profile: None,
+            device_id: None,
+            message: format!("{} reset to its default: {}", field, err),
+        });
+        false
+    });
+
+    let mut settings: Settings =
+        serde_json::from_value(serde_json::Value::Object(object)).map_err(|err| err.to_string())?;
+    settings.normalize_profiles();
+    Ok((settings, warnings))
+}
+
+/// Why `value` doesn't deserialize as a `T`: the first of its fields that
+/// doesn't on its own, as "buttons: invalid type: ...", or serde's error
+/// when none does.
+fn misfit<T: serde::de::DeserializeOwned>(value: &serde_json::Value) -> Option<String> {
+    let err = serde_json::from_value::<T>(value.clone()).err()?;
+    let fields = value.as_object().into_iter().flatten();
+    for (field, inner) in fields {
+        let single = serde_json::Map::from_iter([(field.clone(), inner.clone())]);
+        if let Err(err) = serde_json::from_value::<T>(serde_json::Value::Object(single)) {
+            return Some(format!("{}: {}", field, err));
+        }
+    }
+    Some(err.to_string())
+}
+
+/// A settings file's JSON object, migrated to `SETTINGS_VERSION` when it's
+/// older.
+fn migrated_settings(data: &str) -> Result<serde_json::Map<String, serde_json::Value>, String> {
+    let value: serde_json::Value = serde_json::from_str(data).map_err(|err| err.to_string())?;
+    let serde_json::Value::Object(mut object) = value else {
+        return Err("settings must be a JSON object".to_string());
+    };
+    let version = object
+        .get("version")
+        .map_or(Some(0), serde_json::Value::as_u64)
+        .and_then(|version| u32::try_from(version).ok())
+        .ok_or_else(|| "settings version must be a number".to_string())?;
+    if version < SETTINGS_VERSION {
+        for migrate in &SETTINGS_MIGRATIONS[version as usize..] {
+            migrate(&mut object);
+        }
+        object.insert("version".to_string(), SETTINGS_VERSION.into());
+    }
+    Ok(object)
+}
+
+/// Saves `settings`, through the settings writer once it runs. Its writes
+/// fail later, reported by `settings-save-failed` and the next flush.
+pub(crate) fn persist_settings(app: &tauri::AppHandle, settings: Settings) -> Result<(), String> {
+    if app.state::<AppState>().is_settings_read_only() {
+        return Err(
+            "settings were saved by a newer version of Edit Mouse and are read-only".to_string(),
+        );
+    }
+    if app.state::<AppState>().is_safe_mode() {
+        return Err("settings are read-only in safe mode; exit it to save changes".to_string());
+    }
+    if app.state::<AppState>().queue_settings_write(&settings) {
+        return Ok(());
+    }
+    write_settings(app, &settings)
+}
+
+/// Saves what the settings writer still holds before the app exits. When it
+/// fails or doesn't answer in time, the current settings are written here.
+pub(crate) fn save_settings_before_exit(app: &tauri::AppHandle) {
+    let state = app.state::<AppState>();
+    let Err(err) = state.flush_settings() else {
+        return;
+    };
+    log::warn!("settings: {}; writing them before exiting", err);
+    if state.is_settings_read_only() || state.is_safe_mode() {
+        return;
+    }
+    if let Err(err) = write_settings(app, &state.snapshot_settings()) {
+        log::error!("settings: failed to save before exiting: {}", err);
+    }
+}
+
+/// Quiet time after the latest change before the settings writer saves, so
+/// a rename typed out or a run of picks is written once.
+const SETTINGS_WRITE_DELAY: Duration = Duration::from_millis(500);
+
+/// What `persist_settings` and `AppState::flush_settings` tell the
+/// settings writer.
+pub(crate) enum SettingsWrite {
+    /// Write these once `SETTINGS_WRITE_DELAY` passes without newer ones.
+    Changed(Box<Settings>),
+    /// Write what's waiting now, then answer with how the latest write went.
+    Flush(std::sync::mpsc::Sender<Result<(), String>>),
+}
+
+/// Payload of `settings-save-failed`.
+#[derive(Debug, Serialize, Clone)]
+struct SettingsSaveFailed {
+    error: String,
+}
+
+/// Starts the thread `persist_settings` hands settings to, so saving them
+/// doesn't hold up the main thread. Until it runs they're written at once.
+pub(crate) fn start_settings_writer(app: &tauri::AppHandle) {
+    let (sender, receiver) = std::sync::mpsc::channel();
+    if app.state::<AppState>().settings_writer.set(sender).is_err() {
+        return;
+    }
+    let app = app.clone();
+    std::thread::spawn(move || {
+        write_settled_settings(&receiver, SETTINGS_WRITE_DELAY, |settings| {
+            let Err(error) = write_settings(&app, settings) else {
+                return Ok(());
+            };
+            log::error!("settings: failed to save: {}", error);
+            let failed = SettingsSaveFailed {
+                error: error.clone(),
+            };
+            if let Err(err) = app.emit("settings-save-failed", failed) {
+                log::error!("settings: failed to emit settings-save-failed: {}", err);
+            }
+            Err(error)
+        });
+    });
+}
+
+/// Hands the settings `receiver` gets to `write` once `delay` passes
+/// without newer ones, or right away on a flush, until every sender is
+/// gone; what's waiting then is written before it returns. A flush is
+/// answered with the error of the latest write when it failed.
+pub(crate) fn write_settled_settings(
+    receiver: &std::sync::mpsc::Receiver<SettingsWrite>,
+    delay: Duration,
+    mut write: impl FnMut(&Settings) -> Result<(), String>,
+) {
+    use std::sync::mpsc::RecvTimeoutError;
+
+    // The latest settings not written yet, and when they came.
+    let mut pending: Option<(Settings, Instant)> = None;
+    let mut written = Ok(());
+    loop {
+        let received = match &pending {
+            Some((_, since)) => receiver.recv_timeout(delay.saturating_sub(since.elapsed())),
+            None => receiver.recv().map_err(|_| RecvTimeoutError::Disconnected),
+        };
+        let mut answer = None;
+        match received {
+            Ok(SettingsWrite::Changed(settings)) => pending = Some((*settings, Instant::now())),
+            Ok(SettingsWrite::Flush(done)) => answer = Some(done),
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => {
+                if let Some((settings, _)) = pending.take() {
+                    let _ = write(&settings);
+                }
+                return;
+            }
+        }
+        let due = pending
+            .as_ref()
+            .is_some_and(|(_, since)| since.elapsed() >= delay);
+        if due || answer.is_some() {
+            if let Some((settings, _)) = pending.take() {
+                written = write(&settings);
+            }
+        }
+        if let Some(done) = answer {
+            let _ = done.send(written.clone());
+        }
+    }
+}
+
+/// Writes `settings` to the config directory, and to the sync folder when
+/// one is set and can be reached.
+fn write_settings(app: &tauri::AppHandle, settings: &Settings) -> Result<(), String> {
+    let path = settings_path(app)?;
+    write_local_settings(app, &path, settings)?;
+    if let Some(synced) = reachable_synced_path(app, settings, &path) {
+        write_synced_settings(app, &synced, settings);
+    }
+    Ok(())
+}
+
+/// Backs up and replaces the settings in the config directory.
+fn write_local_settings(
+    app: &tauri::AppHandle,
+    path: &Path,
+    settings: &Settings,
+) -> Result<(), String> {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_millis())
+        .unwrap_or_default();
+    if let Err(err) = back_up_settings(path, &settings_backups_dir(path), timestamp) {
+        log::error!("settings: failed to back up {}: {}", path.display(), err);
+    }
+    let settings = Settings {
+        version: SETTINGS_VERSION,
+        ..settings.clone()
+    };
+    let data = serde_json::to_string_pretty(&settings).map_err(|err| err.to_string())?;
+    // Noted first, so the watcher never sees this write as someone else's.
+    app.state::<AppState>().note_settings_file(&data);
+    write_atomically(path, |file| file.write_all(data.as_bytes())).map_err(|err| err.to_string())
+}
+
+/// Replaces the synced copy at `path`, unless it changed since the app last
+/// read or wrote it: then another machine saved since, and the watcher
+/// loads that rather than it being lost. That also keeps the copy a newly
+/// picked sync folder already has. The local copy is saved either way, so
+/// a failed write only makes the folder unavailable.
+fn write_synced_settings(app: &tauri::AppHandle, path: &Path, settings: &Settings) {
+    let state = app.state::<AppState>();
+    if fs::read_to_string(path).is_ok_and(|current| state.is_synced_file_changed(&current)) {
+        log::warn!(
+            "settings: {} changed on another machine; loading it rather than saving over it",
+            path.display()
+        );
+        return;
+    }
+    let synced = Settings {
+        version: SETTINGS_VERSION,
+        sync_dir: None,
+        ..settings.clone()
+    };
+    let written = serde_json::to_string_pretty(&synced)
+        .map_err(|err| err.to_string())
+        .and_then(|data| {
+            state.note_synced_file(&data);
+            write_atomically(path, |file| file.write_all(data.as_bytes()))
+                .map_err(|err| err.to_string())
+        });
+    if let Err(error) = written {
+        let dir = path.parent().unwrap_or(path).to_path_buf();
+        report_sync_unavailable(app, Some(SettingsSyncUnavailable { dir, error }));
+    }
+}
+
+/// Replaces `path` with what `write` puts in a temporary file next to it, so
+/// a crash or power loss mid-write leaves either the old file or the new one,
+/// never a truncated mix. The temporary file is flushed to disk before it's
+/// renamed over `path`; `fs::rename` replaces an existing file on Windows as
+/// well.
+pub(crate) fn write_atomically(
+    path: &Path,
+    write: impl FnOnce(&mut fs::File) -> std::io::Result<()>,
+) -> std::io::Result<()> {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".tmp");
+    let temp = path.with_file_name(name);
+    let written = fs::File::create(&temp).and_then(|mut file| {
+        write(&mut file)?;
+        file.sync_all()
+    });
+    if let Err(err) = written.and_then(|()| fs::rename(&temp, path)) {
+        let _ = fs::remove_file(&temp);
+        return Err(err);
+    }
+    // Makes the rename itself durable. The new file is in place either
+    // way, so failing here isn't a failed write.
+    #[cfg(unix)]
+    if let Some(dir) = path.parent() {
+        if let Err(err) = fs::File::open(dir).and_then(|dir| dir.sync_all()) {
+            log::warn!("files: failed to sync {}: {}", dir.display(), err);
+        }
+    }
+    Ok(())
+}
+
+/// How often `watch_settings_file` looks for edits made outside the app.
+const SETTINGS_POLL: Duration = Duration::from_secs(1);
+
+/// Reads settings edited outside the app. `None` when `data` is what the app
+/// last wrote or read, so its own saves never reload. An edit that doesn't
+/// parse or validate is an error, and is only looked at again once the file
+/// changes.
+pub(crate) fn external_settings(state: &AppState, data: &str) -> Option<Result<Settings, String>> {
+    if !state.note_settings_file(data) {
+        return None;
+    }
+    Some(checked_settings(data))
+}
+
+/// What `AppState` remembers of a settings file it read or wrote, to tell
+/// its own writes from others'.
+pub(crate) fn settings_digest(data: &str) -> u64 {
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    data.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Settings from a file another program may have written, which have to
+/// parse, validate, and not be from a newer version.
+pub(crate) fn checked_settings(data: &str) -> Result<Settings, String> {
+    let settings = parse_settings(data)?;
+    if settings.version > SETTINGS_VERSION {
+        return Err(format!(
+            "version {} is newer than {}",
+            settings.version, SETTINGS_VERSION
+        ));
+    }
+    settings.validate()?;
+    Ok(settings)
+}
+
+/// Polls the settings file and applies edits made outside the app, such as
+/// one kept in a dotfiles repository, as if the settings window saved them.
+/// The sync folder's copy is polled as well, for changes synced from
+/// another machine.
+pub(crate) fn watch_settings_file(app: tauri::AppHandle, state: AppState) {
+    let path = match settings_path(&app) {
+        Ok(path) => path,
+        Err(err) => {
+            log::warn!("settings: not watching for edits: {}", err);
+            return;
+        }
+    };
+    std::thread::spawn(move || {
+        let mut modified = None;
+        let mut synced_modified = None;
+        loop {
+            std::thread::sleep(SETTINGS_POLL);
+            if let Some(data) = read_if_modified(&path, &mut modified) {
+                match external_settings(&state, &data) {
+                    None => {}
+                    Some(Ok(settings)) => {
+                        log::info!("settings: reloaded {}", path.display());
+                        state.set_settings_read_only(false);
+                        if let Err(err) = state.adopt_settings(&app, settings) {
+                            log::error!("settings: failed to reload: {}", err);
+                        }
+                    }
+                    Some(Err(err)) => {
+                        log::warn!("settings: ignoring edit to {}: {}", path.display(), err)
+                    }
+                }
+            }
+            let synced = reachable_synced_path(&app, &state.snapshot_settings(), &path);
+            if let Some(data) = synced
+                .as_deref()
+                .and_then(|synced| read_if_modified(synced, &mut synced_modified))
+            {
+                adopt_synced_settings(&app, &state, &path, &data);
+            }
+        }
+    });
+}
+
+/// What `path` holds, when it was modified since the time in `modified`
+/// for the same path; `modified` is brought up to date.
+pub(crate) fn read_if_modified(
+    path: &Path,
+    modified: &mut Option<(PathBuf, SystemTime)>,
+) -> Option<String> {
+    let stamp = fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()?;
+    let seen = (path.to_path_buf(), stamp);
+    if modified.replace(seen.clone()) == Some(seen) {
+        return None;
+    }
+    fs::read_to_string(path).ok()
+}
+
+/// Applies the sync folder's copy once another machine changed it, keeping
+/// this machine's `sync_dir`, and saves it over the local copy at `path`.
+fn adopt_synced_settings(app: &tauri::AppHandle, state: &AppState, path: &Path, data: &str) {
+    if !state.is_synced_file_changed(data) {
+        return;
+    }
+    let settings = match checked_settings(data) {
+        Ok(settings) => settings,
+        Err(err) => return log::warn!("settings: ignoring the synced copy: {}", err),
+    };
+    state.note_synced_file(data);
+    log::info!("settings: loaded the synced copy");
+    let settings = Settings {
+        sync_dir: state.snapshot_settings().sync_dir,
+        ..settings
+    };
+    match state.adopt_settings(app, settings) {
+        Ok(settings) if !state.is_safe_mode() && !state.is_settings_read_only() => {
+            if let Err(err) = write_local_settings(app, path, &settings) {
+                log::error!("settings: failed to save the synced copy locally: {}", err);
+            }
+        }
+        Ok(_) => {}
+        Err(err) => log::error!("settings: failed to load the synced copy: {}", err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn action_of(config: &DeviceConfig, button: i64, button_count: u8) -> Action {
+        config
+            .mapping(button, button_count, None, 0)
+            .map_or(Action::Default, ActionValue::action)
+    }
+
+    /// A settings file as the app wrote it before versioning, with every
+    /// kind of mapping.
+    const SETTINGS_V0: &str = r#"{
+  "theme": "dark",
+  "startup": true,
+  "selected_device": "046d:c08b:A",
+  "profiles": {
+    "Default": {
+      "devices": {
+        "046d:c08b:A": {
+          "name": "G502",
+          "buttons": {
+            "left": "Default",
+            "button4": "Back",
+            "button5": {
+              "gesture": {
+                "tap": "Forward",
+                "up": "Next Profile",
+                "down": "Previous Profile",
+                "left": "Back",
+                "right": "Forward",
+                "threshold": 80.0,
+                "suppress_cursor": false
+              }
+            }
+          },
+          "extended_buttons": true,
+          "dpi_stops": [800, 1600, 3200],
+          "app_overrides": {
+            "com.apple.Safari": { "button4": "Middle Click" }
+          },
+          "modifier_mappings": {
+            "button4": [{ "modifiers": ["shift", "command"], "action": "Forward" }]
+          },
+          "merge_modifiers": ["button5"]
+        }
+      }
+    },
+    "Games": {
+      "devices": {
+        "046d:c08b:A": {
+          "name": "G502",
+          "buttons": { "middle": "Set DPI 400" },
+          "extended_buttons": false,
+          "dpi_stops": [],
+          "app_overrides": {},
+          "modifier_mappings": {},
+          "merge_modifiers": []
+        }
+      }
+    }
+  },
+  "active_profile": "Games",
+  "profile_app_rules": [{ "bundle_id": "com.valve.steam", "profile": "Games" }],
+  "include_all_pointing_devices": true,
+  "show_non_mouse_devices": true,
+  "excluded_apps": ["com.apple.Terminal"],
+  "game_mode": true,
+  "remap_enabled": false
+}"#;
+
+    #[test]
+    fn extra_buttons_round_trip() {
+        let mut config = DeviceConfig {
+            name: "Naga".to_string(),
+            buttons: default_buttons(10),
+            ..DeviceConfig::default()
+        };
+        assert_eq!(config.buttons.len(), 10);
+        config
+            .buttons
+            .insert("button7".to_string(), ActionValue::Action(Action::Back));
+        let mut settings = Settings::default();
+        settings
+            .profiles
+            .get_mut(DEFAULT_PROFILE)
+            .unwrap()
+            .devices
+            .insert("1532:0040:A".to_string(), config);
+
+        let json = serde_json::to_string(&settings).unwrap();
+        let loaded = parse_settings(&json).unwrap();
+        let config = loaded.device_config("1532:0040:A").unwrap();
+        assert_eq!(config.buttons["button7"], ActionValue::Action(Action::Back));
+        assert_eq!(action_of(config, 6, 10), Action::Back);
+        // A five-button device never resolves the extra slots.
+        assert_eq!(action_of(config, 6, 5), Action::Default);
+    }
+
+    #[test]
+    fn configs_without_extra_buttons_still_load() {
+        let json = r#"{"devices":{"046d:c08b:A":{"name":"G502","buttons":{"button4":"Back"}}}}"#;
+        let settings = parse_settings(json).unwrap();
+        let config = settings.device_config("046d:c08b:A").unwrap();
+        assert_eq!(action_of(config, 3, 10), Action::Back);
+        assert_eq!(action_of(config, 8, 10), Action::Default);
+        assert_eq!(DeviceConfig::default().buttons.len(), 5);
+    }
+
+    #[test]
+    fn legacy_devices_migrate_into_default_profile() {
+        let json = r#"{"selected_device":"046d:c08b:A","devices":{"046d:c08b:A":{"name":"G502","buttons":{"button4":"Back"}}}}"#;
+        let settings = parse_settings(json).unwrap();
+        assert_eq!(settings.profile_names(), vec![DEFAULT_PROFILE.to_string()]);
+        assert_eq!(settings.active_profile, DEFAULT_PROFILE);
+        assert_eq!(
+            action_of(settings.device_config("046d:c08b:A").unwrap(), 3, 5),
+            Action::Back
+        );
+
+        let saved = serde_json::to_string(&settings).unwrap();
+        let value: serde_json::Value = serde_json::from_str(&saved).unwrap();
+        assert!(value.get("devices").is_none());
+        let reloaded = parse_settings(&saved).unwrap();
+        assert!(reloaded.device_config("046d:c08b:A").is_some());
+    }
+
+    #[test]
+    fn config_dir_comes_from_flag_then_environment_then_portable_flag() {
+        let args = |args: &[&str]| -> Vec<std::ffi::OsString> {
+            args.iter().map(|arg| arg.into()).collect()
+        };
+        let exe = Path::new("/Volumes/USB/Edit Mouse/edit-mouse");
+        let env = || Some(std::ffi::OsString::from("/etc/edit-mouse"));
+        let resolve = |argv: &[&str], env: Option<std::ffi::OsString>, portable: bool| {
+            configured_config_dir(args(argv), env, Some(exe), |_| portable)
+        };
+
+        assert_eq!(
+            resolve(&["--config-dir", "/tmp/a"], env(), true),
+            Some((PathBuf::from("/tmp/a"), ConfigDirSource::Flag))
+        );
+        assert_eq!(
+            resolve(&["--verbose", "--config-dir=/tmp/b"], env(), true),
+            Some((PathBuf::from("/tmp/b"), ConfigDirSource::Flag))
+        );
+        assert_eq!(
+            resolve(&["--config-dir"], env(), false),
+            Some((
+                PathBuf::from("/etc/edit-mouse"),
+                ConfigDirSource::Environment
+            ))
+        );
+        assert_eq!(
+            resolve(&[], Some("".into()), true),
+            Some((
+                PathBuf::from("/Volumes/USB/Edit Mouse").join(PORTABLE_CONFIG_DIR),
+                ConfigDirSource::Portable
+            ))
+        );
+        assert_eq!(resolve(&[], None, false), None);
+        let checked = std::cell::RefCell::new(Vec::new());
+        configured_config_dir(args(&[]), None, Some(exe), |flag| {
+            checked.borrow_mut().push(flag.to_path_buf());
+            false
+        });
+        assert_eq!(
+            checked.into_inner(),
+            vec![PathBuf::from("/Volumes/USB/Edit Mouse/portable.flag")]
+        );
+    }
+
+    #[test]
+    fn interrupted_settings_writes_keep_the_previous_file() {
+        let dir = crate::tests::TempDir::new("writes");
+        let path = dir.join("settings.json");
+        let previous = Settings {
+            theme: "dark".to_string(),
+            ..Settings::default()
+        };
+        let data = serde_json::to_string_pretty(&previous).unwrap();
+        write_atomically(&path, |file| file.write_all(data.as_bytes())).unwrap();
+
+        // Half of the next file makes it out before the write fails.
+        let next = serde_json::to_string_pretty(&Settings::default()).unwrap();
+        let failed = write_atomically(&path, |file| {
+            file.write_all(&next.as_bytes()[..next.len() / 2])?;
+            Err(std::io::Error::other("disk full"))
+        });
+        assert!(failed.is_err());
+        let survived = parse_settings(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(survived.theme, "dark");
+        assert!(!dir.join("settings.json.tmp").exists());
+
+        // A temporary file left by a crash doesn't get in the way either.
+        fs::write(dir.join("settings.json.tmp"), &next[..10]).unwrap();
+        write_atomically(&path, |file| file.write_all(next.as_bytes())).unwrap();
+        let replaced = parse_settings(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(replaced.theme, "system");
+    }
+
+    #[test]
+    fn corrupt_settings_are_moved_aside_without_overwriting_backups() {
+        let dir = crate::tests::TempDir::new("corrupt");
+        let path = dir.join("settings.json");
+        let backups = settings_backups_dir(&path);
+
+        fs::write(&path, "{\"theme\": \"dark\",").unwrap();
+        assert!(parse_settings(&fs::read_to_string(&path).unwrap()).is_err());
+        let first = move_corrupt_settings(&path, &backups, 1_700_000_000).unwrap();
+        assert_eq!(first, backups.join("settings.json.corrupt-1700000000"));
+        assert!(!path.exists());
+
+        fs::write(&path, "not json").unwrap();
+        let second = move_corrupt_settings(&path, &backups, 1_700_000_000).unwrap();
+        assert_eq!(second, backups.join("settings.json.corrupt-1700000000-2"));
+        assert_eq!(fs::read_to_string(&first).unwrap(), "{\"theme\": \"dark\",");
+        assert_eq!(fs::read_to_string(&second).unwrap(), "not json");
+    }
+
+    #[test]
+    fn legacy_settings_are_found_in_listed_folders_then_known_ones() {
+        let dir = crate::tests::TempDir::new("legacy");
+        let base = dir.join("config");
+        let current = base.join(BUNDLE_IDENTIFIER).join("settings.json");
+        let listed = std::env::join_paths([dir.join("old"), dir.join("older")]).unwrap();
+        let paths = legacy_settings_paths(Some(listed), Some(&base), &current);
+        assert_eq!(paths[0], dir.join("old").join("settings.json"));
+        assert_eq!(paths[2], base.join("com.tauri.dev").join("settings.json"));
+        assert_eq!(paths.len(), 6);
+        let same = legacy_settings_paths(Some(current.parent().unwrap().into()), None, &current);
+        assert!(same.is_empty());
+
+        assert!(find_legacy_settings(&paths).is_none());
+        fs::create_dir_all(dir.join("old")).unwrap();
+        fs::write(&paths[0], "{\"theme\": \"dark\",").unwrap();
+        fs::create_dir_all(base.join("Edit Mouse")).unwrap();
+        let old = base.join("Edit Mouse").join("settings.json");
+        fs::write(&old, r#"{"version": 1, "theme": "dark"}"#).unwrap();
+        let (from, settings, _) = find_legacy_settings(&paths).unwrap();
+        assert_eq!(from, old);
+        assert_eq!(settings.theme, "dark");
+        assert_eq!(settings.version, SETTINGS_VERSION);
+        assert!(settings.onboarding_completed);
+        fs::write(&paths[0], r#"{"version": 999}"#).unwrap();
+        assert_eq!(find_legacy_settings(&paths).unwrap().0, old);
+    }
+
+    #[test]
+    fn settings_backups_roll_over_and_spare_corrupt_files() {
+        let dir = crate::tests::TempDir::new("backups");
+        let path = dir.join("settings.json");
+        let backups = settings_backups_dir(&path);
+
+        // Nothing to back up before the first save.
+        back_up_settings(&path, &backups, 1).unwrap();
+        assert!(!backups.exists());
+
+        fs::create_dir_all(&backups).unwrap();
+        fs::write(backups.join("settings.json.corrupt-5"), "{").unwrap();
+        for save in 0..SETTINGS_BACKUPS + 3 {
+            fs::write(&path, format!("{{\"theme\": \"theme-{}\"}}", save)).unwrap();
+            back_up_settings(&path, &backups, 1_700_000_000_000 + save as u128).unwrap();
+        }
+        // Saving without a change doesn't push out an older copy.
+        back_up_settings(&path, &backups, 1_800_000_000_000).unwrap();
+
+        let names = rolling_backups(&backups).unwrap();
+        assert_eq!(names.len(), SETTINGS_BACKUPS);
+        assert_eq!(names[0], "settings-1700000000003.json");
+        let newest = fs::read_to_string(backups.join(names.last().unwrap())).unwrap();
+        assert_eq!(
+            parse_settings(&newest).unwrap().theme,
+            format!("theme-{}", SETTINGS_BACKUPS + 2)
+        );
+        let listed = settings_backups(&backups).unwrap();
+        assert_eq!(listed.len(), SETTINGS_BACKUPS + 1);
+        assert_eq!(listed.iter().filter(|backup| backup.corrupt).count(), 1);
+    }
+
+    #[test]
+    fn forgetting_a_device_drops_pristine_configs_gone_for_months() {
+        let mut settings = Settings::default();
+        let stale = STALE_PRISTINE_DEVICE.as_secs();
+        let devices = &mut settings.profiles.get_mut(DEFAULT_PROFILE).unwrap().devices;
+        for (id, pristine, last_seen) in [
+            ("046d:c08b:A", false, Some(0)),
+            ("046d:b023:B", true, Some(0)),
+            ("046d:b023:C", true, Some(1)),
+            ("046d:b023:D", true, None),
+            ("046d:b023:E", false, Some(0)),
+        ] {
+            devices.insert(
+                id.to_string(),
+                DeviceConfig {
+                    pristine,
+                    last_seen,
+                    ..DeviceConfig::default()
+                },
+            );
+        }
+        assert_eq!(
+            settings.forget_device("046d:c08b:A", stale),
+            Ok(vec!["046d:b023:B".to_string(), "046d:c08b:A".to_string()])
+        );
+        let mut left: Vec<&String> = settings.profiles[DEFAULT_PROFILE].devices.keys().collect();
+        left.sort();
+        assert_eq!(left, ["046d:b023:C", "046d:b023:D", "046d:b023:E"]);
+        assert!(settings.forget_device("046d:c08b:A", stale).is_err());
+    }
+
+    #[test]
+    fn remapping_the_left_button_takes_an_acknowledgement() {
+        let previous = Settings::default();
+        let mut settings = previous.clone();
+        settings
+            .set_button_action("m", "button4", ActionValue::Action(Action::Back))
+            .unwrap();
+        assert!(settings.check_primary_remap(&previous, false).is_ok());
+
+        let mut disabled = settings.clone();
+        disabled
+            .set_button_action("m", "left", ActionValue::Action(Action::Disabled))
+            .unwrap();
+        let err = disabled.check_primary_remap(&settings, false).unwrap_err();
+        assert!(err.contains("acknowledge_primary_remap"), "{}", err);
+        assert!(disabled.check_primary_remap(&settings, true).is_ok());
+        // Once saved, other edits don't ask again.
+        assert!(disabled.check_primary_remap(&disabled, false).is_ok());
+
+        let mut overridden = settings.clone();
+        overridden
+            .profiles
+            .get_mut(DEFAULT_PROFILE)
+            .unwrap()
+            .devices
+            .get_mut("m")
+            .unwrap()
+            .app_overrides
+            .insert(
+                "com.apple.Safari".to_string(),
+                HashMap::from([("left".to_string(), ActionValue::Action(Action::Back))]),
+            );
+        assert!(overridden.check_primary_remap(&settings, false).is_err());
+        let mut restored = disabled.clone();
+        restored
+            .set_button_action("m", "left", ActionValue::Action(Action::Default))
+            .unwrap();
+        assert!(restored.check_primary_remap(&disabled, false).is_ok());
+    }
+
+    #[test]
+    fn reset_devices_keep_their_name_and_lose_every_mapping() {
+        let mut settings = Settings::default();
+        let config = DeviceConfig {
+            name: "Desk mouse".to_string(),
+            buttons: HashMap::from([("button4".to_string(), ActionValue::Action(Action::Back))]),
+            dpi_stops: vec![800, 1600],
+            app_overrides: HashMap::from([(
+                "com.apple.Safari".to_string(),
+                HashMap::from([("button5".to_string(), ActionValue::Action(Action::Forward))]),
+            )]),
+            merge_modifiers: vec!["button4".to_string()],
+            ..DeviceConfig::default()
+        };
+        settings
+            .profiles
+            .get_mut(DEFAULT_PROFILE)
+            .unwrap()
+            .devices
+            .insert("046d:c52b:1".to_string(), config);
+
+        settings.reset_device("046d:c52b:1", 8).unwrap();
+        let reset = settings.device_config("046d:c52b:1").unwrap();
+        assert_eq!(reset.name, "Desk mouse");
+        assert_eq!(reset.dpi_stops, vec![800, 1600]);
+        assert_eq!(reset.buttons, default_buttons(8));
+        assert!(reset.app_overrides.is_empty() && reset.merge_modifiers.is_empty());
+        assert!(!reset.has_remapping());
+
+        let err = settings.reset_device("1532:0084:1", 5).unwrap_err();
+        assert_eq!(err, "device not in settings: 1532:0084:1");
+    }
+
+    #[test]
+    fn reset_buttons_return_what_they_were_mapped_to() {
+        let mut settings = Settings::default();
+        let config = DeviceConfig {
+            name: "Desk mouse".to_string(),
+            buttons: HashMap::from([
+                ("button4".to_string(), ActionValue::Action(Action::Back)),
+                ("button7".to_string(), ActionValue::Action(Action::NextTab)),
+            ]),
+            app_overrides: HashMap::from([(
+                "com.apple.Safari".to_string(),
+                HashMap::from([("button4".to_string(), ActionValue::Action(Action::Forward))]),
+            )]),
+            ..DeviceConfig::default()
+        };
+        settings
+            .profiles
+            .get_mut(DEFAULT_PROFILE)
+            .unwrap()
+            .devices
+            .insert("046d:c52b:1".to_string(), config);
+
+        let previous = settings
+            .reset_button("046d:c52b:1", "button4", Some(5))
+            .unwrap();
+        assert_eq!(previous, ActionValue::Action(Action::Back));
+        let config = settings.device_config("046d:c52b:1").unwrap();
+        assert_eq!(config.buttons["button4"], ActionValue::default());
+        assert_eq!(
+            config.buttons["button7"],
+            ActionValue::Action(Action::NextTab)
+        );
+        assert_eq!(
+            config.app_overrides["com.apple.Safari"]["button4"],
+            ActionValue::Action(Action::Forward)
+        );
+
+        // Connected with five buttons, it has no button 7; disconnected, its
+        // config says it does. Units share their first one's config.
+        let err = settings
+            .reset_button("046d:c52b:1", "button7", Some(5))
+            .unwrap_err();
+        assert_eq!(err, "Desk mouse has no button7");
+        assert_eq!(
+            settings.reset_button("046d:c52b:1~2", "button7", None),
+            Ok(ActionValue::Action(Action::NextTab))
+        );
+        assert!(settings
+            .reset_button("046d:c52b:1", "button8", None)
+            .is_err());
+        assert_eq!(
+            settings.reset_button("046d:c52b:1", "middle", None),
+            Ok(ActionValue::default())
+        );
+        assert!(settings.reset_button("046d:c52b:1", "wheel", None).is_err());
+        assert!(settings.reset_button("1532:0084:1", "left", None).is_err());
+    }
+
+    #[test]
+    fn reset_settings_are_valid_and_remap_nothing() {
+        let settings = Settings::default();
+        assert!(settings.issues().is_empty());
+        assert!(settings.validate().is_ok());
+        assert_eq!(settings.profile_names(), vec![DEFAULT_PROFILE.to_string()]);
+        assert_eq!(settings.active_profile, DEFAULT_PROFILE);
+        assert!(settings.remap_enabled && !settings.startup);
+        assert!(settings.profiles[DEFAULT_PROFILE].devices.is_empty());
+    }
+
+    #[test]
+    fn imported_settings_reject_unknown_buttons_and_bad_values() {
+        let parse = |json: &str| parse_settings(json).unwrap().validate();
+        let devices = |device: &str| {
+            format!(
+                r#"{{"version": 1, "profiles": {{"Work": {{"devices": {{"046d:c52b": {}}}}}}}}}"#,
+                device
+            )
+        };
+
+        assert!(parse(&devices(
+            r#"{"buttons": {"button4": "Back", "button5": "Set DPI 1600"},
+                "modifier_mappings": {"middle": [{"modifiers": ["shift"], "action": "Next Profile"}]},
+                "merge_modifiers": ["button4"]}"#
+        ))
+        .is_ok());
+        // Unknown actions are only warnings, so they come along.
+        assert!(parse(&devices(r#"{"buttons": {"button4": "Rewind"}}"#)).is_ok());
+        let err = parse(&devices(r#"{"buttons": {"button11": "Back"}}"#)).unwrap_err();
+        assert!(
+            err.contains("button11") && err.contains("046d:c52b"),
+            "{}",
+            err
+        );
+        assert!(parse(&devices(r#"{"merge_modifiers": ["wheel"]}"#)).is_err());
+        assert!(parse(&devices(r#"{"buttons": {"middle": "set_dpi:0"}}"#)).is_err());
+        assert!(parse(&devices(
+            r#"{"app_overrides": {"com.apple.Safari": {"button5": {"gesture": {"up": {"gesture": {}}}}}}}"#
+        ))
+        .is_err());
+    }
+
+    #[test]
+    fn settings_issues_locate_errors_and_warnings() {
+        let mut settings = parse_settings(
+            r#"{"version": 1, "selected_device": "1532:0084:1",
+                "profiles": {"Default": {"devices": {
+                    "046d:c52b:1": {"name": "", "buttons": {"button4": "Rewind", "button5": "Set DPI 0"},
+                                    "dpi_stops": [800, 70000]},
+                    "*": {"buttons": {"button12": "Back"}}
+                }}}}"#,
+        )
+        .unwrap();
+        let issues = settings.issues();
+        let codes: Vec<(&str, Option<&str>, Option<&str>, IssueSeverity)> = issues
+            .iter()
+            .map(|issue| {
+                (
+                    issue.code,
+                    issue.device_id.as_deref(),
+                    issue.button.as_deref(),
+                    issue.severity,
+                )
+            })
+            .collect();
+        for expected in [
+            (
+                "unknown-button",
+                Some(ANY_DEVICE_ID),
+                Some("button12"),
+                IssueSeverity::Error,
+            ),
+            (
+                "unknown-action",
+                Some("046d:c52b:1"),
+                Some("button4"),
+                IssueSeverity::Warning,
+            ),
+            (
+                "out-of-range",
+                Some("046d:c52b:1"),
+                Some("button5"),
+                IssueSeverity::Error,
+            ),
+            (
+                "out-of-range",
+                Some("046d:c52b:1"),
+                None,
+                IssueSeverity::Error,
+            ),
+            (
+                "empty-device-name",
+                Some("046d:c52b:1"),
+                None,
+                IssueSeverity::Warning,
+            ),
+            (
+                "unknown-selected-device",
+                Some("1532:0084:1"),
+                None,
+                IssueSeverity::Warning,
+            ),
+        ] {
+            assert!(
+                codes.contains(&expected),
+                "{:?} not in {:?}",
+                expected,
+                codes
+            );
+        }
+        assert_eq!(issues.len(), 6);
+        let err = settings.validate().unwrap_err();
+        assert!(err.starts_with("* in profile Default: "), "{}", err);
+
+        // Warnings alone don't stop a save.
+        let devices = &mut settings.profiles.get_mut(DEFAULT_PROFILE).unwrap().devices;
+        devices.remove(ANY_DEVICE_ID);
+        let config = devices.get_mut("046d:c52b:1").unwrap();
+        config.buttons.clear();
+        config.dpi_stops.clear();
+        assert_eq!(settings.issues().len(), 2);
+        assert!(settings.validate().is_ok());
+
+        // A schedule time that isn't HH:MM stops it.
+        let profile = settings.profiles.get_mut(DEFAULT_PROFILE).unwrap();
+        profile.schedule.push(ProfileSchedule {
+            days: vec![Weekday::Monday],
+            start: "9:00".to_string(),
+            end: "24:00".to_string(),
+        });
+        let issues = settings.issues();
+        assert_eq!(issues.len(), 3);
+        assert!(issues.iter().any(|issue| issue.code == "invalid-time"
+            && issue.field == "schedule"
+            && issue.message.contains("24:00")));
+        assert!(settings.validate().is_err());
+
+        settings.input_overlay.auto_hide_secs = MAX_OVERLAY_AUTO_HIDE_SECS + 1;
+        assert!(settings
+            .issues()
+            .iter()
+            .any(|issue| issue.code == "out-of-range" && issue.field == "input_overlay"));
+    }
+
+    #[test]
+    fn saved_selections_point_at_a_stored_device() {
+        let connected = [MouseDevice {
+            id: "046d:c08b:A".to_string(),
+            name: "G502".to_string(),
+            kind: DeviceKind::Mouse,
+            button_count: 7,
+            preset: None,
+            first_seen: None,
+            ambiguous: false,
+            disabled: false,
+            ignored: false,
+        }];
+        let previous = Settings::default();
+
+        // A newly selected connected device gets a default config.
+        let mut settings = Settings {
+            selected_device: Some("046d:c08b:A".to_string()),
+            ..Settings::default()
+        };
+        settings
+            .settle_selected_device(&previous, &connected)
+            .unwrap();
+        let config = settings.device_config("046d:c08b:A").unwrap();
+        assert_eq!(config.name, "G502");
+        assert_eq!(config.buttons.len(), 7);
+
+        // An id that is neither configured nor connected is refused.
+        let mut unknown = Settings {
+            selected_device: Some("1532:0084:1".to_string()),
+            ..Settings::default()
+        };
+        let issue = unknown
+            .settle_selected_device(&previous, &connected)
+            .unwrap_err();
+        assert_eq!(issue.code, "unknown-selected-device");
+        assert_eq!(issue.device_id.as_deref(), Some("1532:0084:1"));
+
+        // Removing the selected device's config clears the selection, as
+        // does a stale id that was already selected.
+        let mut removed = settings.clone();
+        removed
+            .profiles
+            .get_mut(DEFAULT_PROFILE)
+            .unwrap()
+            .devices
+            .remove("046d:c08b:A");
+        removed.settle_selected_device(&settings, &[]).unwrap();
+        assert_eq!(removed.selected_device, None);
+        let stale = unknown.clone();
+        unknown.settle_selected_device(&stale, &[]).unwrap();
+        assert_eq!(unknown.selected_device, None);
+
+        let mut profiles = Settings::default();
+        profiles
+            .create_profile("Games", Profile::default())
+            .unwrap();
+        profiles
+            .profiles
+            .get_mut("Games")
+            .unwrap()
+            .devices
+            .insert("046d:c08b:A".to_string(), DeviceConfig::default());
+        profiles.selected_device = Some("046d:c08b:A".to_string());
+        profiles.delete_profile("Games").unwrap();
+        assert_eq!(profiles.selected_device, None);
+    }
+
+    #[test]
+    fn merged_imports_add_devices_and_keep_local_preferences() {
+        let mut current = Settings {
+            theme: "dark".to_string(),
+            excluded_apps: vec!["com.valvesoftware.steam".to_string()],
+            ..Settings::default()
+        };
+        let kept = DeviceConfig {
+            name: "Desk mouse".to_string(),
+            ..DeviceConfig::default()
+        };
+        current.profiles.get_mut(DEFAULT_PROFILE).unwrap().devices =
+            HashMap::from([("046d:c52b".to_string(), kept)]);
+        current.profile_app_rules.push(ProfileAppRule {
+            bundle_id: "com.apple.Safari".to_string(),
+            profile: DEFAULT_PROFILE.to_string(),
+        });
+
+        let imported = parse_settings(
+            r#"{"version": 1, "theme": "light", "active_profile": "Travel",
+                "profiles": {
+                    "Default": {"devices": {"1532:0084": {"name": "Laptop mouse"}}},
+                    "Travel": {"devices": {"046d:c52b": {"name": "Travel mouse"}}}
+                },
+                "profile_app_rules": [{"bundle_id": "com.apple.Safari", "profile": "Travel"}],
+                "excluded_apps": ["com.valvesoftware.steam", "com.blizzard.worldofwarcraft"]}"#,
+        )
+        .unwrap();
+        current.merge(imported);
+
+        assert_eq!(current.theme, "dark");
+        assert_eq!(current.active_profile, DEFAULT_PROFILE);
+        let default = &current.profiles[DEFAULT_PROFILE].devices;
+        assert_eq!(default["046d:c52b"].name, "Desk mouse");
+        assert_eq!(default["1532:0084"].name, "Laptop mouse");
+        assert_eq!(
+            current.profiles["Travel"].devices["046d:c52b"].name,
+            "Travel mouse"
+        );
+        assert_eq!(current.profile_app_rules.len(), 1);
+        assert_eq!(current.profile_app_rules[0].profile, "Travel");
+        assert_eq!(current.excluded_apps.len(), 2);
+        assert!(current.validate().is_ok());
+    }
+
+    #[test]
+    fn v0_settings_migrate_without_losing_anything() {
+        let settings = parse_settings(SETTINGS_V0).unwrap();
+        assert_eq!(settings.version, SETTINGS_VERSION);
+
+        // Display names come back as tagged actions, the gesture gains its
+        // tag, and nothing else changes.
+        let mut migrated = SETTINGS_V0.to_string();
+        for (name, action) in [
+            ("Default", r#"{"type": "default"}"#),
+            ("Back", r#"{"type": "back"}"#),
+            ("Forward", r#"{"type": "forward"}"#),
+            ("Next Profile", r#"{"type": "next_profile"}"#),
+            ("Previous Profile", r#"{"type": "previous_profile"}"#),
+            ("Middle Click", r#"{"type": "middle_click"}"#),
+            ("Set DPI 400", r#"{"type": "set_dpi", "dpi": 400}"#),
+        ] {
+            migrated = migrated.replace(&format!(": \"{}\"", name), &format!(": {}", action));
+        }
+        let mut expected: serde_json::Value = serde_json::from_str(&migrated).unwrap();
+        expected["version"] = SETTINGS_VERSION.into();
+        // Settings added since then come in at their defaults.
+        expected["start_hidden"] = false.into();
+        expected["locale"] = "system".into();
+        expected["toggle_hotkey"] =
+            serde_json::json!({"keycode": 46, "modifiers": ["control", "option", "command"]});
+        expected["window_hotkey"] = serde_json::Value::Null;
+        expected["quick_panel_hotkey"] = serde_json::Value::Null;
+        expected["cycle_device_hotkey"] = serde_json::Value::Null;
+        expected["tray_click_action"] = "toggle_window".into();
+        expected["notify_device_changes"] = false.into();
+        expected["battery_check_minutes"] = DEFAULT_BATTERY_CHECK_MINUTES.into();
+        expected["low_battery_percent"] = DEFAULT_LOW_BATTERY_PERCENT.into();
+        expected["show_connect_hud"] = true.into();
+        expected["fallback_to_any_device"] = false.into();
+        expected["fallback_devices"] = "configured".into();
+        expected["ignored_devices"] = serde_json::json!([]);
+        expected["synthetic_events_per_sec"] = DEFAULT_SYNTHETIC_EVENTS_PER_SEC.into();
+        expected["show_profile_toast"] = true.into();
+        expected["show_profile_in_tray"] = false.into();
+        expected["tab_keys"] = serde_json::json!({});
+        expected["focus_shortcut"] = DEFAULT_FOCUS_SHORTCUT.into();
+        expected["quick_look_apps"] = serde_json::json!([FINDER_BUNDLE_ID]);
+        expected["show_dock_icon"] = false.into();
+        expected["close_behavior"] = "ask".into();
+        expected["log_level"] = "info".into();
+        expected["auto_check_updates"] = false.into();
+        expected["collect_stats"] = true.into();
+        expected["idle_suspend_minutes"] = serde_json::Value::Null;
+        expected["record_history"] = true.into();
+        expected["onboarding_completed"] = true.into();
+        expected["pause_in_own_window"] = true.into();
+        expected["mute_feedback"] = false.into();
+        expected["double_click_interval_ms"] = DEFAULT_DOUBLE_CLICK_INTERVAL_MS.into();
+        expected["last_seen_version"] = "".into();
+        expected["sync_dir"] = serde_json::Value::Null;
+        expected["ignored_conflicts"] = serde_json::json!([]);
+        expected["migrated_from"] = serde_json::Value::Null;
+        expected["integration_server"] =
+            serde_json::json!({"enabled": false, "port": 45781, "token": ""});
+        expected["input_overlay"] =
+            serde_json::json!({"corner": "bottom_right", "auto_hide_secs": 0});
+        for profile in expected["profiles"].as_object_mut().unwrap().values_mut() {
+            for device in profile["devices"].as_object_mut().unwrap().values_mut() {
+                device["strip_modifiers"] = serde_json::json!([]);
+                device["triggers"] = serde_json::json!({});
+                device["feedback"] = serde_json::json!({});
+                device["swap_primary_buttons"] = false.into();
+                device["disable_scroll_inertia"] = false.into();
+                device["double_click_interval_ms"] = serde_json::json!({});
+                device["debounce_ms"] = serde_json::json!({});
+                device["repeat_while_held"] = serde_json::json!([]);
+                device["disabled"] = false.into();
+            }
+        }
+        let button5 =
+            &mut expected["profiles"]["Default"]["devices"]["046d:c08b:A"]["buttons"]["button5"];
+        let mut gesture = button5["gesture"].take();
+        gesture["type"] = "gesture".into();
+        *button5 = gesture;
+        assert_eq!(serde_json::to_value(&settings).unwrap(), expected);
+        let saved = serde_json::to_string(&settings).unwrap();
+        let reloaded = serde_json::to_value(parse_settings(&saved).unwrap()).unwrap();
+        assert_eq!(reloaded, expected);
+    }
+
+    #[test]
+    fn legacy_devices_never_replace_profile_configs() {
+        let json = r#"{
+            "profiles": {"Default": {"devices": {"046d:c08b:A": {"name": "kept"}}}},
+            "devices": {"046d:c08b:A": {"name": "legacy"}, "046d:c539:B": {"name": "moved"}}
+        }"#;
+        let settings = parse_settings(json).unwrap();
+        assert_eq!(settings.device_config("046d:c08b:A").unwrap().name, "kept");
+        assert_eq!(settings.device_config("046d:c539:B").unwrap().name, "moved");
+    }
+
+    #[test]
+    fn newer_settings_keep_their_version() {
+        let json = r#"{"version": 99, "theme": "dark", "from_the_future": {"a": 1}}"#;
+        let settings = parse_settings(json).unwrap();
+        assert_eq!(settings.version, 99);
+        assert_eq!(settings.theme, "dark");
+        // A current file isn't migrated again.
+        let current = format!(
+            r#"{{"version": {}, "devices": {{"x": {{}}}}}}"#,
+            SETTINGS_VERSION
+        );
+        assert!(parse_settings(&current)
+            .unwrap()
+            .device_config("x")
+            .is_none());
+        assert!(parse_settings(r#"{"version": "2"}"#).is_err());
+        assert!(parse_settings("[]").is_err());
+    }
+
+    #[test]
+    fn lenient_settings_keep_what_fits_and_say_what_didnt() {
+        let json = format!(
+            r#"{{"version": {}, "theme": 7, "them": "dark", "game_mode": true,
+                "profiles": {{
+                    "Default": {{"devices": {{
+                        "046d:c08b:A": {{"name": "kept", "buttons": {{"button4": "Back"}}}},
+                        "abc": {{"name": "lost", "buttons": "Back"}}
+                    }}}},
+                    "Games": "nope"
+                }}}}"#,
+            SETTINGS_VERSION
+        );
+        assert!(parse_settings(&json).is_err());
+        let (settings, warnings) = parse_settings_leniently(&json).unwrap();
+        assert!(settings.game_mode);
+        assert_eq!(settings.theme, Settings::default().theme);
+        assert_eq!(settings.device_config("046d:c08b:A").unwrap().name, "kept");
+        assert!(settings.device_config("abc").is_none());
+        assert!(!settings.profiles.contains_key("Games"));
+
+        let messages: Vec<&str> = warnings
+            .iter()
+            .map(|warning| warning.message.as_str())
+            .collect();
+        assert_eq!(messages.len(), 4, "{:?}", messages);
+        assert!(messages[0]
+            .starts_with("devices entry 'abc' in profile Default dropped: buttons: invalid type"));
+        assert!(messages[1].starts_with("profile Games dropped: "));
+        assert_eq!(messages[2], "unknown field 'them' ignored");
+        assert!(messages[3].starts_with("theme reset to its default: "));
+        assert_eq!(warnings[0].device_id.as_deref(), Some("abc"));
+
+        // A newer file's own fields aren't flagged, and a clean file has
+        // nothing to say.
+        let (_, newer) = parse_settings_leniently(r#"{"version": 99, "later": 1}"#).unwrap();
+        assert!(newer.is_empty());
+        let saved = serde_json::to_string(&Settings::default()).unwrap();
+        assert!(parse_settings_leniently(&saved).unwrap().1.is_empty());
+        assert!(parse_settings_leniently("[]").is_err());
+    }
+
+    #[test]
+    fn profiles_switch_and_survive_deletion() {
+        let mut settings = parse_settings(
+            r#"{"devices":{"046d:c08b:A":{"name":"G502","buttons":{"button4":"Back"}}}}"#,
+        )
+        .unwrap();
+        settings
+            .duplicate_profile(DEFAULT_PROFILE, "Gaming")
+            .unwrap();
+        settings.create_profile("Work", Profile::default()).unwrap();
+        assert!(settings
+            .create_profile(" Work ", Profile::default())
+            .is_err());
+        assert!(settings.create_profile("  ", Profile::default()).is_err());
+        assert!(settings.set_active_profile("Editing").is_err());
+
+        settings.set_active_profile("Work").unwrap();
+        assert!(settings.device_config("046d:c08b:A").is_none());
+        settings.set_active_profile("Gaming").unwrap();
+        assert!(settings.device_config("046d:c08b:A").is_some());
+
+        // Deleting the active profile falls back to the first remaining one.
+        settings.delete_profile("Gaming").unwrap();
+        assert_eq!(settings.active_profile, DEFAULT_PROFILE);
+        settings.delete_profile(DEFAULT_PROFILE).unwrap();
+        assert_eq!(settings.active_profile, "Work");
+        assert!(settings.delete_profile("Work").is_err());
+    }
+
+    #[test]
+    fn disabled_devices_swallow_every_button_and_keep_their_mappings() {
+        let mut settings = Settings::default();
+        settings.apply_preset("046d:c08b:A", presets::find("g502").unwrap(), 5);
+        let mapped = settings
+            .device_config("046d:c08b:A")
+            .unwrap()
+            .buttons
+            .clone();
+        settings.set_device_disabled("046d:c08b:A", true);
+        let config = settings.device_config("046d:c08b:A").unwrap();
+        assert!(config.has_remapping());
+        let mappings = ResolvedMappings::build(config, "046d:c08b:A", 5, None);
+        let disabled = Some(ResolvedAction::Action(Action::Disabled));
+        for button in 0..5 {
+            assert_eq!(mappings.mapping(button, 0), disabled);
+        }
+        assert_eq!(mappings.mapping(5, 0), None);
+
+        settings.set_device_disabled("046d:c08b:A", false);
+        let config = settings.device_config("046d:c08b:A").unwrap();
+        assert_eq!(config.buttons, mapped);
+        assert_ne!(
+            ResolvedMappings::build(config, "046d:c08b:A", 5, None).mapping(3, 0),
+            disabled
+        );
+    }
+}